@@ -9,12 +9,19 @@ pub const XSYNTH_AUDIO_EVENT_PITCH: u16 = 7;
 pub const XSYNTH_AUDIO_EVENT_FINETUNE: u16 = 8;
 pub const XSYNTH_AUDIO_EVENT_COARSETUNE: u16 = 9;
 pub const XSYNTH_AUDIO_EVENT_SYSTEMRESET: u16 = 10;
+pub const XSYNTH_AUDIO_EVENT_RESETCHANNEL: u16 = 11;
 
 pub const XSYNTH_CONFIG_SETLAYERS: u16 = 0;
 pub const XSYNTH_CONFIG_SETPERCUSSIONMODE: u16 = 1;
+pub const XSYNTH_CONFIG_SETTRANSPOSE: u16 = 2;
+pub const XSYNTH_CONFIG_SETTRANSPOSECLAMP: u16 = 3;
+pub const XSYNTH_CONFIG_SETTUNING: u16 = 4;
+pub const XSYNTH_CONFIG_SETTUNINGRETUNE: u16 = 5;
 
 pub const XSYNTH_AUDIO_CHANNELS_MONO: u16 = 1;
 pub const XSYNTH_AUDIO_CHANNELS_STEREO: u16 = 2;
+pub const XSYNTH_AUDIO_CHANNELS_QUAD: u16 = 4;
+pub const XSYNTH_AUDIO_CHANNELS_SURROUND51: u16 = 6;
 
 pub const XSYNTH_INTERPOLATION_NEAREST: u16 = 0;
 pub const XSYNTH_INTERPOLATION_LINEAR: u16 = 1;
@@ -78,6 +78,7 @@ pub extern "C" fn XSynth_GenDefault_GroupOptions() -> XSynth_GroupOptions {
 pub extern "C" fn XSynth_ChannelGroup_Create(options: XSynth_GroupOptions) -> XSynth_ChannelGroup {
     let channel_init_options = ChannelInitOptions {
         fade_out_killing: options.fade_out_killing,
+        ..Default::default()
     };
 
     let config = ChannelGroupConfig {
@@ -118,6 +119,10 @@ pub extern "C" fn XSynth_ChannelGroup_Create(options: XSynth_GroupOptions) -> XS
 ///         params: fine tune value in cents (0-8192, 4096=normal/middle)
 /// - XSYNTH_AUDIO_EVENT_COARSETUNE: Changes the coarse tuning
 ///         params: coarse tune value in semitones (0-128, 64=normal/middle)
+/// - XSYNTH_AUDIO_EVENT_SYSTEMRESET: Kill all notes and reset the control
+///         change data and the program (No parameters)
+/// - XSYNTH_AUDIO_EVENT_RESETCHANNEL: Kill all notes and reset the control
+///         change data, keeping the program (No parameters)
 #[no_mangle]
 pub extern "C" fn XSynth_ChannelGroup_SendAudioEvent(
     handle: XSynth_ChannelGroup,
@@ -164,6 +169,18 @@ pub extern "C" fn XSynth_ChannelGroup_SendAudioEventAll(
 ///         standard or percussion.
 ///         params: 1 = set the channel to only use percussion patches,
 ///                 0 = set the channel to use standard patches
+/// - XSYNTH_CONFIG_SETTRANSPOSE: Shifts the keys of the following notes.
+///         params: Semitones as a signed 32bit integer (12 = one octave up,
+///                 -12 = one octave down)
+/// - XSYNTH_CONFIG_SETTRANSPOSECLAMP: Controls what happens to notes that
+///         are transposed outside of the 0-127 key range.
+///         params: 1 = clamp them to the nearest valid key,
+///                 0 = drop them
+/// - XSYNTH_CONFIG_SETTUNING: Sets the reference pitch of A4 for the
+///         following notes.
+///         params: The pitch in hundredths of Hz (e.g. 44000 = 440Hz)
+/// - XSYNTH_CONFIG_SETTUNINGRETUNE: Same as XSYNTH_CONFIG_SETTUNING, but
+///         also retunes the notes that are already playing.
 #[no_mangle]
 pub extern "C" fn XSynth_ChannelGroup_SendConfigEvent(
     handle: XSynth_ChannelGroup,
@@ -213,7 +230,7 @@ pub unsafe extern "C" fn XSynth_ChannelGroup_SetSoundfonts(
         handle
             .as_mut()
             .send_event(SynthEvent::AllChannels(ChannelEvent::Config(
-                ChannelConfigEvent::SetSoundfonts(sfvec),
+                ChannelConfigEvent::SetSoundfonts(sfvec.into()),
             )));
     }
 }
@@ -227,7 +244,7 @@ pub extern "C" fn XSynth_ChannelGroup_ClearSoundfonts(handle: XSynth_ChannelGrou
     handle
         .as_mut()
         .send_event(SynthEvent::AllChannels(ChannelEvent::Config(
-            ChannelConfigEvent::SetSoundfonts(Vec::new()),
+            ChannelConfigEvent::SetSoundfonts(Vec::new().into()),
         )));
 }
 
@@ -29,7 +29,9 @@ pub extern "C" fn XSynth_GetVersion() -> u32 {
 /// - sample_rate: Audio sample rate
 /// - audio_channels: Number of audio channels
 ///         Supported: XSYNTH_AUDIO_CHANNELS_MONO (mono),
-///                    XSYNTH_AUDIO_CHANNELS_STEREO (stereo)
+///                    XSYNTH_AUDIO_CHANNELS_STEREO (stereo),
+///                    XSYNTH_AUDIO_CHANNELS_QUAD (quad),
+///                    XSYNTH_AUDIO_CHANNELS_SURROUND51 (5.1)
 #[repr(C)]
 pub struct XSynth_StreamParams {
     pub sample_rate: u32,
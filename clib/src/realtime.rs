@@ -70,6 +70,7 @@ pub struct XSynth_RealtimeStats {
 pub extern "C" fn XSynth_Realtime_Create(config: XSynth_RealtimeConfig) -> XSynth_RealtimeSynth {
     let channel_init_options = ChannelInitOptions {
         fade_out_killing: config.fade_out_killing,
+        ..Default::default()
     };
 
     let options = XSynthRealtimeConfig {
@@ -78,6 +79,7 @@ pub extern "C" fn XSynth_Realtime_Create(config: XSynth_RealtimeConfig) -> XSynt
         format: convert_synth_format(config.channels),
         multithreading: convert_threadcount(config.multithreading),
         ignore_range: config.ignore_range.start..=config.ignore_range.end,
+        ..Default::default()
     };
 
     let new = RealtimeSynth::open_with_default_output(options);
@@ -219,7 +221,7 @@ pub unsafe extern "C" fn XSynth_Realtime_SetSoundfonts(
         handle
             .as_mut()
             .send_event(SynthEvent::AllChannels(ChannelEvent::Config(
-                ChannelConfigEvent::SetSoundfonts(sfvec),
+                ChannelConfigEvent::SetSoundfonts(sfvec.into()),
             )));
     }
 }
@@ -233,7 +235,7 @@ pub extern "C" fn XSynth_Realtime_ClearSoundfonts(handle: XSynth_RealtimeSynth)
     handle
         .as_mut()
         .send_event(SynthEvent::AllChannels(ChannelEvent::Config(
-            ChannelConfigEvent::SetSoundfonts(Vec::new()),
+            ChannelConfigEvent::SetSoundfonts(Vec::new().into()),
         )));
 }
 
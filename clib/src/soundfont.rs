@@ -123,6 +123,7 @@ pub unsafe extern "C" fn XSynth_Soundfont_LoadNew(
                 XSYNTH_INTERPOLATION_LINEAR => Interpolator::Linear,
                 _ => Interpolator::Nearest,
             },
+            ..Default::default()
         };
 
         let stream_params = convert_streamparams_to_rust(options.stream_params);
@@ -46,6 +46,7 @@ pub(crate) fn convert_envelope_to_rust(
         attack_curve: convert_envelope_curve(options.attack_curve)?,
         decay_curve: convert_envelope_curve(options.decay_curve)?,
         release_curve: convert_envelope_curve(options.release_curve)?,
+        ..Default::default()
     })
 }
 
@@ -93,6 +94,7 @@ pub(crate) fn convert_audio_event(event: u16, params: u16) -> Result<ChannelEven
             ChannelAudioEvent::Control(ControlEvent::CoarseTune(val - 64.0))
         }
         XSYNTH_AUDIO_EVENT_SYSTEMRESET => ChannelAudioEvent::SystemReset,
+        XSYNTH_AUDIO_EVENT_RESETCHANNEL => ChannelAudioEvent::ResetChannel,
         _ => return Err(()),
     };
 
@@ -108,6 +110,17 @@ pub(crate) fn convert_config_event(event: u16, params: u32) -> Result<ChannelEve
         XSYNTH_CONFIG_SETPERCUSSIONMODE => {
             ChannelConfigEvent::SetPercussionMode(matches!(params, 1))
         }
+        XSYNTH_CONFIG_SETTRANSPOSE => {
+            let semitones = (params as i32).clamp(-127, 127) as i8;
+            ChannelConfigEvent::SetTranspose(semitones)
+        }
+        XSYNTH_CONFIG_SETTRANSPOSECLAMP => {
+            ChannelConfigEvent::SetTransposeClamp(matches!(params, 1))
+        }
+        XSYNTH_CONFIG_SETTUNING | XSYNTH_CONFIG_SETTUNINGRETUNE => ChannelConfigEvent::SetTuning {
+            a4: params as f32 / 100.0,
+            retune_sounding: event == XSYNTH_CONFIG_SETTUNINGRETUNE,
+        },
         _ => return Err(()),
     };
 
@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+
+use xsynth_core::channel::ChannelAudioEvent;
+use xsynth_core::channel::ChannelConfigEvent;
+use xsynth_core::channel::ChannelEvent;
+use xsynth_core::channel::VoiceChannel;
+use xsynth_core::soundfont::SampleSoundfont;
+use xsynth_core::soundfont::SoundfontBase;
+use xsynth_core::AudioPipe;
+use xsynth_core::AudioStreamParams;
+use xsynth_core::ChannelCount;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let Some(sfz) = std::env::var("XSYNTH_EXAMPLE_SFZ").ok() else {
+        println!(
+            "Usage: {} [sfz]",
+            std::env::current_exe()
+                .unwrap_or("example".into())
+                .display()
+        );
+        return;
+    };
+
+    let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+
+    println!("Loading soundfont...");
+
+    let soundfonts: Vec<Arc<dyn SoundfontBase>> = vec![Arc::new(
+        SampleSoundfont::new(sfz, stream_params, Default::default()).unwrap(),
+    )];
+
+    let mut channel = VoiceChannel::new(Default::default(), stream_params, None);
+    channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
+        soundfonts.clone().into(),
+    )));
+    channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetLayerCount(
+        Some(1),
+    )));
+
+    let mut buffer = vec![0.0; 0];
+
+    c.bench_function("note on (all keys and velocities)", |f| {
+        f.iter(|| {
+            for vel in (1..128).step_by(8) {
+                for key in 0..128 {
+                    channel
+                        .process_event(ChannelEvent::Audio(ChannelAudioEvent::NoteOn { key, vel }));
+                }
+
+                // Key events get processed when we read samples
+                channel.read_samples(&mut buffer);
+                channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::AllNotesKilled));
+            }
+        })
+    });
+
+    c.bench_function("program change (two programs)", |f| {
+        f.iter(|| {
+            for preset in [1, 0] {
+                channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::ProgramChange(
+                    preset,
+                )));
+                channel.read_samples(&mut buffer);
+            }
+        })
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
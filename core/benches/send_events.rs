@@ -62,7 +62,7 @@ fn criterion_benchmark(c: &mut Criterion) {
             };
             let mut channel = VoiceChannel::new(init, stream_params, None);
             channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
-                soundfonts.clone(),
+                soundfonts.clone().into(),
             )));
             channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetLayerCount(
                 Some(4),
@@ -80,7 +80,7 @@ fn criterion_benchmark(c: &mut Criterion) {
             };
             let mut channel = VoiceChannel::new(init, stream_params, None);
             channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
-                soundfonts.clone(),
+                soundfonts.clone().into(),
             )));
             channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetLayerCount(
                 Some(4),
@@ -98,7 +98,7 @@ fn criterion_benchmark(c: &mut Criterion) {
             };
             let mut channel = VoiceChannel::new(init, stream_params, None);
             channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
-                soundfonts.clone(),
+                soundfonts.clone().into(),
             )));
             channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetLayerCount(
                 None,
@@ -116,7 +116,7 @@ fn criterion_benchmark(c: &mut Criterion) {
             };
             let mut channel = VoiceChannel::new(init, stream_params, None);
             channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
-                soundfonts.clone(),
+                soundfonts.clone().into(),
             )));
             channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetLayerCount(
                 None,
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+
+use xsynth_core::channel::ChannelAudioEvent;
+use xsynth_core::channel::ChannelConfigEvent;
+use xsynth_core::channel::ChannelEvent;
+use xsynth_core::channel::ChannelInitOptions;
+use xsynth_core::channel::ControlEvent;
+use xsynth_core::channel::RenderBudget;
+use xsynth_core::channel::VoiceChannel;
+use xsynth_core::channel::VoiceLimitMode;
+use xsynth_core::soundfont::SampleSoundfont;
+use xsynth_core::soundfont::SoundfontBase;
+use xsynth_core::AudioPipe;
+use xsynth_core::AudioStreamParams;
+use xsynth_core::ChannelCount;
+
+/// Plays mixed content: repeated quiet notes whose voices pile up in their
+/// release on the low keys, and loud held notes on the high keys. The
+/// channel is pitched up, so the voices of the high keys are expensive.
+fn play_mixed_content(channel: &mut VoiceChannel) {
+    let mut buffer = vec![0.0; 960];
+    for i in 0..200 {
+        for key in 0..64 {
+            channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::NoteOn {
+                key,
+                vel: 20,
+            }));
+            channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::NoteOff { key }));
+        }
+
+        if i % 10 == 0 {
+            for key in 64..128 {
+                channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::NoteOn {
+                    key,
+                    vel: 127,
+                }));
+            }
+        }
+
+        channel.read_samples(&mut buffer);
+    }
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let Some(sfz) = std::env::var("XSYNTH_EXAMPLE_SFZ").ok() else {
+        println!(
+            "Usage: {} [sfz]",
+            std::env::current_exe()
+                .unwrap_or("example".into())
+                .display()
+        );
+        return;
+    };
+
+    let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+
+    println!("Loading soundfont...");
+
+    let soundfonts: Vec<Arc<dyn SoundfontBase>> = vec![Arc::new(
+        SampleSoundfont::new(sfz, stream_params, Default::default()).unwrap(),
+    )];
+
+    let make_new_channel = |voice_limit| {
+        let init = ChannelInitOptions {
+            voice_limit,
+            ..Default::default()
+        };
+        let mut channel = VoiceChannel::new(init, stream_params, None);
+        channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
+            soundfonts.clone().into(),
+        )));
+        channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetLayerCount(
+            Some(16),
+        )));
+        channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::Control(
+            ControlEvent::PitchBend(12.0),
+        )));
+        channel
+    };
+
+    c.bench_function("mixed content (16 layers, count limit)", |f| {
+        f.iter(|| play_mixed_content(&mut make_new_channel(VoiceLimitMode::Count)))
+    });
+
+    c.bench_function("mixed content (16 layers, render budget)", |f| {
+        f.iter(|| {
+            let budget = VoiceLimitMode::RenderBudget(RenderBudget::default());
+            play_mixed_content(&mut make_new_channel(budget))
+        })
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,295 @@
+//! Representative workloads of the synthesizer, rendered with the small
+//! soundfonts in `benches/fixtures` and generated events, so they run
+//! without any external files. Each benchmark also prints its cost per voice.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use criterion::black_box;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::SeedableRng;
+
+use xsynth_core::channel::ChannelAudioEvent;
+use xsynth_core::channel::ChannelConfigEvent;
+use xsynth_core::channel::ChannelEvent;
+use xsynth_core::channel::ChannelInitOptions;
+use xsynth_core::channel::VoiceChannel;
+use xsynth_core::effects::VolumeLimiter;
+use xsynth_core::soundfont::SampleSoundfont;
+use xsynth_core::soundfont::SoundfontBase;
+use xsynth_core::sum_simd;
+use xsynth_core::AudioPipe;
+use xsynth_core::AudioStreamParams;
+use xsynth_core::ChannelCount;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/benches/fixtures");
+
+/// The frames rendered at once, 10ms at 48kHz.
+const BUFFER_FRAMES: usize = 480;
+
+fn stream_params() -> AudioStreamParams {
+    AudioStreamParams::new(48000, ChannelCount::Stereo)
+}
+
+fn load_fixture_sfz(name: &str) -> Arc<dyn SoundfontBase> {
+    let path = format!("{FIXTURES}/{name}");
+    Arc::new(SampleSoundfont::new(path, stream_params(), Default::default()).unwrap())
+}
+
+/// Creates a channel without a layer limit, that keeps every voice it spawns.
+fn make_channel(soundfont: &Arc<dyn SoundfontBase>) -> VoiceChannel {
+    let init = ChannelInitOptions {
+        hard_voice_limit: 8192,
+        ..Default::default()
+    };
+    let mut channel = VoiceChannel::new(init, stream_params(), None);
+    channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
+        Arc::new([soundfont.clone()]),
+    )));
+    channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetLayerCount(
+        None,
+    )));
+    channel
+}
+
+fn note_on(key: u8, vel: u8) -> ChannelEvent {
+    ChannelEvent::Audio(ChannelAudioEvent::NoteOn { key, vel })
+}
+
+fn note_off(key: u8) -> ChannelEvent {
+    ChannelEvent::Audio(ChannelAudioEvent::NoteOff { key })
+}
+
+/// Accumulates the time measured over all iterations of a benchmark,
+/// including the warm up, to print the cost per unit of work afterwards.
+#[derive(Default)]
+struct PerVoice {
+    elapsed: Duration,
+    voices: u64,
+}
+
+impl PerVoice {
+    fn add(&mut self, elapsed: Duration, voices: u64) {
+        self.elapsed += elapsed;
+        self.voices += voices;
+    }
+
+    fn print(&self, name: &str, unit: &str) {
+        let ns = self.elapsed.as_nanos() as f64 / self.voices.max(1) as f64;
+        println!("{name}: {ns:.2} ns per {unit}");
+    }
+}
+
+fn bench_sum(c: &mut Criterion) {
+    // Summing a voice into the key buffer, for one stereo buffer
+    let source = vec![0.5; BUFFER_FRAMES * 2];
+    let mut target = vec![0.0; BUFFER_FRAMES * 2];
+
+    let mut simd = PerVoice::default();
+    c.bench_function("sum_simd", |f| {
+        f.iter_custom(|iters| {
+            let start = Instant::now();
+            for _ in 0..iters {
+                sum_simd(black_box(&source), black_box(&mut target));
+            }
+            let elapsed = start.elapsed();
+            simd.add(elapsed, iters);
+            elapsed
+        })
+    });
+    simd.print("sum_simd", "voice buffer");
+
+    let mut scalar = PerVoice::default();
+    c.bench_function("sum scalar", |f| {
+        f.iter_custom(|iters| {
+            let start = Instant::now();
+            for _ in 0..iters {
+                for (t, s) in black_box(&mut target).iter_mut().zip(black_box(&source)) {
+                    *t += *s;
+                }
+            }
+            let elapsed = start.elapsed();
+            scalar.add(elapsed, iters);
+            elapsed
+        })
+    });
+    scalar.print("sum scalar", "voice buffer");
+}
+
+fn bench_key_render(c: &mut Criterion) {
+    let soundfont = load_fixture_sfz("tone.sfz");
+    let mut buffer = vec![0.0; BUFFER_FRAMES * 2];
+
+    for voices in [10, 100, 5000] {
+        // All voices play on a single key, so this measures the rendering
+        // of one key's data rather than the parallel rendering of the keys
+        let mut channel = make_channel(&soundfont);
+        for _ in 0..voices {
+            channel.process_event(note_on(60, 100));
+        }
+        channel.read_samples(&mut buffer);
+        assert_eq!(channel.get_channel_stats().voice_count(), voices);
+
+        let name = format!("key render ({voices} voices)");
+        let mut per_voice = PerVoice::default();
+        c.bench_function(&name, |f| {
+            f.iter_custom(|iters| {
+                let start = Instant::now();
+                for _ in 0..iters {
+                    channel.read_samples(&mut buffer);
+                }
+                let elapsed = start.elapsed();
+                per_voice.add(elapsed, iters * voices);
+                elapsed
+            })
+        });
+        per_voice.print(&name, "voice per buffer");
+    }
+}
+
+fn bench_spawn(c: &mut Criterion) {
+    // 100 keys with 10 velocity layers each, so each note on has to find its
+    // region among 1000
+    let soundfont = load_fixture_sfz("regions_1000.sfz");
+    let mut channel = make_channel(&soundfont);
+    let mut buffer = vec![0.0; 0];
+
+    let mut per_voice = PerVoice::default();
+    c.bench_function("spawn (1000 regions)", |f| {
+        f.iter_custom(|iters| {
+            let mut elapsed = Duration::ZERO;
+            for i in 0..iters {
+                let vel = (i % 127) as u8 + 1;
+                let start = Instant::now();
+                for key in 14..114 {
+                    channel.process_event(note_on(key, vel));
+                }
+
+                // Key events get processed when we read samples
+                channel.read_samples(&mut buffer);
+                elapsed += start.elapsed();
+                channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::AllNotesKilled));
+            }
+            per_voice.add(elapsed, iters * 100);
+            elapsed
+        })
+    });
+    per_voice.print("spawn (1000 regions)", "voice");
+}
+
+/// The steps of the generated trace, 5ms apart at 48kHz.
+const TRACE_STEPS: usize = 200;
+const TRACE_STEP_FRAMES: usize = 240;
+
+/// The notes played at each step of the trace, and the steps they are held.
+const TRACE_NOTES_PER_STEP: usize = 16;
+const TRACE_NOTE_STEPS: usize = 3;
+
+/// Generates a dense trace of random notes, like the ones of black MIDI.
+/// The seed is fixed, so each run plays the same events. Returns the events
+/// with the frame at 48kHz they happen at.
+fn black_midi_trace() -> Vec<(usize, ChannelEvent)> {
+    let mut rng = StdRng::seed_from_u64(0x163);
+    let mut held = VecDeque::new();
+    let mut trace = Vec::new();
+    for step in 0..TRACE_STEPS + TRACE_NOTE_STEPS {
+        let frame = step * TRACE_STEP_FRAMES;
+        if step < TRACE_STEPS {
+            let keys: Vec<u8> = (0..TRACE_NOTES_PER_STEP)
+                .map(|_| rng.gen_range(21..=108))
+                .collect();
+            for &key in &keys {
+                trace.push((frame, note_on(key, rng.gen_range(40..=127))));
+            }
+            held.push_back(keys);
+        }
+        if step >= TRACE_NOTE_STEPS {
+            for key in held.pop_front().unwrap() {
+                trace.push((frame, note_off(key)));
+            }
+        }
+    }
+    trace
+}
+
+/// Plays the trace, sending the events of each buffer before rendering it.
+/// Returns the number of voices rendered in all buffers.
+fn play_trace(channel: &mut VoiceChannel, trace: &[(usize, ChannelEvent)]) -> u64 {
+    let mut buffer = vec![0.0; BUFFER_FRAMES * 2];
+    let end = trace.last().map_or(0, |(frame, _)| *frame);
+
+    let mut events = trace.iter().peekable();
+    let mut voices = 0;
+    for buffer_start in (0..=end).step_by(BUFFER_FRAMES) {
+        while let Some((_, event)) =
+            events.next_if(|(frame, _)| *frame < buffer_start + BUFFER_FRAMES)
+        {
+            channel.process_event(event.clone());
+        }
+        channel.read_samples(&mut buffer);
+        voices += channel.get_channel_stats().voice_count();
+    }
+    voices
+}
+
+fn bench_trace(c: &mut Criterion) {
+    let soundfont = load_fixture_sfz("tone.sfz");
+    let trace = black_midi_trace();
+
+    let mut per_voice = PerVoice::default();
+    c.bench_function("black MIDI trace", |f| {
+        f.iter_custom(|iters| {
+            let mut elapsed = Duration::ZERO;
+            let mut voices = 0;
+            for _ in 0..iters {
+                let mut channel = make_channel(&soundfont);
+                let start = Instant::now();
+                voices += play_trace(&mut channel, &trace);
+                elapsed += start.elapsed();
+            }
+            per_voice.add(elapsed, voices);
+            elapsed
+        })
+    });
+    per_voice.print("black MIDI trace", "voice per buffer");
+}
+
+fn bench_limiter(c: &mut Criterion) {
+    // A loud sine, restored before each run so the limiter keeps working
+    let mut limiter = VolumeLimiter::new(2);
+    let source: Vec<f32> = (0..BUFFER_FRAMES * 2)
+        .map(|i| (i as f32 * 0.05).sin() * 4.0)
+        .collect();
+    let mut buffer = source.clone();
+
+    let mut per_frame = PerVoice::default();
+    c.bench_function("limiter", |f| {
+        f.iter_custom(|iters| {
+            let start = Instant::now();
+            for _ in 0..iters {
+                buffer.copy_from_slice(&source);
+                limiter.limit(black_box(&mut buffer));
+            }
+            let elapsed = start.elapsed();
+            per_frame.add(elapsed, iters * BUFFER_FRAMES as u64);
+            elapsed
+        })
+    });
+    per_frame.print("limiter", "frame");
+}
+
+criterion_group!(
+    benches,
+    bench_sum,
+    bench_key_render,
+    bench_spawn,
+    bench_trace,
+    bench_limiter
+);
+criterion_main!(benches);
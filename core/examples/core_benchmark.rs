@@ -54,7 +54,7 @@ pub fn main() {
     let make_new_channel = || {
         let mut channel = VoiceChannel::new(Default::default(), stream_params, None);
         channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
-            soundfonts.clone(),
+            soundfonts.clone().into(),
         )));
         channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetLayerCount(
             None,
@@ -40,9 +40,11 @@ pub fn main() {
                     attack_curve: EnvelopeCurveType::Exponential,
                     decay_curve: EnvelopeCurveType::Exponential,
                     release_curve: EnvelopeCurveType::Exponential,
+                    ..Default::default()
                 },
                 interpolator: Interpolator::Nearest,
                 use_effects: false,
+                ..Default::default()
             },
         )
         .unwrap(),
@@ -60,7 +62,7 @@ pub fn main() {
         Some(Arc::new(threadpool)),
     );
     channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
-        soundfonts.clone(),
+        soundfonts.clone().into(),
     )));
     channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetLayerCount(
         Some(layer_count as usize),
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use xsynth_core::{
+    channel::{ChannelAudioEvent, ChannelConfigEvent, ChannelEvent},
+    channel_group::{
+        ChannelGroup, ChannelGroupConfig, ParallelismOptions, SynthEvent, SynthFormat, ThreadCount,
+    },
+    soundfont::{SampleSoundfont, SoundfontBase},
+    AudioStreamParams, ChannelCount,
+};
+
+/// Renders a chord spread over a few channels into a WAV file, with the
+/// channels rendered by the host's scheduler instead of the thread pools of
+/// the channel group. A rayon scope stands in for the job system of a game
+/// engine.
+pub fn main() {
+    let args = std::env::args().collect::<Vec<String>>();
+    let (Some(sfz), Some(out)) = (args.get(1), args.get(2)) else {
+        println!(
+            "Usage: {} [sf2/sfz] [output wav]",
+            std::env::current_exe()
+                .unwrap_or("example".into())
+                .display()
+        );
+        return;
+    };
+
+    let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+
+    println!("Loading soundfont...");
+
+    let soundfonts: Vec<Arc<dyn SoundfontBase>> = vec![Arc::new(
+        SampleSoundfont::new(sfz, stream_params, Default::default()).unwrap(),
+    )];
+
+    // The group doesn't own any threads, the host runs the render jobs
+    let mut group = ChannelGroup::new(ChannelGroupConfig {
+        channel_init_options: Default::default(),
+        format: SynthFormat::Custom { channels: 4 },
+        audio_params: stream_params,
+        parallelism: ParallelismOptions {
+            channel: ThreadCount::None,
+            key: ThreadCount::None,
+        },
+    });
+    group.send_event(SynthEvent::AllChannels(ChannelEvent::Config(
+        ChannelConfigEvent::SetSoundfonts(soundfonts.into()),
+    )));
+
+    let spec = WavSpec {
+        channels: stream_params.channels.count(),
+        sample_rate: stream_params.sample_rate,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut writer = WavWriter::create(out, spec).unwrap();
+
+    // Renders the given number of seconds in buffers of 10 ms
+    let mut buffer = vec![0.0; 480 * stream_params.channels.count() as usize];
+    let mut render = |group: &mut ChannelGroup, seconds: usize| {
+        for _ in 0..seconds * 100 {
+            // The busiest channels are scheduled first
+            let mut jobs: Vec<_> = group.render_jobs(buffer.len()).collect();
+            jobs.sort_by_key(|job| std::cmp::Reverse(job.voice_count()));
+            rayon::scope(|scope| {
+                for job in jobs {
+                    scope.spawn(move |_| job.run());
+                }
+            });
+
+            group.finalize_mix(&mut buffer);
+            for &sample in buffer.iter() {
+                writer.write_sample(sample).unwrap();
+            }
+        }
+    };
+
+    println!("Rendering...");
+
+    let chord = [48, 60, 64, 67];
+    for (channel, key) in chord.into_iter().enumerate() {
+        group.send_event(SynthEvent::Channel(
+            channel as u32,
+            ChannelEvent::Audio(ChannelAudioEvent::NoteOn { key, vel: 100 }),
+        ));
+    }
+    render(&mut group, 2);
+
+    for (channel, key) in chord.into_iter().enumerate() {
+        group.send_event(SynthEvent::Channel(
+            channel as u32,
+            ChannelEvent::Audio(ChannelAudioEvent::NoteOff { key }),
+        ));
+    }
+    render(&mut group, 2);
+
+    writer.finalize().unwrap();
+    println!("Done");
+}
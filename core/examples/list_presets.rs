@@ -0,0 +1,43 @@
+use xsynth_core::{
+    soundfont::{SampleSoundfont, SoundfontBase},
+    AudioStreamParams, ChannelCount,
+};
+
+pub fn main() {
+    let args = std::env::args().collect::<Vec<String>>();
+    let Some(path) = args.get(1) else {
+        println!(
+            "Usage: {} [sf2/sfz]",
+            std::env::current_exe()
+                .unwrap_or("example".into())
+                .display()
+        );
+        return;
+    };
+
+    let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+    let soundfont = SampleSoundfont::new(path, stream_params, Default::default()).unwrap();
+
+    let metadata = soundfont.metadata();
+    println!("Name: {}", metadata.name);
+    if let Some(author) = metadata.author {
+        println!("Author: {author}");
+    }
+    if let Some(copyright) = metadata.copyright {
+        println!("Copyright: {copyright}");
+    }
+
+    println!("\nBank  Preset  Keys     Velocities  Name");
+    for preset in soundfont.presets() {
+        println!(
+            "{:>4}  {:>6}  {:>3}-{:<3}  {:>3}-{:<3}     {}",
+            preset.bank,
+            preset.preset,
+            preset.key_range.start(),
+            preset.key_range.end(),
+            preset.vel_range.start(),
+            preset.vel_range.end(),
+            preset.name
+        );
+    }
+}
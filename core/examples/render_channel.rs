@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use xsynth_core::{
+    channel::{ChannelAudioEvent, ChannelConfigEvent, ChannelEvent, VoiceChannel},
+    soundfont::{SampleSoundfont, SoundfontBase},
+    AudioPipe, AudioStreamParams, ChannelCount,
+};
+
+/// Renders a chord played by a single channel into a WAV file, without a
+/// channel group or any realtime components.
+pub fn main() {
+    let args = std::env::args().collect::<Vec<String>>();
+    let (Some(sfz), Some(out)) = (args.get(1), args.get(2)) else {
+        println!(
+            "Usage: {} [sf2/sfz] [output wav]",
+            std::env::current_exe()
+                .unwrap_or("example".into())
+                .display()
+        );
+        return;
+    };
+
+    let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+
+    println!("Loading soundfont...");
+
+    let soundfonts: Vec<Arc<dyn SoundfontBase>> = vec![Arc::new(
+        SampleSoundfont::new(sfz, stream_params, Default::default()).unwrap(),
+    )];
+
+    let mut channel = VoiceChannel::new(Default::default(), stream_params, None);
+    channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
+        soundfonts.into(),
+    )));
+
+    let spec = WavSpec {
+        channels: stream_params.channels.count(),
+        sample_rate: stream_params.sample_rate,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut writer = WavWriter::create(out, spec).unwrap();
+
+    // Renders the given number of seconds in buffers of 10 ms
+    let mut buffer = vec![0.0; 480 * stream_params.channels.count() as usize];
+    let mut render = |channel: &mut VoiceChannel, seconds: usize| {
+        for _ in 0..seconds * 100 {
+            channel.read_samples(&mut buffer);
+            for &sample in buffer.iter() {
+                writer.write_sample(sample).unwrap();
+            }
+        }
+    };
+
+    println!("Rendering...");
+
+    let chord = [60, 64, 67];
+    channel.push_events_iter(
+        chord
+            .into_iter()
+            .map(|key| ChannelEvent::Audio(ChannelAudioEvent::NoteOn { key, vel: 100 })),
+    );
+    render(&mut channel, 2);
+
+    channel.push_events_iter(
+        chord
+            .into_iter()
+            .map(|key| ChannelEvent::Audio(ChannelAudioEvent::NoteOff { key })),
+    );
+    render(&mut channel, 2);
+
+    writer.finalize().unwrap();
+    println!("Done");
+}
@@ -1,4 +1,4 @@
-use crate::AudioStreamParams;
+use crate::{AudioStreamParams, Sample, SampleConverter};
 
 /// An object to read audio samples from.
 pub trait AudioPipe {
@@ -20,6 +20,20 @@ pub trait AudioPipe {
 
     /// Reads samples from the pipe without checking the channel count of the output.
     fn read_samples_unchecked(&mut self, to: &mut [f32]);
+
+    /// Reads samples from the pipe, converting them to the given sample format.
+    /// 16 bit formats are dithered, see the `SampleConverter` documentation
+    /// for more information.
+    fn read_samples_as<T: Sample>(&mut self, to: &mut [T], converter: &mut SampleConverter)
+    where
+        Self: Sized,
+    {
+        let mut buffer = std::mem::take(&mut converter.buffer);
+        buffer.resize(to.len(), 0.0);
+        self.read_samples(&mut buffer);
+        converter.convert(&buffer, to);
+        converter.buffer = buffer;
+    }
 }
 
 pub struct FunctionAudioPipe<F: 'static + FnMut(&mut [f32]) + Send> {
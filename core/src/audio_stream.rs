@@ -1,9 +1,23 @@
-/// Number of audio channels.
+/// Number of audio channels and their speaker layout.
+///
+/// Voices are rendered in mono for `Mono` and in stereo for every other
+/// layout. For multichannel layouts, the stereo output of each MIDI channel
+/// is then placed into the speaker layout following the channel pan.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum ChannelCount {
+    /// A single channel. Stereo samples are downmixed with -3 dB per side.
     Mono,
+
+    /// Left and right.
     Stereo,
+
+    /// Front left, front right, rear left and rear right.
+    Quad,
+
+    /// Front left, front right, center, LFE, surround left and surround right.
+    /// The LFE channel is left silent.
+    Surround51,
 }
 
 impl ChannelCount {
@@ -11,6 +25,8 @@ impl ChannelCount {
         match self {
             ChannelCount::Mono => 1,
             ChannelCount::Stereo => 2,
+            ChannelCount::Quad => 4,
+            ChannelCount::Surround51 => 6,
         }
     }
 
@@ -18,15 +34,105 @@ impl ChannelCount {
         match count {
             1 => Some(ChannelCount::Mono),
             2 => Some(ChannelCount::Stereo),
+            4 => Some(ChannelCount::Quad),
+            6 => Some(ChannelCount::Surround51),
             _ => None,
         }
     }
+
+    /// The number of channels the voices are rendered with, before being
+    /// placed into the speaker layout.
+    pub fn voice_channels(&self) -> u16 {
+        match self {
+            ChannelCount::Mono => 1,
+            _ => 2,
+        }
+    }
+
+    /// The direction of each output channel in degrees, in interleave order.
+    /// 0 is the front and positive angles are to the right. Channels without
+    /// a direction (LFE) are `None`.
+    pub fn speaker_azimuths(&self) -> &'static [Option<f32>] {
+        match self {
+            ChannelCount::Mono => &[Some(0.0)],
+            ChannelCount::Stereo => &[Some(-30.0), Some(30.0)],
+            ChannelCount::Quad => &[Some(-45.0), Some(45.0), Some(-135.0), Some(135.0)],
+            ChannelCount::Surround51 => &[
+                Some(-30.0),
+                Some(30.0),
+                Some(0.0),
+                None,
+                Some(-110.0),
+                Some(110.0),
+            ],
+        }
+    }
+
+    /// Writes the gain of each output channel for a point source at the given
+    /// azimuth (in degrees, see `speaker_azimuths`) into `gains`.
+    ///
+    /// The source is panned between the two closest speakers around it using
+    /// pairwise amplitude panning (2D VBAP), normalized to constant power.
+    pub fn panning_gains(&self, azimuth: f32, gains: &mut [f32]) {
+        let azimuths = self.speaker_azimuths();
+        gains[..azimuths.len()].fill(0.0);
+
+        let speakers = || {
+            azimuths
+                .iter()
+                .enumerate()
+                .filter_map(|(i, a)| a.map(|a| (i, a.rem_euclid(360.0))))
+        };
+        if speakers().count() == 1 {
+            if let Some((i, _)) = speakers().next() {
+                gains[i] = 1.0;
+            }
+            return;
+        }
+
+        let source = azimuth.rem_euclid(360.0);
+        for (a, a_angle) in speakers() {
+            // The next speaker going clockwise
+            let Some((b, b_angle)) = speakers().filter(|&(i, _)| i != a).min_by(|x, y| {
+                let x = (x.1 - a_angle).rem_euclid(360.0);
+                let y = (y.1 - a_angle).rem_euclid(360.0);
+                x.total_cmp(&y)
+            }) else {
+                continue;
+            };
+
+            let span = (b_angle - a_angle).rem_euclid(360.0);
+            let offset = (source - a_angle).rem_euclid(360.0);
+            if offset > span {
+                continue;
+            }
+
+            let (mut gain_a, mut gain_b) = if span < 180.0 {
+                // Solve the speaker base for the source direction
+                let (sa, ca) = a_angle.to_radians().sin_cos();
+                let (sb, cb) = b_angle.to_radians().sin_cos();
+                let (ss, cs) = source.to_radians().sin_cos();
+                let det = ca * sb - cb * sa;
+                ((cs * sb - cb * ss) / det, (ca * ss - cs * sa) / det)
+            } else {
+                // The speakers are too far apart for VBAP, use a sine law instead
+                let angle = offset / span * std::f32::consts::FRAC_PI_2;
+                (angle.cos(), angle.sin())
+            };
+            gain_a = gain_a.max(0.0);
+            gain_b = gain_b.max(0.0);
+            let norm = (gain_a * gain_a + gain_b * gain_b).sqrt().max(f32::EPSILON);
+            gains[a] = gain_a / norm;
+            gains[b] = gain_b / norm;
+            return;
+        }
+    }
 }
 
 impl From<u16> for ChannelCount {
     fn from(count: u16) -> Self {
         ChannelCount::from_count(count)
-            .expect("Unsupported channel count, only mono and stereo are supported")
+            .expect("Unsupported channel count, only mono, stereo, quad and 5.1 are supported")
     }
 }
 
@@ -46,3 +152,35 @@ impl AudioStreamParams {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panning_gains() {
+        for layout in [ChannelCount::Quad, ChannelCount::Surround51] {
+            let mut gains = [0.0; 8];
+            for azimuth in (-180..180).step_by(5) {
+                layout.panning_gains(azimuth as f32, &mut gains);
+                let power: f32 = gains.iter().map(|g| g * g).sum();
+                assert!((power - 1.0).abs() < 1e-4, "{layout:?} at {azimuth}");
+            }
+
+            // Sources at a speaker only play from that speaker
+            for (i, azimuth) in layout.speaker_azimuths().iter().enumerate() {
+                if let Some(azimuth) = azimuth {
+                    layout.panning_gains(*azimuth, &mut gains);
+                    assert!((gains[i] - 1.0).abs() < 1e-4, "{layout:?} at {azimuth}");
+                }
+            }
+        }
+
+        // The LFE channel stays silent
+        let mut gains = [0.0; 8];
+        for azimuth in (-180..180).step_by(5) {
+            ChannelCount::Surround51.panning_gains(azimuth as f32, &mut gains);
+            assert_eq!(gains[3], 0.0);
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use std::{
     collections::VecDeque,
     sync::{
-        atomic::{AtomicI64, AtomicUsize, Ordering},
+        atomic::{AtomicI64, AtomicU32, AtomicU64, AtomicUsize, Ordering},
         Arc, RwLock,
     },
     thread::{self, JoinHandle},
@@ -22,6 +22,13 @@ struct BufferedRendererStats {
     last_request_samples: Arc<AtomicI64>,
     render_time: Arc<RwLock<VecDeque<f64>>>,
     render_size: Arc<AtomicUsize>,
+    render_ahead: Arc<AtomicUsize>,
+    channel_count: usize,
+    xruns: Arc<AtomicU64>,
+    worst_shortfall: Arc<AtomicU64>,
+    /// Nanoseconds between `created` and the last xrun, plus one. 0 if none happened.
+    last_xrun: Arc<AtomicU64>,
+    created: Instant,
 }
 
 /// Reads the statistics of an instance of BufferedRenderer in a usable way.
@@ -51,6 +58,49 @@ impl BufferedRendererStatsReader {
         self.stats.render_size.load(Ordering::Relaxed)
     }
 
+    /// The number of render iterations the render thread keeps pre-rendered.
+    /// See `BufferedRenderer::set_render_ahead` for more information.
+    pub fn render_ahead(&self) -> usize {
+        self.stats.render_ahead.load(Ordering::Relaxed)
+    }
+
+    /// How full the render-ahead buffer is, from 0 to 1, based on the number
+    /// of samples buffered after the last read. Always 1 if render-ahead is
+    /// disabled.
+    pub fn render_ahead_fill(&self) -> f64 {
+        let target = self.stats.render_ahead.load(Ordering::Relaxed)
+            * self.stats.render_size.load(Ordering::Relaxed)
+            * self.stats.channel_count;
+        if target == 0 {
+            return 1.0;
+        }
+        let samples = self.last_samples_after_read().max(0);
+        (samples as f64 / target as f64).min(1.0)
+    }
+
+    /// The number of buffer underruns (xruns) so far, i.e. reads that
+    /// requested more samples than were rendered at the time.
+    pub fn xrun_count(&self) -> u64 {
+        self.stats.xruns.load(Ordering::Relaxed)
+    }
+
+    /// The largest number of samples missing in a single read so far.
+    pub fn worst_shortfall(&self) -> u64 {
+        self.stats.worst_shortfall.load(Ordering::Relaxed)
+    }
+
+    /// The time elapsed since the last buffer underrun, or `None` if there
+    /// hasn't been one.
+    pub fn time_since_last_xrun(&self) -> Option<Duration> {
+        match self.stats.last_xrun.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => {
+                let at = self.stats.created + Duration::from_nanos(nanos - 1);
+                Some(Instant::now().saturating_duration_since(at))
+            }
+        }
+    }
+
     /// The average render time percentages (0 to 1)
     /// of how long the render thread spent rendering, from the max allowed time.
     pub fn average_renderer_load(&self) -> f64 {
@@ -85,6 +135,8 @@ pub struct BufferedRenderer {
     /// The thread handle to wait for at the end.
     thread_handle: Option<JoinHandle<()>>,
     stream_params: AudioStreamParams,
+    /// The sample rate the render thread paces itself with.
+    sample_rate: Arc<AtomicU32>,
 }
 
 impl BufferedRenderer {
@@ -95,41 +147,64 @@ impl BufferedRenderer {
     /// - `stream_params`: Parameters of the output audio
     /// - `render_size`: The number of samples to render each iteration
     pub fn new<F: 'static + AudioPipe + Send>(
+        render: F,
+        stream_params: AudioStreamParams,
+        render_size: usize,
+    ) -> Self {
+        let channels = stream_params.channels.count() as usize;
+        Self::with_frame_channels(render, stream_params, render_size, channels)
+    }
+
+    /// Creates a new instance of BufferedRenderer whose frames have the given
+    /// number of interleaved channels, instead of the channel count of
+    /// `stream_params`, e.g. to render several stereo mixes side by side.
+    ///
+    /// See `BufferedRenderer::new` for the other parameters.
+    pub fn with_frame_channels<F: 'static + AudioPipe + Send>(
         mut render: F,
         stream_params: AudioStreamParams,
         render_size: usize,
+        channels: usize,
     ) -> Self {
         let (tx, rx) = unbounded();
 
         let samples = Arc::new(AtomicI64::new(0));
         let last_request_samples = Arc::new(AtomicI64::new(0));
         let render_size = Arc::new(AtomicUsize::new(render_size));
+        let render_ahead = Arc::new(AtomicUsize::new(0));
         let last_samples_after_read = Arc::new(AtomicI64::new(0));
         let render_time = Arc::new(RwLock::new(VecDeque::new()));
         let killed = Arc::new(RwLock::new(false));
+        let sample_rate = Arc::new(AtomicU32::new(stream_params.sample_rate));
 
         let thread_handle = {
             let samples = samples.clone();
             let last_request_samples = last_request_samples.clone();
             let render_size = render_size.clone();
+            let render_ahead = render_ahead.clone();
             let render_time = render_time.clone();
             let killed = killed.clone();
+            let sample_rate = sample_rate.clone();
             
             thread::Builder::new()
                 .name("xsynth_buffered_rendering".to_string())
                 .spawn(move || loop {
                     let size = render_size.load(Ordering::Relaxed);
 
+                    // The number of samples to keep buffered in render-ahead mode
+                    let ahead = (render_ahead.load(Ordering::Relaxed) * size * channels) as i64;
+
                     // The expected render time per iteration. It is slightly smaller (*90/100) than
                     // the real time so the render thread can catch up if it's behind.
-                    let delay =
-                        Duration::from_secs(1) * size as u32 / stream_params.sample_rate * 90 / 100;
+                    let sample_rate = sample_rate.load(Ordering::Relaxed);
+                    let delay = Duration::from_secs(1) * size as u32 / sample_rate * 90 / 100;
 
-                    // If the render thread is ahead by over ~10%, wait until more samples are required.
+                    // If the render thread is ahead by over ~10% (or over the render-ahead
+                    // target), wait until more samples are required.
                     loop {
                         let samples = samples.load(Ordering::Relaxed);
                         let last_requested = last_request_samples.load(Ordering::Relaxed);
-                        if samples > last_requested * 110 / 100 {
+                        if samples > (last_requested * 110 / 100).max(ahead) {
                             spin_sleep::sleep(delay / 10);
                         } else {
                             break;
@@ -144,8 +219,7 @@ impl BufferedRenderer {
                     let end = start + delay;
 
                     // Create the vec and write the samples
-                    let mut vec =
-                        vec![0.0f32; size * stream_params.channels.count() as usize];
+                    let mut vec = vec![0.0f32; size * channels];
                     render.read_samples(&mut vec);
 
                     // Send the samples, break if the pipe is broken
@@ -166,9 +240,9 @@ impl BufferedRenderer {
                         }
                     }
 
-                    // Sleep until the next iteration
+                    // Sleep until the next iteration, unless the render-ahead buffer needs refilling
                     let now = Instant::now();
-                    if end > now {
+                    if end > now && samples.load(Ordering::Relaxed) >= ahead {
                         spin_sleep::sleep(end - now);
                     }
                 })
@@ -181,18 +255,29 @@ impl BufferedRenderer {
                 last_request_samples,
                 render_time,
                 render_size,
+                render_ahead,
+                channel_count: channels,
+                xruns: Arc::new(AtomicU64::new(0)),
+                worst_shortfall: Arc::new(AtomicU64::new(0)),
+                last_xrun: Arc::new(AtomicU64::new(0)),
+                created: Instant::now(),
                 last_samples_after_read,
             },
             receive: rx,
             remainder: Vec::new(),
             stream_params,
+            sample_rate,
             thread_handle: Some(thread_handle),
             killed,
         }
     }
 
     /// Reads samples from the remainder and the output queue into the destination array.
-    pub fn read(&mut self, dest: &mut [f32]) {
+    ///
+    /// Returns the number of samples that were not rendered yet when the read
+    /// started (0 if there was no underrun). The read still waits for them, up
+    /// to a timeout, but the audio output was likely late.
+    pub fn read(&mut self, dest: &mut [f32]) -> usize {
         dest.fill(0.0);
 
         let mut i: usize = 0;
@@ -239,6 +324,18 @@ impl BufferedRenderer {
         self.stats
             .last_samples_after_read
             .store(samples, Ordering::Relaxed);
+
+        let shortfall = (dest.len() as i64 - samples.max(0)).max(0) as u64;
+        if shortfall > 0 {
+            self.stats.xruns.fetch_add(1, Ordering::Relaxed);
+            self.stats
+                .worst_shortfall
+                .fetch_max(shortfall, Ordering::Relaxed);
+            let nanos = self.stats.created.elapsed().as_nanos() as u64;
+            self.stats.last_xrun.store(nanos + 1, Ordering::Relaxed);
+        }
+
+        shortfall as usize
     }
 
     /// Sets the number of samples that should be rendered each iteration.
@@ -246,6 +343,27 @@ impl BufferedRenderer {
         self.stats.render_size.store(size, Ordering::Relaxed);
     }
 
+    /// Sets the sample rate of the rendered audio, e.g. after the render
+    /// function switched to another rate, so the render thread keeps pace
+    /// with the reader. The samples already rendered are read as they are.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.stream_params.sample_rate = sample_rate;
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+    }
+
+    /// Sets the number of render iterations that should be kept pre-rendered.
+    ///
+    /// This decouples the render timing jitter from the reader (e.g. the audio
+    /// device callback), at the cost of a fixed added latency of
+    /// `render_ahead * render_size` samples. Events still take effect at the
+    /// start of the next rendered iteration, so their relative timing is kept.
+    /// `0` disables render-ahead.
+    pub fn set_render_ahead(&self, render_ahead: usize) {
+        self.stats
+            .render_ahead
+            .store(render_ahead, Ordering::Relaxed);
+    }
+
     /// Returns a statistics reader.
     /// See the `BufferedRendererStatsReader` documentation for more information.
     pub fn get_buffer_stats(&self) -> BufferedRendererStatsReader {
@@ -268,6 +386,6 @@ impl AudioPipe for BufferedRenderer {
     }
 
     fn read_samples_unchecked(&mut self, to: &mut [f32]) {
-        self.read(to)
+        self.read(to);
     }
 }
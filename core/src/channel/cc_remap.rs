@@ -0,0 +1,102 @@
+use super::{ControlEvent, ControllerRemap, ControllerSource};
+
+/// Keeps the controller remaps of a channel and applies them to incoming
+/// control events.
+///
+/// Remapped values are applied directly and are not remapped again, so
+/// remaps can't loop.
+#[derive(Default)]
+pub struct ControllerRemapTable {
+    remaps: Vec<ControllerRemap>,
+}
+
+impl ControllerRemapTable {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_remaps(&mut self, remaps: Vec<ControllerRemap>) {
+        self.remaps = remaps;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaps.is_empty()
+    }
+
+    /// Returns the source and value of a control event that can be remapped.
+    pub fn source_of(event: &ControlEvent) -> Option<(ControllerSource, u8)> {
+        match *event {
+            ControlEvent::ChannelPressure(value) => {
+                Some((ControllerSource::ChannelPressure, value))
+            }
+            event => event
+                .to_raw()
+                .map(|(controller, value)| (ControllerSource::Controller(controller), value)),
+        }
+    }
+
+    /// Returns the remapped controller events for the given source, or
+    /// `None` if the source isn't remapped and should be applied as is.
+    pub fn remap(
+        &self,
+        source: ControllerSource,
+        value: u8,
+    ) -> Option<impl Iterator<Item = ControlEvent> + '_> {
+        if !self.remaps.iter().any(|r| r.source == source) {
+            return None;
+        }
+
+        Some(
+            self.remaps
+                .iter()
+                .filter(move |r| r.source == source)
+                .map(move |r| ControlEvent::Raw(r.target, r.apply(value))),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remapped(table: &ControllerRemapTable, event: ControlEvent) -> Option<Vec<ControlEvent>> {
+        let (source, value) = ControllerRemapTable::source_of(&event)?;
+        table.remap(source, value).map(|events| events.collect())
+    }
+
+    #[test]
+    fn test_remap_scale_and_invert() {
+        let mut table = ControllerRemapTable::new();
+        table.set_remaps(vec![
+            ControllerRemap::new(ControllerSource::Controller(2), 11),
+            ControllerRemap::new(ControllerSource::Controller(2), 1)
+                .with_scale(0.5)
+                .with_offset(10.0),
+            ControllerRemap::new(ControllerSource::ChannelPressure, 74).inverted(),
+        ]);
+
+        assert_eq!(
+            remapped(&table, ControlEvent::Raw(2, 100)),
+            Some(vec![ControlEvent::Raw(11, 100), ControlEvent::Raw(1, 60)])
+        );
+        assert_eq!(
+            remapped(&table, ControlEvent::ChannelPressure(27)),
+            Some(vec![ControlEvent::Raw(74, 100)])
+        );
+        assert_eq!(remapped(&table, ControlEvent::Raw(11, 100)), None);
+        assert_eq!(remapped(&table, ControlEvent::PitchBendValue(0.5)), None);
+
+        table.set_remaps(Vec::new());
+        assert!(table.is_empty());
+        assert_eq!(remapped(&table, ControlEvent::Raw(2, 100)), None);
+    }
+
+    #[test]
+    fn test_remap_clamps() {
+        let remap = ControllerRemap::new(ControllerSource::Controller(2), 11).with_scale(2.0);
+        assert_eq!(remap.apply(100), 127);
+
+        let remap = remap.with_scale(1.0).with_offset(-50.0);
+        assert_eq!(remap.apply(20), 0);
+    }
+}
@@ -1,12 +1,20 @@
-use std::{iter, ops::Deref, sync::Arc};
+use std::{
+    cell::RefCell,
+    iter, mem,
+    ops::{Deref, RangeInclusive},
+    sync::Arc,
+};
 
 use crate::{
-    helpers::are_arc_vecs_equal,
-    soundfont::SoundfontBase,
+    soundfont::{CcVoiceSpawner, SoundfontBase, VoiceSpawner},
+    util::{db_to_amp, DeferredDrops},
     voice::{Voice, VoiceControlData},
 };
 
-use super::voice_spawner::VoiceSpawnerMatrix;
+use super::{
+    voice_spawner::{ReleaseSpawners, VoiceSpawnerMatrix},
+    CoverageReport, ProgramMap, ProgramMapTarget, RoutedSoundfont,
+};
 
 #[derive(Default, PartialEq, Eq, Clone, Copy, Debug)]
 pub struct ProgramDescriptor {
@@ -14,10 +22,47 @@ pub struct ProgramDescriptor {
     pub preset: u8,
 }
 
+/// The maximum number of programs whose spawners are kept after switching
+/// to another program.
+const PROGRAM_CACHE_SIZE: usize = 8;
+
+/// The spawners resolved for a program.
+struct ProgramSpawners {
+    program: ProgramDescriptor,
+    matrix: VoiceSpawnerMatrix,
+    cc_spawners: Vec<CcVoiceSpawner>,
+}
+
 pub struct ChannelSoundfont {
-    soundfonts: Vec<Arc<dyn SoundfontBase>>,
+    soundfonts: Vec<RoutedSoundfont>,
+    program_map: Option<ProgramMap>,
     matrix: VoiceSpawnerMatrix,
+    cc_spawners: Vec<CcVoiceSpawner>,
     curr_program: ProgramDescriptor,
+
+    /// The width of the velocity layer crossfades, see
+    /// `ChannelConfigEvent::SetVelocityCrossfade`
+    velocity_crossfade: Option<u8>,
+
+    /// Plays the notes without regions, see `UnmatchedNotePolicy::Fallback`
+    fallback: Option<Arc<dyn SoundfontBase>>,
+
+    /// The spawners of recently used programs, most recent last. Switching
+    /// back to one of them doesn't resolve its spawners again.
+    program_cache: Vec<ProgramSpawners>,
+
+    /// Whether the soundfonts or the program map changed since the spawners
+    /// were resolved. The spawners are resolved again when the channel is
+    /// next rendered, so channels that stay idle don't do it right away.
+    needs_rebuild: bool,
+
+    /// The `SoundfontBase::generation` of each soundfont when the spawners
+    /// were resolved, in the order of `soundfonts()`
+    generations: Vec<u64>,
+
+    /// Drops the replaced soundfonts and spawners on a background thread,
+    /// as the last reference to a soundfont frees all of its samples.
+    deferred_drops: DeferredDrops,
 }
 
 impl Deref for ChannelSoundfont {
@@ -29,88 +74,424 @@ impl Deref for ChannelSoundfont {
     }
 }
 
+fn are_routed_soundfonts_equal(old: &[RoutedSoundfont], new: &[RoutedSoundfont]) -> bool {
+    old.len() == new.len()
+        && old.iter().zip(new.iter()).all(|(old, new)| {
+            Arc::ptr_eq(&old.soundfont, &new.soundfont)
+                && old.key_range == new.key_range
+                && old.vel_range == new.vel_range
+                && old.gain_db == new.gain_db
+        })
+}
+
+/// Finds the spawners of the first soundfont in the list that has regions for
+/// the given program, falling back to the replacement program if none do.
+fn find_spawners_in<'a, T>(
+    soundfonts: impl Clone + Iterator<Item = &'a RoutedSoundfont>,
+    program: ProgramDescriptor,
+    get: &impl Fn(&RoutedSoundfont, u8, u8) -> Vec<T>,
+) -> Vec<T> {
+    // If a preset/instr. is missing from all banks it will be muted,
+    // if a preset/instr. has regions in bank 0, all missing banks will be replaced by 0,
+    // if a preset/instr. has regions in any bank other than 0, all missing banks will be muted.
+    // For drum patches the same applies with bank and preset switched.
+
+    let bank = program.bank;
+    let preset = program.preset;
+
+    let find_replacement = || {
+        let (bank, preset) = if bank == 128 { (bank, 0) } else { (0, preset) };
+        soundfonts
+            .clone()
+            .map(|sf| get(sf, bank, preset))
+            .find(|vec| !vec.is_empty())
+    };
+
+    soundfonts
+        .clone()
+        .map(|sf| get(sf, bank, preset))
+        .chain(iter::once_with(find_replacement).flatten())
+        .find(|vec| !vec.is_empty())
+        .unwrap_or_default()
+}
+
+/// Applies the gain of a routed soundfont to its spawners.
+fn apply_routed_gain<'a>(
+    sf: &RoutedSoundfont,
+    spawners: impl Iterator<Item = &'a mut Box<dyn VoiceSpawner>>,
+) {
+    if sf.gain_db != 0.0 {
+        let gain = db_to_amp(sf.gain_db);
+        spawners.for_each(|spawner| spawner.apply_gain(gain));
+    }
+}
+
+/// The attack and release spawners of a note.
+type NoteSpawners = (Vec<Box<dyn VoiceSpawner>>, Vec<Box<dyn VoiceSpawner>>);
+
+/// Finds the spawners of the fallback soundfont for a note without regions,
+/// with the program of the channel or else bank 0, preset 0.
+fn fallback_spawners(
+    fallback: &Arc<dyn SoundfontBase>,
+    program: ProgramDescriptor,
+    key: u8,
+    vel: u8,
+) -> NoteSpawners {
+    let spawners = |bank, preset| {
+        (
+            fallback.get_attack_voice_spawners_at(bank, preset, key, vel),
+            fallback.get_release_voice_spawners_at(bank, preset, key, vel),
+        )
+    };
+    let found = spawners(program.bank, program.preset);
+    if found.0.is_empty() {
+        spawners(0, 0)
+    } else {
+        found
+    }
+}
+
 impl ChannelSoundfont {
-    pub fn new() -> Self {
+    pub fn new(deferred_drops: DeferredDrops) -> Self {
         ChannelSoundfont {
             soundfonts: Vec::new(),
+            program_map: None,
             matrix: VoiceSpawnerMatrix::new(),
+            cc_spawners: Vec::new(),
             curr_program: Default::default(),
+            velocity_crossfade: None,
+            fallback: None,
+            program_cache: Vec::new(),
+            needs_rebuild: false,
+            generations: Vec::new(),
+            deferred_drops,
+        }
+    }
+
+    pub fn set_soundfonts(&mut self, soundfonts: Vec<RoutedSoundfont>) {
+        if !are_routed_soundfonts_equal(&self.soundfonts, &soundfonts) {
+            let old = mem::replace(&mut self.soundfonts, soundfonts);
+            self.deferred_drops.drop_later(old);
+            self.clear_program_cache();
+            self.needs_rebuild = true;
+        }
+    }
+
+    /// Sets soundfonts without key or velocity ranges. Nothing is allocated
+    /// if they are the current ones.
+    pub fn set_unrouted_soundfonts(&mut self, soundfonts: &[Arc<dyn SoundfontBase>]) {
+        let unchanged = self.soundfonts.len() == soundfonts.len()
+            && self.soundfonts.iter().zip(soundfonts).all(|(old, new)| {
+                !old.is_ranged() && old.gain_db == 0.0 && Arc::ptr_eq(&old.soundfont, new)
+            });
+        if !unchanged {
+            let soundfonts = soundfonts.iter().cloned().map(RoutedSoundfont::from);
+            self.set_soundfonts(soundfonts.collect());
+        }
+    }
+
+    /// Returns the soundfonts of the channel, without their routing, and the
+    /// fallback soundfont last.
+    pub fn soundfonts(&self) -> impl Iterator<Item = &Arc<dyn SoundfontBase>> {
+        self.soundfonts
+            .iter()
+            .map(|sf| &sf.soundfont)
+            .chain(self.fallback.as_ref())
+    }
+
+    /// Replaces each soundfont, including the fallback soundfont, with the
+    /// one returned by `convert`, keeping its routing. The spawners are
+    /// resolved again if any of them changed.
+    pub fn convert_soundfonts(
+        &mut self,
+        convert: impl Fn(&Arc<dyn SoundfontBase>) -> Arc<dyn SoundfontBase>,
+    ) {
+        let soundfonts = self
+            .soundfonts
+            .iter()
+            .map(|sf| RoutedSoundfont {
+                soundfont: convert(&sf.soundfont),
+                ..sf.clone()
+            })
+            .collect();
+        self.set_soundfonts(soundfonts);
+
+        let fallback = self.fallback.as_ref().map(&convert);
+        self.set_fallback_soundfont(fallback);
+    }
+
+    pub fn set_program_map(&mut self, program_map: Option<ProgramMap>) {
+        if self.program_map != program_map {
+            self.program_map = program_map;
+            self.clear_program_cache();
+            self.needs_rebuild = true;
+        }
+    }
+
+    pub fn set_velocity_crossfade(&mut self, width: Option<u8>) {
+        if self.velocity_crossfade != width {
+            self.velocity_crossfade = width;
+            self.clear_program_cache();
+            self.needs_rebuild = true;
+        }
+    }
+
+    /// Sets the soundfont that plays the notes without regions in the other
+    /// soundfonts. See `UnmatchedNotePolicy::Fallback`.
+    pub fn set_fallback_soundfont(&mut self, fallback: Option<Arc<dyn SoundfontBase>>) {
+        let unchanged = match (&self.fallback, &fallback) {
+            (Some(old), Some(new)) => Arc::ptr_eq(old, new),
+            (None, None) => true,
+            _ => false,
+        };
+        if !unchanged {
+            if let Some(old) = mem::replace(&mut self.fallback, fallback) {
+                self.deferred_drops.drop_later(old);
+            }
+            self.clear_program_cache();
+            self.needs_rebuild = true;
+        }
+    }
+
+    /// The current program of the channel.
+    pub fn program(&self) -> ProgramDescriptor {
+        self.curr_program
+    }
+
+    fn clear_program_cache(&mut self) {
+        if !self.program_cache.is_empty() {
+            let cache = mem::take(&mut self.program_cache);
+            self.deferred_drops.drop_later(cache);
         }
     }
 
-    pub fn set_soundfonts(&mut self, soundfonts: Vec<Arc<dyn SoundfontBase>>) {
-        if !are_arc_vecs_equal(&self.soundfonts, &soundfonts) {
-            self.soundfonts = soundfonts;
+    /// Marks the spawners to be resolved again if any of the soundfonts was
+    /// edited in place since they were resolved. The cached programs are
+    /// outdated as well, so they are dropped.
+    fn check_generations(&mut self) {
+        let edited = !self
+            .soundfonts()
+            .map(|sf| sf.generation())
+            .eq(self.generations.iter().copied());
+        if edited {
+            self.clear_program_cache();
+            self.needs_rebuild = true;
+        }
+    }
+
+    /// Resolves the spawners again if the soundfonts or the program map
+    /// changed since they were last resolved.
+    pub fn rebuild_if_needed(&mut self) {
+        self.check_generations();
+        if self.needs_rebuild {
             self.rebuild_matrix();
         }
     }
 
     pub fn change_program(&mut self, program: ProgramDescriptor) {
-        if self.curr_program != program {
+        self.check_generations();
+        if self.needs_rebuild {
+            // The current spawners are outdated, so they aren't cached
             self.curr_program = program;
             self.rebuild_matrix();
+            return;
+        }
+
+        if self.curr_program == program {
+            return;
+        }
+
+        let cached = self
+            .program_cache
+            .iter()
+            .position(|p| p.program == program)
+            .map(|index| self.program_cache.remove(index));
+        let is_cached = cached.is_some();
+
+        let (matrix, cc_spawners) = match cached {
+            Some(cached) => (cached.matrix, cached.cc_spawners),
+            None => (VoiceSpawnerMatrix::new(), Vec::new()),
+        };
+        let previous = ProgramSpawners {
+            program: self.curr_program,
+            matrix: mem::replace(&mut self.matrix, matrix),
+            cc_spawners: mem::replace(&mut self.cc_spawners, cc_spawners),
+        };
+        if self.program_cache.len() >= PROGRAM_CACHE_SIZE {
+            self.program_cache.remove(0);
+        }
+        self.program_cache.push(previous);
+
+        self.curr_program = program;
+        if !is_cached {
+            self.rebuild_matrix();
         }
     }
 
-    fn rebuild_matrix(&mut self) {
-        // If a preset/instr. is missing from all banks it will be muted,
-        // if a preset/instr. has regions in bank 0, all missing banks will be replaced by 0,
-        // if a preset/instr. has regions in any bank other than 0, all missing banks will be muted.
-        // For drum patches the same applies with bank and preset switched.
+    fn find_spawners(
+        &self,
+        key: u8,
+        vel: u8,
+        get: impl Fn(&dyn SoundfontBase, u8, u8) -> Vec<Box<dyn VoiceSpawner>>,
+    ) -> Vec<Box<dyn VoiceSpawner>> {
+        let get = |sf: &RoutedSoundfont, bank: u8, preset: u8| {
+            let mut spawners = get(sf.soundfont.as_ref(), bank, preset);
+            apply_routed_gain(sf, spawners.iter_mut());
+            spawners
+        };
+
+        if let Some(program_map) = &self.program_map {
+            let program = self.curr_program;
+            return match program_map.get(program.bank, program.preset) {
+                ProgramMapTarget::Soundfont {
+                    index,
+                    bank,
+                    preset,
+                } => match self.soundfonts.get(index) {
+                    Some(sf) if sf.contains(key, vel) => get(sf, bank, preset),
+                    _ => Vec::new(),
+                },
+                ProgramMapTarget::Ignore => Vec::new(),
+            };
+        }
 
-        let bank = self.curr_program.bank;
-        let preset = self.curr_program.preset;
+        // Ranged soundfonts layer with each other
+        let mut layered = Vec::new();
+        for sf in self
+            .soundfonts
+            .iter()
+            .filter(|sf| sf.is_ranged() && sf.contains(key, vel))
+        {
+            layered.extend(find_spawners_in(iter::once(sf), self.curr_program, &get));
+        }
+        if !layered.is_empty() {
+            return layered;
+        }
+
+        let unranged = self.soundfonts.iter().filter(|sf| !sf.is_ranged());
+        find_spawners_in(unranged, self.curr_program, &get)
+    }
+
+    /// Finds the controller triggered spawners of the current program. They
+    /// don't depend on a key, so key and velocity ranges are ignored.
+    fn find_cc_spawners(&self) -> Vec<CcVoiceSpawner> {
+        let get = |sf: &RoutedSoundfont, bank: u8, preset: u8| {
+            let mut spawners = sf.soundfont.get_cc_voice_spawners_at(bank, preset);
+            apply_routed_gain(sf, spawners.iter_mut().map(|s| &mut s.spawner));
+            spawners
+        };
+
+        if let Some(program_map) = &self.program_map {
+            let program = self.curr_program;
+            return match program_map.get(program.bank, program.preset) {
+                ProgramMapTarget::Soundfont {
+                    index,
+                    bank,
+                    preset,
+                } => match self.soundfonts.get(index) {
+                    Some(sf) => get(sf, bank, preset),
+                    None => Vec::new(),
+                },
+                ProgramMapTarget::Ignore => Vec::new(),
+            };
+        }
+
+        find_spawners_in(self.soundfonts.iter(), self.curr_program, &get)
+    }
+
+    /// Checks which soundfonts have attack regions for the cells of the
+    /// given keys with the current program, and which of them are played.
+    /// See `VoiceChannel::coverage_report`.
+    pub fn coverage_report(&self, keys: RangeInclusive<u8>) -> CoverageReport {
+        let program = self.curr_program;
+        let mut report = CoverageReport::new(
+            program.bank,
+            program.preset,
+            keys.clone(),
+            self.soundfonts.len(),
+        );
+        let index_of = |sf: &dyn SoundfontBase| {
+            let sf = sf as *const _ as *const ();
+            self.soundfonts
+                .iter()
+                .position(|routed| Arc::as_ptr(&routed.soundfont) as *const () == sf)
+        };
+
+        for key in keys {
+            for vel in 1..=127u8 {
+                // Only the soundfonts that are played return any spawners
+                let played = RefCell::new(Vec::new());
+                let spawners = self.find_spawners(key, vel, |sf, bank, preset| {
+                    let spawners = sf.get_attack_voice_spawners_at(bank, preset, key, vel);
+                    if !spawners.is_empty() {
+                        played.borrow_mut().extend(index_of(sf));
+                    }
+                    spawners
+                });
+                if spawners.is_empty() {
+                    report.add_hole(key, vel);
+                }
+
+                let played = played.into_inner();
+                for (index, sf) in self.soundfonts.iter().enumerate() {
+                    let coverage = &mut report.soundfonts[index];
+                    if played.contains(&index) {
+                        coverage.played_cells += 1;
+                    } else if sf.contains(key, vel)
+                        && !sf
+                            .soundfont
+                            .get_attack_voice_spawners_at(program.bank, program.preset, key, vel)
+                            .is_empty()
+                    {
+                        coverage.shadowed_cells += 1;
+                    }
+                }
+            }
+        }
+        report
+    }
+
+    fn rebuild_matrix(&mut self) {
+        self.needs_rebuild = false;
+        self.generations = self.soundfonts().map(|sf| sf.generation()).collect();
+        let mut matrix = VoiceSpawnerMatrix::new();
+        let cc_spawners = self.find_cc_spawners();
+        let program = self.curr_program;
+        let ignored = self
+            .program_map
+            .as_ref()
+            .is_some_and(|map| map.get(program.bank, program.preset) == ProgramMapTarget::Ignore);
 
         for k in 0..128u8 {
             for v in 0..128u8 {
-                let find_replacement_attack = || {
-                    if bank == 128 {
-                        self.soundfonts
-                            .iter()
-                            .map(|sf| sf.get_attack_voice_spawners_at(bank, 0, k, v))
-                            .find(|vec| !vec.is_empty())
-                    } else {
-                        self.soundfonts
-                            .iter()
-                            .map(|sf| sf.get_attack_voice_spawners_at(0, preset, k, v))
-                            .find(|vec| !vec.is_empty())
-                    }
-                };
-
-                let attack_spawners = self
-                    .soundfonts
-                    .iter()
-                    .map(|sf| sf.get_attack_voice_spawners_at(bank, preset, k, v))
-                    .chain(iter::once_with(find_replacement_attack).flatten())
-                    .find(|vec| !vec.is_empty())
-                    .unwrap_or_default();
-
-                let find_replacement_release = || {
-                    if bank == 128 {
-                        self.soundfonts
-                            .iter()
-                            .map(|sf| sf.get_release_voice_spawners_at(bank, 0, k, v))
-                            .find(|vec| !vec.is_empty())
-                    } else {
-                        self.soundfonts
-                            .iter()
-                            .map(|sf| sf.get_release_voice_spawners_at(0, preset, k, v))
-                            .find(|vec| !vec.is_empty())
+                let mut attack_spawners =
+                    self.find_spawners(k, v, |sf, bank, preset| match self.velocity_crossfade {
+                        Some(width) => {
+                            sf.get_crossfaded_attack_voice_spawners_at(bank, preset, k, v, width)
+                        }
+                        None => sf.get_attack_voice_spawners_at(bank, preset, k, v),
+                    });
+                let mut release_spawners = self.find_spawners(k, v, |sf, bank, preset| {
+                    sf.get_release_voice_spawners_at(bank, preset, k, v)
+                });
+
+                if attack_spawners.is_empty() && !ignored {
+                    matrix.set_unmatched(k, v);
+                    if let Some(fallback) = &self.fallback {
+                        (attack_spawners, release_spawners) =
+                            fallback_spawners(fallback, program, k, v);
                     }
-                };
-
-                let release_spawners = self
-                    .soundfonts
-                    .iter()
-                    .map(|sf| sf.get_release_voice_spawners_at(bank, preset, k, v))
-                    .chain(iter::once_with(find_replacement_release).flatten())
-                    .find(|vec| !vec.is_empty())
-                    .unwrap_or_default();
-
-                self.matrix.set_spawners_attack(k, v, attack_spawners);
-                self.matrix.set_spawners_release(k, v, release_spawners);
+                }
+
+                matrix.set_spawners_attack(k, v, attack_spawners);
+                matrix.set_spawners_release(k, v, release_spawners);
             }
         }
+
+        let old_matrix = mem::replace(&mut self.matrix, matrix);
+        let old_cc_spawners = mem::replace(&mut self.cc_spawners, cc_spawners);
+        self.deferred_drops
+            .drop_later((old_matrix, old_cc_spawners));
     }
 
     pub fn spawn_voices_attack<'a>(
@@ -122,12 +503,229 @@ impl ChannelSoundfont {
         self.matrix.spawn_voices_attack(control, key, vel)
     }
 
-    pub fn spawn_voices_release<'a>(
+    /// Returns the release spawners of the current program for the key and
+    /// velocity, to be kept with the voices of a note until its note off.
+    pub fn release_spawners(&self, key: u8, vel: u8) -> Option<ReleaseSpawners> {
+        self.matrix.release_spawners_at(key, vel).cloned()
+    }
+
+    /// Spawns the voices triggered by a controller changing from `previous`
+    /// to `value`.
+    pub fn spawn_voices_cc<'a>(
         &'a self,
         control: &'a VoiceControlData,
-        key: u8,
-        vel: u8,
+        controller: u8,
+        previous: u8,
+        value: u8,
     ) -> impl Iterator<Item = Box<dyn Voice>> + 'a {
-        self.matrix.spawn_voices_release(control, key, vel)
+        self.cc_spawners
+            .iter()
+            .filter(move |s| s.controller == controller && s.is_triggered(previous, value))
+            .filter(move |s| s.spawner.matches(control))
+            .map(move |s| s.spawner.spawn_voice(control))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicU64, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::test_soundfont::TestSoundfont;
+
+    fn spawned_ids(channel_sf: &mut ChannelSoundfont, key: u8, vel: u8) -> Vec<u8> {
+        channel_sf.rebuild_if_needed();
+        let control = VoiceControlData::new_defaults();
+        channel_sf
+            .spawn_voices_attack(&control, key, vel)
+            .map(|voice| voice.velocity())
+            .collect()
+    }
+
+    #[test]
+    fn test_key_range_split() {
+        let mut channel_sf = ChannelSoundfont::new(Default::default());
+        channel_sf.set_soundfonts(vec![
+            RoutedSoundfont::new(TestSoundfont::new_arc(1)).with_key_range(0..=59),
+            RoutedSoundfont::new(TestSoundfont::new_arc(2)).with_key_range(60..=127),
+        ]);
+
+        assert_eq!(spawned_ids(&mut channel_sf, 59, 100), vec![1]);
+        assert_eq!(spawned_ids(&mut channel_sf, 60, 100), vec![2]);
+    }
+
+    #[test]
+    fn test_ranges_layer_and_fall_back() {
+        let mut channel_sf = ChannelSoundfont::new(Default::default());
+        channel_sf.set_soundfonts(vec![
+            RoutedSoundfont::new(TestSoundfont::new_arc(1)).with_key_range(0..=70),
+            RoutedSoundfont::new(TestSoundfont::new_arc(2)).with_key_range(50..=100),
+            RoutedSoundfont::new(TestSoundfont::new_arc(3)).with_vel_range(0..=63),
+            RoutedSoundfont::new(TestSoundfont::new_arc(4)),
+            RoutedSoundfont::new(TestSoundfont::new_arc(5)),
+        ]);
+
+        assert_eq!(spawned_ids(&mut channel_sf, 60, 100), vec![1, 2]);
+        assert_eq!(spawned_ids(&mut channel_sf, 110, 20), vec![3]);
+        assert_eq!(spawned_ids(&mut channel_sf, 110, 100), vec![4]);
+    }
+
+    #[test]
+    fn test_routed_gain() {
+        let mut channel_sf = ChannelSoundfont::new(Default::default());
+        channel_sf.set_soundfonts(vec![
+            RoutedSoundfont::new(TestSoundfont::new_arc(1))
+                .with_key_range(0..=59)
+                .with_gain_db(-20.0),
+            RoutedSoundfont::new(TestSoundfont::new_arc(2)).with_key_range(60..=127),
+        ]);
+        channel_sf.rebuild_if_needed();
+
+        let control = VoiceControlData::new_defaults();
+        let amplitudes = |key| {
+            channel_sf
+                .spawn_voices_attack(&control, key, 100)
+                .map(|voice| voice.amplitude())
+                .collect::<Vec<_>>()
+        };
+        assert!((amplitudes(40)[0] - 0.1).abs() < 1e-6);
+        assert_eq!(amplitudes(80), vec![1.0]);
+
+        // The gain applies to the controller triggered voices as well
+        let cc_amplitude = channel_sf
+            .spawn_voices_cc(&control, 64, 0, 127)
+            .map(|voice| voice.amplitude())
+            .collect::<Vec<_>>();
+        assert!((cc_amplitude[0] - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_program_cache() {
+        let mut channel_sf = ChannelSoundfont::new(Default::default());
+        channel_sf.set_soundfonts(vec![TestSoundfont::new_arc(1).into()]);
+
+        let program = |preset| ProgramDescriptor { bank: 0, preset };
+
+        channel_sf.change_program(program(5));
+        assert_eq!(spawned_ids(&mut channel_sf, 60, 100), Vec::<u8>::new());
+        channel_sf.change_program(program(0));
+        assert_eq!(spawned_ids(&mut channel_sf, 60, 100), vec![1]);
+
+        // Cached programs are dropped when the soundfonts change
+        channel_sf.change_program(program(5));
+        channel_sf.set_soundfonts(vec![TestSoundfont::new_arc(2).into()]);
+        channel_sf.change_program(program(0));
+        assert_eq!(spawned_ids(&mut channel_sf, 60, 100), vec![2]);
+    }
+
+    #[test]
+    fn test_program_map() {
+        let mut channel_sf = ChannelSoundfont::new(Default::default());
+        channel_sf.set_soundfonts(vec![
+            TestSoundfont::new_arc(1).into(),
+            TestSoundfont::new_arc(2).into(),
+        ]);
+
+        let mut program_map = ProgramMap::default();
+        program_map.programs.insert(
+            (0, 33),
+            ProgramMapTarget::Soundfont {
+                index: 1,
+                bank: 0,
+                preset: 0,
+            },
+        );
+        program_map
+            .programs
+            .insert((0, 40), ProgramMapTarget::Ignore);
+        channel_sf.set_program_map(Some(program_map));
+
+        let program = |preset| ProgramDescriptor { bank: 0, preset };
+
+        channel_sf.change_program(program(33));
+        assert_eq!(spawned_ids(&mut channel_sf, 60, 100), vec![2]);
+
+        channel_sf.change_program(program(40));
+        assert_eq!(spawned_ids(&mut channel_sf, 60, 100), Vec::<u8>::new());
+
+        channel_sf.change_program(program(5));
+        assert_eq!(spawned_ids(&mut channel_sf, 60, 100), vec![1]);
+
+        // Without the map, the SFZ-like soundfonts have nothing on preset 5
+        channel_sf.set_program_map(None);
+        assert_eq!(spawned_ids(&mut channel_sf, 60, 100), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_cc_triggered_spawners() {
+        let mut channel_sf = ChannelSoundfont::new(Default::default());
+        channel_sf.set_soundfonts(vec![TestSoundfont::new_arc(1).into()]);
+        channel_sf.rebuild_if_needed();
+
+        let control = VoiceControlData::new_defaults();
+        let spawned = |controller, previous, value| {
+            channel_sf
+                .spawn_voices_cc(&control, controller, previous, value)
+                .count()
+        };
+
+        // Only moving the controller into the range triggers the spawner
+        assert_eq!(spawned(64, 0, 127), 1);
+        assert_eq!(spawned(64, 100, 127), 0);
+        assert_eq!(spawned(64, 127, 0), 0);
+        assert_eq!(spawned(11, 0, 127), 0);
+    }
+
+    #[test]
+    fn test_identical_soundfonts_skip_rebuild() {
+        let soundfont = Arc::new(TestSoundfont::new(1));
+        let soundfonts: Vec<Arc<dyn SoundfontBase>> = vec![soundfont.clone()];
+        let rebuilds = || soundfont.cc_lookups.load(Ordering::Relaxed);
+
+        let mut channel_sf = ChannelSoundfont::new(Default::default());
+        channel_sf.set_unrouted_soundfonts(&soundfonts);
+        assert_eq!(rebuilds(), 0);
+        channel_sf.rebuild_if_needed();
+        assert_eq!(rebuilds(), 1);
+
+        // A new list with the same soundfonts does no work at all
+        let same: Vec<Arc<dyn SoundfontBase>> = vec![soundfont.clone()];
+        channel_sf.set_unrouted_soundfonts(&same);
+        channel_sf.rebuild_if_needed();
+        channel_sf.change_program(ProgramDescriptor::default());
+        assert_eq!(rebuilds(), 1);
+        assert_eq!(spawned_ids(&mut channel_sf, 60, 100), vec![1]);
+
+        // Changed soundfonts are only resolved once, when needed
+        channel_sf.set_unrouted_soundfonts(&[]);
+        channel_sf.set_unrouted_soundfonts(&soundfonts);
+        channel_sf.change_program(ProgramDescriptor::default());
+        assert_eq!(rebuilds(), 2);
+    }
+
+    #[test]
+    fn test_replaced_soundfonts_are_dropped_later() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut soundfont = TestSoundfont::new(1);
+        soundfont.on_drop = Some(sender);
+        let soundfont: Arc<dyn SoundfontBase> = Arc::new(soundfont);
+
+        let deferred = Arc::new(AtomicU64::new(0));
+        let mut channel_sf = ChannelSoundfont::new(DeferredDrops::new(deferred.clone()));
+        channel_sf.set_unrouted_soundfonts(&[soundfont]);
+        channel_sf.rebuild_if_needed();
+        assert_eq!(spawned_ids(&mut channel_sf, 60, 100), vec![1]);
+
+        // The channel holds the last reference, so replacing the soundfont
+        // frees it, which must not happen on the calling thread
+        channel_sf.set_unrouted_soundfonts(&[]);
+        channel_sf.rebuild_if_needed();
+        let thread = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_ne!(thread, thread::current().id());
+        assert!(deferred.load(Ordering::Relaxed) > 0);
     }
 }
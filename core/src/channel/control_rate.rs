@@ -0,0 +1,174 @@
+use crate::voice::VoiceControlData;
+
+/// How often the controls of a channel's voices, such as the pitch, are
+/// evaluated while rendering.
+///
+/// The volume, expression, pan and cutoff of a channel are smoothed per
+/// sample and aren't affected by this setting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ControlRate {
+    /// Control changes are applied to the voices as they are received, so
+    /// they change once per rendered buffer. Fast pitch bend streams may
+    /// sound stepped with large buffers.
+    #[default]
+    PerBuffer,
+
+    /// Control changes are ramped across the next rendered buffer, and the
+    /// voices are updated every given number of frames. Lower values sound
+    /// smoother but cost more, values around the SIMD width (e.g. `8`) are
+    /// the smoothest worth using.
+    Frames(usize),
+}
+
+/// Ramps the voice controls of a channel across the rendered buffers at the
+/// configured control rate.
+pub(super) struct ControlRamp {
+    rate: ControlRate,
+
+    /// The pitch multiplier the voices were last updated with
+    pitch: f32,
+
+    /// The controls of each block of the buffer being rendered. Empty if
+    /// the controls don't change during the buffer.
+    blocks: Vec<VoiceControlData>,
+
+    /// The length of each block, in frames
+    block_frames: usize,
+}
+
+impl ControlRamp {
+    pub fn new(rate: ControlRate, control: &VoiceControlData) -> Self {
+        Self {
+            rate,
+            pitch: control.voice_pitch_multiplier,
+            blocks: Vec::new(),
+            block_frames: 0,
+        }
+    }
+
+    /// Returns the controls the voices should be updated with right away
+    /// when the channel's controls change. With a control rate, the pitch
+    /// is kept until it is ramped while rendering.
+    pub fn current(&mut self, target: &VoiceControlData) -> VoiceControlData {
+        match self.rate {
+            ControlRate::PerBuffer => {
+                self.pitch = target.voice_pitch_multiplier;
+                *target
+            }
+            ControlRate::Frames(_) => VoiceControlData {
+                voice_pitch_multiplier: self.pitch,
+                ..*target
+            },
+        }
+    }
+
+    /// Plans the control blocks of a buffer with the given number of frames,
+    /// ramping from the current controls to `target`.
+    pub fn start_buffer(&mut self, target: &VoiceControlData, frames: usize) {
+        self.blocks.clear();
+
+        let ControlRate::Frames(block_frames) = self.rate else {
+            return;
+        };
+        if self.pitch == target.voice_pitch_multiplier || frames == 0 {
+            return;
+        }
+
+        self.block_frames = block_frames.max(1);
+        let count = frames.div_ceil(self.block_frames);
+
+        // The pitch is ramped linearly in semitones
+        let ratio = target.voice_pitch_multiplier / self.pitch;
+        for i in 1..=count {
+            let progress = i as f32 / count as f32;
+            self.blocks.push(VoiceControlData {
+                voice_pitch_multiplier: self.pitch * ratio.powf(progress),
+                ..*target
+            });
+        }
+        self.pitch = target.voice_pitch_multiplier;
+    }
+
+    /// Returns the controls of each block of the buffer being rendered, or
+    /// an empty slice if the whole buffer can be rendered at once.
+    pub fn blocks(&self) -> &[VoiceControlData] {
+        &self.blocks
+    }
+
+    /// Returns the length of each block of the buffer being rendered, in
+    /// samples with the given channel count.
+    pub fn block_len(&self, channels: usize) -> usize {
+        self.block_frames * channels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pitch_control(semitones: f32) -> VoiceControlData {
+        VoiceControlData {
+            voice_pitch_multiplier: 2.0f32.powf(semitones / 12.0),
+            ..VoiceControlData::new_defaults()
+        }
+    }
+
+    /// Returns the largest pitch change between two updates of the voices
+    /// while rendering a 10 Hz vibrato of 2 semitones, in semitones.
+    fn largest_pitch_step(rate: ControlRate) -> f32 {
+        let frames = 2048;
+        let mut ramp = ControlRamp::new(rate, &pitch_control(0.0));
+        let mut last = 0.0;
+        let mut largest: f32 = 0.0;
+
+        for buffer in 1..=48 {
+            let time = (buffer * frames) as f32 / 48000.0;
+            let target = pitch_control(2.0 * (time * 10.0 * std::f32::consts::TAU).sin());
+            ramp.start_buffer(&target, frames);
+            let updates: Vec<f32> = match rate {
+                ControlRate::PerBuffer => vec![ramp.current(&target).voice_pitch_multiplier],
+                ControlRate::Frames(_) => ramp
+                    .blocks()
+                    .iter()
+                    .map(|c| c.voice_pitch_multiplier)
+                    .collect(),
+            };
+
+            for pitch in updates {
+                let semitones = 12.0 * pitch.log2();
+                largest = largest.max((semitones - last).abs());
+                last = semitones;
+            }
+        }
+
+        largest
+    }
+
+    #[test]
+    fn test_control_rate_ramps_pitch() {
+        let stepped = largest_pitch_step(ControlRate::PerBuffer);
+        let ramped = largest_pitch_step(ControlRate::Frames(64));
+        assert!(stepped > 1.0);
+        assert!(ramped < stepped / 16.0);
+    }
+
+    #[test]
+    fn test_blocks_reach_target() {
+        let mut ramp = ControlRamp::new(ControlRate::Frames(64), &pitch_control(0.0));
+        let target = pitch_control(12.0);
+
+        // The voices keep their pitch until the buffer is rendered
+        assert_eq!(ramp.current(&target).voice_pitch_multiplier, 1.0);
+
+        ramp.start_buffer(&target, 1000);
+        assert_eq!(ramp.blocks().len(), 16);
+        assert_eq!(ramp.block_len(2), 128);
+        assert!((ramp.blocks()[7].voice_pitch_multiplier - 2.0f32.powf(0.5)).abs() < 1e-4);
+        assert_eq!(ramp.blocks()[15].voice_pitch_multiplier, 2.0);
+
+        // Nothing is ramped once the target is reached
+        ramp.start_buffer(&target, 1000);
+        assert!(ramp.blocks().is_empty());
+    }
+}
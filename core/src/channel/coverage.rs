@@ -0,0 +1,171 @@
+use std::{fmt::Write, ops::RangeInclusive};
+
+/// The key and velocity coverage of the soundfonts of a channel for its
+/// current program, e.g. to check a layered setup has no holes before a
+/// performance. See `VoiceChannel::coverage_report`.
+///
+/// Cells are the (key, velocity) pairs of note ons. Velocity 0 is a note
+/// off, so only velocities 1 to 127 are checked. A cell is covered if a
+/// soundfont has attack regions for it, regardless of their controller
+/// conditions and triggers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoverageReport {
+    /// The bank of the checked program.
+    pub bank: u8,
+
+    /// The preset of the checked program.
+    pub preset: u8,
+
+    /// The checked keys.
+    pub keys: RangeInclusive<u8>,
+
+    /// The cells that no soundfont has a region for, merged into ranges of
+    /// velocities of the same key. Sorted by key and velocity.
+    pub holes: Vec<CoverageHole>,
+
+    /// The coverage of each soundfont of the channel, in the order of the
+    /// soundfont list.
+    pub soundfonts: Vec<SoundfontCoverage>,
+}
+
+/// A range of velocities of a key that no soundfont has a region for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoverageHole {
+    pub key: u8,
+    pub velocities: RangeInclusive<u8>,
+}
+
+/// The cells a soundfont of a channel is played for, and the cells it has
+/// regions for that are never played because other soundfonts take
+/// precedence, see `RoutedSoundfont`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SoundfontCoverage {
+    /// The number of cells the soundfont is played for.
+    pub played_cells: u32,
+
+    /// The number of cells the soundfont has regions for, but is shadowed
+    /// by soundfonts of higher priority.
+    pub shadowed_cells: u32,
+}
+
+impl SoundfontCoverage {
+    /// Returns true if the soundfont has regions for the checked cells but
+    /// is never played.
+    pub fn is_shadowed(&self) -> bool {
+        self.played_cells == 0 && self.shadowed_cells > 0
+    }
+}
+
+impl CoverageReport {
+    pub(super) fn new(bank: u8, preset: u8, keys: RangeInclusive<u8>, soundfonts: usize) -> Self {
+        Self {
+            bank,
+            preset,
+            keys,
+            holes: Vec::new(),
+            soundfonts: vec![Default::default(); soundfonts],
+        }
+    }
+
+    /// Adds a cell without any regions. Cells are added in order.
+    pub(super) fn add_hole(&mut self, key: u8, vel: u8) {
+        if let Some(hole) = self.holes.last_mut() {
+            if hole.key == key && *hole.velocities.end() + 1 == vel {
+                hole.velocities = *hole.velocities.start()..=vel;
+                return;
+            }
+        }
+        self.holes.push(CoverageHole {
+            key,
+            velocities: vel..=vel,
+        });
+    }
+
+    /// Returns true if every checked cell has a region.
+    pub fn is_complete(&self) -> bool {
+        self.holes.is_empty()
+    }
+
+    /// Returns the number of cells without regions.
+    pub fn hole_cells(&self) -> usize {
+        self.holes.iter().map(|hole| hole.velocities.len()).sum()
+    }
+
+    /// Returns a summary of the report with one line per hole and per
+    /// shadowed soundfont, e.g. to print it or log it.
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "Bank {}, preset {}, keys {}-{}: ",
+            self.bank,
+            self.preset,
+            self.keys.start(),
+            self.keys.end()
+        );
+        if self.is_complete() {
+            summary.push_str("all cells have regions\n");
+        } else {
+            writeln!(summary, "{} cells without regions", self.hole_cells()).unwrap();
+            for hole in &self.holes {
+                let (start, end) = (hole.velocities.start(), hole.velocities.end());
+                writeln!(summary, "  key {}: velocities {start}-{end}", hole.key).unwrap();
+            }
+        }
+
+        for (index, soundfont) in self.soundfonts.iter().enumerate() {
+            if soundfont.shadowed_cells == 0 {
+                continue;
+            }
+            write!(
+                summary,
+                "Soundfont {index}: played for {} cells, shadowed for {} cells",
+                soundfont.played_cells, soundfont.shadowed_cells
+            )
+            .unwrap();
+            if soundfont.is_shadowed() {
+                summary.push_str(" (never played)");
+            }
+            summary.push('\n');
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_holes() {
+        let mut report = CoverageReport::new(0, 0, 60..=61, 2);
+        for vel in [1, 2, 3, 10] {
+            report.add_hole(60, vel);
+        }
+        report.add_hole(61, 11);
+        report.soundfonts[1].shadowed_cells = 4;
+
+        assert_eq!(
+            report.holes,
+            [
+                CoverageHole {
+                    key: 60,
+                    velocities: 1..=3
+                },
+                CoverageHole {
+                    key: 60,
+                    velocities: 10..=10
+                },
+                CoverageHole {
+                    key: 61,
+                    velocities: 11..=11
+                },
+            ]
+        );
+        assert_eq!(report.hole_cells(), 5);
+        assert_eq!(
+            report.summary(),
+            "Bank 0, preset 0, keys 60-61: 5 cells without regions\n  \
+             key 60: velocities 1-3\n  key 60: velocities 10-10\n  key 61: velocities 11-11\n\
+             Soundfont 1: played for 0 cells, shadowed for 4 cells (never played)\n"
+        );
+    }
+}
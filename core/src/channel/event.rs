@@ -1,6 +1,12 @@
-use std::sync::Arc;
+use std::{collections::HashMap, fmt, ops::RangeInclusive, sync::Arc};
 
-use crate::soundfont::SoundfontBase;
+use super::{ChannelLfo, NoteSpawnHook, SampleRateChange};
+use crate::{
+    effects::{AudioInsert, SidechainTap},
+    soundfont::SoundfontBase,
+    util::{db_to_amp, key_to_name},
+    voice::ReleaseTimeScales,
+};
 
 /// MIDI events for a single key in a channel.
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -9,6 +15,14 @@ pub enum KeyNoteEvent {
     /// Starts a new note voice with a velocity
     On(u8),
 
+    /// Starts a new note voice with a velocity while other notes are held
+    /// on the channel
+    LegatoOn(u8),
+
+    /// Starts a new note voice with a velocity, with its pitch offset by
+    /// `cents`. `legato` is true if other notes are held on the channel.
+    DetunedOn { vel: u8, cents: f32, legato: bool },
+
     /// Signals off to a note voice
     Off,
 
@@ -23,16 +37,439 @@ pub enum KeyNoteEvent {
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum ChannelConfigEvent {
-    /// Sets the soundfonts for the channel
+    /// Sets the soundfonts for the channel. The list is shared, so sending
+    /// it to all channels doesn't copy it for each one, and channels that
+    /// already use the same soundfonts keep their resolved spawners.
     #[cfg_attr(feature = "serde", serde(skip))]
-    SetSoundfonts(Vec<Arc<dyn SoundfontBase>>),
+    SetSoundfonts(Arc<[Arc<dyn SoundfontBase>]>),
+
+    /// Sets the soundfonts for the channel, each with optional key and
+    /// velocity ranges. See the `RoutedSoundfont` documentation for more
+    /// information.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    SetRoutedSoundfonts(Vec<RoutedSoundfont>),
+
+    /// Sets the soundfont that plays the note ons without regions in the
+    /// channel's soundfonts, or removes it with `None`. Only used with
+    /// `UnmatchedNotePolicy::Fallback`, see its documentation for more
+    /// information.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    SetFallbackSoundfont(Option<Arc<dyn SoundfontBase>>),
+
+    /// Sets a table that maps program changes to specific soundfonts of the
+    /// channel. `None` disables the mapping and restores the standard
+    /// bank/preset lookup. See the `ProgramMap` documentation for more
+    /// information.
+    SetProgramMap(Option<ProgramMap>),
 
     /// Sets the layer count for the soundfont
     SetLayerCount(Option<usize>),
 
+    /// Crossfades between adjacent velocity layers of the soundfonts, so
+    /// the timbre doesn't jump at the layer boundaries. Notes within `width`
+    /// velocity units around a boundary spawn the voices of both layers,
+    /// with gains that depend on their distance from the boundary. `None`
+    /// switches the layers at their boundaries.
+    ///
+    /// The voices of both layers count against the layer count. The SFZ
+    /// `xfin`/`xfout` opcodes aren't supported, so this applies to all
+    /// soundfonts.
+    ///
+    /// Default: `None`
+    SetVelocityCrossfade(Option<u8>),
+
     /// Controls whether the channel will be standard or percussion.
     /// Setting to `true` will make the channel only use percussion patches.
     SetPercussionMode(bool),
+
+    /// Shifts the keys of all following notes by the given amount of semitones
+    /// (use multiples of 12 for an octave shift). Held notes are released on
+    /// the key they were started with, even if the transpose changes meanwhile.
+    SetTranspose(i8),
+
+    /// Controls what happens to notes that are transposed outside of the
+    /// 0-127 key range. Setting to `true` will clamp them to the nearest
+    /// valid key, while `false` will drop them.
+    ///
+    /// Default: `false`
+    SetTransposeClamp(bool),
+
+    /// Sets the release time multipliers of the volume envelope for each
+    /// release type, e.g. for a longer release when the damper pedal is
+    /// lifted than when a key is released. Applies to held notes as well.
+    /// See the `ReleaseTimeScales` documentation for more information.
+    ///
+    /// Default: `1.0` for all release types
+    SetReleaseTimeScales(ReleaseTimeScales),
+
+    /// Tunes the channel to the given reference pitch of A4, in Hz.
+    /// Invalid pitches (zero, negative or not finite) are ignored.
+    ///
+    /// If `retune_sounding` is `false`, only the notes started afterwards
+    /// use the new tuning, while the held notes keep their pitch. Otherwise
+    /// the held notes are retuned as well.
+    ///
+    /// Default: `440.0`
+    SetTuning { a4: f32, retune_sounding: bool },
+
+    /// Sets how the expression (CC11) is converted to a gain. Only the
+    /// expression values received afterwards use the new curve. See the
+    /// `ExpressionCurve` documentation for the available curves.
+    ///
+    /// Default: `ExpressionCurve::Square`
+    SetExpressionCurve(ExpressionCurve),
+
+    /// Sets the vibrato LFO of the channel, or removes it with `None`. Its
+    /// depth follows the modulation wheel. See the `ChannelLfo`
+    /// documentation for more information.
+    ///
+    /// Default: `None`
+    SetLfo(Option<ChannelLfo>),
+
+    /// Sets the controller remaps of the channel, which are applied to the
+    /// incoming control events before the channel handles them. An empty
+    /// list removes all remaps. See the `ControllerRemap` documentation for
+    /// more information.
+    SetControllerRemaps(Vec<ControllerRemap>),
+
+    /// Sets an effect that processes the channel's output after its voices
+    /// are mixed, or removes it with `None`. The new insert is used from the
+    /// next rendered buffer on, and the replaced one is dropped on a
+    /// background thread. See the `AudioInsert` documentation for more
+    /// information.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    SetInsert(Option<Box<dyn AudioInsert>>),
+
+    /// Sends the audio of the channel's voices to the sidechain input of
+    /// compressors, or stops sending it with `None`. The audio is taken
+    /// before the channel's volume, pan, cutoff and insert are applied. See
+    /// the `SidechainTap` documentation for more information.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    SetSidechainSend(Option<SidechainTap>),
+
+    /// Sets a function that is called for each note on of the channel
+    /// before it is played, which can change the note or skip it, or
+    /// removes it with `None`. The replaced hook is dropped on a background
+    /// thread. See the `NoteSpawnHook` documentation for more information.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    SetNoteSpawnHook(Option<NoteSpawnHook>),
+
+    /// Controls whether the channel records the state of its voices after
+    /// each rendered buffer, for debugging and visualization. The snapshots
+    /// are read with `VoiceChannelStatsReader::voice_snapshot`. Costs some
+    /// render time per voice, so it should stay disabled otherwise.
+    ///
+    /// Default: `false`
+    SetVoiceSnapshots(bool),
+
+    /// Renders the current voices into a loop of about `loop_frames` frames
+    /// and plays it in place of them. See `VoiceChannel::freeze`.
+    Freeze { loop_frames: usize },
+
+    /// Fades out the loop of a frozen channel. See `VoiceChannel::freeze`.
+    Unfreeze,
+
+    /// Changes the sample rate the channel renders at. The voices and the
+    /// frozen loop are dropped, the controls are kept, and the soundfonts
+    /// are converted to the new rate. See the `SampleRateChange`
+    /// documentation for more information.
+    ///
+    /// The channels of a synthesizer render at the same rate, so the change
+    /// should be sent to all of them with `SynthEvent::AllChannels`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    SetSampleRate(SampleRateChange),
+}
+
+impl ChannelConfigEvent {
+    /// Returns the name of the event's variant, e.g. to log it without its
+    /// data, which can be large.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChannelConfigEvent::SetSoundfonts(_) => "SetSoundfonts",
+            ChannelConfigEvent::SetRoutedSoundfonts(_) => "SetRoutedSoundfonts",
+            ChannelConfigEvent::SetFallbackSoundfont(_) => "SetFallbackSoundfont",
+            ChannelConfigEvent::SetProgramMap(_) => "SetProgramMap",
+            ChannelConfigEvent::SetLayerCount(_) => "SetLayerCount",
+            ChannelConfigEvent::SetVelocityCrossfade(_) => "SetVelocityCrossfade",
+            ChannelConfigEvent::SetPercussionMode(_) => "SetPercussionMode",
+            ChannelConfigEvent::SetTranspose(_) => "SetTranspose",
+            ChannelConfigEvent::SetTransposeClamp(_) => "SetTransposeClamp",
+            ChannelConfigEvent::SetReleaseTimeScales(_) => "SetReleaseTimeScales",
+            ChannelConfigEvent::SetTuning { .. } => "SetTuning",
+            ChannelConfigEvent::SetExpressionCurve(_) => "SetExpressionCurve",
+            ChannelConfigEvent::SetLfo(_) => "SetLfo",
+            ChannelConfigEvent::SetControllerRemaps(_) => "SetControllerRemaps",
+            ChannelConfigEvent::SetInsert(_) => "SetInsert",
+            ChannelConfigEvent::SetSidechainSend(_) => "SetSidechainSend",
+            ChannelConfigEvent::SetNoteSpawnHook(_) => "SetNoteSpawnHook",
+            ChannelConfigEvent::SetVoiceSnapshots(_) => "SetVoiceSnapshots",
+            ChannelConfigEvent::Freeze { .. } => "Freeze",
+            ChannelConfigEvent::Unfreeze => "Unfreeze",
+            ChannelConfigEvent::SetSampleRate(_) => "SetSampleRate",
+        }
+    }
+}
+
+/// A soundfont assigned to a channel, optionally limited to a range of keys
+/// and velocities.
+///
+/// Soundfonts without any range keep the standard behavior, where the first
+/// soundfont in the list that has regions for a note is used. Soundfonts with
+/// a key or velocity range are only considered for notes inside that range,
+/// and all matching ranged soundfonts are layered on top of each other. The
+/// unranged soundfonts are only used for notes where no ranged soundfont
+/// produced any regions.
+#[derive(Clone, Debug)]
+pub struct RoutedSoundfont {
+    /// The soundfont to be used.
+    pub soundfont: Arc<dyn SoundfontBase>,
+
+    /// The range of keys the soundfont will be used for.
+    ///
+    /// Default: `None` (all keys)
+    pub key_range: Option<RangeInclusive<u8>>,
+
+    /// The range of velocities the soundfont will be used for.
+    ///
+    /// Default: `None` (all velocities)
+    pub vel_range: Option<RangeInclusive<u8>>,
+
+    /// The gain applied to the voices of the soundfont on this channel, in
+    /// decibels. Applied on top of the gain the soundfont was loaded with.
+    ///
+    /// Default: `0.0`
+    pub gain_db: f32,
+}
+
+impl RoutedSoundfont {
+    /// Creates a new entry for the given soundfont without any range limits.
+    pub fn new(soundfont: Arc<dyn SoundfontBase>) -> Self {
+        Self {
+            soundfont,
+            key_range: None,
+            vel_range: None,
+            gain_db: 0.0,
+        }
+    }
+
+    /// Limits the soundfont to the given range of keys.
+    pub fn with_key_range(mut self, key_range: RangeInclusive<u8>) -> Self {
+        self.key_range = Some(key_range);
+        self
+    }
+
+    /// Limits the soundfont to the given range of velocities.
+    pub fn with_vel_range(mut self, vel_range: RangeInclusive<u8>) -> Self {
+        self.vel_range = Some(vel_range);
+        self
+    }
+
+    /// Sets the gain applied to the voices of the soundfont, in decibels.
+    pub fn with_gain_db(mut self, gain_db: f32) -> Self {
+        self.gain_db = gain_db;
+        self
+    }
+
+    /// Returns true if a key or velocity range is set.
+    pub fn is_ranged(&self) -> bool {
+        self.key_range.is_some() || self.vel_range.is_some()
+    }
+
+    /// Returns true if the given key and velocity fall within the ranges.
+    pub fn contains(&self, key: u8, vel: u8) -> bool {
+        self.key_range.as_ref().is_none_or(|r| r.contains(&key))
+            && self.vel_range.as_ref().is_none_or(|r| r.contains(&vel))
+    }
+}
+
+impl From<Arc<dyn SoundfontBase>> for RoutedSoundfont {
+    fn from(soundfont: Arc<dyn SoundfontBase>) -> Self {
+        Self::new(soundfont)
+    }
+}
+
+/// The soundfont that a program should be played with when a `ProgramMap`
+/// is in use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ProgramMapTarget {
+    /// Use the soundfont at the given index of the channel's soundfont list,
+    /// playing the given bank and preset from it. Single-instrument SFZ files
+    /// are loaded in bank 0, preset 0 unless configured otherwise.
+    Soundfont { index: usize, bank: u8, preset: u8 },
+
+    /// Mute the channel while the program is selected.
+    Ignore,
+}
+
+impl Default for ProgramMapTarget {
+    fn default() -> Self {
+        ProgramMapTarget::Soundfont {
+            index: 0,
+            bank: 0,
+            preset: 0,
+        }
+    }
+}
+
+/// A table that maps (bank, program) pairs to specific soundfonts of a
+/// channel, so that program changes can select between several loaded
+/// single-instrument soundfonts (e.g. SFZ files). Percussion channels use
+/// bank 128.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(default)
+)]
+pub struct ProgramMap {
+    /// The mapped programs, keyed by (bank, program).
+    pub programs: HashMap<(u8, u8), ProgramMapTarget>,
+
+    /// The target used for programs that are not in the table.
+    ///
+    /// Default: The first soundfont, bank 0, preset 0
+    pub default: ProgramMapTarget,
+}
+
+impl ProgramMap {
+    /// Returns the target for the given bank and program.
+    pub fn get(&self, bank: u8, program: u8) -> ProgramMapTarget {
+        self.programs
+            .get(&(bank, program))
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// The source of the values of a `ControllerRemap`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ControllerSource {
+    /// A MIDI control change with the given controller number.
+    Controller(u8),
+
+    /// The channel pressure (aftertouch) of the channel.
+    ChannelPressure,
+}
+
+/// How the expression (CC11) of a channel is converted to a gain.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ExpressionCurve {
+    /// The gain is the expression value.
+    Linear,
+
+    /// The gain is the square of the expression value, close to the
+    /// `40 * log10(value)` dB curve that General MIDI recommends.
+    #[default]
+    Square,
+
+    /// The expression value is linear in dB, from the given range in dB
+    /// below full level at 0 up to full level at 1. With a range of 40 dB,
+    /// half the expression is -20 dB. An expression of 0 is silent.
+    Db(f32),
+
+    /// The gains of equally spaced expression values from 0 to 1, which
+    /// are interpolated linearly. An empty table keeps the full level.
+    Custom(Vec<f32>),
+}
+
+impl ExpressionCurve {
+    /// Returns the gain of an expression value between 0 and 1.
+    pub fn gain(&self, value: f32) -> f32 {
+        let value = value.clamp(0.0, 1.0);
+        match self {
+            ExpressionCurve::Linear => value,
+            ExpressionCurve::Square => value * value,
+            ExpressionCurve::Db(_) if value == 0.0 => 0.0,
+            ExpressionCurve::Db(range) => db_to_amp(-range.abs() * (1.0 - value)),
+            ExpressionCurve::Custom(table) => match table.len() {
+                0 => 1.0,
+                1 => table[0],
+                len => {
+                    let position = value * (len - 1) as f32;
+                    let index = (position as usize).min(len - 2);
+                    let fraction = position - index as f32;
+                    table[index] + (table[index + 1] - table[index]) * fraction
+                }
+            },
+        }
+    }
+}
+
+/// Sends the values of a source controller to a different controller,
+/// e.g. to play soundfonts that listen to expression (CC11) with a breath
+/// controller (CC2).
+///
+/// The remapped source is not applied itself. If several remaps share the
+/// same source, its values are sent to all of their targets. The values are
+/// remapped once only, so a target is never remapped again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct ControllerRemap {
+    /// The source of the remapped values.
+    pub source: ControllerSource,
+
+    /// The number of the controller the values are sent to.
+    pub target: u8,
+
+    /// Multiplier for the value, applied after inverting it.
+    ///
+    /// Default: `1.0`
+    pub scale: f32,
+
+    /// Offset added to the value after scaling it. The result is clamped
+    /// to 0-127.
+    ///
+    /// Default: `0.0`
+    pub offset: f32,
+
+    /// Inverts the value (`127 - value`) before scaling it.
+    ///
+    /// Default: `false`
+    pub invert: bool,
+}
+
+impl ControllerRemap {
+    /// Creates a new remap from the source to the target controller,
+    /// keeping the values unchanged.
+    pub fn new(source: ControllerSource, target: u8) -> Self {
+        Self {
+            source,
+            target,
+            scale: 1.0,
+            offset: 0.0,
+            invert: false,
+        }
+    }
+
+    /// Sets the multiplier for the value.
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Sets the offset added to the value.
+    pub fn with_offset(mut self, offset: f32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Inverts the value.
+    pub fn inverted(mut self) -> Self {
+        self.invert = true;
+        self
+    }
+
+    /// Returns the target value for the given source value.
+    pub fn apply(&self, value: u8) -> u8 {
+        let value = value.min(127);
+        let value = if self.invert { 127 - value } else { value };
+        (value as f32 * self.scale + self.offset)
+            .round()
+            .clamp(0.0, 127.0) as u8
+    }
 }
 
 /// MIDI events for a channel.
@@ -42,28 +479,132 @@ pub enum ChannelAudioEvent {
     /// Starts a new note voice
     NoteOn { key: u8, vel: u8 },
 
+    /// Starts a new note voice with its pitch offset by `cents_offset`.
+    /// The key is still used to select the soundfont regions and to match
+    /// the note off, and the offset adds to the pitch bend and tuning of
+    /// the channel.
+    NoteOnDetuned { key: u8, vel: u8, cents_offset: f32 },
+
     /// Signals off to a note voice
     NoteOff { key: u8 },
 
+    /// Kills the voices of a single key without decay, e.g. to stop a stuck
+    /// note. Voices fade out over 1ms if `fade_out_killing` is enabled in
+    /// the `ChannelInitOptions`, otherwise they are removed right away.
+    NoteKill { key: u8 },
+
+    /// Bends the pitch of the notes of a single key on top of the pitch
+    /// bend of the channel, e.g. for a MIDI 2.0 per-note pitch bend. The
+    /// value is between -1 and 1 and is scaled by the pitch bend
+    /// sensitivity of the channel, like `ControlEvent::PitchBendValue`.
+    ///
+    /// The bend applies to the sounding and following notes of the key
+    /// until it is changed or the controllers are reset.
+    NotePitchBend { key: u8, value: f32 },
+
     /// Signal off to all voices
     AllNotesOff,
 
-    /// Kill all voices without decay
+    /// Kill all voices without decay. Only the voices are affected: the
+    /// controllers, the damper pedal, the pitch bend and the program keep
+    /// their values, so a held damper also holds the following notes.
     AllNotesKilled,
 
-    /// Resets all CC to their default values
+    /// Resets all CC to their default values, including the pitch bend and
+    /// the damper pedal. The notes held by the damper are released, and the
+    /// voices keep playing.
     ResetControl,
 
+    /// Kills all voices without decay and resets all CC to their default
+    /// values, like `AllNotesKilled` followed by `ResetControl`. The note
+    /// events not yet rendered are dropped. The program and bank are kept.
+    ResetChannel,
+
     /// Control event for the channel
     Control(ControlEvent),
 
     /// Program change event
     ProgramChange(u8),
 
-    /// System reset
+    /// Resets the channel like `ResetChannel`, and also sets the program
+    /// and bank back to 0.
     SystemReset,
 }
 
+impl ChannelAudioEvent {
+    /// Returns the name of the event's variant.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChannelAudioEvent::NoteOn { .. } => "NoteOn",
+            ChannelAudioEvent::NoteOnDetuned { .. } => "NoteOnDetuned",
+            ChannelAudioEvent::NoteOff { .. } => "NoteOff",
+            ChannelAudioEvent::NoteKill { .. } => "NoteKill",
+            ChannelAudioEvent::NotePitchBend { .. } => "NotePitchBend",
+            ChannelAudioEvent::AllNotesOff => "AllNotesOff",
+            ChannelAudioEvent::AllNotesKilled => "AllNotesKilled",
+            ChannelAudioEvent::ResetControl => "ResetControl",
+            ChannelAudioEvent::ResetChannel => "ResetChannel",
+            ChannelAudioEvent::Control(control) => control.name(),
+            ChannelAudioEvent::ProgramChange(_) => "ProgramChange",
+            ChannelAudioEvent::SystemReset => "SystemReset",
+        }
+    }
+
+    /// Returns the key of the note events, or `None` for the other events.
+    pub fn key(&self) -> Option<u8> {
+        match *self {
+            ChannelAudioEvent::NoteOn { key, .. }
+            | ChannelAudioEvent::NoteOnDetuned { key, .. }
+            | ChannelAudioEvent::NoteOff { key }
+            | ChannelAudioEvent::NoteKill { key }
+            | ChannelAudioEvent::NotePitchBend { key, .. } => Some(key),
+            _ => None,
+        }
+    }
+
+    /// Returns the main value of the event: the velocity of note ons, the
+    /// program of program changes and the value of control events and
+    /// per-note pitch bends. `None` for the events without a value.
+    pub fn value(&self) -> Option<f32> {
+        match *self {
+            ChannelAudioEvent::NoteOn { vel, .. }
+            | ChannelAudioEvent::NoteOnDetuned { vel, .. } => Some(vel as f32),
+            ChannelAudioEvent::NotePitchBend { value, .. } => Some(value),
+            ChannelAudioEvent::ProgramChange(program) => Some(program as f32),
+            ChannelAudioEvent::Control(control) => control.value(),
+            _ => None,
+        }
+    }
+}
+
+/// Formats the event with note names, e.g. "NoteOn C#4 vel 96".
+impl fmt::Display for ChannelAudioEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ChannelAudioEvent::NoteOn { key, vel } => {
+                write!(f, "NoteOn {} vel {vel}", key_to_name(key))
+            }
+            ChannelAudioEvent::NoteOnDetuned {
+                key,
+                vel,
+                cents_offset,
+            } => write!(
+                f,
+                "NoteOn {} vel {vel} {cents_offset:+} cents",
+                key_to_name(key)
+            ),
+            ChannelAudioEvent::NoteOff { key } => write!(f, "NoteOff {}", key_to_name(key)),
+            ChannelAudioEvent::NoteKill { key } => write!(f, "NoteKill {}", key_to_name(key)),
+            ChannelAudioEvent::NotePitchBend { key, value } => {
+                write!(f, "NotePitchBend {} {value}", key_to_name(key))
+            }
+            ChannelAudioEvent::Control(control) => control.fmt(f),
+            ChannelAudioEvent::ProgramChange(program) => write!(f, "ProgramChange {program}"),
+            _ => f.write_str(self.name()),
+        }
+    }
+}
+
 /// Wrapper enum for various events for a channel.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
@@ -75,16 +616,64 @@ pub enum ChannelEvent {
     Config(ChannelConfigEvent),
 }
 
+/// Formats audio events with their data, and config events by their name.
+impl fmt::Display for ChannelEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelEvent::Audio(event) => event.fmt(f),
+            ChannelEvent::Config(event) => f.write_str(event.name()),
+        }
+    }
+}
+
 /// MIDI control events for a channel.
+///
+/// The common controllers have typed variants, which the channel applies
+/// like the raw control changes they stand for. Raw control changes of
+/// these controllers are translated to the typed variants, see
+/// `ControlEvent::from_raw` for the recognized controllers. The typed
+/// variants also set the value of their controller, which the SFZ `loccN`
+/// and `hiccN` conditions and the controller remaps use.
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum ControlEvent {
     /// A raw control change event
     Raw(u8, u8),
 
+    /// The damper (sustain) pedal, CC64. True if it is held.
+    Damper(bool),
+
+    /// The channel volume, CC7, between 0 and 1. Unlike the controller, the
+    /// value isn't limited to 7 or 14 bits.
+    Volume(f32),
+
+    /// The expression, CC11, between 0 and 1. Unlike the controller, the
+    /// value isn't limited to 7 or 14 bits.
+    Expression(f32),
+
+    /// The pan, CC10, between 0 (left) and 1 (right). 0.5 is the center.
+    Pan(f32),
+
+    /// The modulation wheel, CC1, between 0 and 1. Only used through its
+    /// controller value, e.g. by SFZ region conditions.
+    Modulation(f32),
+
+    /// Releases all notes of the channel, CC123
+    AllNotesOff,
+
+    /// Kills all notes of the channel immediately, CC120
+    AllSoundOff,
+
     /// The pitch bend strength, in tones
     PitchBendSensitivity(f32),
 
+    /// The pitch bend strength below the center, in semitones, for
+    /// synthesizer standards with different up and down ranges. Once set,
+    /// `PitchBendSensitivity` only applies above the center, until the
+    /// controllers are reset. Sent by the NRPN configured in
+    /// `ChannelInitOptions::pitch_bend_down_nrpn`.
+    PitchBendDownSensitivity(f32),
+
     /// The pitch bend value, between -1 and 1
     PitchBendValue(f32),
 
@@ -96,4 +685,222 @@ pub enum ControlEvent {
 
     /// Coarse tune value in semitones
     CoarseTune(f32),
+
+    /// The channel pressure (aftertouch), between 0 and 127. Has no effect
+    /// unless it is remapped to a controller, see `ControllerRemap`.
+    ChannelPressure(u8),
+}
+
+/// Converts a controller value to the range of the typed control events.
+fn from_cc_value(value: u8) -> f32 {
+    value as f32 / 128.0
+}
+
+fn to_cc_value(value: f32) -> u8 {
+    (value * 128.0).round().clamp(0.0, 127.0) as u8
+}
+
+/// Returns the LSB controller that refines the value of a controller, for
+/// the 14-bit pairs of the modulation wheel (CC1/33), the volume (CC7/39)
+/// and the expression (CC11/43). An MSB resets the value of its LSB to 0.
+pub(crate) fn lsb_controller(msb: u8) -> Option<u8> {
+    match msb {
+        0x01 | 0x07 | 0x0B => Some(msb + 0x20),
+        _ => None,
+    }
+}
+
+/// Combines the MSB and LSB of a 14-bit controller pair to the range of the
+/// typed control events.
+pub(crate) fn from_cc_value_14bit(msb: u8, lsb: u8) -> f32 {
+    (msb as f32 * 128.0 + lsb as f32) / 16384.0
+}
+
+impl ControlEvent {
+    /// Translates a raw control change to its typed variant, if the
+    /// controller is recognized. Other controllers are returned as `Raw`.
+    ///
+    /// Recognized controllers:
+    /// - CC1: `Modulation`
+    /// - CC7: `Volume`
+    /// - CC8 (balance) and CC10: `Pan`
+    /// - CC11: `Expression`
+    /// - CC64: `Damper`, held from a value of 64
+    /// - CC120 with a value of 0: `AllSoundOff`
+    /// - CC123 with a value of 0: `AllNotesOff`
+    ///
+    /// The values of the continuous controllers are divided by 128.
+    pub fn from_raw(controller: u8, value: u8) -> Self {
+        match (controller, value) {
+            (0x01, _) => ControlEvent::Modulation(from_cc_value(value)),
+            (0x07, _) => ControlEvent::Volume(from_cc_value(value)),
+            (0x08 | 0x0A, _) => ControlEvent::Pan(from_cc_value(value)),
+            (0x0B, _) => ControlEvent::Expression(from_cc_value(value)),
+            (0x40, _) => ControlEvent::Damper(value >= 64),
+            (0x78, 0) => ControlEvent::AllSoundOff,
+            (0x7B, 0) => ControlEvent::AllNotesOff,
+            _ => ControlEvent::Raw(controller, value),
+        }
+    }
+
+    /// Returns the controller and value of a raw or typed control change,
+    /// the inverse of `ControlEvent::from_raw`. Returns `None` for the
+    /// events that aren't control changes, such as the pitch bend.
+    pub fn to_raw(self) -> Option<(u8, u8)> {
+        match self {
+            ControlEvent::Raw(controller, value) => Some((controller, value)),
+            ControlEvent::Damper(held) => Some((0x40, if held { 127 } else { 0 })),
+            ControlEvent::Volume(value) => Some((0x07, to_cc_value(value))),
+            ControlEvent::Expression(value) => Some((0x0B, to_cc_value(value))),
+            ControlEvent::Pan(value) => Some((0x0A, to_cc_value(value))),
+            ControlEvent::Modulation(value) => Some((0x01, to_cc_value(value))),
+            ControlEvent::AllNotesOff => Some((0x7B, 0)),
+            ControlEvent::AllSoundOff => Some((0x78, 0)),
+            ControlEvent::PitchBendSensitivity(_)
+            | ControlEvent::PitchBendDownSensitivity(_)
+            | ControlEvent::PitchBendValue(_)
+            | ControlEvent::PitchBend(_)
+            | ControlEvent::FineTune(_)
+            | ControlEvent::CoarseTune(_)
+            | ControlEvent::ChannelPressure(_) => None,
+        }
+    }
+
+    /// Returns the name of the event's variant.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ControlEvent::Raw(..) => "ControlChange",
+            ControlEvent::Damper(_) => "Damper",
+            ControlEvent::Volume(_) => "Volume",
+            ControlEvent::Expression(_) => "Expression",
+            ControlEvent::Pan(_) => "Pan",
+            ControlEvent::Modulation(_) => "Modulation",
+            ControlEvent::AllNotesOff => "AllNotesOff",
+            ControlEvent::AllSoundOff => "AllSoundOff",
+            ControlEvent::PitchBendSensitivity(_) => "PitchBendSensitivity",
+            ControlEvent::PitchBendDownSensitivity(_) => "PitchBendDownSensitivity",
+            ControlEvent::PitchBendValue(_) => "PitchBendValue",
+            ControlEvent::PitchBend(_) => "PitchBend",
+            ControlEvent::FineTune(_) => "FineTune",
+            ControlEvent::CoarseTune(_) => "CoarseTune",
+            ControlEvent::ChannelPressure(_) => "ChannelPressure",
+        }
+    }
+
+    /// Returns the value of the event, `1.0` or `0.0` for the damper, or
+    /// `None` for the events without a value.
+    pub fn value(&self) -> Option<f32> {
+        match *self {
+            ControlEvent::Raw(_, value) | ControlEvent::ChannelPressure(value) => {
+                Some(value as f32)
+            }
+            ControlEvent::Damper(held) => Some(if held { 1.0 } else { 0.0 }),
+            ControlEvent::Volume(value)
+            | ControlEvent::Expression(value)
+            | ControlEvent::Pan(value)
+            | ControlEvent::Modulation(value)
+            | ControlEvent::PitchBendSensitivity(value)
+            | ControlEvent::PitchBendDownSensitivity(value)
+            | ControlEvent::PitchBendValue(value)
+            | ControlEvent::PitchBend(value)
+            | ControlEvent::FineTune(value)
+            | ControlEvent::CoarseTune(value) => Some(value),
+            ControlEvent::AllNotesOff | ControlEvent::AllSoundOff => None,
+        }
+    }
+}
+
+/// Formats the event with its value, e.g. "Volume 0.5" or "CC74 100" for a
+/// raw control change.
+impl fmt::Display for ControlEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ControlEvent::Raw(controller, value) => write!(f, "CC{controller} {value}"),
+            ControlEvent::Damper(held) => {
+                write!(f, "Damper {}", if held { "on" } else { "off" })
+            }
+            _ => match self.value() {
+                Some(value) => write!(f, "{} {value}", self.name()),
+                None => f.write_str(self.name()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw_round_trip() {
+        assert_eq!(ControlEvent::from_raw(0x40, 64), ControlEvent::Damper(true));
+        assert_eq!(ControlEvent::from_raw(0x07, 64), ControlEvent::Volume(0.5));
+        assert_eq!(ControlEvent::from_raw(0x7B, 0), ControlEvent::AllNotesOff);
+        assert_eq!(ControlEvent::from_raw(0x7B, 1), ControlEvent::Raw(0x7B, 1));
+        assert_eq!(
+            ControlEvent::from_raw(0x47, 10),
+            ControlEvent::Raw(0x47, 10)
+        );
+
+        for controller in [0x01, 0x07, 0x0A, 0x0B, 0x47] {
+            for value in 0..128 {
+                let event = ControlEvent::from_raw(controller, value);
+                assert_eq!(event.to_raw(), Some((controller, value)));
+            }
+        }
+        assert_eq!(ControlEvent::Volume(2.0).to_raw(), Some((0x07, 127)));
+        assert_eq!(ControlEvent::PitchBend(1.0).to_raw(), None);
+    }
+
+    #[test]
+    fn test_display() {
+        let note_on = ChannelAudioEvent::NoteOn { key: 61, vel: 96 };
+        assert_eq!(note_on.to_string(), "NoteOn C#4 vel 96");
+        assert_eq!(note_on.key(), Some(61));
+        assert_eq!(note_on.value(), Some(96.0));
+
+        let detuned = ChannelAudioEvent::NoteOnDetuned {
+            key: 60,
+            vel: 1,
+            cents_offset: -12.5,
+        };
+        assert_eq!(detuned.to_string(), "NoteOn C4 vel 1 -12.5 cents");
+        assert_eq!(
+            ChannelAudioEvent::NoteOff { key: 21 }.to_string(),
+            "NoteOff A0"
+        );
+        assert_eq!(ChannelAudioEvent::SystemReset.to_string(), "SystemReset");
+
+        let volume = ChannelAudioEvent::Control(ControlEvent::Volume(0.5));
+        assert_eq!(volume.to_string(), "Volume 0.5");
+        assert_eq!(volume.name(), "Volume");
+        assert_eq!(ControlEvent::Raw(74, 100).to_string(), "CC74 100");
+        assert_eq!(ControlEvent::Damper(true).to_string(), "Damper on");
+        assert_eq!(ControlEvent::AllSoundOff.to_string(), "AllSoundOff");
+
+        let config = ChannelEvent::Config(ChannelConfigEvent::SetTranspose(12));
+        assert_eq!(config.to_string(), "SetTranspose");
+    }
+
+    #[test]
+    fn test_expression_curves() {
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-4;
+
+        assert!(close(ExpressionCurve::Linear.gain(0.5), 0.5));
+        assert!(close(ExpressionCurve::Square.gain(0.5), 0.25));
+        assert!(close(ExpressionCurve::Square.gain(2.0), 1.0));
+
+        let db = ExpressionCurve::Db(40.0);
+        assert!(close(db.gain(1.0), 1.0));
+        assert!(close(db.gain(0.5), 0.1));
+        assert!(close(db.gain(0.75), db_to_amp(-10.0)));
+        assert_eq!(db.gain(0.0), 0.0);
+
+        let custom = ExpressionCurve::Custom(vec![0.0, 0.8, 1.0]);
+        assert!(close(custom.gain(0.0), 0.0));
+        assert!(close(custom.gain(0.25), 0.4));
+        assert!(close(custom.gain(0.5), 0.8));
+        assert!(close(custom.gain(1.0), 1.0));
+        assert_eq!(ExpressionCurve::Custom(Vec::new()).gain(0.3), 1.0);
+    }
 }
@@ -0,0 +1,188 @@
+/// The maximum length of the crossfade that joins the end of a frozen loop
+/// to its start, in frames.
+const LOOP_CROSSFADE_FRAMES: usize = 1024;
+
+/// The length of the fade out when a channel is unfrozen, in frames.
+const FADE_OUT_FRAMES: usize = 256;
+
+/// The audio of a channel's voices rendered into a loop, played back in
+/// place of the voices. See `VoiceChannel::freeze`.
+pub(super) struct FrozenLoop {
+    /// The interleaved loop audio, before the channel effects
+    samples: Vec<f32>,
+    channels: usize,
+
+    /// The current frame within the loop
+    position: usize,
+
+    /// The remaining frames of the fade out, if it was started
+    fade_out: Option<usize>,
+}
+
+/// The lengths used to build a loop of about `loop_frames` frames.
+struct LoopLayout {
+    crossfade: usize,
+    search: usize,
+}
+
+impl LoopLayout {
+    fn new(loop_frames: usize) -> Self {
+        LoopLayout {
+            crossfade: LOOP_CROSSFADE_FRAMES.min(loop_frames / 2),
+            search: loop_frames / 8,
+        }
+    }
+}
+
+impl FrozenLoop {
+    /// The number of frames that have to be captured to build a loop of
+    /// about `loop_frames` frames.
+    pub fn capture_frames(loop_frames: usize) -> usize {
+        let layout = LoopLayout::new(loop_frames);
+        layout.crossfade + loop_frames + layout.search
+    }
+
+    /// Builds a loop from the captured audio, which has to be at least
+    /// `capture_frames(loop_frames)` long.
+    ///
+    /// The loop starts after the crossfade length, and its length is searched
+    /// within an eighth of `loop_frames` for the end that correlates best
+    /// with the audio before the start. The end of the loop is then
+    /// crossfaded into that audio, so it continues seamlessly at the start.
+    pub fn from_capture(capture: &[f32], channels: usize, loop_frames: usize) -> Self {
+        let layout = LoopLayout::new(loop_frames);
+        let start = layout.crossfade;
+
+        let mono: Vec<f32> = capture
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().sum())
+            .collect();
+        let length = find_loop_length(&mono, start, layout.crossfade, loop_frames, layout.search);
+
+        let mut samples = capture[start * channels..(start + length) * channels].to_vec();
+        let fade_start = length - layout.crossfade;
+        for i in fade_start..length {
+            let fade_in = (i - fade_start) as f32 / layout.crossfade as f32;
+            for c in 0..channels {
+                let before_start = capture[(start + i - length) * channels + c];
+                let sample = &mut samples[i * channels + c];
+                *sample = *sample * (1.0 - fade_in) + before_start * fade_in;
+            }
+        }
+
+        FrozenLoop {
+            samples,
+            channels,
+            position: 0,
+            fade_out: None,
+        }
+    }
+
+    /// The length of the loop in frames.
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    /// Adds the loop to the interleaved output.
+    pub fn render_to(&mut self, out: &mut [f32]) {
+        let frames = self.frames();
+        for frame in out.chunks_exact_mut(self.channels) {
+            let gain = match self.fade_out.as_mut() {
+                Some(0) => return,
+                Some(remaining) => {
+                    *remaining -= 1;
+                    *remaining as f32 / FADE_OUT_FRAMES as f32
+                }
+                None => 1.0,
+            };
+
+            let start = self.position * self.channels;
+            for (sample, looped) in frame.iter_mut().zip(&self.samples[start..]) {
+                *sample += looped * gain;
+            }
+            self.position = (self.position + 1) % frames;
+        }
+    }
+
+    pub fn start_fade_out(&mut self) {
+        if self.fade_out.is_none() {
+            self.fade_out = Some(FADE_OUT_FRAMES);
+        }
+    }
+
+    pub fn ended(&self) -> bool {
+        self.fade_out == Some(0)
+    }
+}
+
+/// Finds the loop length whose last `crossfade` frames correlate best with
+/// the `crossfade` frames before `start`.
+fn find_loop_length(
+    mono: &[f32],
+    start: usize,
+    crossfade: usize,
+    loop_frames: usize,
+    search: usize,
+) -> usize {
+    if crossfade == 0 {
+        return loop_frames;
+    }
+
+    let before_start = &mono[start - crossfade..start];
+    let before_energy: f32 = before_start.iter().map(|s| s * s).sum();
+
+    let mut best = (loop_frames, f32::MIN);
+    for length in loop_frames.saturating_sub(search).max(crossfade)..=loop_frames + search {
+        let end = &mono[start + length - crossfade..start + length];
+        let mut product = 0.0;
+        let mut energy = 0.0;
+        for (a, b) in end.iter().zip(before_start) {
+            product += a * b;
+            energy += a * a;
+        }
+
+        let correlation = product / (energy * before_energy).sqrt().max(f32::EPSILON);
+        if correlation > best.1 {
+            best = (length, correlation);
+        }
+    }
+    best.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frozen_loop_is_seamless() {
+        // A stereo sine wave with a period of 100 frames
+        let loop_frames = 4950;
+        let capture: Vec<f32> = (0..FrozenLoop::capture_frames(loop_frames))
+            .flat_map(|i| {
+                let s = (i as f32 * std::f32::consts::TAU / 100.0).sin();
+                [s, s * 0.5]
+            })
+            .collect();
+
+        let mut frozen = FrozenLoop::from_capture(&capture, 2, loop_frames);
+        assert_eq!(frozen.frames() % 100, 0);
+        assert!(frozen.frames().abs_diff(loop_frames) <= loop_frames / 8);
+
+        // The loop starts after the crossfade and continues the wave across
+        // its end
+        let mut out = vec![0.0; frozen.frames() * 4];
+        frozen.render_to(&mut out);
+        for (i, frame) in out.chunks(2).enumerate() {
+            let phase = (i + LOOP_CROSSFADE_FRAMES) as f32 * std::f32::consts::TAU / 100.0;
+            let expected = phase.sin();
+            assert!((frame[0] - expected).abs() < 0.01);
+            assert!((frame[1] - expected * 0.5).abs() < 0.01);
+        }
+
+        frozen.start_fade_out();
+        let mut out = vec![0.0; FADE_OUT_FRAMES * 2 + 2];
+        frozen.render_to(&mut out);
+        assert!(frozen.ended());
+        assert_eq!(out[out.len() - 2..], [0.0, 0.0]);
+    }
+}
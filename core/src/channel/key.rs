@@ -1,18 +1,54 @@
-use std::sync::{
-    atomic::{AtomicU64, Ordering},
-    Arc,
+use std::{
+    borrow::Cow,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
 };
 
 use super::{
-    channel_sf::ChannelSoundfont, event::KeyNoteEvent, voice_buffer::VoiceBuffer,
-    ChannelInitOptions, VoiceControlData,
+    channel_sf::ChannelSoundfont,
+    event::KeyNoteEvent,
+    release_crossfade::ReleaseFader,
+    retrigger::RetriggerOffset,
+    unmatched::UnmatchedNotes,
+    velocity_layers::VelocityLayers,
+    voice_budget::{RenderBudget, VoiceCostMeter},
+    voice_buffer::{ReleasedNote, VoiceBuffer},
+    voice_spawner::voice_iter_from_vec,
+    ChannelInitOptions, VoiceControlData, VoiceSnapshot,
 };
+use crate::{
+    util::DeferredDrops,
+    voice::{Voice, VoicePolyphony},
+};
+
+/// The render budget of a key, see `RenderBudget`.
+struct KeyBudget {
+    meter: VoiceCostMeter,
+    channels: usize,
+
+    /// The layer count the budget is based on. The voice buffer itself
+    /// doesn't limit the voices.
+    layers: Option<usize>,
+}
 
 pub struct KeyData {
     key: u8,
     voices: VoiceBuffer,
     last_voice_count: usize,
     shared_voice_counter: Arc<AtomicU64>,
+    budget: Option<KeyBudget>,
+    deferred_drops: DeferredDrops,
+    velocity_layers: Option<VelocityLayers>,
+    retrigger_offset: Option<RetriggerOffset>,
+    release_fader: Option<ReleaseFader>,
+    trimmed_layers: Arc<AtomicU64>,
+    unmatched_notes: UnmatchedNotes,
+
+    /// The pitch multiplier of the per-note pitch bend, applied on top of
+    /// the pitch of the channel
+    pitch_multiplier: f32,
 }
 
 impl KeyData {
@@ -26,9 +62,73 @@ impl KeyData {
             voices: VoiceBuffer::new(options),
             last_voice_count: 0,
             shared_voice_counter,
+            budget: None,
+            deferred_drops: DeferredDrops::default(),
+            velocity_layers: options.velocity_layers,
+            retrigger_offset: options.retrigger_offset,
+            release_fader: None,
+            trimmed_layers: Default::default(),
+            unmatched_notes: Default::default(),
+            pitch_multiplier: 1.0,
         }
     }
 
+    /// Sets where the voices killed by the velocity layer limits are
+    /// counted, see `ChannelInitOptions::velocity_layers`.
+    pub fn set_trimmed_layers_counter(&mut self, counter: Arc<AtomicU64>) {
+        self.trimmed_layers = counter;
+    }
+
+    /// Sets where the note ons without regions are counted, see
+    /// `ChannelInitOptions::unmatched_notes`.
+    pub fn set_unmatched_notes(&mut self, unmatched_notes: UnmatchedNotes) {
+        self.unmatched_notes = unmatched_notes;
+    }
+
+    /// Sets where the voices removed at once by a kill are dropped, see
+    /// `kill_all_voices`.
+    pub fn set_deferred_drops(&mut self, deferred_drops: DeferredDrops) {
+        self.deferred_drops = deferred_drops;
+    }
+
+    /// Limits the voices of the key by their render time instead of their
+    /// number. `channels` is the number of interleaved audio channels.
+    pub fn set_render_budget(&mut self, budget: RenderBudget, channels: usize) {
+        self.budget = Some(KeyBudget {
+            meter: VoiceCostMeter::new(budget),
+            channels,
+            layers: self.voices.max_voices,
+        });
+        self.voices.set_max_voices(None);
+    }
+
+    /// Crossfades the release sample voices with the released voices, see
+    /// `ChannelInitOptions::release_crossfade`.
+    pub fn set_release_fader(&mut self, fader: ReleaseFader) {
+        self.release_fader = Some(fader);
+    }
+
+    /// Sets the pitch multiplier of the per-note pitch bend, see
+    /// `ChannelAudioEvent::NotePitchBend`. The voices are updated right away
+    /// with the given controls of the channel.
+    pub fn set_pitch_multiplier(&mut self, multiplier: f32, control: &VoiceControlData) {
+        if self.pitch_multiplier != multiplier {
+            self.pitch_multiplier = multiplier;
+            self.process_controls(control);
+        }
+    }
+
+    /// Applies the per-note pitch bend to the controls of the channel.
+    fn bend_controls<'a>(&self, control: &'a VoiceControlData) -> Cow<'a, VoiceControlData> {
+        if self.pitch_multiplier == 1.0 {
+            return Cow::Borrowed(control);
+        }
+        Cow::Owned(VoiceControlData {
+            voice_pitch_multiplier: control.voice_pitch_multiplier * self.pitch_multiplier,
+            ..*control
+        })
+    }
+
     #[inline(always)]
     pub fn send_event(
         &mut self,
@@ -36,32 +136,118 @@ impl KeyData {
         control: &VoiceControlData,
         channel_sf: &ChannelSoundfont,
     ) {
+        let control = self.bend_controls(control);
+        let control = &*control;
         match event {
             KeyNoteEvent::On(vel) => {
-                let voices = channel_sf.spawn_voices_attack(control, self.key, vel);
-                self.voices.push_voices(voices);
+                self.push_note(control, channel_sf, vel);
+            }
+            KeyNoteEvent::LegatoOn(vel) => {
+                let control = VoiceControlData {
+                    legato: true,
+                    ..*control
+                };
+                self.push_note(&control, channel_sf, vel);
+            }
+            KeyNoteEvent::DetunedOn { vel, cents, legato } => {
+                let control = VoiceControlData {
+                    legato,
+                    cents_offset: cents,
+                    ..*control
+                };
+                self.push_note(&control, channel_sf, vel);
             }
             KeyNoteEvent::Off => {
-                let vel = self.voices.release_next_voice();
-                if let Some(vel) = vel {
-                    let voices = channel_sf.spawn_voices_release(control, self.key, vel);
-                    self.voices.push_voices(voices);
+                if let Some(note) = self.voices.release_next_voice() {
+                    self.push_release(control, note);
                 }
             }
             KeyNoteEvent::AllOff => {
-                while let Some(vel) = self.voices.release_next_voice() {
-                    let voices = channel_sf.spawn_voices_release(control, self.key, vel);
-                    self.voices.push_voices(voices);
+                // Each group has at least one voice, so this releases each
+                // group once, even if a voice doesn't report its release
+                for _ in 0..self.voices.voice_count() {
+                    let Some(note) = self.voices.release_next_voice() else {
+                        break;
+                    };
+                    self.push_release(control, note);
                 }
             }
             KeyNoteEvent::AllKilled => {
-                self.voices.kill_all_voices();
+                self.kill_all_voices();
+            }
+        }
+    }
+
+    /// Spawns the voices of a note on, keeping the release spawners of the
+    /// current program for its note off.
+    fn push_note(&mut self, control: &VoiceControlData, channel_sf: &ChannelSoundfont, vel: u8) {
+        let retriggered = self
+            .retrigger_offset
+            .zip(self.voices.newest_sample_position())
+            .and_then(|(offset, position)| offset.start_position(position))
+            .map(|start_position| VoiceControlData {
+                start_position,
+                ..*control
+            });
+        let control = retriggered.as_ref().unwrap_or(control);
+
+        if channel_sf.is_unmatched(self.key, vel) {
+            self.unmatched_notes
+                .record(channel_sf.program(), self.key, vel);
+        }
+
+        let voices = channel_sf.spawn_voices_attack(control, self.key, vel);
+        let release_spawners = channel_sf.release_spawners(self.key, vel);
+
+        let limit = self.velocity_layers.and_then(|l| l.max_layers(vel));
+        let layers = match self.budget.as_ref() {
+            Some(budget) => budget.layers,
+            None => self.voices.max_voices,
+        };
+        match limit {
+            Some(limit) if layers.is_none_or(|layers| limit < layers) => {
+                // The limit only applies while the voices of this note are
+                // pushed, the following notes use their own
+                let max_voices = self.voices.max_voices;
+                self.voices.set_max_voices(Some(limit));
+
+                let mut spawned = 0;
+                let active = self.voices.get_active_count();
+                let voices = voices.inspect(|_| spawned += 1);
+                self.voices.push_note_voices(voices, release_spawners);
+                let trimmed = (active + spawned).saturating_sub(self.voices.get_active_count());
+                self.trimmed_layers
+                    .fetch_add(trimmed as u64, Ordering::Relaxed);
+
+                self.voices.set_max_voices(max_voices);
             }
+            _ => self.voices.push_note_voices(voices, release_spawners),
         }
     }
 
+    /// Spawns the release voices of a released note, with the program the
+    /// note started with even if the program changed since.
+    fn push_release(&mut self, control: &VoiceControlData, note: ReleasedNote) {
+        if let Some(release_spawners) = note.release_spawners {
+            let voices = voice_iter_from_vec(&release_spawners, control);
+            match self.release_fader {
+                Some(fader) => self
+                    .voices
+                    .push_voices(voices.map(|voice| fader.fade_in(voice, note.level))),
+                None => self.voices.push_voices(voices),
+            };
+        }
+    }
+
+    /// Adds voices that weren't spawned by a note event, such as pedal noises.
+    pub fn push_voices(&mut self, voices: impl Iterator<Item = Box<dyn Voice>>) {
+        self.voices.push_voices(voices);
+    }
+
     #[inline(always)]
     pub fn process_controls(&mut self, control: &VoiceControlData) {
+        let control = self.bend_controls(control);
+        let control = &*control;
         for voice in &mut self.voices.iter_voices_mut() {
             voice.process_controls(control);
         }
@@ -77,6 +263,15 @@ impl KeyData {
             return;
         }
 
+        let mut measure = None;
+        if let Some(budget) = self.budget.as_mut() {
+            if let Some(layers) = budget.layers {
+                self.voices
+                    .keep_loudest_voices(budget.meter.voice_limit(layers));
+                measure = budget.meter.start();
+            }
+        }
+
         // Direct sequential rendering - most efficient approach
         // Avoid any intermediate allocations
         let voices = self.voices.get_voices_mut();
@@ -84,6 +279,11 @@ impl KeyData {
             voice.voice.render_to(out);
         }
 
+        if let (Some(start), Some(budget)) = (measure, self.budget.as_mut()) {
+            let frames = out.len() / budget.channels;
+            budget.meter.finish(start, voices.len(), frames);
+        }
+
         self.voices.remove_ended_voices();
         self.update_voice_counter(self.voices.voice_count());
     }
@@ -110,4 +310,44 @@ impl KeyData {
     pub fn set_damper(&mut self, damper: bool) {
         self.voices.set_damper(damper);
     }
+
+    pub fn set_max_voices(&mut self, max: Option<usize>) {
+        match self.budget.as_mut() {
+            Some(budget) => budget.layers = max,
+            None => self.voices.set_max_voices(max),
+        }
+    }
+
+    /// Kills all voices. The voices removed right away are dropped on the
+    /// background thread, as freeing many of them could stall rendering.
+    pub fn kill_all_voices(&mut self) {
+        let killed = self.voices.kill_all_voices();
+        if !killed.is_empty() {
+            self.deferred_drops.drop_later(killed);
+        }
+    }
+
+    /// Returns the polyphony limits and spawn order of the voices that
+    /// weren't killed, see `Voice::polyphony`.
+    pub fn voice_polyphony(&self) -> impl Iterator<Item = (VoicePolyphony, u64)> + '_ {
+        self.voices.voice_polyphony()
+    }
+
+    /// Kills the voices that `kill` returns true for, see
+    /// `ChannelInitOptions::fade_out_killing`.
+    pub fn kill_voices_where(&mut self, kill: impl FnMut(&dyn Voice) -> bool) {
+        self.voices.kill_voices_where(kill);
+    }
+
+    /// Removes all voices right away, without a release or fade out.
+    pub fn clear_voices(&mut self) {
+        self.voices.clear();
+    }
+
+    /// Adds a snapshot of each voice of the key to `out`.
+    pub fn snapshot_voices(&self, out: &mut Vec<VoiceSnapshot>) {
+        for group in self.voices.iter_voices() {
+            out.push(VoiceSnapshot::new(self.key, group.id, group.voice.as_ref()));
+        }
+    }
 }
@@ -0,0 +1,156 @@
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use crate::util::db_to_amp;
+
+/// The maximum number of audio channels of a stream.
+const MAX_CHANNELS: usize = 8;
+
+/// The time the RMS level takes to reach 99% of a steady signal, like the
+/// integration time of a VU meter, in seconds.
+const RMS_RISE_TIME: f32 = 0.3;
+
+/// The rate the peak level falls at, in dB per second, like a peak
+/// programme meter (20 dB in 1.7 seconds).
+const PEAK_FALL_RATE: f32 = 20.0 / 1.7;
+
+/// The output level of one audio channel of a VoiceChannel, as amplitude
+/// (`1.0` is full scale).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChannelLevel {
+    /// The peak level. Rises instantly and falls by 20 dB per 1.7 seconds.
+    pub peak: f32,
+
+    /// The RMS level, integrated over 300 ms like a VU meter.
+    pub rms: f32,
+
+    /// The highest peak since the meter was created or last reset.
+    pub max_hold: f32,
+}
+
+#[derive(Debug, Default)]
+struct AtomicLevel {
+    peak: AtomicU32,
+    mean_square: AtomicU32,
+    max_hold: AtomicU32,
+}
+
+fn load(value: &AtomicU32) -> f32 {
+    f32::from_bits(value.load(Ordering::Relaxed))
+}
+
+fn store(value: &AtomicU32, level: f32) {
+    value.store(level.to_bits(), Ordering::Relaxed);
+}
+
+/// Lock-free level meter of the output of a VoiceChannel, written by the
+/// channel once per rendered buffer and read from any thread.
+#[derive(Debug, Default)]
+pub(super) struct LevelMeter {
+    levels: [AtomicLevel; MAX_CHANNELS],
+    channel_count: AtomicUsize,
+}
+
+impl LevelMeter {
+    /// Measures a rendered buffer of interleaved samples and updates the
+    /// levels with the meter ballistics. Must only be called by the channel
+    /// that owns the statistics.
+    pub fn measure(&self, buffer: &[f32], channel_count: usize, sample_rate: u32) {
+        let channel_count = channel_count.min(MAX_CHANNELS);
+        let frames = buffer.len() / channel_count;
+        if frames == 0 {
+            return;
+        }
+        self.channel_count.store(channel_count, Ordering::Relaxed);
+
+        let mut peaks = [0.0f32; MAX_CHANNELS];
+        let mut squares = [0.0f32; MAX_CHANNELS];
+        for frame in buffer.chunks_exact(channel_count) {
+            for (i, &sample) in frame.iter().enumerate() {
+                peaks[i] = peaks[i].max(sample.abs());
+                squares[i] += sample * sample;
+            }
+        }
+
+        let seconds = frames as f32 / sample_rate as f32;
+        let peak_fall = db_to_amp(-PEAK_FALL_RATE * seconds);
+        // 99% of the way after the rise time is a time constant of
+        // rise time / ln(100)
+        let rms_keep = (-seconds * 100f32.ln() / RMS_RISE_TIME).exp();
+
+        for (i, level) in self.levels[..channel_count].iter().enumerate() {
+            let peak = peaks[i].max(load(&level.peak) * peak_fall);
+            store(&level.peak, peak);
+
+            let mean_square = squares[i] / frames as f32;
+            let previous = load(&level.mean_square);
+            store(
+                &level.mean_square,
+                mean_square + (previous - mean_square) * rms_keep,
+            );
+
+            // The reader may reset the maximum at any time
+            let _ = level
+                .max_hold
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |max| {
+                    (peaks[i] > f32::from_bits(max)).then(|| peaks[i].to_bits())
+                });
+        }
+    }
+
+    /// Returns the level of each audio channel, e.g. left and right for
+    /// stereo. Empty until the first buffer is rendered.
+    pub fn levels(&self) -> Vec<ChannelLevel> {
+        let channel_count = self.channel_count.load(Ordering::Relaxed);
+        self.levels[..channel_count]
+            .iter()
+            .map(|level| ChannelLevel {
+                peak: load(&level.peak),
+                rms: load(&level.mean_square).sqrt(),
+                max_hold: load(&level.max_hold),
+            })
+            .collect()
+    }
+
+    pub fn reset_max_hold(&self) {
+        for level in self.levels.iter() {
+            store(&level.max_hold, 0.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_ballistics() {
+        let meter = LevelMeter::default();
+        assert!(meter.levels().is_empty());
+
+        // A full scale square wave on the left channel only
+        let buffer: Vec<f32> = (0..480).flat_map(|i| [(i % 2) as f32, 0.0]).collect();
+        for _ in 0..30 {
+            meter.measure(&buffer, 2, 48000);
+        }
+
+        let levels = meter.levels();
+        assert_eq!(levels.len(), 2);
+        assert_eq!(levels[0].peak, 1.0);
+        assert!((levels[0].rms - 0.5f32.sqrt()).abs() < 0.01);
+        assert_eq!(levels[1], ChannelLevel::default());
+
+        // After 1.7 seconds of silence the peak fell by 20 dB, while the
+        // maximum is held until it is reset
+        let silence = vec![0.0; 2 * 480];
+        for _ in 0..170 {
+            meter.measure(&silence, 2, 48000);
+        }
+        let left = meter.levels()[0];
+        assert!((left.peak - 0.1).abs() < 0.001);
+        assert!(left.rms < 0.001);
+        assert_eq!(left.max_hold, 1.0);
+
+        meter.reset_max_hold();
+        assert_eq!(meter.levels()[0].max_hold, 0.0);
+    }
+}
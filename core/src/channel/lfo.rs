@@ -0,0 +1,176 @@
+use std::f32::consts::TAU;
+
+use crate::effects::{NoteValue, TempoClock};
+
+/// The rate of a `ChannelLfo`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum LfoRate {
+    /// A fixed rate, in Hz.
+    Hz(f32),
+
+    /// A cycle per note value at the tempo of the channel's `TempoClock`,
+    /// e.g. `NoteValue::new(8)` for a cycle per eighth note. Tempo changes
+    /// change the rate from the next rendered buffer on, without restarting
+    /// the cycle.
+    Synced(NoteValue),
+}
+
+impl LfoRate {
+    /// Returns the rate in Hz at the given tempo.
+    pub fn hz(&self, bpm: f32) -> f32 {
+        match self {
+            LfoRate::Hz(hz) => *hz,
+            LfoRate::Synced(note) => note.frequency(bpm),
+        }
+    }
+}
+
+/// A vibrato of the channel's voices, whose depth follows the modulation
+/// wheel (CC1 and CC33), like the vibrato of most GM synthesizers.
+///
+/// The LFO moves the pitch of the voices at the channel's `ControlRate`, so
+/// with `ControlRate::PerBuffer` it steps once per rendered buffer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(default)
+)]
+pub struct ChannelLfo {
+    /// The rate of the vibrato.
+    ///
+    /// Default: `LfoRate::Hz(5.0)`
+    pub rate: LfoRate,
+
+    /// The depth of the vibrato with the modulation wheel fully up, in cents
+    /// above and below the pitch of the notes.
+    ///
+    /// Default: `50.0`
+    pub depth_cents: f32,
+}
+
+impl Default for ChannelLfo {
+    fn default() -> Self {
+        Self {
+            rate: LfoRate::Hz(5.0),
+            depth_cents: 50.0,
+        }
+    }
+}
+
+/// The LFO of a channel and the position in its cycle.
+pub(super) struct LfoState {
+    lfo: Option<ChannelLfo>,
+    tempo: TempoClock,
+
+    /// The position in the current cycle, between 0 and 1
+    phase: f32,
+}
+
+impl LfoState {
+    pub fn new() -> Self {
+        Self {
+            lfo: None,
+            tempo: TempoClock::default(),
+            phase: 0.0,
+        }
+    }
+
+    pub fn set_lfo(&mut self, lfo: Option<ChannelLfo>) {
+        if lfo.is_none() {
+            self.phase = 0.0;
+        }
+        self.lfo = lfo;
+    }
+
+    pub fn set_tempo_clock(&mut self, tempo: TempoClock) {
+        self.tempo = tempo;
+    }
+
+    /// Advances the LFO by the given number of frames, and returns the pitch
+    /// offset in semitones at the end of them for the given modulation
+    /// wheel position between 0 and 1.
+    pub fn advance(&mut self, frames: usize, sample_rate: u32, modulation: f32) -> f32 {
+        let Some(lfo) = self.lfo else {
+            return 0.0;
+        };
+
+        let hz = lfo.rate.hz(self.tempo.bpm()).max(0.0);
+        self.phase = (self.phase + hz * frames as f32 / sample_rate as f32).fract();
+        (self.phase * TAU).sin() * lfo.depth_cents * modulation / 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        channel::{
+            ChannelAudioEvent, ChannelConfigEvent, ChannelEvent, ControlEvent, VoiceChannel,
+        },
+        AudioPipe, AudioStreamParams, ChannelCount,
+    };
+
+    #[test]
+    fn test_synced_rate_follows_tempo() {
+        let clock = TempoClock::new(120.0);
+        let mut lfo = LfoState::new();
+        lfo.set_tempo_clock(clock.clone());
+        lfo.set_lfo(Some(ChannelLfo {
+            rate: LfoRate::Synced(NoteValue::new(4)),
+            depth_cents: 100.0,
+        }));
+
+        // A sixteenth note at 120 BPM is a quarter of the cycle, at its peak
+        assert!((lfo.advance(6000, 48000, 1.0) - 1.0).abs() < 1e-4);
+
+        // At 60 BPM, the same time only moves an eighth of a cycle, from the
+        // peak instead of restarting the cycle
+        clock.set_bpm(60.0);
+        let expected = (0.375 * TAU).sin();
+        assert!((lfo.advance(6000, 48000, 1.0) - expected).abs() < 1e-4);
+
+        // The depth follows the modulation wheel
+        let half = lfo.advance(0, 48000, 0.5);
+        assert!((half - expected / 2.0).abs() < 1e-4);
+
+        lfo.set_lfo(None);
+        assert_eq!(lfo.advance(12000, 48000, 1.0), 0.0);
+    }
+
+    #[test]
+    fn test_channel_vibrato() {
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let mut channel = VoiceChannel::new(Default::default(), stream_params, None);
+        let clock = TempoClock::new(120.0);
+        channel.set_tempo_clock(clock.clone());
+        channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetLfo(Some(
+            ChannelLfo {
+                rate: LfoRate::Synced(NoteValue::new(4)),
+                depth_cents: 100.0,
+            },
+        ))));
+
+        let cents = |channel: &VoiceChannel| {
+            1200.0 * channel.voice_control_data.voice_pitch_multiplier.log2()
+        };
+        // Each buffer is an eighth of a cycle
+        let mut buffer = vec![0.0; 3000 * 2];
+
+        // Without modulation, the pitch doesn't move
+        channel.read_samples(&mut buffer);
+        assert_eq!(cents(&channel), 0.0);
+
+        channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::Control(
+            ControlEvent::Modulation(1.0 - 1.0 / 128.0),
+        )));
+        channel.read_samples(&mut buffer);
+        assert!(cents(&channel).abs() > 90.0);
+
+        // Resetting the controllers resets the modulation wheel
+        channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::ResetControl));
+        channel.read_samples(&mut buffer);
+        assert_eq!(cents(&channel), 0.0);
+    }
+}
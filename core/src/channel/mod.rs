@@ -1,15 +1,36 @@
-use std::sync::{atomic::AtomicU64, Arc};
+use std::{
+    ops::RangeInclusive,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 
 use crate::{
-    effects::MultiChannelBiQuad,
-    helpers::{db_to_amp, fast_zero_fill, sum_simd, FREQS},
-    voice::VoiceControlData,
+    effects::{AudioInsert, MultiChannelBiQuad, SidechainTap, TempoClock},
+    util::{
+        db_to_amp, fast_zero_fill, init_deferred_drops, sum_simd, KeyFrequencies,
+        RenderThreadCheck, FREQS,
+    },
+    voice::{TuningControlData, VoiceControlData},
     AudioStreamParams, ChannelCount,
 };
 
 use xsynth_soundfonts::FilterType;
 
-use self::{key::KeyData, params::VoiceChannelParams};
+use self::{
+    cc_remap::ControllerRemapTable,
+    control_rate::ControlRamp,
+    freeze::FrozenLoop,
+    key::KeyData,
+    lfo::LfoState,
+    params::VoiceChannelParams,
+    release_crossfade::ReleaseFader,
+    retrigger::{Retrigger, RetriggerAction},
+    spawn_budget::SpawnThrottle,
+    unmatched::UnmatchedNotes,
+};
 
 use super::AudioPipe;
 
@@ -17,16 +38,51 @@ use biquad::Q_BUTTERWORTH_F32;
 
 use rayon::prelude::*;
 
+mod cc_remap;
 mod channel_sf;
+mod control_rate;
+mod coverage;
+mod freeze;
 mod key;
+mod level_meter;
+mod lfo;
 mod params;
+mod polyphony;
+mod release_crossfade;
+mod retrigger;
+mod sample_rate;
+#[cfg(test)]
+mod sfz_tests;
+mod spawn_budget;
+mod spawn_hook;
+mod state_snapshot;
+mod transpose;
+mod unmatched;
+mod velocity_layers;
+mod voice_budget;
 mod voice_buffer;
+mod voice_snapshot;
 mod voice_spawner;
 
 mod event;
 pub use event::*;
 
+pub use control_rate::ControlRate;
+pub use coverage::{CoverageHole, CoverageReport, SoundfontCoverage};
+pub use level_meter::ChannelLevel;
+pub use lfo::{ChannelLfo, LfoRate};
 pub use params::VoiceChannelStatsReader;
+pub use release_crossfade::ReleaseCrossfade;
+pub use retrigger::{RetriggerOffset, RetriggerPolicy};
+pub use sample_rate::SampleRateChange;
+pub use spawn_budget::{SpawnBudget, SpawnOverflow};
+pub use spawn_hook::{NoteSpawnHook, NoteSpawnParams, SpawnDecision};
+pub use state_snapshot::ChannelStateSnapshot;
+pub use unmatched::{UnmatchedNote, UnmatchedNotePolicy};
+pub use velocity_layers::VelocityLayers;
+pub use voice_budget::{RenderBudget, VoiceLimitMode};
+pub use voice_buffer::NoteOffMatching;
+pub use voice_snapshot::{VoiceSnapshot, VoiceStage};
 
 pub(crate) struct ValueLerp {
     lerp_length: f32,
@@ -45,6 +101,12 @@ impl ValueLerp {
         }
     }
 
+    /// Jumps to the end of the current ramp, and ramps over the same time
+    /// at the new sample rate afterwards.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        *self = Self::new(self.end, sample_rate);
+    }
+
     pub fn set_end(&mut self, end: f32) {
         self.step = (end - self.current) / self.lerp_length;
         self.end = end;
@@ -79,19 +141,35 @@ impl Key {
 struct ControlEventData {
     selected_lsb: i8,
     selected_msb: i8,
+    nrpn_lsb: i8,
+    nrpn_msb: i8,
+    /// Whether the data entries go to the selected NRPN instead of the RPN
+    nrpn_selected: bool,
     pitch_bend_sensitivity_lsb: u8,
     pitch_bend_sensitivity_msb: u8,
     pitch_bend_sensitivity: f32,
+    pitch_bend_down_sensitivity_lsb: u8,
+    pitch_bend_down_sensitivity_msb: u8,
+    /// The sensitivity below the center, if it differs from the one above
+    pitch_bend_down_sensitivity: Option<f32>,
     pitch_bend_value: f32,
+    /// The applied pitch bend in semitones, usually the value times the
+    /// sensitivity
+    pitch_bend: f32,
     fine_tune_lsb: u8,
     fine_tune_msb: u8,
     fine_tune_value: f32,
     coarse_tune_value: f32,
+    /// The pitch offset of the channel's LFO in semitones
+    lfo_pitch: f32,
     volume: ValueLerp, // 0.0 = silent, 1.0 = max volume
     pan: ValueLerp,    // 0.0 = left, 0.5 = center, 1.0 = right
     cutoff: Option<f32>,
     resonance: Option<f32>,
+    /// The gain of the expression, after its curve
     expression: ValueLerp,
+    /// The per-note pitch bend value of each key, between -1 and 1
+    note_pitch_bends: [f32; 128],
 }
 
 impl ControlEventData {
@@ -99,21 +177,42 @@ impl ControlEventData {
         ControlEventData {
             selected_lsb: -1,
             selected_msb: -1,
+            nrpn_lsb: -1,
+            nrpn_msb: -1,
+            nrpn_selected: false,
             pitch_bend_sensitivity_lsb: 0,
             pitch_bend_sensitivity_msb: 2,
             pitch_bend_sensitivity: 2.0,
+            pitch_bend_down_sensitivity_lsb: 0,
+            pitch_bend_down_sensitivity_msb: 0,
+            pitch_bend_down_sensitivity: None,
             pitch_bend_value: 0.0,
+            pitch_bend: 0.0,
             fine_tune_lsb: 0,
             fine_tune_msb: 0,
             fine_tune_value: 0.0,
             coarse_tune_value: 0.0,
+            lfo_pitch: 0.0,
             volume: ValueLerp::new(1.0, sample_rate),
             pan: ValueLerp::new(0.5, sample_rate),
             cutoff: None,
             resonance: None,
             expression: ValueLerp::new(1.0, sample_rate),
+            note_pitch_bends: [0.0; 128],
         }
     }
+
+    /// Returns the pitch bend in semitones of a pitch bend value between -1
+    /// and 1, with the down sensitivity below the center.
+    fn pitch_bend_semitones(&self, value: f32) -> f32 {
+        let sensitivity = if value < 0.0 {
+            self.pitch_bend_down_sensitivity
+                .unwrap_or(self.pitch_bend_sensitivity)
+        } else {
+            self.pitch_bend_sensitivity
+        };
+        value * sensitivity
+    }
 }
 
 /// Options for initializing a new VoiceChannel.
@@ -130,6 +229,83 @@ pub struct ChannelInitOptions {
     ///
     /// Default: `false`
     pub fade_out_killing: bool,
+
+    /// The absolute maximum number of voices per key, including the ones
+    /// fading out after being killed. Unlike the layer count, it also applies
+    /// when the layer limit is disabled, so pathological MIDI files can't
+    /// make the memory usage grow without bound.
+    ///
+    /// Default: `4096`
+    pub hard_voice_limit: usize,
+
+    /// Limits the number of note ons that spawn voices in each rendered
+    /// buffer. See the `SpawnBudget` documentation for more information.
+    ///
+    /// Default: `None`
+    pub spawn_budget: Option<SpawnBudget>,
+
+    /// How often the controls of the voices, such as the pitch, are
+    /// evaluated while rendering. See the `ControlRate` documentation for
+    /// more information.
+    ///
+    /// Default: `ControlRate::PerBuffer`
+    pub control_rate: ControlRate,
+
+    /// Which of the overlapping notes on a key a note off releases. See the
+    /// `NoteOffMatching` documentation for available options.
+    ///
+    /// Default: `NoteOffMatching::OldestFirst`
+    pub note_off_matching: NoteOffMatching,
+
+    /// How the layer count limits the voices of each key. See the
+    /// `VoiceLimitMode` documentation for available options.
+    ///
+    /// Default: `VoiceLimitMode::Count`
+    pub voice_limit: VoiceLimitMode,
+
+    /// What happens when a key receives a note on while a previous note on
+    /// the same key is still held. See the `RetriggerPolicy` documentation
+    /// for available options.
+    ///
+    /// Default: `RetriggerPolicy::Allow`
+    pub retrigger: RetriggerPolicy,
+
+    /// Limits the layer count of soft note ons. See the `VelocityLayers`
+    /// documentation for more information.
+    ///
+    /// Default: `None`
+    pub velocity_layers: Option<VelocityLayers>,
+
+    /// Starts the notes of a key played again shortly after the previous
+    /// one at the sample position of the previous note, instead of
+    /// restarting the attack. See the `RetriggerOffset` documentation for
+    /// more information.
+    ///
+    /// Default: `None`
+    pub retrigger_offset: Option<RetriggerOffset>,
+
+    /// Crossfades the release samples of each note with its releasing
+    /// voices, at the level the voices were released at. See the
+    /// `ReleaseCrossfade` documentation for more information.
+    ///
+    /// Default: `None`
+    pub release_crossfade: Option<ReleaseCrossfade>,
+
+    /// What happens to note ons that no region of the soundfonts matches.
+    /// See the `UnmatchedNotePolicy` documentation for available options.
+    ///
+    /// Default: `UnmatchedNotePolicy::Silent`
+    pub unmatched_notes: UnmatchedNotePolicy,
+
+    /// The NRPN, as `(MSB, LSB)`, that sets the pitch bend sensitivity
+    /// below the center, for synthesizer standards with different up and
+    /// down ranges. Like the pitch bend sensitivity RPN 0,0, its data entry
+    /// MSB is in semitones and its LSB in cents. Until it is received, both
+    /// directions use the pitch bend sensitivity. See
+    /// `ControlEvent::PitchBendDownSensitivity`.
+    ///
+    /// Default: `None`
+    pub pitch_bend_down_nrpn: Option<(u8, u8)>,
 }
 
 #[allow(clippy::derivable_impls)]
@@ -137,10 +313,47 @@ impl Default for ChannelInitOptions {
     fn default() -> Self {
         Self {
             fade_out_killing: false,
+            hard_voice_limit: 4096,
+            spawn_budget: None,
+            control_rate: ControlRate::PerBuffer,
+            note_off_matching: NoteOffMatching::OldestFirst,
+            voice_limit: VoiceLimitMode::Count,
+            retrigger: RetriggerPolicy::Allow,
+            velocity_layers: None,
+            retrigger_offset: None,
+            release_crossfade: None,
+            unmatched_notes: UnmatchedNotePolicy::Silent,
+            pitch_bend_down_nrpn: None,
         }
     }
 }
 
+/// Renders the voices of a key, updating their controls before each block of
+/// the buffer. Renders the whole buffer at once if there are no blocks.
+fn render_control_blocks(
+    data: &mut KeyData,
+    out: &mut [f32],
+    blocks: &[VoiceControlData],
+    block_len: usize,
+) {
+    if blocks.is_empty() {
+        data.render_to(out);
+        return;
+    }
+
+    for (control, chunk) in blocks.iter().zip(out.chunks_mut(block_len)) {
+        data.process_controls(control);
+        data.render_to(chunk);
+    }
+}
+
+/// The voice limit of the channel's controller triggered voices, such as
+/// pedal noises.
+const NOISE_VOICE_LIMIT: usize = 8;
+
+/// The number of frames rendered at once when a channel is frozen.
+const FREEZE_CHUNK_FRAMES: usize = 1024;
+
 /// Represents a single MIDI channel within XSynth.
 ///
 /// Keeps track and manages MIDI events and the active voices of a channel.
@@ -152,6 +365,8 @@ impl Default for ChannelInitOptions {
 /// - `CC8`: Balance
 /// - `CC10`: Pan
 /// - `CC11`: Expression
+/// - `CC33`, `CC39`, `CC43`: The LSBs of the modulation wheel, the volume
+///   and the expression, for 14-bit resolution. They are reset by their MSB.
 /// - `CC64`: Damper pedal
 /// - `CC71`: Cutoff resonance
 /// - `CC72`: Release time multiplier
@@ -160,9 +375,54 @@ impl Default for ChannelInitOptions {
 /// - `CC120`: All sounds off
 /// - `CC121`: Reset all controllers
 /// - `CC123`: All notes off
+///
+/// ## Standalone use
+/// A channel doesn't need a `ChannelGroup` or the realtime synthesizer, so it
+/// can be driven directly by another audio engine:
+/// - Create it with `VoiceChannel::new`, and send it the soundfonts with
+///   `ChannelConfigEvent::SetSoundfonts`.
+/// - Send events with `process_event` or `push_events_iter`. They are applied
+///   at the start of the next rendered buffer, or at a frame offset within
+///   it with `read_samples_timed`.
+/// - Render interleaved audio with `AudioPipe::read_samples`, in the format
+///   of the `AudioStreamParams` the channel was created with.
+///
+/// The channel is `Send` but not `Sync`: sending events and rendering both
+/// need exclusive access, so an engine that receives events on another
+/// thread should queue them and send them to the channel on its audio
+/// thread before each buffer. The statistics returned by
+/// `get_channel_stats` can be read from any thread.
+///
+/// See the `render_channel` example of this crate.
 pub struct VoiceChannel {
     key_voices: Vec<Key>,
 
+    /// The voices triggered by controllers, such as pedal noises. They
+    /// aren't held by the damper and have their own voice limit.
+    noise_voices: KeyData,
+
+    /// The number of notes currently held, used to select `first` and
+    /// `legato` soundfont regions
+    held_notes: u32,
+
+    /// Applies the spawn budget to the note ons
+    spawn_throttle: SpawnThrottle,
+
+    /// Applies the retrigger policy to the note events
+    retrigger: Retrigger,
+
+    /// The NRPN setting the pitch bend sensitivity below the center
+    pitch_bend_down_nrpn: Option<(u8, u8)>,
+
+    /// What happens to the note ons without regions
+    unmatched_notes: UnmatchedNotePolicy,
+
+    /// Crossfades the release samples with the released voices
+    release_crossfade: Option<ReleaseCrossfade>,
+
+    /// Called for each note on before it is played
+    spawn_hook: Option<NoteSpawnHook>,
+
     params: VoiceChannelParams,
     threadpool: Option<Arc<rayon::ThreadPool>>,
 
@@ -174,8 +434,39 @@ pub struct VoiceChannel {
     /// Processed control data, ready to feed to voices
     voice_control_data: VoiceControlData,
 
+    /// Converts the expression values to the gain of the channel
+    expression_curve: ExpressionCurve,
+
+    /// Ramps the voice controls at the configured control rate
+    control_ramp: ControlRamp,
+
+    /// The vibrato of the voices
+    lfo: LfoState,
+
+    /// The key frequencies of the channel's tuning
+    key_frequencies: KeyFrequencies,
+
     /// Effects
     cutoff: MultiChannelBiQuad,
+
+    /// The stereo voice output for multichannel layouts, before it gets
+    /// placed into the speaker layout
+    voice_buffer: Vec<f32>,
+
+    /// The user effect applied to the channel's output
+    insert: Option<Box<dyn AudioInsert>>,
+
+    /// The sidechain the audio of the voices is sent to
+    sidechain_send: Option<SidechainTap>,
+
+    /// Whether the voices are recorded after each rendered buffer
+    snapshot_voices: bool,
+
+    /// The loop playing in place of the voices while the channel is frozen
+    frozen: Option<FrozenLoop>,
+
+    /// Catches the channel being rendered by two threads at once
+    render_check: RenderThreadCheck,
 }
 
 impl VoiceChannel {
@@ -199,27 +490,84 @@ impl VoiceChannel {
             vec
         }
 
-        let params = VoiceChannelParams::new(stream_params);
+        let mut params = VoiceChannelParams::new(stream_params);
+        params.stats.unmatched_notes = UnmatchedNotes::new(options.unmatched_notes);
+        params
+            .transpose
+            .set_note_off_matching(options.note_off_matching);
         let shared_voice_counter = params.stats.voice_counter.clone();
 
+        init_deferred_drops();
+
+        let voice_control_data = VoiceControlData::new_defaults();
+
+        let mut noise_voices = KeyData::new(0, shared_voice_counter.clone(), options);
+        noise_voices.set_max_voices(Some(NOISE_VOICE_LIMIT));
+        noise_voices.set_deferred_drops(params.stats.deferred_drops());
+
+        let mut key_voices = fill_key_array(|i| Key::new(i, shared_voice_counter.clone(), options));
+        for key in key_voices.iter_mut() {
+            key.data.set_deferred_drops(params.stats.deferred_drops());
+            key.data
+                .set_trimmed_layers_counter(params.stats.trimmed_layers.clone());
+            key.data
+                .set_unmatched_notes(params.stats.unmatched_notes.clone());
+        }
+        if let VoiceLimitMode::RenderBudget(budget) = options.voice_limit {
+            let channels = stream_params.channels.voice_channels() as usize;
+            for key in key_voices.iter_mut() {
+                key.data.set_render_budget(budget, channels);
+            }
+        }
+        if let Some(crossfade) = options.release_crossfade {
+            let fader = ReleaseFader::new(crossfade, stream_params);
+            for key in key_voices.iter_mut() {
+                key.data.set_release_fader(fader);
+            }
+        }
+
         VoiceChannel {
             params,
-            key_voices: fill_key_array(|i| Key::new(i, shared_voice_counter.clone(), options)),
+            key_voices,
+            noise_voices,
+            held_notes: 0,
+            spawn_throttle: SpawnThrottle::new(options.spawn_budget),
+            retrigger: Retrigger::new(options.retrigger),
+            pitch_bend_down_nrpn: options.pitch_bend_down_nrpn,
+            unmatched_notes: options.unmatched_notes,
+            release_crossfade: options.release_crossfade,
+            spawn_hook: None,
 
             threadpool,
 
             stream_params,
 
             control_event_data: ControlEventData::new_defaults(stream_params.sample_rate),
-            voice_control_data: VoiceControlData::new_defaults(),
+            voice_control_data,
+            expression_curve: ExpressionCurve::default(),
+            control_ramp: ControlRamp::new(options.control_rate, &voice_control_data),
+            lfo: LfoState::new(),
+            key_frequencies: KeyFrequencies::default(),
 
             cutoff: MultiChannelBiQuad::new(
-                stream_params.channels.count() as usize,
+                stream_params.channels.voice_channels() as usize,
                 FilterType::LowPass,
                 stream_params.sample_rate as f32 / 2.0,
                 stream_params.sample_rate as f32,
                 None,
             ),
+
+            voice_buffer: Vec::new(),
+
+            insert: None,
+
+            sidechain_send: None,
+
+            snapshot_voices: false,
+
+            frozen: None,
+
+            render_check: RenderThreadCheck::default(),
         }
     }
 
@@ -229,18 +577,19 @@ impl VoiceChannel {
         match self.stream_params.channels {
             ChannelCount::Mono => {
                 // Volume with smoother curve (cubic instead of quadratic for more natural response)
+                // The expression is already converted to a gain by its curve
                 for sample in out.iter_mut() {
-                    let vol = control.volume.get_next() * control.expression.get_next();
+                    let vol = control.volume.get_next();
                     // Use a gentler curve to prevent sudden volume jumps
-                    let vol = vol * vol * vol;
+                    let vol = vol * vol * vol * control.expression.get_next();
                     *sample *= vol;
                 }
             }
             ChannelCount::Stereo => {
                 // Pre-calculate volume and pan to avoid redundant computations
-                let vol = control.volume.get_next() * control.expression.get_next();
+                let vol = control.volume.get_next();
                 // Use a gentler cubic curve to prevent sudden volume jumps
-                let vol = vol * vol * vol;
+                let vol = vol * vol * vol * control.expression.get_next();
                 
                 // Pan with constant power panning law for smooth stereo image
                 let pan = control.pan.get_next().clamp(0.0, 1.0);
@@ -253,6 +602,39 @@ impl VoiceChannel {
                     sample[1] *= vol * right_gain;
                 }
             }
+            layout => {
+                // The voices were rendered in stereo, so the cutoff is applied
+                // before placing them into the speaker layout
+                if let Some(cutoff) = control.cutoff {
+                    self.cutoff
+                        .set_filter_type(FilterType::LowPass, cutoff, control.resonance);
+                    self.cutoff.process(&mut self.voice_buffer);
+                }
+
+                let vol = control.volume.get_next();
+                let vol = vol * vol * vol * control.expression.get_next();
+
+                // The left and right voice outputs are placed one front speaker
+                // width apart around the pan position, so a centered channel
+                // plays from the front pair and panning moves the whole image.
+                let width = layout.speaker_azimuths()[1].unwrap_or(30.0);
+                let pan = control.pan.get_next().clamp(0.0, 1.0);
+                let azimuth = (pan * 2.0 - 1.0) * width;
+
+                let speakers = layout.count() as usize;
+                let mut left_gains = [0.0; 8];
+                let mut right_gains = [0.0; 8];
+                layout.panning_gains(azimuth - width, &mut left_gains);
+                layout.panning_gains(azimuth + width, &mut right_gains);
+
+                for (frame, voice) in out.chunks_mut(speakers).zip(self.voice_buffer.chunks(2)) {
+                    let (left, right) = (voice[0] * vol, voice[1] * vol);
+                    for (i, sample) in frame.iter_mut().enumerate() {
+                        *sample = left * left_gains[i] + right * right_gains[i];
+                    }
+                }
+                return;
+            }
         }
 
         // Cutoff
@@ -264,18 +646,62 @@ impl VoiceChannel {
     }
 
     fn push_key_events_and_render(&mut self, out: &mut [f32]) {
+        let layout = self.stream_params.channels;
+        if layout.voice_channels() == layout.count() {
+            self.render_voices(out);
+            if let Some(sidechain) = self.sidechain_send.as_ref() {
+                sidechain.push(out, layout.count() as usize);
+            }
+        } else {
+            // Multichannel layouts render the voices in stereo first
+            let frames = out.len() / layout.count() as usize;
+            let mut voice_buffer = std::mem::take(&mut self.voice_buffer);
+            voice_buffer.resize(frames * layout.voice_channels() as usize, 0.0);
+            self.render_voices(&mut voice_buffer);
+            if let Some(sidechain) = self.sidechain_send.as_ref() {
+                sidechain.push(&voice_buffer, layout.voice_channels() as usize);
+            }
+            self.voice_buffer = voice_buffer;
+        }
+
+        let start = Instant::now();
+        self.apply_channel_effects(out);
+        if let Some(insert) = self.insert.as_mut() {
+            insert.process(out, &self.stream_params);
+            let reduction = insert.gain_reduction_db().unwrap_or(0.0);
+            self.params
+                .stats
+                .insert_gain_reduction
+                .store(reduction.to_bits(), Ordering::Relaxed);
+        }
+        self.params.stats.effects_time.record_since(start);
+    }
+
+    fn render_voices(&mut self, out: &mut [f32]) {
+        let start = Instant::now();
         self.params.load_program();
 
         // Fast zero using write_bytes (optimized by compiler to SIMD)
         unsafe {
             std::ptr::write_bytes(out.as_mut_ptr(), 0, out.len());
         }
+        let channels = self.stream_params.channels.voice_channels() as usize;
+        self.advance_lfo(out.len() / channels);
+        self.control_ramp
+            .start_buffer(&self.voice_control_data, out.len() / channels);
+
+        if self.params.channel_sf.has_group_polyphony() {
+            self.send_key_events_and_limit_groups();
+        }
+
         match self.threadpool.as_ref() {
             Some(pool) => {
                 let len = out.len();
                 let key_voices = &mut self.key_voices;
                 let params = &self.params;
                 let control_data = &self.voice_control_data;
+                let control_blocks = self.control_ramp.blocks();
+                let block_len = self.control_ramp.block_len(channels);
                 pool.install(|| {
                     key_voices.par_iter_mut().for_each(move |key| {
                         for e in key.event_cache.drain(..) {
@@ -284,7 +710,12 @@ impl VoiceChannel {
                         }
 
                         fast_zero_fill(&mut key.audio_cache, len);
-                        key.data.render_to(&mut key.audio_cache);
+                        render_control_blocks(
+                            &mut key.data,
+                            &mut key.audio_cache,
+                            control_blocks,
+                            block_len,
+                        );
                     });
                 });
 
@@ -294,6 +725,13 @@ impl VoiceChannel {
                         sum_simd(&key.audio_cache, out);
                     }
                 }
+
+                render_control_blocks(
+                    &mut self.noise_voices,
+                    out,
+                    self.control_ramp.blocks(),
+                    self.control_ramp.block_len(channels),
+                );
             }
             None => {
                 for key in self.key_voices.iter_mut() {
@@ -305,34 +743,186 @@ impl VoiceChannel {
                         );
                     }
 
-                    key.data.render_to(out);
+                    render_control_blocks(
+                        &mut key.data,
+                        out,
+                        self.control_ramp.blocks(),
+                        self.control_ramp.block_len(channels),
+                    );
                 }
+
+                render_control_blocks(
+                    &mut self.noise_voices,
+                    out,
+                    self.control_ramp.blocks(),
+                    self.control_ramp.block_len(channels),
+                );
             }
         }
 
-        self.apply_channel_effects(out);
+        if let Some(frozen) = self.frozen.as_mut() {
+            frozen.render_to(out);
+            if frozen.ended() {
+                self.frozen = None;
+                self.params
+                    .stats
+                    .voice_counter
+                    .fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+        self.params.stats.voice_render_time.record_since(start);
+    }
+
+    /// Sends the queued note events to the keys right away, so the regions
+    /// they spawn are selected with the controller values and soundfonts at
+    /// the time of the events, e.g. before an articulation switch or a
+    /// `SetSoundfonts` event changes them.
+    fn flush_key_events(&mut self) {
+        if self.key_voices.iter().all(|key| key.event_cache.is_empty()) {
+            return;
+        }
+
+        self.params.load_program();
+        for key in self.key_voices.iter_mut() {
+            for e in key.event_cache.drain(..) {
+                key.data
+                    .send_event(e, &self.voice_control_data, &self.params.channel_sf);
+            }
+        }
+        if self.params.channel_sf.has_group_polyphony() {
+            polyphony::limit_group_polyphony(&mut self.key_voices);
+        }
+    }
+
+    /// Sends the queued events to all keys before any of them is rendered,
+    /// so the voices they spawn can be limited by their polyphony groups
+    /// across keys. See `VoicePolyphony::polyphony`.
+    fn send_key_events_and_limit_groups(&mut self) {
+        let control_data = &self.voice_control_data;
+        let channel_sf = &self.params.channel_sf;
+        let send_events = |key: &mut Key| {
+            for e in key.event_cache.drain(..) {
+                key.data.send_event(e, control_data, channel_sf);
+            }
+        };
+
+        match self.threadpool.as_ref() {
+            Some(pool) => pool.install(|| self.key_voices.par_iter_mut().for_each(send_events)),
+            None => self.key_voices.iter_mut().for_each(send_events),
+        }
+
+        polyphony::limit_group_polyphony(&mut self.key_voices);
     }
 
     fn propagate_voice_controls(&mut self) {
+        let control = self.control_ramp.current(&self.voice_control_data);
         for key in self.key_voices.iter_mut() {
-            key.data.process_controls(&self.voice_control_data);
+            key.data.process_controls(&control);
         }
+        self.noise_voices.process_controls(&control);
     }
 
     /// Sends a ControlEvent to the channel.
     /// See the `ControlEvent` documentation for more information.
+    ///
+    /// The event is remapped first if its controller has a remap, see the
+    /// `ControllerRemap` documentation.
     pub fn process_control_event(&mut self, event: ControlEvent) {
+        let source = ControllerRemapTable::source_of(&event);
+        let Some((source, value)) = source.filter(|_| !self.params.cc_remap.is_empty()) else {
+            self.apply_control_event(event);
+            return;
+        };
+
+        let cc_remap = std::mem::take(&mut self.params.cc_remap);
+        match cc_remap.remap(source, value) {
+            Some(events) => {
+                for event in events {
+                    self.apply_control_event(event);
+                }
+            }
+            None => self.apply_control_event(event),
+        }
+        self.params.cc_remap = cc_remap;
+    }
+
+    fn apply_control_event(&mut self, event: ControlEvent) {
+        if let Some((controller, value)) = event.to_raw() {
+            let changed = self
+                .voice_control_data
+                .controllers
+                .get(controller as usize)
+                .is_some_and(|&stored| stored != value);
+            if changed {
+                self.flush_key_events();
+            }
+
+            if let Some(stored) = self
+                .voice_control_data
+                .controllers
+                .get_mut(controller as usize)
+            {
+                let previous = std::mem::replace(stored, value);
+                if let Some(lsb) = lsb_controller(controller) {
+                    self.voice_control_data.controllers[lsb as usize] = 0;
+                }
+                self.params.channel_sf.rebuild_if_needed();
+                let voices = self.params.channel_sf.spawn_voices_cc(
+                    &self.voice_control_data,
+                    controller,
+                    previous,
+                    value,
+                );
+                self.noise_voices.push_voices(voices);
+            }
+        }
+
+        // Raw control changes of the recognized controllers are applied
+        // through their typed variants
+        let event = match event {
+            ControlEvent::Raw(controller, value) => ControlEvent::from_raw(controller, value),
+            event => event,
+        };
+
         match event {
             ControlEvent::Raw(controller, value) => match controller {
                 0x00 => {
                     // Bank select
                     self.params.set_bank(value);
                 }
+                0x62 => {
+                    self.control_event_data.nrpn_lsb = value as i8;
+                    self.control_event_data.nrpn_selected = true;
+                }
+                0x63 => {
+                    self.control_event_data.nrpn_msb = value as i8;
+                    self.control_event_data.nrpn_selected = true;
+                }
                 0x64 => {
                     self.control_event_data.selected_lsb = value as i8;
+                    self.control_event_data.nrpn_selected = false;
                 }
                 0x65 => {
                     self.control_event_data.selected_msb = value as i8;
+                    self.control_event_data.nrpn_selected = false;
+                }
+                0x06 | 0x26 if self.control_event_data.nrpn_selected => {
+                    let data = &mut self.control_event_data;
+                    let selected = (data.nrpn_msb, data.nrpn_lsb);
+                    let is_pitch_bend_down = self
+                        .pitch_bend_down_nrpn
+                        .is_some_and(|(msb, lsb)| (msb as i8, lsb as i8) == selected);
+                    if is_pitch_bend_down {
+                        match controller {
+                            0x06 => data.pitch_bend_down_sensitivity_msb = value,
+                            _ => data.pitch_bend_down_sensitivity_lsb = value,
+                        }
+                        let sensitivity = (data.pitch_bend_down_sensitivity_msb as f32)
+                            + (data.pitch_bend_down_sensitivity_lsb as f32) / 100.0;
+                        self.apply_control_event(ControlEvent::PitchBendDownSensitivity(
+                            sensitivity,
+                        ));
+                    }
                 }
                 0x06 | 0x26 => {
                     let (lsb, msb) = {
@@ -359,7 +949,7 @@ impl VoiceChannel {
                                         + (data.pitch_bend_sensitivity_lsb as f32) / 100.0
                                 };
 
-                                self.process_control_event(ControlEvent::PitchBendSensitivity(
+                                self.apply_control_event(ControlEvent::PitchBendSensitivity(
                                     sensitivity,
                                 ))
                             }
@@ -374,12 +964,12 @@ impl VoiceChannel {
                                     << 6)
                                     + self.control_event_data.fine_tune_lsb as u16;
                                 let val = (val as f32 - 4096.0) / 4096.0 * 100.0;
-                                self.process_control_event(ControlEvent::FineTune(val));
+                                self.apply_control_event(ControlEvent::FineTune(val));
                             }
                             2 => {
                                 // Coarse tune
                                 if controller == 0x06 {
-                                    self.process_control_event(ControlEvent::CoarseTune(
+                                    self.apply_control_event(ControlEvent::CoarseTune(
                                         value as f32 - 64.0,
                                     ))
                                 }
@@ -388,31 +978,19 @@ impl VoiceChannel {
                         }
                     }
                 }
-                0x07 => {
-                    // Volume
-                    let vol: f32 = value as f32 / 128.0;
-                    self.control_event_data.volume.set_end(vol);
-                }
-                0x0A | 0x08 => {
-                    // Pan
-                    let pan: f32 = value as f32 / 128.0;
-                    self.control_event_data.pan.set_end(pan);
-                }
-                0x0B => {
-                    // Expression
-                    let expr = value as f32 / 128.0;
-                    self.control_event_data.expression.set_end(expr);
-                }
-                0x40 => {
-                    // Damper / Sustain
-                    let damper = match value {
-                        0..=63 => false,
-                        64..=127 => true,
-                        _ => false,
-                    };
-
-                    for key in self.key_voices.iter_mut() {
-                        key.data.set_damper(damper);
+                0x21 | 0x27 | 0x2B => {
+                    // The LSB of a 14-bit pair refines the value of its MSB.
+                    // The modulation wheel is only used through the
+                    // controller values.
+                    let msb = self.voice_control_data.controllers[controller as usize - 0x20];
+                    let value = from_cc_value_14bit(msb, value);
+                    match controller {
+                        0x27 => self.control_event_data.volume.set_end(value),
+                        0x2B => {
+                            let gain = self.expression_curve.gain(value);
+                            self.control_event_data.expression.set_end(gain);
+                        }
+                        _ => {}
                     }
                 }
                 0x47 => {
@@ -451,44 +1029,53 @@ impl VoiceChannel {
                         self.control_event_data.cutoff = None;
                     }
                 }
-                0x78 => {
-                    // All Sounds Off
-                    if value == 0 {
-                        self.process_event(ChannelEvent::Audio(ChannelAudioEvent::AllNotesKilled));
-                    }
-                }
                 0x79 => {
                     // Reset All Controllers
                     if value == 0 {
                         self.reset_control();
                     }
                 }
-                0x7B => {
-                    // All Notes Off
-                    if value == 0 {
-                        self.process_event(ChannelEvent::Audio(ChannelAudioEvent::AllNotesOff));
-                    }
-                }
                 _ => {}
             },
+            ControlEvent::Damper(damper) => {
+                for key in self.key_voices.iter_mut() {
+                    key.data.set_damper(damper);
+                }
+            }
+            ControlEvent::Volume(volume) => {
+                self.control_event_data.volume.set_end(volume);
+            }
+            ControlEvent::Expression(expression) => {
+                let gain = self.expression_curve.gain(expression);
+                self.control_event_data.expression.set_end(gain);
+            }
+            ControlEvent::Pan(pan) => {
+                self.control_event_data.pan.set_end(pan);
+            }
+            ControlEvent::AllNotesOff => {
+                self.process_event(ChannelEvent::Audio(ChannelAudioEvent::AllNotesOff));
+            }
+            ControlEvent::AllSoundOff => {
+                self.process_event(ChannelEvent::Audio(ChannelAudioEvent::AllNotesKilled));
+            }
             ControlEvent::PitchBendSensitivity(sensitivity) => {
-                let pitch_bend = {
-                    let data = &mut self.control_event_data;
-                    data.pitch_bend_sensitivity = sensitivity;
-                    data.pitch_bend_sensitivity * data.pitch_bend_value
-                };
-                self.process_control_event(ControlEvent::PitchBend(pitch_bend));
+                self.control_event_data.pitch_bend_sensitivity = sensitivity;
+                self.apply_pitch_bend_range();
+            }
+            ControlEvent::PitchBendDownSensitivity(sensitivity) => {
+                self.control_event_data.pitch_bend_down_sensitivity = Some(sensitivity);
+                self.apply_pitch_bend_range();
             }
             ControlEvent::PitchBendValue(value) => {
                 let pitch_bend = {
                     let data = &mut self.control_event_data;
                     data.pitch_bend_value = value;
-                    data.pitch_bend_sensitivity * data.pitch_bend_value
+                    data.pitch_bend_semitones(value)
                 };
-                self.process_control_event(ControlEvent::PitchBend(pitch_bend));
+                self.apply_control_event(ControlEvent::PitchBend(pitch_bend));
             }
             ControlEvent::PitchBend(value) => {
-                self.control_event_data.pitch_bend_value = value;
+                self.control_event_data.pitch_bend = value;
                 self.process_pitch();
             }
             ControlEvent::FineTune(value) => {
@@ -499,20 +1086,149 @@ impl VoiceChannel {
                 self.control_event_data.coarse_tune_value = value;
                 self.process_pitch();
             }
+            // Only used through the controller values and as a source of
+            // controller remaps
+            ControlEvent::Modulation(_) | ControlEvent::ChannelPressure(_) => {}
+        }
+    }
+
+    /// Applies a change of the pitch bend sensitivity to the pitch bend and
+    /// the per-note pitch bends.
+    fn apply_pitch_bend_range(&mut self) {
+        let data = &self.control_event_data;
+        let pitch_bend = data.pitch_bend_semitones(data.pitch_bend_value);
+        self.apply_control_event(ControlEvent::PitchBend(pitch_bend));
+
+        // The per-note pitch bends use the same sensitivity
+        for key in 0..128 {
+            if self.control_event_data.note_pitch_bends[key as usize] != 0.0 {
+                self.apply_note_pitch_bend(key);
+            }
         }
     }
 
     fn process_pitch(&mut self) {
         let data = &mut self.control_event_data;
-        let pitch_bend = data.pitch_bend_value;
+        let pitch_bend = data.pitch_bend;
         let fine_tune = data.fine_tune_value;
         let coarse_tune = data.coarse_tune_value;
-        let combined = pitch_bend + coarse_tune + fine_tune / 100.0;
+        let combined = pitch_bend + coarse_tune + fine_tune / 100.0 + data.lfo_pitch;
 
         self.voice_control_data.voice_pitch_multiplier = 2.0f32.powf(combined / 12.0);
         self.propagate_voice_controls();
     }
 
+    /// Moves the pitch of the voices with the channel's LFO, to its position
+    /// at the end of the buffer of the given number of frames.
+    fn advance_lfo(&mut self, frames: usize) {
+        let controllers = &self.voice_control_data.controllers;
+        let modulation = from_cc_value_14bit(controllers[0x01], controllers[0x21]);
+        let pitch = self
+            .lfo
+            .advance(frames, self.stream_params.sample_rate, modulation);
+        if pitch != self.control_event_data.lfo_pitch {
+            self.control_event_data.lfo_pitch = pitch;
+            self.process_pitch();
+        }
+    }
+
+    /// Returns the pitch multiplier of the per-note pitch bend of a key.
+    fn note_pitch_multiplier(&self, key: u8) -> f32 {
+        let data = &self.control_event_data;
+        let semitones = data.pitch_bend_semitones(data.note_pitch_bends[key as usize & 0x7F]);
+        2.0f32.powf(semitones / 12.0)
+    }
+
+    /// Applies the per-note pitch bend of a key to the keys its held notes
+    /// sound at, see `ChannelAudioEvent::NotePitchBend`.
+    fn apply_note_pitch_bend(&mut self, key: u8) {
+        let multiplier = self.note_pitch_multiplier(key);
+        let control = self.control_ramp.current(&self.voice_control_data);
+        let key_voices = &mut self.key_voices;
+        self.params.transpose.sounding_keys(key, |sounding| {
+            if let Some(sounding) = key_voices.get_mut(sounding as usize) {
+                sounding.data.set_pitch_multiplier(multiplier, &control);
+            }
+        });
+    }
+
+    /// Sends a note on event to its key, through the retrigger policy, the
+    /// spawn hook, the transpose and the spawn budget.
+    fn note_on(&mut self, mut key: u8, mut vel: u8, mut cents_offset: f32) {
+        match self.retrigger.note_on(key) {
+            RetriggerAction::Play => {}
+            RetriggerAction::ReleaseOldAndPlay => {
+                self.note_off(key);
+                let stats = &self.params.stats;
+                stats.retriggered_notes.fetch_add(1, Ordering::Relaxed);
+            }
+            RetriggerAction::Reject => {
+                let stats = &self.params.stats;
+                stats.rejected_notes.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        }
+
+        let original_key = key;
+        if let Some(hook) = &self.spawn_hook {
+            let mut params = NoteSpawnParams {
+                key,
+                velocity: vel,
+                cents_offset,
+                controllers: &self.voice_control_data.controllers,
+            };
+            let decision = hook.call(&mut params);
+            if decision == SpawnDecision::Skip {
+                self.params.transpose.note_on_as(original_key, None);
+                return;
+            }
+            (key, vel, cents_offset) = (params.key, params.velocity.min(127), params.cents_offset);
+        }
+
+        let legato = self.held_notes > 0;
+        let ev = if cents_offset != 0.0 {
+            KeyNoteEvent::DetunedOn {
+                vel,
+                cents: cents_offset,
+                legato,
+            }
+        } else if legato {
+            KeyNoteEvent::LegatoOn(vel)
+        } else {
+            KeyNoteEvent::On(vel)
+        };
+
+        let Some(key) = self.params.transpose.note_on_as(original_key, Some(key)) else {
+            return;
+        };
+        if (key as usize) < self.key_voices.len() {
+            // The note starts with the per-note pitch bend of its own key
+            let multiplier = self.note_pitch_multiplier(original_key);
+            let control = self.control_ramp.current(&self.voice_control_data);
+            self.key_voices[key as usize]
+                .data
+                .set_pitch_multiplier(multiplier, &control);
+
+            self.spawn_throttle
+                .note_on(key, vel, ev, &mut self.key_voices, &self.params.stats);
+            self.held_notes += 1;
+        }
+    }
+
+    /// Sends a note off event to its key, through the transpose and the
+    /// spawn budget.
+    fn note_off(&mut self, key: u8) {
+        self.params.transpose.note_off(key, |key| {
+            if let Some(key_data) = self.key_voices.get_mut(key as usize) {
+                if self.spawn_throttle.note_off(key) {
+                    let ev = KeyNoteEvent::Off;
+                    key_data.event_cache.push(ev);
+                }
+                self.held_notes = self.held_notes.saturating_sub(1);
+            }
+        });
+    }
+
     /// Sends a ChannelEvent to the channel.
     /// See the `ChannelEvent` documentation for more information.
     pub fn process_event(&mut self, event: ChannelEvent) {
@@ -521,54 +1237,162 @@ impl VoiceChannel {
 
     /// Sends multiple ChannelEvent items to the channel as an iterator.
     pub fn push_events_iter<T: Iterator<Item = ChannelEvent>>(&mut self, iter: T) {
+        let start = Instant::now();
         for e in iter {
+            #[cfg(feature = "tracing")]
+            crate::trace::dispatch(&e);
+
             match e {
                 ChannelEvent::Audio(audio) => match audio {
                     ChannelAudioEvent::NoteOn { key, vel } => {
-                        if let Some(key) = self.key_voices.get_mut(key as usize) {
-                            let ev = KeyNoteEvent::On(vel);
-                            key.event_cache.push(ev);
-                        }
+                        self.note_on(key, vel, 0.0);
+                    }
+                    ChannelAudioEvent::NoteOnDetuned {
+                        key,
+                        vel,
+                        cents_offset,
+                    } => {
+                        self.note_on(key, vel, cents_offset);
                     }
                     ChannelAudioEvent::NoteOff { key } => {
-                        if let Some(key) = self.key_voices.get_mut(key as usize) {
-                            let ev = KeyNoteEvent::Off;
-                            key.event_cache.push(ev);
+                        if self.retrigger.note_off(key) {
+                            self.note_off(key);
+                        }
+                    }
+                    ChannelAudioEvent::NoteKill { key } => {
+                        self.retrigger.note_kill(key);
+                        let killed = self.params.transpose.note_kill(key, |key| {
+                            if let Some(key_data) = self.key_voices.get_mut(key as usize) {
+                                self.spawn_throttle.note_kill(key);
+                                key_data.event_cache.push(KeyNoteEvent::AllKilled);
+                            }
+                        });
+                        self.held_notes = self.held_notes.saturating_sub(killed);
+                    }
+                    ChannelAudioEvent::NotePitchBend { key, value } => {
+                        if key < 128 {
+                            self.control_event_data.note_pitch_bends[key as usize] = value;
+                            self.apply_note_pitch_bend(key);
                         }
                     }
                     ChannelAudioEvent::AllNotesOff => {
+                        self.params.transpose.clear();
+                        self.spawn_throttle.clear();
+                        self.retrigger.clear();
+                        self.held_notes = 0;
                         for key in self.key_voices.iter_mut() {
                             let ev = KeyNoteEvent::AllOff;
                             key.event_cache.push(ev);
                         }
                     }
                     ChannelAudioEvent::AllNotesKilled => {
-                        for key in self.key_voices.iter_mut() {
-                            let ev = KeyNoteEvent::AllKilled;
-                            key.event_cache.push(ev);
-                        }
+                        self.kill_all_notes(false);
                     }
                     ChannelAudioEvent::ResetControl => {
                         self.reset_control();
                     }
+                    ChannelAudioEvent::ResetChannel => {
+                        self.kill_all_notes(true);
+                        self.reset_control();
+                    }
                     ChannelAudioEvent::Control(control) => {
                         self.process_control_event(control);
                     }
                     ChannelAudioEvent::ProgramChange(preset) => {
+                        // The queued notes start with the previous program
+                        self.flush_key_events();
                         self.params.set_preset(preset);
                     }
                     ChannelAudioEvent::SystemReset => {
-                        for key in self.key_voices.iter_mut() {
-                            key.event_cache.clear();
-                            key.event_cache.push(KeyNoteEvent::AllKilled);
-                        }
+                        self.kill_all_notes(true);
                         self.reset_control();
                         self.reset_program();
                     }
                 },
-                ChannelEvent::Config(config) => self.params.process_config_event(config),
+                ChannelEvent::Config(ChannelConfigEvent::SetReleaseTimeScales(scales)) => {
+                    self.voice_control_data.envelope.release_scales = scales;
+                    self.propagate_voice_controls();
+                }
+                ChannelEvent::Config(ChannelConfigEvent::SetTuning {
+                    a4,
+                    retune_sounding,
+                }) => self.set_tuning(a4, retune_sounding),
+                ChannelEvent::Config(ChannelConfigEvent::SetExpressionCurve(curve)) => {
+                    self.expression_curve = curve;
+                }
+                ChannelEvent::Config(ChannelConfigEvent::SetLfo(lfo)) => self.lfo.set_lfo(lfo),
+                ChannelEvent::Config(ChannelConfigEvent::SetInsert(insert)) => {
+                    if let Some(old) = std::mem::replace(&mut self.insert, insert) {
+                        self.params.stats.deferred_drops().drop_later(old);
+                    }
+                    self.params
+                        .stats
+                        .insert_gain_reduction
+                        .store(0.0f32.to_bits(), Ordering::Relaxed);
+                }
+                ChannelEvent::Config(ChannelConfigEvent::SetSidechainSend(sidechain)) => {
+                    if let Some(old) = self.sidechain_send.as_ref() {
+                        // The compressors reading it shouldn't keep ducking
+                        old.clear();
+                    }
+                    self.params
+                        .stats
+                        .sidechain_send
+                        .store(sidechain.is_some(), Ordering::Relaxed);
+                    self.sidechain_send = sidechain;
+                }
+                ChannelEvent::Config(ChannelConfigEvent::SetNoteSpawnHook(hook)) => {
+                    // The last reference to the hook may own large state
+                    if let Some(old) = std::mem::replace(&mut self.spawn_hook, hook) {
+                        self.params.stats.deferred_drops().drop_later(old);
+                    }
+                }
+                ChannelEvent::Config(ChannelConfigEvent::SetFallbackSoundfont(soundfont)) => {
+                    if self.unmatched_notes == UnmatchedNotePolicy::Fallback {
+                        self.flush_key_events();
+                        self.params.channel_sf.set_fallback_soundfont(soundfont);
+                        self.params.publish_soundfonts();
+                    }
+                }
+                ChannelEvent::Config(ChannelConfigEvent::SetVoiceSnapshots(enabled)) => {
+                    self.snapshot_voices = enabled;
+                    if !enabled {
+                        // Don't leave the voices of the last snapshot behind
+                        self.params.stats.voice_snapshots.publish(Vec::clear);
+                    }
+                }
+                ChannelEvent::Config(ChannelConfigEvent::Freeze { loop_frames }) => {
+                    self.freeze(loop_frames)
+                }
+                ChannelEvent::Config(ChannelConfigEvent::Unfreeze) => self.unfreeze(),
+                ChannelEvent::Config(ChannelConfigEvent::SetSampleRate(change)) => {
+                    self.set_sample_rate(change)
+                }
+                ChannelEvent::Config(ChannelConfigEvent::SetLayerCount(count)) => {
+                    for key in self.key_voices.iter_mut() {
+                        key.data.set_max_voices(count);
+                    }
+                    self.params
+                        .process_config_event(ChannelConfigEvent::SetLayerCount(count));
+                }
+                ChannelEvent::Config(config) => {
+                    // The notes sent before the spawners change keep playing
+                    // the regions they were sent for
+                    if matches!(
+                        config,
+                        ChannelConfigEvent::SetSoundfonts(_)
+                            | ChannelConfigEvent::SetRoutedSoundfonts(_)
+                            | ChannelConfigEvent::SetProgramMap(_)
+                            | ChannelConfigEvent::SetPercussionMode(_)
+                            | ChannelConfigEvent::SetVelocityCrossfade(_)
+                    ) {
+                        self.flush_key_events();
+                    }
+                    self.params.process_config_event(config)
+                }
             }
         }
+        self.params.stats.event_time.record_since(start);
     }
 
     /// Returns a reader for the VoiceChannel statistics.
@@ -578,15 +1402,277 @@ impl VoiceChannel {
         VoiceChannelStatsReader::new(stats)
     }
 
+    fn set_tuning(&mut self, a4: f32, retune_sounding: bool) {
+        if !a4.is_finite() || a4 <= 0.0 {
+            return;
+        }
+
+        self.key_frequencies.set_a4(a4);
+        self.voice_control_data.tuning = TuningControlData {
+            multiplier: self.key_frequencies.pitch_multiplier(),
+            retune_sounding,
+        };
+        if retune_sounding {
+            self.propagate_voice_controls();
+        }
+    }
+
+    /// Renders a buffer like `read_samples`, applying each event at its
+    /// frame offset within the buffer instead of before it. The buffer is
+    /// rendered in segments split at the event offsets, so notes start and
+    /// controls change at the exact frame.
+    ///
+    /// The events should be sorted by offset. An event with an offset before
+    /// the previous one is applied at the offset of the previous one, and
+    /// events with an offset at or after the end of the buffer are applied
+    /// after rendering it.
+    pub fn read_samples_timed(
+        &mut self,
+        out: &mut [f32],
+        events: impl IntoIterator<Item = (usize, ChannelEvent)>,
+    ) {
+        let _render = self.render_check.enter("VoiceChannel");
+        let channels = self.stream_params.channels.count() as usize;
+        assert!(out.len().is_multiple_of(channels));
+        let frames = out.len() / channels;
+
+        let mut events = events.into_iter().peekable();
+        let mut position = 0;
+
+        self.spawn_throttle.start_buffer(&mut self.key_voices);
+        self.clear_sidechain_send();
+        while position < frames {
+            let due = std::iter::from_fn(|| events.next_if(|(f, _)| *f <= position));
+            self.push_events_iter(due.map(|(_, e)| e));
+
+            let end = match events.peek() {
+                Some((offset, _)) => (*offset).min(frames),
+                None => frames,
+            };
+            self.push_key_events_and_render(&mut out[position * channels..end * channels]);
+            position = end;
+        }
+        self.spawn_throttle.end_buffer();
+        self.measure_levels(out);
+        self.publish_voice_snapshot();
+        self.publish_state();
+
+        self.push_events_iter(events.map(|(_, e)| e));
+    }
+
+    /// Renders the current voices of the channel into a loop of about
+    /// `loop_frames` frames, and replaces them with a single voice that
+    /// plays the loop, e.g. to keep a sustained layer of thousands of voices
+    /// going at the cost of one. The loop is played before the channel
+    /// effects, so they still apply to it.
+    ///
+    /// The loop is rendered right away, which takes as long as rendering
+    /// slightly more than `loop_frames` frames of the voices. The exact loop
+    /// length is searched within an eighth of `loop_frames` for the best
+    /// match with the start of the loop, and the end of the loop is
+    /// crossfaded into its start. Freezing a frozen channel includes the
+    /// previous loop in the new one.
+    pub fn freeze(&mut self, loop_frames: usize) {
+        if loop_frames == 0 {
+            return;
+        }
+
+        let channels = self.stream_params.channels.voice_channels() as usize;
+        let mut capture = vec![0.0; FrozenLoop::capture_frames(loop_frames) * channels];
+        for chunk in capture.chunks_mut(FREEZE_CHUNK_FRAMES * channels) {
+            self.render_voices(chunk);
+        }
+        let frozen = FrozenLoop::from_capture(&capture, channels, loop_frames);
+
+        for key in self.key_voices.iter_mut() {
+            key.data.clear_voices();
+        }
+        self.noise_voices.clear_voices();
+        if self.frozen.replace(frozen).is_none() {
+            self.params
+                .stats
+                .voice_counter
+                .fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Fades out the loop of a frozen channel. See `freeze`.
+    pub fn unfreeze(&mut self) {
+        if let Some(frozen) = self.frozen.as_mut() {
+            frozen.start_fade_out();
+        }
+    }
+
+    /// Returns whether the channel plays a frozen loop. See `freeze`.
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.is_some()
+    }
+
+    /// Switches the channel to another sample rate, see
+    /// `ChannelConfigEvent::SetSampleRate`. The voices play samples
+    /// resampled for the previous rate, so they are dropped.
+    fn set_sample_rate(&mut self, change: SampleRateChange) {
+        self.params.transpose.clear();
+        self.spawn_throttle.clear();
+        self.held_notes = 0;
+        for key in self.key_voices.iter_mut() {
+            key.event_cache.clear();
+            key.data.clear_voices();
+        }
+        self.noise_voices.clear_voices();
+        if self.frozen.take().is_some() {
+            self.params
+                .stats
+                .voice_counter
+                .fetch_sub(1, Ordering::Relaxed);
+        }
+
+        let sample_rate = change.sample_rate();
+        self.stream_params.sample_rate = sample_rate;
+        self.params.constant.stream_params = self.stream_params;
+
+        let control = &mut self.control_event_data;
+        control.volume.set_sample_rate(sample_rate);
+        control.pan.set_sample_rate(sample_rate);
+        control.expression.set_sample_rate(sample_rate);
+        self.cutoff = MultiChannelBiQuad::new(
+            self.stream_params.channels.voice_channels() as usize,
+            FilterType::LowPass,
+            sample_rate as f32 / 2.0,
+            sample_rate as f32,
+            None,
+        );
+        if let Some(crossfade) = self.release_crossfade {
+            let fader = ReleaseFader::new(crossfade, self.stream_params);
+            for key in self.key_voices.iter_mut() {
+                key.data.set_release_fader(fader);
+            }
+        }
+
+        self.params
+            .channel_sf
+            .convert_soundfonts(|soundfont| change.convert(soundfont));
+        self.params.publish_soundfonts();
+        // The change may hold the last reference to the replaced soundfonts
+        self.params.stats.deferred_drops().drop_later(change);
+    }
+
+    /// Sets the tempo clock that the rate of a synced `ChannelLfo` follows.
+    /// Channel groups share their own clock with their channels.
+    pub fn set_tempo_clock(&mut self, tempo: TempoClock) {
+        self.lfo.set_tempo_clock(tempo);
+    }
+
+    /// Returns the key frequency table of the channel's current tuning.
+    pub fn key_frequencies(&self) -> &KeyFrequencies {
+        &self.key_frequencies
+    }
+
+    /// Returns whether the channel sends the audio of its voices to a
+    /// sidechain. See `ChannelConfigEvent::SetSidechainSend`.
+    pub fn has_sidechain_send(&self) -> bool {
+        self.sidechain_send.is_some()
+    }
+
+    /// Removes the sidechain audio of the previous buffer, which the
+    /// segments of the new buffer are appended to.
+    fn clear_sidechain_send(&self) {
+        if let Some(sidechain) = self.sidechain_send.as_ref() {
+            sidechain.clear();
+        }
+    }
+
+    fn measure_levels(&self, out: &[f32]) {
+        self.params.stats.levels.measure(
+            out,
+            self.stream_params.channels.count() as usize,
+            self.stream_params.sample_rate,
+        );
+    }
+
+    /// Returns the current controller state of the channel, including the
+    /// events sent since the last rendered buffer. See the
+    /// `ChannelStateSnapshot` documentation for more information.
+    pub fn state(&self) -> ChannelStateSnapshot {
+        let data = &self.control_event_data;
+        ChannelStateSnapshot {
+            controllers: self.voice_control_data.controllers,
+            pitch_bend_value: data.pitch_bend_value,
+            pitch_bend_sensitivity: data.pitch_bend_sensitivity,
+            pitch_bend_down_sensitivity: data.pitch_bend_down_sensitivity,
+            pitch_bend: data.pitch_bend,
+            fine_tune: data.fine_tune_value,
+            coarse_tune: data.coarse_tune_value,
+            bank: self.params.program.bank,
+            program: self.params.program.preset,
+        }
+    }
+
+    /// Checks the key and velocity coverage of the channel's soundfonts for
+    /// its current program, limited to the given keys: the cells without
+    /// any regions, and the soundfonts whose regions are shadowed by other
+    /// soundfonts. See the `CoverageReport` documentation for more
+    /// information.
+    ///
+    /// This resolves the regions of every cell, so it's meant to be run
+    /// offline, e.g. before a performance, not between renders.
+    pub fn coverage_report(&self, keys: RangeInclusive<u8>) -> CoverageReport {
+        self.params.channel_sf.coverage_report(keys)
+    }
+
+    fn publish_state(&self) {
+        let state = self.state();
+        self.params.stats.state.publish(|slot| *slot = state);
+    }
+
+    fn publish_voice_snapshot(&self) {
+        if !self.snapshot_voices {
+            return;
+        }
+
+        self.params.stats.voice_snapshots.publish(|snapshot| {
+            snapshot.clear();
+            for key in self.key_voices.iter() {
+                key.data.snapshot_voices(snapshot);
+            }
+        });
+    }
+
+    /// Kills all voices and forgets the held notes, keeping the controller
+    /// state. With `drop_queued`, the note events not yet sent to the keys
+    /// are dropped instead of spawning voices that are killed right away.
+    fn kill_all_notes(&mut self, drop_queued: bool) {
+        self.params.transpose.clear();
+        self.spawn_throttle.clear();
+        self.retrigger.clear();
+        self.held_notes = 0;
+        for key in self.key_voices.iter_mut() {
+            if drop_queued {
+                key.event_cache.clear();
+            }
+            key.event_cache.push(KeyNoteEvent::AllKilled);
+        }
+        self.noise_voices.kill_all_voices();
+        self.unfreeze();
+    }
+
     fn reset_control(&mut self) {
         self.control_event_data = ControlEventData::new_defaults(self.stream_params.sample_rate);
+
+        // The configured release scales and tuning aren't controller state
+        let release_scales = self.voice_control_data.envelope.release_scales;
+        let tuning = self.voice_control_data.tuning;
         self.voice_control_data = VoiceControlData::new_defaults();
+        self.voice_control_data.envelope.release_scales = release_scales;
+        self.voice_control_data.tuning = tuning;
         self.propagate_voice_controls();
 
         self.control_event_data.cutoff = None;
 
+        let control = self.control_ramp.current(&self.voice_control_data);
         for key in self.key_voices.iter_mut() {
             key.data.set_damper(false);
+            key.data.set_pitch_multiplier(1.0, &control);
         }
     }
 
@@ -602,6 +1688,13 @@ impl AudioPipe for VoiceChannel {
     }
 
     fn read_samples_unchecked(&mut self, out: &mut [f32]) {
+        let _render = self.render_check.enter("VoiceChannel");
+        self.spawn_throttle.start_buffer(&mut self.key_voices);
+        self.clear_sidechain_send();
         self.push_key_events_and_render(out);
+        self.spawn_throttle.end_buffer();
+        self.measure_levels(out);
+        self.publish_voice_snapshot();
+        self.publish_state();
     }
 }
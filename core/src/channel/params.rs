@@ -1,19 +1,50 @@
-use std::sync::{atomic::AtomicU64, Arc};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, AtomicU64},
+    Arc, Weak,
+};
 
-use crate::AudioStreamParams;
+use crate::{
+    soundfont::SoundfontBase,
+    util::{DeferredDrops, SectionTimer, SectionTiming},
+    AudioStreamParams,
+};
 
 use super::{
+    cc_remap::ControllerRemapTable,
     channel_sf::{ChannelSoundfont, ProgramDescriptor},
-    ChannelConfigEvent,
+    level_meter::LevelMeter,
+    transpose::KeyTranspose,
+    unmatched::UnmatchedNotes,
+    voice_snapshot::SnapshotBuffer,
+    ChannelConfigEvent, ChannelLevel, ChannelStateSnapshot, UnmatchedNote, VoiceSnapshot,
 };
 
 /// Holds the statistics for an instance of VoiceChannel.
 #[derive(Debug, Clone)]
 pub struct VoiceChannelStats {
     pub(super) voice_counter: Arc<AtomicU64>,
+    pub(super) voice_render_time: Arc<SectionTimer>,
+    pub(super) effects_time: Arc<SectionTimer>,
+    pub(super) event_time: Arc<SectionTimer>,
+    pub(super) deferred_spawns: Arc<AtomicU64>,
+    pub(super) dropped_spawns: Arc<AtomicU64>,
+    pub(super) deferred_drops: Arc<AtomicU64>,
+    pub(super) retriggered_notes: Arc<AtomicU64>,
+    pub(super) rejected_notes: Arc<AtomicU64>,
+    pub(super) trimmed_layers: Arc<AtomicU64>,
+    pub(super) unmatched_notes: UnmatchedNotes,
+    pub(super) voice_snapshots: Arc<SnapshotBuffer<Vec<VoiceSnapshot>>>,
+    pub(super) levels: Arc<LevelMeter>,
+    pub(super) insert_gain_reduction: Arc<AtomicU32>,
+    pub(super) sidechain_send: Arc<AtomicBool>,
+
+    /// The soundfonts of the channel, published when they change
+    pub(super) soundfonts: Arc<SnapshotBuffer<Vec<Weak<dyn SoundfontBase>>>>,
+    pub(super) state: Arc<SnapshotBuffer<ChannelStateSnapshot>>,
 }
 
 /// Reads the statistics of an instance of VoiceChannel in a usable way.
+#[derive(Debug)]
 pub struct VoiceChannelStatsReader {
     stats: VoiceChannelStats,
 }
@@ -28,13 +59,39 @@ pub struct VoiceChannelParams {
     pub layers: Option<usize>,
     pub channel_sf: ChannelSoundfont,
     pub program: ProgramDescriptor,
+    pub transpose: KeyTranspose,
+    pub cc_remap: ControllerRemapTable,
     pub constant: VoiceChannelConst,
 }
 
 impl VoiceChannelStats {
     pub fn new() -> Self {
         let voice_counter = Arc::new(AtomicU64::new(0));
-        Self { voice_counter }
+        Self {
+            voice_counter,
+            voice_render_time: Default::default(),
+            effects_time: Default::default(),
+            event_time: Default::default(),
+            deferred_spawns: Default::default(),
+            dropped_spawns: Default::default(),
+            deferred_drops: Default::default(),
+            retriggered_notes: Default::default(),
+            rejected_notes: Default::default(),
+            trimmed_layers: Default::default(),
+            unmatched_notes: Default::default(),
+            voice_snapshots: Default::default(),
+            levels: Default::default(),
+            insert_gain_reduction: Default::default(),
+            sidechain_send: Default::default(),
+            soundfonts: Default::default(),
+            state: Default::default(),
+        }
+    }
+
+    /// Drops values on the background thread, counting them in the
+    /// statistics.
+    pub(super) fn deferred_drops(&self) -> DeferredDrops {
+        DeferredDrops::new(self.deferred_drops.clone())
     }
 }
 
@@ -46,13 +103,16 @@ impl Default for VoiceChannelStats {
 
 impl VoiceChannelParams {
     pub fn new(stream_params: AudioStreamParams) -> Self {
-        let channel_sf = ChannelSoundfont::new();
+        let stats = VoiceChannelStats::new();
+        let channel_sf = ChannelSoundfont::new(stats.deferred_drops());
 
         Self {
-            stats: VoiceChannelStats::new(),
+            stats,
             layers: Some(4), // Limit to 4 voices per key
             channel_sf,
             program: Default::default(),
+            transpose: KeyTranspose::new(),
+            cc_remap: ControllerRemapTable::new(),
             constant: VoiceChannelConst { stream_params },
         }
     }
@@ -60,8 +120,14 @@ impl VoiceChannelParams {
     pub fn process_config_event(&mut self, event: ChannelConfigEvent) {
         match event {
             ChannelConfigEvent::SetSoundfonts(soundfonts) => {
-                self.channel_sf.set_soundfonts(soundfonts)
+                self.channel_sf.set_unrouted_soundfonts(&soundfonts);
+                self.publish_soundfonts();
+            }
+            ChannelConfigEvent::SetRoutedSoundfonts(soundfonts) => {
+                self.channel_sf.set_soundfonts(soundfonts);
+                self.publish_soundfonts();
             }
+            ChannelConfigEvent::SetProgramMap(map) => self.channel_sf.set_program_map(map),
             ChannelConfigEvent::SetLayerCount(count) => {
                 self.layers = count;
             }
@@ -73,6 +139,31 @@ impl VoiceChannelParams {
                 }
                 self.channel_sf.change_program(self.program);
             }
+            ChannelConfigEvent::SetTranspose(semitones) => {
+                self.transpose.set_semitones(semitones);
+            }
+            ChannelConfigEvent::SetTransposeClamp(clamp) => {
+                self.transpose.set_clamp(clamp);
+            }
+            ChannelConfigEvent::SetControllerRemaps(remaps) => {
+                self.cc_remap.set_remaps(remaps);
+            }
+            ChannelConfigEvent::SetVelocityCrossfade(width) => {
+                self.channel_sf.set_velocity_crossfade(width);
+            }
+            // Applied by the channel
+            ChannelConfigEvent::SetReleaseTimeScales(_)
+            | ChannelConfigEvent::SetTuning { .. }
+            | ChannelConfigEvent::SetExpressionCurve(_)
+            | ChannelConfigEvent::SetLfo(_)
+            | ChannelConfigEvent::SetFallbackSoundfont(_)
+            | ChannelConfigEvent::SetInsert(_)
+            | ChannelConfigEvent::SetSidechainSend(_)
+            | ChannelConfigEvent::SetNoteSpawnHook(_)
+            | ChannelConfigEvent::SetVoiceSnapshots(_)
+            | ChannelConfigEvent::Freeze { .. }
+            | ChannelConfigEvent::SetSampleRate(_)
+            | ChannelConfigEvent::Unfreeze => {}
         }
     }
 
@@ -89,6 +180,15 @@ impl VoiceChannelParams {
     pub fn load_program(&mut self) {
         self.channel_sf.change_program(self.program);
     }
+
+    /// Publishes the soundfonts of the channel to its statistics, see
+    /// `VoiceChannelStatsReader::soundfonts`.
+    pub fn publish_soundfonts(&self) {
+        self.stats.soundfonts.publish(|soundfonts| {
+            soundfonts.clear();
+            soundfonts.extend(self.channel_sf.soundfonts().map(Arc::downgrade));
+        });
+    }
 }
 
 impl VoiceChannelStatsReader {
@@ -102,4 +202,142 @@ impl VoiceChannelStatsReader {
             .voice_counter
             .load(std::sync::atomic::Ordering::Relaxed)
     }
+
+    /// The time spent rendering the voices of the VoiceChannel per buffer,
+    /// including dispatching the note events to the keys.
+    pub fn voice_render_time(&self) -> SectionTiming {
+        self.stats.voice_render_time.timing()
+    }
+
+    /// The time spent applying the channel effects (volume, pan, cutoff)
+    /// per buffer.
+    pub fn effects_time(&self) -> SectionTiming {
+        self.stats.effects_time.timing()
+    }
+
+    /// The time spent processing incoming events per call to
+    /// `push_events_iter`.
+    pub fn event_time(&self) -> SectionTiming {
+        self.stats.event_time.timing()
+    }
+
+    /// The number of note ons that were deferred to a later buffer by the
+    /// spawn budget. See the `SpawnBudget` documentation.
+    pub fn deferred_spawns(&self) -> u64 {
+        self.stats
+            .deferred_spawns
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// The number of note ons that were dropped by the spawn budget. See
+    /// the `SpawnBudget` documentation.
+    pub fn dropped_spawns(&self) -> u64 {
+        self.stats
+            .dropped_spawns
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// The number of note ons that released the previous note on their key
+    /// first. See `RetriggerPolicy::ReleaseOld`.
+    pub fn retriggered_notes(&self) -> u64 {
+        self.stats
+            .retriggered_notes
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// The number of note ons that were ignored because their key was
+    /// already held. See `RetriggerPolicy::Reject`.
+    pub fn rejected_notes(&self) -> u64 {
+        self.stats
+            .rejected_notes
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// The number of voices killed to keep the keys within the layer limit
+    /// of soft note ons. See `ChannelInitOptions::velocity_layers`.
+    pub fn trimmed_layers(&self) -> u64 {
+        self.stats
+            .trimmed_layers
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// The number of note ons that no region of the soundfonts matched,
+    /// whatever the unmatched note policy. See `UnmatchedNotePolicy`.
+    pub fn unmatched_notes(&self) -> u64 {
+        self.stats.unmatched_notes.count()
+    }
+
+    /// Takes the note ons that no region matched since the last call, with
+    /// `UnmatchedNotePolicy::Diagnostics`. At most 256 notes are kept until
+    /// they are taken, the following ones are only counted.
+    pub fn take_unmatched_notes(&self) -> Vec<UnmatchedNote> {
+        self.stats.unmatched_notes.take()
+    }
+
+    /// The number of large values, such as replaced soundfonts and killed
+    /// voices, that were dropped on a background thread instead of the
+    /// render thread.
+    pub fn deferred_drops(&self) -> u64 {
+        self.stats
+            .deferred_drops
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// The state of each active voice of the VoiceChannel after the last
+    /// rendered buffer, without the voices triggered by controllers. Empty
+    /// unless voice snapshots are enabled, see
+    /// `ChannelConfigEvent::SetVoiceSnapshots`.
+    pub fn voice_snapshot(&self) -> Vec<VoiceSnapshot> {
+        self.stats.voice_snapshots.latest()
+    }
+
+    /// The output level of each audio channel of the VoiceChannel, e.g. left
+    /// and right for stereo, after the insert effect and before mixing it
+    /// with the other channels. Updated once per rendered buffer. See the
+    /// `ChannelLevel` documentation for more information.
+    pub fn levels(&self) -> Vec<ChannelLevel> {
+        self.stats.levels.levels()
+    }
+
+    /// The gain reduction of the insert of the VoiceChannel in the last
+    /// rendered buffer, in decibels, e.g. to meter a `Compressor`. `0.0` if
+    /// the insert doesn't reduce the gain. See
+    /// `AudioInsert::gain_reduction_db`.
+    pub fn insert_gain_reduction_db(&self) -> f32 {
+        f32::from_bits(
+            self.stats
+                .insert_gain_reduction
+                .load(std::sync::atomic::Ordering::Relaxed),
+        )
+    }
+
+    /// Whether the VoiceChannel sends its audio to a sidechain, in which
+    /// case it has to be rendered before the other channels. See
+    /// `ChannelConfigEvent::SetSidechainSend`.
+    pub fn has_sidechain_send(&self) -> bool {
+        self.stats
+            .sidechain_send
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// The controller state of the VoiceChannel after the last rendered
+    /// buffer, e.g. to display it. Events sent since then are only included
+    /// once the next buffer is rendered. See the `ChannelStateSnapshot`
+    /// documentation for more information.
+    pub fn state(&self) -> ChannelStateSnapshot {
+        self.stats.state.latest()
+    }
+
+    /// Resets the held maximum of the levels.
+    pub fn reset_max_hold(&self) {
+        self.stats.levels.reset_max_hold();
+    }
+
+    /// The soundfonts the VoiceChannel plays, including its fallback
+    /// soundfont, e.g. to convert them ahead of a `SampleRateChange`.
+    /// Updated when the channel processes a soundfont change.
+    pub fn soundfonts(&self) -> Vec<Arc<dyn SoundfontBase>> {
+        let soundfonts = self.stats.soundfonts.latest();
+        soundfonts.iter().filter_map(Weak::upgrade).collect()
+    }
 }
@@ -0,0 +1,65 @@
+use crate::voice::Voice;
+
+use super::Key;
+
+/// A playing voice of a polyphony group, with the limit of its region.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct GroupedVoice {
+    pub group: u32,
+    pub limit: u32,
+    pub order: u64,
+}
+
+/// Finds the polyphony groups that play more voices than their limit.
+/// Replaces the contents of `cutoffs` with each of these groups and the
+/// spawn order of its oldest voice that can keep playing, the older voices
+/// have to be killed. If the regions of a group have different limits, the
+/// most restrictive one applies.
+pub(super) fn polyphony_cutoffs(voices: &mut [GroupedVoice], cutoffs: &mut Vec<(u32, u64)>) {
+    voices.sort_unstable_by_key(|v| (v.group, v.order));
+    cutoffs.clear();
+    cutoffs.extend(
+        voices
+            .chunk_by(|a, b| a.group == b.group)
+            .filter_map(|group| {
+                let limit = group.iter().map(|v| v.limit).min()? as usize;
+                let excess = group.len().checked_sub(limit).filter(|&e| e > 0)?;
+                Some((group[0].group, group[excess].order))
+            }),
+    );
+}
+
+/// Returns true if the voice is older than the cutoff of its group.
+pub(super) fn is_cut_off(voice: &dyn Voice, cutoffs: &[(u32, u64)]) -> bool {
+    voice.polyphony().is_some_and(|(polyphony, order)| {
+        cutoffs
+            .iter()
+            .any(|&(group, cutoff)| group == polyphony.group && order < cutoff)
+    })
+}
+
+/// Kills the oldest voices of the polyphony groups that play more voices on
+/// all keys than their `polyphony` limit allows. Called after the keys
+/// spawned their voices, as each key spawns its own voices in parallel.
+pub(super) fn limit_group_polyphony(keys: &mut [Key]) {
+    let mut voices = Vec::new();
+    for key in keys.iter() {
+        voices.extend(key.data.voice_polyphony().filter_map(|(polyphony, order)| {
+            Some(GroupedVoice {
+                group: polyphony.group,
+                limit: polyphony.polyphony?,
+                order,
+            })
+        }));
+    }
+
+    let mut cutoffs = Vec::new();
+    polyphony_cutoffs(&mut voices, &mut cutoffs);
+    if cutoffs.is_empty() {
+        return;
+    }
+    for key in keys.iter_mut() {
+        key.data
+            .kill_voices_where(|voice| is_cut_off(voice, &cutoffs));
+    }
+}
@@ -0,0 +1,50 @@
+use crate::{
+    voice::{FadeInVoice, Voice},
+    AudioStreamParams,
+};
+
+/// Crossfades the release samples of a note, such as the damper noise of a
+/// piano, with its releasing voices, so the sound doesn't bump where they
+/// overlap.
+///
+/// On a note off, the level of the volume envelope of the released voices
+/// is measured, and the release sample voices fade in to that level with an
+/// equal power curve. The combined energy stays continuous when the released
+/// regions fade out in a straight line over the same time, i.e. with
+/// `ampeg_release` matching `time_ms` and the `Exponential` release curve of
+/// `EnvelopeOptions`, whose release is a straight line in amplitude, and the
+/// release samples are recorded at the level of the sustaining samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct ReleaseCrossfade {
+    /// The time the release samples take to fade in, in milliseconds.
+    pub time_ms: f32,
+}
+
+/// Applies the release crossfade of a channel to the release sample voices
+/// of its keys.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct ReleaseFader {
+    fade_frames: u32,
+    channels: usize,
+}
+
+impl ReleaseFader {
+    pub fn new(crossfade: ReleaseCrossfade, stream_params: AudioStreamParams) -> Self {
+        let fade_frames = crossfade.time_ms.max(0.0) / 1000.0 * stream_params.sample_rate as f32;
+        Self {
+            fade_frames: fade_frames as u32,
+            channels: stream_params.channels.voice_channels() as usize,
+        }
+    }
+
+    /// Fades in a release sample voice to the level of the released voices.
+    pub fn fade_in(&self, voice: Box<dyn Voice>, level: f32) -> Box<dyn Voice> {
+        Box::new(FadeInVoice::new(
+            voice,
+            level,
+            self.fade_frames,
+            self.channels,
+        ))
+    }
+}
@@ -0,0 +1,175 @@
+/// Controls what happens when a key receives a note on while a previous note
+/// on the same key is still held, e.g. in MIDI files that repeat note ons
+/// without note offs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum RetriggerPolicy {
+    /// The notes overlap, each one playing its own voices.
+    #[default]
+    Allow,
+
+    /// The oldest held note of the key is released before the new one is
+    /// played, as if it received a note off. Note offs beyond the number of
+    /// held notes are ignored.
+    ReleaseOld,
+
+    /// The new note is ignored, along with its note off.
+    Reject,
+}
+
+/// Continues the playback position of the previous note of a key when the
+/// key is played again early in that note, e.g. for fast repeated notes of
+/// strings, which sound unnatural when each one restarts the attack of the
+/// sample.
+///
+/// The voices of the new note start at the sample position of the newest
+/// voice of the key, whether it's still held or already released, and its
+/// position is read when the note on is applied. Voices of other regions
+/// use the same position.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct RetriggerOffset {
+    /// The previous note is only continued while its position is below this
+    /// number of frames of its sample. Later notes start from the beginning.
+    pub max_position: u32,
+
+    /// The fraction of the previous note's position the new note starts
+    /// at. `1.0` starts it at the same position.
+    pub fraction: f32,
+}
+
+impl RetriggerOffset {
+    /// Returns the position a new note starts at, given the position of the
+    /// previous note, or `None` if the previous note is too far along.
+    pub(super) fn start_position(&self, previous: f64) -> Option<f64> {
+        (previous < self.max_position as f64).then(|| previous * self.fraction.max(0.0) as f64)
+    }
+}
+
+/// What to do with a note on, according to the retrigger policy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum RetriggerAction {
+    Play,
+    ReleaseOldAndPlay,
+    Reject,
+}
+
+/// Applies the retrigger policy of a channel to its note events, before they
+/// are sent to the keys.
+pub(super) struct Retrigger {
+    policy: RetriggerPolicy,
+
+    /// The number of held notes on each key
+    held: [u32; 128],
+
+    /// The number of note offs to ignore on each key, because their note
+    /// ons were rejected
+    skipped_note_offs: [u32; 128],
+}
+
+impl Retrigger {
+    pub fn new(policy: RetriggerPolicy) -> Self {
+        Self {
+            policy,
+            held: [0; 128],
+            skipped_note_offs: [0; 128],
+        }
+    }
+
+    /// Returns what to do with a note on of the key.
+    pub fn note_on(&mut self, key: u8) -> RetriggerAction {
+        let Some(held) = self.held.get_mut(key as usize) else {
+            return RetriggerAction::Play;
+        };
+
+        match self.policy {
+            RetriggerPolicy::Allow => RetriggerAction::Play,
+            _ if *held == 0 => {
+                *held += 1;
+                RetriggerAction::Play
+            }
+            RetriggerPolicy::ReleaseOld => RetriggerAction::ReleaseOldAndPlay,
+            RetriggerPolicy::Reject => {
+                self.skipped_note_offs[key as usize] += 1;
+                RetriggerAction::Reject
+            }
+        }
+    }
+
+    /// Returns true if a note off should be sent to its key, or false if it
+    /// belongs to a rejected note or to a note that was already released.
+    pub fn note_off(&mut self, key: u8) -> bool {
+        let Some(held) = self.held.get_mut(key as usize) else {
+            return true;
+        };
+
+        match self.policy {
+            RetriggerPolicy::Allow => true,
+            RetriggerPolicy::ReleaseOld => {
+                let was_held = *held > 0;
+                *held = held.saturating_sub(1);
+                was_held
+            }
+            RetriggerPolicy::Reject => {
+                let skipped = &mut self.skipped_note_offs[key as usize];
+                if *skipped > 0 {
+                    *skipped -= 1;
+                    return false;
+                }
+                *held = held.saturating_sub(1);
+                true
+            }
+        }
+    }
+
+    /// Forgets the held notes of a key, e.g. when it is killed.
+    pub fn note_kill(&mut self, key: u8) {
+        if let Some(held) = self.held.get_mut(key as usize) {
+            *held = 0;
+            self.skipped_note_offs[key as usize] = 0;
+        }
+    }
+
+    /// Forgets all held notes, e.g. after all notes were released or killed.
+    pub fn clear(&mut self) {
+        self.held = [0; 128];
+        self.skipped_note_offs = [0; 128];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_release_old() {
+        let mut retrigger = Retrigger::new(RetriggerPolicy::ReleaseOld);
+        assert_eq!(retrigger.note_on(60), RetriggerAction::Play);
+        assert_eq!(retrigger.note_on(60), RetriggerAction::ReleaseOldAndPlay);
+        assert_eq!(retrigger.note_on(60), RetriggerAction::ReleaseOldAndPlay);
+        assert_eq!(retrigger.note_on(61), RetriggerAction::Play);
+
+        // Only one note is held on the key, so only one note off applies
+        assert!(retrigger.note_off(60));
+        assert!(!retrigger.note_off(60));
+        assert!(!retrigger.note_off(60));
+        assert_eq!(retrigger.note_on(60), RetriggerAction::Play);
+    }
+
+    #[test]
+    fn test_reject() {
+        let mut retrigger = Retrigger::new(RetriggerPolicy::Reject);
+        assert_eq!(retrigger.note_on(60), RetriggerAction::Play);
+        assert_eq!(retrigger.note_on(60), RetriggerAction::Reject);
+
+        // The note off of the rejected note is ignored, the next one
+        // releases the held note
+        assert!(!retrigger.note_off(60));
+        assert!(retrigger.note_off(60));
+        assert_eq!(retrigger.note_on(60), RetriggerAction::Play);
+
+        retrigger.note_on(60);
+        retrigger.note_kill(60);
+        assert_eq!(retrigger.note_on(60), RetriggerAction::Play);
+    }
+}
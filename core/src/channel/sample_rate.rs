@@ -0,0 +1,212 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use crate::soundfont::{SoundfontBase, SoundfontLoadError};
+
+struct ConvertedSoundfonts {
+    /// The soundfonts converted so far, with the soundfont they replace
+    soundfonts: Vec<(Arc<dyn SoundfontBase>, Arc<dyn SoundfontBase>)>,
+    errors: Vec<SoundfontLoadError>,
+}
+
+/// A change of the sample rate of the channels of a synthesizer, sent to
+/// each of them with `ChannelConfigEvent::SetSampleRate`.
+///
+/// The soundfonts of the channels are converted to the new rate with
+/// `SoundfontBase::with_sample_rate`. Converting a soundfont usually loads
+/// it again, so they should be converted with `prepare` before the change
+/// is sent, as the channels otherwise convert them while they render.
+/// Cloning the change shares the converted soundfonts, so a soundfont used
+/// by several channels is only converted once. The channels keep the
+/// soundfonts that fail to convert, whose errors are returned by
+/// `take_errors`.
+#[derive(Clone)]
+pub struct SampleRateChange {
+    sample_rate: u32,
+    converted: Arc<Mutex<ConvertedSoundfonts>>,
+}
+
+impl SampleRateChange {
+    /// Creates a change to the given sample rate.
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            converted: Arc::new(Mutex::new(ConvertedSoundfonts {
+                soundfonts: Vec::new(),
+                errors: Vec::new(),
+            })),
+        }
+    }
+
+    /// The new sample rate.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Converts the soundfonts to the new sample rate ahead of time, so the
+    /// channels receiving the change only swap them in. See
+    /// `VoiceChannelStatsReader::soundfonts` for the soundfonts a channel
+    /// plays.
+    pub fn prepare(&self, soundfonts: impl IntoIterator<Item = Arc<dyn SoundfontBase>>) {
+        for soundfont in soundfonts {
+            self.convert(&soundfont);
+        }
+    }
+
+    /// Returns the errors of the soundfonts that failed to convert so far,
+    /// and removes them.
+    pub fn take_errors(&self) -> Vec<SoundfontLoadError> {
+        std::mem::take(&mut self.converted.lock().unwrap().errors)
+    }
+
+    /// Returns the soundfont converted to the new sample rate, converting
+    /// it if it wasn't yet.
+    pub(super) fn convert(&self, soundfont: &Arc<dyn SoundfontBase>) -> Arc<dyn SoundfontBase> {
+        // Held while converting, so the other channels wait for the
+        // soundfonts they share instead of converting them again
+        let mut converted = self.converted.lock().unwrap();
+        let found = converted
+            .soundfonts
+            .iter()
+            .find(|(old, _)| Arc::ptr_eq(old, soundfont));
+        if let Some((_, new)) = found {
+            return new.clone();
+        }
+
+        let new = match soundfont.with_sample_rate(self.sample_rate) {
+            Ok(new) => new.unwrap_or_else(|| soundfont.clone()),
+            Err(error) => {
+                converted.errors.push(error);
+                soundfont.clone()
+            }
+        };
+        converted.soundfonts.push((soundfont.clone(), new.clone()));
+        new
+    }
+}
+
+impl fmt::Debug for SampleRateChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SampleRateChange")
+            .field("sample_rate", &self.sample_rate)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use hound::{SampleFormat, WavSpec, WavWriter};
+
+    use super::*;
+    use crate::{
+        channel::{
+            ChannelAudioEvent, ChannelConfigEvent, ChannelEvent, ChannelInitOptions, VoiceChannel,
+        },
+        soundfont::SampleSoundfont,
+        AudioPipe, AudioStreamParams, ChannelCount,
+    };
+
+    /// Writes an SFZ playing a looped 480 Hz tone at 48 kHz, and returns its
+    /// path.
+    fn write_tone_sfz() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("xsynth_sample_rate_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(dir.join("tone.wav"), spec).unwrap();
+        for i in 0..4800 {
+            writer.write_sample((i % 100 - 50) as i16 * 100).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let path = dir.join("test.sfz");
+        let region = "sample=tone.wav loop_mode=loop_continuous loop_start=0 loop_end=4799";
+        fs::write(&path, format!("<region> {region}\n")).unwrap();
+        path
+    }
+
+    /// Returns the frequency of the tone on the left channel, from the
+    /// number of its periods in 0.2 seconds at the given rate.
+    fn frequency(channel: &mut VoiceChannel, sample_rate: u32) -> f32 {
+        let mut buffer = vec![0.0; sample_rate as usize / 5 * 2];
+        channel.read_samples(&mut buffer);
+        let left: Vec<f32> = buffer.iter().step_by(2).copied().collect();
+        let periods = left
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+            .count();
+        periods as f32 * 5.0
+    }
+
+    fn voice_count(channel: &mut VoiceChannel) -> u64 {
+        let mut buffer = vec![0.0; 256];
+        channel.read_samples(&mut buffer);
+        channel.get_channel_stats().voice_count()
+    }
+
+    fn note_on() -> ChannelEvent {
+        ChannelEvent::Audio(ChannelAudioEvent::NoteOn { key: 60, vel: 100 })
+    }
+
+    /// Converts the soundfonts of the channel ahead of time and sends it the
+    /// change, like the realtime synthesizer does.
+    fn set_sample_rate(channel: &mut VoiceChannel, sample_rate: u32) -> Vec<SoundfontLoadError> {
+        let change = SampleRateChange::new(sample_rate);
+        change.prepare(channel.get_channel_stats().soundfonts());
+        let errors = change.take_errors();
+        channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSampleRate(
+            change,
+        )));
+        errors
+    }
+
+    #[test]
+    fn test_sample_rate_change() {
+        let sfz = write_tone_sfz();
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let soundfont: Arc<dyn SoundfontBase> = Arc::new(
+            SampleSoundfont::new_sfz(sfz.clone(), stream_params, Default::default()).unwrap(),
+        );
+        let options = ChannelInitOptions {
+            fade_out_killing: false,
+            ..Default::default()
+        };
+        let mut channel = VoiceChannel::new(options, stream_params, None);
+        channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
+            Arc::new([soundfont.clone()]),
+        )));
+        let stats = channel.get_channel_stats();
+
+        channel.process_event(note_on());
+        assert!((frequency(&mut channel, 48000) - 480.0).abs() < 10.0);
+
+        // The voices are killed, and the channel plays the soundfont loaded
+        // again by `prepare`, so the notes keep their pitch at the new rate
+        assert!(set_sample_rate(&mut channel, 96000).is_empty());
+        assert_eq!(channel.stream_params().sample_rate, 96000);
+        assert_eq!(voice_count(&mut channel), 0);
+        let converted = stats.soundfonts();
+        assert_eq!(converted.len(), 1);
+        assert!(!Arc::ptr_eq(&converted[0], &soundfont));
+        assert_eq!(converted[0].stream_params().sample_rate, 96000);
+        channel.process_event(note_on());
+        assert!((frequency(&mut channel, 96000) - 480.0).abs() < 10.0);
+
+        // The soundfonts that fail to load again are kept and reported
+        fs::remove_file(&sfz).unwrap();
+        assert_eq!(set_sample_rate(&mut channel, 44100).len(), 1);
+        assert_eq!(channel.stream_params().sample_rate, 44100);
+        assert!(Arc::ptr_eq(&stats.soundfonts()[0], &converted[0]));
+        channel.process_event(note_on());
+        assert_eq!(voice_count(&mut channel), 1);
+    }
+}
@@ -0,0 +1,819 @@
+//! Tests of channel features that depend on SFZ opcodes, played through a
+//! small SFZ written to a temporary directory.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+use crate::{
+    channel::{
+        ChannelAudioEvent, ChannelConfigEvent, ChannelEvent, ChannelInitOptions,
+        ChannelStateSnapshot, ControlEvent, CoverageHole, NoteSpawnHook, ReleaseCrossfade,
+        RetriggerOffset, SoundfontCoverage, SpawnDecision, UnmatchedNote, UnmatchedNotePolicy,
+        VelocityLayers, VoiceChannel,
+    },
+    soundfont::{
+        ArticulationSwitch, EnvelopeCurveType, RegionOverride, SampleSoundfont, SoundfontBase,
+        SoundfontInitOptions,
+    },
+    util::db_to_amp,
+    AudioPipe, AudioStreamParams, ChannelCount,
+};
+
+/// Writes an SFZ with the given headers and opcodes, whose regions can play
+/// the looped `tone.wav` sample, and returns its path.
+fn write_sfz(name: &str, sfz: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("xsynth_{name}_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    write_wav(&dir.join("tone.wav"), 1, tone);
+
+    let path = dir.join("test.sfz");
+    fs::write(&path, sfz).unwrap();
+    path
+}
+
+fn tone(i: i32) -> i16 {
+    (i % 100 - 50) as i16 * 100
+}
+
+/// Writes a 4800 frames long WAV file, with the given value of each
+/// interleaved sample.
+fn write_wav(path: &Path, channels: u16, sample: impl Fn(i32) -> i16) {
+    let spec = WavSpec {
+        channels,
+        sample_rate: 48000,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::create(path, spec).unwrap();
+    for i in 0..4800 * channels as i32 {
+        writer.write_sample(sample(i)).unwrap();
+    }
+    writer.finalize().unwrap();
+}
+
+/// The opcodes of a region that plays `tone.wav` until it is released.
+const TONE: &str = "sample=tone.wav loop_mode=loop_continuous loop_start=0 loop_end=4799";
+
+fn load_sfz(sfz: PathBuf) -> Arc<dyn SoundfontBase> {
+    let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+    Arc::new(SampleSoundfont::new_sfz(sfz, stream_params, Default::default()).unwrap())
+}
+
+fn new_channel(soundfont: Arc<dyn SoundfontBase>) -> VoiceChannel {
+    let mut channel = VoiceChannel::new(
+        ChannelInitOptions {
+            fade_out_killing: false,
+            ..Default::default()
+        },
+        *soundfont.stream_params(),
+        None,
+    );
+    channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
+        Arc::new([soundfont]),
+    )));
+    channel
+}
+
+fn note_on(key: u8) -> ChannelEvent {
+    ChannelEvent::Audio(ChannelAudioEvent::NoteOn { key, vel: 100 })
+}
+
+fn voice_count(channel: &mut VoiceChannel) -> u64 {
+    let mut buffer = vec![0.0; 256];
+    channel.read_samples(&mut buffer);
+    channel.get_channel_stats().voice_count()
+}
+
+/// Plays the keys at once on a channel with the SFZ, and returns the
+/// number of voices playing afterwards.
+fn voice_count_after(sfz: PathBuf, keys: &[u8]) -> u64 {
+    let mut channel = new_channel(load_sfz(sfz));
+    for &key in keys {
+        channel.process_event(note_on(key));
+    }
+    voice_count(&mut channel)
+}
+
+#[test]
+fn test_group_polyphony() {
+    let sfz = write_sfz(
+        "group_polyphony",
+        &format!("<group> group=1 polyphony=2\n<region> {TONE}\n"),
+    );
+    assert_eq!(voice_count_after(sfz.clone(), &[60, 62, 64, 65]), 2);
+    assert_eq!(voice_count_after(sfz, &[60, 60, 60]), 2);
+}
+
+#[test]
+fn test_note_polyphony() {
+    let sfz = write_sfz(
+        "note_polyphony",
+        &format!("<group> group=1 note_polyphony=2\n<region> {TONE}\n"),
+    );
+    assert_eq!(voice_count_after(sfz.clone(), &[60, 60, 60]), 2);
+    assert_eq!(voice_count_after(sfz, &[60, 60, 60, 62, 64]), 4);
+}
+
+#[test]
+fn test_articulation_switches() {
+    let sfz = write_sfz(
+        "articulation_switches",
+        &format!(
+            "<group> group_label=Legato locc32=10 hicc32=19\n<region> {TONE}\n\
+             <group> group_label=Staccato hicc32=9\n<region> region_label=Short {TONE}\n"
+        ),
+    );
+    let soundfont = load_sfz(sfz);
+
+    let switch = |range, name: &str| ArticulationSwitch {
+        controller: 32,
+        range,
+        name: name.to_owned(),
+    };
+    assert_eq!(
+        soundfont.articulation_switches(0, 0),
+        [switch(0..=9, "Short"), switch(10..=19, "Legato")]
+    );
+
+    // The note is played with the articulation selected when it started,
+    // even if the switch changes before the channel is rendered
+    let mut channel = new_channel(soundfont);
+    channel.process_event(note_on(60));
+    channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::Control(
+        ControlEvent::Raw(32, 20),
+    )));
+    assert_eq!(voice_count(&mut channel), 1);
+
+    channel.process_event(note_on(62));
+    assert_eq!(voice_count(&mut channel), 1);
+}
+
+#[test]
+fn test_release_uses_note_on_program() {
+    // The release region plays the unlooped sample for 0.1s, outlasting the
+    // release of the attack region
+    let sfz = write_sfz(
+        "release_program",
+        &format!("<region> {TONE}\n<region> trigger=release sample=tone.wav\n"),
+    );
+    let mut channel = new_channel(load_sfz(sfz));
+
+    // The program change mutes the channel, as the SFZ only has program 0,
+    // but the note keeps playing and releases with the old program
+    channel.process_event(note_on(60));
+    channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::ProgramChange(5)));
+    channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::NoteOff { key: 60 }));
+    for _ in 0..20 {
+        voice_count(&mut channel);
+    }
+    assert_eq!(voice_count(&mut channel), 1);
+
+    channel.process_event(note_on(62));
+    assert_eq!(voice_count(&mut channel), 1);
+}
+
+#[test]
+fn test_release_floor() {
+    let sfz = write_sfz(
+        "release_floor",
+        &format!("<region> {TONE} ampeg_release=30\n"),
+    );
+
+    // Returns the voice count after releasing a note for the given time
+    let count_after_release = |release_floor_db, seconds| {
+        let mut options = SoundfontInitOptions::default();
+        options.vol_envelope_options.release_floor_db = release_floor_db;
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let soundfont = SampleSoundfont::new_sfz(sfz.clone(), stream_params, options).unwrap();
+        let mut channel = new_channel(Arc::new(soundfont));
+
+        channel.process_event(note_on(60));
+        voice_count(&mut channel);
+        channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::NoteOff { key: 60 }));
+
+        let mut buffer = vec![0.0; 48000 * 2];
+        for _ in 0..seconds {
+            channel.read_samples(&mut buffer);
+        }
+        channel.get_channel_stats().voice_count()
+    };
+
+    // The default release curve falls below -100 dB after about 23 seconds
+    assert_eq!(count_after_release(Some(-100.0), 24), 0);
+    assert_eq!(count_after_release(None, 24), 1);
+
+    // A higher floor ends the voice even sooner
+    assert_eq!(count_after_release(Some(-60.0), 18), 0);
+}
+
+#[test]
+fn test_note_spawn_hook() {
+    // Only notes up to velocity 63 have a region
+    let sfz = write_sfz("spawn_hook", &format!("<region> hivel=63 {TONE}\n"));
+    let mut channel = new_channel(load_sfz(sfz));
+    channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetVoiceSnapshots(
+        true,
+    )));
+
+    channel.process_event(note_on(60));
+    assert_eq!(voice_count(&mut channel), 0);
+
+    // Halving the velocities selects the soft layer
+    let hook = NoteSpawnHook::new(|note| {
+        note.velocity /= 2;
+        if note.key == 62 {
+            SpawnDecision::Skip
+        } else {
+            SpawnDecision::Play
+        }
+    });
+    channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetNoteSpawnHook(
+        Some(hook),
+    )));
+    channel.process_event(note_on(60));
+    channel.process_event(note_on(62));
+    assert_eq!(voice_count(&mut channel), 1);
+    let snapshot = channel.get_channel_stats().voice_snapshot();
+    assert_eq!((snapshot[0].key, snapshot[0].velocity), (60, 50));
+
+    channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetNoteSpawnHook(
+        None,
+    )));
+    channel.process_event(note_on(61));
+    assert_eq!(voice_count(&mut channel), 1);
+}
+
+#[test]
+fn test_velocity_layers() {
+    let sfz = write_sfz("velocity_layers", &format!("<region> {TONE}\n"));
+    let mut channel = VoiceChannel::new(
+        ChannelInitOptions {
+            velocity_layers: Some(VelocityLayers {
+                single_below: 32,
+                double_below: 64,
+            }),
+            ..Default::default()
+        },
+        AudioStreamParams::new(48000, ChannelCount::Stereo),
+        None,
+    );
+    channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
+        Arc::new([load_sfz(sfz)]),
+    )));
+    channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetLayerCount(
+        Some(4),
+    )));
+
+    let note_on = |key, vel| ChannelEvent::Audio(ChannelAudioEvent::NoteOn { key, vel });
+    for _ in 0..3 {
+        channel.process_event(note_on(60, 100));
+        channel.process_event(note_on(62, 40));
+        channel.process_event(note_on(64, 10));
+    }
+    let mut buffer = vec![0.0; 256];
+    channel.read_samples(&mut buffer);
+    let stats = channel.get_channel_stats();
+    assert_eq!(stats.voice_count(), 3 + 2 + 1);
+    assert_eq!(stats.trimmed_layers(), 1 + 2);
+}
+
+#[test]
+fn test_reset_semantics() {
+    let sfz = write_sfz("reset", &format!("<region> {TONE} ampeg_release=0.001\n"));
+    let mut channel = new_channel(load_sfz(sfz));
+    let audio = ChannelEvent::Audio;
+    let cc = |controller, value| {
+        audio(ChannelAudioEvent::Control(ControlEvent::Raw(
+            controller, value,
+        )))
+    };
+
+    // Plays a note on and off, and returns the voice count once a release
+    // would have ended
+    let play_note = |channel: &mut VoiceChannel, key| {
+        channel.process_event(note_on(key));
+        channel.process_event(audio(ChannelAudioEvent::NoteOff { key }));
+        for _ in 0..4 {
+            voice_count(channel);
+        }
+        voice_count(channel)
+    };
+
+    channel.process_event(cc(7, 50));
+    channel.process_event(cc(0x40, 127));
+    channel.process_event(audio(ChannelAudioEvent::Control(
+        ControlEvent::PitchBendValue(0.5),
+    )));
+    assert_eq!(play_note(&mut channel, 60), 1);
+
+    // Killing the notes keeps the controllers and the pedal, so the damper
+    // still holds the following notes
+    channel.process_event(audio(ChannelAudioEvent::AllNotesKilled));
+    assert_eq!(voice_count(&mut channel), 0);
+    let state = channel.state();
+    assert_eq!(state.controllers[7], 50);
+    assert!(state.damper());
+    assert_eq!(state.pitch_bend_value, 0.5);
+    assert_eq!(play_note(&mut channel, 62), 1);
+
+    // Resetting the channel also resets the controllers and the pedal
+    channel.process_event(audio(ChannelAudioEvent::ResetChannel));
+    assert_eq!(voice_count(&mut channel), 0);
+    let state = channel.state();
+    assert_eq!(
+        state.controllers,
+        ChannelStateSnapshot::default().controllers
+    );
+    assert!(!state.damper());
+    assert_eq!(state.pitch_bend_value, 0.0);
+    assert_eq!(play_note(&mut channel, 64), 0);
+
+    // Only a system reset restores the program
+    channel.process_event(audio(ChannelAudioEvent::ProgramChange(5)));
+    channel.process_event(audio(ChannelAudioEvent::AllNotesKilled));
+    assert_eq!(channel.state().program, 5);
+    channel.process_event(audio(ChannelAudioEvent::ResetChannel));
+    assert_eq!(channel.state().program, 5);
+    channel.process_event(audio(ChannelAudioEvent::SystemReset));
+    assert_eq!(channel.state().program, 0);
+}
+
+#[test]
+fn test_14bit_expression() {
+    let sfz = write_sfz("expression_14bit", &format!("<region> {TONE}\n"));
+    let mut channel = new_channel(load_sfz(sfz));
+    let cc = |controller, value| {
+        ChannelEvent::Audio(ChannelAudioEvent::Control(ControlEvent::Raw(
+            controller, value,
+        )))
+    };
+
+    // Returns the RMS level of whole periods of the tone, once the
+    // expression has settled. The stereo expression ramp takes a step per
+    // rendered block, so it settles over many small blocks.
+    let level = |channel: &mut VoiceChannel| {
+        let mut block = vec![0.0; 16 * 2];
+        for _ in 0..600 {
+            channel.read_samples(&mut block);
+        }
+        let mut buffer = vec![0.0; 4800 * 2];
+        channel.read_samples(&mut buffer);
+        (buffer.iter().map(|s| s * s).sum::<f32>() / buffer.len() as f32).sqrt()
+    };
+
+    channel.process_event(note_on(60));
+    channel.process_event(cc(11, 64));
+    let coarse = level(&mut channel);
+
+    // Half a step of the MSB is only audible through the LSB
+    channel.process_event(cc(43, 64));
+    let fine = level(&mut channel);
+    assert!(fine > coarse * 1.002, "{fine} vs {coarse}");
+
+    // A new MSB resets the LSB
+    channel.process_event(cc(11, 64));
+    assert!((level(&mut channel) - coarse).abs() < coarse * 1e-4);
+}
+
+#[test]
+fn test_note_pitch_bend() {
+    let sfz = write_sfz("note_pitch_bend", &format!("<region> {TONE}\n"));
+    let mut channel = new_channel(load_sfz(sfz));
+    let bend = |key, value| ChannelEvent::Audio(ChannelAudioEvent::NotePitchBend { key, value });
+
+    // Returns the pitch of the left channel relative to the unbent tone
+    // at key 60, from the number of periods of the tone in 0.2 seconds
+    let pitch = |channel: &mut VoiceChannel| {
+        let mut buffer = vec![0.0; 9600 * 2];
+        channel.read_samples(&mut buffer);
+        let left: Vec<f32> = buffer.iter().step_by(2).copied().collect();
+        let periods = left
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+            .count();
+        periods as f32 / 96.0
+    };
+    let semitones = |semitones: f32| 2.0f32.powf(semitones / 12.0);
+
+    channel.process_event(note_on(60));
+    assert!((pitch(&mut channel) - 1.0).abs() < 0.02);
+
+    // A full bend raises the note by the pitch bend sensitivity, and bends
+    // of other keys don't affect it
+    channel.process_event(bend(62, 1.0));
+    assert!((pitch(&mut channel) - 1.0).abs() < 0.02);
+    channel.process_event(bend(60, 1.0));
+    assert!((pitch(&mut channel) - semitones(2.0)).abs() < 0.02);
+
+    // Notes start with the bend of their key
+    channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::AllNotesKilled));
+    channel.process_event(note_on(62));
+    assert!((pitch(&mut channel) - semitones(4.0)).abs() < 0.02);
+
+    // Resetting the controllers removes the bends
+    channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::ResetControl));
+    assert!((pitch(&mut channel) - semitones(2.0)).abs() < 0.02);
+}
+
+#[test]
+fn test_pitch_bend_range() {
+    let sfz = write_sfz("pitch_bend_range", &format!("<region> {TONE}\n"));
+    let mut channel = VoiceChannel::new(
+        ChannelInitOptions {
+            pitch_bend_down_nrpn: Some((0x7F, 0x00)),
+            ..Default::default()
+        },
+        AudioStreamParams::new(48000, ChannelCount::Stereo),
+        None,
+    );
+    channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
+        Arc::new([load_sfz(sfz)]),
+    )));
+    let control = |control| ChannelEvent::Audio(ChannelAudioEvent::Control(control));
+    let cc = |controller, value| control(ControlEvent::Raw(controller, value));
+    let cents =
+        |channel: &VoiceChannel| 1200.0 * channel.voice_control_data.voice_pitch_multiplier.log2();
+
+    // RPN 0,0 with 2 semitones and 50 cents
+    for (controller, value) in [(0x65, 0), (0x64, 0), (0x06, 2), (0x26, 50)] {
+        channel.process_event(cc(controller, value));
+    }
+    channel.process_event(control(ControlEvent::PitchBendValue(1.0)));
+    assert_eq!(channel.state().pitch_bend, 2.5);
+    assert_eq!(
+        channel.voice_control_data.voice_pitch_multiplier,
+        2.0f32.powf(2.5 / 12.0)
+    );
+    assert!((cents(&channel) - 250.0).abs() < 1e-3);
+
+    // The down range is the same until the NRPN sets it, and data entries
+    // to other NRPNs are ignored
+    channel.process_event(control(ControlEvent::PitchBendValue(-1.0)));
+    assert!((cents(&channel) + 250.0).abs() < 1e-3);
+    for (controller, value) in [(0x63, 0x01), (0x62, 0x00), (0x06, 12)] {
+        channel.process_event(cc(controller, value));
+    }
+    assert!((cents(&channel) + 250.0).abs() < 1e-3);
+    for (controller, value) in [(0x63, 0x7F), (0x62, 0x00), (0x06, 12), (0x26, 0)] {
+        channel.process_event(cc(controller, value));
+    }
+    assert!((cents(&channel) + 1200.0).abs() < 1e-3);
+    assert_eq!(channel.state().pitch_bend_down_sensitivity, Some(12.0));
+
+    // Bends up still use the RPN, and selecting it again sends the data
+    // entries to it
+    channel.process_event(control(ControlEvent::PitchBendValue(0.5)));
+    assert!((cents(&channel) - 125.0).abs() < 1e-3);
+    for (controller, value) in [(0x65, 0), (0x64, 0), (0x06, 1), (0x26, 0)] {
+        channel.process_event(cc(controller, value));
+    }
+    assert!((cents(&channel) - 50.0).abs() < 1e-3);
+
+    // The per-note pitch bends use the same ranges
+    channel.process_event(note_on(60));
+    channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::NotePitchBend {
+        key: 60,
+        value: -0.5,
+    }));
+    assert_eq!(channel.note_pitch_multiplier(60), 2.0f32.powf(-6.0 / 12.0));
+
+    // Resetting the controllers makes the range symmetric again
+    channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::ResetControl));
+    channel.process_event(control(ControlEvent::PitchBendValue(-1.0)));
+    assert_eq!(channel.state().pitch_bend, -2.0);
+    assert_eq!(channel.state().pitch_bend_down_sensitivity, None);
+}
+
+#[test]
+fn test_retrigger_offset() {
+    let sfz = write_sfz("retrigger_offset", &format!("<region> {TONE}\n"));
+    let soundfont = load_sfz(sfz);
+
+    // Plays key 60 twice, 480 frames apart, and returns the sample position
+    // of the second note shortly after it starts, and the audio rendered
+    // after the retrigger
+    let retrigger = |retrigger_offset| {
+        let mut channel = VoiceChannel::new(
+            ChannelInitOptions {
+                retrigger_offset,
+                ..Default::default()
+            },
+            AudioStreamParams::new(48000, ChannelCount::Stereo),
+            None,
+        );
+        channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
+            Arc::new([soundfont.clone()]),
+        )));
+        channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetVoiceSnapshots(
+            true,
+        )));
+
+        let mut buffer = vec![0.0; 480 * 2];
+        channel.process_event(note_on(60));
+        channel.read_samples(&mut buffer);
+        channel.process_event(note_on(60));
+        channel.read_samples(&mut buffer[..2 * 2]);
+
+        let snapshot = channel.get_channel_stats().voice_snapshot();
+        let newest = snapshot.iter().max_by_key(|v| v.group_id).unwrap();
+        let position = newest.sample_position.unwrap();
+
+        channel.read_samples(&mut buffer);
+        (position, buffer)
+    };
+
+    let (position, independent) = retrigger(None);
+    assert!(position < 10.0);
+
+    let (continued, repeat) = retrigger(Some(RetriggerOffset {
+        max_position: 4800,
+        fraction: 0.5,
+    }));
+    assert!((continued - 242.0).abs() < 10.0);
+
+    // The continued note sounds different from two independent starts
+    let difference: f32 = repeat
+        .iter()
+        .zip(&independent)
+        .map(|(a, b)| (a - b).abs())
+        .sum();
+    assert!(difference > independent.iter().map(|s| s.abs()).sum::<f32>() * 0.1);
+
+    // The previous note is too far along to be continued
+    let (restarted, restarted_audio) = retrigger(Some(RetriggerOffset {
+        max_position: 240,
+        fraction: 0.5,
+    }));
+    assert!(restarted < 10.0);
+    assert_eq!(restarted_audio, independent);
+}
+
+#[test]
+fn test_phase_and_channel_fixes() {
+    let sfz = write_sfz("phase_fixes", &format!("<region> {TONE}\n"));
+    let dir = sfz.parent().unwrap().to_owned();
+    write_wav(&dir.join("inverted.wav"), 1, |i| -tone(i));
+    write_wav(&dir.join("left.wav"), 2, |i| {
+        if i % 2 == 0 {
+            tone(i / 2)
+        } else {
+            0
+        }
+    });
+
+    // Plays key 60 with the regions, and returns the peaks of the left and
+    // right output channels
+    let peaks = |regions: &str, options| {
+        let path = dir.join("fixes.sfz");
+        fs::write(&path, regions).unwrap();
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let soundfont = SampleSoundfont::new_sfz(path, stream_params, options).unwrap();
+        let mut channel = new_channel(Arc::new(soundfont));
+
+        let mut buffer = vec![0.0; 4800 * 2];
+        channel.process_event(note_on(60));
+        channel.read_samples(&mut buffer);
+        let peak = |channel| {
+            buffer
+                .iter()
+                .skip(channel)
+                .step_by(2)
+                .fold(0.0f32, |peak, s| peak.max(s.abs()))
+        };
+        (peak(0), peak(1))
+    };
+    let inverted = TONE.replace("tone.wav", "inverted.wav");
+    let left = TONE.replace("tone.wav", "left.wav");
+
+    // The layered pair cancels out, unless one of them is inverted by an
+    // opcode or by the soundfont options
+    let layered = format!("<region> {TONE}\n<region> {inverted}\n");
+    let (l, r) = peaks(&layered, SoundfontInitOptions::default());
+    assert!(l < 1e-6 && r < 1e-6);
+
+    let fixed = format!("<region> {TONE}\n<region> {inverted} xsynth_invert_phase=on\n");
+    let (l, r) = peaks(&fixed, SoundfontInitOptions::default());
+    assert!(l > 0.01 && r > 0.01);
+
+    let inverted_font = SoundfontInitOptions {
+        invert_phase: true,
+        ..Default::default()
+    };
+    let (l, _) = peaks(&layered, inverted_font);
+    assert!(l < 1e-6);
+    let overridden = format!("<region> {TONE} xsynth_invert_phase=off\n<region> {inverted}\n");
+    let (l, _) = peaks(&overridden, inverted_font);
+    assert!(l > 0.01);
+
+    // Swapping the channels of a sample that only has a left channel
+    let (l, r) = peaks(&format!("<region> {left}\n"), Default::default());
+    assert!(l > 0.01 && r < 1e-6);
+    let (l, r) = peaks(
+        &format!("<region> {left} xsynth_swap_channels=on\n"),
+        Default::default(),
+    );
+    assert!(l < 1e-6 && r > 0.01);
+
+    let swapped_font = SoundfontInitOptions {
+        swap_channels: true,
+        ..Default::default()
+    };
+    let (l, r) = peaks(&format!("<region> {left}\n"), swapped_font);
+    assert!(l < 1e-6 && r > 0.01);
+}
+
+#[test]
+fn test_coverage_report() {
+    let low = write_sfz(
+        "coverage_low",
+        &format!("<region> lokey=60 hikey=61 lovel=1 hivel=63 {TONE}\n"),
+    );
+    let key = write_sfz(
+        "coverage_key",
+        &format!("<region> lokey=60 hikey=60 {TONE}\n"),
+    );
+    let hidden = write_sfz(
+        "coverage_hidden",
+        &format!("<region> lokey=61 hikey=61 lovel=1 hivel=10 {TONE}\n"),
+    );
+
+    // The first soundfont in the list that has regions for a cell is played
+    let low = load_sfz(low);
+    let mut channel = new_channel(low.clone());
+    channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
+        Arc::new([low, load_sfz(key), load_sfz(hidden)]),
+    )));
+    let report = channel.coverage_report(60..=61);
+
+    assert_eq!(
+        report.holes,
+        [CoverageHole {
+            key: 61,
+            velocities: 64..=127,
+        }]
+    );
+    assert_eq!(report.hole_cells(), 64);
+    let coverage = |played_cells, shadowed_cells| SoundfontCoverage {
+        played_cells,
+        shadowed_cells,
+    };
+    assert_eq!(
+        report.soundfonts,
+        [coverage(126, 0), coverage(64, 63), coverage(0, 10)]
+    );
+    assert!(report.soundfonts[2].is_shadowed());
+    assert!(channel.coverage_report(60..=60).is_complete());
+}
+
+#[test]
+fn test_release_crossfade() {
+    let sfz = write_sfz("release_crossfade", "");
+    let dir = sfz.parent().unwrap().to_owned();
+    let sine = |freq: f32| {
+        move |i: i32| ((i as f32 * freq / 48000.0 * std::f32::consts::TAU).sin() * 10000.0) as i16
+    };
+    write_wav(&dir.join("sustain.wav"), 1, sine(480.0));
+    write_wav(&dir.join("release.wav"), 1, sine(720.0));
+
+    // A piano-like note that decays to half its level, and a release sample
+    // as loud as the sustain sample
+    let loop_opcodes = "loop_mode=loop_continuous loop_start=0 loop_end=4799";
+    fs::write(
+        &sfz,
+        format!(
+            "<region> sample=sustain.wav {loop_opcodes} \
+             ampeg_decay=0.01 ampeg_sustain=50 ampeg_release=0.1\n\
+             <region> trigger=release sample=release.wav {loop_opcodes}\n"
+        ),
+    )
+    .unwrap();
+    let mut options = SoundfontInitOptions::default();
+    // Fades out in a straight line
+    options.vol_envelope_options.release_curve = EnvelopeCurveType::Exponential;
+    let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+    let soundfont: Arc<dyn SoundfontBase> =
+        Arc::new(SampleSoundfont::new_sfz(sfz, stream_params, options).unwrap());
+
+    // Releases a note after 0.2 seconds, and returns the largest change of
+    // the RMS of 20 ms windows across the release, in dB
+    let max_rms_change = |release_crossfade| {
+        let mut channel = VoiceChannel::new(
+            ChannelInitOptions {
+                release_crossfade,
+                ..Default::default()
+            },
+            stream_params,
+            None,
+        );
+        channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
+            Arc::new([soundfont.clone()]),
+        )));
+
+        let window = 960 * 2;
+        let mut held = vec![0.0; 9600 * 2];
+        channel.process_event(note_on(60));
+        channel.read_samples(&mut held);
+        channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::NoteOff { key: 60 }));
+        let mut released = vec![0.0; 14400 * 2];
+        channel.read_samples(&mut released);
+
+        let rms = |samples: &[f32]| {
+            (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+        };
+        let held_rms = rms(&held[held.len() - window..]);
+        released
+            .chunks(window)
+            .map(|samples| (20.0 * (rms(samples) / held_rms).log10()).abs())
+            .fold(0.0f32, f32::max)
+    };
+
+    assert!(max_rms_change(Some(ReleaseCrossfade { time_ms: 100.0 })) < 1.0);
+    assert!(max_rms_change(None) > 3.0);
+}
+
+#[test]
+fn test_unmatched_notes() {
+    let sfz = write_sfz("unmatched", &format!("<region> lokey=60 hikey=60 {TONE}\n"));
+    let fallback = write_sfz("unmatched_fallback", &format!("<region> {TONE}\n"));
+    let (soundfont, fallback) = (load_sfz(sfz), load_sfz(fallback));
+
+    // Plays keys 60 and 61 with the policy, and returns the voice count,
+    // the number of misses and the reported notes
+    let play = |unmatched_notes| {
+        let mut channel = VoiceChannel::new(
+            ChannelInitOptions {
+                unmatched_notes,
+                ..Default::default()
+            },
+            *soundfont.stream_params(),
+            None,
+        );
+        channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
+            Arc::new([soundfont.clone()]),
+        )));
+        channel.process_event(ChannelEvent::Config(
+            ChannelConfigEvent::SetFallbackSoundfont(Some(fallback.clone())),
+        ));
+
+        channel.process_event(note_on(60));
+        channel.process_event(note_on(61));
+        let voices = voice_count(&mut channel);
+        let stats = channel.get_channel_stats();
+        let reported = stats.take_unmatched_notes();
+        assert!(stats.take_unmatched_notes().is_empty());
+        (voices, stats.unmatched_notes(), reported)
+    };
+
+    assert_eq!(play(UnmatchedNotePolicy::Silent), (1, 1, vec![]));
+
+    let missed = UnmatchedNote {
+        bank: 0,
+        preset: 0,
+        key: 61,
+        vel: 100,
+    };
+    assert_eq!(play(UnmatchedNotePolicy::Diagnostics), (1, 1, vec![missed]));
+
+    // The fallback soundfont plays the note, which still counts as a miss
+    assert_eq!(play(UnmatchedNotePolicy::Fallback), (2, 1, vec![]));
+}
+
+#[test]
+fn test_region_override() {
+    let sfz = write_sfz("region_override", &format!("<region> {TONE}\n"));
+    let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+    let soundfont =
+        Arc::new(SampleSoundfont::new_sfz(sfz, stream_params, Default::default()).unwrap());
+    let mut channel = new_channel(soundfont.clone());
+
+    // Returns the peak of a note played for 0.1 seconds
+    let peak = |channel: &mut VoiceChannel| {
+        channel.process_event(note_on(60));
+        let mut buffer = vec![0.0; 9600];
+        channel.read_samples(&mut buffer);
+        let peak = buffer.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+
+        channel.process_event(ChannelEvent::Audio(ChannelAudioEvent::AllNotesKilled));
+        assert_eq!(voice_count(channel), 0);
+        peak
+    };
+    let loaded = peak(&mut channel);
+    assert!(loaded > 0.01);
+
+    // The channel resolved its spawners before the override, and picks it
+    // up for the next note
+    let overrides = RegionOverride {
+        gain_db: Some(-6.0),
+        ..Default::default()
+    };
+    soundfont.set_region_override(0, overrides).unwrap();
+    assert!((peak(&mut channel) / loaded - db_to_amp(-6.0)).abs() < 0.01);
+
+    soundfont.clear_region_overrides();
+    assert!((peak(&mut channel) / loaded - 1.0).abs() < 0.01);
+}
@@ -0,0 +1,301 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
+    sync::atomic::Ordering,
+};
+
+use super::{event::KeyNoteEvent, params::VoiceChannelStats, Key};
+
+/// Limits the number of note ons that spawn voices in each rendered buffer,
+/// to avoid a render spike when a huge chord arrives at once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct SpawnBudget {
+    /// The maximum number of note ons that spawn voices per rendered buffer.
+    pub notes_per_buffer: usize,
+
+    /// What happens to the note ons beyond the budget. See the
+    /// `SpawnOverflow` documentation for the available options.
+    pub overflow: SpawnOverflow,
+}
+
+/// Controls what happens to the note ons beyond a `SpawnBudget`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum SpawnOverflow {
+    /// The note ons are deferred to the following buffers, keeping their
+    /// order. When `max_deferred` note ons are already waiting, new ones are
+    /// dropped. A note off that arrives before its note on was spawned
+    /// cancels the note on.
+    Defer { max_deferred: usize },
+
+    /// The quietest note ons of each buffer are dropped, so only the loudest
+    /// ones spawn voices.
+    DropQuietest,
+}
+
+struct DeferredNote {
+    key: u8,
+    event: KeyNoteEvent,
+}
+
+/// Applies the spawn budget of a channel to its note ons before they are
+/// sent to the keys.
+pub(super) struct SpawnThrottle {
+    budget: Option<SpawnBudget>,
+
+    /// The number of note ons sent to the keys for the next buffer
+    spawned: usize,
+
+    /// Deferred note ons, oldest first
+    deferred: VecDeque<DeferredNote>,
+
+    /// The velocities and keys of the note ons sent to the keys for the next
+    /// buffer, quietest first. Only used with `SpawnOverflow::DropQuietest`.
+    admitted: BinaryHeap<Reverse<(u8, u8)>>,
+
+    /// The number of note offs to ignore on each key, because their note
+    /// ons were dropped
+    skipped_note_offs: [u32; 128],
+}
+
+impl SpawnThrottle {
+    pub fn new(budget: Option<SpawnBudget>) -> Self {
+        Self {
+            budget,
+            spawned: 0,
+            deferred: VecDeque::new(),
+            admitted: BinaryHeap::new(),
+            skipped_note_offs: [0; 128],
+        }
+    }
+
+    /// Sends a note on to its key, or defers or drops it if the budget of
+    /// the buffer is used up.
+    pub fn note_on(
+        &mut self,
+        key: u8,
+        vel: u8,
+        event: KeyNoteEvent,
+        keys: &mut [Key],
+        stats: &VoiceChannelStats,
+    ) {
+        let Some(budget) = self.budget else {
+            keys[key as usize].event_cache.push(event);
+            return;
+        };
+
+        if self.deferred.is_empty() && self.spawned < budget.notes_per_buffer {
+            self.admit(key, vel, event, keys);
+            return;
+        }
+
+        match budget.overflow {
+            SpawnOverflow::Defer { max_deferred } => {
+                if self.deferred.len() < max_deferred {
+                    self.deferred.push_back(DeferredNote { key, event });
+                    stats.deferred_spawns.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    self.drop_note_on(key, stats);
+                }
+            }
+            SpawnOverflow::DropQuietest => match self.admitted.peek() {
+                Some(&Reverse((quietest_vel, quietest_key))) if quietest_vel < vel => {
+                    self.admitted.pop();
+                    self.spawned -= 1;
+                    self.cancel_admitted(quietest_key, quietest_vel, keys);
+                    stats.dropped_spawns.fetch_add(1, Ordering::Relaxed);
+                    self.admit(key, vel, event, keys);
+                }
+                _ => self.drop_note_on(key, stats),
+            },
+        }
+    }
+
+    /// Returns true if a note off should be sent to its key, or false if it
+    /// cancelled a deferred note on or belongs to a dropped one.
+    pub fn note_off(&mut self, key: u8) -> bool {
+        if let Some(index) = self.deferred.iter().position(|n| n.key == key) {
+            self.deferred.remove(index);
+            return false;
+        }
+
+        let skipped = &mut self.skipped_note_offs[key as usize];
+        if *skipped > 0 {
+            *skipped -= 1;
+            return false;
+        }
+
+        true
+    }
+
+    /// Forgets the deferred and dropped note ons of a key, e.g. when it is
+    /// killed.
+    pub fn note_kill(&mut self, key: u8) {
+        self.deferred.retain(|n| n.key != key);
+        self.skipped_note_offs[key as usize] = 0;
+    }
+
+    /// Sends the deferred note ons that fit in the budget to their keys.
+    /// Called before the keys process their events for a buffer.
+    pub fn start_buffer(&mut self, keys: &mut [Key]) {
+        let Some(budget) = self.budget else {
+            return;
+        };
+
+        while self.spawned < budget.notes_per_buffer {
+            let Some(note) = self.deferred.pop_front() else {
+                break;
+            };
+            keys[note.key as usize].event_cache.push(note.event);
+            self.spawned += 1;
+        }
+    }
+
+    /// Resets the budget after the keys processed their events for a buffer.
+    pub fn end_buffer(&mut self) {
+        self.spawned = 0;
+        self.admitted.clear();
+    }
+
+    /// Forgets the deferred and dropped note ons, e.g. when all notes are
+    /// released.
+    pub fn clear(&mut self) {
+        self.deferred.clear();
+        self.admitted.clear();
+        self.skipped_note_offs = [0; 128];
+    }
+
+    fn admit(&mut self, key: u8, vel: u8, event: KeyNoteEvent, keys: &mut [Key]) {
+        keys[key as usize].event_cache.push(event);
+        self.spawned += 1;
+        if self.budget.map(|b| b.overflow) == Some(SpawnOverflow::DropQuietest) {
+            self.admitted.push(Reverse((vel, key)));
+        }
+    }
+
+    fn drop_note_on(&mut self, key: u8, stats: &VoiceChannelStats) {
+        self.skipped_note_offs[key as usize] += 1;
+        stats.dropped_spawns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Removes a note on that was already sent to its key, along with its
+    /// note off if that was sent too.
+    fn cancel_admitted(&mut self, key: u8, vel: u8, keys: &mut [Key]) {
+        let events = &mut keys[key as usize].event_cache;
+        let Some(on) = events.iter().position(|e| match *e {
+            KeyNoteEvent::On(v) | KeyNoteEvent::LegatoOn(v) => v == vel,
+            KeyNoteEvent::DetunedOn { vel: v, .. } => v == vel,
+            _ => false,
+        }) else {
+            return;
+        };
+
+        let off = events[on..]
+            .iter()
+            .position(|e| *e == KeyNoteEvent::Off)
+            .map(|i| i + on);
+        match off {
+            Some(off) => {
+                events.remove(off);
+            }
+            None => self.skipped_note_offs[key as usize] += 1,
+        }
+        events.remove(on);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::channel::ChannelInitOptions;
+
+    fn new_keys() -> Vec<Key> {
+        (0..128)
+            .map(|i| Key::new(i, Arc::default(), ChannelInitOptions::default()))
+            .collect()
+    }
+
+    fn note_ons(keys: &[Key]) -> Vec<(u8, u8)> {
+        let mut notes = Vec::new();
+        for (key, data) in keys.iter().enumerate() {
+            for event in data.event_cache.iter() {
+                if let KeyNoteEvent::On(vel) = *event {
+                    notes.push((key as u8, vel));
+                }
+            }
+        }
+        notes
+    }
+
+    fn take_note_ons(keys: &mut [Key]) -> Vec<(u8, u8)> {
+        let notes = note_ons(keys);
+        for key in keys.iter_mut() {
+            key.event_cache.clear();
+        }
+        notes
+    }
+
+    #[test]
+    fn test_defer() {
+        let stats = VoiceChannelStats::new();
+        let mut keys = new_keys();
+        let mut throttle = SpawnThrottle::new(Some(SpawnBudget {
+            notes_per_buffer: 2,
+            overflow: SpawnOverflow::Defer { max_deferred: 2 },
+        }));
+
+        for key in 0..5 {
+            throttle.note_on(key, 100, KeyNoteEvent::On(100), &mut keys, &stats);
+        }
+        assert_eq!(stats.deferred_spawns.load(Ordering::Relaxed), 2);
+        assert_eq!(stats.dropped_spawns.load(Ordering::Relaxed), 1);
+
+        // The note off of key 3 cancels its deferred note on, and the one
+        // of the dropped key 4 is ignored
+        assert!(!throttle.note_off(3));
+        assert!(!throttle.note_off(4));
+        assert!(throttle.note_off(4));
+
+        throttle.start_buffer(&mut keys);
+        assert_eq!(take_note_ons(&mut keys), vec![(0, 100), (1, 100)]);
+        throttle.end_buffer();
+
+        // New note ons wait for the deferred ones
+        throttle.note_on(10, 100, KeyNoteEvent::On(100), &mut keys, &stats);
+        throttle.start_buffer(&mut keys);
+        assert_eq!(take_note_ons(&mut keys), vec![(2, 100), (10, 100)]);
+        throttle.end_buffer();
+    }
+
+    #[test]
+    fn test_drop_quietest() {
+        let stats = VoiceChannelStats::new();
+        let mut keys = new_keys();
+        let mut throttle = SpawnThrottle::new(Some(SpawnBudget {
+            notes_per_buffer: 2,
+            overflow: SpawnOverflow::DropQuietest,
+        }));
+
+        throttle.note_on(0, 50, KeyNoteEvent::On(50), &mut keys, &stats);
+        throttle.note_on(1, 20, KeyNoteEvent::On(20), &mut keys, &stats);
+        keys[1].event_cache.push(KeyNoteEvent::Off);
+        throttle.note_on(2, 90, KeyNoteEvent::On(90), &mut keys, &stats);
+        throttle.note_on(3, 10, KeyNoteEvent::On(10), &mut keys, &stats);
+        throttle.note_on(4, 70, KeyNoteEvent::On(70), &mut keys, &stats);
+
+        throttle.start_buffer(&mut keys);
+        assert_eq!(note_ons(&keys), vec![(2, 90), (4, 70)]);
+        assert_eq!(stats.dropped_spawns.load(Ordering::Relaxed), 3);
+
+        // The note off of key 1 was removed with its note on, the ones of
+        // keys 0 and 3 will be ignored
+        assert!(keys[1].event_cache.is_empty());
+        assert!(!throttle.note_off(0));
+        assert!(!throttle.note_off(3));
+        assert!(throttle.note_off(2));
+        throttle.end_buffer();
+    }
+}
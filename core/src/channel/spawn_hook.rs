@@ -0,0 +1,75 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// The parameters of a note about to be played, which a `NoteSpawnHook`
+/// can change.
+#[derive(Debug)]
+pub struct NoteSpawnParams<'a> {
+    /// The key the note is played on, before the channel transpose. The
+    /// note off of the original key releases the note on this key.
+    pub key: u8,
+
+    /// The velocity of the note, from 1 to 127. Values above 127 are
+    /// clamped.
+    pub velocity: u8,
+
+    /// The pitch offset of the note in cents, e.g. set by
+    /// `ChannelAudioEvent::NoteOnDetuned`.
+    pub cents_offset: f32,
+
+    /// The current values of the channel's MIDI controllers.
+    pub controllers: &'a [u8; 128],
+}
+
+/// Whether a note passed to a `NoteSpawnHook` is played.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SpawnDecision {
+    /// Plays the note with the possibly changed parameters.
+    Play,
+
+    /// Drops the note. Its note off is ignored as well.
+    Skip,
+}
+
+type SpawnHookFn = dyn FnMut(&mut NoteSpawnParams) -> SpawnDecision + Send;
+
+/// A function called for each note on of a channel before its voices are
+/// spawned, e.g. to humanize the velocities or to skip notes by a custom
+/// rule. Set it with `ChannelConfigEvent::SetNoteSpawnHook`.
+///
+/// The hook runs on the thread that sends the events to the channel, usually
+/// the audio thread, so it should be fast and must not block. It runs after
+/// the retrigger policy and before the transpose and the spawn budget.
+///
+/// Cloning the hook shares the function, e.g. when the event is sent to all
+/// channels. Channels rendered in parallel take turns calling it.
+#[derive(Clone)]
+pub struct NoteSpawnHook {
+    hook: Arc<Mutex<Box<SpawnHookFn>>>,
+}
+
+impl NoteSpawnHook {
+    /// Creates a new hook that calls the given function.
+    pub fn new(hook: impl FnMut(&mut NoteSpawnParams) -> SpawnDecision + Send + 'static) -> Self {
+        Self {
+            hook: Arc::new(Mutex::new(Box::new(hook))),
+        }
+    }
+
+    /// Calls the hook. A hook that panicked before plays the notes
+    /// unchanged.
+    pub(super) fn call(&self, params: &mut NoteSpawnParams) -> SpawnDecision {
+        match self.hook.lock() {
+            Ok(mut hook) => hook(params),
+            Err(_) => SpawnDecision::Play,
+        }
+    }
+}
+
+impl fmt::Debug for NoteSpawnHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("NoteSpawnHook")
+    }
+}
@@ -0,0 +1,140 @@
+use crate::voice::VoiceControlData;
+
+/// The controller state of a channel, e.g. for a host to display it or to
+/// implement its own chase logic. See `VoiceChannelStatsReader::state` and
+/// `VoiceChannel::state`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ChannelStateSnapshot {
+    /// The current values of the MIDI controllers, as received in raw
+    /// control changes or set by the typed control events. Controllers that
+    /// weren't received since the last reset keep their defaults: 100 for
+    /// the volume, 64 for the pan, 127 for the expression and 0 for the
+    /// others.
+    pub controllers: [u8; 128],
+
+    /// The position of the pitch wheel, between -1 and 1.
+    pub pitch_bend_value: f32,
+
+    /// The pitch bend sensitivity, in semitones.
+    pub pitch_bend_sensitivity: f32,
+
+    /// The pitch bend sensitivity below the center, in semitones, or `None`
+    /// while `pitch_bend_sensitivity` applies in both directions.
+    pub pitch_bend_down_sensitivity: Option<f32>,
+
+    /// The pitch bend applied to the voices, in semitones. Usually the
+    /// product of the value and the sensitivity, unless it was set directly
+    /// with `ControlEvent::PitchBend`.
+    pub pitch_bend: f32,
+
+    /// The fine tune, in cents.
+    pub fine_tune: f32,
+
+    /// The coarse tune, in semitones.
+    pub coarse_tune: f32,
+
+    /// The selected bank. Always 128 on percussion channels.
+    pub bank: u8,
+
+    /// The selected program.
+    pub program: u8,
+}
+
+impl ChannelStateSnapshot {
+    /// Returns whether the damper (sustain) pedal is held.
+    pub fn damper(&self) -> bool {
+        self.controllers[0x40] >= 64
+    }
+}
+
+impl Default for ChannelStateSnapshot {
+    fn default() -> Self {
+        Self {
+            controllers: VoiceControlData::new_defaults().controllers,
+            pitch_bend_value: 0.0,
+            pitch_bend_sensitivity: 2.0,
+            pitch_bend_down_sensitivity: None,
+            pitch_bend: 0.0,
+            fine_tune: 0.0,
+            coarse_tune: 0.0,
+            bank: 0,
+            program: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        channel::{ChannelAudioEvent, ChannelEvent, ControlEvent, VoiceChannel},
+        AudioPipe, AudioStreamParams, ChannelCount,
+    };
+
+    fn control(event: ControlEvent) -> ChannelEvent {
+        ChannelEvent::Audio(ChannelAudioEvent::Control(event))
+    }
+
+    fn cc(controller: u8, value: u8) -> ChannelEvent {
+        control(ControlEvent::Raw(controller, value))
+    }
+
+    #[test]
+    fn test_controller_state() {
+        let params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let mut channel = VoiceChannel::new(Default::default(), params, None);
+        let stats = channel.get_channel_stats();
+        let mut buffer = vec![0.0; 256];
+
+        for event in [
+            cc(0x07, 90),
+            cc(0x0B, 80),
+            cc(0x40, 127),
+            cc(0x00, 5),
+            ChannelEvent::Audio(ChannelAudioEvent::ProgramChange(10)),
+            // Pitch bend sensitivity of 12 semitones
+            cc(0x65, 0),
+            cc(0x64, 0),
+            cc(0x06, 12),
+            control(ControlEvent::PitchBendValue(0.5)),
+        ] {
+            channel.process_event(event);
+        }
+
+        // The reader only sees the state after the next rendered buffer
+        assert_eq!(stats.state(), ChannelStateSnapshot::default());
+        channel.read_samples(&mut buffer);
+        let state = stats.state();
+        assert_eq!(state, channel.state());
+
+        assert_eq!(state.controllers[0x07], 90);
+        assert_eq!(state.controllers[0x0B], 80);
+        assert!(state.damper());
+        assert_eq!((state.bank, state.program), (5, 10));
+        assert_eq!(state.pitch_bend_value, 0.5);
+        assert_eq!(state.pitch_bend_sensitivity, 12.0);
+        assert_eq!(state.pitch_bend, 6.0);
+
+        // Changing the sensitivity scales the wheel position, not the
+        // previous bend
+        channel.process_event(cc(0x06, 2));
+        assert_eq!(channel.state().pitch_bend, 1.0);
+
+        // Typed control events set their controller
+        channel.process_event(control(ControlEvent::Volume(0.5)));
+        channel.process_event(control(ControlEvent::Damper(false)));
+        assert_eq!(channel.state().controllers[0x07], 64);
+        assert!(!channel.state().damper());
+
+        // Resetting the controllers keeps the program
+        channel.process_event(cc(0x79, 0));
+        channel.read_samples(&mut buffer);
+        let state = stats.state();
+        assert_eq!(
+            state.controllers,
+            ChannelStateSnapshot::default().controllers
+        );
+        assert_eq!(state.pitch_bend, 0.0);
+        assert_eq!((state.bank, state.program), (5, 10));
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::VecDeque;
+
+use super::NoteOffMatching;
+
+/// The maximum number of held notes remembered per key. A key that gets
+/// more NoteOns without a NoteOff forgets its oldest ones, whose NoteOffs
+/// then use the current transpose.
+const MAX_HELD_NOTES: usize = 1024;
+
+/// Keeps track of the channel transpose and of the key each held note was
+/// mapped to, so that a NoteOff always releases the key its NoteOn started,
+/// even if the transpose was changed in between.
+pub struct KeyTranspose {
+    semitones: i8,
+    clamp: bool,
+    note_off_matching: NoteOffMatching,
+    sounding: Vec<VecDeque<Option<u8>>>,
+}
+
+impl KeyTranspose {
+    pub fn new() -> Self {
+        Self {
+            semitones: 0,
+            clamp: false,
+            note_off_matching: NoteOffMatching::OldestFirst,
+            sounding: (0..128).map(|_| VecDeque::new()).collect(),
+        }
+    }
+
+    pub fn set_semitones(&mut self, semitones: i8) {
+        self.semitones = semitones;
+    }
+
+    pub fn set_clamp(&mut self, clamp: bool) {
+        self.clamp = clamp;
+    }
+
+    /// Sets which held notes of a key a NoteOff releases, the same way as
+    /// the voices of the keys release them.
+    pub fn set_note_off_matching(&mut self, note_off_matching: NoteOffMatching) {
+        self.note_off_matching = note_off_matching;
+    }
+
+    /// Returns the key that the given key will sound at with the current
+    /// transpose, or `None` if it falls outside 0-127 and clamping is off.
+    pub fn transpose(&self, key: u8) -> Option<u8> {
+        if key > 127 {
+            return None;
+        }
+
+        let shifted = key as i16 + self.semitones as i16;
+        if (0..=127).contains(&shifted) {
+            Some(shifted as u8)
+        } else if self.clamp {
+            Some(shifted.clamp(0, 127) as u8)
+        } else {
+            None
+        }
+    }
+
+    /// Maps the NoteOn of `key` played on the `target` key, which differs
+    /// from `key` e.g. when a spawn hook moved it, and remembers the result
+    /// for the NoteOff of `key`. A `None` target drops the note and its
+    /// NoteOff.
+    pub fn note_on_as(&mut self, key: u8, target: Option<u8>) -> Option<u8> {
+        let sounding = target.and_then(|target| self.transpose(target));
+        if let Some(held) = self.sounding.get_mut(key as usize) {
+            if held.len() >= MAX_HELD_NOTES {
+                held.pop_front();
+            }
+            held.push_back(sounding);
+        }
+        sounding
+    }
+
+    /// Maps a NoteOff key to the keys its held NoteOns were sent to, calling
+    /// `release` with each of them. The `NoteOffMatching` picks the oldest
+    /// or the newest held note, or all of them. Without held notes, the key
+    /// it currently maps to is released.
+    pub fn note_off(&mut self, key: u8, mut release: impl FnMut(u8)) {
+        let current = self.transpose(key);
+        let Some(held) = self.sounding.get_mut(key as usize) else {
+            return;
+        };
+
+        if held.is_empty() {
+            if let Some(sounding) = current {
+                release(sounding);
+            }
+            return;
+        }
+        let released = match self.note_off_matching {
+            NoteOffMatching::OldestFirst => held.pop_front(),
+            NoteOffMatching::NewestFirst => held.pop_back(),
+            NoteOffMatching::All => {
+                for sounding in held.drain(..).flatten() {
+                    release(sounding);
+                }
+                return;
+            }
+        };
+        if let Some(sounding) = released.flatten() {
+            release(sounding);
+        }
+    }
+
+    /// Forgets the held notes of a key, calling `kill` with each key they
+    /// were sent to, or with the key it currently maps to if none are held.
+    /// Returns the number of forgotten notes that were sounding.
+    pub fn note_kill(&mut self, key: u8, mut kill: impl FnMut(u8)) -> u32 {
+        let current = self.transpose(key);
+        let Some(held) = self.sounding.get_mut(key as usize) else {
+            return 0;
+        };
+
+        if held.is_empty() {
+            if let Some(sounding) = current {
+                kill(sounding);
+            }
+        }
+        let mut count = 0;
+        for sounding in held.drain(..).flatten() {
+            kill(sounding);
+            count += 1;
+        }
+        count
+    }
+
+    /// Calls `f` with each key the held notes of the given key were sent
+    /// to, or with the key it currently maps to if none are held.
+    pub fn sounding_keys(&self, key: u8, mut f: impl FnMut(u8)) {
+        let Some(held) = self.sounding.get(key as usize) else {
+            return;
+        };
+
+        if held.is_empty() {
+            if let Some(sounding) = self.transpose(key) {
+                f(sounding);
+            }
+        }
+        for &sounding in held.iter().flatten() {
+            f(sounding);
+        }
+    }
+
+    /// Forgets all held notes, e.g. after all notes were released or killed.
+    pub fn clear(&mut self) {
+        for held in self.sounding.iter_mut() {
+            held.clear();
+        }
+    }
+}
+
+impl Default for KeyTranspose {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note_on(transpose: &mut KeyTranspose, key: u8) -> Option<u8> {
+        transpose.note_on_as(key, Some(key))
+    }
+
+    fn note_off(transpose: &mut KeyTranspose, key: u8) -> Vec<u8> {
+        let mut released = Vec::new();
+        transpose.note_off(key, |key| released.push(key));
+        released
+    }
+
+    #[test]
+    fn test_note_off_after_transpose_change() {
+        let mut transpose = KeyTranspose::new();
+        transpose.set_semitones(12);
+        assert_eq!(note_on(&mut transpose, 60), Some(72));
+
+        // The NoteOff releases the key its NoteOn started
+        transpose.set_semitones(-5);
+        assert_eq!(note_off(&mut transpose, 60), [72]);
+
+        // A NoteOff without a NoteOn uses the current transpose
+        assert_eq!(note_off(&mut transpose, 60), [55]);
+    }
+
+    #[test]
+    fn test_note_off_matching() {
+        // The transpose changes between two NoteOns of the same key
+        let on_on_off = |note_off_matching| {
+            let mut transpose = KeyTranspose::new();
+            transpose.set_note_off_matching(note_off_matching);
+            note_on(&mut transpose, 60);
+            transpose.set_semitones(7);
+            note_on(&mut transpose, 60);
+            (note_off(&mut transpose, 60), note_off(&mut transpose, 60))
+        };
+
+        assert_eq!(
+            on_on_off(NoteOffMatching::OldestFirst),
+            (vec![60], vec![67])
+        );
+        assert_eq!(
+            on_on_off(NoteOffMatching::NewestFirst),
+            (vec![67], vec![60])
+        );
+        // The second NoteOff has no held notes left, so it uses the
+        // current transpose
+        assert_eq!(on_on_off(NoteOffMatching::All), (vec![60, 67], vec![67]));
+    }
+
+    #[test]
+    fn test_clamp_and_drop() {
+        let mut transpose = KeyTranspose::new();
+        transpose.set_semitones(5);
+        assert_eq!(note_on(&mut transpose, 125), None);
+        assert_eq!(note_off(&mut transpose, 125), vec![]);
+
+        transpose.set_semitones(-5);
+        assert_eq!(note_on(&mut transpose, 2), None);
+        assert_eq!(note_off(&mut transpose, 2), vec![]);
+
+        transpose.set_clamp(true);
+        assert_eq!(note_on(&mut transpose, 2), Some(0));
+        transpose.set_semitones(5);
+        assert_eq!(note_on(&mut transpose, 125), Some(127));
+        assert_eq!(note_off(&mut transpose, 2), [0]);
+        assert_eq!(note_off(&mut transpose, 125), [127]);
+    }
+
+    #[test]
+    fn test_note_kill() {
+        let mut transpose = KeyTranspose::new();
+        transpose.set_semitones(2);
+        note_on(&mut transpose, 60);
+        transpose.set_semitones(4);
+        note_on(&mut transpose, 60);
+        transpose.note_on_as(60, None);
+
+        let mut killed = Vec::new();
+        assert_eq!(transpose.note_kill(60, |key| killed.push(key)), 2);
+        assert_eq!(killed, [62, 64]);
+
+        // Without held notes, the key it currently maps to is killed
+        killed.clear();
+        assert_eq!(transpose.note_kill(60, |key| killed.push(key)), 0);
+        assert_eq!(killed, [64]);
+    }
+
+    #[test]
+    fn test_held_notes_limit() {
+        let mut transpose = KeyTranspose::new();
+        for _ in 0..MAX_HELD_NOTES * 2 {
+            note_on(&mut transpose, 60);
+        }
+        assert_eq!(transpose.sounding[60].len(), MAX_HELD_NOTES);
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+use super::channel_sf::ProgramDescriptor;
+
+/// Controls what a channel does with a note on that no region of its
+/// soundfonts matches for the current program, e.g. because the soundfont
+/// lacks the preset or the key. The misses are counted either way, see
+/// `VoiceChannelStatsReader::unmatched_notes`.
+///
+/// Notes of programs that a `ProgramMap` ignores aren't misses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum UnmatchedNotePolicy {
+    /// The note is silent.
+    #[default]
+    Silent,
+
+    /// The note is silent, and reported as an `UnmatchedNote` to diagnose
+    /// the setup, see `VoiceChannelStatsReader::take_unmatched_notes`.
+    Diagnostics,
+
+    /// The note is played by the fallback soundfont of the channel, set
+    /// with `ChannelConfigEvent::SetFallbackSoundfont`, with the current
+    /// program or else bank 0, preset 0. It is silent until a fallback
+    /// soundfont is set.
+    Fallback,
+}
+
+/// A note on that no region matched, reported with
+/// `UnmatchedNotePolicy::Diagnostics`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnmatchedNote {
+    /// The bank of the channel's program.
+    pub bank: u8,
+
+    /// The preset of the channel's program.
+    pub preset: u8,
+
+    /// The key the note played, after the transpose.
+    pub key: u8,
+
+    /// The velocity of the note.
+    pub vel: u8,
+}
+
+/// The maximum number of unmatched notes kept until they are read. Later
+/// notes are only counted.
+const MAX_PENDING_NOTES: usize = 256;
+
+/// Counts the unmatched notes of a channel, and keeps them until they are
+/// read if they are reported.
+#[derive(Debug, Clone, Default)]
+pub(super) struct UnmatchedNotes {
+    count: Arc<AtomicU64>,
+    pending: Arc<Mutex<Vec<UnmatchedNote>>>,
+    report: bool,
+}
+
+impl UnmatchedNotes {
+    pub fn new(policy: UnmatchedNotePolicy) -> Self {
+        Self {
+            report: policy == UnmatchedNotePolicy::Diagnostics,
+            ..Default::default()
+        }
+    }
+
+    pub fn record(&self, program: ProgramDescriptor, key: u8, vel: u8) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        if self.report {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.len() < MAX_PENDING_NOTES {
+                pending.push(UnmatchedNote {
+                    bank: program.bank,
+                    preset: program.preset,
+                    key,
+                    vel,
+                });
+            }
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn take(&self) -> Vec<UnmatchedNote> {
+        std::mem::take(&mut *self.pending.lock().unwrap())
+    }
+}
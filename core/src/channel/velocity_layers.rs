@@ -0,0 +1,48 @@
+/// Limits the layer count of soft notes, which are masked by the louder
+/// ones and rarely need as many layers.
+///
+/// The limit applies to the voices of the key when the note on is played,
+/// the same way as the layer count, and never raises the configured layer
+/// count. Notes at or above `double_below` use the configured layer count.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct VelocityLayers {
+    /// Note ons with a lower velocity are limited to a single layer.
+    pub single_below: u8,
+
+    /// Note ons with a lower velocity, that aren't below `single_below`,
+    /// are limited to two layers.
+    pub double_below: u8,
+}
+
+impl VelocityLayers {
+    /// Returns the layer limit of a note on with the given velocity, or
+    /// `None` if it uses the configured layer count.
+    pub fn max_layers(&self, vel: u8) -> Option<usize> {
+        if vel < self.single_below {
+            Some(1)
+        } else if vel < self.double_below {
+            Some(2)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_layers() {
+        let layers = VelocityLayers {
+            single_below: 32,
+            double_below: 64,
+        };
+        assert_eq!(layers.max_layers(0), Some(1));
+        assert_eq!(layers.max_layers(31), Some(1));
+        assert_eq!(layers.max_layers(32), Some(2));
+        assert_eq!(layers.max_layers(63), Some(2));
+        assert_eq!(layers.max_layers(64), None);
+    }
+}
@@ -0,0 +1,160 @@
+use std::time::Instant;
+
+/// Controls how the layer count limits the voices of each key.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum VoiceLimitMode {
+    /// The layer count is the maximum number of voices per key. When a note
+    /// spawns voices beyond it, the voice groups of the quietest notes are
+    /// killed.
+    #[default]
+    Count,
+
+    /// The layer count is converted into a render time budget per key. See
+    /// the `RenderBudget` documentation for more information.
+    RenderBudget(RenderBudget),
+}
+
+/// Limits the voices of each key by their measured render time instead of
+/// their number.
+///
+/// The budget of a key is the time `layer count` voices of the expected
+/// cost take to render. The render time of each key is measured every few
+/// buffers and averaged per voice, and the key renders as many voices as
+/// fit in its budget at that cost, loudest first. The quieter voices are
+/// killed, like the ones beyond the layer count in `VoiceLimitMode::Count`.
+///
+/// So keys playing cheap voices, such as ones near the end of their release,
+/// keep more voices than the layer count, while keys playing expensive
+/// voices, such as highly pitched ones, keep fewer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(default)
+)]
+pub struct RenderBudget {
+    /// The expected render time of one voice per frame, in nanoseconds.
+    ///
+    /// Default: `20.0`
+    pub voice_cost_ns: f32,
+
+    /// How often the render time of a key is measured, in rendered buffers.
+    ///
+    /// Default: `8`
+    pub measure_interval: u32,
+
+    /// The weight of each new measurement in the moving average of the cost
+    /// per voice, from 0 to 1. Higher values follow changes faster.
+    ///
+    /// Default: `0.25`
+    pub smoothing: f32,
+}
+
+impl Default for RenderBudget {
+    fn default() -> Self {
+        Self {
+            voice_cost_ns: 20.0,
+            measure_interval: 8,
+            smoothing: 0.25,
+        }
+    }
+}
+
+/// Measures the render cost per voice of a key, and converts its layer count
+/// into the number of voices that fit in the render budget.
+pub(super) struct VoiceCostMeter {
+    budget: RenderBudget,
+
+    /// The moving average of the render time per voice and frame, in
+    /// nanoseconds
+    average_cost_ns: Option<f32>,
+
+    /// The number of buffers rendered until the next measurement
+    countdown: u32,
+}
+
+impl VoiceCostMeter {
+    pub fn new(budget: RenderBudget) -> Self {
+        Self {
+            budget,
+            average_cost_ns: None,
+            countdown: 0,
+        }
+    }
+
+    /// Returns the start time if the current buffer should be measured.
+    pub fn start(&mut self) -> Option<Instant> {
+        if self.countdown > 0 {
+            self.countdown -= 1;
+            return None;
+        }
+
+        self.countdown = self.budget.measure_interval.max(1) - 1;
+        Some(Instant::now())
+    }
+
+    /// Records the render time since `start` of `voices` voices rendering
+    /// `frames` frames.
+    pub fn finish(&mut self, start: Instant, voices: usize, frames: usize) {
+        if voices > 0 && frames > 0 {
+            let cost = start.elapsed().as_nanos() as f32 / (voices * frames) as f32;
+            self.record(cost);
+        }
+    }
+
+    fn record(&mut self, cost_ns: f32) {
+        let smoothing = self.budget.smoothing.clamp(0.0, 1.0);
+        self.average_cost_ns = Some(match self.average_cost_ns {
+            Some(average) => average + (cost_ns - average) * smoothing,
+            None => cost_ns,
+        });
+    }
+
+    /// The number of voices that fit in the budget of `layers` voices. Equals
+    /// the layer count until the first measurement, and is at least 1.
+    pub fn voice_limit(&self, layers: usize) -> usize {
+        match self.average_cost_ns {
+            Some(cost) if cost > 0.0 => {
+                let limit = layers as f32 * self.budget.voice_cost_ns / cost;
+                (limit as usize).max(1)
+            }
+            _ => layers,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_voice_limit_follows_cost() {
+        let mut meter = VoiceCostMeter::new(RenderBudget {
+            voice_cost_ns: 20.0,
+            measure_interval: 4,
+            smoothing: 0.5,
+        });
+        assert_eq!(meter.voice_limit(8), 8);
+
+        // Only every fourth buffer is measured
+        assert!(meter.start().is_some());
+        assert!(meter.start().is_none());
+        assert!(meter.start().is_none());
+        assert!(meter.start().is_none());
+        assert!(meter.start().is_some());
+
+        // Voices twice as expensive as expected halve the limit, and cheaper
+        // voices raise it
+        meter.record(40.0);
+        assert_eq!(meter.voice_limit(8), 4);
+        meter.record(0.0);
+        assert_eq!(meter.voice_limit(8), 8);
+        meter.record(0.0);
+        assert_eq!(meter.voice_limit(8), 16);
+
+        // At least one voice is always rendered
+        meter.record(1e9);
+        assert_eq!(meter.voice_limit(8), 1);
+    }
+}
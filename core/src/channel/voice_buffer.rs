@@ -1,6 +1,43 @@
-use super::ChannelInitOptions;
-use crate::voice::{ReleaseType, Voice};
-use std::ops::{Deref, DerefMut};
+use super::{
+    polyphony::{is_cut_off, polyphony_cutoffs, GroupedVoice},
+    voice_spawner::ReleaseSpawners,
+    ChannelInitOptions,
+};
+use crate::voice::{ReleaseType, Voice, VoiceGeneratorState, VoicePolyphony};
+use std::{
+    collections::HashSet,
+    mem,
+    ops::{Deref, DerefMut},
+};
+
+/// Controls which of the voice groups playing on a key a note off releases,
+/// when the key received several note ons.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum NoteOffMatching {
+    /// The group of the oldest held note is released.
+    #[default]
+    OldestFirst,
+
+    /// The group of the newest held note is released.
+    NewestFirst,
+
+    /// All the held groups of the key are released.
+    All,
+}
+
+/// The current level of the volume envelope of a voice, or 1.0 if the voice
+/// has none.
+fn envelope_level(voice: &dyn Voice) -> f32 {
+    let mut state = VoiceGeneratorState::default();
+    voice.inspect(&mut state);
+    state.envelope_amplitude.unwrap_or(1.0)
+}
+
+/// The current loudness of a voice, from its volume envelope and amplitude.
+fn loudness(voice: &dyn Voice) -> f32 {
+    envelope_level(voice) * voice.amplitude()
+}
 
 /// A voice with its group ID for tracking
 pub struct GroupVoice {
@@ -31,6 +68,16 @@ impl DerefMut for GroupVoice {
     }
 }
 
+/// A voice group released by a note off, see `VoiceBuffer::release_next_voice`.
+pub struct ReleasedNote {
+    /// The release spawners of the note, from the program it started with
+    pub release_spawners: Option<ReleaseSpawners>,
+
+    /// The highest volume envelope level of the released voices when they
+    /// were released, only measured with a release crossfade. 1.0 otherwise.
+    pub level: f32,
+}
+
 /// Voice buffer optimized for high voice counts with parallel processing support
 pub struct VoiceBuffer {
     options: ChannelInitOptions,
@@ -38,7 +85,18 @@ pub struct VoiceBuffer {
     // Pre-allocated Vec for better performance with high voice counts
     voices: Vec<GroupVoice>,
     damper_held: bool,
-    held_by_damper: Vec<usize>,
+    /// The IDs of the voice groups released while the damper is held
+    held_by_damper: HashSet<usize>,
+    /// Scratch buffer for the IDs of the voice groups that are still
+    /// playing, used to forget the ended groups held by the damper
+    live_ids: Vec<usize>,
+    /// Scratch buffers for the voices of the polyphony groups and their
+    /// cutoffs, so limiting the note polyphony of a note doesn't allocate
+    polyphony_voices: Vec<GroupedVoice>,
+    polyphony_cutoffs: Vec<(u32, u64)>,
+    /// The release spawners of the voice groups that have any, kept until
+    /// the group is released
+    release_spawners: Vec<(usize, ReleaseSpawners)>,
     pub max_voices: Option<usize>,
 }
 
@@ -50,7 +108,11 @@ impl VoiceBuffer {
             // Pre-allocate for high voice count scenarios
             voices: Vec::with_capacity(256),
             damper_held: false,
-            held_by_damper: Vec::with_capacity(16),
+            held_by_damper: HashSet::with_capacity(16),
+            live_ids: Vec::new(),
+            polyphony_voices: Vec::new(),
+            polyphony_cutoffs: Vec::new(),
+            release_spawners: Vec::new(),
             max_voices: Some(4),
         }
     }
@@ -61,15 +123,18 @@ impl VoiceBuffer {
         self.id_counter
     }
 
-    /// Fast linear scan to find quietest voice
-    fn pop_quietest_voice_group(&mut self, ignored_id: usize) {
+    /// Fast linear scan to find quietest voice, whose whole group is then
+    /// killed. The group of `ignored_id` is never picked, and neither are
+    /// voices that were already killed. Returns the number of voices that
+    /// were killed, zero if there was no voice group to remove.
+    fn pop_quietest_voice_group(&mut self, ignored_id: usize, fade_out: bool) -> usize {
         if self.voices.is_empty() {
-            return;
+            return 0;
         }
 
         let mut quietest_vel = u8::MAX;
         let mut quietest_id = None;
-        
+
         for voice in &self.voices {
             if voice.id == ignored_id || voice.is_killed() {
                 continue;
@@ -84,101 +149,252 @@ impl VoiceBuffer {
             }
         }
 
-        if let Some(id) = quietest_id {
-            if self.options.fade_out_killing {
-                for voice in &mut self.voices {
-                    if voice.id == id {
-                        voice.signal_release(ReleaseType::Kill);
+        let Some(id) = quietest_id else {
+            return 0;
+        };
+
+        let mut killed = 0;
+        if fade_out {
+            for voice in &mut self.voices {
+                if voice.id == id {
+                    if !voice.is_killed() {
+                        killed += 1;
                     }
+                    voice.signal_release(ReleaseType::Kill);
                 }
-            } else {
-                self.voices.retain(|v| v.id != id);
-            }
-
-            if let Some(index) = self.held_by_damper.iter().position(|&x| x == id) {
-                self.held_by_damper.swap_remove(index);
             }
+        } else {
+            let count = self.voices.len();
+            self.voices.retain(|v| v.id != id);
+            killed = count - self.voices.len();
         }
+        self.held_by_damper.remove(&id);
+        self.release_spawners.retain(|(group, _)| *group != id);
+
+        killed
     }
 
-    pub fn kill_all_voices(&mut self) {
-        if self.options.fade_out_killing {
+    pub fn clear(&mut self) {
+        self.voices.clear();
+        self.held_by_damper.clear();
+        self.release_spawners.clear();
+    }
+
+    /// Kills all voices. Without a fade out they are removed right away and
+    /// returned, so the caller decides where they are dropped.
+    pub fn kill_all_voices(&mut self) -> Vec<GroupVoice> {
+        let killed = if self.options.fade_out_killing {
             for voice in &mut self.voices {
                 voice.signal_release(ReleaseType::Kill);
             }
+            Vec::new()
         } else {
-            self.voices.clear();
-        }
+            self.voices.drain(..).collect()
+        };
         self.held_by_damper.clear();
+        self.release_spawners.clear();
+        killed
     }
 
-    #[inline(always)]
-    pub fn push_voices(
+    /// Pushes the voices of a note as a new group, with the release spawners
+    /// of its key and velocity, which the note off spawns.
+    pub fn push_note_voices(
         &mut self,
         voices: impl Iterator<Item = Box<dyn Voice>>,
+        release_spawners: Option<ReleaseSpawners>,
     ) {
+        let id = self.push_voices(voices);
+
+        // The group can't be released if none of its voices are left
+        let has_voices = self.voices.last().is_some_and(|v| v.id == id);
+        if let (Some(release_spawners), true) = (release_spawners, has_voices) {
+            self.release_spawners.push((id, release_spawners));
+        }
+    }
+
+    /// Pushes the voices as a new group and returns its ID.
+    #[inline(always)]
+    pub fn push_voices(&mut self, voices: impl Iterator<Item = Box<dyn Voice>>) -> usize {
         let id = self.get_id();
+        let first_new = self.voices.len();
 
         for voice in voices {
             self.voices.push(GroupVoice { id, voice });
         }
 
+        let has_note_polyphony = self.voices[first_new..].iter().any(|v| {
+            v.polyphony()
+                .is_some_and(|(p, _)| p.note_polyphony.is_some())
+        });
+        if has_note_polyphony {
+            self.limit_note_polyphony();
+        }
+
         if let Some(max_voices) = self.max_voices {
             if self.options.fade_out_killing {
-                while self.get_active_count() > max_voices {
-                    self.pop_quietest_voice_group(id);
+                // The killed voices keep fading out, so they are subtracted
+                // instead of counting the active voices again
+                let mut active = self.get_active_count();
+                while active > max_voices {
+                    match self.pop_quietest_voice_group(id, true) {
+                        0 => break,
+                        killed => active -= killed,
+                    }
                 }
             } else {
                 while self.voices.len() > max_voices {
-                    self.pop_quietest_voice_group(id);
+                    if self.pop_quietest_voice_group(id, false) == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+
+        // The hard limit also counts the voices fading out after being
+        // killed, and removes voices immediately
+        let hard_limit = self.options.hard_voice_limit.max(1);
+        if self.voices.len() > hard_limit {
+            self.voices.retain(|v| !v.is_killed());
+            while self.voices.len() > hard_limit {
+                if self.pop_quietest_voice_group(id, false) == 0 {
+                    break;
                 }
             }
         }
+
+        id
     }
 
-    fn get_active_count(&self) -> usize {
-        self.voices.iter().filter(|v| !v.is_killed()).count()
+    /// Kills the quietest voices until at most `max` voices are active, so
+    /// only the loudest ones are rendered. Used by the `RenderBudget`.
+    pub fn keep_loudest_voices(&mut self, max: usize) {
+        let mut active = self.get_active_count();
+        while active > max {
+            let quietest = self
+                .voices
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| !v.is_killed())
+                .map(|(i, v)| (i, loudness(v.voice.as_ref())))
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            let Some((index, _)) = quietest else {
+                break;
+            };
+
+            if self.options.fade_out_killing {
+                self.voices[index].signal_release(ReleaseType::Kill);
+            } else {
+                self.voices.swap_remove(index);
+            }
+            active -= 1;
+        }
     }
 
-    pub fn release_next_voice(&mut self) -> Option<u8> {
-        if !self.damper_held {
-            let mut id: Option<usize> = None;
-            let mut vel = None;
+    /// Kills the oldest voices of the polyphony groups that play more voices
+    /// on the key than their `note_polyphony` limit allows.
+    fn limit_note_polyphony(&mut self) {
+        let mut voices = mem::take(&mut self.polyphony_voices);
+        voices.clear();
+        voices.extend(self.voice_polyphony().filter_map(|(polyphony, order)| {
+            Some(GroupedVoice {
+                group: polyphony.group,
+                limit: polyphony.note_polyphony?,
+                order,
+            })
+        }));
+
+        let mut cutoffs = mem::take(&mut self.polyphony_cutoffs);
+        polyphony_cutoffs(&mut voices, &mut cutoffs);
+        if !cutoffs.is_empty() {
+            self.kill_voices_where(|voice| is_cut_off(voice, &cutoffs));
+        }
+        self.polyphony_voices = voices;
+        self.polyphony_cutoffs = cutoffs;
+    }
 
-            for voice in &mut self.voices {
-                if voice.is_releasing() || voice.is_killed() {
-                    continue;
-                }
+    /// Returns the polyphony limits and spawn order of the voices that
+    /// weren't killed, see `Voice::polyphony`.
+    pub fn voice_polyphony(&self) -> impl Iterator<Item = (VoicePolyphony, u64)> + '_ {
+        self.voices
+            .iter()
+            .filter(|v| !v.is_killed())
+            .filter_map(|v| v.polyphony())
+    }
 
-                if id.is_none() {
-                    id = Some(voice.id);
-                    vel = Some(voice.velocity())
+    /// Kills the voices that `kill` returns true for. They fade out if
+    /// `fade_out_killing` is enabled, and are removed right away otherwise.
+    pub fn kill_voices_where(&mut self, mut kill: impl FnMut(&dyn Voice) -> bool) {
+        if self.options.fade_out_killing {
+            for voice in &mut self.voices {
+                if !voice.is_killed() && kill(voice.voice.as_ref()) {
+                    voice.signal_release(ReleaseType::Kill);
                 }
+            }
+        } else {
+            self.voices.retain(|v| !kill(v.voice.as_ref()));
+        }
+    }
 
-                if id != Some(voice.id) {
-                    break;
-                }
+    pub fn get_active_count(&self) -> usize {
+        self.voices.iter().filter(|v| !v.is_killed()).count()
+    }
 
-                voice.signal_release(ReleaseType::Standard);
-            }
+    /// Finds the group the next note off releases, among the groups that
+    /// are neither releasing nor held by the damper. The voices aren't kept
+    /// in order, so the groups are ordered by their ID.
+    fn next_release_group(&self) -> Option<usize> {
+        let held = self.voices.iter().filter(|v| {
+            !v.is_releasing() && !v.is_killed() && !self.held_by_damper.contains(&v.id)
+        });
+        let group = match self.options.note_off_matching {
+            NoteOffMatching::OldestFirst | NoteOffMatching::All => held.min_by_key(|v| v.id),
+            NoteOffMatching::NewestFirst => held.max_by_key(|v| v.id),
+        };
+        group.map(|v| v.id)
+    }
 
-            vel
-        } else {
-            for voice in &mut self.voices {
-                if voice.is_releasing() || voice.is_killed() {
-                    continue;
+    /// Releases the voice groups matched by a note off, see
+    /// `NoteOffMatching`. Returns the release spawners of the released
+    /// group, or of the oldest one if all groups are released.
+    /// While the damper is held the groups are only marked to be released
+    /// when it is lifted, and `None` is returned.
+    pub fn release_next_voice(&mut self) -> Option<ReleasedNote> {
+        let id = self.next_release_group()?;
+        let all = self.options.note_off_matching == NoteOffMatching::All;
+
+        if self.damper_held {
+            if all {
+                for voice in &self.voices {
+                    if !voice.is_releasing() && !voice.is_killed() {
+                        self.held_by_damper.insert(voice.id);
+                    }
                 }
+            } else {
+                self.held_by_damper.insert(id);
+            }
+            return None;
+        }
 
-                if self.held_by_damper.contains(&voice.id) {
-                    continue;
+        let measure = self.options.release_crossfade.is_some();
+        let mut level = if measure { 0.0f32 } else { 1.0 };
+        for voice in &mut self.voices {
+            if (all || voice.id == id) && !voice.is_releasing() && !voice.is_killed() {
+                if measure {
+                    level = level.max(envelope_level(voice.voice.as_ref()));
                 }
-
-                self.held_by_damper.push(voice.id);
-                break;
+                voice.signal_release(ReleaseType::Standard);
             }
+        }
 
-            None
+        let index = self.release_spawners.iter().position(|(g, _)| *g == id);
+        let release_spawners = index.map(|i| self.release_spawners.swap_remove(i).1);
+        if all {
+            self.release_spawners.clear();
         }
+        Some(ReleasedNote {
+            release_spawners,
+            level,
+        })
     }
 
     /// Batch remove ended voices using swap_remove for efficiency
@@ -196,14 +412,45 @@ impl VoiceBuffer {
                 i += 1;
             }
         }
-        
-        // Always clear held_by_damper to avoid O(n*m) complexity
-        // This is the fastest approach for high voice counts
-        if !self.held_by_damper.is_empty() {
-            self.held_by_damper.clear();
+
+        // There are more held groups than voices only if some of the held
+        // groups ended, so they are forgotten. The same goes for the groups
+        // with release spawners.
+        if self.held_by_damper.len() > self.voices.len()
+            || self.release_spawners.len() > self.voices.len()
+        {
+            self.live_ids.clear();
+            self.live_ids.extend(self.voices.iter().map(|v| v.id));
+            self.live_ids.sort_unstable();
+
+            let live_ids = &self.live_ids;
+            self.held_by_damper
+                .retain(|id| live_ids.binary_search(id).is_ok());
+            self.release_spawners
+                .retain(|(id, _)| live_ids.binary_search(id).is_ok());
         }
     }
 
+    /// Returns the sample position of the newest voice group that wasn't
+    /// killed, see `VoiceGeneratorState::sample_position`.
+    pub fn newest_sample_position(&self) -> Option<f64> {
+        let newest = self
+            .voices
+            .iter()
+            .filter(|v| !v.is_killed())
+            .map(|v| v.id)
+            .max()?;
+        self.voices.iter().filter(|v| v.id == newest).find_map(|v| {
+            let mut state = VoiceGeneratorState::default();
+            v.inspect(&mut state);
+            state.sample_position
+        })
+    }
+
+    pub fn iter_voices(&self) -> impl Iterator<Item = &GroupVoice> {
+        self.voices.iter()
+    }
+
     #[inline(always)]
     pub fn iter_voices_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn Voice>> {
         self.voices.iter_mut().map(|group| &mut group.voice)
@@ -229,7 +476,7 @@ impl VoiceBuffer {
         if self.damper_held && !damper {
             for voice in &mut self.voices {
                 if self.held_by_damper.contains(&voice.id) {
-                    voice.signal_release(ReleaseType::Standard);
+                    voice.signal_release(ReleaseType::Damper);
                 }
             }
             self.held_by_damper.clear();
@@ -242,3 +489,267 @@ impl VoiceBuffer {
         self.max_voices = max;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voice::{
+        PolyphonyVoice, VoiceControlData, VoiceGeneratorBase, VoiceSampleGenerator,
+    };
+
+    /// A silent voice that plays until it is released or killed.
+    struct HeldVoice {
+        vel: u8,
+        releasing: bool,
+        killed: bool,
+    }
+
+    impl VoiceGeneratorBase for HeldVoice {
+        fn ended(&self) -> bool {
+            self.releasing || self.killed
+        }
+
+        fn signal_release(&mut self, rel_type: ReleaseType) {
+            match rel_type {
+                ReleaseType::Kill => self.killed = true,
+                _ => self.releasing = true,
+            }
+        }
+
+        fn process_controls(&mut self, _control: &VoiceControlData) {}
+    }
+
+    impl VoiceSampleGenerator for HeldVoice {
+        fn render_to(&mut self, _buffer: &mut [f32]) {}
+    }
+
+    impl Voice for HeldVoice {
+        fn is_releasing(&self) -> bool {
+            self.releasing
+        }
+
+        fn is_killed(&self) -> bool {
+            self.killed
+        }
+
+        fn velocity(&self) -> u8 {
+            self.vel
+        }
+    }
+
+    fn held_voice(vel: u8) -> Box<dyn Voice> {
+        Box::new(HeldVoice {
+            vel,
+            releasing: false,
+            killed: false,
+        })
+    }
+
+    #[test]
+    fn test_damper_stress_stays_bounded() {
+        for fade_out_killing in [false, true] {
+            let mut buffer = VoiceBuffer::new(ChannelInitOptions {
+                fade_out_killing,
+                hard_voice_limit: 64,
+                ..Default::default()
+            });
+            buffer.set_max_voices(None);
+            buffer.set_damper(true);
+
+            let mut capacity = 0;
+            for i in 0..1_000_000u32 {
+                buffer.push_voices(std::iter::once(held_voice((i % 127) as u8 + 1)));
+                buffer.release_next_voice();
+
+                assert!(buffer.voice_count() <= 64);
+                assert!(buffer.held_by_damper.len() <= 64);
+
+                // Memory stays flat once the limit is reached
+                if i == 1000 {
+                    capacity = buffer.voices.capacity();
+                }
+                if i % 1000 == 0 {
+                    buffer.remove_ended_voices();
+                }
+            }
+            assert_eq!(buffer.voices.capacity(), capacity);
+
+            // Lifting the damper releases the held voices
+            buffer.set_damper(false);
+            buffer.remove_ended_voices();
+            assert_eq!(buffer.voice_count(), 0);
+        }
+    }
+
+    fn releasing_velocities(buffer: &VoiceBuffer) -> Vec<u8> {
+        let mut velocities: Vec<u8> = buffer
+            .voices
+            .iter()
+            .filter(|v| v.is_releasing())
+            .map(|v| v.velocity())
+            .collect();
+        velocities.sort_unstable();
+        velocities
+    }
+
+    #[test]
+    fn test_note_off_matching() {
+        // Two overlapping notes on the same key, followed by two note offs
+        let on_on_off_off = |note_off_matching| {
+            let mut buffer = VoiceBuffer::new(ChannelInitOptions {
+                note_off_matching,
+                ..Default::default()
+            });
+            buffer.push_voices(std::iter::once(held_voice(10)));
+            buffer.push_voices(std::iter::once(held_voice(20)));
+
+            let first = buffer.release_next_voice().is_some();
+            let after_first = releasing_velocities(&buffer);
+            let second = buffer.release_next_voice().is_some();
+            (first, after_first, second, releasing_velocities(&buffer))
+        };
+
+        assert_eq!(
+            on_on_off_off(NoteOffMatching::OldestFirst),
+            (true, vec![10], true, vec![10, 20])
+        );
+        assert_eq!(
+            on_on_off_off(NoteOffMatching::NewestFirst),
+            (true, vec![20], true, vec![10, 20])
+        );
+        assert_eq!(
+            on_on_off_off(NoteOffMatching::All),
+            (true, vec![10, 20], false, vec![10, 20])
+        );
+    }
+
+    #[test]
+    fn test_note_off_order_survives_removal() {
+        let mut buffer = VoiceBuffer::new(Default::default());
+        for vel in [10, 20, 30] {
+            buffer.push_voices(std::iter::once(held_voice(vel)));
+        }
+
+        // Removing the released first group moves the newest voice to the
+        // front, but the next note off still releases the oldest one
+        assert!(buffer.release_next_voice().is_some());
+        buffer.remove_ended_voices();
+        assert!(buffer.release_next_voice().is_some());
+        assert_eq!(releasing_velocities(&buffer), vec![20]);
+
+        // On, off, on, off with the damper held releases both when it lifts
+        let mut buffer = VoiceBuffer::new(ChannelInitOptions {
+            note_off_matching: NoteOffMatching::NewestFirst,
+            ..Default::default()
+        });
+        buffer.set_damper(true);
+        for vel in [10, 20] {
+            buffer.push_voices(std::iter::once(held_voice(vel)));
+            assert!(buffer.release_next_voice().is_none());
+        }
+        assert_eq!(releasing_velocities(&buffer), Vec::<u8>::new());
+        buffer.set_damper(false);
+        assert_eq!(releasing_velocities(&buffer), vec![10, 20]);
+    }
+
+    #[test]
+    fn test_keep_loudest_voices() {
+        for fade_out_killing in [false, true] {
+            let mut buffer = VoiceBuffer::new(ChannelInitOptions {
+                fade_out_killing,
+                ..Default::default()
+            });
+            buffer.set_max_voices(None);
+            for vel in [10, 40, 20, 30] {
+                buffer.push_voices(std::iter::once(held_voice(vel)));
+            }
+
+            buffer.keep_loudest_voices(2);
+            let mut kept: Vec<u8> = buffer
+                .voices
+                .iter()
+                .filter(|v| !v.is_killed())
+                .map(|v| v.velocity())
+                .collect();
+            kept.sort_unstable();
+            assert_eq!(kept, [30, 40]);
+        }
+    }
+
+    fn active_velocities(buffer: &VoiceBuffer) -> Vec<u8> {
+        let mut velocities: Vec<u8> = buffer
+            .voices
+            .iter()
+            .filter(|v| !v.is_killed())
+            .map(|v| v.velocity())
+            .collect();
+        velocities.sort_unstable();
+        velocities
+    }
+
+    #[test]
+    fn test_voice_stealing() {
+        let group = |vel: u8, count: usize| (0..count).map(move |_| held_voice(vel));
+
+        for fade_out_killing in [false, true] {
+            let mut buffer = VoiceBuffer::new(ChannelInitOptions {
+                fade_out_killing,
+                ..Default::default()
+            });
+            buffer.set_max_voices(Some(4));
+
+            // The whole group of the quietest voice is stolen
+            buffer.push_voices(group(100, 2));
+            buffer.push_voices(group(20, 2));
+            buffer.push_voices(group(60, 2));
+            assert_eq!(active_velocities(&buffer), [60, 60, 100, 100]);
+
+            // The group being spawned is never stolen, even if it's the
+            // quietest one or has more voices than the limit
+            buffer.push_voices(group(10, 3));
+            assert_eq!(active_velocities(&buffer), [10, 10, 10]);
+            buffer.push_voices(group(5, 5));
+            assert_eq!(active_velocities(&buffer), [5, 5, 5, 5, 5]);
+
+            // Voices that were killed already are only removed when they
+            // end, and aren't stolen again
+            let killed = buffer.voices.len() - 5;
+            assert_eq!(killed, if fade_out_killing { 9 } else { 0 });
+        }
+    }
+
+    #[test]
+    fn test_note_polyphony() {
+        let grouped_voice = |vel: u8, group: u32| -> Box<dyn Voice> {
+            let polyphony = VoicePolyphony {
+                group,
+                polyphony: None,
+                note_polyphony: Some(2),
+            };
+            Box::new(PolyphonyVoice::new(held_voice(vel), polyphony))
+        };
+
+        for fade_out_killing in [false, true] {
+            let mut buffer = VoiceBuffer::new(ChannelInitOptions {
+                fade_out_killing,
+                ..Default::default()
+            });
+            buffer.set_max_voices(None);
+            for vel in [10, 20, 30] {
+                buffer.push_voices(std::iter::once(grouped_voice(vel, 1)));
+            }
+            // Voices of other groups don't count towards the limit
+            buffer.push_voices(std::iter::once(grouped_voice(40, 2)));
+            buffer.push_voices(std::iter::once(held_voice(50)));
+
+            let mut kept: Vec<u8> = buffer
+                .voices
+                .iter()
+                .filter(|v| !v.is_killed())
+                .map(|v| v.velocity())
+                .collect();
+            kept.sort_unstable();
+            assert_eq!(kept, [20, 30, 40, 50]);
+        }
+    }
+}
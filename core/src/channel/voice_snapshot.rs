@@ -0,0 +1,190 @@
+use std::{
+    cell::UnsafeCell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use crate::voice::{EnvelopeStage, Voice, VoiceGeneratorState};
+
+/// The stage of a voice's volume envelope, or whether it is being killed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VoiceStage {
+    Delay,
+    Attack,
+    Hold,
+    Decay,
+    Sustain,
+    Release,
+
+    /// The voice is fading out quickly after being killed, e.g. by the
+    /// voice limit or an all notes killed event.
+    Killed,
+}
+
+/// The state of an active voice of a channel, for debugging and
+/// visualization. See `ChannelConfigEvent::SetVoiceSnapshots`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VoiceSnapshot {
+    /// The key the voice is playing on.
+    pub key: u8,
+
+    /// The velocity the voice was started with.
+    pub velocity: u8,
+
+    /// The ID of the group of voices spawned by the same note. Unique per
+    /// key.
+    pub group_id: usize,
+
+    /// The stage of the voice's volume envelope. `None` for voices without
+    /// an envelope.
+    pub stage: Option<VoiceStage>,
+
+    /// The current value of the voice's volume envelope, from 0 to 1. Falls
+    /// back to the voice's velocity for voices without an envelope.
+    pub amplitude: f32,
+
+    /// The current playback position in the voice's sample, in sample
+    /// frames. `None` for voices that don't play a sample.
+    pub sample_position: Option<f64>,
+}
+
+impl VoiceSnapshot {
+    pub(super) fn new(key: u8, group_id: usize, voice: &dyn Voice) -> Self {
+        let mut state = VoiceGeneratorState::default();
+        voice.inspect(&mut state);
+
+        let stage = if voice.is_killed() {
+            Some(VoiceStage::Killed)
+        } else {
+            state.envelope_stage.map(|stage| match stage {
+                EnvelopeStage::Delay => VoiceStage::Delay,
+                EnvelopeStage::Attack => VoiceStage::Attack,
+                EnvelopeStage::Hold => VoiceStage::Hold,
+                EnvelopeStage::Decay => VoiceStage::Decay,
+                EnvelopeStage::Sustain => VoiceStage::Sustain,
+                EnvelopeStage::Release | EnvelopeStage::Finished => VoiceStage::Release,
+            })
+        };
+
+        Self {
+            key,
+            velocity: voice.velocity(),
+            group_id,
+            stage,
+            amplitude: state
+                .envelope_amplitude
+                .unwrap_or_else(|| voice.amplitude()),
+            sample_position: state.sample_position,
+        }
+    }
+}
+
+/// Set on the middle slot index when it holds a snapshot no reader took yet
+const NEW_SNAPSHOT: usize = 0b100;
+
+/// Hands snapshots of the channel's state, e.g. of its voices, from the
+/// render thread to the readers without the render thread ever waiting for
+/// a reader.
+///
+/// The channel fills the back slot and swaps it with the middle slot, and
+/// the readers swap the middle slot with the front slot when it holds a new
+/// snapshot. Each slot is only ever accessed by whoever holds its index.
+#[derive(Debug)]
+pub(super) struct SnapshotBuffer<T> {
+    slots: [UnsafeCell<T>; 3],
+
+    /// The index of the slot the channel writes to
+    back: AtomicUsize,
+
+    /// The index of the slot holding the latest published snapshot
+    middle: AtomicUsize,
+
+    /// The index of the slot the readers copy from. Only locked by readers.
+    front: Mutex<usize>,
+}
+
+// SAFETY: the slots are only accessed through the index that is owned by
+// the channel, the readers or neither of them, see the struct documentation
+unsafe impl<T: Send> Sync for SnapshotBuffer<T> {}
+
+impl<T: Clone> SnapshotBuffer<T> {
+    /// Fills the back slot and publishes it. The slot still holds an older
+    /// snapshot, which `fill` has to overwrite or clear. Must only be called
+    /// by the channel that owns the statistics.
+    pub fn publish(&self, fill: impl FnOnce(&mut T)) {
+        let back = self.back.load(Ordering::Relaxed);
+
+        // SAFETY: only the channel accesses the back slot
+        let slot = unsafe { &mut *self.slots[back].get() };
+        fill(slot);
+
+        let previous = self.middle.swap(back | NEW_SNAPSHOT, Ordering::AcqRel);
+        self.back.store(previous & !NEW_SNAPSHOT, Ordering::Relaxed);
+    }
+
+    /// Returns a copy of the latest published snapshot.
+    pub fn latest(&self) -> T {
+        let mut front = self.front.lock().unwrap();
+        if self.middle.load(Ordering::Relaxed) & NEW_SNAPSHOT != 0 {
+            let previous = self.middle.swap(*front, Ordering::AcqRel);
+            *front = previous & !NEW_SNAPSHOT;
+        }
+
+        // SAFETY: only the reader holding the lock accesses the front slot
+        unsafe { (*self.slots[*front].get()).clone() }
+    }
+}
+
+impl<T: Default> Default for SnapshotBuffer<T> {
+    fn default() -> Self {
+        Self {
+            slots: Default::default(),
+            back: AtomicUsize::new(0),
+            middle: AtomicUsize::new(1),
+            front: Mutex::new(2),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(key: u8) -> VoiceSnapshot {
+        VoiceSnapshot {
+            key,
+            velocity: 100,
+            group_id: 0,
+            stage: Some(VoiceStage::Sustain),
+            amplitude: 1.0,
+            sample_position: None,
+        }
+    }
+
+    #[test]
+    fn test_snapshot_buffer() {
+        let buffer = SnapshotBuffer::<Vec<VoiceSnapshot>>::default();
+        assert!(buffer.latest().is_empty());
+
+        let publish = |key| {
+            buffer.publish(|s| {
+                s.clear();
+                s.push(snapshot(key));
+            })
+        };
+        publish(1);
+        publish(2);
+        assert_eq!(buffer.latest(), vec![snapshot(2)]);
+
+        // Reading again without a new snapshot returns the same one
+        assert_eq!(buffer.latest(), vec![snapshot(2)]);
+
+        // The slots are reused without keeping their old voices
+        for key in 3..10 {
+            publish(key);
+        }
+        assert_eq!(buffer.latest(), vec![snapshot(9)]);
+    }
+}
@@ -1,17 +1,51 @@
+use std::sync::Arc;
+
 use crate::soundfont::VoiceSpawner;
 
-use crate::voice::{Voice, VoiceControlData};
+use crate::voice::{PolyphonyVoice, Voice, VoiceControlData};
+
+/// The release spawners of a key and velocity. They are shared with the
+/// voice groups of the notes started while they were current, so a note
+/// releases with the program it started with.
+pub type ReleaseSpawners = Arc<[Box<dyn VoiceSpawner>]>;
 
+/// The spawners of every key and velocity, resolved when the soundfonts or
+/// the program change, so spawning a note only visits the regions that can
+/// play it.
 pub struct VoiceSpawnerMatrix {
     voice_spawners_attack: Vec<Vec<Box<dyn VoiceSpawner>>>,
-    voice_spawners_release: Vec<Vec<Box<dyn VoiceSpawner>>>,
+    voice_spawners_release: Vec<Option<ReleaseSpawners>>,
+
+    /// Whether any spawner limits the polyphony of a group on all keys, see
+    /// `VoicePolyphony::polyphony`
+    has_group_polyphony: bool,
+
+    /// The keys without regions of each velocity, as bits, see
+    /// `UnmatchedNotePolicy`
+    unmatched: Vec<u128>,
+}
+
+/// Returns true if any of the spawners limits the polyphony of a group on
+/// all keys.
+fn has_group_polyphony(spawners: &[Box<dyn VoiceSpawner>]) -> bool {
+    spawners
+        .iter()
+        .any(|spawner| spawner.polyphony().is_some_and(|p| p.polyphony.is_some()))
 }
 
-fn voice_iter_from_vec<'a>(
+pub fn voice_iter_from_vec<'a>(
     vec: &'a [Box<dyn VoiceSpawner>],
     control: &'a VoiceControlData,
 ) -> impl Iterator<Item = Box<dyn Voice>> + 'a {
-    vec.iter().map(move |voice| voice.spawn_voice(control))
+    vec.iter()
+        .filter(move |spawner| spawner.matches(control))
+        .map(move |spawner| {
+            let voice = spawner.spawn_voice(control);
+            match spawner.polyphony() {
+                Some(polyphony) => Box::new(PolyphonyVoice::new(voice, polyphony)),
+                None => voice,
+            }
+        })
 }
 
 impl VoiceSpawnerMatrix {
@@ -21,7 +55,7 @@ impl VoiceSpawnerMatrix {
 
         for _ in 0..(128 * 128) {
             voice_spawners_attack.push(Vec::new());
-            voice_spawners_release.push(Vec::new());
+            voice_spawners_release.push(None);
         }
 
         voice_spawners_attack.shrink_to_fit();
@@ -30,9 +64,18 @@ impl VoiceSpawnerMatrix {
         VoiceSpawnerMatrix {
             voice_spawners_attack,
             voice_spawners_release,
+            has_group_polyphony: false,
+            unmatched: vec![0; 128],
         }
     }
 
+    /// Returns true if any spawner limits the polyphony of a group on all
+    /// keys, so the voices of all keys need to be counted after spawning.
+    #[inline(always)]
+    pub fn has_group_polyphony(&self) -> bool {
+        self.has_group_polyphony
+    }
+
     #[inline(always)]
     fn get_spawners_index_at_attack(&self, key: u8, vel: u8) -> usize {
         key as usize + vel as usize * 128
@@ -48,40 +91,45 @@ impl VoiceSpawnerMatrix {
         &self.voice_spawners_attack[self.get_spawners_index_at_attack(key, vel)]
     }
 
+    /// Returns the release spawners of the key and velocity, or `None` if
+    /// there are none.
     #[inline(always)]
-    fn get_release_spawners_vec_at(&self, key: u8, vel: u8) -> &Vec<Box<dyn VoiceSpawner>> {
-        &self.voice_spawners_release[self.get_spawners_index_at_release(key, vel)]
+    pub fn release_spawners_at(&self, key: u8, vel: u8) -> Option<&ReleaseSpawners> {
+        self.voice_spawners_release[self.get_spawners_index_at_release(key, vel)].as_ref()
     }
 
+    /// Returns true if no soundfont of the channel has regions for the key
+    /// and velocity, even if the fallback soundfont does.
     #[inline(always)]
-    pub fn spawn_voices_attack<'a>(
-        &'a self,
-        control: &'a VoiceControlData,
-        key: u8,
-        vel: u8,
-    ) -> impl Iterator<Item = Box<dyn Voice>> + 'a {
-        voice_iter_from_vec(self.get_attack_spawners_vec_at(key, vel), control)
+    pub fn is_unmatched(&self, key: u8, vel: u8) -> bool {
+        self.unmatched[vel as usize] & (1 << key) != 0
+    }
+
+    pub fn set_unmatched(&mut self, key: u8, vel: u8) {
+        self.unmatched[vel as usize] |= 1 << key;
     }
 
     #[inline(always)]
-    pub fn spawn_voices_release<'a>(
+    pub fn spawn_voices_attack<'a>(
         &'a self,
         control: &'a VoiceControlData,
         key: u8,
         vel: u8,
     ) -> impl Iterator<Item = Box<dyn Voice>> + 'a {
-        voice_iter_from_vec(self.get_release_spawners_vec_at(key, vel), control)
+        voice_iter_from_vec(self.get_attack_spawners_vec_at(key, vel), control)
     }
 
     #[inline(always)]
     pub fn set_spawners_attack(&mut self, key: u8, vel: u8, spawners: Vec<Box<dyn VoiceSpawner>>) {
         let index = self.get_spawners_index_at_attack(key, vel);
+        self.has_group_polyphony |= has_group_polyphony(&spawners);
         self.voice_spawners_attack[index] = spawners;
     }
 
     #[inline(always)]
     pub fn set_spawners_release(&mut self, key: u8, vel: u8, spawners: Vec<Box<dyn VoiceSpawner>>) {
         let index = self.get_spawners_index_at_release(key, vel);
-        self.voice_spawners_release[index] = spawners;
+        self.has_group_polyphony |= has_group_polyphony(&spawners);
+        self.voice_spawners_release[index] = (!spawners.is_empty()).then(|| spawners.into());
     }
 }
@@ -0,0 +1,430 @@
+use std::{collections::BTreeMap, mem};
+
+use crate::channel::{lsb_controller, ChannelAudioEvent, ChannelEvent, ControlEvent};
+
+use super::{ump_events, SynthEvent};
+
+/// A data entry value sent to a registered parameter (RPN).
+#[derive(Clone, Copy, Debug)]
+struct ParameterData {
+    msb: u8,
+    lsb: u8,
+    data_msb: Option<u8>,
+    data_lsb: Option<u8>,
+}
+
+/// The chased state of a single channel, relative to its default state.
+#[derive(Clone, Debug, Default)]
+struct ChannelChaseState {
+    bank: Option<u8>,
+    program: Option<u8>,
+    controllers: BTreeMap<u8, u8>,
+    selected_msb: Option<u8>,
+    selected_lsb: Option<u8>,
+    /// Whether the data entries go to an NRPN, which isn't chased
+    nrpn_selected: bool,
+    parameters: Vec<ParameterData>,
+    controls: Vec<ControlEvent>,
+}
+
+impl ChannelChaseState {
+    fn push(&mut self, event: &ChannelAudioEvent) {
+        match *event {
+            ChannelAudioEvent::ResetControl | ChannelAudioEvent::ResetChannel => {
+                self.reset_control()
+            }
+            ChannelAudioEvent::SystemReset => *self = Default::default(),
+            ChannelAudioEvent::ProgramChange(preset) => self.program = Some(preset),
+            ChannelAudioEvent::Control(control) => self.push_control(control),
+            ChannelAudioEvent::NoteOn { .. }
+            | ChannelAudioEvent::NoteOnDetuned { .. }
+            | ChannelAudioEvent::NoteOff { .. }
+            | ChannelAudioEvent::NoteKill { .. }
+            | ChannelAudioEvent::NotePitchBend { .. }
+            | ChannelAudioEvent::AllNotesOff
+            | ChannelAudioEvent::AllNotesKilled => {}
+        }
+    }
+
+    fn push_control(&mut self, control: ControlEvent) {
+        // Typed control changes are chased like the raw ones
+        let control = match control.to_raw() {
+            Some((controller, value)) => ControlEvent::Raw(controller, value),
+            None => control,
+        };
+
+        match control {
+            ControlEvent::Raw(controller, value) => match controller {
+                0x00 => self.bank = Some(value),
+                0x64 => {
+                    self.selected_lsb = Some(value);
+                    self.nrpn_selected = false;
+                }
+                0x65 => {
+                    self.selected_msb = Some(value);
+                    self.nrpn_selected = false;
+                }
+                0x62 | 0x63 => self.nrpn_selected = true,
+                0x06 | 0x26 if self.nrpn_selected => {}
+                0x06 | 0x26 => {
+                    let (Some(msb), Some(lsb)) = (self.selected_msb, self.selected_lsb) else {
+                        // No parameter is selected, so the channel ignores it
+                        return;
+                    };
+
+                    if msb == 0 {
+                        // The processed equivalent is overridden
+                        let overridden = match lsb {
+                            0 => Some(ControlEvent::PitchBendSensitivity(0.0)),
+                            1 => Some(ControlEvent::FineTune(0.0)),
+                            2 => Some(ControlEvent::CoarseTune(0.0)),
+                            _ => None,
+                        };
+                        if let Some(overridden) = overridden {
+                            self.remove_control(&overridden);
+                        }
+                    }
+
+                    let index = match self
+                        .parameters
+                        .iter()
+                        .position(|p| p.msb == msb && p.lsb == lsb)
+                    {
+                        Some(index) => index,
+                        None => {
+                            self.parameters.push(ParameterData {
+                                msb,
+                                lsb,
+                                data_msb: None,
+                                data_lsb: None,
+                            });
+                            self.parameters.len() - 1
+                        }
+                    };
+
+                    let parameter = &mut self.parameters[index];
+                    if controller == 0x06 {
+                        parameter.data_msb = Some(value);
+                    } else {
+                        parameter.data_lsb = Some(value);
+                    }
+                }
+                0x79 => {
+                    if value == 0 {
+                        self.reset_control();
+                    }
+                }
+                // Channel mode messages only affect playing notes
+                0x78 | 0x7A..=0x7F => {}
+                _ => {
+                    self.controllers.insert(controller, value);
+                    if let Some(lsb) = lsb_controller(controller) {
+                        self.controllers.remove(&lsb);
+                    }
+                }
+            },
+            control => {
+                let rpn = match control {
+                    ControlEvent::PitchBendSensitivity(_) => Some(0),
+                    ControlEvent::FineTune(_) => Some(1),
+                    ControlEvent::CoarseTune(_) => Some(2),
+                    _ => None,
+                };
+                if let Some(lsb) = rpn {
+                    self.parameters.retain(|p| p.msb != 0 || p.lsb != lsb);
+                }
+
+                // Only the latest of each kind matters, but their order does
+                self.remove_control(&control);
+                self.controls.push(control);
+            }
+        }
+    }
+
+    fn remove_control(&mut self, control: &ControlEvent) {
+        self.controls
+            .retain(|c| mem::discriminant(c) != mem::discriminant(control));
+    }
+
+    fn reset_control(&mut self) {
+        // The bank and program are kept, like in `VoiceChannel`
+        *self = ChannelChaseState {
+            bank: self.bank,
+            program: self.program,
+            ..Default::default()
+        };
+    }
+
+    fn events(&self) -> Vec<ChannelAudioEvent> {
+        let raw =
+            |controller, value| ChannelAudioEvent::Control(ControlEvent::Raw(controller, value));
+        let mut events = Vec::new();
+
+        if let Some(bank) = self.bank {
+            events.push(raw(0x00, bank));
+        }
+        if let Some(program) = self.program {
+            events.push(ChannelAudioEvent::ProgramChange(program));
+        }
+
+        for (&controller, &value) in self.controllers.iter() {
+            events.push(raw(controller, value));
+        }
+
+        for parameter in self.parameters.iter() {
+            events.push(raw(0x65, parameter.msb));
+            events.push(raw(0x64, parameter.lsb));
+            if let Some(value) = parameter.data_msb {
+                events.push(raw(0x06, value));
+            }
+            if let Some(value) = parameter.data_lsb {
+                events.push(raw(0x26, value));
+            }
+        }
+
+        // Restore the parameter selection, so later data entries go to the right place
+        let last_selected = self
+            .parameters
+            .last()
+            .map(|p| (Some(p.msb), Some(p.lsb)))
+            .unwrap_or_default();
+        if (self.selected_msb, self.selected_lsb) != last_selected {
+            if let Some(msb) = self.selected_msb {
+                events.push(raw(0x65, msb));
+            }
+            if let Some(lsb) = self.selected_lsb {
+                events.push(raw(0x64, lsb));
+            }
+        }
+
+        events.extend(
+            self.controls
+                .iter()
+                .copied()
+                .map(ChannelAudioEvent::Control),
+        );
+
+        events
+    }
+}
+
+/// Keeps track of the controller state of each channel, so that it can be
+/// restored when seeking in a MIDI sequence without replaying its notes.
+///
+/// Program changes, bank selects, controllers, registered parameters and
+/// pitch bend are tracked. Note events and config events are ignored. The
+/// events returned by `events` assume that all the channels are in their
+/// default state, e.g. after a `SystemReset`, and bring them to the state
+/// they would be in if every pushed event had been sent.
+#[derive(Clone, Debug)]
+pub struct EventChaser {
+    channels: Vec<ChannelChaseState>,
+}
+
+impl EventChaser {
+    /// Creates a new chaser for a synthesizer with the given number of
+    /// channels. Events for channels outside this range are ignored.
+    pub fn new(channel_count: u32) -> Self {
+        Self {
+            channels: vec![Default::default(); channel_count as usize],
+        }
+    }
+
+    /// Returns the number of channels tracked by the chaser.
+    pub fn channel_count(&self) -> u32 {
+        self.channels.len() as u32
+    }
+
+    /// Updates the tracked state with the given event.
+    pub fn push(&mut self, event: &SynthEvent) {
+        match event {
+            SynthEvent::Channel(channel, ChannelEvent::Audio(e)) => {
+                if let Some(state) = self.channels.get_mut(*channel as usize) {
+                    state.push(e);
+                }
+            }
+            SynthEvent::AllChannels(ChannelEvent::Audio(e)) => {
+                for state in self.channels.iter_mut() {
+                    state.push(e);
+                }
+            }
+            SynthEvent::Ump(packet) => {
+                for (channel, e) in ump_events(*packet) {
+                    if let Some(state) = self.channels.get_mut(channel as usize) {
+                        state.push(&e);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Forgets all the tracked state.
+    pub fn clear(&mut self) {
+        for state in self.channels.iter_mut() {
+            *state = Default::default();
+        }
+    }
+
+    /// Returns the minimal set of events that brings the channels from
+    /// their default state to the tracked state, in the order they should
+    /// be sent.
+    pub fn events(&self) -> Vec<SynthEvent> {
+        let mut events = Vec::new();
+        for (channel, state) in self.channels.iter().enumerate() {
+            events.extend(
+                state
+                    .events()
+                    .into_iter()
+                    .map(|e| SynthEvent::Channel(channel as u32, ChannelEvent::Audio(e))),
+            );
+        }
+        events
+    }
+}
+
+/// Computes the events needed to restore the controller state of the
+/// channels at the given position of an ordered sequence of timed events,
+/// without replaying any notes. Events at exactly the target time are not
+/// included, as they would normally be played after seeking.
+///
+/// See the `EventChaser` documentation for more information.
+pub fn chase_events<'a, T: PartialOrd + 'a>(
+    events: impl IntoIterator<Item = &'a (T, SynthEvent)>,
+    target: &T,
+    channel_count: u32,
+) -> Vec<SynthEvent> {
+    let mut chaser = EventChaser::new(channel_count);
+    for (time, event) in events {
+        if time >= target {
+            break;
+        }
+        chaser.push(event);
+    }
+    chaser.events()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cc(channel: u32, controller: u8, value: u8) -> SynthEvent {
+        SynthEvent::Channel(
+            channel,
+            ChannelEvent::Audio(ChannelAudioEvent::Control(ControlEvent::Raw(
+                controller, value,
+            ))),
+        )
+    }
+
+    fn audio(channel: u32, event: ChannelAudioEvent) -> SynthEvent {
+        SynthEvent::Channel(channel, ChannelEvent::Audio(event))
+    }
+
+    fn controls(events: &[SynthEvent], channel: u32) -> Vec<ChannelAudioEvent> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                SynthEvent::Channel(c, ChannelEvent::Audio(e)) if *c == channel => Some(*e),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn raw(controller: u8, value: u8) -> ChannelAudioEvent {
+        ChannelAudioEvent::Control(ControlEvent::Raw(controller, value))
+    }
+
+    #[test]
+    fn test_overlapping_cc_streams() {
+        let mut events = Vec::new();
+        for i in 0..100u8 {
+            let time = i as u32 * 10;
+            events.push((time, cc(0, 0x07, i)));
+            events.push((time, cc(1, 0x07, 127 - i)));
+            events.push((time + 5, cc(1, 0x0A, i)));
+            events.push((
+                time + 5,
+                audio(0, ChannelAudioEvent::NoteOn { key: i, vel: 100 }),
+            ));
+        }
+        events.push((200, audio(1, ChannelAudioEvent::ProgramChange(42))));
+        events.sort_by_key(|(time, _)| *time);
+
+        let chased = chase_events(&events, &500, 16);
+
+        // Events at 500 itself are excluded
+        assert_eq!(controls(&chased, 0), vec![raw(0x07, 49)]);
+        assert_eq!(
+            controls(&chased, 1),
+            vec![
+                ChannelAudioEvent::ProgramChange(42),
+                raw(0x07, 127 - 49),
+                raw(0x0A, 49)
+            ]
+        );
+        assert!(controls(&chased, 2).is_empty());
+    }
+
+    #[test]
+    fn test_resets_and_all_channels() {
+        let events = vec![
+            (0, audio(0, ChannelAudioEvent::ProgramChange(5))),
+            (1, cc(0, 0x0B, 80)),
+            (2, cc(1, 0x0B, 90)),
+            (
+                3,
+                SynthEvent::AllChannels(ChannelEvent::Audio(ChannelAudioEvent::Control(
+                    ControlEvent::Raw(0x07, 100),
+                ))),
+            ),
+            (4, audio(0, ChannelAudioEvent::ResetControl)),
+            (5, cc(0, 0x40, 127)),
+            (6, audio(1, ChannelAudioEvent::SystemReset)),
+            (7, cc(1, 0x0A, 10)),
+        ];
+
+        let chased = chase_events(&events, &8, 2);
+
+        assert_eq!(
+            controls(&chased, 0),
+            vec![ChannelAudioEvent::ProgramChange(5), raw(0x40, 127)]
+        );
+        assert_eq!(controls(&chased, 1), vec![raw(0x0A, 10)]);
+    }
+
+    #[test]
+    fn test_registered_parameters_and_pitch() {
+        let mut chaser = EventChaser::new(1);
+        // Data entry without a selected parameter is ignored
+        chaser.push(&cc(0, 0x06, 5));
+        chaser.push(&cc(0, 0x65, 0));
+        chaser.push(&cc(0, 0x64, 0));
+        chaser.push(&cc(0, 0x06, 12));
+        chaser.push(&cc(0, 0x06, 24));
+        chaser.push(&audio(
+            0,
+            ChannelAudioEvent::Control(ControlEvent::PitchBendValue(0.5)),
+        ));
+        chaser.push(&audio(
+            0,
+            ChannelAudioEvent::Control(ControlEvent::PitchBendValue(-0.25)),
+        ));
+        chaser.push(&cc(0, 0x64, 2));
+
+        assert_eq!(
+            controls(&chaser.events(), 0),
+            vec![
+                raw(0x65, 0),
+                raw(0x64, 0),
+                raw(0x06, 24),
+                raw(0x65, 0),
+                raw(0x64, 2),
+                ChannelAudioEvent::Control(ControlEvent::PitchBendValue(-0.25)),
+            ]
+        );
+
+        chaser.clear();
+        assert!(chaser.events().is_empty());
+    }
+}
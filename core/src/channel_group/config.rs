@@ -10,6 +10,32 @@ pub enum SynthFormat {
 
     /// Creates a custom number of channels with the default settings.
     Custom { channels: u32 },
+
+    /// Standard MIDI format for files using multiple MIDI ports, with 16 channels
+    /// per port. Channel `port * 16 + c` is channel `c` of the given port, and
+    /// channel 10 of every port will be used for percussion.
+    MultiPort { ports: u32 },
+}
+
+impl SynthFormat {
+    /// Returns the number of channels the format creates.
+    pub fn channel_count(&self) -> u32 {
+        match *self {
+            SynthFormat::Midi => 16,
+            SynthFormat::Custom { channels } => channels,
+            SynthFormat::MultiPort { ports } => ports * 16,
+        }
+    }
+
+    /// Returns true if the given channel is used for percussion.
+    pub fn is_percussion(&self, channel: u32) -> bool {
+        match *self {
+            SynthFormat::Midi | SynthFormat::MultiPort { .. } => {
+                channel < self.channel_count() && channel % 16 == 9
+            }
+            SynthFormat::Custom { .. } => false,
+        }
+    }
 }
 
 /// Defines the multithreading options for each task that supports it.
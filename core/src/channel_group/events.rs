@@ -1,4 +1,10 @@
-use crate::channel::ChannelEvent;
+use std::fmt;
+
+use crate::{channel::ChannelEvent, effects::EffectsChain};
+
+/// The ID of a channel of a synthesizer, which is its index in the events
+/// sent to single channels. See `ChannelGroup::add_channel`.
+pub type ChannelId = u32;
 
 /// Wrapper enum for various events to be sent to a MIDI synthesizer.
 #[derive(Clone, Debug)]
@@ -6,9 +12,46 @@ use crate::channel::ChannelEvent;
 pub enum SynthEvent {
     /// A channel event to be sent to the specified channel.
     /// See `ChannelEvent` documentation for more information.
-    Channel(u32, ChannelEvent),
+    Channel(ChannelId, ChannelEvent),
 
     /// A channel event to be sent to all available channels.
     /// See `ChannelAudioEvent` documentation for more information.
     AllChannels(ChannelEvent),
+
+    /// Replaces the whole master effects chain at once, e.g. to reorder or
+    /// remove its elements. See the `EffectsChain` documentation for more
+    /// information.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    SetMasterEffects(EffectsChain),
+
+    /// Routes the audio of a channel to an output pair of the audio device,
+    /// `0` being outputs 1/2, `1` outputs 3/4 and so on. Only used by the
+    /// realtime synthesizer with routed output, and ignored otherwise.
+    SetOutputPair(ChannelId, u16),
+
+    /// Sets the tempo of the synthesizer in beats per minute, e.g. from the
+    /// clock of a DAW. The effects synced to its `TempoClock` follow the
+    /// new tempo from the next rendered buffer on.
+    SetTempo(f32),
+
+    /// A Universal MIDI Packet (UMP), e.g. a MIDI 2.0 channel voice message.
+    /// Packets shorter than 128 bits only use their first words. The packet
+    /// is translated to the channel events it stands for, see `ump_events`.
+    Ump([u32; 4]),
+}
+
+/// Formats the event with its channel, e.g. "ch 3 NoteOn C#4 vel 96".
+impl fmt::Display for SynthEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SynthEvent::Channel(channel, event) => write!(f, "ch {channel} {event}"),
+            SynthEvent::AllChannels(event) => write!(f, "all {event}"),
+            SynthEvent::SetMasterEffects(_) => f.write_str("SetMasterEffects"),
+            SynthEvent::SetOutputPair(channel, pair) => {
+                write!(f, "ch {channel} SetOutputPair {pair}")
+            }
+            SynthEvent::SetTempo(bpm) => write!(f, "SetTempo {bpm}"),
+            SynthEvent::Ump(packet) => write!(f, "Ump {:08X}", packet[0]),
+        }
+    }
 }
@@ -0,0 +1,98 @@
+use super::{ChannelGroup, ChannelId};
+use crate::{channel::VoiceChannel, util::fast_zero_fill, AudioPipe};
+
+/// Renders one channel of a `ChannelGroup` into the channel's own buffer.
+/// Returned by `ChannelGroup::render_jobs`.
+///
+/// Jobs are independent of each other, so the host can run them on any
+/// threads and in any order. The only exception are the channels sending to
+/// a sidechain, whose jobs have to finish before the others start so the
+/// compressors reading it get the audio of the same buffer, see
+/// `has_sidechain_send`.
+pub struct RenderJob<'a> {
+    id: ChannelId,
+    channel: &'a mut VoiceChannel,
+    samples: &'a mut Vec<f32>,
+    len: usize,
+}
+
+impl RenderJob<'_> {
+    /// Returns the ID of the channel the job renders.
+    pub fn channel(&self) -> ChannelId {
+        self.id
+    }
+
+    /// Returns the active voice count of the channel, e.g. to schedule the
+    /// busiest channels first.
+    pub fn voice_count(&self) -> u64 {
+        self.channel.get_channel_stats().voice_count()
+    }
+
+    /// Returns whether the channel sends its audio to a sidechain, in which
+    /// case the job has to run before the jobs of the channels without one.
+    /// See `ChannelConfigEvent::SetSidechainSend`.
+    pub fn has_sidechain_send(&self) -> bool {
+        self.channel.has_sidechain_send()
+    }
+
+    /// Renders the channel.
+    pub fn run(self) {
+        #[cfg(feature = "tracing")]
+        let _span = crate::trace::enter_channel(self.id);
+        fast_zero_fill(self.samples, self.len);
+        self.channel.read_samples(self.samples.as_mut_slice());
+    }
+}
+
+impl ChannelGroup {
+    /// Starts rendering the given number of samples with the host's own
+    /// threads, instead of the thread pools of the group. Returns a job for
+    /// each channel, which the host runs, e.g. on the job system of a game
+    /// engine. Once the jobs ran, `finalize_mix` mixes the channels into the
+    /// output buffer.
+    ///
+    /// This is an alternative to reading samples with the `AudioPipe` trait,
+    /// and both can be used on the same group. The pending events are
+    /// applied before the jobs are returned. Channels whose job isn't run are
+    /// silent and don't advance for this buffer.
+    ///
+    /// The channels still split their keys between threads if the key
+    /// parallelism of the group is enabled, see `ParallelismOptions`.
+    ///
+    /// The length must be a multiple of the channel count of the audio
+    /// stream, like with `AudioPipe::read_samples`.
+    pub fn render_jobs(&mut self, len: usize) -> impl Iterator<Item = RenderJob<'_>> {
+        assert!(len.is_multiple_of(self.audio_params.channels.count() as usize));
+        self.flush_events();
+
+        // Channels whose job isn't run aren't mixed
+        for samples in self.sample_cache_vecs.iter_mut() {
+            samples.clear();
+        }
+
+        self.channels
+            .iter_mut()
+            .zip(self.sample_cache_vecs.iter_mut())
+            .enumerate()
+            .filter_map(move |(id, (channel, samples))| {
+                Some(RenderJob {
+                    id: id as ChannelId,
+                    channel: channel.as_mut()?,
+                    samples,
+                    len,
+                })
+            })
+    }
+
+    /// Mixes the channels rendered by the jobs of `render_jobs` into the
+    /// buffer, and applies the master effects. The buffer must have the
+    /// length passed to `render_jobs`.
+    pub fn finalize_mix(&mut self, buffer: &mut [f32]) {
+        let _render = self.render_check.enter("ChannelGroup");
+        assert!(self
+            .sample_cache_vecs
+            .iter()
+            .all(|samples| samples.is_empty() || samples.len() == buffer.len()));
+        self.mix_to(buffer);
+    }
+}
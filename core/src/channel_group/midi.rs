@@ -0,0 +1,351 @@
+use crate::channel::{lsb_controller, ChannelAudioEvent, ControlEvent};
+
+/// A channel voice message with the resolution of MIDI 2.0. MIDI 1.0
+/// messages are scaled up to it by shifting their values to the top bits,
+/// so both protocols are translated to channel events by the same code and
+/// equivalent messages give the same events.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum VoiceMessage {
+    NoteOff {
+        key: u8,
+    },
+    NoteOn {
+        key: u8,
+        vel: u16,
+    },
+    NotePitchBend {
+        key: u8,
+        value: u32,
+    },
+    ControlChange {
+        controller: u8,
+        value: u32,
+    },
+    Parameter {
+        registered: bool,
+        msb: u8,
+        lsb: u8,
+        value: u32,
+    },
+    ProgramChange {
+        program: u8,
+        bank: Option<(u8, u8)>,
+    },
+    ChannelPressure(u32),
+    PitchBend(u32),
+}
+
+impl VoiceMessage {
+    /// Parses a MIDI 1.0 channel voice message from its status and data
+    /// bytes.
+    fn from_midi1(status: u8, data1: u8, data2: u8) -> Option<Self> {
+        let (data1, data2) = (data1 & 0x7F, data2 & 0x7F);
+        let message = match status >> 4 {
+            0x8 => VoiceMessage::NoteOff { key: data1 },
+            0x9 => VoiceMessage::NoteOn {
+                key: data1,
+                vel: (data2 as u16) << 9,
+            },
+            0xB => VoiceMessage::ControlChange {
+                controller: data1,
+                value: (data2 as u32) << 25,
+            },
+            0xC => VoiceMessage::ProgramChange {
+                program: data1,
+                bank: None,
+            },
+            0xD => VoiceMessage::ChannelPressure((data1 as u32) << 25),
+            0xE => VoiceMessage::PitchBend((((data2 as u32) << 7) | data1 as u32) << 18),
+            _ => return None,
+        };
+        Some(message)
+    }
+
+    /// Parses a MIDI 2.0 channel voice message from the two words of its
+    /// packet.
+    fn from_midi2(word0: u32, word1: u32) -> Option<Self> {
+        let index = (word0 >> 8) as u8 & 0x7F;
+        let byte4 = word0 as u8;
+        let bank = (byte4 & 1 == 1).then_some(((word1 >> 8) as u8 & 0x7F, word1 as u8 & 0x7F));
+        let message = match (word0 >> 20) & 0xF {
+            0x8 => VoiceMessage::NoteOff { key: index },
+            // A MIDI 2.0 note on with a velocity of 0 is still a note on, so
+            // it's played with the lowest MIDI 1.0 velocity
+            0x9 => VoiceMessage::NoteOn {
+                key: index,
+                vel: ((word1 >> 16) as u16).max(1 << 9),
+            },
+            0x6 => VoiceMessage::NotePitchBend {
+                key: index,
+                value: word1,
+            },
+            0xB => VoiceMessage::ControlChange {
+                controller: index,
+                value: word1,
+            },
+            status @ (0x2 | 0x3) => VoiceMessage::Parameter {
+                registered: status == 0x2,
+                msb: index,
+                lsb: byte4 & 0x7F,
+                value: word1,
+            },
+            0xC => VoiceMessage::ProgramChange {
+                program: (word1 >> 24) as u8 & 0x7F,
+                bank,
+            },
+            0xD => VoiceMessage::ChannelPressure(word1),
+            0xE => VoiceMessage::PitchBend(word1),
+            _ => return None,
+        };
+        Some(message)
+    }
+
+    /// Translates the message to channel events. Values are kept at the
+    /// highest resolution the events support: 14 bits for the controllers
+    /// with an LSB controller and the parameters, 7 bits for the velocity
+    /// and the other controllers, and the full resolution for the pitch
+    /// bends.
+    fn events(self) -> [Option<ChannelAudioEvent>; 4] {
+        // The center of the pitch bends
+        const CENTER: f64 = 0x8000_0000u32 as f64;
+
+        let raw = |controller, value| {
+            Some(ChannelAudioEvent::Control(ControlEvent::Raw(
+                controller, value,
+            )))
+        };
+        let msb = |value: u32| (value >> 25) as u8;
+        let lsb = |value: u32| (value >> 18) as u8 & 0x7F;
+        let bend = |value: u32| ((value as f64 - CENTER) / CENTER) as f32;
+
+        match self {
+            VoiceMessage::NoteOff { key } => {
+                [Some(ChannelAudioEvent::NoteOff { key }), None, None, None]
+            }
+            VoiceMessage::NoteOn { key, vel } => [
+                Some(ChannelAudioEvent::NoteOn {
+                    key,
+                    vel: (vel >> 9) as u8,
+                }),
+                None,
+                None,
+                None,
+            ],
+            VoiceMessage::NotePitchBend { key, value } => [
+                Some(ChannelAudioEvent::NotePitchBend {
+                    key,
+                    value: bend(value),
+                }),
+                None,
+                None,
+                None,
+            ],
+            VoiceMessage::ControlChange { controller, value } => {
+                // The channel resets the LSB with the MSB, so it's only sent
+                // when it refines the value
+                let fine = lsb_controller(controller).filter(|_| lsb(value) != 0);
+                [
+                    raw(controller, msb(value)),
+                    fine.and_then(|fine| raw(fine, lsb(value))),
+                    None,
+                    None,
+                ]
+            }
+            VoiceMessage::Parameter {
+                registered,
+                msb: parameter_msb,
+                lsb: parameter_lsb,
+                value,
+            } => {
+                let (select_msb, select_lsb) = if registered {
+                    (0x65, 0x64)
+                } else {
+                    (0x63, 0x62)
+                };
+                [
+                    raw(select_msb, parameter_msb),
+                    raw(select_lsb, parameter_lsb),
+                    raw(0x06, msb(value)),
+                    raw(0x26, lsb(value)),
+                ]
+            }
+            VoiceMessage::ProgramChange { program, bank } => [
+                bank.and_then(|(bank_msb, _)| raw(0x00, bank_msb)),
+                bank.and_then(|(_, bank_lsb)| raw(0x20, bank_lsb)),
+                Some(ChannelAudioEvent::ProgramChange(program)),
+                None,
+            ],
+            VoiceMessage::ChannelPressure(value) => [
+                Some(ChannelAudioEvent::Control(ControlEvent::ChannelPressure(
+                    msb(value),
+                ))),
+                None,
+                None,
+                None,
+            ],
+            VoiceMessage::PitchBend(value) => [
+                Some(ChannelAudioEvent::Control(ControlEvent::PitchBendValue(
+                    bend(value),
+                ))),
+                None,
+                None,
+                None,
+            ],
+        }
+    }
+}
+
+fn channel_events(
+    channel: u32,
+    message: Option<VoiceMessage>,
+) -> impl Iterator<Item = (u32, ChannelAudioEvent)> {
+    message
+        .map(VoiceMessage::events)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(move |event| (channel, event))
+}
+
+/// Translates a MIDI 1.0 channel voice message to the channel events it
+/// stands for, each with the index of its channel. The status byte is in
+/// the lowest byte of the message and the data bytes in the next ones, like
+/// in the messages of the Windows MIDI API.
+///
+/// Note on, note off, control change, program change, channel pressure and
+/// pitch bend messages are translated, other messages are ignored.
+pub fn midi1_events(message: u32) -> impl Iterator<Item = (u32, ChannelAudioEvent)> {
+    let status = message as u8;
+    let message = VoiceMessage::from_midi1(status, (message >> 8) as u8, (message >> 16) as u8);
+    channel_events((status & 0xF) as u32, message)
+}
+
+/// Translates a Universal MIDI Packet (UMP) to the channel events it stands
+/// for, each with the index of its channel. The channel index is the group
+/// of the packet times 16 plus the channel within the group, so the groups
+/// address the channels of large synthesizers.
+///
+/// The MIDI 1.0 channel voice messages (message type 2) are translated like
+/// `midi1_events`. Of the MIDI 2.0 channel voice messages (message type 4),
+/// note on, note off, per-note pitch bend, control change, registered and
+/// assignable controller (RPN and NRPN), program change, channel pressure
+/// and pitch bend messages are translated, with their extended resolution
+/// kept where the channel events support it:
+/// - The pitch bends keep their full resolution, see
+///   `ChannelAudioEvent::NotePitchBend`.
+/// - The controllers with an LSB controller, such as the volume, and the
+///   RPN and NRPN values keep 14 bits, which are sent as their MSB and LSB.
+/// - The velocities and the other controller values are reduced to 7 bits,
+///   the resolution of the soundfont regions they select.
+///
+/// Other messages are ignored. Only the words of the packet's size are
+/// read, so the remaining words of shorter packets can be anything.
+pub fn ump_events(packet: [u32; 4]) -> impl Iterator<Item = (u32, ChannelAudioEvent)> {
+    let [word0, word1, ..] = packet;
+    let group = (word0 >> 24) & 0xF;
+    let channel = group * 16 + ((word0 >> 16) & 0xF);
+    let message = match word0 >> 28 {
+        0x2 => VoiceMessage::from_midi1((word0 >> 16) as u8, (word0 >> 8) as u8, word0 as u8),
+        0x4 => VoiceMessage::from_midi2(word0, word1),
+        _ => None,
+    };
+    channel_events(channel, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ump(packet: [u32; 4]) -> Vec<(u32, ChannelAudioEvent)> {
+        ump_events(packet).collect()
+    }
+
+    fn raw(channel: u32, controller: u8, value: u8) -> (u32, ChannelAudioEvent) {
+        (
+            channel,
+            ChannelAudioEvent::Control(ControlEvent::Raw(controller, value)),
+        )
+    }
+
+    #[test]
+    fn test_midi1_and_ump_match() {
+        let messages = [
+            0x00643C92, 0x00003C82, 0x00500BB2, 0x000005C2, 0x000040D2, 0x00402AE2,
+        ];
+        for message in messages {
+            let midi1: Vec<_> = midi1_events(message).collect();
+            assert_eq!(midi1.len(), 1);
+
+            // The same message in a MIDI 1.0 packet, and scaled up to a
+            // MIDI 2.0 packet
+            let [status, data1, data2, _] = message.to_le_bytes();
+            let packet = 0x20000000 | u32::from_be_bytes([0, status, data1, data2]);
+            assert_eq!(ump([packet, 0, 0, 0]), midi1);
+
+            let upscaled = VoiceMessage::from_midi1(status, data1, data2).unwrap();
+            let midi2 = match upscaled {
+                VoiceMessage::NoteOff { key } => [0x40820000 | ((key as u32) << 8), 0],
+                VoiceMessage::NoteOn { key, vel } => {
+                    [0x40920000 | ((key as u32) << 8), (vel as u32) << 16]
+                }
+                VoiceMessage::ControlChange { controller, value } => {
+                    [0x40B20000 | ((controller as u32) << 8), value]
+                }
+                VoiceMessage::ProgramChange { program, .. } => [0x40C20000, (program as u32) << 24],
+                VoiceMessage::ChannelPressure(value) => [0x40D20000, value],
+                VoiceMessage::PitchBend(value) => [0x40E20000, value],
+                _ => unreachable!(),
+            };
+            assert_eq!(ump([midi2[0], midi2[1], 0, 0]), midi1);
+        }
+    }
+
+    #[test]
+    fn test_midi2_resolution() {
+        // Group 1, channel 3: a 32-bit volume keeps 14 bits
+        assert_eq!(
+            ump([0x41B30700, 0x80FC0000, 0, 0]),
+            [raw(19, 0x07, 0x40), raw(19, 0x27, 0x3F)]
+        );
+
+        // The note on velocity is reduced to 7 bits, but never to 0
+        assert_eq!(
+            ump([0x40903C00, 0x00050000, 0, 0]),
+            [(0, ChannelAudioEvent::NoteOn { key: 60, vel: 1 })]
+        );
+
+        // A quarter of the full per-note pitch bend range upwards
+        assert_eq!(
+            ump([0x40613C00, 0xA0000000, 0, 0]),
+            [(
+                1,
+                ChannelAudioEvent::NotePitchBend {
+                    key: 60,
+                    value: 0.25
+                }
+            )]
+        );
+
+        // Program change with a bank, and RPN 0 (pitch bend sensitivity)
+        assert_eq!(
+            ump([0x40C00001, 0x05000203, 0, 0]),
+            [
+                raw(0, 0x00, 2),
+                raw(0, 0x20, 3),
+                (0, ChannelAudioEvent::ProgramChange(5)),
+            ]
+        );
+        assert_eq!(
+            ump([0x40200000, 0x18000000, 0, 0]),
+            [
+                raw(0, 0x65, 0),
+                raw(0, 0x64, 0),
+                raw(0, 0x06, 12),
+                raw(0, 0x26, 0)
+            ]
+        );
+
+        // Other message types are ignored
+        assert!(ump([0x10F80000, 0, 0, 0]).is_empty());
+    }
+}
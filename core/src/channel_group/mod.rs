@@ -1,8 +1,12 @@
-use std::sync::Arc;
+use std::{ops::RangeInclusive, sync::Arc};
 
 use crate::{
-    channel::{ChannelAudioEvent, ChannelConfigEvent, ChannelEvent, VoiceChannel},
-    helpers::{fast_zero_fill, prepapre_cache_vec, sum_simd},
+    channel::{
+        ChannelAudioEvent, ChannelConfigEvent, ChannelEvent, ChannelInitOptions,
+        ChannelStateSnapshot, CoverageReport, VoiceChannel,
+    },
+    effects::{AudioInsert, EffectsChain, TempoClock},
+    util::{drop_later, fast_zero_fill, sum_simd, RenderThreadCheck},
     AudioPipe, AudioStreamParams,
 };
 
@@ -10,10 +14,34 @@ mod config;
 pub use config::*;
 mod events;
 pub use events::*;
+mod chase;
+pub use chase::*;
+mod routing;
+pub use routing::*;
+mod midi;
+pub use midi::*;
+mod jobs;
+pub use jobs::*;
 use rayon::prelude::*;
 
 const MAX_EVENT_CACHE_SIZE: u32 = 1024 * 1024;
 
+/// The longest time a removed channel plays its released voices before it
+/// is dropped, in seconds. Voices that don't end by then, such as sustained
+/// loops, are cut off.
+const REMOVED_CHANNEL_RELEASE_SECONDS: usize = 5;
+
+/// The number of silent buffers rendered by `ChannelGroup::warm_up`.
+const WARM_UP_BUFFERS: usize = 4;
+
+/// A channel removed with `ChannelGroup::remove_channel`, playing its
+/// released voices until they end.
+struct RemovedChannel {
+    channel: VoiceChannel,
+    samples: Vec<f32>,
+    frames_left: usize,
+}
+
 /// Represents a MIDI synthesizer within XSynth.
 ///
 /// Manages multiple VoiceChannel objects at once. For info about MIDI CC
@@ -21,10 +49,22 @@ const MAX_EVENT_CACHE_SIZE: u32 = 1024 * 1024;
 pub struct ChannelGroup {
     thread_pool: Option<rayon::ThreadPool>,
     cached_event_count: u32,
-    channel_events_cache: Box<[Vec<ChannelAudioEvent>]>,
-    sample_cache_vecs: Box<[Vec<f32>]>,
-    channels: Box<[VoiceChannel]>,
+    channel_events_cache: Vec<Vec<ChannelEvent>>,
+    sample_cache_vecs: Vec<Vec<f32>>,
+
+    /// The channels by ID. Removed channels leave an empty slot, which is
+    /// reused by the next added channel.
+    channels: Vec<Option<VoiceChannel>>,
+    removed_channels: Vec<RemovedChannel>,
+    channel_pool: Option<Arc<rayon::ThreadPool>>,
+    routing: ChannelRouting,
+    master_effects: EffectsChain,
+    tempo: TempoClock,
     audio_params: AudioStreamParams,
+
+    /// Catches the group being rendered by two threads at once, e.g. through
+    /// the C API
+    render_check: RenderThreadCheck,
 }
 
 impl ChannelGroup {
@@ -59,70 +99,196 @@ impl ChannelGroup {
             ),
         };
 
-        let channel_count = match config.format {
-            SynthFormat::Midi => 16,
-            SynthFormat::Custom { channels } => channels,
-        };
+        let channel_count = config.format.channel_count();
+        let tempo = TempoClock::default();
 
-        for _ in 0..channel_count {
-            channels.push(VoiceChannel::new(
+        for i in 0..channel_count {
+            let mut channel = VoiceChannel::new(
                 config.channel_init_options,
                 config.audio_params,
                 channel_pool.clone(),
-            ));
+            );
+            channel.set_tempo_clock(tempo.clone());
+            if config.format.is_percussion(i) {
+                channel.push_events_iter(std::iter::once(ChannelEvent::Config(
+                    ChannelConfigEvent::SetPercussionMode(true),
+                )));
+            }
+            channels.push(Some(channel));
             channel_events_cache.push(Vec::new());
             sample_cache_vecs.push(Vec::new());
         }
 
-        if config.format == SynthFormat::Midi {
-            channels[9].push_events_iter(std::iter::once(ChannelEvent::Config(
-                ChannelConfigEvent::SetPercussionMode(true),
-            )));
-        }
-
         Self {
             thread_pool: group_pool,
             cached_event_count: 0,
-            channel_events_cache: channel_events_cache.into_boxed_slice(),
-            channels: channels.into_boxed_slice(),
-            sample_cache_vecs: sample_cache_vecs.into_boxed_slice(),
+            channel_events_cache,
+            channels,
+            sample_cache_vecs,
+            removed_channels: Vec::new(),
+            channel_pool,
+            routing: ChannelRouting::new(),
+            master_effects: EffectsChain::default(),
+            tempo,
             audio_params: config.audio_params,
+            render_check: RenderThreadCheck::default(),
         }
     }
 
     /// Sends a SynthEvent to the ChannelGroup.
     /// See the `SynthEvent` documentation for more information.
+    ///
+    /// Events for a single channel are remapped by the routing matrix, see
+    /// `set_routing`. Events sent to a channel index outside the configured
+    /// channel count are ignored.
+    ///
+    /// Each channel applies its events in the order they were sent, whether
+    /// they were sent to the channel or to all channels, and whether they
+    /// are audio or config events. For example, notes sent after a
+    /// `SetSoundfonts` event play with the new soundfonts, and notes sent
+    /// before it with the previous ones.
     pub fn send_event(&mut self, event: SynthEvent) {
+        #[cfg(feature = "tracing")]
+        crate::trace::route(&event);
+
         match event {
-            SynthEvent::Channel(channel, event) => match event {
-                ChannelEvent::Audio(e) => {
-                    self.channel_events_cache[channel as usize].push(e);
-                    self.cached_event_count += 1;
-                    if self.cached_event_count > MAX_EVENT_CACHE_SIZE {
-                        self.flush_events();
-                    }
+            SynthEvent::Channel(channel, event) => {
+                if self.routing.is_passthrough() {
+                    self.send_channel_event(channel, event);
+                    return;
                 }
-                ChannelEvent::Config(_) => self.channels[channel as usize].process_event(event),
-            },
-            SynthEvent::AllChannels(event) => match event {
-                ChannelEvent::Audio(e) => {
-                    for channel in self.channel_events_cache.iter_mut() {
-                        channel.push(e);
-                    }
-                    self.cached_event_count += self.channel_events_cache.len() as u32;
-                    if self.cached_event_count > MAX_EVENT_CACHE_SIZE {
-                        self.flush_events();
-                    }
+
+                let routing = std::mem::take(&mut self.routing);
+                for target in routing.targets(channel, &event) {
+                    self.send_channel_event(target, event.clone());
+                }
+                self.routing = routing;
+            }
+            SynthEvent::AllChannels(event) => {
+                if let ChannelEvent::Config(ChannelConfigEvent::SetSampleRate(change)) = &event {
+                    self.set_sample_rate(change.sample_rate());
                 }
-                ChannelEvent::Config(_) => {
-                    for channel in self.channels.iter_mut() {
-                        channel.process_event(event.clone());
+
+                // Queued with the events of each channel, so they stay in order
+                for (channel, events) in self.channels.iter().zip(&mut self.channel_events_cache) {
+                    if channel.is_some() {
+                        events.push(event.clone());
+                        self.cached_event_count += 1;
                     }
                 }
-            },
+                if self.cached_event_count > MAX_EVENT_CACHE_SIZE {
+                    self.flush_events();
+                }
+            }
+            SynthEvent::SetMasterEffects(chain) => self.master_effects.replace(chain),
+            SynthEvent::SetTempo(bpm) => self.tempo.set_bpm(bpm),
+            // The group renders a single mix
+            SynthEvent::SetOutputPair(..) => {}
+            SynthEvent::Ump(packet) => {
+                for (channel, event) in ump_events(packet) {
+                    self.send_event(SynthEvent::Channel(channel, ChannelEvent::Audio(event)));
+                }
+            }
+        }
+    }
+
+    fn send_channel_event(&mut self, channel: u32, event: ChannelEvent) {
+        if !self.has_channel(channel) {
+            return;
+        }
+
+        self.channel_events_cache[channel as usize].push(event);
+        self.cached_event_count += 1;
+        if self.cached_event_count > MAX_EVENT_CACHE_SIZE {
+            self.flush_events();
+        }
+    }
+
+    /// Adds a channel with the given options, and returns its ID, which is
+    /// the channel index of the events sent to it. The ID of a removed
+    /// channel is reused, otherwise the channel is added after the last one.
+    ///
+    /// The channel starts with the default state and no soundfonts, so its
+    /// soundfonts have to be set with a `SynthEvent::Channel` event. Events
+    /// sent to all channels only reach it once it was added.
+    pub fn add_channel(&mut self, options: ChannelInitOptions) -> ChannelId {
+        let mut channel = VoiceChannel::new(options, self.audio_params, self.channel_pool.clone());
+        channel.set_tempo_clock(self.tempo.clone());
+        match self.channels.iter().position(|c| c.is_none()) {
+            Some(id) => {
+                self.channels[id] = Some(channel);
+                id as ChannelId
+            }
+            None => {
+                self.channels.push(Some(channel));
+                self.channel_events_cache.push(Vec::new());
+                self.sample_cache_vecs.push(Vec::new());
+                self.channel_count() - 1
+            }
         }
     }
 
+    /// Removes the channel with the given ID. Returns false if there is no
+    /// such channel.
+    ///
+    /// The events already sent to the channel are applied, and its notes are
+    /// released like with `ChannelAudioEvent::AllNotesOff`. The channel then
+    /// keeps playing until its voices end, for at most 5 seconds, and is
+    /// dropped on a background thread. Events sent to the ID afterwards are
+    /// ignored, until the ID is reused by `add_channel`.
+    pub fn remove_channel(&mut self, id: ChannelId) -> bool {
+        let Some(mut channel) = self.channels.get_mut(id as usize).and_then(Option::take) else {
+            return false;
+        };
+
+        let events = std::mem::take(&mut self.channel_events_cache[id as usize]);
+        self.cached_event_count = self.cached_event_count.saturating_sub(events.len() as u32);
+        let all_notes_off = ChannelEvent::Audio(ChannelAudioEvent::AllNotesOff);
+        channel.push_events_iter(events.into_iter().chain(std::iter::once(all_notes_off)));
+
+        self.removed_channels.push(RemovedChannel {
+            channel,
+            samples: std::mem::take(&mut self.sample_cache_vecs[id as usize]),
+            frames_left: self.audio_params.sample_rate as usize * REMOVED_CHANNEL_RELEASE_SECONDS,
+        });
+        true
+    }
+
+    /// Switches the group to the sample rate of a `SetSampleRate` event sent
+    /// to all the channels. The removed channels still play the voices of
+    /// the previous rate, so they are dropped.
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.audio_params.sample_rate = sample_rate;
+        for removed in self.removed_channels.drain(..) {
+            drop_later(removed);
+        }
+    }
+
+    /// Returns true if a channel with the given ID exists.
+    pub fn has_channel(&self, id: ChannelId) -> bool {
+        matches!(self.channels.get(id as usize), Some(Some(_)))
+    }
+
+    /// Returns the IDs of the channels, in ascending order.
+    pub fn channel_ids(&self) -> impl Iterator<Item = ChannelId> + '_ {
+        self.channels
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.is_some())
+            .map(|(id, _)| id as ChannelId)
+    }
+
+    /// Sets the matrix that remaps the events sent to single channels.
+    /// See the `ChannelRouting` documentation for more information.
+    pub fn set_routing(&mut self, routing: ChannelRouting) {
+        self.routing = routing;
+    }
+
+    /// Returns the current channel routing matrix.
+    pub fn routing(&self) -> &ChannelRouting {
+        &self.routing
+    }
+
     fn flush_events(&mut self) {
         if self.cached_event_count == 0 {
             return;
@@ -137,18 +303,23 @@ impl ChannelGroup {
                     channels
                         .par_iter_mut()
                         .zip(channel_events_cache.par_iter_mut())
-                        .for_each(|(channel, events)| {
-                            channel.push_events_iter(events.drain(..).map(ChannelEvent::Audio));
+                        .enumerate()
+                        .for_each(|(_i, (channel, events))| {
+                            if let Some(channel) = channel {
+                                #[cfg(feature = "tracing")]
+                                let _span = crate::trace::enter_channel(_i as u32);
+                                channel.push_events_iter(events.drain(..));
+                            }
                         });
                 });
             }
             None => {
-                for (channel, events) in self
-                    .channels
-                    .iter_mut()
-                    .zip(self.channel_events_cache.iter_mut())
-                {
-                    channel.push_events_iter(events.drain(..).map(ChannelEvent::Audio));
+                for (i, events) in self.channel_events_cache.iter_mut().enumerate() {
+                    if let Some(Some(channel)) = self.channels.get_mut(i) {
+                        #[cfg(feature = "tracing")]
+                        let _span = crate::trace::enter_channel(i as u32);
+                        channel.push_events_iter(events.drain(..));
+                    }
                 }
             }
         }
@@ -157,59 +328,209 @@ impl ChannelGroup {
     }
 
     fn render_to(&mut self, buffer: &mut [f32]) {
+        let _render = self.render_check.enter("ChannelGroup");
         self.flush_events();
-        // Fast zero using write_bytes (optimized by compiler to SIMD)
-        unsafe {
-            std::ptr::write_bytes(buffer.as_mut_ptr(), 0, buffer.len());
+
+        let len = buffer.len();
+        let sidechain_first = self
+            .channels
+            .iter()
+            .flatten()
+            .any(VoiceChannel::has_sidechain_send);
+        if sidechain_first {
+            // The channels sending to a sidechain are rendered first, so the
+            // compressors reading it get the audio of the same buffer
+            self.render_channels(len, VoiceChannel::has_sidechain_send);
+            self.render_channels(len, |c| !c.has_sidechain_send());
+        } else {
+            self.render_channels(len, |_| true);
         }
 
+        self.mix_to(buffer);
+    }
+
+    /// Renders the channels selected by the filter into their buffers. The
+    /// buffers of the empty channel slots are cleared.
+    fn render_channels(&mut self, len: usize, filter: impl Fn(&VoiceChannel) -> bool + Sync) {
+        let selected = |channel: &Option<VoiceChannel>| channel.as_ref().is_none_or(&filter);
         match self.thread_pool.as_ref() {
             Some(pool) => {
-                let len = buffer.len();
                 let channels = &mut self.channels;
                 let sample_cache_vecs = &mut self.sample_cache_vecs;
-                pool.install(move || {
+                pool.install(|| {
                     channels
                         .par_iter_mut()
                         .zip(sample_cache_vecs.par_iter_mut())
+                        .filter(|(channel, _)| selected(channel))
                         .for_each(|(channel, samples)| {
-                            fast_zero_fill(samples, len);
-                            channel.read_samples(samples.as_mut_slice());
+                            render_channel(channel.as_mut(), samples, len)
                         });
-
-                    for vec in sample_cache_vecs.iter_mut() {
-                        sum_simd(vec, buffer);
-                    }
                 });
             }
             None => {
-                let len = buffer.len();
-
                 for (channel, samples) in self
                     .channels
                     .iter_mut()
                     .zip(self.sample_cache_vecs.iter_mut())
+                    .filter(|(channel, _)| selected(channel))
                 {
-                    fast_zero_fill(samples, len);
-                    channel.read_samples(samples.as_mut_slice());
+                    render_channel(channel.as_mut(), samples, len);
                 }
+            }
+        }
+    }
 
-                for vec in self.sample_cache_vecs.iter_mut() {
-                    sum_simd(vec, buffer);
-                }
+    /// Mixes the rendered channels and the removed channels into the buffer,
+    /// and applies the master effects.
+    fn mix_to(&mut self, buffer: &mut [f32]) {
+        // Fast zero using write_bytes (optimized by compiler to SIMD)
+        unsafe {
+            std::ptr::write_bytes(buffer.as_mut_ptr(), 0, buffer.len());
+        }
+
+        for vec in self.sample_cache_vecs.iter().filter(|v| !v.is_empty()) {
+            sum_simd(vec, buffer);
+        }
+
+        self.render_removed_channels(buffer);
+        self.master_effects.process(buffer, &self.audio_params);
+    }
+
+    /// Renders the released voices of the removed channels into the buffer,
+    /// and drops the channels whose voices ended.
+    fn render_removed_channels(&mut self, buffer: &mut [f32]) {
+        let frames = buffer.len() / self.audio_params.channels.count() as usize;
+        for removed in self.removed_channels.iter_mut() {
+            fast_zero_fill(&mut removed.samples, buffer.len());
+            removed.channel.read_samples(&mut removed.samples);
+            sum_simd(&removed.samples, buffer);
+            removed.frames_left = removed.frames_left.saturating_sub(frames);
+        }
+
+        let mut i = 0;
+        while i < self.removed_channels.len() {
+            let removed = &self.removed_channels[i];
+            if removed.frames_left == 0 || removed.channel.get_channel_stats().voice_count() == 0 {
+                drop_later(self.removed_channels.swap_remove(i));
+            } else {
+                i += 1;
             }
         }
     }
 
-    /// Returns the active voice count of the synthesizer.
+    /// Renders a few silent buffers of the given length through all the
+    /// channels and the master effects, and discards them. This allocates
+    /// the buffers of the channels and their keys and wakes up the thread
+    /// pools before the first note, which otherwise renders slower than the
+    /// following ones, e.g. causing a glitch in a realtime host.
+    ///
+    /// The pending events are applied during the warm-up, so it should be
+    /// called before sending notes. The length must be a multiple of the
+    /// channel count of the audio stream.
+    pub fn warm_up(&mut self, len: usize) {
+        let mut buffer = vec![0.0; len];
+        for _ in 0..WARM_UP_BUFFERS {
+            self.read_samples(&mut buffer);
+        }
+    }
+
+    /// Returns the gain reduction of the master effects chain in the last
+    /// rendered buffer, in decibels, e.g. to meter a `Compressor`. `0.0` if
+    /// no element reduces the gain. See `AudioInsert::gain_reduction_db`.
+    pub fn master_gain_reduction_db(&self) -> f32 {
+        self.master_effects.gain_reduction_db().unwrap_or(0.0)
+    }
+
+    /// Returns the latency of the master effects chain, in frames. The chain
+    /// is empty by default, see `SynthEvent::SetMasterEffects`.
+    pub fn master_effects_latency(&self) -> usize {
+        self.master_effects.latency_frames()
+    }
+
+    /// Returns the tempo clock of the group, set with `SynthEvent::SetTempo`,
+    /// to sync effects such as a `StereoDelay` to it.
+    pub fn tempo_clock(&self) -> TempoClock {
+        self.tempo.clone()
+    }
+
+    /// Returns the number of channel IDs of the synthesizer, which is one
+    /// more than the highest ID. Includes the empty slots of removed
+    /// channels, see `channel_ids`.
+    pub fn channel_count(&self) -> u32 {
+        self.channels.len() as u32
+    }
+
+    /// Returns the samples the given channel rendered in the last read of
+    /// the group, before they were mixed with the other channels and the
+    /// master effects were applied, e.g. to write each channel to its own
+    /// file. Returns an empty slice if the channel doesn't exist.
+    pub fn channel_samples(&self, channel: ChannelId) -> &[f32] {
+        self.sample_cache_vecs
+            .get(channel as usize)
+            .map(|samples| samples.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Returns the controller state of the given channel, or `None` if the
+    /// channel doesn't exist. Events are queued until the next render, so
+    /// the events sent since then aren't included yet. See the
+    /// `ChannelStateSnapshot` documentation for more information.
+    pub fn channel_state(&self, channel: ChannelId) -> Option<ChannelStateSnapshot> {
+        self.channels
+            .get(channel as usize)?
+            .as_ref()
+            .map(|c| c.state())
+    }
+
+    /// Checks the key and velocity coverage of the soundfonts of the given
+    /// channel, or returns `None` if the channel doesn't exist. Events are
+    /// queued until the next render, so soundfonts and program changes sent
+    /// since then aren't included yet. See `VoiceChannel::coverage_report`.
+    pub fn channel_coverage(
+        &self,
+        channel: ChannelId,
+        keys: RangeInclusive<u8>,
+    ) -> Option<CoverageReport> {
+        self.channels
+            .get(channel as usize)?
+            .as_ref()
+            .map(|c| c.coverage_report(keys))
+    }
+
+    /// Returns the active voice count of the given channel, or `0` if the
+    /// channel doesn't exist.
+    pub fn channel_voice_count(&self, channel: ChannelId) -> u64 {
+        self.channels
+            .get(channel as usize)
+            .and_then(Option::as_ref)
+            .map(|c| c.get_channel_stats().voice_count())
+            .unwrap_or(0)
+    }
+
+    /// Returns the active voice count of the synthesizer, including the
+    /// voices of removed channels that are still releasing.
     pub fn voice_count(&self) -> u64 {
         self.channels
             .iter()
+            .flatten()
+            .chain(self.removed_channels.iter().map(|r| &r.channel))
             .map(|c| c.get_channel_stats().voice_count())
             .sum()
     }
 }
 
+/// Renders a channel to its sample buffer, or clears the buffer of an empty
+/// channel slot so it isn't mixed.
+fn render_channel(channel: Option<&mut VoiceChannel>, samples: &mut Vec<f32>, len: usize) {
+    match channel {
+        Some(channel) => {
+            fast_zero_fill(samples, len);
+            channel.read_samples(samples.as_mut_slice());
+        }
+        None => samples.clear(),
+    }
+}
+
 impl AudioPipe for ChannelGroup {
     fn stream_params(&self) -> &AudioStreamParams {
         &self.audio_params
@@ -219,3 +540,327 @@ impl AudioPipe for ChannelGroup {
         self.render_to(to);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        channel::{ChannelAudioEvent, ChannelInitOptions},
+        effects::{Compressor, CompressorOptions, SidechainTap},
+        soundfont::SoundfontBase,
+        test_soundfont::TestSoundfont,
+        voice::Voice,
+        ChannelCount,
+    };
+
+    /// Returns the keys of the voices playing on each channel, with the ID
+    /// of the soundfont that spawned them.
+    fn playing_fonts(group: &mut ChannelGroup) -> Vec<Vec<(u8, u8)>> {
+        let mut buffer = vec![0.0; 64];
+        group.read_samples(&mut buffer);
+        group
+            .channels
+            .iter()
+            .flatten()
+            .map(|channel| {
+                let mut voices: Vec<_> = channel
+                    .get_channel_stats()
+                    .voice_snapshot()
+                    .iter()
+                    .map(|v| (v.key, v.velocity))
+                    .collect();
+                voices.sort_unstable();
+                voices
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_thread_safety() {
+        // Channels are rendered on the channel threads, and the realtime
+        // synthesizer moves the whole group to the audio thread
+        fn assert_send<T: Send>() {}
+        fn assert_send_sync<T: Send + Sync + ?Sized>() {}
+        assert_send::<ChannelGroup>();
+        assert_send::<VoiceChannel>();
+        assert_send_sync::<dyn SoundfontBase>();
+        assert_send_sync::<dyn Voice>();
+    }
+
+    #[test]
+    fn test_config_and_note_order() {
+        const CHANNELS: usize = 4;
+
+        let audio_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let fonts =
+            |id: u8| -> Arc<[Arc<dyn SoundfontBase>]> { Arc::new([TestSoundfont::new_arc(id)]) };
+
+        let mut group = ChannelGroup::new(ChannelGroupConfig {
+            channel_init_options: ChannelInitOptions::default(),
+            format: SynthFormat::Custom {
+                channels: CHANNELS as u32,
+            },
+            audio_params,
+            parallelism: ParallelismOptions {
+                channel: ThreadCount::None,
+                key: ThreadCount::None,
+            },
+        });
+        group.send_event(SynthEvent::AllChannels(ChannelEvent::Config(
+            ChannelConfigEvent::SetVoiceSnapshots(true),
+        )));
+
+        // Random sequences of soundfont changes, sent to one or all
+        // channels, and notes. Each note must play the soundfont that was
+        // set on its channel when it was sent.
+        let mut rng = StdRng::seed_from_u64(167);
+        let mut active = [None; CHANNELS];
+        for _ in 0..200 {
+            let mut expected = vec![Vec::new(); CHANNELS];
+            for key in 0..rng.gen_range(1..32u8) {
+                let channel = rng.gen_range(0..CHANNELS);
+                let font = rng.gen_range(1..=3);
+                match rng.gen_range(0..4) {
+                    0 => {
+                        active = [Some(font); CHANNELS];
+                        group.send_event(SynthEvent::AllChannels(ChannelEvent::Config(
+                            ChannelConfigEvent::SetSoundfonts(fonts(font)),
+                        )));
+                    }
+                    1 => {
+                        active[channel] = Some(font);
+                        group.send_event(SynthEvent::Channel(
+                            channel as u32,
+                            ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(fonts(font))),
+                        ));
+                    }
+                    _ => {
+                        if let Some(font) = active[channel] {
+                            expected[channel].push((key, font));
+                        }
+                        group.send_event(SynthEvent::Channel(
+                            channel as u32,
+                            ChannelEvent::Audio(ChannelAudioEvent::NoteOn { key, vel: 100 }),
+                        ));
+                    }
+                }
+            }
+            assert_eq!(playing_fonts(&mut group), expected);
+
+            group.send_event(SynthEvent::AllChannels(ChannelEvent::Audio(
+                ChannelAudioEvent::AllNotesKilled,
+            )));
+            assert_eq!(playing_fonts(&mut group), vec![Vec::new(); CHANNELS]);
+        }
+    }
+
+    #[test]
+    fn test_add_and_remove_channels() {
+        let audio_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let fonts: Arc<[Arc<dyn SoundfontBase>]> = Arc::new([TestSoundfont::new_arc(1)]);
+        let note_on = |key| ChannelEvent::Audio(ChannelAudioEvent::NoteOn { key, vel: 100 });
+
+        let mut group = ChannelGroup::new(ChannelGroupConfig {
+            channel_init_options: ChannelInitOptions::default(),
+            format: SynthFormat::Custom { channels: 2 },
+            audio_params,
+            parallelism: ParallelismOptions {
+                channel: ThreadCount::None,
+                key: ThreadCount::None,
+            },
+        });
+        group.send_event(SynthEvent::AllChannels(ChannelEvent::Config(
+            ChannelConfigEvent::SetSoundfonts(fonts.clone()),
+        )));
+        group.send_event(SynthEvent::Channel(0, note_on(60)));
+
+        let mut buffer = vec![0.0; 64];
+        for key in 0..100 {
+            // The ID of the channel removed before is reused
+            let id = group.add_channel(ChannelInitOptions::default());
+            assert_eq!(id, 2);
+            group.send_event(SynthEvent::Channel(
+                id,
+                ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(fonts.clone())),
+            ));
+            group.send_event(SynthEvent::Channel(id, note_on(key)));
+            group.read_samples(&mut buffer);
+            assert_eq!(group.channel_voice_count(id), 1);
+            assert_eq!(group.voice_count(), 2);
+
+            // A note sent right before the removal is still played and
+            // released
+            group.send_event(SynthEvent::Channel(id, note_on(key + 1)));
+            assert!(group.remove_channel(id));
+            assert!(!group.remove_channel(id));
+            group.send_event(SynthEvent::Channel(id, note_on(key)));
+            group.read_samples(&mut buffer);
+            assert_eq!(group.voice_count(), 1);
+        }
+
+        assert!(group.removed_channels.is_empty());
+        assert_eq!(group.channel_ids().collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(group.channel_count(), 3);
+        assert_eq!(group.channel_voice_count(0), 1);
+    }
+
+    #[test]
+    fn test_render_jobs() {
+        let audio_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let fonts: Arc<[Arc<dyn SoundfontBase>]> = Arc::new([TestSoundfont::new_arc(1)]);
+        let new_group = || {
+            let mut group = ChannelGroup::new(ChannelGroupConfig {
+                channel_init_options: ChannelInitOptions::default(),
+                format: SynthFormat::Custom { channels: 4 },
+                audio_params,
+                parallelism: ParallelismOptions {
+                    channel: ThreadCount::None,
+                    key: ThreadCount::None,
+                },
+            });
+            group.send_event(SynthEvent::AllChannels(ChannelEvent::Config(
+                ChannelConfigEvent::SetSoundfonts(fonts.clone()),
+            )));
+            for channel in 0..4 {
+                let key = 60 + channel as u8;
+                group.send_event(SynthEvent::Channel(
+                    channel,
+                    ChannelEvent::Audio(ChannelAudioEvent::NoteOn { key, vel: 100 }),
+                ));
+            }
+            group
+        };
+
+        let mut expected = vec![0.0; 128];
+        new_group().read_samples(&mut expected);
+        assert!(expected.iter().any(|&s| s != 0.0));
+
+        // The host runs the jobs on its own threads
+        let mut group = new_group();
+        let mut buffer = vec![0.0; 128];
+        let jobs: Vec<_> = group.render_jobs(buffer.len()).collect();
+        assert_eq!(jobs.len(), 4);
+        rayon::scope(|s| {
+            for job in jobs {
+                s.spawn(move |_| job.run());
+            }
+        });
+        group.finalize_mix(&mut buffer);
+        assert_eq!(buffer, expected);
+
+        // Channels whose job isn't run are silent
+        for job in group.render_jobs(buffer.len()) {
+            if job.channel() != 0 {
+                job.run();
+            }
+        }
+        group.finalize_mix(&mut buffer);
+        assert!(group.channel_samples(0).is_empty());
+        assert_eq!(group.channel_samples(1).len(), buffer.len());
+        assert!(buffer.iter().any(|&s| s != 0.0));
+    }
+
+    #[test]
+    fn test_sidechain_order() {
+        let audio_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let mut group = ChannelGroup::new(ChannelGroupConfig {
+            channel_init_options: ChannelInitOptions::default(),
+            format: SynthFormat::Custom { channels: 2 },
+            audio_params,
+            parallelism: ParallelismOptions {
+                channel: ThreadCount::Auto,
+                key: ThreadCount::None,
+            },
+        });
+        let fonts: Arc<[Arc<dyn SoundfontBase>]> = Arc::new([TestSoundfont::new_arc(1)]);
+        group.send_event(SynthEvent::AllChannels(ChannelEvent::Config(
+            ChannelConfigEvent::SetSoundfonts(fonts),
+        )));
+
+        // The second channel ducks the first one
+        let tap = SidechainTap::new();
+        let compressor = Compressor::new(CompressorOptions {
+            threshold_db: -60.0,
+            ratio: 10.0,
+            attack_ms: 0.0,
+            ..Default::default()
+        })
+        .with_sidechain(tap.clone());
+        group.send_event(SynthEvent::Channel(
+            0,
+            ChannelEvent::Config(ChannelConfigEvent::SetInsert(Some(Box::new(compressor)))),
+        ));
+        group.send_event(SynthEvent::Channel(
+            1,
+            ChannelEvent::Config(ChannelConfigEvent::SetSidechainSend(Some(tap))),
+        ));
+        for channel in 0..2 {
+            group.send_event(SynthEvent::Channel(
+                channel,
+                ChannelEvent::Audio(ChannelAudioEvent::NoteOn { key: 60, vel: 100 }),
+            ));
+        }
+
+        // The first buffer is already ducked, as the second channel is
+        // rendered first
+        let mut buffer = vec![0.0; 128];
+        group.read_samples(&mut buffer);
+        let (ducked, source) = (group.channel_samples(0), group.channel_samples(1));
+        assert!(source.iter().all(|&s| s > 0.0));
+        assert!(ducked.iter().zip(source).all(|(d, s)| *d < s * 0.2));
+
+        let stats = group.channels[0].as_ref().unwrap().get_channel_stats();
+        assert!(stats.insert_gain_reduction_db() > 10.0);
+        assert!(group.channels[1]
+            .as_ref()
+            .unwrap()
+            .get_channel_stats()
+            .has_sidechain_send());
+    }
+
+    #[test]
+    fn test_warm_up() {
+        let audio_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let fonts: Arc<[Arc<dyn SoundfontBase>]> = Arc::new([TestSoundfont::new_arc(1)]);
+
+        // Returns the render time and the audio of the first quantum with
+        // notes on all the channels
+        let first_quantum = |warm_up: bool| {
+            let mut group = ChannelGroup::new(ChannelGroupConfig {
+                channel_init_options: ChannelInitOptions::default(),
+                format: SynthFormat::Midi,
+                audio_params,
+                parallelism: ParallelismOptions {
+                    channel: ThreadCount::Auto,
+                    key: ThreadCount::Auto,
+                },
+            });
+            group.send_event(SynthEvent::AllChannels(ChannelEvent::Config(
+                ChannelConfigEvent::SetSoundfonts(fonts.clone()),
+            )));
+            let mut buffer = vec![0.0; 512];
+            if warm_up {
+                group.warm_up(buffer.len());
+            }
+
+            group.send_event(SynthEvent::AllChannels(ChannelEvent::Audio(
+                ChannelAudioEvent::NoteOn { key: 60, vel: 100 },
+            )));
+            let start = std::time::Instant::now();
+            group.read_samples(&mut buffer);
+            (start.elapsed(), buffer)
+        };
+
+        let (cold_time, cold) = first_quantum(false);
+        let (warm_time, warm) = first_quantum(true);
+        // The warm-up doesn't change the audio
+        assert!(cold.iter().zip(&warm).all(|(a, b)| (a - b).abs() < 1e-6));
+        assert!(cold.iter().any(|&s| s != 0.0));
+        assert!(
+            warm_time <= cold_time + std::time::Duration::from_millis(5),
+            "first quantum took {warm_time:?} after the warm-up, {cold_time:?} without"
+        );
+    }
+}
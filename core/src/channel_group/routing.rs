@@ -0,0 +1,282 @@
+use std::ops::RangeInclusive;
+
+use crate::channel::{ChannelAudioEvent, ChannelEvent};
+
+/// A target channel of an incoming channel in a `ChannelRouting` matrix,
+/// optionally limited to a range of keys and velocities.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct ChannelRoute {
+    /// The index of the target channel.
+    pub channel: u32,
+
+    /// The range of keys whose note events are sent to the target.
+    ///
+    /// Default: `None` (all keys)
+    pub key_range: Option<RangeInclusive<u8>>,
+
+    /// The range of velocities whose note on events are sent to the target.
+    ///
+    /// Default: `None` (all velocities)
+    pub vel_range: Option<RangeInclusive<u8>>,
+}
+
+impl ChannelRoute {
+    /// Creates a new route to the given channel without any range limits.
+    pub fn new(channel: u32) -> Self {
+        Self {
+            channel,
+            key_range: None,
+            vel_range: None,
+        }
+    }
+
+    /// Limits the route to notes on the given range of keys.
+    pub fn with_key_range(mut self, key_range: RangeInclusive<u8>) -> Self {
+        self.key_range = Some(key_range);
+        self
+    }
+
+    /// Limits the route to note ons with the given range of velocities.
+    pub fn with_vel_range(mut self, vel_range: RangeInclusive<u8>) -> Self {
+        self.vel_range = Some(vel_range);
+        self
+    }
+
+    /// Returns true if the event should be sent through this route.
+    ///
+    /// Only note events are filtered. Note offs, kills and per-note pitch
+    /// bends are only filtered by key, as they don't carry a velocity. All other events, such as
+    /// controllers and config events, always pass.
+    pub fn accepts(&self, event: &ChannelEvent) -> bool {
+        let in_key_range = |key: u8| self.key_range.as_ref().is_none_or(|r| r.contains(&key));
+
+        match *event {
+            ChannelEvent::Audio(
+                ChannelAudioEvent::NoteOn { key, vel }
+                | ChannelAudioEvent::NoteOnDetuned { key, vel, .. },
+            ) => in_key_range(key) && self.vel_range.as_ref().is_none_or(|r| r.contains(&vel)),
+            ChannelEvent::Audio(
+                ChannelAudioEvent::NoteOff { key }
+                | ChannelAudioEvent::NoteKill { key }
+                | ChannelAudioEvent::NotePitchBend { key, .. },
+            ) => in_key_range(key),
+            _ => true,
+        }
+    }
+}
+
+impl From<u32> for ChannelRoute {
+    fn from(channel: u32) -> Self {
+        Self::new(channel)
+    }
+}
+
+/// A matrix that remaps the events of incoming channels to the channels of
+/// the synthesizer, e.g. to layer an incoming channel on several synth
+/// channels or to mute it.
+///
+/// Each incoming channel maps to zero or more routes. Channels without any
+/// configured routes are passed through to the channel with the same index,
+/// while channels with an empty list of routes are muted. Events sent to all
+/// channels with `SynthEvent::AllChannels` bypass the matrix.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct ChannelRouting {
+    routes: Vec<Option<Vec<ChannelRoute>>>,
+}
+
+impl ChannelRouting {
+    /// Creates a new matrix that passes all channels through unchanged.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the routes of an incoming channel. An empty list mutes it.
+    pub fn set_routes(&mut self, channel: u32, routes: Vec<ChannelRoute>) {
+        let index = channel as usize;
+        if self.routes.len() <= index {
+            self.routes.resize(index + 1, None);
+        }
+        self.routes[index] = Some(routes);
+    }
+
+    /// Removes the routes of an incoming channel, passing it through
+    /// unchanged again.
+    pub fn clear_routes(&mut self, channel: u32) {
+        if let Some(routes) = self.routes.get_mut(channel as usize) {
+            *routes = None;
+        }
+    }
+
+    /// Mutes an incoming channel.
+    pub fn mute(&mut self, channel: u32) {
+        self.set_routes(channel, Vec::new());
+    }
+
+    /// Returns the routes of an incoming channel, or `None` if it is passed
+    /// through unchanged.
+    pub fn routes(&self, channel: u32) -> Option<&[ChannelRoute]> {
+        self.routes.get(channel as usize)?.as_deref()
+    }
+
+    /// Returns true if no channel is remapped.
+    pub fn is_passthrough(&self) -> bool {
+        self.routes.iter().all(|r| r.is_none())
+    }
+
+    /// Returns the target channels that an event of the given incoming
+    /// channel should be sent to.
+    pub fn targets<'a>(
+        &'a self,
+        channel: u32,
+        event: &'a ChannelEvent,
+    ) -> impl Iterator<Item = u32> + 'a {
+        let routes = self.routes(channel);
+        let passthrough = routes.is_none().then_some(channel);
+
+        routes
+            .into_iter()
+            .flatten()
+            .filter(move |route| route.accepts(event))
+            .map(|route| route.channel)
+            .chain(passthrough)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        channel::{ChannelConfigEvent, ChannelInitOptions},
+        channel_group::{
+            ChannelGroup, ChannelGroupConfig, ParallelismOptions, SynthEvent, SynthFormat,
+            ThreadCount,
+        },
+        test_soundfont::TestSoundfont,
+        AudioPipe, AudioStreamParams, ChannelCount,
+    };
+
+    fn new_group(channels: u32) -> ChannelGroup {
+        let audio_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let mut group = ChannelGroup::new(ChannelGroupConfig {
+            channel_init_options: ChannelInitOptions::default(),
+            format: SynthFormat::Custom { channels },
+            audio_params,
+            parallelism: ParallelismOptions {
+                channel: ThreadCount::None,
+                key: ThreadCount::None,
+            },
+        });
+        group.send_event(SynthEvent::AllChannels(ChannelEvent::Config(
+            ChannelConfigEvent::SetSoundfonts(Arc::new([TestSoundfont::new_arc(1)])),
+        )));
+        group
+    }
+
+    fn note_on(channel: u32, key: u8, vel: u8) -> SynthEvent {
+        SynthEvent::Channel(
+            channel,
+            ChannelEvent::Audio(ChannelAudioEvent::NoteOn { key, vel }),
+        )
+    }
+
+    fn voice_counts(group: &mut ChannelGroup) -> Vec<u64> {
+        let mut buffer = vec![0.0; 64];
+        group.read_samples(&mut buffer);
+        (0..group.channel_count())
+            .map(|c| group.channel_voice_count(c))
+            .collect()
+    }
+
+    #[test]
+    fn test_fan_out() {
+        let mut group = new_group(3);
+        let mut routing = ChannelRouting::new();
+        routing.set_routes(0, vec![1.into(), 2.into()]);
+        group.set_routing(routing);
+
+        group.send_event(note_on(0, 60, 100));
+        assert_eq!(voice_counts(&mut group), vec![0, 1, 1]);
+
+        // Other channels are passed through unchanged
+        group.send_event(note_on(2, 62, 100));
+        assert_eq!(voice_counts(&mut group), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_empty_routes_mute() {
+        let mut group = new_group(2);
+        let mut routing = ChannelRouting::new();
+        routing.mute(1);
+        group.set_routing(routing);
+
+        group.send_event(note_on(1, 60, 100));
+        assert_eq!(voice_counts(&mut group), vec![0, 0]);
+
+        // All channel events bypass the matrix
+        group.send_event(SynthEvent::AllChannels(ChannelEvent::Audio(
+            ChannelAudioEvent::NoteOn { key: 60, vel: 100 },
+        )));
+        assert_eq!(voice_counts(&mut group), vec![1, 1]);
+    }
+
+    #[test]
+    fn test_note_kill_only_kills_its_key() {
+        let mut group = new_group(2);
+        let mut routing = ChannelRouting::new();
+        routing.set_routes(
+            0,
+            vec![
+                ChannelRoute::new(0).with_key_range(0..=59),
+                ChannelRoute::new(1),
+            ],
+        );
+        group.set_routing(routing);
+
+        group.send_event(note_on(0, 40, 100));
+        group.send_event(note_on(0, 40, 100));
+        group.send_event(note_on(0, 70, 100));
+        assert_eq!(voice_counts(&mut group), vec![2, 3]);
+
+        // Both notes of the key are killed, on every channel it is routed to
+        group.send_event(SynthEvent::Channel(
+            0,
+            ChannelEvent::Audio(ChannelAudioEvent::NoteKill { key: 40 }),
+        ));
+        assert_eq!(voice_counts(&mut group), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_route_filters() {
+        let mut routing = ChannelRouting::new();
+        routing.set_routes(
+            0,
+            vec![
+                ChannelRoute::new(0).with_key_range(0..=59),
+                ChannelRoute::new(1).with_key_range(60..=127),
+                ChannelRoute::new(2).with_vel_range(100..=127),
+            ],
+        );
+
+        let targets = |event: ChannelAudioEvent| -> Vec<u32> {
+            routing.targets(0, &ChannelEvent::Audio(event)).collect()
+        };
+        assert_eq!(
+            targets(ChannelAudioEvent::NoteOn { key: 40, vel: 110 }),
+            vec![0, 2]
+        );
+        assert_eq!(
+            targets(ChannelAudioEvent::NoteOn { key: 70, vel: 50 }),
+            vec![1]
+        );
+        assert_eq!(targets(ChannelAudioEvent::NoteOff { key: 70 }), vec![1, 2]);
+        assert_eq!(targets(ChannelAudioEvent::NoteKill { key: 40 }), vec![0, 2]);
+        assert_eq!(targets(ChannelAudioEvent::ProgramChange(3)), vec![0, 1, 2]);
+
+        routing.clear_routes(0);
+        assert!(routing.is_passthrough());
+    }
+}
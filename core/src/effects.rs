@@ -2,3 +2,19 @@ mod limiter;
 pub use limiter::*;
 mod filter;
 pub use filter::*;
+mod resampler;
+pub use resampler::*;
+mod insert;
+pub use insert::*;
+mod chain;
+pub use chain::*;
+mod dc_blocker;
+pub use dc_blocker::*;
+mod compressor;
+pub use compressor::*;
+mod auto_gain;
+pub use auto_gain::*;
+mod tempo;
+pub use tempo::*;
+mod delay;
+pub use delay::*;
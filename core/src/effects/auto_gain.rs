@@ -0,0 +1,160 @@
+/// The settings of an `AutoGain` stage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(default)
+)]
+pub struct AutoGainOptions {
+    /// The exponent `k` of the gain `1 / max(1, voices) ^ k`. `0.5` keeps
+    /// the level of uncorrelated voices roughly constant, while `1.0` keeps
+    /// the level of voices playing the same signal constant. `0.0` leaves
+    /// the audio unchanged.
+    ///
+    /// Default: `0.5`
+    pub exponent: f32,
+
+    /// The time the gain takes to follow a change of the voice count, in
+    /// milliseconds. It should be long, as a gain following every note is
+    /// heard as pumping.
+    ///
+    /// Default: `1000.0`
+    pub smoothing_ms: f32,
+}
+
+impl Default for AutoGainOptions {
+    fn default() -> Self {
+        Self {
+            exponent: 0.5,
+            smoothing_ms: 1000.0,
+        }
+    }
+}
+
+/// Divides the mixed output by a function of the active voice count, which
+/// keeps the level of very dense MIDIs (e.g. black MIDI) in a sane range
+/// before the limiter. Applied to the mixed output of a synthesizer, see
+/// `XSynthRealtimeConfig::auto_gain` in the realtime crate.
+///
+/// The gain approaches `1 / max(1, voices) ^ exponent` smoothly, and is
+/// exactly `1.0` while at most one voice plays. See the `AutoGainOptions`
+/// documentation for the available settings.
+#[derive(Clone, Debug)]
+pub struct AutoGain {
+    options: AutoGainOptions,
+
+    // The slow smoothing would stall short of the target in f32
+    coefficient: f64,
+    gain: f64,
+}
+
+impl AutoGain {
+    /// Creates a new auto-gain stage for audio with the given sample rate.
+    pub fn new(options: AutoGainOptions, sample_rate: u32) -> Self {
+        let coefficient = if options.smoothing_ms <= 0.0 {
+            0.0
+        } else {
+            (-1000.0 / (options.smoothing_ms as f64 * sample_rate as f64)).exp()
+        };
+
+        Self {
+            options,
+            coefficient,
+            gain: 1.0,
+        }
+    }
+
+    /// Returns the settings of the stage.
+    pub fn options(&self) -> &AutoGainOptions {
+        &self.options
+    }
+
+    /// Returns the gain applied to the last processed frame.
+    pub fn gain(&self) -> f32 {
+        self.gain as f32
+    }
+
+    /// Returns the gain the stage approaches for the given voice count.
+    pub fn target_gain(&self, voices: u64) -> f32 {
+        self.target(voices) as f32
+    }
+
+    fn target(&self, voices: u64) -> f64 {
+        if voices <= 1 || self.options.exponent == 0.0 {
+            1.0
+        } else {
+            (voices as f64).powf(-self.options.exponent as f64)
+        }
+    }
+
+    /// Applies the gain to a buffer interleaved with the given number of
+    /// channels, with the current active voice count.
+    pub fn process(&mut self, buffer: &mut [f32], channels: usize, voices: u64) {
+        let target = self.target(voices);
+        if self.gain == target {
+            if target != 1.0 {
+                let gain = target as f32;
+                buffer.iter_mut().for_each(|s| *s *= gain);
+            }
+            return;
+        }
+
+        for frame in buffer.chunks_mut(channels) {
+            self.gain = target + self.coefficient * (self.gain - target);
+            // Snap to the target, so the gain gets back to exactly 1.0
+            if (self.gain - target).abs() < 1e-6 {
+                self.gain = target;
+            }
+            let gain = self.gain as f32;
+            frame.iter_mut().for_each(|s| *s *= gain);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_gain() {
+        let auto_gain = AutoGain::new(Default::default(), 48000);
+        assert_eq!(auto_gain.target_gain(0), 1.0);
+        assert_eq!(auto_gain.target_gain(1), 1.0);
+        assert!((auto_gain.target_gain(100) - 0.1).abs() < 1e-6);
+
+        let linear = AutoGainOptions {
+            exponent: 1.0,
+            ..Default::default()
+        };
+        let auto_gain = AutoGain::new(linear, 48000);
+        assert!((auto_gain.target_gain(4) - 0.25).abs() < 1e-6);
+
+        let disabled = AutoGainOptions {
+            exponent: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(AutoGain::new(disabled, 48000).target_gain(10000), 1.0);
+    }
+
+    #[test]
+    fn test_smoothing() {
+        let mut auto_gain = AutoGain::new(Default::default(), 48000);
+
+        // The gain falls slowly when the voice count rises
+        let mut buffer = vec![1.0; 480 * 2];
+        auto_gain.process(&mut buffer, 2, 100);
+        assert!(auto_gain.gain() > 0.9);
+        assert_eq!(buffer[buffer.len() - 1], auto_gain.gain());
+
+        let mut buffer = vec![1.0; 48000 * 20 * 2];
+        auto_gain.process(&mut buffer, 2, 100);
+        assert!((auto_gain.gain() - 0.1).abs() < 1e-6);
+
+        // And gets back to exactly 1.0 when the voices end
+        auto_gain.process(&mut buffer, 2, 0);
+        assert_eq!(auto_gain.gain(), 1.0);
+        let mut buffer = vec![0.5; 64];
+        auto_gain.process(&mut buffer, 2, 1);
+        assert!(buffer.iter().all(|&s| s == 0.5));
+    }
+}
@@ -0,0 +1,147 @@
+use super::AudioInsert;
+use crate::{
+    util::{drop_later, init_deferred_drops},
+    AudioStreamParams,
+};
+
+/// An ordered list of inserts that process the mixed output of the
+/// synthesizer, e.g. the volume limiter followed by a DC blocker.
+///
+/// The elements are applied in order, so they can be reordered or removed
+/// by building a new chain. The chain is an insert itself, so chains can be
+/// nested.
+#[derive(Clone, Debug)]
+pub struct EffectsChain {
+    inserts: Vec<Box<dyn AudioInsert>>,
+}
+
+impl EffectsChain {
+    /// Creates a new chain with the given elements, applied in order.
+    pub fn new(inserts: Vec<Box<dyn AudioInsert>>) -> Self {
+        init_deferred_drops();
+        Self { inserts }
+    }
+
+    /// Appends an element to the end of the chain.
+    pub fn with(mut self, insert: impl AudioInsert + 'static) -> Self {
+        self.inserts.push(Box::new(insert));
+        self
+    }
+
+    /// Returns the elements of the chain, in processing order.
+    pub fn inserts(&self) -> &[Box<dyn AudioInsert>] {
+        &self.inserts
+    }
+
+    /// Returns the elements of the chain, so a modified chain can be built
+    /// from them.
+    pub fn into_inserts(self) -> Vec<Box<dyn AudioInsert>> {
+        self.inserts
+    }
+
+    /// Returns the number of elements in the chain.
+    pub fn len(&self) -> usize {
+        self.inserts.len()
+    }
+
+    /// Returns true if the chain has no elements and leaves the audio
+    /// unchanged.
+    pub fn is_empty(&self) -> bool {
+        self.inserts.is_empty()
+    }
+
+    /// Replaces all the elements of the chain at once. The old elements are
+    /// dropped on a background thread, so this can be called while rendering.
+    pub fn replace(&mut self, chain: EffectsChain) {
+        let old = std::mem::replace(&mut self.inserts, chain.inserts);
+        drop_later(old);
+    }
+}
+
+impl Default for EffectsChain {
+    /// An empty chain.
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl AudioInsert for EffectsChain {
+    fn process(&mut self, buffer: &mut [f32], stream_params: &AudioStreamParams) {
+        for insert in self.inserts.iter_mut() {
+            insert.process(buffer, stream_params);
+        }
+    }
+
+    /// Returns the sum of the latencies of the elements.
+    fn latency_frames(&self) -> usize {
+        self.inserts.iter().map(|i| i.latency_frames()).sum()
+    }
+
+    /// Returns the sum of the gain reductions of the elements that report
+    /// one.
+    fn gain_reduction_db(&self) -> Option<f32> {
+        self.inserts
+            .iter()
+            .filter_map(|i| i.gain_reduction_db())
+            .reduce(|a, b| a + b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        effects::{DcBlocker, GainInsert, VolumeLimiter},
+        ChannelCount,
+    };
+
+    /// An insert that delays the audio by a number of frames.
+    #[derive(Clone, Debug)]
+    struct DelayInsert(usize);
+
+    impl AudioInsert for DelayInsert {
+        fn process(&mut self, _buffer: &mut [f32], _stream_params: &AudioStreamParams) {}
+
+        fn latency_frames(&self) -> usize {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_chain_order_and_latency() {
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+
+        let mut chain = EffectsChain::default()
+            .with(GainInsert::new(2.0))
+            .with(DelayInsert(64))
+            .with(GainInsert::new(0.25))
+            .with(DelayInsert(32));
+        assert_eq!(chain.len(), 4);
+        assert_eq!(chain.latency_frames(), 96);
+
+        let mut buffer = vec![1.0; 8];
+        chain.process(&mut buffer, &stream_params);
+        assert!(buffer.iter().all(|&s| s == 0.5));
+
+        // Elements can be removed by rebuilding the chain
+        let mut inserts = chain.clone().into_inserts();
+        inserts.retain(|i| i.latency_frames() == 0);
+        chain.replace(EffectsChain::new(inserts));
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain.latency_frames(), 0);
+    }
+
+    #[test]
+    fn test_builtin_elements() {
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let mut chain = EffectsChain::default()
+            .with(DcBlocker::default())
+            .with(VolumeLimiter::default());
+
+        // A constant offset is removed and the output never clips
+        let mut buffer = vec![4.0; 48000 * 2];
+        chain.process(&mut buffer, &stream_params);
+        assert!(buffer.iter().all(|s| s.abs() < 1.0));
+        assert!(buffer[buffer.len() - 1].abs() < 0.01);
+    }
+}
@@ -0,0 +1,359 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc, Mutex,
+};
+
+use super::AudioInsert;
+use crate::{
+    util::{amp_to_db, db_to_amp},
+    AudioStreamParams,
+};
+
+/// The settings of a `Compressor`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(default)
+)]
+pub struct CompressorOptions {
+    /// The level above which the gain is reduced, in dBFS.
+    ///
+    /// Default: `-18.0`
+    pub threshold_db: f32,
+
+    /// The ratio of the input level above the threshold to the output level
+    /// above it, e.g. `4.0` for 4:1. `1.0` leaves the audio unchanged.
+    ///
+    /// Default: `4.0`
+    pub ratio: f32,
+
+    /// The time the gain reduction takes to rise, in milliseconds. `0.0`
+    /// reduces the gain at the first sample above the threshold.
+    ///
+    /// Default: `10.0`
+    pub attack_ms: f32,
+
+    /// The time the gain reduction takes to fall, in milliseconds.
+    ///
+    /// Default: `100.0`
+    pub release_ms: f32,
+
+    /// The gain added to the compressed audio, in decibels.
+    ///
+    /// Default: `0.0`
+    pub makeup_db: f32,
+
+    /// The width of the range around the threshold over which the ratio is
+    /// gradually applied, in decibels. `0.0` is a hard knee.
+    ///
+    /// Default: `0.0`
+    pub knee_db: f32,
+}
+
+impl Default for CompressorOptions {
+    fn default() -> Self {
+        Self {
+            threshold_db: -18.0,
+            ratio: 4.0,
+            attack_ms: 10.0,
+            release_ms: 100.0,
+            makeup_db: 0.0,
+            knee_db: 0.0,
+        }
+    }
+}
+
+impl CompressorOptions {
+    /// Returns the gain reduction in decibels for a constant input level in
+    /// dBFS, before the makeup gain, e.g. to draw the curve of the
+    /// compressor.
+    pub fn static_gain_reduction_db(&self, level_db: f32) -> f32 {
+        let slope = 1.0 - 1.0 / self.ratio.max(1.0);
+        let over = level_db - self.threshold_db;
+        let knee = self.knee_db.max(0.0);
+
+        if 2.0 * over <= -knee {
+            0.0
+        } else if 2.0 * over < knee {
+            slope * (over + knee / 2.0).powi(2) / (2.0 * knee)
+        } else {
+            slope * over
+        }
+    }
+}
+
+/// The audio of a channel sent to the sidechain input of compressors, e.g.
+/// to duck pads under the drums. Set it as the sidechain send of the
+/// source channel with `ChannelConfigEvent::SetSidechainSend`, and as the
+/// sidechain of a compressor with `Compressor::with_sidechain`.
+///
+/// The channel sends the audio of its voices, before its volume, pan,
+/// cutoff and insert are applied. Synthesizers render the channels with a
+/// sidechain send before the others, so compressors get the audio of the
+/// same buffer without any latency.
+///
+/// The tap is cheap to clone, and the clones share the same audio.
+#[derive(Clone, Debug, Default)]
+pub struct SidechainTap {
+    levels: Arc<Mutex<Vec<f32>>>,
+}
+
+impl SidechainTap {
+    /// Creates a new tap without any audio.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Removes the audio of the previous buffer.
+    pub(crate) fn clear(&self) {
+        self.levels.lock().unwrap().clear();
+    }
+
+    /// Appends the peak level of each frame of the given audio, which is
+    /// interleaved with the given number of channels.
+    pub(crate) fn push(&self, audio: &[f32], channels: usize) {
+        let mut levels = self.levels.lock().unwrap();
+        levels.extend(
+            audio
+                .chunks_exact(channels)
+                .map(|frame| frame.iter().fold(0.0f32, |max, s| max.max(s.abs()))),
+        );
+    }
+}
+
+/// Reads the gain reduction of a `Compressor` from any thread, e.g. to
+/// display it while the compressor is part of the master effects. See
+/// `Compressor::meter`.
+#[derive(Clone, Debug, Default)]
+pub struct GainReductionMeter {
+    reduction: Arc<AtomicU32>,
+}
+
+impl GainReductionMeter {
+    /// Returns the highest gain reduction of the last processed buffer, in
+    /// decibels.
+    pub fn gain_reduction_db(&self) -> f32 {
+        f32::from_bits(self.reduction.load(Ordering::Relaxed))
+    }
+
+    fn store(&self, reduction: f32) {
+        self.reduction.store(reduction.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// A compressor insert, which reduces the gain of the audio above a
+/// threshold. It can be used as the insert of a channel or as an element
+/// of the master `EffectsChain`.
+///
+/// The level is detected from the peak of all the audio channels, so the
+/// stereo image is kept. With a sidechain, the level is detected from the
+/// audio of another channel instead, see `SidechainTap`.
+#[derive(Clone, Debug)]
+pub struct Compressor {
+    options: CompressorOptions,
+    sidechain: Option<SidechainTap>,
+    reduction: f32,
+    meter: GainReductionMeter,
+}
+
+impl Compressor {
+    /// Creates a new compressor with the given settings.
+    pub fn new(options: CompressorOptions) -> Self {
+        Self {
+            options,
+            sidechain: None,
+            reduction: 0.0,
+            meter: Default::default(),
+        }
+    }
+
+    /// Detects the level from the audio sent to the given tap instead of
+    /// the processed audio. Frames the tap has no audio for are detected as
+    /// silent.
+    pub fn with_sidechain(mut self, sidechain: SidechainTap) -> Self {
+        self.sidechain = Some(sidechain);
+        self
+    }
+
+    /// Returns the settings of the compressor.
+    pub fn options(&self) -> &CompressorOptions {
+        &self.options
+    }
+
+    /// Returns a meter of the gain reduction, which keeps working after the
+    /// compressor is moved into a channel or an effects chain. Clones of the
+    /// compressor share the same meter.
+    pub fn meter(&self) -> GainReductionMeter {
+        self.meter.clone()
+    }
+
+    /// Returns the smoothing coefficient of the given time constant.
+    fn coefficient(time_ms: f32, sample_rate: u32) -> f32 {
+        if time_ms <= 0.0 {
+            0.0
+        } else {
+            (-1000.0 / (time_ms * sample_rate as f32)).exp()
+        }
+    }
+
+    /// Applies the gain reduction for the given detected level to a frame.
+    fn process_frame(&mut self, frame: &mut [f32], level: f32, attack: f32, release: f32) -> f32 {
+        let target = self.options.static_gain_reduction_db(amp_to_db(level));
+        let coefficient = if target > self.reduction {
+            attack
+        } else {
+            release
+        };
+        self.reduction = target + coefficient * (self.reduction - target);
+
+        let gain = db_to_amp(self.options.makeup_db - self.reduction);
+        for sample in frame.iter_mut() {
+            *sample *= gain;
+        }
+        self.reduction
+    }
+}
+
+impl Default for Compressor {
+    /// A compressor with the default `CompressorOptions`.
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl AudioInsert for Compressor {
+    fn process(&mut self, buffer: &mut [f32], stream_params: &AudioStreamParams) {
+        let channel_count = stream_params.channels.count() as usize;
+        let attack = Self::coefficient(self.options.attack_ms, stream_params.sample_rate);
+        let release = Self::coefficient(self.options.release_ms, stream_params.sample_rate);
+
+        let mut max_reduction = 0.0f32;
+        match self.sidechain.clone() {
+            Some(sidechain) => {
+                let levels = sidechain.levels.lock().unwrap();
+                for (i, frame) in buffer.chunks_mut(channel_count).enumerate() {
+                    let level = levels.get(i).copied().unwrap_or(0.0);
+                    let reduction = self.process_frame(frame, level, attack, release);
+                    max_reduction = max_reduction.max(reduction);
+                }
+            }
+            None => {
+                for frame in buffer.chunks_mut(channel_count) {
+                    let level = frame.iter().fold(0.0f32, |max, s| max.max(s.abs()));
+                    let reduction = self.process_frame(frame, level, attack, release);
+                    max_reduction = max_reduction.max(reduction);
+                }
+            }
+        }
+        self.meter.store(max_reduction);
+    }
+
+    fn gain_reduction_db(&self) -> Option<f32> {
+        Some(self.meter.gain_reduction_db())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChannelCount;
+
+    /// Renders a stereo sine with the given peak amplitude.
+    fn sine(amplitude: f32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .flat_map(|i| {
+                let sample = amplitude * (i as f32 * 440.0 / 48000.0 * std::f32::consts::TAU).sin();
+                [sample, sample]
+            })
+            .collect()
+    }
+
+    fn peak_db(buffer: &[f32]) -> f32 {
+        amp_to_db(buffer.iter().fold(0.0f32, |max, s| max.max(s.abs())))
+    }
+
+    #[test]
+    fn test_static_curve() {
+        let options = CompressorOptions {
+            threshold_db: -20.0,
+            ratio: 4.0,
+            ..Default::default()
+        };
+        assert_eq!(options.static_gain_reduction_db(-30.0), 0.0);
+        assert_eq!(options.static_gain_reduction_db(-20.0), 0.0);
+        assert_eq!(options.static_gain_reduction_db(-8.0), 9.0);
+
+        // The soft knee starts below the threshold and meets the hard knee
+        // curve at its end
+        let soft = CompressorOptions {
+            knee_db: 6.0,
+            ..options
+        };
+        assert_eq!(soft.static_gain_reduction_db(-23.0), 0.0);
+        assert_eq!(soft.static_gain_reduction_db(-20.0), 0.5625);
+        assert_eq!(soft.static_gain_reduction_db(-17.0), 2.25);
+        assert_eq!(soft.static_gain_reduction_db(-8.0), 9.0);
+    }
+
+    #[test]
+    fn test_sine_ratio() {
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        for (ratio, makeup_db) in [(2.0, 0.0), (4.0, 0.0), (10.0, 6.0)] {
+            let options = CompressorOptions {
+                threshold_db: -18.0,
+                ratio,
+                attack_ms: 0.0,
+                makeup_db,
+                ..Default::default()
+            };
+            let mut compressor = Compressor::new(options);
+
+            let input = sine(0.5, 48000);
+            let mut output = input.clone();
+            compressor.process(&mut output, &stream_params);
+
+            // Each dB above the threshold becomes 1 / ratio dB
+            let tail = input.len() / 2;
+            let over = peak_db(&input[tail..]) + 18.0;
+            let expected = -18.0 + over / ratio + makeup_db;
+            assert!((peak_db(&output[tail..]) - expected).abs() < 0.05);
+            let reduction = compressor.gain_reduction_db().unwrap();
+            assert!((reduction - (over - over / ratio)).abs() < 0.05);
+        }
+
+        // A quiet sine is left unchanged
+        let mut compressor = Compressor::default();
+        let input = sine(0.05, 4800);
+        let mut output = input.clone();
+        compressor.process(&mut output, &stream_params);
+        assert_eq!(input, output);
+        assert_eq!(compressor.meter().gain_reduction_db(), 0.0);
+    }
+
+    #[test]
+    fn test_sidechain() {
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let tap = SidechainTap::new();
+        let mut compressor = Compressor::new(CompressorOptions {
+            threshold_db: -30.0,
+            ratio: 10.0,
+            attack_ms: 0.0,
+            ..Default::default()
+        })
+        .with_sidechain(tap.clone());
+
+        // The quiet pad is ducked by the loud drums on the sidechain
+        tap.push(&sine(1.0, 480), 2);
+        let mut pad = vec![0.01; 480 * 2];
+        compressor.process(&mut pad, &stream_params);
+        assert!(pad[pad.len() - 1] < 0.01);
+        assert!(compressor.meter().gain_reduction_db() > 20.0);
+
+        // Without sidechain audio, it recovers
+        tap.clear();
+        let mut pad = vec![0.01; 48000 * 2];
+        compressor.process(&mut pad, &stream_params);
+        assert!((pad[pad.len() - 1] - 0.01).abs() < 1e-4);
+    }
+}
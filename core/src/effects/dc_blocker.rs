@@ -0,0 +1,57 @@
+use super::AudioInsert;
+use crate::{util::ANTI_DENORMAL, AudioStreamParams};
+
+#[derive(Clone, Copy, Debug, Default)]
+struct DcBlockerState {
+    last_input: f32,
+    last_output: f32,
+}
+
+/// An insert that removes the DC offset of the audio, using a one-pole
+/// high-pass filter with a very low cutoff.
+#[derive(Clone, Debug)]
+pub struct DcBlocker {
+    cutoff: f32,
+    channels: Vec<DcBlockerState>,
+}
+
+impl DcBlocker {
+    /// Creates a new DC blocker with the given cutoff frequency in Hz.
+    pub fn new(cutoff: f32) -> Self {
+        Self {
+            cutoff,
+            channels: Vec::new(),
+        }
+    }
+
+    /// Returns the cutoff frequency of the filter in Hz.
+    pub fn cutoff(&self) -> f32 {
+        self.cutoff
+    }
+}
+
+impl Default for DcBlocker {
+    /// A DC blocker with a cutoff of 10 Hz.
+    fn default() -> Self {
+        Self::new(10.0)
+    }
+}
+
+impl AudioInsert for DcBlocker {
+    fn process(&mut self, buffer: &mut [f32], stream_params: &AudioStreamParams) {
+        let channel_count = stream_params.channels.count() as usize;
+        self.channels.resize(channel_count, Default::default());
+
+        let pole =
+            (-2.0 * std::f32::consts::PI * self.cutoff / stream_params.sample_rate as f32).exp();
+
+        for frame in buffer.chunks_mut(channel_count) {
+            for (sample, state) in frame.iter_mut().zip(self.channels.iter_mut()) {
+                let output = *sample - state.last_input + pole * state.last_output;
+                state.last_input = *sample;
+                state.last_output = output + ANTI_DENORMAL;
+                *sample = output;
+            }
+        }
+    }
+}
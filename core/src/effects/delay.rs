@@ -0,0 +1,332 @@
+use std::fmt;
+
+use super::{AudioInsert, NoteValue, TempoClock};
+use crate::AudioStreamParams;
+
+/// The longest delay time of a `StereoDelay`, in seconds. Longer times are
+/// clamped to it.
+pub const MAX_DELAY_SECONDS: f32 = 4.0;
+
+/// The time the read head of a `StereoDelay` takes to move to a new delay
+/// time, in milliseconds.
+const CROSSFADE_MS: f32 = 50.0;
+
+/// The time between the input of a `StereoDelay` and its echoes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum DelayTime {
+    /// A fixed time in milliseconds.
+    Milliseconds(f32),
+
+    /// A note value at the tempo of the delay's `TempoClock`, which follows
+    /// the tempo changes.
+    Synced(NoteValue),
+}
+
+impl DelayTime {
+    /// Returns the time in seconds at the given tempo.
+    pub fn seconds(&self, bpm: f32) -> f32 {
+        match self {
+            DelayTime::Milliseconds(ms) => ms / 1000.0,
+            DelayTime::Synced(note) => note.seconds(bpm),
+        }
+    }
+}
+
+/// The settings of a `StereoDelay`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(default)
+)]
+pub struct DelayOptions {
+    /// The time between the echoes.
+    ///
+    /// Default: `DelayTime::Synced(NoteValue::new(8))`
+    pub time: DelayTime,
+
+    /// The gain of each echo relative to the previous one, below `1.0`.
+    ///
+    /// Default: `0.35`
+    pub feedback: f32,
+
+    /// The balance between the input (`0.0`) and the echoes (`1.0`).
+    ///
+    /// Default: `0.3`
+    pub mix: f32,
+
+    /// Alternates the echoes between the left and the right channel. The
+    /// input of both channels is sent to the first echo, on the left. Only
+    /// applies to the first two channels of audio with more than one.
+    ///
+    /// Default: `false`
+    pub ping_pong: bool,
+}
+
+impl Default for DelayOptions {
+    fn default() -> Self {
+        Self {
+            time: DelayTime::Synced(NoteValue::new(8)),
+            feedback: 0.35,
+            mix: 0.3,
+            ping_pong: false,
+        }
+    }
+}
+
+/// A delay insert with feedback, whose time can be synced to the tempo of
+/// the synthesizer. It can be used as the insert of a channel or as an
+/// element of the master `EffectsChain`.
+///
+/// Each audio channel is delayed separately, unless the echoes ping-pong
+/// between the left and the right channel. When the delay time changes,
+/// e.g. with the tempo, the echoes crossfade from the old time to the new
+/// one over 50 ms instead of jumping, so they don't click.
+///
+/// The delay lines hold `MAX_DELAY_SECONDS` of audio, and are allocated when
+/// the delay is created for the given stream parameters.
+#[derive(Clone)]
+pub struct StereoDelay {
+    options: DelayOptions,
+    tempo: TempoClock,
+    sample_rate: u32,
+    channels: usize,
+
+    /// The delay lines of the audio channels, interleaved like the audio
+    lines: Vec<f32>,
+    line_frames: usize,
+    write: usize,
+
+    /// The current delay in frames
+    delay: usize,
+
+    /// The previous delay in frames and the progress of the crossfade from
+    /// it, while the delay time changes
+    fade_from: Option<(usize, usize)>,
+    fade_frames: usize,
+}
+
+impl StereoDelay {
+    /// Creates a new delay with the given settings for audio with the given
+    /// stream parameters. Synced times use the default tempo until a clock
+    /// is set with `with_tempo_clock`.
+    pub fn new(options: DelayOptions, stream_params: AudioStreamParams) -> Self {
+        let sample_rate = stream_params.sample_rate;
+        let channels = stream_params.channels.count() as usize;
+        let line_frames = (MAX_DELAY_SECONDS * sample_rate as f32) as usize + 1;
+
+        let mut delay = Self {
+            options,
+            tempo: TempoClock::default(),
+            sample_rate,
+            channels,
+            lines: vec![0.0; line_frames * channels],
+            line_frames,
+            write: 0,
+            delay: 1,
+            fade_from: None,
+            fade_frames: (CROSSFADE_MS / 1000.0 * sample_rate as f32) as usize,
+        };
+        delay.delay = delay.target_delay();
+        delay
+    }
+
+    /// Syncs the delay time to the tempo of the given clock, e.g. the clock
+    /// of a synthesizer from `ChannelGroup::tempo_clock`.
+    pub fn with_tempo_clock(mut self, tempo: TempoClock) -> Self {
+        self.tempo = tempo;
+        self.delay = self.target_delay();
+        self
+    }
+
+    /// Returns the settings of the delay.
+    pub fn options(&self) -> &DelayOptions {
+        &self.options
+    }
+
+    /// Returns the current delay time in frames.
+    pub fn delay_frames(&self) -> usize {
+        self.delay
+    }
+
+    /// The delay in frames for the current time and tempo.
+    fn target_delay(&self) -> usize {
+        let seconds = self.options.time.seconds(self.tempo.bpm());
+        let frames = (seconds * self.sample_rate as f32).round();
+        (frames.max(1.0) as usize).min(self.line_frames - 1)
+    }
+
+    #[inline(always)]
+    fn read(&self, channel: usize, delay: usize) -> f32 {
+        let frame = (self.write + self.line_frames - delay) % self.line_frames;
+        self.lines[frame * self.channels + channel]
+    }
+}
+
+impl fmt::Debug for StereoDelay {
+    // The delay lines are too long to print
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StereoDelay")
+            .field("options", &self.options)
+            .field("tempo", &self.tempo)
+            .field("delay", &self.delay)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AudioInsert for StereoDelay {
+    fn process(&mut self, buffer: &mut [f32], stream_params: &AudioStreamParams) {
+        if stream_params.channels.count() as usize != self.channels
+            || stream_params.sample_rate != self.sample_rate
+        {
+            *self = Self::new(self.options, *stream_params).with_tempo_clock(self.tempo.clone());
+        }
+
+        // A change during a crossfade waits for it to end
+        let target = self.target_delay();
+        if target != self.delay && self.fade_from.is_none() {
+            self.fade_from = Some((self.delay, 0));
+            self.delay = target;
+        }
+
+        let channels = self.channels;
+        let feedback = self.options.feedback.clamp(0.0, 0.99);
+        let mix = self.options.mix.clamp(0.0, 1.0);
+        let ping_pong = self.options.ping_pong && channels > 1;
+
+        let mut delayed = [0.0f32; 8];
+        for frame in buffer.chunks_exact_mut(channels) {
+            for (channel, delayed) in delayed.iter_mut().enumerate().take(channels) {
+                *delayed = match self.fade_from {
+                    Some((from, position)) => {
+                        let fade = (position as f32 + 0.5) / self.fade_frames as f32;
+                        let previous = self.read(channel, from);
+                        previous + (self.read(channel, self.delay) - previous) * fade
+                    }
+                    None => self.read(channel, self.delay),
+                };
+            }
+
+            let line = self.write * channels;
+            let mono = if ping_pong {
+                (frame[0] + frame[1]) * 0.5
+            } else {
+                0.0
+            };
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let input = match channel {
+                    0 if ping_pong => mono + delayed[1] * feedback,
+                    1 if ping_pong => delayed[0] * feedback,
+                    _ => *sample + delayed[channel] * feedback,
+                };
+                self.lines[line + channel] = input;
+                *sample += (delayed[channel] - *sample) * mix;
+            }
+
+            self.write = (self.write + 1) % self.line_frames;
+            if let Some((_, position)) = &mut self.fade_from {
+                *position += 1;
+                if *position >= self.fade_frames {
+                    self.fade_from = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChannelCount;
+
+    /// Returns the frame and the value of each nonzero sample of a channel.
+    fn echoes(buffer: &[f32], channel: usize) -> Vec<(usize, f32)> {
+        buffer
+            .iter()
+            .skip(channel)
+            .step_by(2)
+            .enumerate()
+            .filter(|(_, s)| s.abs() > 1e-6)
+            .map(|(i, s)| (i, *s))
+            .collect()
+    }
+
+    #[test]
+    fn test_synced_echoes() {
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let options = DelayOptions {
+            time: DelayTime::Synced(NoteValue::new(8)),
+            feedback: 0.5,
+            mix: 1.0,
+            ping_pong: false,
+        };
+        let clock = TempoClock::new(120.0);
+        let mut delay = StereoDelay::new(options, stream_params).with_tempo_clock(clock.clone());
+
+        // An eighth note at 120 BPM is 12000 frames
+        let mut buffer = vec![0.0; 30000 * 2];
+        buffer[0] = 1.0;
+        delay.process(&mut buffer, &stream_params);
+        assert_eq!(echoes(&buffer, 0), [(12000, 1.0), (24000, 0.5)]);
+        assert!(echoes(&buffer, 1).is_empty());
+
+        // A dotted quarter note at 90 BPM is 48000 frames
+        clock.set_bpm(90.0);
+        let mut delay = StereoDelay::new(
+            DelayOptions {
+                time: DelayTime::Synced(NoteValue::dotted(4)),
+                ping_pong: true,
+                ..options
+            },
+            stream_params,
+        )
+        .with_tempo_clock(clock);
+        let mut buffer = vec![0.0; 100000 * 2];
+        buffer[0] = 1.0;
+        buffer[1] = 1.0;
+        delay.process(&mut buffer, &stream_params);
+
+        // The echoes alternate between the channels
+        assert_eq!(echoes(&buffer, 0), [(48000, 1.0)]);
+        assert_eq!(echoes(&buffer, 1), [(96000, 0.5)]);
+    }
+
+    #[test]
+    fn test_tempo_change_crossfade() {
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let options = DelayOptions {
+            feedback: 0.0,
+            mix: 1.0,
+            ..Default::default()
+        };
+        let clock = TempoClock::new(120.0);
+        let mut delay = StereoDelay::new(options, stream_params).with_tempo_clock(clock.clone());
+
+        let amplitude = 0.5;
+        let step = 440.0 / 48000.0 * std::f32::consts::TAU;
+        let mut phase = 0.0f32;
+        let mut output = Vec::new();
+        for bpm in [120.0, 100.0, 100.0] {
+            clock.set_bpm(bpm);
+            let mut buffer: Vec<f32> = (0..24000)
+                .flat_map(|_| {
+                    phase += step;
+                    let sample = amplitude * phase.sin();
+                    [sample, sample]
+                })
+                .collect();
+            delay.process(&mut buffer, &stream_params);
+            output.extend(buffer.into_iter().step_by(2));
+        }
+
+        // The echoes follow the tempo, an eighth note at 100 BPM being
+        // 14400 frames
+        assert_eq!(delay.delay_frames(), 14400);
+
+        // The delayed sine never jumps by more than its own slope allows
+        let max_step = amplitude * step * 1.1;
+        let jumps = output.windows(2).skip(12000);
+        assert!(jumps.into_iter().all(|w| (w[1] - w[0]).abs() < max_step));
+    }
+}
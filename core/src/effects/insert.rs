@@ -0,0 +1,143 @@
+use crate::{util::db_to_amp, AudioStreamParams};
+
+/// A user-provided effect that processes the output of a channel, e.g. a
+/// cabinet simulation. Set it with `ChannelConfigEvent::SetInsert`.
+///
+/// The insert is applied after the channel's voices are mixed and the
+/// channel's volume, pan and cutoff are applied, before the channel is
+/// mixed with the others.
+///
+/// Inserts can also be used as elements of the master `EffectsChain`, which
+/// processes the mixed output of all channels.
+///
+/// Inserts are cloned when the event is sent to multiple channels, so each
+/// channel gets its own instance. Deriving `Clone` is enough to implement
+/// `AudioInsertClone`.
+pub trait AudioInsert: AudioInsertClone + Send + std::fmt::Debug {
+    /// Processes a buffer of the channel's audio in place. The samples are
+    /// interleaved with the channel count of `stream_params`.
+    fn process(&mut self, buffer: &mut [f32], stream_params: &AudioStreamParams);
+
+    /// Returns the delay the insert adds to the audio, in frames.
+    ///
+    /// Default: `0`
+    fn latency_frames(&self) -> usize {
+        0
+    }
+
+    /// Returns the current gain reduction of the insert in decibels, for
+    /// inserts that reduce the gain of loud audio like a `Compressor`. For
+    /// channel inserts, it is published in the statistics of the channel,
+    /// see `VoiceChannelStatsReader::insert_gain_reduction_db`.
+    ///
+    /// Default: `None`
+    fn gain_reduction_db(&self) -> Option<f32> {
+        None
+    }
+}
+
+/// Clones boxed `AudioInsert` objects. Implemented for all inserts that
+/// implement `Clone`.
+pub trait AudioInsertClone {
+    fn clone_box(&self) -> Box<dyn AudioInsert>;
+}
+
+impl<T: 'static + AudioInsert + Clone> AudioInsertClone for T {
+    fn clone_box(&self) -> Box<dyn AudioInsert> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn AudioInsert> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// An insert that multiplies the audio by a constant gain.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GainInsert {
+    gain: f32,
+}
+
+impl GainInsert {
+    /// Creates a new insert with the given linear gain.
+    pub fn new(gain: f32) -> Self {
+        Self { gain }
+    }
+
+    /// Creates a new insert with the given gain in decibels.
+    pub fn from_db(db: f32) -> Self {
+        Self::new(db_to_amp(db))
+    }
+
+    /// Returns the linear gain of the insert.
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+}
+
+impl AudioInsert for GainInsert {
+    fn process(&mut self, buffer: &mut [f32], _stream_params: &AudioStreamParams) {
+        for sample in buffer.iter_mut() {
+            *sample *= self.gain;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        channel::{ChannelConfigEvent, ChannelEvent, ChannelInitOptions, VoiceChannel},
+        AudioPipe, ChannelCount,
+    };
+
+    /// An insert that replaces the audio with a constant value.
+    #[derive(Clone, Debug)]
+    struct ConstantInsert(f32);
+
+    impl AudioInsert for ConstantInsert {
+        fn process(&mut self, buffer: &mut [f32], _stream_params: &AudioStreamParams) {
+            buffer.fill(self.0);
+        }
+    }
+
+    #[test]
+    fn test_gain_insert() {
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let mut buffer = vec![0.5, -1.0, 0.25, 0.0];
+
+        let mut insert = GainInsert::new(2.0);
+        insert.process(&mut buffer, &stream_params);
+        assert_eq!(buffer, vec![1.0, -2.0, 0.5, 0.0]);
+
+        let insert: Box<dyn AudioInsert> = Box::new(GainInsert::from_db(-6.0));
+        insert.clone().process(&mut buffer, &stream_params);
+        assert!((buffer[0] - 0.501).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_channel_insert() {
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let mut channel = VoiceChannel::new(ChannelInitOptions::default(), stream_params, None);
+        let mut buffer = vec![0.0; 64];
+
+        channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetInsert(Some(
+            Box::new(ConstantInsert(0.5)),
+        ))));
+        channel.read_samples(&mut buffer);
+        assert!(buffer.iter().all(|&s| s == 0.5));
+
+        // The new insert replaces the old one, and the channel is silent
+        channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetInsert(Some(
+            Box::new(GainInsert::new(2.0)),
+        ))));
+        channel.read_samples(&mut buffer);
+        assert!(buffer.iter().all(|&s| s == 0.0));
+
+        channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetInsert(None)));
+        channel.read_samples(&mut buffer);
+        assert!(buffer.iter().all(|&s| s == 0.0));
+    }
+}
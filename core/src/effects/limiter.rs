@@ -1,5 +1,9 @@
 use std::marker::PhantomData;
 
+use super::AudioInsert;
+use crate::{util::ANTI_DENORMAL, AudioStreamParams};
+
+#[derive(Clone, Debug)]
 struct SingleChannelLimiter {
     loudness: f32,
     attack: f32,
@@ -21,27 +25,42 @@ impl SingleChannelLimiter {
         }
     }
 
-    fn limit(&mut self, val: f32) -> f32 {
+    /// Updates the loudness detector with the next sample.
+    fn detect(&mut self, val: f32) {
         let abs = val.abs();
         
         // Smooth envelope follower with different attack/release times
         if self.loudness > abs {
-            // Release phase: slower decay
-            self.loudness = (self.loudness * self.falloff + abs) / (self.falloff + 1.0);
+            // Release phase: slower decay, which settles on the anti-denormal
+            // offset in silence
+            self.loudness =
+                (self.loudness * self.falloff + abs) / (self.falloff + 1.0) + ANTI_DENORMAL;
         } else {
             // Attack phase: faster response
             self.loudness = (self.loudness * self.attack + abs) / (self.attack + 1.0);
         }
+    }
 
+    /// Returns the gain reduction for the given detected loudness.
+    fn gain_reduction(&self, loudness: f32) -> f32 {
         // Ensure minimum threshold to prevent division by very small numbers
-        let effective_loudness = self.loudness.max(self.min_thresh);
+        let effective_loudness = loudness.max(self.min_thresh);
 
         // Calculate gain reduction: when loudness is high, reduce more
         // The formula now uses a softer knee to prevent hard limiting artifacts
-        let gain_reduction = 1.0 / (1.0 + (effective_loudness - 1.0).max(0.0) * self.strength);
-        
+        1.0 / (1.0 + (effective_loudness - 1.0).max(0.0) * self.strength)
+    }
+
+    /// Applies the gain reduction and the final clipping stage to a sample.
+    fn apply(&self, val: f32, gain_reduction: f32, clip_mode: ClipMode) -> f32 {
         // Apply limiting with soft clipping for values near the threshold
         let limited = val * gain_reduction;
+
+        match clip_mode {
+            ClipMode::HardClip => return limited.clamp(-1.0, 1.0),
+            ClipMode::None => return limited,
+            ClipMode::SoftClip => {}
+        }
         
         // Soft clipping to prevent any hard digital clipping
         // Using tanh-like soft clipping for smooth transition
@@ -59,12 +78,80 @@ impl SingleChannelLimiter {
     }
 }
 
+/// How the channels of a `VolumeLimiter` share their gain reduction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum LimiterLinking {
+    /// Linked for stereo audio and independent for any other channel count.
+    #[default]
+    Auto,
+
+    /// The gain reduction is computed from the loudest channel and applied
+    /// equally to all channels, so a transient on one side doesn't shift the
+    /// stereo image.
+    Linked,
+
+    /// Each channel is limited on its own, e.g. for multichannel stems that
+    /// are mixed later.
+    Independent,
+}
+
+/// The final stage of a `VolumeLimiter`, applied after the gain reduction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ClipMode {
+    /// Samples above 0.95 are compressed with a tanh-like soft knee, and the
+    /// output is kept within ±0.99.
+    #[default]
+    SoftClip,
+
+    /// Samples are clamped to ±1.0.
+    HardClip,
+
+    /// No clipping at all, so samples may exceed ±1.0. Useful for float
+    /// renders that are processed further, where the headroom is kept.
+    None,
+}
+
+/// Options for a `VolumeLimiter`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(default)
+)]
+pub struct LimiterConfig {
+    /// How the channels share their gain reduction. See the `LimiterLinking`
+    /// documentation for available options.
+    ///
+    /// Default: `Auto`
+    pub linking: LimiterLinking,
+
+    /// The clipping applied to the output after the gain reduction. See the
+    /// `ClipMode` documentation for available options.
+    ///
+    /// Default: `SoftClip`
+    pub clip_mode: ClipMode,
+}
+
 /// A multi-channel audio limiter.
 ///
-/// Can be useful to prevent clipping on loud audio.
+/// Can be useful to prevent clipping on loud audio. It can also be used as
+/// an insert, e.g. in the master `EffectsChain`, where it adapts to the
+/// channel count of the stream.
+#[derive(Clone, Debug)]
 pub struct VolumeLimiter {
     channels: Vec<SingleChannelLimiter>,
     channel_count: usize,
+    config: LimiterConfig,
+    linked: bool,
+}
+
+impl Default for VolumeLimiter {
+    /// A stereo limiter.
+    fn default() -> Self {
+        Self::new(2)
+    }
 }
 
 pub struct VolumeLimiterIter<'a, 'b, T: 'b + Iterator<Item = f32>> {
@@ -77,20 +164,51 @@ pub struct VolumeLimiterIter<'a, 'b, T: 'b + Iterator<Item = f32>> {
 impl VolumeLimiter {
     /// Initializes a new audio limiter with a specified audio channel count.
     pub fn new(channel_count: u16) -> VolumeLimiter {
+        Self::with_config(channel_count, Default::default())
+    }
+
+    /// Initializes a new audio limiter with a specified audio channel count
+    /// and options. See the `LimiterConfig` documentation for more
+    /// information.
+    pub fn with_config(channel_count: u16, config: LimiterConfig) -> VolumeLimiter {
         let mut limiters = Vec::new();
         for _ in 0..channel_count {
             limiters.push(SingleChannelLimiter::new());
         }
+        let linked = match config.linking {
+            LimiterLinking::Auto => channel_count == 2,
+            LimiterLinking::Linked => true,
+            LimiterLinking::Independent => false,
+        };
         VolumeLimiter {
             channels: limiters,
             channel_count: channel_count as usize,
+            config,
+            linked,
         }
     }
 
+    /// Limits a single sample of the given channel.
+    ///
+    /// When linked, the gain reduction follows the loudest channel. The
+    /// detectors of the channels that come later in the frame still hold the
+    /// previous frame, which delays their reduction by a single frame.
+    fn limit_sample(&mut self, channel: usize, val: f32) -> f32 {
+        self.channels[channel].detect(val);
+        let loudness = if self.linked {
+            self.channels.iter().map(|c| c.loudness).fold(0.0, f32::max)
+        } else {
+            self.channels[channel].loudness
+        };
+
+        let limiter = &self.channels[channel];
+        limiter.apply(val, limiter.gain_reduction(loudness), self.config.clip_mode)
+    }
+
     /// Applies the limiting algorithm to the given sample buffer to prevent clipping.
     pub fn limit(&mut self, sample: &mut [f32]) {
         for (i, s) in sample.iter_mut().enumerate() {
-            *s = self.channels[i % self.channel_count].limit(*s);
+            *s = self.limit_sample(i % self.channel_count, *s);
         }
     }
 
@@ -104,8 +222,8 @@ impl VolumeLimiter {
             fn next(&mut self) -> Option<Self::Item> {
                 let next = self.samples.next();
                 if let Some(next) = next {
-                    let val =
-                        self.limiter.channels[self.pos % self.limiter.channel_count].limit(next);
+                    let channel = self.pos % self.limiter.channel_count;
+                    let val = self.limiter.limit_sample(channel, next);
                     self.pos += 1;
                     Some(val)
                 } else {
@@ -122,3 +240,67 @@ impl VolumeLimiter {
         }
     }
 }
+
+impl AudioInsert for VolumeLimiter {
+    fn process(&mut self, buffer: &mut [f32], stream_params: &AudioStreamParams) {
+        let channel_count = stream_params.channels.count();
+        if channel_count as usize != self.channel_count {
+            *self = VolumeLimiter::with_config(channel_count, self.config);
+        }
+        self.limit(buffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds a loud impulse on the left channel only, followed by a quiet
+    /// signal on both channels, and returns the last output frame.
+    fn limit_left_impulse(linking: LimiterLinking) -> (f32, f32) {
+        let config = LimiterConfig {
+            linking,
+            ..Default::default()
+        };
+        let mut limiter = VolumeLimiter::with_config(2, config);
+
+        let mut impulse: Vec<f32> = (0..2000).flat_map(|_| [4.0, 0.0]).collect();
+        limiter.limit(&mut impulse);
+
+        let mut quiet: Vec<f32> = (0..100).flat_map(|_| [0.2, 0.2]).collect();
+        limiter.limit(&mut quiet);
+        (quiet[quiet.len() - 2], quiet[quiet.len() - 1])
+    }
+
+    #[test]
+    fn test_linked_limiter() {
+        let (left, right) = limit_left_impulse(LimiterLinking::Auto);
+        assert!(left < 0.1);
+        assert!((left - right).abs() < 1e-6);
+
+        // Independent channels only duck the left side
+        let (left, right) = limit_left_impulse(LimiterLinking::Independent);
+        assert!(left < 0.1);
+        assert!(right > 0.19);
+    }
+
+    #[test]
+    fn test_clip_modes() {
+        let limit = |clip_mode| {
+            let config = LimiterConfig {
+                clip_mode,
+                ..Default::default()
+            };
+            let mut limiter = VolumeLimiter::with_config(1, config);
+            let mut samples = [1.2];
+            limiter.limit(&mut samples);
+            samples[0]
+        };
+
+        // The detector hasn't caught up with a single sample, so only the
+        // final stage affects it
+        assert!(limit(ClipMode::SoftClip) <= 0.99);
+        assert_eq!(limit(ClipMode::HardClip), 1.0);
+        assert!(limit(ClipMode::None) > 1.0);
+    }
+}
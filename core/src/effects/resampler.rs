@@ -0,0 +1,285 @@
+use std::f64::consts::PI;
+
+/// The interpolation used by the `StreamResampler`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ResamplerQuality {
+    /// Linear interpolation between neighbouring samples. Cheap, but
+    /// attenuates high frequencies and doesn't filter aliasing.
+    Linear,
+
+    /// A 16 tap windowed sinc polyphase filter, with the cutoff lowered
+    /// when downsampling to prevent aliasing.
+    #[default]
+    Polyphase,
+}
+
+/// Number of sinc taps on each side of the interpolated position.
+const HALF_TAPS: usize = 8;
+
+/// Number of phases of the polyphase filter bank.
+const PHASES: usize = 256;
+
+/// A streaming sample rate converter for interleaved audio.
+///
+/// The output is pulled in blocks of any size: `input_frames_needed` returns
+/// how many input frames have to be passed to `process` to produce the
+/// requested number of output frames. When the rates match, the audio is
+/// passed through unchanged.
+pub struct StreamResampler {
+    channels: usize,
+    quality: ResamplerQuality,
+
+    /// Input frames per output frame
+    ratio: f64,
+
+    /// The filter bank, `PHASES + 1` rows of `2 * HALF_TAPS` coefficients
+    filter: Vec<f32>,
+
+    /// Interleaved input kept for the filter history and lookahead
+    pending: Vec<f32>,
+
+    /// Position of the next output frame in `pending`, in frames
+    position: f64,
+}
+
+impl StreamResampler {
+    /// Initializes a new resampler.
+    ///
+    /// - `channels`: Number of audio channels
+    /// - `input_rate`: Sample rate of the audio to be processed
+    /// - `output_rate`: Sample rate of the produced audio
+    /// - `quality`: The interpolation used. See the `ResamplerQuality` docs
+    pub fn new(
+        channels: u16,
+        input_rate: u32,
+        output_rate: u32,
+        quality: ResamplerQuality,
+    ) -> Self {
+        let ratio = input_rate as f64 / output_rate as f64;
+        let channels = channels as usize;
+
+        let filter = match quality {
+            ResamplerQuality::Linear => Vec::new(),
+            ResamplerQuality::Polyphase => build_filter(ratio),
+        };
+
+        // Start with silent history so the first output frame can be filtered
+        let history = Self::history_frames(quality);
+        Self {
+            channels,
+            quality,
+            ratio,
+            filter,
+            pending: vec![0.0; history * channels],
+            position: history as f64,
+        }
+    }
+
+    fn history_frames(quality: ResamplerQuality) -> usize {
+        match quality {
+            ResamplerQuality::Linear => 0,
+            ResamplerQuality::Polyphase => HALF_TAPS - 1,
+        }
+    }
+
+    fn lookahead_frames(&self) -> usize {
+        match self.quality {
+            ResamplerQuality::Linear => 1,
+            ResamplerQuality::Polyphase => HALF_TAPS,
+        }
+    }
+
+    /// Returns true if the input and output rates match, in which case
+    /// `process` copies the input as is.
+    pub fn is_passthrough(&self) -> bool {
+        self.ratio == 1.0
+    }
+
+    /// The delay added by the resampler, in input frames.
+    pub fn latency_frames(&self) -> usize {
+        if self.is_passthrough() {
+            0
+        } else {
+            self.lookahead_frames()
+        }
+    }
+
+    /// Returns the number of input frames needed to produce the given
+    /// number of output frames.
+    pub fn input_frames_needed(&self, output_frames: usize) -> usize {
+        if self.is_passthrough() {
+            return output_frames;
+        }
+        if output_frames == 0 {
+            return 0;
+        }
+
+        let last = self.position + (output_frames - 1) as f64 * self.ratio;
+        let required = last.floor() as usize + self.lookahead_frames() + 1;
+        let available = self.pending.len() / self.channels;
+        required.saturating_sub(available)
+    }
+
+    /// Resamples the input into the output buffer. The input should contain
+    /// the number of frames returned by `input_frames_needed` for the length
+    /// of the output.
+    pub fn process(&mut self, input: &[f32], output: &mut [f32]) {
+        if self.is_passthrough() {
+            output.copy_from_slice(input);
+            return;
+        }
+
+        self.pending.extend_from_slice(input);
+        let channels = self.channels;
+        let available = self.pending.len() / channels;
+
+        for frame in output.chunks_mut(channels) {
+            let index = self.position.floor() as usize;
+            let fraction = self.position - index as f64;
+
+            // Treat missing input as silence rather than reading out of bounds
+            if index + self.lookahead_frames() >= available {
+                frame.fill(0.0);
+                self.position += self.ratio;
+                continue;
+            }
+
+            match self.quality {
+                ResamplerQuality::Linear => {
+                    let fraction = fraction as f32;
+                    let a = &self.pending[index * channels..];
+                    let b = &self.pending[(index + 1) * channels..];
+                    for (c, s) in frame.iter_mut().enumerate() {
+                        *s = a[c] + (b[c] - a[c]) * fraction;
+                    }
+                }
+                ResamplerQuality::Polyphase => {
+                    let phase = fraction * PHASES as f64;
+                    let row = phase.floor() as usize;
+                    let blend = (phase - row as f64) as f32;
+                    let taps = HALF_TAPS * 2;
+                    let low = &self.filter[row * taps..(row + 1) * taps];
+                    let high = &self.filter[(row + 1) * taps..(row + 2) * taps];
+
+                    let start = index + 1 - HALF_TAPS;
+                    frame.fill(0.0);
+                    for t in 0..taps {
+                        let coeff = low[t] + (high[t] - low[t]) * blend;
+                        let input = &self.pending[(start + t) * channels..];
+                        for (c, s) in frame.iter_mut().enumerate() {
+                            *s += input[c] * coeff;
+                        }
+                    }
+                }
+            }
+
+            self.position += self.ratio;
+        }
+
+        // Drop the input that is no longer needed, keeping the filter history
+        let keep_from = (self.position.floor() as usize)
+            .saturating_sub(Self::history_frames(self.quality))
+            .min(available);
+        self.pending.drain(..keep_from * channels);
+        self.position -= keep_from as f64;
+    }
+}
+
+/// Builds a Blackman windowed sinc filter bank. Row `p` holds the taps for
+/// the fractional position `p / PHASES`, with an extra row for interpolation.
+fn build_filter(ratio: f64) -> Vec<f32> {
+    // Lower the cutoff when downsampling, with a little headroom
+    let cutoff = (1.0 / ratio).min(1.0) * 0.95;
+    let taps = HALF_TAPS * 2;
+
+    let mut filter = Vec::with_capacity((PHASES + 1) * taps);
+    for phase in 0..=PHASES {
+        let fraction = phase as f64 / PHASES as f64;
+        let row_start = filter.len();
+        for t in 0..taps {
+            // Distance from the interpolated position to the tap
+            let x = t as f64 - (HALF_TAPS - 1) as f64 - fraction;
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                (PI * x * cutoff).sin() / (PI * x * cutoff)
+            };
+            let w = (x / HALF_TAPS as f64 + 1.0) / 2.0;
+            let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+            filter.push((sinc * window * cutoff) as f32);
+        }
+
+        // Normalize each phase to unity gain at DC
+        let sum: f32 = filter[row_start..].iter().sum();
+        for coeff in filter[row_start..].iter_mut() {
+            *coeff /= sum;
+        }
+    }
+    filter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resample_in_blocks(
+        resampler: &mut StreamResampler,
+        input: &[f32],
+        block: usize,
+    ) -> Vec<f32> {
+        let mut output = Vec::new();
+        let mut read = 0;
+        while read < input.len() {
+            let needed = resampler.input_frames_needed(block);
+            let end = (read + needed).min(input.len());
+            let mut out = vec![0.0; block];
+            resampler.process(&input[read..end], &mut out);
+            output.extend(out);
+            read = end;
+        }
+        output
+    }
+
+    #[test]
+    fn test_passthrough_is_bit_exact() {
+        let input: Vec<f32> = (0..4096)
+            .map(|i| ((i * 7919) % 1000) as f32 / 999.0)
+            .collect();
+        let mut resampler = StreamResampler::new(2, 48000, 48000, ResamplerQuality::Polyphase);
+        assert!(resampler.is_passthrough());
+        assert_eq!(resampler.latency_frames(), 0);
+
+        let mut output = vec![0.0; input.len()];
+        for (input, output) in input.chunks(256).zip(output.chunks_mut(256)) {
+            assert_eq!(resampler.input_frames_needed(128), 128);
+            resampler.process(input, output);
+        }
+        assert_eq!(input, output);
+    }
+
+    #[test]
+    fn test_sine_keeps_frequency_and_amplitude() {
+        for quality in [ResamplerQuality::Linear, ResamplerQuality::Polyphase] {
+            let freq = 1000.0;
+            let input: Vec<f32> = (0..48000)
+                .map(|i| (i as f64 / 48000.0 * freq * 2.0 * PI).sin() as f32)
+                .collect();
+
+            let mut resampler = StreamResampler::new(1, 48000, 44100, quality);
+            let output = resample_in_blocks(&mut resampler, &input, 441);
+
+            // The lookahead only delays the stream, so the output stays aligned
+            // with the input. The first frames are skipped as the filter starts
+            // from silence.
+            for (i, s) in output.iter().enumerate().skip(100).take(40000) {
+                let t = i as f64 / 44100.0;
+                let expected = (t * freq * 2.0 * PI).sin() as f32;
+                assert!(
+                    (s - expected).abs() < 0.02,
+                    "{quality:?} at {i}: {s} != {expected}"
+                );
+            }
+        }
+    }
+}
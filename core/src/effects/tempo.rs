@@ -0,0 +1,145 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+/// The default tempo of a `TempoClock`, in beats per minute.
+pub const DEFAULT_TEMPO_BPM: f32 = 120.0;
+
+/// The tempo of a synthesizer, shared with its tempo-synced effects such as
+/// `StereoDelay`. The tempo is set with `SynthEvent::SetTempo`, e.g. from
+/// the clock of a DAW, and the effects follow its changes. See
+/// `ChannelGroup::tempo_clock`.
+///
+/// The clock is cheap to clone, and the clones share the same tempo.
+#[derive(Clone, Debug)]
+pub struct TempoClock {
+    bpm: Arc<AtomicU32>,
+}
+
+impl TempoClock {
+    /// Creates a new clock with the given tempo in beats per minute.
+    pub fn new(bpm: f32) -> Self {
+        let clock = Self {
+            bpm: Arc::new(AtomicU32::new(DEFAULT_TEMPO_BPM.to_bits())),
+        };
+        clock.set_bpm(bpm);
+        clock
+    }
+
+    /// Returns the current tempo in beats per minute.
+    pub fn bpm(&self) -> f32 {
+        f32::from_bits(self.bpm.load(Ordering::Relaxed))
+    }
+
+    /// Sets the tempo in beats per minute. Tempos that aren't positive are
+    /// ignored.
+    pub fn set_bpm(&self, bpm: f32) {
+        if bpm.is_finite() && bpm > 0.0 {
+            self.bpm.store(bpm.to_bits(), Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for TempoClock {
+    /// A clock at `DEFAULT_TEMPO_BPM`.
+    fn default() -> Self {
+        Self::new(DEFAULT_TEMPO_BPM)
+    }
+}
+
+/// Changes the length of a `NoteValue`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum NoteModifier {
+    /// The plain length of the note.
+    #[default]
+    Straight,
+
+    /// One and a half times the length of the note.
+    Dotted,
+
+    /// Two thirds of the length of the note.
+    Triplet,
+}
+
+/// A length in musical units, e.g. an eighth note or a dotted quarter note,
+/// whose time depends on the tempo. A beat is a quarter note.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct NoteValue {
+    /// The fraction of a whole note, e.g. `8` for an eighth note.
+    pub division: u32,
+
+    /// Changes the length of the note.
+    pub modifier: NoteModifier,
+}
+
+impl NoteValue {
+    /// A `1 / division` note, e.g. `NoteValue::new(8)` for an eighth note.
+    pub const fn new(division: u32) -> Self {
+        Self {
+            division,
+            modifier: NoteModifier::Straight,
+        }
+    }
+
+    /// A dotted `1 / division` note.
+    pub const fn dotted(division: u32) -> Self {
+        Self {
+            division,
+            modifier: NoteModifier::Dotted,
+        }
+    }
+
+    /// A triplet `1 / division` note.
+    pub const fn triplet(division: u32) -> Self {
+        Self {
+            division,
+            modifier: NoteModifier::Triplet,
+        }
+    }
+
+    /// Returns the length of the note in beats.
+    pub fn beats(&self) -> f32 {
+        let beats = 4.0 / self.division.max(1) as f32;
+        match self.modifier {
+            NoteModifier::Straight => beats,
+            NoteModifier::Dotted => beats * 1.5,
+            NoteModifier::Triplet => beats * 2.0 / 3.0,
+        }
+    }
+
+    /// Returns the length of the note in seconds at the given tempo.
+    pub fn seconds(&self, bpm: f32) -> f32 {
+        self.beats() * 60.0 / bpm
+    }
+
+    /// Returns the rate of a cycle per note in Hz at the given tempo, e.g.
+    /// for a tempo-synced modulation.
+    pub fn frequency(&self, bpm: f32) -> f32 {
+        1.0 / self.seconds(bpm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_note_values() {
+        assert_eq!(NoteValue::new(4).seconds(120.0), 0.5);
+        assert_eq!(NoteValue::new(8).seconds(120.0), 0.25);
+        assert_eq!(NoteValue::dotted(4).seconds(120.0), 0.75);
+        assert!((NoteValue::triplet(8).seconds(120.0) - 1.0 / 6.0).abs() < 1e-6);
+        assert_eq!(NoteValue::new(1).frequency(60.0), 0.25);
+
+        let clock = TempoClock::default();
+        let shared = clock.clone();
+        shared.set_bpm(90.0);
+        assert_eq!(clock.bpm(), 90.0);
+        shared.set_bpm(0.0);
+        shared.set_bpm(f32::NAN);
+        assert_eq!(clock.bpm(), 90.0);
+    }
+}
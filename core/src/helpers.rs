@@ -1,45 +1,102 @@
-use std::sync::Arc;
+//! The helpers of the previous versions. The ones used by embedders moved
+//! to the crate root, the others are internal now. Kept for one release.
+
+// The expansion of `lazy_static!` uses the deprecated `FREQS`
+#![allow(deprecated)]
+
 use std::cell::RefCell;
+use std::sync::Arc;
 
-mod frequencies;
-pub use frequencies::*;
+use lazy_static::lazy_static;
 
-mod simd;
-pub use simd::*;
+use crate::util;
 
-/// Take any f32 vec, set its length and fill it with the default value.
-#[inline(always)]
-pub fn prepapre_cache_vec<T: Copy>(vec: &mut Vec<T>, len: usize, default: T) {
-    if vec.len() < len {
-        vec.reserve(len - vec.len());
-    }
-    unsafe {
-        vec.set_len(len);
-    }
-    vec.fill(default);
+#[deprecated(since = "0.3.5", note = "moved to the crate root")]
+pub type KeyFrequencies = crate::KeyFrequencies;
+
+#[deprecated(since = "0.3.5", note = "moved to the crate root")]
+pub type SectionTimer = crate::SectionTimer;
+
+#[deprecated(since = "0.3.5", note = "moved to the crate root")]
+pub type SectionTiming = crate::SectionTiming;
+
+#[deprecated(since = "0.3.5", note = "moved to the crate root")]
+pub type SimdBackend = crate::SimdBackend;
+
+#[deprecated(since = "0.3.5", note = "moved to the crate root")]
+pub const DEFAULT_A4_FREQUENCY: f32 = crate::DEFAULT_A4_FREQUENCY;
+
+lazy_static! {
+    #[deprecated(since = "0.3.5", note = "internal to XSynth, will be removed")]
+    pub static ref FREQS: [f32; 128] = *util::FREQS;
 }
 
-/// Fast zero-fill for f32 buffers using SIMD-like operations
-#[inline(always)]
+#[deprecated(since = "0.3.5", note = "internal to XSynth, will be removed")]
+pub const ANTI_DENORMAL: f32 = util::ANTI_DENORMAL;
+
+#[deprecated(since = "0.3.5", note = "moved to the crate root")]
+pub fn flush_denormals_on_current_thread() -> bool {
+    crate::flush_denormals_on_current_thread()
+}
+
+#[deprecated(since = "0.3.5", note = "moved to the crate root")]
+pub fn key_to_name(key: u8) -> String {
+    crate::key_to_name(key)
+}
+
+#[deprecated(since = "0.3.5", note = "moved to the crate root")]
+pub fn set_simd_backend(backend: crate::SimdBackend) {
+    crate::set_simd_backend(backend)
+}
+
+#[deprecated(since = "0.3.5", note = "moved to the crate root")]
+pub fn simd_backend() -> crate::SimdBackend {
+    crate::simd_backend()
+}
+
+#[deprecated(since = "0.3.5", note = "moved to the crate root")]
+pub fn active_simd_backend() -> crate::SimdBackend {
+    crate::active_simd_backend()
+}
+
+#[deprecated(since = "0.3.5", note = "moved to the crate root")]
+pub fn db_to_amp(db: f32) -> f32 {
+    crate::db_to_amp(db)
+}
+
+#[deprecated(since = "0.3.5", note = "moved to the crate root")]
+pub fn amp_to_db(amp: f32) -> f32 {
+    crate::amp_to_db(amp)
+}
+
+#[deprecated(since = "0.3.5", note = "internal to XSynth, will be removed")]
+pub fn sum_simd(source: &[f32], target: &mut [f32]) {
+    util::sum_simd(source, target)
+}
+
+#[deprecated(since = "0.3.5", note = "internal to XSynth, will be removed")]
 pub fn fast_zero_fill(vec: &mut Vec<f32>, len: usize) {
-    if vec.len() < len {
-        vec.reserve(len - vec.len());
-    }
-    unsafe {
-        vec.set_len(len);
-        // Use write_bytes for fast zeroing - this is optimized by the compiler
-        // to use SIMD instructions when available
-        std::ptr::write_bytes(vec.as_mut_ptr(), 0, len);
-    }
+    util::fast_zero_fill(vec, len)
+}
+
+#[deprecated(since = "0.3.5", note = "internal to XSynth, will be removed")]
+pub fn prepare_cache_vec<T: Copy>(vec: &mut Vec<T>, len: usize, default: T) {
+    util::prepare_cache_vec(vec, len, default)
+}
+
+#[deprecated(since = "0.3.5", note = "internal to XSynth, will be removed")]
+pub fn prepapre_cache_vec<T: Copy>(vec: &mut Vec<T>, len: usize, default: T) {
+    util::prepare_cache_vec(vec, len, default)
 }
 
-/// Thread-local buffer pool for voice rendering to avoid allocations
+// Thread-local buffer pool for voice rendering to avoid allocations
 thread_local! {
     static VOICE_RENDER_BUFFERS: RefCell<Vec<Vec<f32>>> = RefCell::new(Vec::new());
 }
 
 /// Get a buffer from the thread-local pool or create a new one
 #[inline(always)]
+#[deprecated(since = "0.3.5", note = "internal to XSynth, will be removed")]
 pub fn get_render_buffer(size: usize) -> Vec<f32> {
     VOICE_RENDER_BUFFERS.with(|pool| {
         let mut pool = pool.borrow_mut();
@@ -47,7 +104,9 @@ pub fn get_render_buffer(size: usize) -> Vec<f32> {
             if buf.capacity() < size {
                 buf.reserve(size - buf.capacity());
             }
-            unsafe { buf.set_len(size); }
+            unsafe {
+                buf.set_len(size);
+            }
             buf.fill(0.0);
             buf
         } else {
@@ -58,6 +117,7 @@ pub fn get_render_buffer(size: usize) -> Vec<f32> {
 
 /// Return a buffer to the thread-local pool
 #[inline(always)]
+#[deprecated(since = "0.3.5", note = "internal to XSynth, will be removed")]
 pub fn return_render_buffer(buf: Vec<f32>) {
     VOICE_RENDER_BUFFERS.with(|pool| {
         let mut pool = pool.borrow_mut();
@@ -71,24 +131,25 @@ pub fn return_render_buffer(buf: Vec<f32>) {
 /// Ultra-fast SIMD sum of multiple buffers into target
 /// Uses unsafe code to eliminate bounds checking
 #[inline(always)]
+#[deprecated(since = "0.3.5", note = "internal to XSynth, will be removed")]
 pub fn sum_buffers_to_target(sources: &[Vec<f32>], target: &mut [f32]) {
     if sources.is_empty() {
         return;
     }
-    
+
     let len = target.len();
-    
+
     // Process 8 elements at a time for better cache utilization
     let chunks = len / 8;
     let remainder = len % 8;
-    
+
     for source in sources {
         debug_assert!(source.len() >= len);
-        
+
         unsafe {
             let src_ptr = source.as_ptr();
             let dst_ptr = target.as_mut_ptr();
-            
+
             // Unrolled loop for 8 elements at a time
             for i in 0..chunks {
                 let base = i * 8;
@@ -101,7 +162,7 @@ pub fn sum_buffers_to_target(sources: &[Vec<f32>], target: &mut [f32]) {
                 *dst_ptr.add(base + 6) += *src_ptr.add(base + 6);
                 *dst_ptr.add(base + 7) += *src_ptr.add(base + 7);
             }
-            
+
             // Handle remainder
             let base = chunks * 8;
             for i in 0..remainder {
@@ -111,12 +172,8 @@ pub fn sum_buffers_to_target(sources: &[Vec<f32>], target: &mut [f32]) {
     }
 }
 
-/// Converts a dB value to 0-1 amplitude.
-pub fn db_to_amp(db: f32) -> f32 {
-    10f32.powf(db / 20.0)
-}
-
 /// Checks if two `Arc<T>` vecs are equal based on `Arc::ptr_eq`.
+#[deprecated(since = "0.3.5", note = "internal to XSynth, will be removed")]
 pub fn are_arc_vecs_equal<T: ?Sized>(old: &[Arc<T>], new: &[Arc<T>]) -> bool {
     // First, check if the lengths are the same
     if old.len() != new.len() {
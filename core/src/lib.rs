@@ -13,10 +13,34 @@ pub use audio_pipe::*;
 mod audio_stream;
 pub use audio_stream::*;
 
+mod sample;
+pub use sample::*;
+
 pub mod soundfont;
 
 pub mod effects;
 
+pub(crate) mod util;
+pub use util::{
+    active_simd_backend, amp_to_db, db_to_amp, flush_denormals_on_current_thread, key_to_name,
+    set_simd_backend, simd_backend, KeyFrequencies, SectionTimer, SectionTiming, SimdBackend,
+    DEFAULT_A4_FREQUENCY,
+};
+// Used by the other XSynth crates
+#[doc(hidden)]
+pub use util::{fast_zero_fill, sum_simd};
+
 pub mod helpers;
 
 pub mod channel_group;
+
+pub mod prelude;
+
+#[cfg(feature = "tracing")]
+pub mod trace;
+
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+
+#[cfg(test)]
+mod test_soundfont;
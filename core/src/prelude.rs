@@ -0,0 +1,30 @@
+//! Re-exports the types needed to render MIDI with XSynth, so embedders can
+//! import them at once instead of from each module:
+//!
+//! ```
+//! use xsynth_core::prelude::*;
+//! ```
+//!
+//! Covers the synthesizer and its channels, their events, configs and
+//! statistics, soundfont loading, the traits to implement custom
+//! soundfonts, voices and effects, and audio rendering. Less common types
+//! are still imported from their modules.
+
+pub use crate::{
+    buffered_renderer::{BufferedRenderer, BufferedRendererStatsReader},
+    channel::{
+        ChannelAudioEvent, ChannelConfigEvent, ChannelEvent, ChannelInitOptions,
+        ChannelStateSnapshot, ControlEvent, VoiceChannel, VoiceChannelStatsReader,
+    },
+    channel_group::{
+        ChannelGroup, ChannelGroupConfig, ChannelId, ParallelismOptions, SynthEvent, SynthFormat,
+        ThreadCount,
+    },
+    effects::{AudioInsert, EffectsChain, VolumeLimiter},
+    soundfont::{
+        LoadSfError, SampleSoundfont, SoundfontBase, SoundfontInitOptions, SoundfontLoadError,
+        VoiceSpawner,
+    },
+    voice::{ReleaseType, Voice, VoiceControlData, VoiceGeneratorBase, VoiceSampleGenerator},
+    AudioPipe, AudioStreamParams, ChannelCount, FunctionAudioPipe,
+};
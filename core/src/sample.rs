@@ -1 +1,149 @@
-pub trait Sample {}
\ No newline at end of file
+/// An output sample format that the rendered `f32` audio can be converted to.
+///
+/// Integer formats clamp the audio to the `-1.0..=1.0` range before
+/// converting it, while floating point formats keep it as is.
+pub trait Sample: Copy + Default + Send + 'static {
+    /// Whether TPDF dither should be added when converting to this format.
+    const DITHERED: bool = false;
+
+    /// Converts a sample, without dithering.
+    fn from_f32(s: f32) -> Self;
+
+    /// Converts a sample, adding the given noise in LSB units of the format
+    /// if it is dithered.
+    #[inline(always)]
+    fn from_f32_dithered(s: f32, _noise: f32) -> Self {
+        Self::from_f32(s)
+    }
+}
+
+impl Sample for f32 {
+    #[inline(always)]
+    fn from_f32(s: f32) -> Self {
+        s
+    }
+}
+
+impl Sample for f64 {
+    #[inline(always)]
+    fn from_f32(s: f32) -> Self {
+        s as f64
+    }
+}
+
+impl Sample for i16 {
+    const DITHERED: bool = true;
+
+    #[inline(always)]
+    fn from_f32(s: f32) -> Self {
+        (s.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+    }
+
+    #[inline(always)]
+    fn from_f32_dithered(s: f32, noise: f32) -> Self {
+        (s.clamp(-1.0, 1.0) * i16::MAX as f32 + noise)
+            .round()
+            .clamp(i16::MIN as f32, i16::MAX as f32) as i16
+    }
+}
+
+impl Sample for u16 {
+    const DITHERED: bool = true;
+
+    #[inline(always)]
+    fn from_f32(s: f32) -> Self {
+        (i16::from_f32(s) as i32 - i16::MIN as i32) as u16
+    }
+
+    #[inline(always)]
+    fn from_f32_dithered(s: f32, noise: f32) -> Self {
+        (i16::from_f32_dithered(s, noise) as i32 - i16::MIN as i32) as u16
+    }
+}
+
+// 32 bit integers have more precision than the f32 mantissa, so they don't
+// need dithering
+impl Sample for i32 {
+    #[inline(always)]
+    fn from_f32(s: f32) -> Self {
+        (s.clamp(-1.0, 1.0) as f64 * i32::MAX as f64).round() as i32
+    }
+}
+
+/// Converts rendered `f32` audio to other sample formats, adding triangular
+/// (TPDF) dither when reducing it to 16 bit.
+///
+/// The converter keeps the state of the dither noise, so the same converter
+/// should be used for consecutive buffers of a stream.
+pub struct SampleConverter {
+    rng: u32,
+    pub(crate) buffer: Vec<f32>,
+}
+
+impl SampleConverter {
+    /// Initializes a new sample converter.
+    pub fn new() -> Self {
+        Self {
+            rng: 0x9E37_79B9,
+            buffer: Vec::new(),
+        }
+    }
+
+    #[inline(always)]
+    fn next_uniform(&mut self) -> f32 {
+        // xorshift32
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// Converts the samples of `from` into `to`. Both buffers should have
+    /// the same length.
+    pub fn convert<T: Sample>(&mut self, from: &[f32], to: &mut [T]) {
+        if T::DITHERED {
+            for (s, out) in from.iter().zip(to.iter_mut()) {
+                // The difference of two uniform values has a triangular
+                // distribution in the -1..1 LSB range
+                let noise = self.next_uniform() - self.next_uniform();
+                *out = T::from_f32_dithered(*s, noise);
+            }
+        } else {
+            for (s, out) in from.iter().zip(to.iter_mut()) {
+                *out = T::from_f32(*s);
+            }
+        }
+    }
+}
+
+impl Default for SampleConverter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamping() {
+        assert_eq!(i16::from_f32(2.0), i16::MAX);
+        assert_eq!(i16::from_f32(-2.0), -i16::MAX);
+        assert_eq!(i32::from_f32(2.0), i32::MAX);
+        assert_eq!(u16::from_f32(0.0), 32768);
+        assert_eq!(f32::from_f32(2.0), 2.0);
+    }
+
+    #[test]
+    fn test_tpdf_dither() {
+        // A quarter of an LSB would always truncate to 0 without dither
+        let input = vec![0.25 / i16::MAX as f32; 100000];
+        let mut output = vec![0i16; input.len()];
+        SampleConverter::new().convert(&input, &mut output);
+
+        assert!(output.iter().all(|&s| (-1..=1).contains(&s)));
+        let mean = output.iter().map(|&s| s as f64).sum::<f64>() / output.len() as f64;
+        assert!((mean - 0.25).abs() < 0.02, "mean {mean}");
+    }
+}
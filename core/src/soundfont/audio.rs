@@ -13,11 +13,11 @@ use xsynth_soundfonts::resample::resample_vecs;
 /// Errors that can be generated when loading an audio file.
 #[derive(Debug, Error)]
 pub enum AudioLoadError {
-    #[error("IO Error")]
-    IOError(#[from] io::Error),
+    #[error("Failed to read the audio file {0}: {1}")]
+    IOError(PathBuf, #[source] io::Error),
 
-    #[error("Audio decoding failed for {0}")]
-    AudioDecodingFailed(PathBuf, Error),
+    #[error("Audio decoding failed for {0}: {1}")]
+    AudioDecodingFailed(PathBuf, #[source] Error),
 
     #[error("Audio file {0} has an invalid channel count")]
     InvalidChannelCount(PathBuf),
@@ -26,7 +26,7 @@ pub enum AudioLoadError {
     NoTracks(PathBuf),
 }
 
-type ProcessedSample = (Arc<[Arc<[f32]>]>, u32);
+pub(super) type ProcessedSample = (Arc<[Arc<[f32]>]>, u32);
 
 pub(super) fn load_audio_file(
     path: &PathBuf,
@@ -36,7 +36,7 @@ pub(super) fn load_audio_file(
 
     let extension = path.extension().and_then(|ext| ext.to_str());
 
-    let file = Box::new(File::open(path)?);
+    let file = Box::new(File::open(path).map_err(|e| AudioLoadError::IOError(path.clone(), e))?);
 
     // Create the media source stream using the boxed media source from above.
     let mss = MediaSourceStream::new(file, Default::default());
@@ -113,6 +113,30 @@ pub(super) fn load_audio_file(
     Ok((built, sample_rate))
 }
 
+/// Inverts the polarity and/or swaps the channels of a decoded stereo
+/// sample. Swapping only reorders the channels, the data is only copied to
+/// invert it.
+pub(super) fn fix_sample(
+    sample: &Arc<[Arc<[f32]>]>,
+    invert_phase: bool,
+    swap_channels: bool,
+) -> Arc<[Arc<[f32]>]> {
+    if !invert_phase && !swap_channels {
+        return sample.clone();
+    }
+
+    let mut channels = sample.to_vec();
+    if swap_channels && channels.len() == 2 {
+        channels.swap(0, 1);
+    }
+    if invert_phase {
+        for channel in channels.iter_mut() {
+            *channel = channel.iter().map(|s| -s).collect();
+        }
+    }
+    channels.into()
+}
+
 struct BuilderVecs {
     vecs: Vec<Vec<f32>>,
 }
@@ -169,7 +193,7 @@ impl BuilderVecs {
             let combined: Vec<f32> = left
                 .iter()
                 .zip(right.iter())
-                .map(|(&l, &r)| (l + r) * 0.5)
+                .map(|(&l, &r)| (l + r) * std::f32::consts::FRAC_1_SQRT_2)
                 .collect();
             vecs.push(combined);
         }
@@ -0,0 +1,211 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use xsynth_soundfonts::sf2::{Sf2Info, Sf2Preset};
+
+use super::audio::ProcessedSample;
+use crate::AudioStreamParams;
+
+/// Identifies a decoded file: its path, its modification time, so files
+/// changed on disk are decoded again, and the stream parameters it was
+/// decoded for.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct CacheKey {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl CacheKey {
+    fn new(path: &Path, stream_params: AudioStreamParams) -> Self {
+        CacheKey {
+            path: path.canonicalize().unwrap_or_else(|_| path.to_path_buf()),
+            modified: fs::metadata(path).and_then(|m| m.modified()).ok(),
+            sample_rate: stream_params.sample_rate,
+            channels: stream_params.channels.count(),
+        }
+    }
+}
+
+/// The decoded data of a file in the cache.
+pub(super) enum CachedFile {
+    /// An audio file used by the regions of an SFZ.
+    Sample(ProcessedSample),
+
+    /// A whole SF2, with the samples of its regions.
+    Sf2(Sf2Info, Vec<Sf2Preset>),
+}
+
+/// Shares the decoded samples of soundfonts between the `SampleSoundfont`
+/// instances loaded with it, e.g. when the same soundfont is loaded twice
+/// with different options, or reloaded after its options changed. Decoding
+/// is skipped for the files found in the cache.
+///
+/// Files are identified by their path, their modification time and the
+/// sample rate and channel count they were decoded for, so a file changed
+/// on disk is decoded again. SFZ samples are cached per audio file and SF2
+/// samples per SF2 file.
+///
+/// The cache is cheap to clone, and the clones share the same files. Files
+/// are kept until they are removed with `evict_unused`, `remove` or `clear`,
+/// so the memory they use is under the control of the host. See
+/// `SampleSoundfont::new_with_cache`.
+#[derive(Clone, Default)]
+pub struct SoundfontCache {
+    files: Arc<Mutex<HashMap<CacheKey, Arc<CachedFile>>>>,
+}
+
+impl SoundfontCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the number of files in the cache.
+    pub fn len(&self) -> usize {
+        self.files.lock().unwrap().len()
+    }
+
+    /// Returns true if the cache has no files.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes the files that aren't used by any loaded soundfont anymore,
+    /// and returns the number of files removed.
+    pub fn evict_unused(&self) -> usize {
+        let mut files = self.files.lock().unwrap();
+        let len = files.len();
+        files.retain(|_, file| Arc::strong_count(file) > 1);
+        len - files.len()
+    }
+
+    /// Removes the files with the given path, decoded for any stream
+    /// parameters. The soundfonts using them keep their samples. Returns the
+    /// number of files removed.
+    pub fn remove(&self, path: impl AsRef<Path>) -> usize {
+        let path = path.as_ref();
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let mut files = self.files.lock().unwrap();
+        let len = files.len();
+        files.retain(|key, _| key.path != path);
+        len - files.len()
+    }
+
+    /// Removes all the files. The soundfonts using them keep their samples.
+    pub fn clear(&self) {
+        self.files.lock().unwrap().clear();
+    }
+
+    /// Returns the cached file, or loads it and adds it to the cache, and
+    /// whether it was found in the cache. The cache isn't locked while the
+    /// file is loaded, so files can be loaded in parallel.
+    pub(super) fn get_or_load<E>(
+        &self,
+        path: &Path,
+        stream_params: AudioStreamParams,
+        load: impl FnOnce() -> Result<CachedFile, E>,
+    ) -> Result<(Arc<CachedFile>, bool), E> {
+        let key = CacheKey::new(path, stream_params);
+        if let Some(file) = self.files.lock().unwrap().get(&key) {
+            return Ok((file.clone(), true));
+        }
+
+        let file = Arc::new(load()?);
+        let mut files = self.files.lock().unwrap();
+        Ok((files.entry(key).or_insert(file).clone(), false))
+    }
+}
+
+impl std::fmt::Debug for SoundfontCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "SoundfontCache({} files)", self.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        soundfont::{SampleSoundfont, SoundfontInitOptions},
+        ChannelCount,
+    };
+
+    const SF2: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../soundfonts/fixtures/valid.sf2"
+    );
+
+    fn first_sample(soundfont: &SampleSoundfont) -> Arc<[Arc<[f32]>]> {
+        let mut params = soundfont
+            .instruments
+            .iter()
+            .flat_map(|i| i.spawner_params_list.iter().flatten());
+        params.next().unwrap().sample.clone()
+    }
+
+    #[test]
+    fn test_shared_samples() {
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let cache = SoundfontCache::new();
+        let load =
+            |options| SampleSoundfont::new_with_cache(SF2, stream_params, options, &cache).unwrap();
+
+        let first = load(SoundfontInitOptions::default());
+        let second = load(SoundfontInitOptions {
+            gain_db: -6.0,
+            ..Default::default()
+        });
+        assert!(Arc::ptr_eq(&first_sample(&first), &first_sample(&second)));
+        assert_eq!(cache.len(), 1);
+
+        let (first_report, second_report) = (first.memory_report(), second.memory_report());
+        assert!(first_report.total_bytes > 0);
+        assert_eq!(first_report.total_bytes, second_report.total_bytes);
+        assert_eq!(
+            (first_report.cached_files, first_report.cached_bytes),
+            (0, 0)
+        );
+        assert_eq!(
+            (second_report.cached_files, second_report.cached_bytes),
+            (1, second_report.total_bytes)
+        );
+
+        // Without the cache, or for another sample rate, the samples are
+        // decoded again
+        let uncached = SampleSoundfont::new(SF2, stream_params, Default::default()).unwrap();
+        assert!(!Arc::ptr_eq(
+            &first_sample(&first),
+            &first_sample(&uncached)
+        ));
+        let resampled = SampleSoundfont::new_with_cache(
+            SF2,
+            AudioStreamParams::new(44100, ChannelCount::Stereo),
+            Default::default(),
+            &cache,
+        )
+        .unwrap();
+        assert_eq!(cache.len(), 2);
+
+        // Files are only evicted once no soundfont uses them
+        drop(resampled);
+        assert_eq!(cache.evict_unused(), 1);
+        drop(first);
+        assert_eq!(cache.evict_unused(), 0);
+        let reloaded = load(SoundfontInitOptions::default());
+        assert!(Arc::ptr_eq(
+            &first_sample(&second),
+            &first_sample(&reloaded)
+        ));
+
+        drop((second, reloaded));
+        assert_eq!(cache.evict_unused(), 1);
+        assert!(cache.is_empty());
+    }
+}
@@ -27,7 +27,7 @@ pub enum EnvelopeCurveType {
     Exponential,
 }
 
-/// Options for the curves of a specific envelope.
+/// Options for the curves and the end of a specific envelope.
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[cfg_attr(
     feature = "serde",
@@ -52,6 +52,15 @@ pub struct EnvelopeOptions {
     ///
     /// Default: `Linear`
     pub release_curve: EnvelopeCurveType,
+
+    /// The level in dB below which a releasing voice ends, instead of
+    /// playing out the full release time. Long release tails spend much of
+    /// their time far below audibility. `None` always plays the release to
+    /// its end, e.g. for soundfonts whose samples get loud after a long
+    /// silence.
+    ///
+    /// Default: `Some(-100.0)`
+    pub release_floor_db: Option<f32>,
 }
 
 impl Default for EnvelopeOptions {
@@ -60,10 +69,27 @@ impl Default for EnvelopeOptions {
             attack_curve: EnvelopeCurveType::Exponential,
             decay_curve: EnvelopeCurveType::Linear,
             release_curve: EnvelopeCurveType::Linear,
+            release_floor_db: Some(-100.0),
         }
     }
 }
 
+/// How the level of a sample soundfont is normalized when it is loaded, so
+/// soundfonts mastered at different levels can be layered.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Normalization {
+    /// The soundfont is played at its own level.
+    #[default]
+    None,
+
+    /// The samples are scanned for their peaks, and the soundfont is
+    /// amplified or attenuated so the average peak level of its regions
+    /// matches the target level, in dB. The volume of the regions (SFZ
+    /// `volume` and SF2 `initialAttenuation`) is included in their level.
+    PeakLevel { target_db: f32 },
+}
+
 /// Options for initializing/loading a new sample soundfont.
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[cfg_attr(
@@ -100,6 +126,41 @@ pub struct SoundfontInitOptions {
     ///
     /// Default: `Nearest`
     pub interpolator: Interpolator,
+
+    /// The length in samples of a ramp applied to the start of every voice,
+    /// to prevent clicks on regions without an attack whose sample doesn't
+    /// start at zero. Regions with a volume envelope that already fades in
+    /// for at least this long are not affected. `0` disables the ramp.
+    ///
+    /// Default: `32`
+    pub start_ramp: u32,
+
+    /// A gain applied to all the voices of the soundfont, in dB. It is
+    /// applied on top of the normalization gain.
+    ///
+    /// Default: `0.0`
+    pub gain_db: f32,
+
+    /// Controls how the level of the soundfont is normalized. See the
+    /// documentation of the `Normalization` enum for available options.
+    ///
+    /// Default: `None`
+    pub normalization: Normalization,
+
+    /// If set to true, the polarity of the samples is inverted when they
+    /// are loaded, e.g. for samples recorded with an inverted polarity that
+    /// cancel out when layered with other soundfonts. SFZ regions can
+    /// override it with the `xsynth_invert_phase` opcode.
+    ///
+    /// Default: `false`
+    pub invert_phase: bool,
+
+    /// If set to true, the left and right channels of stereo samples are
+    /// swapped when they are loaded. SFZ regions can override it with the
+    /// `xsynth_swap_channels` opcode.
+    ///
+    /// Default: `false`
+    pub swap_channels: bool,
 }
 
 impl Default for SoundfontInitOptions {
@@ -110,6 +171,11 @@ impl Default for SoundfontInitOptions {
             vol_envelope_options: Default::default(),
             use_effects: true,
             interpolator: Interpolator::Nearest,
+            start_ramp: 32,
+            gain_db: 0.0,
+            normalization: Normalization::None,
+            invert_phase: false,
+            swap_channels: false,
         }
     }
 }
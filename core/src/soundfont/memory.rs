@@ -0,0 +1,95 @@
+use std::{mem::size_of, path::PathBuf, sync::Arc, time::Duration};
+
+/// The memory used by the decoded samples of a soundfont and the time it
+/// took to load, e.g. to show it in a user interface. It is gathered while
+/// the soundfont is loaded. See `SampleSoundfont::memory_report`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// The size in bytes of the decoded samples used by the soundfont,
+    /// including the samples shared with other soundfonts through a cache.
+    pub total_bytes: usize,
+
+    /// The decoded sample files, sorted by path. SFZ soundfonts list each
+    /// audio file used by their regions, and SF2 soundfonts list the SF2 file
+    /// with the samples of the loaded presets.
+    pub sample_files: Vec<SampleFileMemory>,
+
+    /// The number of regions loaded.
+    pub region_count: usize,
+
+    /// The number of sample files taken from a `SoundfontCache` instead of
+    /// being decoded again.
+    pub cached_files: usize,
+
+    /// The size in bytes of the samples taken from a `SoundfontCache`, i.e.
+    /// the memory saved by sharing them.
+    pub cached_bytes: usize,
+
+    /// The time spent decoding the samples, or taking them from the cache.
+    /// For SF2 soundfonts, this includes parsing the file.
+    pub sample_load_time: Duration,
+
+    /// The total time spent loading the soundfont.
+    pub load_time: Duration,
+}
+
+/// The memory used by the decoded samples of a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SampleFileMemory {
+    /// The path of the file.
+    pub path: PathBuf,
+
+    /// The size in bytes of the decoded samples.
+    pub bytes: usize,
+
+    /// True if the samples were taken from a `SoundfontCache`.
+    pub cached: bool,
+}
+
+impl MemoryReport {
+    /// Adds a decoded sample file to the report.
+    pub(super) fn add_file(&mut self, path: PathBuf, bytes: usize, cached: bool) {
+        self.total_bytes += bytes;
+        if cached {
+            self.cached_files += 1;
+            self.cached_bytes += bytes;
+        }
+        let index = self.sample_files.partition_point(|f| f.path < path);
+        self.sample_files.insert(
+            index,
+            SampleFileMemory {
+                path,
+                bytes,
+                cached,
+            },
+        );
+    }
+}
+
+/// Returns the size in bytes of a decoded sample.
+pub(super) fn sample_bytes(sample: &[Arc<[f32]>]) -> usize {
+    sample.iter().map(|c| c.len() * size_of::<f32>()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_file() {
+        let mut report = MemoryReport::default();
+        report.add_file("b.wav".into(), 400, false);
+        report.add_file("a.wav".into(), 100, true);
+
+        assert_eq!(report.total_bytes, 500);
+        assert_eq!((report.cached_files, report.cached_bytes), (1, 100));
+        let paths: Vec<_> = report.sample_files.iter().map(|f| &f.path).collect();
+        assert_eq!(paths, [&PathBuf::from("a.wav"), &PathBuf::from("b.wav")]);
+    }
+
+    #[test]
+    fn test_sample_bytes() {
+        let sample: [Arc<[f32]>; 2] = [Arc::new([0.0; 10]), Arc::new([0.0; 6])];
+        assert_eq!(sample_bytes(&sample), 64);
+    }
+}
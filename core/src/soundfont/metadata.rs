@@ -0,0 +1,135 @@
+use std::ops::RangeInclusive;
+
+use xsynth_soundfonts::sfz::CcCondition;
+
+/// Information about a soundfont, e.g. to show it in a user interface.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SoundfontMetadata {
+    /// The name of the soundfont. For SFZ files, this is the file name
+    /// without the extension.
+    pub name: String,
+
+    /// The author of the soundfont, if specified.
+    pub author: Option<String>,
+
+    /// The copyright notice of the soundfont, if specified.
+    pub copyright: Option<String>,
+
+    /// Comments about the soundfont, if specified.
+    pub comments: Option<String>,
+
+    /// The malformed parts of the soundfont that were skipped while loading
+    /// it, if any.
+    pub warnings: Vec<String>,
+}
+
+/// Information about a preset provided by a soundfont.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PresetInfo {
+    /// The bank number of the preset.
+    pub bank: u8,
+
+    /// The program number of the preset.
+    pub preset: u8,
+
+    /// The name of the preset.
+    pub name: String,
+
+    /// The range of keys that play at least one region.
+    pub key_range: RangeInclusive<u8>,
+
+    /// The range of velocities that play at least one region.
+    pub vel_range: RangeInclusive<u8>,
+}
+
+/// A controller range that selects an articulation of a preset, e.g. the
+/// regions of a library played while CC32 is within a range of values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArticulationSwitch {
+    /// The controller number.
+    pub controller: u8,
+
+    /// The range of controller values that selects the articulation.
+    pub range: RangeInclusive<u8>,
+
+    /// The name of the articulation, from the label of its regions.
+    pub name: String,
+}
+
+/// Lists the articulation switches of labeled regions and their controller
+/// conditions, sorted by controller and range. Regions with the same label
+/// and condition only add one switch.
+pub(super) fn articulation_switches<'a>(
+    regions: impl IntoIterator<Item = (&'a str, &'a [CcCondition])>,
+) -> Vec<ArticulationSwitch> {
+    let mut switches: Vec<_> = regions
+        .into_iter()
+        .flat_map(|(name, conditions)| {
+            conditions.iter().map(move |c| ArticulationSwitch {
+                controller: c.cc,
+                range: c.range.clone(),
+                name: name.to_owned(),
+            })
+        })
+        .collect();
+    switches.sort_by(|a, b| {
+        (a.controller, a.range.start(), a.range.end(), &a.name).cmp(&(
+            b.controller,
+            b.range.start(),
+            b.range.end(),
+            &b.name,
+        ))
+    });
+    switches.dedup();
+    switches
+}
+
+/// Returns the smallest range that contains all the given ranges, or `None`
+/// if there are none.
+pub(super) fn range_union(
+    ranges: impl IntoIterator<Item = RangeInclusive<u8>>,
+) -> Option<RangeInclusive<u8>> {
+    ranges
+        .into_iter()
+        .filter(|r| !r.is_empty())
+        .map(|r| r.into_inner())
+        .reduce(|(lo_a, hi_a), (lo_b, hi_b)| (lo_a.min(lo_b), hi_a.max(hi_b)))
+        .map(|(lo, hi)| lo..=hi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range_union() {
+        assert_eq!(range_union([40..=60, 10..=20, 50..=70]), Some(10..=70));
+        assert_eq!(range_union([3..=3]), Some(3..=3));
+        assert_eq!(range_union([]), None);
+    }
+
+    #[test]
+    fn test_articulation_switches() {
+        let condition = |cc, range| CcCondition { cc, range };
+        let legato = [condition(32, 10..=19)];
+        let staccato = [condition(32, 0..=9), condition(1, 64..=127)];
+
+        let switches = articulation_switches([
+            ("legato", legato.as_slice()),
+            ("staccato", staccato.as_slice()),
+            ("legato", legato.as_slice()),
+        ]);
+        let summary: Vec<_> = switches
+            .iter()
+            .map(|s| (s.controller, s.range.clone(), s.name.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (1, 64..=127, "staccato"),
+                (32, 0..=9, "staccato"),
+                (32, 10..=19, "legato"),
+            ]
+        );
+    }
+}
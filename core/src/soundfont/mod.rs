@@ -2,39 +2,87 @@
 use std::{
     collections::{HashMap, HashSet},
     io,
-    path::PathBuf,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
     sync::Arc,
+    time::Instant,
 };
 
 use biquad::Q_BUTTERWORTH_F32;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use thiserror::Error;
-use xsynth_soundfonts::{convert_sample_index, FilterType, LoopMode};
+use xsynth_soundfonts::{
+    convert_sample_index,
+    sfz::{CcCondition, RegionTrigger},
+    FilterType, LoopMode,
+};
 
-use self::audio::load_audio_file;
 pub use self::audio::AudioLoadError;
+use self::{
+    audio::{fix_sample, load_audio_file, ProcessedSample},
+    cache::CachedFile,
+    memory::sample_bytes,
+    normalization::normalization_gain,
+    regions::{OverrideSnapshot, RegionOverrides},
+    velocity_crossfade::velocity_crossfade,
+};
 
 use super::{
     voice::VoiceControlData,
-    voice::{EnvelopeParameters, Voice},
+    voice::{EnvelopeParameters, Voice, VoicePolyphony},
+};
+use crate::{
+    util::{db_to_amp, simd_dispatch},
+    AudioStreamParams, ChannelCount,
 };
-use crate::{helpers::db_to_amp, AudioStreamParams, ChannelCount};
 
 pub use xsynth_soundfonts::{sf2::Sf2ParseError, sfz::SfzParseError};
 
 mod audio;
+mod cache;
 mod config;
+mod memory;
+mod metadata;
+mod normalization;
+mod regions;
 mod utils;
+mod velocity_crossfade;
 mod voice_spawners;
 use utils::*;
 use voice_spawners::*;
 
+pub use cache::SoundfontCache;
 pub use config::*;
+pub use memory::*;
+pub use metadata::*;
+pub use regions::{RegionInfo, RegionOverride, RegionOverrideError, RegionPatch, SoundfontPatch};
 
 pub trait VoiceSpawner: Sync + Send {
     fn spawn_voice(&self, control: &VoiceControlData) -> Box<dyn Voice>;
+
+    /// Returns true if a voice should be spawned with the given controls,
+    /// e.g. for regions that are only played in a range of controller values.
+    fn matches(&self, _control: &VoiceControlData) -> bool {
+        true
+    }
+
+    /// Multiplies the amplitude of the voices spawned afterwards by `gain`,
+    /// e.g. for the gain of a `RoutedSoundfont`. Spawners that don't support
+    /// a gain ignore it.
+    fn apply_gain(&mut self, _gain: f32) {}
+
+    /// Returns the polyphony limits of the spawned voices, e.g. for regions
+    /// with the `polyphony` or `note_polyphony` SFZ opcodes.
+    fn polyphony(&self) -> Option<VoicePolyphony> {
+        None
+    }
 }
 
+/// A soundfont that spawns the voices of the notes played on a channel.
+///
+/// Soundfonts are shared by the channels of a synthesizer, which are
+/// rendered on several threads, so they have to be `Send` and `Sync`. See
+/// the threading section of `Voice`.
 pub trait SoundfontBase: Sync + Send + std::fmt::Debug {
     fn stream_params(&self) -> &'_ AudioStreamParams;
 
@@ -45,6 +93,22 @@ pub trait SoundfontBase: Sync + Send + std::fmt::Debug {
         key: u8,
         vel: u8,
     ) -> Vec<Box<dyn VoiceSpawner>>;
+
+    /// Like `get_attack_voice_spawners_at`, but crossfades between adjacent
+    /// velocity layers within `width` velocity units of their boundaries.
+    /// See `ChannelConfigEvent::SetVelocityCrossfade`. Soundfonts without
+    /// velocity layers return the same spawners.
+    fn get_crossfaded_attack_voice_spawners_at(
+        &self,
+        bank: u8,
+        preset: u8,
+        key: u8,
+        vel: u8,
+        _width: u8,
+    ) -> Vec<Box<dyn VoiceSpawner>> {
+        self.get_attack_voice_spawners_at(bank, preset, key, vel)
+    }
+
     fn get_release_voice_spawners_at(
         &self,
         bank: u8,
@@ -52,6 +116,69 @@ pub trait SoundfontBase: Sync + Send + std::fmt::Debug {
         key: u8,
         vel: u8,
     ) -> Vec<Box<dyn VoiceSpawner>>;
+
+    /// Returns the voice spawners of the regions that are played when a
+    /// controller moves into a range of values, such as pedal noises.
+    fn get_cc_voice_spawners_at(&self, _bank: u8, _preset: u8) -> Vec<CcVoiceSpawner> {
+        Vec::new()
+    }
+
+    /// Returns the name and other information about the soundfont.
+    fn metadata(&self) -> SoundfontMetadata {
+        Default::default()
+    }
+
+    /// Returns the presets the soundfont provides, sorted by bank and
+    /// preset number. Presets without any playable regions are not listed.
+    fn presets(&self) -> Vec<PresetInfo> {
+        Vec::new()
+    }
+
+    /// Returns the controller ranges that select the labeled articulations
+    /// of a preset, sorted by controller and range. Soundfonts without
+    /// controller conditions on their regions return an empty list.
+    fn articulation_switches(&self, _bank: u8, _preset: u8) -> Vec<ArticulationSwitch> {
+        Vec::new()
+    }
+
+    /// A counter that changes whenever the spawners the soundfont returns
+    /// change without replacing the soundfont, e.g. after an edit of the
+    /// region overrides of a `SampleSoundfont`. The channels resolve their
+    /// spawners again when it changes. Soundfonts that never change return
+    /// a constant.
+    fn generation(&self) -> u64 {
+        0
+    }
+
+    /// Returns the soundfont converted to another sample rate, e.g. when the
+    /// sample rate of a synthesizer changes, or `None` if the soundfont can
+    /// be kept as is. See `ChannelConfigEvent::SetSampleRate`.
+    fn with_sample_rate(
+        &self,
+        _sample_rate: u32,
+    ) -> Result<Option<Arc<dyn SoundfontBase>>, SoundfontLoadError> {
+        Ok(None)
+    }
+}
+
+/// A voice spawner that is triggered by a controller moving into a range of
+/// values instead of a note.
+pub struct CcVoiceSpawner {
+    /// The controller number
+    pub controller: u8,
+
+    /// The range of controller values that triggers the spawner
+    pub range: RangeInclusive<u8>,
+
+    pub spawner: Box<dyn VoiceSpawner>,
+}
+
+impl CcVoiceSpawner {
+    /// Returns true if a controller change from `previous` to `value` moves
+    /// the controller into the range.
+    pub fn is_triggered(&self, previous: u8, value: u8) -> bool {
+        !self.range.contains(&previous) && self.range.contains(&value)
+    }
 }
 
 #[derive(Clone)]
@@ -62,6 +189,7 @@ pub(super) struct LoopParams {
     pub end: u32,
 }
 
+#[derive(Clone)]
 struct SampleVoiceSpawnerParams {
     volume: f32,
     pan: f32,
@@ -73,12 +201,66 @@ struct SampleVoiceSpawnerParams {
     envelope: Arc<EnvelopeParameters>,
     sample: Arc<[Arc<[f32]>]>,
     interpolator: Interpolator,
+    start_ramp: u32,
+    trigger: RegionTrigger,
+    cc_conditions: Arc<[CcCondition]>,
+    polyphony: Option<VoicePolyphony>,
+
+    /// The index of the region in `SampleSoundfont::regions`
+    region: usize,
+}
+
+/// Returns true if a region with the given trigger and controller conditions
+/// should be played with the given controls.
+fn region_matches(
+    trigger: RegionTrigger,
+    cc_conditions: &[CcCondition],
+    control: &VoiceControlData,
+) -> bool {
+    let trigger_matches = match trigger {
+        RegionTrigger::First => !control.legato,
+        RegionTrigger::Legato => control.legato,
+        RegionTrigger::Attack | RegionTrigger::Release => true,
+    };
+    trigger_matches
+        && cc_conditions
+            .iter()
+            .all(|c| c.matches(&control.controllers))
 }
 
 pub(super) struct SoundfontInstrument {
     bank: u8,
     preset: u8,
     spawner_params_list: Vec<Vec<Arc<SampleVoiceSpawnerParams>>>,
+    release_spawner_params_list: Vec<Vec<Arc<SampleVoiceSpawnerParams>>>,
+    cc_spawner_params_list: Vec<(CcCondition, Arc<SampleVoiceSpawnerParams>)>,
+    articulation_switches: Vec<ArticulationSwitch>,
+}
+
+fn new_spawner_params_list() -> Vec<Vec<Arc<SampleVoiceSpawnerParams>>> {
+    (0..(128 * 128)).map(|_| Vec::new()).collect()
+}
+
+/// Loads an audio file used by SFZ regions, through the cache if any.
+/// Returns the cached file too, so the soundfont keeps it in use, and
+/// whether it was found in the cache.
+fn load_sample(
+    path: &Path,
+    stream_params: AudioStreamParams,
+    cache: Option<&SoundfontCache>,
+) -> Result<(ProcessedSample, Option<Arc<CachedFile>>, bool), AudioLoadError> {
+    let path = path.to_path_buf();
+    let Some(cache) = cache else {
+        return Ok((load_audio_file(&path, stream_params)?, None, false));
+    };
+
+    let (file, hit) = cache.get_or_load(&path, stream_params, || {
+        load_audio_file(&path, stream_params).map(CachedFile::Sample)
+    })?;
+    match &*file {
+        CachedFile::Sample(sample) => Ok((sample.clone(), Some(file.clone()), hit)),
+        CachedFile::Sf2(..) => Ok((load_audio_file(&path, stream_params)?, None, false)),
+    }
 }
 
 /// Represents a sample soundfont to be used within XSynth.
@@ -111,6 +293,34 @@ pub(super) struct SoundfontInstrument {
 /// - `ampeg_decay`
 /// - `ampeg_sustain`
 /// - `ampeg_release`
+/// - `trigger` (`attack`, `release`, `first` and `legato`)
+/// - `loccN` & `hiccN`
+/// - `on_loccN` & `on_hiccN` (or `start_loccN` & `start_hiccN`)
+/// - `group`, `polyphony` & `note_polyphony`
+/// - `region_label`, `group_label` & `global_label`
+/// - `xsynth_invert_phase` & `xsynth_swap_channels` (XSynth extensions, see
+///   `SoundfontInitOptions`)
+///
+/// Regions with `loccN`/`hiccN` conditions are only played if the channel's
+/// controller values are within the ranges when the note starts, or when it
+/// is released for release regions. Release regions use the velocity of
+/// the released note.
+///
+/// Regions with `on_loccN`/`on_hiccN` are played at their `pitch_keycenter`
+/// and full velocity when the controller moves into the range, e.g. for
+/// pedal noises on CC64. They aren't played by notes.
+///
+/// The `loccN`/`hiccN` conditions of regions with a `region_label` or
+/// `group_label` are listed as articulation switches, see
+/// `SoundfontBase::articulation_switches`.
+///
+/// Regions with `polyphony` limit the voices of their `group` on all keys of
+/// a channel, and regions with `note_polyphony` limit them on each key. The
+/// oldest voices of the group are killed first, and the channel and key voice
+/// limits still apply.
+///
+/// The loop points, root key, tuning and gain of the regions of both formats
+/// can be overridden after loading, see `SampleSoundfont::set_region_override`.
 ///
 /// ## SF2 specification support
 /// ### Generators
@@ -141,6 +351,49 @@ pub(super) struct SoundfontInstrument {
 pub struct SampleSoundfont {
     instruments: Vec<SoundfontInstrument>,
     stream_params: AudioStreamParams,
+    metadata: SoundfontMetadata,
+    presets: Vec<PresetInfo>,
+
+    /// The gain applied to the spawned voices, including the normalization
+    /// gain
+    gain: f32,
+    normalization_gain: f32,
+    memory_report: MemoryReport,
+
+    regions: Vec<RegionInfo>,
+    region_overrides: RegionOverrides,
+
+    /// Keeps the cached files the soundfont was loaded from in use, see
+    /// `SoundfontCache::evict_unused`. Only held, never read.
+    _cached_files: Vec<Arc<CachedFile>>,
+
+    source: SoundfontSource,
+}
+
+/// Where a `SampleSoundfont` was loaded from, to load it again at another
+/// sample rate.
+#[derive(Clone)]
+struct SoundfontSource {
+    path: PathBuf,
+    format: SoundfontFormat,
+    options: SoundfontInitOptions,
+    cache: Option<SoundfontCache>,
+}
+
+#[derive(Clone, Copy)]
+enum SoundfontFormat {
+    Sfz,
+    Sf2,
+}
+
+/// The parts of a `SampleSoundfont` that are read from its files.
+struct LoadedSoundfont {
+    instruments: Vec<SoundfontInstrument>,
+    metadata: SoundfontMetadata,
+    presets: Vec<PresetInfo>,
+    memory_report: MemoryReport,
+    regions: Vec<RegionInfo>,
+    cached_files: Vec<Arc<CachedFile>>,
 }
 
 /// Errors that can be generated when loading an SFZ soundfont.
@@ -149,7 +402,7 @@ pub enum LoadSfzError {
     #[error("IO Error")]
     IOError(#[from] io::Error),
 
-    #[error("Error loading samples")]
+    #[error("Error loading samples: {0}")]
     AudioLoadError(#[from] AudioLoadError),
 
     #[error("Error parsing the SFZ: {0}")]
@@ -170,6 +423,21 @@ pub enum LoadSfError {
     Unsupported,
 }
 
+/// The error returned by `SampleSoundfont::new` and
+/// `SampleSoundfont::new_with_cache`, with the path of the soundfont that
+/// failed to load. The reason is also the source of the error, so the whole
+/// chain can be walked with `std::error::Error::source`.
+#[derive(Debug, Error)]
+#[error("Error loading the soundfont {}: {reason}", path.display())]
+pub struct SoundfontLoadError {
+    /// The path the soundfont was loaded from.
+    pub path: PathBuf,
+
+    /// Why the soundfont failed to load.
+    #[source]
+    pub reason: LoadSfError,
+}
+
 impl SampleSoundfont {
     /// Loads a new sample soundfont of an unspecified type.
     /// The type of the soundfont will be determined from the file extension.
@@ -184,21 +452,42 @@ impl SampleSoundfont {
         path: impl Into<PathBuf>,
         stream_params: AudioStreamParams,
         options: SoundfontInitOptions,
-    ) -> Result<Self, LoadSfError> {
-        let path: PathBuf = path.into();
-        if let Some(ext) = path.extension() {
-            match ext.to_str().unwrap_or("").to_lowercase().as_str() {
-                "sfz" => {
-                    Self::new_sfz(path, stream_params, options).map_err(LoadSfError::LoadSfzError)
-                }
-                "sf2" => {
-                    Self::new_sf2(path, stream_params, options).map_err(LoadSfError::LoadSf2Error)
-                }
-                _ => Err(LoadSfError::Unsupported),
-            }
-        } else {
-            Err(LoadSfError::Unsupported)
-        }
+    ) -> Result<Self, SoundfontLoadError> {
+        Self::load(path.into(), stream_params, options, None)
+    }
+
+    /// Loads a new sample soundfont of an unspecified type like `new`, with
+    /// its samples shared through the given cache. Samples decoded for
+    /// another soundfont loaded with the cache are reused instead of being
+    /// decoded and stored again.
+    ///
+    /// See the `SoundfontCache` documentation for more information.
+    pub fn new_with_cache(
+        path: impl Into<PathBuf>,
+        stream_params: AudioStreamParams,
+        options: SoundfontInitOptions,
+        cache: &SoundfontCache,
+    ) -> Result<Self, SoundfontLoadError> {
+        Self::load(path.into(), stream_params, options, Some(cache))
+    }
+
+    fn load(
+        path: PathBuf,
+        stream_params: AudioStreamParams,
+        options: SoundfontInitOptions,
+        cache: Option<&SoundfontCache>,
+    ) -> Result<Self, SoundfontLoadError> {
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_str().unwrap_or("").to_lowercase());
+        let result = match extension.as_deref() {
+            Some("sfz") => Self::load_sfz(path.clone(), stream_params, options, cache)
+                .map_err(LoadSfError::LoadSfzError),
+            Some("sf2") => Self::load_sf2(path.clone(), stream_params, options, cache)
+                .map_err(LoadSfError::LoadSf2Error),
+            _ => Err(LoadSfError::Unsupported),
+        };
+        result.map_err(|reason| SoundfontLoadError { path, reason })
     }
 
     /// Loads a new SFZ soundfont
@@ -214,46 +503,172 @@ impl SampleSoundfont {
         stream_params: AudioStreamParams,
         options: SoundfontInitOptions,
     ) -> Result<Self, LoadSfzError> {
-        let regions = xsynth_soundfonts::sfz::parse_soundfont(sfz_path.into())?;
+        Self::load_sfz(sfz_path.into(), stream_params, options, None)
+    }
+
+    fn load_sfz(
+        sfz_path: PathBuf,
+        stream_params: AudioStreamParams,
+        options: SoundfontInitOptions,
+        cache: Option<&SoundfontCache>,
+    ) -> Result<Self, LoadSfzError> {
+        let start = Instant::now();
+        let regions = xsynth_soundfonts::sfz::parse_soundfont(sfz_path.clone())?;
+
+        let metadata = SoundfontMetadata {
+            name: sfz_path
+                .file_stem()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+
+        // The whole file is a single preset, named after its labels if any
+        let playable_regions = || {
+            regions
+                .iter()
+                .filter(|r| r.cc_trigger.is_none() && !r.keyrange.contains(&-1))
+        };
+        let key_range = range_union(
+            playable_regions()
+                .map(|r| (*r.keyrange.start()).max(0) as u8..=(*r.keyrange.end()).max(0) as u8),
+        );
+        let vel_range = range_union(
+            playable_regions().map(|r| *r.velrange.start()..=(*r.velrange.end()).min(127)),
+        );
+        let label = regions
+            .iter()
+            .find_map(|r| r.global_label.clone())
+            .or_else(|| regions.iter().find_map(|r| r.group_label.clone()));
+
+        let mut presets = Vec::new();
+        if let (Some(key_range), Some(vel_range)) = (key_range, vel_range) {
+            presets.push(PresetInfo {
+                bank: options.bank.unwrap_or(0),
+                preset: options.preset.unwrap_or(0),
+                name: label.unwrap_or_else(|| metadata.name.clone()),
+                key_range,
+                vel_range,
+            });
+        }
+
+        let articulation_switches = articulation_switches(playable_regions().filter_map(|r| {
+            let label = r.region_label.as_ref().or(r.group_label.as_ref())?;
+            Some((label.as_str(), r.cc_conditions.as_slice()))
+        }));
 
         // Find the unique samples that we need to parse and convert
         let unique_sample_params: HashSet<_> = regions
             .iter()
-            .map(sample_cache_from_region_params)
+            .map(|r| sample_cache_from_region_params(r, &options))
             .collect();
 
         // Parse and convert them in parallel
-        let samples: Result<HashMap<_, _>, _> = unique_sample_params
+        let sample_load_start = Instant::now();
+        let loaded: Result<Vec<_>, _> = unique_sample_params
             .into_par_iter()
-            .map(|params| -> Result<(_, _), LoadSfzError> {
-                let sample = load_audio_file(&params.path, stream_params)?;
-                Ok((params, sample))
+            .map(|params| -> Result<(_, _, _, _), LoadSfzError> {
+                let ((sample, sample_rate), cached, hit) =
+                    load_sample(&params.path, stream_params, cache)?;
+                let sample = fix_sample(&sample, params.invert_phase, params.swap_channels);
+                // Inverted samples are copies of the cached ones
+                let hit = hit && !params.invert_phase;
+                Ok((params, (sample, sample_rate), cached, hit))
             })
             .collect();
-        let samples = samples?;
+        let mut memory_report = MemoryReport {
+            sample_load_time: sample_load_start.elapsed(),
+            ..Default::default()
+        };
+        let mut samples = HashMap::new();
+        let mut cached_files = Vec::new();
+        for (params, sample, cached, hit) in loaded? {
+            memory_report.add_file(params.path.clone(), sample_bytes(&sample.0), hit);
+            samples.insert(params, sample);
+            cached_files.extend(cached);
+        }
 
         // Generate region params
-        let mut spawner_params_list = Vec::<Vec<Arc<SampleVoiceSpawnerParams>>>::new();
-        for _ in 0..(128 * 128) {
-            spawner_params_list.push(Vec::new());
-        }
+        let mut spawner_params_list = new_spawner_params_list();
+        let mut release_spawner_params_list = new_spawner_params_list();
+        let mut cc_spawner_params_list = Vec::new();
+        let mut region_infos = Vec::new();
 
         // Write region params
         for region in regions {
-            let params = sample_cache_from_region_params(&region);
+            let params = sample_cache_from_region_params(&region, &options);
             let envelope = envelope_descriptor_from_region_params(&region.ampeg_envelope);
 
-            // Key value -1 is used for CC triggered regions which are not supported by XSynth
-            if region.keyrange.contains(&-1) {
+            // CC triggered regions are played once at their key center, other
+            // regions with key value -1 can't be played and are skipped
+            let (keyrange, velrange, pitch_keycenter) = if region.cc_trigger.is_some() {
+                let key = region.pitch_keycenter.max(0);
+                (key..=key, 127..=127, key)
+            } else if region.keyrange.contains(&-1) {
                 continue;
-            }
+            } else {
+                (
+                    region.keyrange.clone(),
+                    region.velrange.clone(),
+                    region.pitch_keycenter,
+                )
+            };
+            memory_report.region_count += 1;
+
+            let cc_conditions: Arc<[CcCondition]> = region.cc_conditions.clone().into();
+            let polyphony = VoicePolyphony {
+                group: region.group,
+                polyphony: region.polyphony,
+                note_polyphony: region.note_polyphony,
+            };
+            let polyphony = polyphony.is_limited().then_some(polyphony);
+            let params_list = match region.trigger {
+                RegionTrigger::Attack | RegionTrigger::First | RegionTrigger::Legato => {
+                    &mut spawner_params_list
+                }
+                RegionTrigger::Release => &mut release_spawner_params_list,
+            };
 
-            for key in region.keyrange.clone() {
-                for vel in region.velrange.clone() {
+            let (sample, sample_rate) = &samples[&params];
+            let loop_params = LoopParams {
+                mode: if region.loop_start == region.loop_end {
+                    LoopMode::NoLoop
+                } else {
+                    region.loop_mode
+                },
+                offset: convert_sample_index(
+                    region.offset,
+                    *sample_rate,
+                    stream_params.sample_rate,
+                ),
+                start: convert_sample_index(
+                    region.loop_start,
+                    *sample_rate,
+                    stream_params.sample_rate,
+                ),
+                end: convert_sample_index(region.loop_end, *sample_rate, stream_params.sample_rate),
+            };
+
+            let region_index = region_infos.len();
+            region_infos.push(RegionInfo {
+                bank: options.bank.unwrap_or(0),
+                preset: options.preset.unwrap_or(0),
+                key_range: *keyrange.start() as u8..=*keyrange.end() as u8,
+                vel_range: velrange.clone(),
+                sample_path: Some(params.path.clone()),
+                sample_frames: sample.first().map_or(0, |s| s.len() as u32),
+                loop_mode: region.loop_mode,
+                loop_start: loop_params.start,
+                loop_end: loop_params.end,
+                root_key: pitch_keycenter as u8,
+                tune_cents: region.tune as f32,
+            });
+
+            for key in keyrange {
+                for vel in velrange.clone() {
                     let index = key_vel_to_index(key as u8, vel);
-                    let speed_mult =
-                        get_speed_mult_from_keys(key as u8, region.pitch_keycenter as u8)
-                            * cents_factor(region.tune as f32);
+                    let speed_mult = get_speed_mult_from_keys(key as u8, pitch_keycenter as u8)
+                        * cents_factor(region.tune as f32);
 
                     let mut envelope = envelope;
                     envelope.release +=
@@ -301,37 +716,6 @@ impl SampleSoundfont {
                     let vol_db = (region.volume as f32 + vol_db_add).clamp(-96.0, 12.0);
                     let volume = vol_mult * db_to_amp(vol_db);
 
-                    let sample_rate = samples[&params].1;
-
-                    let loop_params = LoopParams {
-                        mode: if region.loop_start == region.loop_end {
-                            LoopMode::NoLoop
-                        } else {
-                            region.loop_mode
-                        },
-                        offset: convert_sample_index(
-                            region.offset,
-                            sample_rate,
-                            stream_params.sample_rate,
-                        ),
-                        start: convert_sample_index(
-                            region.loop_start,
-                            sample_rate,
-                            stream_params.sample_rate,
-                        ),
-                        end: convert_sample_index(
-                            region.loop_end,
-                            sample_rate,
-                            stream_params.sample_rate,
-                        ),
-                    };
-
-                    let mut region_samples = samples[&params].0.clone();
-                    if stream_params.channels == ChannelCount::Stereo && region_samples.len() == 1 {
-                        region_samples =
-                            Arc::new([region_samples[0].clone(), region_samples[0].clone()]);
-                    }
-
                     let spawner_params = Arc::new(SampleVoiceSpawnerParams {
                         pan,
                         volume,
@@ -341,27 +725,58 @@ impl SampleSoundfont {
                         resonance: db_to_amp(region.resonance) * Q_BUTTERWORTH_F32,
                         filter_type: region.filter_type,
                         interpolator: options.interpolator,
-                        loop_params,
-                        sample: region_samples,
+                        loop_params: loop_params.clone(),
+                        sample: sample.clone(),
+                        start_ramp: options.start_ramp,
+                        trigger: region.trigger,
+                        cc_conditions: cc_conditions.clone(),
+                        polyphony,
+                        region: region_index,
                     });
 
-                    spawner_params_list[index].push(spawner_params.clone());
+                    match &region.cc_trigger {
+                        Some(cc_trigger) => {
+                            cc_spawner_params_list.push((cc_trigger.clone(), spawner_params))
+                        }
+                        None => params_list[index].push(spawner_params.clone()),
+                    }
                 }
             }
         }
 
-        Ok(SampleSoundfont {
-            instruments: vec![SoundfontInstrument {
-                bank: options.bank.unwrap_or(0),
-                preset: options.preset.unwrap_or(0),
-                spawner_params_list,
-            }],
-            stream_params,
-        })
+        let instruments = vec![SoundfontInstrument {
+            bank: options.bank.unwrap_or(0),
+            preset: options.preset.unwrap_or(0),
+            spawner_params_list,
+            release_spawner_params_list,
+            cc_spawner_params_list,
+            articulation_switches,
+        }];
+
+        let loaded = LoadedSoundfont {
+            instruments,
+            metadata,
+            presets,
+            memory_report,
+            regions: region_infos,
+            cached_files,
+        };
+        let source = SoundfontSource {
+            path: sfz_path,
+            format: SoundfontFormat::Sfz,
+            options,
+            cache: cache.cloned(),
+        };
+        let mut soundfont = SampleSoundfont::from_loaded(loaded, stream_params, source);
+        soundfont.memory_report.load_time = start.elapsed();
+        Ok(soundfont)
     }
 
     /// Loads a new SF2 soundfont
     ///
+    /// If some samples, instruments or zones of the file are malformed, they
+    /// are skipped and listed in the `warnings` of the soundfont metadata.
+    ///
     /// Parameters:
     /// - `path`: The path of the SF2 soundfont to be loaded.
     /// - `stream_params`: Parameters of the output audio. See the `AudioStreamParams`
@@ -373,10 +788,60 @@ impl SampleSoundfont {
         stream_params: AudioStreamParams,
         options: SoundfontInitOptions,
     ) -> Result<Self, Sf2ParseError> {
-        let presets =
-            xsynth_soundfonts::sf2::load_soundfont(sf2_path.into(), stream_params.sample_rate)?;
+        Self::load_sf2(sf2_path.into(), stream_params, options, None)
+    }
+
+    fn load_sf2(
+        sf2_path: PathBuf,
+        stream_params: AudioStreamParams,
+        options: SoundfontInitOptions,
+        cache: Option<&SoundfontCache>,
+    ) -> Result<Self, Sf2ParseError> {
+        let start = Instant::now();
+        let load = || {
+            xsynth_soundfonts::sf2::load_soundfont_with_info(
+                sf2_path.clone(),
+                stream_params.sample_rate,
+            )
+        };
+        let mut cached_files = Vec::new();
+        let mut cache_hit = false;
+        let (info, presets) = match cache {
+            Some(cache) => {
+                let (file, hit) = cache.get_or_load(&sf2_path, stream_params, || {
+                    load().map(|(info, presets)| CachedFile::Sf2(info, presets))
+                })?;
+                match &*file {
+                    CachedFile::Sf2(info, presets) => {
+                        let loaded = (info.clone(), presets.clone());
+                        cached_files.push(file);
+                        cache_hit = hit;
+                        loaded
+                    }
+                    CachedFile::Sample(_) => load()?,
+                }
+            }
+            None => load()?,
+        };
+        let mut memory_report = MemoryReport {
+            sample_load_time: start.elapsed(),
+            ..Default::default()
+        };
+        // Samples are shared by the regions that use them
+        let mut fixed_samples = HashMap::new();
+        let mut sample_bytes_total = 0;
+
+        let metadata = SoundfontMetadata {
+            name: info.name,
+            author: info.author,
+            copyright: info.copyright,
+            comments: info.comments,
+            warnings: info.warnings.iter().map(|w| w.to_string()).collect(),
+        };
 
         let mut instruments = Vec::new();
+        let mut preset_infos = Vec::new();
+        let mut region_infos = Vec::new();
 
         for preset in presets {
             if let Some(bank) = options.bank {
@@ -390,12 +855,30 @@ impl SampleSoundfont {
                 }
             }
 
-            let mut spawner_params_list = Vec::<Vec<Arc<SampleVoiceSpawnerParams>>>::new();
-            for _ in 0..(128 * 128) {
-                spawner_params_list.push(Vec::new());
+            let key_range = range_union(preset.regions.iter().map(|r| r.keyrange.clone()));
+            let vel_range = range_union(preset.regions.iter().map(|r| r.velrange.clone()));
+            if let (Some(key_range), Some(vel_range)) = (key_range, vel_range) {
+                preset_infos.push(PresetInfo {
+                    bank: preset.bank as u8,
+                    preset: preset.preset as u8,
+                    name: preset.name.clone(),
+                    key_range,
+                    vel_range,
+                });
             }
 
+            let mut spawner_params_list = new_spawner_params_list();
+            let no_conditions: Arc<[CcCondition]> = Arc::new([]);
+
+            memory_report.region_count += preset.regions.len();
             for region in preset.regions {
+                let sample = fixed_samples
+                    .entry(Arc::as_ptr(&region.sample))
+                    .or_insert_with(|| {
+                        sample_bytes_total += sample_bytes(&region.sample);
+                        fix_sample(&region.sample, options.invert_phase, options.swap_channels)
+                    })
+                    .clone();
                 let envelope_params = Arc::new(
                     envelope_descriptor_from_region_params(&region.ampeg_envelope)
                         .to_envelope_params(
@@ -404,6 +887,32 @@ impl SampleSoundfont {
                         ),
                 );
 
+                let loop_params = LoopParams {
+                    mode: if region.loop_start == region.loop_end {
+                        LoopMode::NoLoop
+                    } else {
+                        region.loop_mode
+                    },
+                    offset: region.offset,
+                    start: region.loop_start,
+                    end: region.loop_end,
+                };
+
+                let region_index = region_infos.len();
+                region_infos.push(RegionInfo {
+                    bank: preset.bank as u8,
+                    preset: preset.preset as u8,
+                    key_range: region.keyrange.clone(),
+                    vel_range: region.velrange.clone(),
+                    sample_path: None,
+                    sample_frames: sample.first().map_or(0, |s| s.len() as u32),
+                    loop_mode: region.loop_mode,
+                    loop_start: region.loop_start,
+                    loop_end: region.loop_end,
+                    root_key: region.root_key,
+                    tune_cents: region.fine_tune as f32 + region.coarse_tune as f32 * 100.0,
+                });
+
                 for key in region.keyrange.clone() {
                     for vel in region.velrange.clone() {
                         let index = key_vel_to_index(key, vel);
@@ -427,25 +936,6 @@ impl SampleSoundfont {
                         let pan = ((region.pan as f32 / 500.0) + 1.0) / 2.0;
                         let volume = region.volume * (vel as f32 / 127.0).powi(2);
 
-                        let loop_params = LoopParams {
-                            mode: if region.loop_start == region.loop_end {
-                                LoopMode::NoLoop
-                            } else {
-                                region.loop_mode
-                            },
-                            offset: region.offset,
-                            start: region.loop_start,
-                            end: region.loop_end,
-                        };
-
-                        let mut region_samples = region.sample.clone();
-                        if stream_params.channels == ChannelCount::Stereo
-                            && region_samples.len() == 1
-                        {
-                            region_samples =
-                                Arc::new([region_samples[0].clone(), region_samples[0].clone()]);
-                        }
-
                         let spawner_params = Arc::new(SampleVoiceSpawnerParams {
                             pan,
                             volume,
@@ -455,8 +945,13 @@ impl SampleSoundfont {
                             resonance: db_to_amp(region.resonance) * Q_BUTTERWORTH_F32,
                             filter_type: FilterType::LowPass,
                             interpolator: options.interpolator,
-                            loop_params,
-                            sample: region_samples,
+                            loop_params: loop_params.clone(),
+                            sample: sample.clone(),
+                            start_ramp: options.start_ramp,
+                            trigger: RegionTrigger::Attack,
+                            cc_conditions: no_conditions.clone(),
+                            polyphony: None,
+                            region: region_index,
                         });
 
                         spawner_params_list[index].push(spawner_params.clone());
@@ -468,14 +963,112 @@ impl SampleSoundfont {
                 bank: preset.bank as u8,
                 preset: preset.preset as u8,
                 spawner_params_list,
+                release_spawner_params_list: Vec::new(),
+                cc_spawner_params_list: Vec::new(),
+                articulation_switches: Vec::new(),
             };
             instruments.push(new);
         }
 
-        Ok(SampleSoundfont {
+        // Sorted so the instruments can be looked up with a binary search.
+        // The sort is stable, so the first preset of duplicates is used.
+        instruments.sort_by_key(|i| (i.bank, i.preset));
+
+        // Inverted samples are copies of the cached ones
+        let cache_hit = cache_hit && !options.invert_phase;
+        memory_report.add_file(sf2_path.clone(), sample_bytes_total, cache_hit);
+        let loaded = LoadedSoundfont {
             instruments,
+            metadata,
+            presets: preset_infos,
+            memory_report,
+            regions: region_infos,
+            cached_files,
+        };
+        let source = SoundfontSource {
+            path: sf2_path,
+            format: SoundfontFormat::Sf2,
+            options,
+            cache: cache.cloned(),
+        };
+        let mut soundfont = SampleSoundfont::from_loaded(loaded, stream_params, source);
+        soundfont.memory_report.load_time = start.elapsed();
+        Ok(soundfont)
+    }
+
+    fn from_loaded(
+        loaded: LoadedSoundfont,
+        stream_params: AudioStreamParams,
+        source: SoundfontSource,
+    ) -> Self {
+        let options = source.options;
+        let normalization_gain = normalization_gain(&loaded.instruments, options.normalization);
+        SampleSoundfont {
+            instruments: loaded.instruments,
             stream_params,
-        })
+            metadata: loaded.metadata,
+            presets: loaded.presets,
+            gain: normalization_gain * db_to_amp(options.gain_db),
+            normalization_gain,
+            memory_report: loaded.memory_report,
+            regions: loaded.regions,
+            region_overrides: Default::default(),
+            _cached_files: loaded.cached_files,
+            source,
+        }
+    }
+
+    /// Returns the gain applied to the voices of the soundfont, including
+    /// the normalization gain. See `SoundfontInitOptions::gain_db`.
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Returns the gain computed by the normalization when the soundfont was
+    /// loaded, or `1.0` if it wasn't normalized. See the `Normalization`
+    /// documentation for more information.
+    pub fn normalization_gain(&self) -> f32 {
+        self.normalization_gain
+    }
+
+    /// Returns the memory used by the decoded samples of the soundfont, its
+    /// number of regions and the time it took to load. See the
+    /// `MemoryReport` documentation for more information.
+    pub fn memory_report(&self) -> &MemoryReport {
+        &self.memory_report
+    }
+
+    /// Loads the soundfont again for another sample rate, from the same
+    /// files, with the same options and cache, and with the same region
+    /// overrides.
+    ///
+    /// The samples are resampled and the envelopes and filters converted to
+    /// the sample rate when a soundfont is loaded, so it has to be loaded
+    /// again to play at another rate. If the files changed since and the
+    /// overrides don't fit the regions anymore, they are dropped.
+    pub fn reload_at(&self, sample_rate: u32) -> Result<Self, SoundfontLoadError> {
+        let SoundfontSource {
+            path,
+            format,
+            options,
+            cache,
+        } = self.source.clone();
+        let stream_params = AudioStreamParams::new(sample_rate, self.stream_params.channels);
+        let result = match format {
+            SoundfontFormat::Sfz => {
+                Self::load_sfz(path.clone(), stream_params, options, cache.as_ref())
+                    .map_err(LoadSfError::LoadSfzError)
+            }
+            SoundfontFormat::Sf2 => {
+                Self::load_sf2(path.clone(), stream_params, options, cache.as_ref())
+                    .map_err(LoadSfError::LoadSf2Error)
+            }
+        };
+        let soundfont = result.map_err(|reason| SoundfontLoadError { path, reason })?;
+
+        // The loop points of the overrides are converted to the new rate
+        soundfont.apply_patch(&self.export_patch()).ok();
+        Ok(soundfont)
     }
 }
 
@@ -485,71 +1078,296 @@ impl std::fmt::Debug for SampleSoundfont {
     }
 }
 
-impl SoundfontBase for SampleSoundfont {
-    fn stream_params(&self) -> &'_ AudioStreamParams {
-        &self.stream_params
+impl SampleSoundfont {
+    fn instrument(&self, bank: u8, preset: u8) -> Option<&SoundfontInstrument> {
+        let index = self
+            .instruments
+            .partition_point(|i| (i.bank, i.preset) < (bank, preset));
+        self.instruments
+            .get(index)
+            .filter(|i| i.bank == bank && i.preset == preset)
     }
 
-    fn get_attack_voice_spawners_at(
+    fn get_voice_spawners_at(
         &self,
         bank: u8,
         preset: u8,
         key: u8,
         vel: u8,
+        crossfade_width: u8,
+        list: impl Fn(&SoundfontInstrument) -> &[Vec<Arc<SampleVoiceSpawnerParams>>],
     ) -> Vec<Box<dyn VoiceSpawner>> {
         use simdeez::*; // nuts
 
         use simdeez::prelude::*;
 
-        simd_runtime_generate!(
+        simd_dispatch!(
             fn get(
-                key: u8,
                 vel: u8,
-                sf: &SoundfontInstrument,
+                layers: &[(&[Arc<SampleVoiceSpawnerParams>], f32)],
+                overrides: &OverrideSnapshot,
                 stream_params: &AudioStreamParams,
             ) -> Vec<Box<dyn VoiceSpawner>> {
-                if sf.spawner_params_list.is_empty() {
-                    return Vec::new();
-                }
-
-                let index = key_vel_to_index(key, vel);
                 let mut vec = Vec::<Box<dyn VoiceSpawner>>::new();
-                for spawner in &sf.spawner_params_list[index] {
-                    match stream_params.channels {
-                        ChannelCount::Stereo => vec.push(Box::new(
-                            StereoSampledVoiceSpawner::<S>::new(spawner, vel, *stream_params),
-                        )),
-                        ChannelCount::Mono => vec.push(Box::new(
-                            MonoSampledVoiceSpawner::<S>::new(spawner, vel, *stream_params),
-                        )),
+                for (spawners, gain) in layers {
+                    for spawner in spawners.iter() {
+                        let spawner = overrides.params(spawner);
+                        let mut spawner: Box<dyn VoiceSpawner> = match stream_params.channels {
+                            ChannelCount::Mono => Box::new(MonoSampledVoiceSpawner::<S>::new(
+                                spawner,
+                                vel,
+                                *stream_params,
+                            )),
+                            _ => Box::new(StereoSampledVoiceSpawner::<S>::new(
+                                spawner,
+                                vel,
+                                *stream_params,
+                            )),
+                        };
+                        spawner.apply_gain(*gain);
+                        vec.push(spawner);
                     }
                 }
                 vec
             }
         );
 
-        let empty = SoundfontInstrument {
-            bank: 0,
-            preset: 0,
-            spawner_params_list: Vec::new(),
+        let spawner_params_list = self.instrument(bank, preset).map(list).unwrap_or(&[]);
+        if spawner_params_list.is_empty() {
+            return Vec::new();
+        }
+
+        let at = |vel: u8| spawner_params_list[key_vel_to_index(key, vel)].as_slice();
+        let spawners = at(vel);
+
+        // The layers are told apart by their samples, and velocities without
+        // regions aren't a layer to crossfade with
+        let is_other_layer = |other: u8| {
+            let other = at(other);
+            !other.is_empty()
+                && (other.len() != spawners.len()
+                    || other
+                        .iter()
+                        .zip(spawners)
+                        .any(|(a, b)| !Arc::ptr_eq(&a.sample, &b.sample)))
+        };
+        let crossfade = if spawners.is_empty() {
+            None
+        } else {
+            velocity_crossfade(vel, crossfade_width, is_other_layer)
         };
 
-        let instrument = self
-            .instruments
-            .iter()
-            .find(|i| i.bank == bank && i.preset == preset)
-            .unwrap_or(&empty);
+        let layers = match crossfade {
+            Some((other, gain)) => vec![
+                (spawners, self.gain * (1.0 - gain)),
+                (at(other), self.gain * gain),
+            ],
+            None => vec![(spawners, self.gain)],
+        };
+
+        self.region_overrides
+            .with(|overrides| get(vel, &layers, overrides, self.stream_params()))
+    }
+}
 
-        get(key, vel, instrument, self.stream_params())
+impl SoundfontBase for SampleSoundfont {
+    fn stream_params(&self) -> &'_ AudioStreamParams {
+        &self.stream_params
+    }
+
+    fn get_attack_voice_spawners_at(
+        &self,
+        bank: u8,
+        preset: u8,
+        key: u8,
+        vel: u8,
+    ) -> Vec<Box<dyn VoiceSpawner>> {
+        self.get_voice_spawners_at(bank, preset, key, vel, 0, |i| {
+            i.spawner_params_list.as_slice()
+        })
+    }
+
+    fn get_crossfaded_attack_voice_spawners_at(
+        &self,
+        bank: u8,
+        preset: u8,
+        key: u8,
+        vel: u8,
+        width: u8,
+    ) -> Vec<Box<dyn VoiceSpawner>> {
+        self.get_voice_spawners_at(bank, preset, key, vel, width, |i| {
+            i.spawner_params_list.as_slice()
+        })
     }
 
     fn get_release_voice_spawners_at(
         &self,
-        _bank: u8,
-        _preset: u8,
-        _key: u8,
-        _vel: u8,
+        bank: u8,
+        preset: u8,
+        key: u8,
+        vel: u8,
     ) -> Vec<Box<dyn VoiceSpawner>> {
-        vec![]
+        self.get_voice_spawners_at(bank, preset, key, vel, 0, |i| {
+            i.release_spawner_params_list.as_slice()
+        })
+    }
+
+    fn get_cc_voice_spawners_at(&self, bank: u8, preset: u8) -> Vec<CcVoiceSpawner> {
+        use simdeez::*; // nuts
+
+        use simdeez::prelude::*;
+
+        simd_dispatch!(
+            fn get(
+                cc_spawner_params_list: &[(CcCondition, Arc<SampleVoiceSpawnerParams>)],
+                overrides: &OverrideSnapshot,
+                stream_params: &AudioStreamParams,
+                gain: f32,
+            ) -> Vec<CcVoiceSpawner> {
+                let mut vec = Vec::new();
+                for (trigger, spawner) in cc_spawner_params_list {
+                    let spawner = overrides.params(spawner);
+                    let mut spawner: Box<dyn VoiceSpawner> = match stream_params.channels {
+                        ChannelCount::Mono => Box::new(MonoSampledVoiceSpawner::<S>::new(
+                            spawner,
+                            127,
+                            *stream_params,
+                        )),
+                        _ => Box::new(StereoSampledVoiceSpawner::<S>::new(
+                            spawner,
+                            127,
+                            *stream_params,
+                        )),
+                    };
+                    spawner.apply_gain(gain);
+                    vec.push(CcVoiceSpawner {
+                        controller: trigger.cc,
+                        range: trigger.range.clone(),
+                        spawner,
+                    });
+                }
+                vec
+            }
+        );
+
+        let cc_spawner_params_list = self
+            .instrument(bank, preset)
+            .map(|i| i.cc_spawner_params_list.as_slice())
+            .unwrap_or(&[]);
+
+        self.region_overrides.with(|overrides| {
+            get(
+                cc_spawner_params_list,
+                overrides,
+                self.stream_params(),
+                self.gain,
+            )
+        })
+    }
+
+    fn metadata(&self) -> SoundfontMetadata {
+        self.metadata.clone()
+    }
+
+    fn presets(&self) -> Vec<PresetInfo> {
+        self.presets.clone()
+    }
+
+    fn articulation_switches(&self, bank: u8, preset: u8) -> Vec<ArticulationSwitch> {
+        self.instrument(bank, preset)
+            .map(|i| i.articulation_switches.clone())
+            .unwrap_or_default()
+    }
+
+    fn generation(&self) -> u64 {
+        self.region_overrides.generation()
+    }
+
+    fn with_sample_rate(
+        &self,
+        sample_rate: u32,
+    ) -> Result<Option<Arc<dyn SoundfontBase>>, SoundfontLoadError> {
+        if sample_rate == self.stream_params.sample_rate {
+            return Ok(None);
+        }
+        Ok(Some(Arc::new(self.reload_at(sample_rate)?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, fs};
+
+    use super::*;
+    use crate::ChannelCount;
+
+    /// Writes the given files to a temporary directory and loads the first
+    /// one as a soundfont.
+    fn load(name: &str, files: &[(&str, &str)]) -> Result<SampleSoundfont, SoundfontLoadError> {
+        let dir = std::env::temp_dir().join(format!("xsynth_{name}_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, contents) in files {
+            fs::write(dir.join(file), contents).unwrap();
+        }
+
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        SampleSoundfont::new(dir.join(files[0].0), stream_params, Default::default())
+    }
+
+    #[test]
+    fn test_missing_files() {
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let error = SampleSoundfont::new("missing.sfz", stream_params, Default::default())
+            .err()
+            .unwrap();
+        assert_eq!(error.path, PathBuf::from("missing.sfz"));
+        assert!(matches!(
+            error.reason,
+            LoadSfError::LoadSfzError(LoadSfzError::SfzParseError(
+                SfzParseError::FailedToReadFile(_)
+            ))
+        ));
+
+        // The missing sample is named by the error and its source chain
+        let error = load(
+            "missing_sample",
+            &[("test.sfz", "<region> sample=missing.wav\n")],
+        )
+        .err()
+        .unwrap();
+        let LoadSfError::LoadSfzError(LoadSfzError::AudioLoadError(AudioLoadError::IOError(
+            path,
+            source,
+        ))) = &error.reason
+        else {
+            panic!("unexpected error: {error}");
+        };
+        assert!(path.ends_with("missing.wav"));
+        assert_eq!(source.kind(), io::ErrorKind::NotFound);
+        assert!(error.to_string().contains("missing.wav"));
+        assert!(error.source().is_some_and(|e| e.source().is_some()));
+    }
+
+    #[test]
+    fn test_bad_files() {
+        // A sample opcode naming a file that isn't audio
+        let error = load(
+            "bad_sample",
+            &[
+                ("test.sfz", "<region> sample=notes.txt\n"),
+                ("notes.txt", "not audio"),
+            ],
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(
+            error.reason,
+            LoadSfError::LoadSfzError(LoadSfzError::AudioLoadError(
+                AudioLoadError::AudioDecodingFailed(..)
+            ))
+        ));
+
+        let error = load("unsupported", &[("test.txt", "")]).err().unwrap();
+        assert!(matches!(error.reason, LoadSfError::Unsupported));
     }
 }
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use crate::util::{amp_to_db, db_to_amp};
+
+use super::{Normalization, SoundfontInstrument};
+
+/// The level silent regions are counted with, in dB
+const LEVEL_FLOOR_DB: f32 = -96.0;
+
+/// Returns the gain that normalizes a soundfont with the given instruments,
+/// or `1.0` if it isn't normalized.
+///
+/// The level of a region is the peak of its sample multiplied by its volume
+/// at the velocity it is played with. Only the regions played by notes are
+/// counted, as release and controller triggered regions are usually quieter
+/// noises.
+pub(super) fn normalization_gain(
+    instruments: &[SoundfontInstrument],
+    normalization: Normalization,
+) -> f32 {
+    let Normalization::PeakLevel { target_db } = normalization else {
+        return 1.0;
+    };
+
+    // Many regions share their samples, so each sample is scanned once
+    let mut peaks: HashMap<*const f32, f32> = HashMap::new();
+    let levels = instruments
+        .iter()
+        .flat_map(|i| i.spawner_params_list.iter().flatten())
+        .map(|params| {
+            let peak = params
+                .sample
+                .iter()
+                .map(|channel| {
+                    *peaks
+                        .entry(channel.as_ptr())
+                        .or_insert_with(|| channel.iter().fold(0.0, |p, s| s.abs().max(p)))
+                })
+                .fold(0.0, f32::max);
+            peak * params.volume
+        });
+
+    match average_level_db(levels) {
+        Some(level_db) => db_to_amp(target_db - level_db),
+        None => 1.0,
+    }
+}
+
+/// Returns the average of the given levels in dB, or `None` if there are no
+/// levels to average.
+fn average_level_db(levels: impl Iterator<Item = f32>) -> Option<f32> {
+    let mut sum = 0.0f64;
+    let mut count = 0usize;
+    for level in levels {
+        sum += amp_to_db(level).max(LEVEL_FLOOR_DB) as f64;
+        count += 1;
+    }
+
+    (count > 0).then(|| (sum / count as f64) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_average_level() {
+        assert_eq!(average_level_db(std::iter::empty()), None);
+
+        // A soundfont mastered 12 dB hotter gets 12 dB more attenuation
+        let quiet = average_level_db([0.25, 0.5].into_iter()).unwrap();
+        let hot = average_level_db([1.0, 2.0].into_iter()).unwrap();
+        assert!((hot - quiet - 12.04).abs() < 0.01);
+
+        // Silent regions are counted at the floor level
+        let silent = average_level_db([0.0, 1.0].into_iter()).unwrap();
+        assert_eq!(silent, LEVEL_FLOOR_DB / 2.0);
+    }
+}
@@ -0,0 +1,552 @@
+use std::{
+    collections::HashMap,
+    ops::RangeInclusive,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crossbeam_epoch::{self as epoch, Atomic, Owned};
+use thiserror::Error;
+use xsynth_soundfonts::{convert_sample_index, LoopMode};
+
+use super::{utils::cents_factor, SampleSoundfont, SampleVoiceSpawnerParams};
+use crate::util::db_to_amp;
+
+/// The sample, loop and tuning settings of a region of a `SampleSoundfont`,
+/// as it was loaded. See `SampleSoundfont::regions`.
+///
+/// Positions in the sample are in frames of the loaded sample, which is
+/// resampled to the sample rate of the soundfont's `AudioStreamParams`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegionInfo {
+    /// The bank of the preset the region is part of.
+    pub bank: u8,
+
+    /// The preset the region is part of.
+    pub preset: u8,
+
+    /// The keys that play the region.
+    pub key_range: RangeInclusive<u8>,
+
+    /// The velocities that play the region.
+    pub vel_range: RangeInclusive<u8>,
+
+    /// The sample file of SFZ regions. SF2 samples are part of the
+    /// soundfont file, so this is `None` for them.
+    pub sample_path: Option<PathBuf>,
+
+    /// The length of the loaded sample in frames.
+    pub sample_frames: u32,
+
+    /// The loop mode of the region. Regions that don't loop ignore the loop
+    /// points, including overridden ones.
+    pub loop_mode: LoopMode,
+
+    /// The frame the loop starts at.
+    pub loop_start: u32,
+
+    /// The frame the loop ends at.
+    pub loop_end: u32,
+
+    /// The key the sample plays at its original pitch.
+    pub root_key: u8,
+
+    /// The tuning of the region, in cents.
+    pub tune_cents: f32,
+}
+
+/// Changes to the settings of a region of a `SampleSoundfont`, applied to
+/// the voices spawned after it is set. Settings left as `None` keep the
+/// value the region was loaded with. See
+/// `SampleSoundfont::set_region_override`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(default)
+)]
+pub struct RegionOverride {
+    /// The frame the loop starts at, see `RegionInfo::loop_start`.
+    pub loop_start: Option<u32>,
+
+    /// The frame the loop ends at, see `RegionInfo::loop_end`.
+    pub loop_end: Option<u32>,
+
+    /// The key the sample plays at its original pitch.
+    pub root_key: Option<u8>,
+
+    /// The tuning of the region in cents, replacing the loaded tuning.
+    pub tune_cents: Option<f32>,
+
+    /// A gain added to the volume of the region, in decibels.
+    pub gain_db: Option<f32>,
+}
+
+impl RegionOverride {
+    /// Returns true if the override doesn't change any setting.
+    pub fn is_empty(&self) -> bool {
+        *self == Default::default()
+    }
+}
+
+/// The override of a single region in a `SoundfontPatch`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(default)
+)]
+pub struct RegionPatch {
+    /// The index of the region in `SampleSoundfont::regions`.
+    pub region: usize,
+
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub overrides: RegionOverride,
+}
+
+/// The region overrides of a `SampleSoundfont`, which can be saved to a
+/// file (e.g. JSON or TOML with the `serde` feature) and applied to the same
+/// soundfont later or by someone else. See `SampleSoundfont::export_patch`.
+///
+/// Regions are identified by their index, so a patch only applies to the
+/// soundfont file it was made for, loaded with the same bank and preset
+/// options.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(default)
+)]
+pub struct SoundfontPatch {
+    /// The sample rate the loop points of the patch are in. They are
+    /// converted when the patch is applied to a soundfont loaded at another
+    /// sample rate. `0` is the sample rate of the soundfont.
+    pub sample_rate: u32,
+
+    /// The overridden regions, sorted by index.
+    pub regions: Vec<RegionPatch>,
+}
+
+/// Errors that can be generated when overriding the settings of a region.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum RegionOverrideError {
+    #[error("The soundfont has no region {0}")]
+    InvalidRegion(usize),
+
+    #[error("Invalid loop {start}..{end} for region {region} with {frames} frames")]
+    InvalidLoop {
+        region: usize,
+        start: u32,
+        end: u32,
+        frames: u32,
+    },
+
+    #[error("Invalid root key {key} for region {region}")]
+    InvalidRootKey { region: usize, key: u8 },
+}
+
+/// The region overrides of a soundfont, with the spawner parameters of the
+/// overridden regions prepared when they are edited.
+#[derive(Default)]
+pub(super) struct OverrideSnapshot {
+    regions: HashMap<usize, RegionOverride>,
+
+    /// The spawner parameters with their override applied, by the address
+    /// of the loaded parameters they replace
+    params: HashMap<usize, Arc<SampleVoiceSpawnerParams>>,
+}
+
+impl OverrideSnapshot {
+    /// Returns the spawner parameters with the override of their region
+    /// applied, if any.
+    pub fn params<'a>(
+        &'a self,
+        params: &'a Arc<SampleVoiceSpawnerParams>,
+    ) -> &'a Arc<SampleVoiceSpawnerParams> {
+        self.params
+            .get(&(Arc::as_ptr(params) as usize))
+            .unwrap_or(params)
+    }
+}
+
+/// The region overrides of a soundfont. Edits swap in a new snapshot, so
+/// spawning voices reads the current one without locking or allocating,
+/// and never waits for an edit.
+pub(super) struct RegionOverrides {
+    current: Atomic<OverrideSnapshot>,
+
+    /// Counts the edits, see `SoundfontBase::generation`
+    generation: AtomicU64,
+
+    /// Serializes the edits, so none of them is lost
+    edit: Mutex<()>,
+}
+
+impl Default for RegionOverrides {
+    fn default() -> Self {
+        RegionOverrides {
+            current: Atomic::new(OverrideSnapshot::default()),
+            generation: AtomicU64::new(0),
+            edit: Mutex::new(()),
+        }
+    }
+}
+
+impl Drop for RegionOverrides {
+    fn drop(&mut self) {
+        // SAFETY: the soundfont is being dropped, so no thread reads the
+        // snapshot anymore
+        unsafe {
+            let current = self.current.load(Ordering::Relaxed, epoch::unprotected());
+            drop(current.into_owned());
+        }
+    }
+}
+
+impl RegionOverrides {
+    /// Calls the function with the current snapshot.
+    pub fn with<T>(&self, f: impl FnOnce(&OverrideSnapshot) -> T) -> T {
+        let guard = epoch::pin();
+        let current = self.current.load(Ordering::Acquire, &guard);
+        // SAFETY: the snapshot is never null, and the guard keeps it alive
+        // until the function returns
+        f(unsafe { current.deref() })
+    }
+
+    /// The number of edits so far.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Edits the overrides, and prepares the spawner parameters of the
+    /// edited snapshot before swapping it in.
+    fn update(
+        &self,
+        f: impl FnOnce(&mut HashMap<usize, RegionOverride>),
+        prepare: impl FnOnce(
+            &HashMap<usize, RegionOverride>,
+        ) -> HashMap<usize, Arc<SampleVoiceSpawnerParams>>,
+    ) {
+        let _edit = self.edit.lock().unwrap();
+
+        let mut regions = self.with(|snapshot| snapshot.regions.clone());
+        f(&mut regions);
+        let params = prepare(&regions);
+
+        let guard = epoch::pin();
+        let previous = self.current.swap(
+            Owned::new(OverrideSnapshot { regions, params }),
+            Ordering::AcqRel,
+            &guard,
+        );
+        // SAFETY: the previous snapshot was swapped out, so it is only read
+        // by the threads that are already pinned
+        unsafe { guard.defer_destroy(previous) };
+        // Counted after the swap, so the channels that see the new count
+        // also see the new snapshot
+        self.generation.fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+impl RegionInfo {
+    fn check(&self, region: usize, overrides: &RegionOverride) -> Result<(), RegionOverrideError> {
+        let start = overrides.loop_start.unwrap_or(self.loop_start);
+        let end = overrides.loop_end.unwrap_or(self.loop_end);
+        if (overrides.loop_start.is_some() || overrides.loop_end.is_some())
+            && (start >= end || end > self.sample_frames)
+        {
+            return Err(RegionOverrideError::InvalidLoop {
+                region,
+                start,
+                end,
+                frames: self.sample_frames,
+            });
+        }
+
+        match overrides.root_key {
+            Some(key) if key > 127 => Err(RegionOverrideError::InvalidRootKey { region, key }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the spawner parameters of the region with the override
+    /// applied.
+    fn apply(
+        &self,
+        params: &SampleVoiceSpawnerParams,
+        overrides: &RegionOverride,
+    ) -> SampleVoiceSpawnerParams {
+        let mut params = params.clone();
+
+        let root_key = overrides.root_key.unwrap_or(self.root_key);
+        let tune_cents = overrides.tune_cents.unwrap_or(self.tune_cents);
+        params.speed_mult *= cents_factor(
+            (self.root_key as f32 - root_key as f32) * 100.0 + tune_cents - self.tune_cents,
+        );
+
+        if let Some(gain_db) = overrides.gain_db {
+            params.volume *= db_to_amp(gain_db);
+        }
+
+        if overrides.loop_start.is_some() || overrides.loop_end.is_some() {
+            params.loop_params.start = overrides.loop_start.unwrap_or(self.loop_start);
+            params.loop_params.end = overrides.loop_end.unwrap_or(self.loop_end);
+            params.loop_params.mode = self.loop_mode;
+        }
+
+        params
+    }
+}
+
+impl SampleSoundfont {
+    /// Returns the regions of the soundfont with their settings as they
+    /// were loaded, without the overrides. Regions are numbered by their
+    /// index in the list.
+    pub fn regions(&self) -> &[RegionInfo] {
+        &self.regions
+    }
+
+    /// Returns the override of the given region, if any.
+    pub fn region_override(&self, region: usize) -> Option<RegionOverride> {
+        self.region_overrides
+            .with(|snapshot| snapshot.regions.get(&region).copied())
+    }
+
+    /// Overrides the loop points, root key, tuning or gain of a region, e.g.
+    /// to fix slightly wrong loop points without editing the soundfont. The
+    /// override replaces any previous one of the region, and an empty
+    /// override removes it.
+    ///
+    /// The channels using the soundfont resolve their spawners again before
+    /// they next render, so the voices spawned afterwards use the new
+    /// settings. The voices already playing are left unchanged.
+    pub fn set_region_override(
+        &self,
+        region: usize,
+        overrides: RegionOverride,
+    ) -> Result<(), RegionOverrideError> {
+        let info = self
+            .regions
+            .get(region)
+            .ok_or(RegionOverrideError::InvalidRegion(region))?;
+        info.check(region, &overrides)?;
+
+        self.region_overrides.update(
+            |map| {
+                if overrides.is_empty() {
+                    map.remove(&region);
+                } else {
+                    map.insert(region, overrides);
+                }
+            },
+            |map| self.overridden_params(map),
+        );
+        Ok(())
+    }
+
+    /// Removes the overrides of all the regions.
+    pub fn clear_region_overrides(&self) {
+        self.region_overrides
+            .update(|map| map.clear(), |map| self.overridden_params(map));
+    }
+
+    /// Returns the overrides of the regions as a patch, to save them to a
+    /// file.
+    pub fn export_patch(&self) -> SoundfontPatch {
+        let mut regions: Vec<_> = self.region_overrides.with(|snapshot| {
+            snapshot
+                .regions
+                .iter()
+                .map(|(&region, &overrides)| RegionPatch { region, overrides })
+                .collect()
+        });
+        regions.sort_by_key(|patch| patch.region);
+
+        SoundfontPatch {
+            sample_rate: self.stream_params.sample_rate,
+            regions,
+        }
+    }
+
+    /// Applies the overrides of a patch, replacing the previous overrides of
+    /// its regions. The patch is checked first, so if any of its overrides
+    /// is invalid none of them are applied.
+    pub fn apply_patch(&self, patch: &SoundfontPatch) -> Result<(), RegionOverrideError> {
+        let sample_rate = self.stream_params.sample_rate;
+        let convert = |frame: u32| match patch.sample_rate {
+            0 => frame,
+            rate => convert_sample_index(frame, rate, sample_rate),
+        };
+
+        let mut overrides = Vec::with_capacity(patch.regions.len());
+        for &RegionPatch {
+            region,
+            overrides: mut region_override,
+        } in &patch.regions
+        {
+            region_override.loop_start = region_override.loop_start.map(convert);
+            region_override.loop_end = region_override.loop_end.map(convert);
+
+            let info = self
+                .regions
+                .get(region)
+                .ok_or(RegionOverrideError::InvalidRegion(region))?;
+            info.check(region, &region_override)?;
+            overrides.push((region, region_override));
+        }
+
+        self.region_overrides.update(
+            |map| {
+                for (region, region_override) in overrides {
+                    if region_override.is_empty() {
+                        map.remove(&region);
+                    } else {
+                        map.insert(region, region_override);
+                    }
+                }
+            },
+            |map| self.overridden_params(map),
+        );
+        Ok(())
+    }
+
+    /// Returns the spawner parameters of the overridden regions with their
+    /// override applied, by the address of the loaded parameters.
+    fn overridden_params(
+        &self,
+        overrides: &HashMap<usize, RegionOverride>,
+    ) -> HashMap<usize, Arc<SampleVoiceSpawnerParams>> {
+        let mut params = HashMap::new();
+        if overrides.is_empty() {
+            return params;
+        }
+
+        for instrument in &self.instruments {
+            let loaded = instrument
+                .spawner_params_list
+                .iter()
+                .chain(&instrument.release_spawner_params_list)
+                .flatten()
+                .chain(instrument.cc_spawner_params_list.iter().map(|(_, p)| p));
+            for loaded in loaded {
+                if let Some(region_override) = overrides.get(&loaded.region) {
+                    params
+                        .entry(Arc::as_ptr(loaded) as usize)
+                        .or_insert_with(|| {
+                            Arc::new(self.regions[loaded.region].apply(loaded, region_override))
+                        });
+                }
+            }
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AudioStreamParams, ChannelCount};
+
+    const SF2: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../soundfonts/fixtures/valid.sf2"
+    );
+
+    fn first_params(soundfont: &SampleSoundfont) -> Arc<SampleVoiceSpawnerParams> {
+        let mut params = soundfont
+            .instruments
+            .iter()
+            .flat_map(|i| i.spawner_params_list.iter().flatten());
+        params.next().unwrap().clone()
+    }
+
+    #[test]
+    fn test_region_override() {
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let soundfont = SampleSoundfont::new(SF2, stream_params, Default::default()).unwrap();
+        let params = first_params(&soundfont);
+        let info = soundfont.regions()[params.region].clone();
+        assert_eq!(info.loop_start, params.loop_params.start);
+        assert_eq!(info.loop_end, params.loop_params.end);
+
+        let overrides = RegionOverride {
+            loop_start: Some(info.loop_start + 1),
+            root_key: Some(60),
+            tune_cents: Some(info.tune_cents + 50.0),
+            gain_db: Some(-6.0),
+            ..Default::default()
+        };
+        soundfont
+            .set_region_override(params.region, overrides)
+            .unwrap();
+
+        let overridden = soundfont
+            .region_overrides
+            .with(|snapshot| snapshot.params(&params).clone());
+        assert!(!Arc::ptr_eq(&overridden, &params));
+        assert_eq!(overridden.loop_params.start, info.loop_start + 1);
+        assert_eq!(overridden.loop_params.end, info.loop_end);
+        let cents = (info.root_key as f32 - 60.0) * 100.0 + 50.0;
+        let expected_speed = params.speed_mult * cents_factor(cents);
+        assert!((overridden.speed_mult - expected_speed).abs() < 1e-6);
+        assert!((overridden.volume - params.volume * db_to_amp(-6.0)).abs() < 1e-6);
+
+        // Invalid overrides are rejected and keep the previous one
+        let invalid = RegionOverride {
+            loop_end: Some(info.sample_frames + 1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            soundfont.set_region_override(params.region, invalid),
+            Err(RegionOverrideError::InvalidLoop { .. })
+        ));
+        let missing = soundfont.regions().len();
+        assert_eq!(
+            soundfont.set_region_override(missing, overrides),
+            Err(RegionOverrideError::InvalidRegion(missing))
+        );
+        assert_eq!(soundfont.region_override(params.region), Some(overrides));
+
+        soundfont
+            .set_region_override(params.region, Default::default())
+            .unwrap();
+        assert_eq!(soundfont.region_override(params.region), None);
+    }
+
+    #[test]
+    fn test_patch() {
+        let load = |sample_rate| {
+            let stream_params = AudioStreamParams::new(sample_rate, ChannelCount::Stereo);
+            SampleSoundfont::new(SF2, stream_params, Default::default()).unwrap()
+        };
+        let soundfont = load(48000);
+        let region = first_params(&soundfont).region;
+        let info = soundfont.regions()[region].clone();
+        let overrides = RegionOverride {
+            loop_start: Some(info.loop_start + 2),
+            gain_db: Some(3.0),
+            ..Default::default()
+        };
+        soundfont.set_region_override(region, overrides).unwrap();
+
+        let patch = soundfont.export_patch();
+        assert_eq!(patch.sample_rate, 48000);
+        assert_eq!(patch.regions, [RegionPatch { region, overrides }]);
+
+        // Loop points are converted to the sample rate of the soundfont
+        let resampled = load(96000);
+        resampled.apply_patch(&patch).unwrap();
+        let applied = resampled.region_override(region).unwrap();
+        assert_eq!(
+            applied.loop_start,
+            Some(convert_sample_index(info.loop_start + 2, 48000, 96000))
+        );
+        assert_eq!(applied.gain_db, Some(3.0));
+
+        resampled.clear_region_overrides();
+        assert!(resampled.export_patch().regions.is_empty());
+    }
+}
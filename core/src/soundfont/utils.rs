@@ -1,15 +1,22 @@
-use crate::{helpers::FREQS, voice::EnvelopeDescriptor};
+use super::SoundfontInitOptions;
+use crate::{util::FREQS, voice::EnvelopeDescriptor};
 use std::path::PathBuf;
 use xsynth_soundfonts::sfz::{AmpegEnvelopeParams, RegionParams};
 
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub(super) struct SampleCache {
     pub(super) path: PathBuf,
+    pub(super) invert_phase: bool,
+    pub(super) swap_channels: bool,
 }
 
 impl SampleCache {
-    pub fn new(path: PathBuf) -> Self {
-        Self { path }
+    pub fn new(path: PathBuf, invert_phase: bool, swap_channels: bool) -> Self {
+        Self {
+            path,
+            invert_phase,
+            swap_channels,
+        }
     }
 }
 
@@ -27,8 +34,15 @@ pub(super) fn cents_factor(cents: f32) -> f32 {
     2.0f32.powf(cents / 1200.0)
 }
 
-pub(super) fn sample_cache_from_region_params(region_params: &RegionParams) -> SampleCache {
-    SampleCache::new(region_params.sample_path.clone())
+pub(super) fn sample_cache_from_region_params(
+    region_params: &RegionParams,
+    options: &SoundfontInitOptions,
+) -> SampleCache {
+    SampleCache::new(
+        region_params.sample_path.clone(),
+        region_params.invert_phase.unwrap_or(options.invert_phase),
+        region_params.swap_channels.unwrap_or(options.swap_channels),
+    )
 }
 
 pub(super) fn envelope_descriptor_from_region_params(
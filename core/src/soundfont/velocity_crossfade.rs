@@ -0,0 +1,51 @@
+/// Finds the velocity layer to crossfade with when playing `vel`, for a
+/// crossfade `width` velocity units wide centered on each layer boundary.
+///
+/// `is_other_layer` returns true if a velocity is played by a different
+/// layer than `vel`. Returns the nearest such velocity within the crossfade
+/// and the gain of its layer, while the layer of `vel` is played with the
+/// complementary gain. Returns `None` outside of the crossfades.
+pub(super) fn velocity_crossfade(
+    vel: u8,
+    width: u8,
+    is_other_layer: impl Fn(u8) -> bool,
+) -> Option<(u8, f32)> {
+    for distance in 1..=width / 2 + 1 {
+        // The boundary is halfway between the last velocity of the layer and
+        // the first velocity of the other one
+        let gain = 0.5 - (distance as f32 - 0.5) / width as f32;
+        if gain <= 0.0 {
+            break;
+        }
+
+        let neighbors = [vel.checked_sub(distance), vel.checked_add(distance)];
+        for other in neighbors.into_iter().flatten().filter(|&v| v <= 127) {
+            if is_other_layer(other) {
+                return Some((other, gain));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_velocity_crossfade() {
+        // Two layers split between velocity 63 and 64
+        let crossfade =
+            |vel: u8, width: u8| velocity_crossfade(vel, width, |other| (other < 64) != (vel < 64));
+
+        assert_eq!(crossfade(63, 8), Some((64, 0.4375)));
+        assert_eq!(crossfade(64, 8), Some((63, 0.4375)));
+        assert_eq!(crossfade(60, 8), Some((64, 0.0625)));
+        assert_eq!(crossfade(59, 8), None);
+        assert_eq!(crossfade(20, 8), None);
+
+        // A width of 1 switches the layers without a crossfade
+        assert_eq!(crossfade(63, 1), None);
+        assert_eq!(crossfade(63, 0), None);
+    }
+}
@@ -14,15 +14,21 @@ use crate::{
     voice::VoiceControlData,
     voice::{
         BufferSamplers, EnvelopeParameters, SIMDConstant, SIMDLinearSampleGrabber, SIMDMonoVoice,
-        SIMDMonoVoiceSampler, SIMDNearestSampleGrabber, SIMDVoiceControl, SIMDVoiceEnvelope,
-        SampleReader, SampleReaderLoop, SampleReaderLoopSustain, SampleReaderNoLoop, Voice,
-        VoiceBase, VoiceCombineSIMD,
+        SIMDMonoVoiceSampler, SIMDNearestSampleGrabber, SIMDStartRamp, SIMDTuningControl,
+        SIMDVoiceControl, SIMDVoiceEnvelope, SampleReader, SampleReaderLoop,
+        SampleReaderLoopSustain, SampleReaderNoLoop, Voice, VoiceBase, VoiceCombineSIMD,
+        VoicePolyphony,
     },
 };
 
-use xsynth_soundfonts::LoopMode;
+use xsynth_soundfonts::{
+    sfz::{CcCondition, RegionTrigger},
+    LoopMode,
+};
 
-use crate::soundfont::{Interpolator, LoopParams, SampleVoiceSpawnerParams, VoiceSpawner};
+use crate::soundfont::{
+    cents_factor, region_matches, Interpolator, LoopParams, SampleVoiceSpawnerParams, VoiceSpawner,
+};
 
 pub struct MonoSampledVoiceSpawner<S: 'static + Simd + Send + Sync> {
     speed_mult: f32,
@@ -33,6 +39,10 @@ pub struct MonoSampledVoiceSpawner<S: 'static + Simd + Send + Sync> {
     samples: Arc<[Arc<[f32]>]>,
     interpolator: Interpolator,
     vel: u8,
+    start_ramp: u32,
+    trigger: RegionTrigger,
+    cc_conditions: Arc<[CcCondition]>,
+    polyphony: Option<VoicePolyphony>,
     stream_params: AudioStreamParams,
     _s: PhantomData<S>,
 }
@@ -63,6 +73,10 @@ impl<S: Simd + Send + Sync> MonoSampledVoiceSpawner<S> {
             samples: params.sample.clone(),
             interpolator: params.interpolator,
             vel,
+            start_ramp: params.start_ramp,
+            trigger: params.trigger,
+            cc_conditions: params.cc_conditions.clone(),
+            polyphony: params.polyphony,
             stream_params,
             _s: PhantomData,
         }
@@ -116,7 +130,8 @@ impl<S: Simd + Send + Sync> MonoSampledVoiceSpawner<S> {
 
         let pitch_fac = self.create_pitch_fac(control);
 
-        let sampler = SIMDMonoVoiceSampler::new(sample, pitch_fac);
+        let sampler =
+            SIMDMonoVoiceSampler::new(sample, pitch_fac).starting_at(control.start_position);
         self.apply_voice_params(sampler, control)
     }
 
@@ -135,9 +150,12 @@ impl<S: Simd + Send + Sync> MonoSampledVoiceSpawner<S> {
         &self,
         control: &VoiceControlData,
     ) -> impl SIMDVoiceGenerator<S, SIMDSampleMono<S>> {
-        let pitch_fac = SIMDConstant::<S>::new(self.speed_mult);
+        let speed_mult = self.speed_mult * cents_factor(control.cents_offset);
+        let pitch_fac = SIMDConstant::<S>::new(speed_mult);
         let pitch_multiplier = SIMDVoiceControl::new(control, |vc| vc.voice_pitch_multiplier);
         let pitch_fac = VoiceCombineSIMD::mult(pitch_fac, pitch_multiplier);
+        let tuning = SIMDTuningControl::new(control);
+        let pitch_fac = VoiceCombineSIMD::mult(pitch_fac, tuning);
         pitch_fac
     }
 
@@ -159,12 +177,25 @@ impl<S: Simd + Send + Sync> MonoSampledVoiceSpawner<S> {
 
         let allow_release = self.loop_params.mode != LoopMode::OneShot;
 
-        let volume_envelope = SIMDVoiceEnvelope::new(
+        let mut volume_envelope = SIMDVoiceEnvelope::new(
             *self.volume_envelope_params.clone(),
             modified_params,
             allow_release,
             self.stream_params.sample_rate as f32,
         );
+        volume_envelope.set_release_scales(control.envelope.release_scales);
+
+        // The ramp is only needed if the envelope doesn't fade in already
+        let ramp_length = if modified_params.has_attack_of(self.start_ramp) {
+            0
+        } else {
+            self.start_ramp
+        };
+        let ramp = SIMDStartRamp::<S>::new(ramp_length);
+        let volume_envelope = VoiceCombineSIMD::mult::<SIMDSampleMono<S>, SIMDSampleMono<S>, _, _>(
+            ramp,
+            volume_envelope,
+        );
 
         let amp = VoiceCombineSIMD::mult(volume_envelope, gen);
         amp
@@ -207,4 +238,16 @@ impl<S: 'static + Sync + Send + Simd> VoiceSpawner for MonoSampledVoiceSpawner<S
     fn spawn_voice(&self, control: &VoiceControlData) -> Box<dyn Voice> {
         self.begin_voice(control)
     }
+
+    fn matches(&self, control: &VoiceControlData) -> bool {
+        region_matches(self.trigger, &self.cc_conditions, control)
+    }
+
+    fn apply_gain(&mut self, gain: f32) {
+        self.amp *= gain;
+    }
+
+    fn polyphony(&self) -> Option<VoicePolyphony> {
+        self.polyphony
+    }
 }
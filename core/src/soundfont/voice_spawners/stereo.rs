@@ -14,15 +14,22 @@ use crate::{
     voice::VoiceControlData,
     voice::{
         BufferSamplers, EnvelopeParameters, SIMDConstant, SIMDConstantStereo,
-        SIMDLinearSampleGrabber, SIMDNearestSampleGrabber, SIMDStereoVoice, SIMDStereoVoiceSampler,
-        SIMDVoiceControl, SIMDVoiceEnvelope, SampleReader, SampleReaderLoop,
+        SIMDLinearSampleGrabber, SIMDMonoVoiceSampler, SIMDNearestSampleGrabber, SIMDStartRamp,
+        SIMDStereoVoice, SIMDStereoVoiceSampler, SIMDTuningControl, SIMDVoiceControl,
+        SIMDVoiceEnvelope, SIMDVoiceMonoToStereo, SampleReader, SampleReaderLoop,
         SampleReaderLoopSustain, SampleReaderNoLoop, Voice, VoiceBase, VoiceCombineSIMD,
+        VoicePolyphony,
     },
 };
 
-use xsynth_soundfonts::LoopMode;
+use xsynth_soundfonts::{
+    sfz::{CcCondition, RegionTrigger},
+    LoopMode,
+};
 
-use crate::soundfont::{Interpolator, LoopParams, SampleVoiceSpawnerParams, VoiceSpawner};
+use crate::soundfont::{
+    cents_factor, region_matches, Interpolator, LoopParams, SampleVoiceSpawnerParams, VoiceSpawner,
+};
 
 pub struct StereoSampledVoiceSpawner<S: 'static + Simd + Send + Sync> {
     speed_mult: f32,
@@ -34,6 +41,10 @@ pub struct StereoSampledVoiceSpawner<S: 'static + Simd + Send + Sync> {
     samples: Arc<[Arc<[f32]>]>,
     interpolator: Interpolator,
     vel: u8,
+    start_ramp: u32,
+    trigger: RegionTrigger,
+    cc_conditions: Arc<[CcCondition]>,
+    polyphony: Option<VoicePolyphony>,
     stream_params: AudioStreamParams,
     _s: PhantomData<S>,
 }
@@ -65,6 +76,10 @@ impl<S: Simd + Send + Sync> StereoSampledVoiceSpawner<S> {
             samples: params.sample.clone(),
             interpolator: params.interpolator,
             vel,
+            start_ramp: params.start_ramp,
+            trigger: params.trigger,
+            cc_conditions: params.cc_conditions.clone(),
+            polyphony: params.polyphony,
             stream_params,
             _s: PhantomData,
         }
@@ -115,12 +130,24 @@ impl<S: Simd + Send + Sync> StereoSampledVoiceSpawner<S> {
         make_sampler: impl Fn(Arc<[f32]>) -> SG,
     ) -> Box<dyn Voice> {
         let left = make_sampler(self.samples[0].clone());
-        let right = make_sampler(self.samples[1].clone());
-
         let pitch_fac = self.create_pitch_fac(control);
 
-        let sampler = SIMDStereoVoiceSampler::new(left, right, pitch_fac);
-        self.apply_voice_params(sampler, control)
+        match self.samples.get(1) {
+            Some(right) => {
+                let right = make_sampler(right.clone());
+                let sampler = SIMDStereoVoiceSampler::new(left, right, pitch_fac)
+                    .starting_at(control.start_position);
+                self.apply_voice_params(sampler, control)
+            }
+            None => {
+                // A mono sample is read once, and each of its samples is
+                // copied to both channels of the same frame before panning
+                let sampler =
+                    SIMDMonoVoiceSampler::new(left, pitch_fac).starting_at(control.start_position);
+                let sampler = SIMDVoiceMonoToStereo::new(sampler);
+                self.apply_voice_params(sampler, control)
+            }
+        }
     }
 
     fn apply_velocity<Gen, Sample>(&self, gen: Gen) -> impl SIMDVoiceGenerator<S, Sample>
@@ -154,9 +181,12 @@ impl<S: Simd + Send + Sync> StereoSampledVoiceSpawner<S> {
         &self,
         control: &VoiceControlData,
     ) -> impl SIMDVoiceGenerator<S, SIMDSampleMono<S>> {
-        let pitch_fac = SIMDConstant::<S>::new(self.speed_mult);
+        let speed_mult = self.speed_mult * cents_factor(control.cents_offset);
+        let pitch_fac = SIMDConstant::<S>::new(speed_mult);
         let pitch_multiplier = SIMDVoiceControl::new(control, |vc| vc.voice_pitch_multiplier);
         let pitch_fac = VoiceCombineSIMD::mult(pitch_fac, pitch_multiplier);
+        let tuning = SIMDTuningControl::new(control);
+        let pitch_fac = VoiceCombineSIMD::mult(pitch_fac, tuning);
         pitch_fac
     }
 
@@ -178,12 +208,25 @@ impl<S: Simd + Send + Sync> StereoSampledVoiceSpawner<S> {
 
         let allow_release = self.loop_params.mode != LoopMode::OneShot;
 
-        let volume_envelope = SIMDVoiceEnvelope::new(
+        let mut volume_envelope = SIMDVoiceEnvelope::new(
             *self.volume_envelope_params.clone(),
             modified_params,
             allow_release,
             self.stream_params.sample_rate as f32,
         );
+        volume_envelope.set_release_scales(control.envelope.release_scales);
+
+        // The ramp is only needed if the envelope doesn't fade in already
+        let ramp_length = if modified_params.has_attack_of(self.start_ramp) {
+            0
+        } else {
+            self.start_ramp
+        };
+        let ramp = SIMDStartRamp::<S>::new(ramp_length);
+        let volume_envelope = VoiceCombineSIMD::mult::<SIMDSampleMono<S>, SIMDSampleMono<S>, _, _>(
+            ramp,
+            volume_envelope,
+        );
 
         let amp = VoiceCombineSIMD::mult(volume_envelope, gen);
         amp
@@ -227,4 +270,200 @@ impl<S: 'static + Sync + Send + Simd> VoiceSpawner for StereoSampledVoiceSpawner
     fn spawn_voice(&self, control: &VoiceControlData) -> Box<dyn Voice> {
         self.begin_voice(control)
     }
+
+    fn matches(&self, control: &VoiceControlData) -> bool {
+        region_matches(self.trigger, &self.cc_conditions, control)
+    }
+
+    fn apply_gain(&mut self, gain: f32) {
+        self.amp *= gain;
+    }
+
+    fn polyphony(&self) -> Option<VoicePolyphony> {
+        self.polyphony
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use simdeez::prelude::*;
+    use xsynth_soundfonts::FilterType;
+
+    use super::*;
+    use crate::{voice::EnvelopeDescriptor, ChannelCount};
+
+    simd_runtime_generate!(
+        fn render(
+            params: &SampleVoiceSpawnerParams,
+            control: &VoiceControlData,
+            stream_params: AudioStreamParams,
+        ) -> Vec<f32> {
+            let spawner = StereoSampledVoiceSpawner::<S>::new(params, 127, stream_params);
+            let mut voice = spawner.spawn_voice(control);
+            let mut out = vec![0.0; 512];
+            voice.render_to(&mut out);
+            out
+        }
+    );
+
+    /// The parameters of a centered note playing `samples` with the given
+    /// attack, without looping.
+    fn note_params(
+        samples: Arc<[Arc<[f32]>]>,
+        interpolator: Interpolator,
+        start_ramp: u32,
+        attack: f32,
+        stream_params: AudioStreamParams,
+    ) -> SampleVoiceSpawnerParams {
+        let envelope = EnvelopeDescriptor {
+            start_percent: 0.0,
+            delay: 0.0,
+            attack,
+            hold: 0.0,
+            decay: 0.0,
+            sustain_percent: 1.0,
+            release: 0.1,
+        }
+        .to_envelope_params(stream_params.sample_rate, Default::default());
+
+        SampleVoiceSpawnerParams {
+            volume: 1.0,
+            pan: 0.5,
+            speed_mult: 1.0,
+            cutoff: None,
+            resonance: 0.0,
+            filter_type: FilterType::LowPass,
+            loop_params: LoopParams {
+                mode: LoopMode::NoLoop,
+                offset: 0,
+                start: 0,
+                end: 0,
+            },
+            envelope: Arc::new(envelope),
+            sample: samples,
+            interpolator,
+            start_ramp,
+            trigger: RegionTrigger::Attack,
+            cc_conditions: Arc::new([]),
+            polyphony: None,
+            region: 0,
+        }
+    }
+
+    /// Renders the start of a note playing `sample` with the given attack,
+    /// without looping. Returns the left channel.
+    fn render_note(
+        sample: Arc<[f32]>,
+        interpolator: Interpolator,
+        start_ramp: u32,
+        attack: f32,
+        control: &VoiceControlData,
+    ) -> Vec<f32> {
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let samples = Arc::new([sample.clone(), sample]);
+        let params = note_params(samples, interpolator, start_ramp, attack, stream_params);
+        render(&params, control, stream_params)
+            .chunks(2)
+            .map(|f| f[0])
+            .collect()
+    }
+
+    /// Renders the start of a note playing a full-scale square wave, which
+    /// starts at 1.0 and flips every 50 samples. Returns the left channel.
+    fn render_square_note(start_ramp: u32, attack: f32) -> Vec<f32> {
+        let square: Arc<[f32]> = (0..4800)
+            .map(|i| if (i / 50) % 2 == 0 { 1.0 } else { -1.0 })
+            .collect();
+        render_note(
+            square,
+            Interpolator::Nearest,
+            start_ramp,
+            attack,
+            &VoiceControlData::new_defaults(),
+        )
+    }
+
+    /// Returns the playback rate of a note at the root key of its sample,
+    /// relative to the root key, by playing a ramp whose values are the
+    /// positions in the sample.
+    fn playback_rate(control: &VoiceControlData) -> f32 {
+        let ramp: Arc<[f32]> = (0..4800).map(|i| i as f32).collect();
+        let out = render_note(ramp, Interpolator::Linear, 0, 0.0, control);
+        (out[200] - out[100]) / 100.0
+    }
+
+    #[test]
+    fn test_start_ramp_removes_step() {
+        // Without the ramp the note starts with a full-scale step
+        let unramped = render_square_note(0, 0.0);
+        assert!(unramped[0] > 0.9);
+
+        // With the ramp the start of the note rises gradually from silence,
+        // up to the first flip of the square wave
+        let ramped = render_square_note(32, 0.0);
+        assert!(ramped[0].abs() < 1e-6);
+        let mut previous = 0.0;
+        for &s in ramped[..50].iter() {
+            assert!(
+                (s - previous).abs() <= 1.0 / 32.0 + 1e-4,
+                "step of {}",
+                s - previous
+            );
+            previous = s;
+        }
+        assert_eq!(ramped[32..50], unramped[32..50]);
+    }
+
+    #[test]
+    fn test_start_ramp_skips_real_attacks() {
+        // A 10ms attack already fades in, so the ramp must not change it
+        let attack = 0.01;
+        assert_eq!(
+            render_square_note(32, attack),
+            render_square_note(0, attack)
+        );
+    }
+
+    #[test]
+    fn test_cents_offset() {
+        // A semitone is 100 cents, so +50 cents plays between C4 and C#4
+        let semitone = 2.0f32.powf(1.0 / 12.0);
+        let mut control = VoiceControlData::new_defaults();
+        assert!((playback_rate(&control) - 1.0).abs() < 1e-3);
+
+        control.cents_offset = 50.0;
+        let detuned = playback_rate(&control);
+        assert!(detuned > 1.0 && detuned < semitone);
+        assert!((detuned - semitone.sqrt()).abs() < 1e-3);
+
+        // The offset adds to the pitch bend and tuning of the channel
+        control.voice_pitch_multiplier = semitone;
+        control.tuning.multiplier = 2.0;
+        let rate = playback_rate(&control);
+        assert!((rate - 2.0 * semitone * semitone.sqrt()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_mono_sample_frames() {
+        // An impulse in a mono sample plays on both channels of one frame
+        let impulse: Arc<[f32]> = (0..64).map(|i| if i == 4 { 1.0 } else { 0.0 }).collect();
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let params = note_params(
+            Arc::new([impulse]),
+            Interpolator::Linear,
+            0,
+            0.0,
+            stream_params,
+        );
+        let out = render(&params, &VoiceControlData::new_defaults(), stream_params);
+
+        for (i, frame) in out.chunks(2).enumerate() {
+            assert_eq!(frame[0], frame[1], "frame {i}");
+            if i == 4 {
+                assert!(frame[0] > 0.9);
+            } else {
+                assert_eq!(frame[0], 0.0, "frame {i}");
+            }
+        }
+    }
 }
@@ -0,0 +1,173 @@
+//! A stub soundfont for the unit tests of the channels and channel groups.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::{self, ThreadId},
+};
+
+use crossbeam_channel::Sender;
+
+use crate::{
+    soundfont::{CcVoiceSpawner, SoundfontBase, VoiceSpawner},
+    voice::{ReleaseType, Voice, VoiceControlData, VoiceGeneratorBase, VoiceSampleGenerator},
+    AudioStreamParams, ChannelCount,
+};
+
+/// A voice that plays a constant level until it is released or killed. It
+/// reports the ID of its soundfont as the velocity and the gain of its
+/// spawner as the amplitude.
+pub(crate) struct TestVoice {
+    id: u8,
+    gain: f32,
+    released: bool,
+}
+
+impl VoiceGeneratorBase for TestVoice {
+    fn ended(&self) -> bool {
+        self.released
+    }
+
+    fn signal_release(&mut self, _rel_type: ReleaseType) {
+        self.released = true;
+    }
+
+    fn process_controls(&mut self, _control: &VoiceControlData) {}
+}
+
+impl VoiceSampleGenerator for TestVoice {
+    fn render_to(&mut self, buffer: &mut [f32]) {
+        for sample in buffer.iter_mut() {
+            *sample += 0.01 * self.gain;
+        }
+    }
+}
+
+impl Voice for TestVoice {
+    fn is_releasing(&self) -> bool {
+        self.released
+    }
+
+    fn is_killed(&self) -> bool {
+        false
+    }
+
+    fn velocity(&self) -> u8 {
+        self.id
+    }
+
+    fn amplitude(&self) -> f32 {
+        self.gain
+    }
+}
+
+pub(crate) struct TestSpawner {
+    id: u8,
+    gain: f32,
+}
+
+impl TestSpawner {
+    pub fn new(id: u8) -> Self {
+        TestSpawner { id, gain: 1.0 }
+    }
+}
+
+impl VoiceSpawner for TestSpawner {
+    fn spawn_voice(&self, _control: &VoiceControlData) -> Box<dyn Voice> {
+        Box::new(TestVoice {
+            id: self.id,
+            gain: self.gain,
+            released: false,
+        })
+    }
+
+    fn apply_gain(&mut self, gain: f32) {
+        self.gain *= gain;
+    }
+}
+
+/// A soundfont that has a single region on every key and velocity of
+/// bank 0, preset 0, like a default-loaded SFZ, and a region triggered by
+/// moving the damper pedal down.
+#[derive(Debug)]
+pub(crate) struct TestSoundfont {
+    id: u8,
+    stream_params: AudioStreamParams,
+
+    /// The number of times the controller triggered spawners were
+    /// requested, which happens once per rebuild
+    pub cc_lookups: AtomicUsize,
+
+    /// Receives the thread the soundfont is dropped on
+    pub on_drop: Option<Sender<ThreadId>>,
+}
+
+impl Drop for TestSoundfont {
+    fn drop(&mut self) {
+        if let Some(on_drop) = &self.on_drop {
+            on_drop.send(thread::current().id()).ok();
+        }
+    }
+}
+
+impl TestSoundfont {
+    /// Creates a soundfont for 48 kHz stereo audio, whose voices report
+    /// the given ID as their velocity.
+    pub fn new(id: u8) -> Self {
+        TestSoundfont {
+            id,
+            stream_params: AudioStreamParams::new(48000, ChannelCount::Stereo),
+            cc_lookups: AtomicUsize::new(0),
+            on_drop: None,
+        }
+    }
+
+    pub fn new_arc(id: u8) -> Arc<dyn SoundfontBase> {
+        Arc::new(Self::new(id))
+    }
+}
+
+impl SoundfontBase for TestSoundfont {
+    fn stream_params(&self) -> &'_ AudioStreamParams {
+        &self.stream_params
+    }
+
+    fn get_attack_voice_spawners_at(
+        &self,
+        bank: u8,
+        preset: u8,
+        _key: u8,
+        _vel: u8,
+    ) -> Vec<Box<dyn VoiceSpawner>> {
+        if bank == 0 && preset == 0 {
+            vec![Box::new(TestSpawner::new(self.id))]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn get_release_voice_spawners_at(
+        &self,
+        _bank: u8,
+        _preset: u8,
+        _key: u8,
+        _vel: u8,
+    ) -> Vec<Box<dyn VoiceSpawner>> {
+        Vec::new()
+    }
+
+    fn get_cc_voice_spawners_at(&self, bank: u8, preset: u8) -> Vec<CcVoiceSpawner> {
+        self.cc_lookups.fetch_add(1, Ordering::Relaxed);
+        if bank == 0 && preset == 0 {
+            vec![CcVoiceSpawner {
+                controller: 64,
+                range: 64..=127,
+                spawner: Box::new(TestSpawner::new(self.id)),
+            }]
+        } else {
+            Vec::new()
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Helpers to render events through a channel and to analyze the rendered
+//! audio in tests, e.g. to check envelopes, panning, tuning and limiting.
+//!
+//! Only available with the `test-support` feature.
+
+use std::f32::consts::PI;
+
+use crate::{
+    channel::{ChannelEvent, VoiceChannel},
+    AudioPipe,
+};
+
+/// Renders the given number of frames of a channel, applying each event at
+/// its frame. The events should be sorted by frame, events at or after the
+/// end are applied after rendering.
+///
+/// Returns the interleaved samples of all audio channels.
+pub fn render_events(
+    channel: &mut VoiceChannel,
+    events: &[(usize, ChannelEvent)],
+    frames: usize,
+) -> Vec<f32> {
+    let channels = channel.stream_params().channels.count() as usize;
+    let mut out = vec![0.0; frames * channels];
+    channel.read_samples_timed(&mut out, events.iter().cloned());
+    out
+}
+
+/// Renders the given number of frames of an audio pipe, e.g. a channel
+/// group, and returns the interleaved samples.
+pub fn render_frames(pipe: &mut impl AudioPipe, frames: usize) -> Vec<f32> {
+    let channels = pipe.stream_params().channels.count() as usize;
+    let mut out = vec![0.0; frames * channels];
+    pipe.read_samples(&mut out);
+    out
+}
+
+/// Returns the samples of one audio channel of an interleaved buffer.
+pub fn channel_samples(samples: &[f32], channels: usize, channel: usize) -> Vec<f32> {
+    samples
+        .iter()
+        .skip(channel)
+        .step_by(channels)
+        .copied()
+        .collect()
+}
+
+/// Returns the root mean square of the samples, or 0 if there are none.
+pub fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let sum: f64 = samples.iter().map(|&s| s as f64 * s as f64).sum();
+    (sum / samples.len() as f64).sqrt() as f32
+}
+
+/// Returns the RMS of each consecutive window of `window` samples. The last
+/// window may be shorter.
+pub fn windowed_rms(samples: &[f32], window: usize) -> Vec<f32> {
+    samples.chunks(window.max(1)).map(rms).collect()
+}
+
+/// Returns the highest absolute sample value.
+pub fn sample_peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0, |peak, s| s.abs().max(peak))
+}
+
+/// The oversampling factor of `true_peak`.
+const TRUE_PEAK_OVERSAMPLING: usize = 4;
+
+/// The number of samples on each side of the windowed sinc used by
+/// `true_peak` to interpolate between samples.
+const TRUE_PEAK_TAPS: isize = 8;
+
+/// Returns the true peak of the samples: the highest absolute value of the
+/// signal they represent, including the peaks between samples, estimated by
+/// oversampling 4 times with a windowed sinc.
+pub fn true_peak(samples: &[f32]) -> f32 {
+    let mut peak = sample_peak(samples);
+    for i in 0..samples.len().saturating_sub(1) {
+        for step in 1..TRUE_PEAK_OVERSAMPLING {
+            let t = step as f32 / TRUE_PEAK_OVERSAMPLING as f32;
+            let mut value = 0.0;
+            for tap in -TRUE_PEAK_TAPS + 1..=TRUE_PEAK_TAPS {
+                let Some(&sample) = samples.get((i as isize + tap) as usize) else {
+                    continue;
+                };
+                let x = t - tap as f32;
+                let window = 0.5 + 0.5 * (PI * x / TRUE_PEAK_TAPS as f32).cos();
+                value += sample * sinc(x) * window;
+            }
+            peak = peak.max(value.abs());
+        }
+    }
+    peak
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Returns the amplitude of the given frequency in the samples, with a
+/// single DFT bin at exactly that frequency. A sine of amplitude `a` at the
+/// frequency gives about `a`, if the samples span many of its periods.
+pub fn frequency_magnitude(samples: &[f32], sample_rate: u32, frequency: f32) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+
+    let omega = 2.0 * std::f64::consts::PI * frequency as f64 / sample_rate as f64;
+    let (mut re, mut im) = (0.0f64, 0.0f64);
+    for (i, &s) in samples.iter().enumerate() {
+        let phase = omega * i as f64;
+        re += s as f64 * phase.cos();
+        im -= s as f64 * phase.sin();
+    }
+    (2.0 * (re * re + im * im).sqrt() / samples.len() as f64) as f32
+}
+
+/// Returns the first sample of each window in which the sound starts: its
+/// RMS reaches `threshold` after a window that was quieter than half of it.
+/// The samples should be of a single audio channel.
+pub fn detect_onsets(samples: &[f32], window: usize, threshold: f32) -> Vec<usize> {
+    let window = window.max(1);
+    let mut onsets = Vec::new();
+    let mut silent = true;
+    for (index, rms) in windowed_rms(samples, window).into_iter().enumerate() {
+        if silent && rms >= threshold {
+            onsets.push(index * window);
+            silent = false;
+        } else if rms < threshold / 2.0 {
+            silent = true;
+        }
+    }
+    onsets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, amplitude: f32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| (2.0 * PI * frequency * i as f32 / 48000.0).sin() * amplitude)
+            .collect()
+    }
+
+    #[test]
+    fn test_rms_and_peak() {
+        let samples = sine(1000.0, 0.5, 48000);
+        assert!((rms(&samples) - 0.5 / 2f32.sqrt()).abs() < 1e-3);
+        assert!((sample_peak(&samples) - 0.5).abs() < 1e-3);
+        assert_eq!(rms(&[]), 0.0);
+
+        let windows = windowed_rms(&[1.0, -1.0, 0.0, 0.0, 2.0], 2);
+        assert_eq!(windows, [1.0, 0.0, 2.0]);
+    }
+
+    #[test]
+    fn test_true_peak() {
+        // A sine at a quarter of the sample rate, sampled 45 degrees off its
+        // peaks, only reaches 0.707 at the samples
+        let samples: Vec<f32> = (0..64)
+            .map(|i| (PI / 2.0 * i as f32 + PI / 4.0).sin())
+            .collect();
+        assert!(sample_peak(&samples) < 0.71);
+        assert!(true_peak(&samples) > 0.95);
+    }
+
+    #[test]
+    fn test_frequency_magnitude() {
+        let mut samples = sine(440.0, 0.5, 48000);
+        for (s, other) in samples.iter_mut().zip(sine(1000.0, 0.25, 48000)) {
+            *s += other;
+        }
+
+        assert!((frequency_magnitude(&samples, 48000, 440.0) - 0.5).abs() < 0.01);
+        assert!((frequency_magnitude(&samples, 48000, 1000.0) - 0.25).abs() < 0.01);
+        assert!(frequency_magnitude(&samples, 48000, 700.0) < 0.01);
+    }
+
+    #[test]
+    fn test_onsets() {
+        let mut samples = vec![0.0; 4800];
+        samples.extend(sine(1000.0, 0.5, 4800));
+        samples.extend(vec![0.0; 4800]);
+        samples.extend(sine(1000.0, 0.5, 4800));
+
+        assert_eq!(detect_onsets(&samples, 480, 0.1), [4800, 14400]);
+        assert_eq!(channel_samples(&[1.0, 2.0, 3.0, 4.0], 2, 1), [2.0, 4.0]);
+    }
+}
@@ -0,0 +1,58 @@
+//! Instrumentation of the event routing and dispatch with `tracing`, only
+//! compiled with the `tracing` feature.
+//!
+//! Events are recorded at the trace level, with the channel, the event type,
+//! the name of the key and the value of the event. Events dispatched to a
+//! channel are recorded within a `channel` span that carries its index.
+
+use tracing::span::EnteredSpan;
+
+use crate::{channel::ChannelEvent, channel_group::SynthEvent, util::key_to_name};
+
+/// Records an event received by the router of a synthesizer, before it is
+/// sent to the channels.
+pub fn route(event: &SynthEvent) {
+    match event {
+        SynthEvent::Channel(channel, event) => record("route", Some(*channel), event),
+        SynthEvent::AllChannels(event) => record("route", None, event),
+        SynthEvent::SetMasterEffects(_) => {
+            tracing::trace!(event = "SetMasterEffects", "route");
+        }
+        SynthEvent::SetOutputPair(channel, pair) => {
+            tracing::trace!(channel, event = "SetOutputPair", value = pair, "route");
+        }
+        SynthEvent::SetTempo(bpm) => {
+            tracing::trace!(event = "SetTempo", value = bpm, "route");
+        }
+        // Traced as the channel events it's translated to
+        SynthEvent::Ump(_) => {}
+    }
+}
+
+/// Enters the span of a channel, for the events dispatched to it.
+pub fn enter_channel(channel: u32) -> EnteredSpan {
+    tracing::trace_span!("channel", channel).entered()
+}
+
+/// Records an event applied by a channel. See `VoiceChannel::process_event`.
+pub(crate) fn dispatch(event: &ChannelEvent) {
+    record("dispatch", None, event);
+}
+
+fn record(stage: &'static str, channel: Option<u32>, event: &ChannelEvent) {
+    match event {
+        ChannelEvent::Audio(audio) => {
+            let key = audio.key().map(key_to_name);
+            tracing::trace!(
+                channel,
+                event = audio.name(),
+                key = key.as_deref(),
+                value = audio.value(),
+                "{stage} {audio}"
+            );
+        }
+        ChannelEvent::Config(config) => {
+            tracing::trace!(channel, event = config.name(), "{stage} config");
+        }
+    }
+}
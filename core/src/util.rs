@@ -0,0 +1,57 @@
+mod denormals;
+pub use denormals::*;
+
+mod deferred_drop;
+pub(crate) use deferred_drop::{drop_later, init_deferred_drops, DeferredDrops};
+
+mod render_check;
+pub(crate) use render_check::RenderThreadCheck;
+
+mod frequencies;
+pub use frequencies::*;
+
+mod simd;
+pub use simd::*;
+
+mod simd_backend;
+pub(crate) use simd_backend::simd_dispatch;
+pub use simd_backend::{active_simd_backend, set_simd_backend, simd_backend, SimdBackend};
+
+mod timing;
+pub use timing::*;
+
+/// Take any f32 vec, set its length and fill it with the default value.
+#[inline(always)]
+pub fn prepare_cache_vec<T: Copy>(vec: &mut Vec<T>, len: usize, default: T) {
+    if vec.len() < len {
+        vec.reserve(len - vec.len());
+    }
+    unsafe {
+        vec.set_len(len);
+    }
+    vec.fill(default);
+}
+
+/// Fast zero-fill for f32 buffers using SIMD-like operations
+#[inline(always)]
+pub fn fast_zero_fill(vec: &mut Vec<f32>, len: usize) {
+    if vec.len() < len {
+        vec.reserve(len - vec.len());
+    }
+    unsafe {
+        vec.set_len(len);
+        // Use write_bytes for fast zeroing - this is optimized by the compiler
+        // to use SIMD instructions when available
+        std::ptr::write_bytes(vec.as_mut_ptr(), 0, len);
+    }
+}
+
+/// Converts a dB value to 0-1 amplitude.
+pub fn db_to_amp(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Converts a 0-1 amplitude to a dB value.
+pub fn amp_to_db(amp: f32) -> f32 {
+    20.0 * amp.log10()
+}
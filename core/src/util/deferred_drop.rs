@@ -0,0 +1,81 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use crossbeam_channel::{unbounded, Sender};
+use lazy_static::lazy_static;
+
+lazy_static! {
+    static ref GARBAGE: Sender<Box<dyn Send>> = {
+        let (sender, receiver) = unbounded::<Box<dyn Send>>();
+        thread::Builder::new()
+            .name("xsynth_dropper".to_string())
+            .spawn(move || receiver.iter().for_each(drop))
+            .unwrap();
+        sender
+    };
+}
+
+/// Starts the thread that drops deferred values, so it isn't spawned on a
+/// render thread by the first deferred drop.
+pub(crate) fn init_deferred_drops() {
+    lazy_static::initialize(&GARBAGE);
+}
+
+/// Drops a value on a background thread, so that freeing its memory doesn't
+/// stall rendering. The thread sleeps while there is nothing to drop.
+pub(crate) fn drop_later<T: Send + 'static>(value: T) {
+    GARBAGE.send(Box::new(value)).ok();
+}
+
+/// Drops values on the background thread like `drop_later`, and counts them
+/// for the statistics of a channel.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DeferredDrops {
+    count: Arc<AtomicU64>,
+}
+
+impl DeferredDrops {
+    pub fn new(count: Arc<AtomicU64>) -> Self {
+        Self { count }
+    }
+
+    pub fn drop_later<T: Send + 'static>(&self, value: T) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        drop_later(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread::ThreadId, time::Duration};
+
+    use super::*;
+
+    /// Reports the thread it is dropped on.
+    struct DropTracker(Sender<ThreadId>);
+
+    impl Drop for DropTracker {
+        fn drop(&mut self) {
+            self.0.send(thread::current().id()).ok();
+        }
+    }
+
+    #[test]
+    fn test_drop_later() {
+        let (sender, receiver) = unbounded();
+        let count = Arc::new(AtomicU64::new(0));
+        let drops = DeferredDrops::new(count.clone());
+
+        let large = (vec![0.0f32; 1 << 20], DropTracker(sender));
+        drops.drop_later(large);
+
+        let thread = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_ne!(thread, thread::current().id());
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+    }
+}
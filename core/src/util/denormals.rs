@@ -0,0 +1,122 @@
+/// A tiny offset kept in the state of feedback paths, such as the DC blocker
+/// and the limiter envelope, so decaying states settle on it instead of
+/// reaching denormal floats, which are very slow to compute on x86. It is
+/// far below the audible range.
+///
+/// This is the fallback for threads that don't flush denormals to zero,
+/// see `flush_denormals_on_current_thread`.
+pub const ANTI_DENORMAL: f32 = 1e-18;
+
+/// Makes the floating point unit of the current thread treat denormal floats
+/// as zero, by setting the flush-to-zero and denormals-are-zero bits of the
+/// MXCSR register on x86, or the flush-to-zero bit of the FPCR register on
+/// AArch64.
+///
+/// The setting only affects the current thread and lasts until the thread
+/// ends. Returns false if it isn't supported on this target.
+pub fn flush_denormals_on_current_thread() -> bool {
+    set_flush_to_zero()
+}
+
+#[cfg(any(
+    target_arch = "x86_64",
+    all(target_arch = "x86", target_feature = "sse")
+))]
+fn set_flush_to_zero() -> bool {
+    // The flush-to-zero (FTZ) and denormals-are-zero (DAZ) bits
+    const FTZ_DAZ: u32 = (1 << 15) | (1 << 6);
+
+    let mut csr = 0u32;
+    unsafe {
+        std::arch::asm!("stmxcsr [{}]", in(reg) &mut csr, options(nostack, preserves_flags));
+        csr |= FTZ_DAZ;
+        std::arch::asm!("ldmxcsr [{}]", in(reg) &csr, options(nostack, readonly, preserves_flags));
+    }
+    true
+}
+
+#[cfg(target_arch = "aarch64")]
+fn set_flush_to_zero() -> bool {
+    // The flush-to-zero (FZ) bit
+    const FZ: u64 = 1 << 24;
+
+    unsafe {
+        let fpcr: u64;
+        std::arch::asm!("mrs {}, fpcr", out(reg) fpcr, options(nomem, nostack, preserves_flags));
+        std::arch::asm!("msr fpcr, {}", in(reg) fpcr | FZ, options(nomem, nostack, preserves_flags));
+    }
+    true
+}
+
+#[cfg(not(any(
+    target_arch = "x86_64",
+    all(target_arch = "x86", target_feature = "sse"),
+    target_arch = "aarch64"
+)))]
+fn set_flush_to_zero() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        effects::{AudioInsert, DcBlocker, VolumeLimiter},
+        AudioStreamParams, ChannelCount,
+    };
+    use std::{hint::black_box, thread, time::Instant};
+
+    #[test]
+    fn test_flush_to_zero() {
+        let flushed = thread::spawn(|| {
+            if !flush_denormals_on_current_thread() {
+                return None;
+            }
+            Some(black_box(f32::MIN_POSITIVE) / black_box(4.0))
+        })
+        .join()
+        .unwrap();
+
+        if let Some(value) = flushed {
+            assert_eq!(value, 0.0);
+        }
+    }
+
+    /// Feeds an impulse through a DC blocker and a limiter, followed by a
+    /// long silence in which their states decay, without flushing denormals.
+    #[test]
+    fn test_decaying_feedback() {
+        let params = AudioStreamParams::new(48000, ChannelCount::Mono);
+        let mut dc_blocker = DcBlocker::default();
+        let mut limiter = VolumeLimiter::new(1);
+        let mut process = |buffer: &mut [f32]| {
+            dc_blocker.process(buffer, &params);
+            limiter.process(buffer, &params);
+        };
+
+        let mut loud = vec![0.5; 48000];
+        let start = Instant::now();
+        process(&mut loud);
+        let loud_time = start.elapsed();
+
+        let mut silence = vec![0.0; 48000];
+        for _ in 0..10 {
+            silence.fill(0.0);
+            process(&mut silence);
+        }
+        let start = Instant::now();
+        process(&mut silence);
+        let tail_time = start.elapsed();
+
+        assert!(silence.iter().all(|s| !s.is_subnormal()));
+
+        // Timing is unreliable on shared machines, so it's only checked
+        // when asked for
+        if std::env::var_os("XSYNTH_TIMING_TESTS").is_some() {
+            assert!(
+                tail_time < loud_time * 4,
+                "the decaying tail took {tail_time:?}, the loud signal {loud_time:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use lazy_static::lazy_static;
+
+/// The standard reference pitch of A4 (key 69), in Hz.
+pub const DEFAULT_A4_FREQUENCY: f32 = 440.0;
+
+/// Create an array of key frequencies for keys 0-127
+fn build_frequencies(a4: f32) -> [f32; 128] {
+    let mut freqs = [0.0f32; 128];
+    for (key, freq) in freqs.iter_mut().enumerate() {
+        *freq = 2.0f32.powf((key as f32 - 69.0) / 12.0) * a4;
+    }
+    freqs
+}
+
+lazy_static! {
+    /// Static array of all frequencies for keys 0-127, with A4 at 440Hz.
+    pub static ref FREQS: [f32; 128] = build_frequencies(DEFAULT_A4_FREQUENCY);
+}
+
+/// A table of the equal temperament frequencies of keys 0-127, tuned to a
+/// reference pitch for A4.
+///
+/// Common references are 440Hz (the default), 415Hz for baroque pitch,
+/// 432Hz and 442Hz/443Hz used by many orchestras.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyFrequencies {
+    a4: f32,
+    freqs: [f32; 128],
+}
+
+impl KeyFrequencies {
+    /// Creates a new table with the given A4 reference pitch, in Hz.
+    pub fn new(a4: f32) -> Self {
+        Self {
+            a4,
+            freqs: build_frequencies(a4),
+        }
+    }
+
+    /// Returns the A4 reference pitch of the table, in Hz.
+    pub fn a4(&self) -> f32 {
+        self.a4
+    }
+
+    /// Changes the A4 reference pitch and rebuilds the table.
+    pub fn set_a4(&mut self, a4: f32) {
+        if self.a4 != a4 {
+            *self = Self::new(a4);
+        }
+    }
+
+    /// Returns the frequency of the given key, in Hz.
+    pub fn frequency(&self, key: u8) -> f32 {
+        self.freqs[key as usize & 0x7F]
+    }
+
+    /// Returns the frequencies of all keys, in Hz.
+    pub fn frequencies(&self) -> &[f32; 128] {
+        &self.freqs
+    }
+
+    /// Returns the pitch multiplier of this tuning relative to the standard
+    /// 440Hz tuning the soundfonts are played back at.
+    pub fn pitch_multiplier(&self) -> f32 {
+        self.a4 / DEFAULT_A4_FREQUENCY
+    }
+}
+
+impl Default for KeyFrequencies {
+    fn default() -> Self {
+        Self::new(DEFAULT_A4_FREQUENCY)
+    }
+}
+
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Returns the name of a MIDI key, e.g. "C4" for middle C (key 60) and
+/// "A#-1" for key 10. Keys above 127 are named as if the scale continued.
+pub fn key_to_name(key: u8) -> String {
+    let octave = key as i32 / 12 - 1;
+    format!("{}{octave}", NOTE_NAMES[key as usize % 12])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reference_pitch() {
+        let mut freqs = KeyFrequencies::default();
+        assert_eq!(freqs.frequency(69), 440.0);
+        assert!((freqs.frequency(81) - 880.0).abs() < 0.01);
+
+        freqs.set_a4(415.0);
+        assert_eq!(freqs.frequency(69), 415.0);
+        assert!((freqs.frequency(57) - 207.5).abs() < 0.01);
+        assert!((freqs.pitch_multiplier() - 415.0 / 440.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_key_names() {
+        assert_eq!(key_to_name(60), "C4");
+        assert_eq!(key_to_name(61), "C#4");
+        assert_eq!(key_to_name(69), "A4");
+        assert_eq!(key_to_name(0), "C-1");
+        assert_eq!(key_to_name(127), "G9");
+    }
+}
@@ -0,0 +1,96 @@
+#[cfg(debug_assertions)]
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+#[cfg(debug_assertions)]
+fn current_thread_token() -> u64 {
+    static NEXT_TOKEN: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static TOKEN: u64 = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+    }
+    TOKEN.with(|token| *token)
+}
+
+/// Records the thread rendering a channel in debug builds, and panics if
+/// another thread starts rendering it at the same time. Rendering takes the
+/// channel mutably, so this only happens if it is shared unsoundly, e.g. by a
+/// host calling the C API from several threads with the same handle.
+///
+/// Channels may be rendered by a different thread on each buffer, only
+/// overlapping renders are reported. In release builds this does nothing.
+#[derive(Debug, Default)]
+pub(crate) struct RenderThreadCheck {
+    /// The token of the thread currently rendering, 0 if none is. Shared
+    /// with the guard, so the channel can be borrowed while rendering.
+    #[cfg(debug_assertions)]
+    rendering: Arc<AtomicU64>,
+}
+
+impl RenderThreadCheck {
+    /// Marks the channel as rendered by the current thread until the
+    /// returned guard is dropped.
+    #[inline(always)]
+    pub fn enter(&self, name: &'static str) -> RenderThreadGuard {
+        #[cfg(debug_assertions)]
+        {
+            let token = current_thread_token();
+            if let Err(other) =
+                self.rendering
+                    .compare_exchange(0, token, Ordering::Acquire, Ordering::Relaxed)
+            {
+                panic!("{name} rendered by two threads at once (threads {other} and {token})");
+            }
+        }
+        #[cfg(not(debug_assertions))]
+        let _ = name;
+
+        RenderThreadGuard {
+            #[cfg(debug_assertions)]
+            rendering: self.rendering.clone(),
+        }
+    }
+}
+
+pub(crate) struct RenderThreadGuard {
+    #[cfg(debug_assertions)]
+    rendering: Arc<AtomicU64>,
+}
+
+impl Drop for RenderThreadGuard {
+    #[inline(always)]
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        self.rendering.store(0, Ordering::Release);
+    }
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_sequential_renders_on_other_threads() {
+        let check = Arc::new(RenderThreadCheck::default());
+        drop(check.enter("channel"));
+
+        let other = check.clone();
+        thread::spawn(move || drop(other.enter("channel")))
+            .join()
+            .unwrap();
+        drop(check.enter("channel"));
+    }
+
+    #[test]
+    fn test_overlapping_renders_panic() {
+        let check = Arc::new(RenderThreadCheck::default());
+        let _guard = check.enter("channel");
+
+        let other = check.clone();
+        let result = thread::spawn(move || drop(other.enter("channel"))).join();
+        assert!(result.is_err());
+    }
+}
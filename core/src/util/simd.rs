@@ -2,6 +2,8 @@ use simdeez::*;
 
 use simdeez::prelude::*;
 
+use super::simd_dispatch;
+
 /// Sum the values of `source` to the values of `target`, writing to `target`.
 ///
 /// Uses runtime selected SIMD operations with aggressive optimization.
@@ -23,7 +25,7 @@ pub fn sum_simd(source: &[f32], target: &mut [f32]) {
         target.len()
     );
 
-    simd_runtime_generate!(
+    simd_dispatch!(
         // Highly optimized SIMD sum with loop unrolling
         fn sum(source: &[f32], target: &mut [f32]) {
             let len = source.len();
@@ -0,0 +1,167 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// The SIMD instruction set used for rendering the voices and for the
+/// SIMD helpers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum SimdBackend {
+    /// The widest instruction set supported by the CPU. On targets other
+    /// than x86, this is the native instruction set of the target, e.g. NEON.
+    #[default]
+    Auto,
+
+    /// No SIMD instructions. Renders the same results on all machines, which
+    /// is useful for tests.
+    Scalar,
+
+    /// SSE4.1 (x86 only)
+    Sse41,
+
+    /// AVX2 (x86 only)
+    Avx2,
+}
+
+impl SimdBackend {
+    /// Returns true if the backend can be used on this CPU. `Auto` and
+    /// `Scalar` are always supported.
+    pub fn is_supported(self) -> bool {
+        match self {
+            SimdBackend::Auto | SimdBackend::Scalar => true,
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            SimdBackend::Sse41 => is_x86_feature_detected!("sse4.1"),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            SimdBackend::Avx2 => is_x86_feature_detected!("avx2"),
+            #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+            SimdBackend::Sse41 | SimdBackend::Avx2 => false,
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => SimdBackend::Scalar,
+            2 => SimdBackend::Sse41,
+            3 => SimdBackend::Avx2,
+            _ => SimdBackend::Auto,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            SimdBackend::Auto => 0,
+            SimdBackend::Scalar => 1,
+            SimdBackend::Sse41 => 2,
+            SimdBackend::Avx2 => 3,
+        }
+    }
+}
+
+static SIMD_BACKEND: AtomicU8 = AtomicU8::new(0);
+
+/// Sets the SIMD backend used by all synthesizers of the process.
+///
+/// Voices keep the backend of the soundfont regions they were spawned from,
+/// which is selected when the soundfonts or the program of a channel change.
+/// If the CPU doesn't support the backend, the next narrower one is used.
+pub fn set_simd_backend(backend: SimdBackend) {
+    SIMD_BACKEND.store(backend.to_u8(), Ordering::Relaxed);
+}
+
+/// Returns the SIMD backend that was set with `set_simd_backend`.
+pub fn simd_backend() -> SimdBackend {
+    SimdBackend::from_u8(SIMD_BACKEND.load(Ordering::Relaxed))
+}
+
+/// Returns the SIMD backend that is actually used, after resolving `Auto`
+/// and falling back from backends that the CPU doesn't support.
+///
+/// Only returns `Auto` on targets other than x86, where the native
+/// instruction set of the target is used.
+pub fn active_simd_backend() -> SimdBackend {
+    resolve_backend(simd_backend())
+}
+
+/// Returns the backend that is used for the requested one on this CPU.
+fn resolve_backend(requested: SimdBackend) -> SimdBackend {
+    let candidates: &[SimdBackend] = match requested {
+        SimdBackend::Scalar => &[],
+        SimdBackend::Sse41 => &[SimdBackend::Sse41],
+        SimdBackend::Auto | SimdBackend::Avx2 => &[SimdBackend::Avx2, SimdBackend::Sse41],
+    };
+
+    if let Some(backend) = candidates.iter().copied().find(|b| b.is_supported()) {
+        return backend;
+    }
+
+    if requested == SimdBackend::Auto && !cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
+        SimdBackend::Auto
+    } else {
+        SimdBackend::Scalar
+    }
+}
+
+/// Generates a function that is generic over `S: Simd`, like
+/// `simdeez::simd_runtime_generate`, but selects the instruction set with
+/// `active_simd_backend` so it follows the configured backend.
+///
+/// The instruction sets of simdeez can't be named outside of it, so the
+/// forced backends go through the functions of `simd_unsafe_generate_all`,
+/// which are only called once `active_simd_backend` checked that the CPU
+/// supports them.
+macro_rules! simd_dispatch {
+    ($vis:vis fn $fn_name:ident ($($arg:ident: $typ:ty),* $(,)?) $body:block) => {
+        simd_dispatch!($vis fn $fn_name($($arg: $typ),*) -> () $body);
+    };
+    ($vis:vis fn $fn_name:ident ($($arg:ident: $typ:ty),* $(,)?) -> $rt:ty $body:block) => {
+        $vis fn $fn_name($($arg: $typ),*) -> $rt {
+            #[inline(always)]
+            fn generic<S: 'static + simdeez::Simd>($($arg: $typ),*) -> $rt {
+                simdeez::simd_invoke!(S, $body)
+            }
+
+            simdeez::simd_runtime_generate!(
+                fn native($($arg: $typ),*) -> $rt $body
+            );
+
+            simdeez::simd_unsafe_generate_all!(
+                #[allow(dead_code)]
+                fn forced($($arg: $typ),*) -> $rt $body
+            );
+
+            match $crate::util::active_simd_backend() {
+                $crate::util::SimdBackend::Scalar => {
+                    generic::<simdeez::scalar::Scalar>($($arg),*)
+                }
+                // Safety: `active_simd_backend` only returns the backends
+                // that the CPU supports
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                $crate::util::SimdBackend::Sse41 => unsafe { forced_sse41($($arg),*) },
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                $crate::util::SimdBackend::Avx2 => unsafe { forced_avx2($($arg),*) },
+                _ => native($($arg),*),
+            }
+        }
+    };
+}
+pub(crate) use simd_dispatch;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The backend is global to the process, so it is only resolved here
+    // instead of being set under the other tests
+    #[test]
+    fn test_resolve_backend() {
+        assert_eq!(resolve_backend(SimdBackend::Scalar), SimdBackend::Scalar);
+        assert!(matches!(
+            resolve_backend(SimdBackend::Sse41),
+            SimdBackend::Sse41 | SimdBackend::Scalar
+        ));
+        assert_ne!(resolve_backend(SimdBackend::Avx2), SimdBackend::Auto);
+
+        for backend in [SimdBackend::Auto, SimdBackend::Sse41, SimdBackend::Avx2] {
+            assert!(resolve_backend(backend).is_supported());
+            assert_eq!(SimdBackend::from_u8(backend.to_u8()), backend);
+        }
+    }
+}
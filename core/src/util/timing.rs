@@ -0,0 +1,77 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+/// The number of measurements kept by a `SectionTimer`.
+const TIMING_WINDOW: usize = 64;
+
+/// The rolling average and maximum time spent in a section of the render path.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SectionTiming {
+    /// The average time spent in the section per buffer, in seconds.
+    pub average: f64,
+
+    /// The maximum time spent in the section per buffer, in seconds.
+    pub max: f64,
+}
+
+/// Lock-free timer for a section of the render path, keeping the last few
+/// measurements to calculate rolling averages and maxima.
+///
+/// Recording doesn't allocate. It is meant to be written from a single
+/// thread, while it can be read from any thread.
+#[derive(Debug)]
+pub struct SectionTimer {
+    samples: [AtomicU64; TIMING_WINDOW],
+    count: AtomicU64,
+}
+
+impl SectionTimer {
+    pub fn new() -> Self {
+        Self {
+            samples: std::array::from_fn(|_| AtomicU64::new(0)),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    /// Records the time elapsed since `start`.
+    #[inline(always)]
+    pub fn record_since(&self, start: Instant) {
+        self.record(start.elapsed().as_secs_f64());
+    }
+
+    /// Records a measurement, in seconds.
+    pub fn record(&self, seconds: f64) {
+        let count = self.count.load(Ordering::Relaxed);
+        self.samples[count as usize % TIMING_WINDOW].store(seconds.to_bits(), Ordering::Relaxed);
+        self.count.store(count + 1, Ordering::Relaxed);
+    }
+
+    /// Returns the rolling average and maximum of the recorded measurements.
+    pub fn timing(&self) -> SectionTiming {
+        let count = (self.count.load(Ordering::Relaxed) as usize).min(TIMING_WINDOW);
+        if count == 0 {
+            return Default::default();
+        }
+
+        let mut sum = 0.0;
+        let mut max = 0.0f64;
+        for sample in self.samples[..count].iter() {
+            let seconds = f64::from_bits(sample.load(Ordering::Relaxed));
+            sum += seconds;
+            max = max.max(seconds);
+        }
+
+        SectionTiming {
+            average: sum / count as f64,
+            max,
+        }
+    }
+}
+
+impl Default for SectionTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
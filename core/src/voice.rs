@@ -2,6 +2,7 @@
 #![allow(non_camel_case_types)] // For the SIMD library
 
 mod envelopes;
+pub use envelopes::EnvelopeStage;
 pub(crate) use envelopes::*;
 
 mod simd;
@@ -24,6 +25,9 @@ pub(crate) use channels::*;
 mod constant;
 pub(crate) use constant::*;
 
+mod ramp;
+pub(crate) use ramp::*;
+
 mod sampler;
 pub(crate) use sampler::*;
 
@@ -33,6 +37,13 @@ pub(crate) use control::*;
 mod cutoff;
 pub(crate) use cutoff::*;
 
+mod fade_in;
+pub(crate) use fade_in::FadeInVoice;
+
+mod polyphony;
+pub(crate) use polyphony::PolyphonyVoice;
+pub use polyphony::VoicePolyphony;
+
 /// Options to modify the envelope of a voice.
 #[derive(Copy, Clone)]
 pub struct EnvelopeControlData {
@@ -43,18 +54,79 @@ pub struct EnvelopeControlData {
     /// Controls the release. Can take values from 0 to 128
     /// according to the MIDI CC spec.
     pub release: Option<u8>,
+
+    /// Time scales of the release, depending on how the voice is released.
+    pub release_scales: ReleaseTimeScales,
 }
 
 /// How a voice should be released.
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum ReleaseType {
-    /// Standard release. Uses the voice's envelope.
+    /// Standard release when the key is released. Uses the voice's envelope.
     Standard,
 
+    /// Release of a note held by the damper pedal when the pedal is lifted.
+    /// Uses the voice's envelope.
+    Damper,
+
     /// Kills the voice with a fadeout of 1ms.
     Kill,
 }
 
+/// Multipliers for the release time of the volume envelope, for each release
+/// type that uses the envelope. For example some pianos sound more realistic
+/// with a slightly longer release when the damper pedal is lifted.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(default)
+)]
+pub struct ReleaseTimeScales {
+    /// The release time multiplier when a key is released.
+    ///
+    /// Default: `1.0`
+    pub standard: f32,
+
+    /// The release time multiplier when the damper pedal is lifted.
+    ///
+    /// Default: `1.0`
+    pub damper: f32,
+}
+
+impl ReleaseTimeScales {
+    /// Returns the release time multiplier for the given release type.
+    /// Kills always use their own fixed fadeout.
+    pub fn scale(&self, rel_type: ReleaseType) -> f32 {
+        match rel_type {
+            ReleaseType::Standard => self.standard,
+            ReleaseType::Damper => self.damper,
+            ReleaseType::Kill => 1.0,
+        }
+    }
+}
+
+impl Default for ReleaseTimeScales {
+    fn default() -> Self {
+        Self {
+            standard: 1.0,
+            damper: 1.0,
+        }
+    }
+}
+
+/// Options to control the tuning of a voice.
+#[derive(Copy, Clone)]
+pub struct TuningControlData {
+    /// Pitch multiplier of the A4 reference pitch, relative to 440Hz.
+    /// Voices keep the multiplier they were started with.
+    pub multiplier: f32,
+
+    /// Whether voices that are already sounding should follow changes
+    /// of the multiplier.
+    pub retune_sounding: bool,
+}
+
 /// Options to control the parameters of a voice.
 #[derive(Copy, Clone)]
 pub struct VoiceControlData {
@@ -63,6 +135,38 @@ pub struct VoiceControlData {
 
     /// Envelope control
     pub envelope: EnvelopeControlData,
+
+    /// Tuning control
+    pub tuning: TuningControlData,
+
+    /// The current values of the channel's MIDI controllers, used to select
+    /// soundfont regions that depend on them.
+    pub controllers: [u8; 128],
+
+    /// Whether the voice is spawned while other notes are held on the
+    /// channel, used to select `first` and `legato` soundfont regions.
+    pub legato: bool,
+
+    /// The pitch offset of the note in cents, set by
+    /// `ChannelAudioEvent::NoteOnDetuned`. Only read when the voice is
+    /// spawned.
+    pub cents_offset: f32,
+
+    /// The position in the sample the voice starts playing at, in frames
+    /// after the offset of its region. Set to continue the position of a
+    /// retriggered key, see `ChannelInitOptions::retrigger_offset`. Only
+    /// read when the voice is spawned.
+    pub start_position: f64,
+}
+
+/// The values of the MIDI controllers after a reset. Volume, pan and
+/// expression start at their usual defaults, all others at 0.
+fn default_controller_values() -> [u8; 128] {
+    let mut controllers = [0; 128];
+    controllers[0x07] = 100;
+    controllers[0x0A] = 64;
+    controllers[0x0B] = 127;
+    controllers
 }
 
 impl VoiceControlData {
@@ -72,21 +176,129 @@ impl VoiceControlData {
             envelope: EnvelopeControlData {
                 attack: None,
                 release: None,
+                release_scales: Default::default(),
             },
+            tuning: TuningControlData {
+                multiplier: 1.0,
+                retune_sounding: false,
+            },
+            controllers: default_controller_values(),
+            legato: false,
+            cents_offset: 0.0,
+            start_position: 0.0,
         }
     }
 }
 
+/// The state of a voice's generators, gathered for debugging and
+/// visualization. See `VoiceGeneratorBase::inspect`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct VoiceGeneratorState {
+    /// The current stage of the volume envelope
+    pub envelope_stage: Option<EnvelopeStage>,
+
+    /// The current value of the volume envelope
+    pub envelope_amplitude: Option<f32>,
+
+    /// The current playback position in the sample, in sample frames
+    pub sample_position: Option<f64>,
+}
+
 pub trait VoiceGeneratorBase: Sync + Send {
     fn ended(&self) -> bool;
     fn signal_release(&mut self, rel_type: ReleaseType);
     fn process_controls(&mut self, control: &VoiceControlData);
+
+    /// Writes the state of the generator into `state`. Generators that wrap
+    /// other generators pass the call on to them.
+    fn inspect(&self, _state: &mut VoiceGeneratorState) {}
 }
 
 pub trait VoiceSampleGenerator: VoiceGeneratorBase {
     fn render_to(&mut self, buffer: &mut [f32]);
 }
 
+/// A playing voice, spawned by a `VoiceSpawner` of a soundfont.
+///
+/// # Threading
+///
+/// Voices are moved between threads: a channel may be rendered by a
+/// different channel thread on each buffer, and with key parallelism the
+/// voices of each key are rendered on a worker thread. The soundfonts and
+/// their spawners are shared by all channels. Voices therefore have to be
+/// `Send` and `Sync`, like all `VoiceGeneratorBase` types, and soundfonts
+/// `Send` and `Sync` (see `SoundfontBase`).
+///
+/// A voice holding shared state has to use thread safe types such as `Arc`:
+///
+/// ```
+/// use std::sync::Arc;
+/// use xsynth_core::voice::{
+///     ReleaseType, Voice, VoiceControlData, VoiceGeneratorBase, VoiceSampleGenerator,
+/// };
+///
+/// struct SharedVoice(Arc<Vec<f32>>);
+///
+/// impl VoiceGeneratorBase for SharedVoice {
+///     fn ended(&self) -> bool {
+///         false
+///     }
+///     fn signal_release(&mut self, _rel_type: ReleaseType) {}
+///     fn process_controls(&mut self, _control: &VoiceControlData) {}
+/// }
+///
+/// impl VoiceSampleGenerator for SharedVoice {
+///     fn render_to(&mut self, _buffer: &mut [f32]) {}
+/// }
+///
+/// impl Voice for SharedVoice {
+///     fn is_releasing(&self) -> bool {
+///         false
+///     }
+///     fn is_killed(&self) -> bool {
+///         false
+///     }
+///     fn velocity(&self) -> u8 {
+///         127
+///     }
+/// }
+/// ```
+///
+/// The same voice using `Rc` is rejected, as it can't be sent to another
+/// thread:
+///
+/// ```compile_fail
+/// use std::rc::Rc;
+/// use xsynth_core::voice::{ReleaseType, VoiceControlData, VoiceGeneratorBase};
+///
+/// struct SharedVoice(Rc<Vec<f32>>);
+///
+/// impl VoiceGeneratorBase for SharedVoice {
+///     fn ended(&self) -> bool {
+///         false
+///     }
+///     fn signal_release(&mut self, _rel_type: ReleaseType) {}
+///     fn process_controls(&mut self, _control: &VoiceControlData) {}
+/// }
+/// ```
+///
+/// So is a voice with interior mutability that isn't thread safe, as voices
+/// have to be `Sync`:
+///
+/// ```compile_fail
+/// use std::cell::Cell;
+/// use xsynth_core::voice::{ReleaseType, VoiceControlData, VoiceGeneratorBase};
+///
+/// struct CountingVoice(Cell<u64>);
+///
+/// impl VoiceGeneratorBase for CountingVoice {
+///     fn ended(&self) -> bool {
+///         false
+///     }
+///     fn signal_release(&mut self, _rel_type: ReleaseType) {}
+///     fn process_controls(&mut self, _control: &VoiceControlData) {}
+/// }
+/// ```
 pub trait Voice: VoiceSampleGenerator + Send + Sync {
     fn is_releasing(&self) -> bool;
     fn is_killed(&self) -> bool;
@@ -99,4 +311,11 @@ pub trait Voice: VoiceSampleGenerator + Send + Sync {
         // Default: estimate from velocity
         self.velocity() as f32 / 127.0
     }
+
+    /// Returns the polyphony limits of the voice and the order it was
+    /// spawned in, if the region it was spawned from has any. Voices with
+    /// a lower order were spawned earlier.
+    fn polyphony(&self) -> Option<(VoicePolyphony, u64)> {
+        None
+    }
 }
@@ -1,6 +1,6 @@
 use crate::voice::{ReleaseType, VoiceControlData};
 
-use super::{Voice, VoiceGeneratorBase, VoiceSampleGenerator};
+use super::{Voice, VoiceGeneratorBase, VoiceGeneratorState, VoiceSampleGenerator};
 
 /// A struct that tracks the highest level voice functionality.
 pub struct VoiceBase<T: Send + Sync + VoiceSampleGenerator> {
@@ -33,7 +33,7 @@ where
     #[inline(always)]
     fn signal_release(&mut self, rel_type: ReleaseType) {
         match rel_type {
-            ReleaseType::Standard => self.releasing = true,
+            ReleaseType::Standard | ReleaseType::Damper => self.releasing = true,
             ReleaseType::Kill => self.killed = true,
         }
         self.sample_generator.signal_release(rel_type)
@@ -43,6 +43,11 @@ where
     fn process_controls(&mut self, control: &VoiceControlData) {
         self.sample_generator.process_controls(control)
     }
+
+    #[inline(always)]
+    fn inspect(&self, state: &mut VoiceGeneratorState) {
+        self.sample_generator.inspect(state)
+    }
 }
 
 impl<T> VoiceSampleGenerator for VoiceBase<T>
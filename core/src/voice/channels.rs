@@ -6,6 +6,7 @@ use crate::voice::VoiceControlData;
 
 use super::{
     ReleaseType, SIMDSampleMono, SIMDSampleStereo, SIMDVoiceGenerator, VoiceGeneratorBase,
+    VoiceGeneratorState,
 };
 
 pub struct SIMDVoiceMonoToStereo<S, G>
@@ -46,6 +47,11 @@ where
     fn process_controls(&mut self, control: &VoiceControlData) {
         self.generator.process_controls(control)
     }
+
+    #[inline(always)]
+    fn inspect(&self, state: &mut VoiceGeneratorState) {
+        self.generator.inspect(state)
+    }
 }
 
 impl<S, G> SIMDVoiceGenerator<S, SIMDSampleStereo<S>> for SIMDVoiceMonoToStereo<S, G>
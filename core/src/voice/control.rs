@@ -46,3 +46,45 @@ impl<S: Simd> SIMDVoiceGenerator<S, SIMDSampleMono<S>> for SIMDVoiceControl<S> {
         SIMDSampleMono(self.values)
     }
 }
+
+/// The tuning multiplier of a voice. Unlike `SIMDVoiceControl`, it keeps the
+/// tuning the voice was started with, unless sounding voices are retuned.
+pub struct SIMDTuningControl<S: Simd> {
+    values: S::Vf32,
+}
+
+impl<S: Simd> SIMDTuningControl<S> {
+    pub fn new(control: &VoiceControlData) -> SIMDTuningControl<S> {
+        simd_invoke!(S, {
+            SIMDTuningControl {
+                values: S::Vf32::set1(control.tuning.multiplier),
+            }
+        })
+    }
+}
+
+impl<S: Simd> VoiceGeneratorBase for SIMDTuningControl<S> {
+    #[inline(always)]
+    fn ended(&self) -> bool {
+        false
+    }
+
+    #[inline(always)]
+    fn signal_release(&mut self, _rel_type: ReleaseType) {}
+
+    #[inline(always)]
+    fn process_controls(&mut self, control: &VoiceControlData) {
+        if control.tuning.retune_sounding {
+            simd_invoke!(S, {
+                self.values = S::Vf32::set1(control.tuning.multiplier);
+            })
+        }
+    }
+}
+
+impl<S: Simd> SIMDVoiceGenerator<S, SIMDSampleMono<S>> for SIMDTuningControl<S> {
+    #[inline(always)]
+    fn next_sample(&mut self) -> SIMDSampleMono<S> {
+        SIMDSampleMono(self.values)
+    }
+}
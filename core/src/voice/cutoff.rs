@@ -7,7 +7,7 @@ use crate::{
     voice::{ReleaseType, SIMDVoiceGenerator, VoiceControlData},
 };
 
-use super::{SIMDSampleMono, SIMDSampleStereo, VoiceGeneratorBase};
+use super::{SIMDSampleMono, SIMDSampleStereo, VoiceGeneratorBase, VoiceGeneratorState};
 
 pub struct SIMDMonoVoiceCutoff<S, V>
 where
@@ -52,6 +52,11 @@ where
     fn process_controls(&mut self, control: &VoiceControlData) {
         self.v.process_controls(control);
     }
+
+    #[inline(always)]
+    fn inspect(&self, state: &mut VoiceGeneratorState) {
+        self.v.inspect(state);
+    }
 }
 
 impl<S, V> SIMDVoiceGenerator<S, SIMDSampleMono<S>> for SIMDMonoVoiceCutoff<S, V>
@@ -114,6 +119,11 @@ where
     fn process_controls(&mut self, control: &VoiceControlData) {
         self.v.process_controls(control);
     }
+
+    #[inline(always)]
+    fn inspect(&self, state: &mut VoiceGeneratorState) {
+        self.v.inspect(state);
+    }
 }
 
 impl<S, V> SIMDVoiceGenerator<S, SIMDSampleStereo<S>> for SIMDStereoVoiceCutoff<S, V>
@@ -1,9 +1,10 @@
 use simdeez::prelude::*;
 
 use crate::soundfont::{EnvelopeCurveType, EnvelopeOptions};
-use crate::voice::{EnvelopeControlData, ReleaseType, VoiceControlData};
+use crate::util::db_to_amp;
+use crate::voice::{EnvelopeControlData, ReleaseTimeScales, ReleaseType, VoiceControlData};
 
-use super::{SIMDSampleMono, SIMDVoiceGenerator, VoiceGeneratorBase};
+use super::{SIMDSampleMono, SIMDVoiceGenerator, VoiceGeneratorBase, VoiceGeneratorState};
 
 /// The stages in envelopes as a numbered enum
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -303,6 +304,7 @@ impl EnvelopeDescriptor {
 
         EnvelopeParameters {
             start: self.start_percent,
+            release_floor: options.release_floor_db.map_or(0.0, db_to_amp),
             parts: [
                 // Delay
                 EnvelopePart::lerp(self.start_percent, (self.delay * samplerate) as u32),
@@ -329,10 +331,27 @@ impl EnvelopeDescriptor {
 #[derive(Debug, Clone, Copy)]
 pub struct EnvelopeParameters {
     start: f32,
+
+    /// The amplitude below which the release stage ends, see
+    /// `EnvelopeOptions::release_floor_db`
+    release_floor: f32,
+
     pub parts: [EnvelopePart; 7],
 }
 
 impl EnvelopeParameters {
+    /// Returns true if the envelope starts from silence and takes at least
+    /// the given number of samples to reach its peak.
+    pub(crate) fn has_attack_of(&self, samples: u32) -> bool {
+        let attack = match self.parts[EnvelopeStage::Attack.as_usize()] {
+            EnvelopePart::Lerp { duration, .. }
+            | EnvelopePart::LerpConcave { duration, .. }
+            | EnvelopePart::LerpConvex { duration, .. } => duration,
+            EnvelopePart::Hold(_) => 0,
+        };
+        self.start == 0.0 && attack >= samples
+    }
+
     fn get_stage_data<T: Simd>(
         &self,
         stage: EnvelopeStage,
@@ -443,6 +462,9 @@ pub struct SIMDVoiceEnvelope<T: Simd> {
     state: VoiceEnvelopeState<T>,
     sample_rate: f32,
     killed: bool,
+    release_scales: ReleaseTimeScales,
+    /// The release time multiplier of the release in progress, if any
+    release_scale: Option<f32>,
 }
 
 impl<T: Simd> SIMDVoiceEnvelope<T> {
@@ -461,6 +483,31 @@ impl<T: Simd> SIMDVoiceEnvelope<T> {
             state,
             sample_rate,
             killed: false,
+            release_scales: Default::default(),
+            release_scale: None,
+        }
+    }
+
+    /// Sets the release time multipliers used when the voice gets released.
+    pub fn set_release_scales(&mut self, release_scales: ReleaseTimeScales) {
+        self.release_scales = release_scales;
+    }
+
+    fn scale_release(params: &mut EnvelopeParameters, scale: f32) {
+        if scale == 1.0 {
+            return;
+        }
+
+        let part = EnvelopeStage::Release.as_usize();
+        let scaled = |duration: u32| (duration as f32 * scale.max(0.0)) as u32;
+        match params.parts[part] {
+            EnvelopePart::Lerp { target, duration } => {
+                params.modify_stage_data(part, EnvelopePart::lerp(target, scaled(duration)))
+            }
+            EnvelopePart::LerpConcave { target, duration } => {
+                params.modify_stage_data(part, EnvelopePart::lerp_concave(target, scaled(duration)))
+            }
+            _ => {}
         }
     }
 
@@ -588,8 +635,10 @@ impl<T: Simd> SIMDVoiceEnvelope<T> {
 
     pub fn modify_envelope(&mut self, envelope: EnvelopeControlData) {
         if !self.killed {
+            self.release_scales = envelope.release_scales;
             self.params =
                 Self::get_modified_envelope(self.original_params, envelope, self.sample_rate);
+            Self::scale_release(&mut self.params, self.release_scale.unwrap_or(1.0));
             self.update_stage();
         }
     }
@@ -598,7 +647,12 @@ impl<T: Simd> SIMDVoiceEnvelope<T> {
 impl<T: Simd> VoiceGeneratorBase for SIMDVoiceEnvelope<T> {
     #[inline(always)]
     fn ended(&self) -> bool {
-        self.state.current_stage == EnvelopeStage::Finished
+        match self.state.current_stage {
+            EnvelopeStage::Finished => true,
+            // The release only gets quieter, so it can end below the floor
+            EnvelopeStage::Release => self.get_value_at_current_time() < self.params.release_floor,
+            _ => false,
+        }
     }
 
     #[inline(always)]
@@ -610,6 +664,11 @@ impl<T: Simd> VoiceGeneratorBase for SIMDVoiceEnvelope<T> {
             );
             self.update_stage();
             self.killed = true;
+        } else if self.release_scale.is_none() {
+            // Only the first release decides the release time
+            let scale = self.release_scales.scale(rel_type);
+            self.release_scale = Some(scale);
+            Self::scale_release(&mut self.params, scale);
         }
         if self.allow_release || self.killed {
             let amp = self.get_value_at_current_time();
@@ -621,6 +680,14 @@ impl<T: Simd> VoiceGeneratorBase for SIMDVoiceEnvelope<T> {
     fn process_controls(&mut self, control: &VoiceControlData) {
         self.modify_envelope(control.envelope);
     }
+
+    fn inspect(&self, state: &mut VoiceGeneratorState) {
+        // The volume envelope is the first one in a voice
+        if state.envelope_stage.is_none() {
+            state.envelope_stage = Some(self.state.current_stage);
+            state.envelope_amplitude = Some(self.get_value_at_current_time());
+        }
+    }
 }
 
 impl<T: Simd> SIMDVoiceGenerator<T, SIMDSampleMono<T>> for SIMDVoiceEnvelope<T> {
@@ -852,4 +919,78 @@ mod tests {
 
         run();
     }
+
+    #[test]
+    fn test_envelope_inspect() {
+        fn inspect<S: Simd>(env: &SIMDVoiceEnvelope<S>) -> VoiceGeneratorState {
+            let mut state = VoiceGeneratorState::default();
+            env.inspect(&mut state);
+            state
+        }
+
+        fn stage_at(sample: usize) -> EnvelopeStage {
+            match sample / 32 {
+                0 => EnvelopeStage::Delay,
+                1 => EnvelopeStage::Attack,
+                2 => EnvelopeStage::Hold,
+                3 => EnvelopeStage::Decay,
+                _ => EnvelopeStage::Sustain,
+            }
+        }
+
+        simd_runtime_generate!(
+            fn run() {
+                let descriptor = EnvelopeDescriptor {
+                    start_percent: 0.0,
+                    delay: 32.0,
+                    attack: 32.0,
+                    hold: 32.0,
+                    decay: 32.0,
+                    sustain_percent: 0.5,
+                    release: 32.0,
+                };
+                let params = descriptor.to_envelope_params(1, Default::default());
+                let mut env = SIMDVoiceEnvelope::<S>::new(params, params, true, 1.0);
+
+                let mut stages = vec![inspect(&env).envelope_stage.unwrap()];
+                let mut rendered = 0;
+                while rendered < 192 {
+                    env.next_sample();
+                    rendered += S::Vf32::WIDTH;
+
+                    // The stage changes either with the last sample of a
+                    // stage or with the first sample of the next one
+                    let stage = inspect(&env).envelope_stage.unwrap();
+                    assert!(stage == stage_at(rendered - 1) || stage == stage_at(rendered));
+                    if stages.last() != Some(&stage) {
+                        stages.push(stage);
+                    }
+                }
+                assert_eq!(
+                    stages,
+                    vec![
+                        EnvelopeStage::Delay,
+                        EnvelopeStage::Attack,
+                        EnvelopeStage::Hold,
+                        EnvelopeStage::Decay,
+                        EnvelopeStage::Sustain,
+                    ]
+                );
+                assert_eq!(inspect(&env).envelope_amplitude, Some(0.5));
+
+                env.signal_release(ReleaseType::Standard);
+                let state = inspect(&env);
+                assert_eq!(state.envelope_stage, Some(EnvelopeStage::Release));
+                assert_eq!(state.envelope_amplitude, Some(0.5));
+
+                while rendered < 192 + 32 + S::Vf32::WIDTH {
+                    env.next_sample();
+                    rendered += S::Vf32::WIDTH;
+                }
+                assert_eq!(inspect(&env).envelope_stage, Some(EnvelopeStage::Finished));
+            }
+        );
+
+        run();
+    }
 }
@@ -0,0 +1,110 @@
+use super::{
+    ReleaseType, Voice, VoiceControlData, VoiceGeneratorBase, VoiceGeneratorState, VoicePolyphony,
+    VoiceSampleGenerator,
+};
+
+/// A voice that fades in to a fixed level with an equal power curve, so it
+/// can be crossfaded with voices fading out linearly.
+pub(crate) struct FadeInVoice {
+    voice: Box<dyn Voice>,
+    level: f32,
+    fade_frames: u32,
+    channels: usize,
+    frame: u32,
+    buffer: Vec<f32>,
+}
+
+impl FadeInVoice {
+    pub fn new(voice: Box<dyn Voice>, level: f32, fade_frames: u32, channels: usize) -> Self {
+        Self {
+            voice,
+            level,
+            fade_frames,
+            channels,
+            frame: 0,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// The gain of the voice at the current frame.
+    fn gain(&self) -> f32 {
+        if self.frame >= self.fade_frames {
+            return self.level;
+        }
+        let fade_out = 1.0 - self.frame as f32 / self.fade_frames as f32;
+        self.level * (1.0 - fade_out * fade_out).sqrt()
+    }
+}
+
+impl VoiceGeneratorBase for FadeInVoice {
+    #[inline(always)]
+    fn ended(&self) -> bool {
+        self.voice.ended()
+    }
+
+    #[inline(always)]
+    fn signal_release(&mut self, rel_type: ReleaseType) {
+        self.voice.signal_release(rel_type)
+    }
+
+    #[inline(always)]
+    fn process_controls(&mut self, control: &VoiceControlData) {
+        self.voice.process_controls(control)
+    }
+
+    #[inline(always)]
+    fn inspect(&self, state: &mut VoiceGeneratorState) {
+        self.voice.inspect(state)
+    }
+}
+
+impl VoiceSampleGenerator for FadeInVoice {
+    fn render_to(&mut self, buffer: &mut [f32]) {
+        if self.frame >= self.fade_frames && self.level == 1.0 {
+            self.voice.render_to(buffer);
+            return;
+        }
+
+        self.buffer.clear();
+        self.buffer.resize(buffer.len(), 0.0);
+        self.voice.render_to(&mut self.buffer);
+
+        for (out, frame) in buffer
+            .chunks_mut(self.channels)
+            .zip(self.buffer.chunks(self.channels))
+        {
+            let gain = self.gain();
+            for (out, sample) in out.iter_mut().zip(frame) {
+                *out += sample * gain;
+            }
+            self.frame = self.frame.saturating_add(1);
+        }
+    }
+}
+
+impl Voice for FadeInVoice {
+    #[inline(always)]
+    fn is_releasing(&self) -> bool {
+        self.voice.is_releasing()
+    }
+
+    #[inline(always)]
+    fn is_killed(&self) -> bool {
+        self.voice.is_killed()
+    }
+
+    #[inline(always)]
+    fn velocity(&self) -> u8 {
+        self.voice.velocity()
+    }
+
+    #[inline(always)]
+    fn amplitude(&self) -> f32 {
+        self.voice.amplitude() * self.gain()
+    }
+
+    #[inline(always)]
+    fn polyphony(&self) -> Option<(VoicePolyphony, u64)> {
+        self.voice.polyphony()
+    }
+}
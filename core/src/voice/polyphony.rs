@@ -0,0 +1,107 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::{
+    ReleaseType, Voice, VoiceControlData, VoiceGeneratorBase, VoiceGeneratorState,
+    VoiceSampleGenerator,
+};
+
+/// The polyphony limits of the voices spawned from a region, such as the
+/// `group`, `polyphony` and `note_polyphony` SFZ opcodes.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct VoicePolyphony {
+    /// The polyphony group the voices belong to.
+    pub group: u32,
+
+    /// The maximum number of voices of the group playing at once on all keys
+    /// of a channel. The oldest voices are killed first.
+    pub polyphony: Option<u32>,
+
+    /// The maximum number of voices of the group playing at once on a single
+    /// key. The oldest voices are killed first.
+    pub note_polyphony: Option<u32>,
+}
+
+impl VoicePolyphony {
+    /// Returns true if the voices are limited at all.
+    pub fn is_limited(&self) -> bool {
+        self.polyphony.is_some() || self.note_polyphony.is_some()
+    }
+}
+
+/// Orders the voices with polyphony limits by when they were spawned, so the
+/// oldest ones can be found across keys.
+static SPAWN_ORDER: AtomicU64 = AtomicU64::new(0);
+
+/// A voice that carries the polyphony limits of the region it was spawned
+/// from. See `Voice::polyphony`.
+pub(crate) struct PolyphonyVoice {
+    voice: Box<dyn Voice>,
+    polyphony: VoicePolyphony,
+    order: u64,
+}
+
+impl PolyphonyVoice {
+    pub fn new(voice: Box<dyn Voice>, polyphony: VoicePolyphony) -> Self {
+        Self {
+            voice,
+            polyphony,
+            order: SPAWN_ORDER.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+impl VoiceGeneratorBase for PolyphonyVoice {
+    #[inline(always)]
+    fn ended(&self) -> bool {
+        self.voice.ended()
+    }
+
+    #[inline(always)]
+    fn signal_release(&mut self, rel_type: ReleaseType) {
+        self.voice.signal_release(rel_type)
+    }
+
+    #[inline(always)]
+    fn process_controls(&mut self, control: &VoiceControlData) {
+        self.voice.process_controls(control)
+    }
+
+    #[inline(always)]
+    fn inspect(&self, state: &mut VoiceGeneratorState) {
+        self.voice.inspect(state)
+    }
+}
+
+impl VoiceSampleGenerator for PolyphonyVoice {
+    #[inline(always)]
+    fn render_to(&mut self, buffer: &mut [f32]) {
+        self.voice.render_to(buffer)
+    }
+}
+
+impl Voice for PolyphonyVoice {
+    #[inline(always)]
+    fn is_releasing(&self) -> bool {
+        self.voice.is_releasing()
+    }
+
+    #[inline(always)]
+    fn is_killed(&self) -> bool {
+        self.voice.is_killed()
+    }
+
+    #[inline(always)]
+    fn velocity(&self) -> u8 {
+        self.voice.velocity()
+    }
+
+    #[inline(always)]
+    fn amplitude(&self) -> f32 {
+        self.voice.amplitude()
+    }
+
+    #[inline(always)]
+    fn polyphony(&self) -> Option<(VoicePolyphony, u64)> {
+        Some((self.polyphony, self.order))
+    }
+}
@@ -0,0 +1,57 @@
+use std::marker::PhantomData;
+
+use simdeez::prelude::*;
+
+use crate::voice::{ReleaseType, VoiceControlData};
+
+use super::{SIMDSampleMono, SIMDVoiceGenerator, VoiceGeneratorBase};
+
+/// A gain rising linearly from silence to 1 over the first samples of a
+/// voice, to prevent clicks on samples that don't start at zero.
+pub struct SIMDStartRamp<S: Simd> {
+    position: u32,
+    length: u32,
+    _s: PhantomData<S>,
+}
+
+impl<S: Simd> SIMDStartRamp<S> {
+    /// A ramp `length` samples long. A length of 0 disables the ramp.
+    pub fn new(length: u32) -> SIMDStartRamp<S> {
+        SIMDStartRamp {
+            position: 0,
+            length,
+            _s: PhantomData,
+        }
+    }
+}
+
+impl<S: Simd> VoiceGeneratorBase for SIMDStartRamp<S> {
+    #[inline(always)]
+    fn ended(&self) -> bool {
+        false
+    }
+
+    #[inline(always)]
+    fn signal_release(&mut self, _rel_type: ReleaseType) {}
+
+    #[inline(always)]
+    fn process_controls(&mut self, _control: &VoiceControlData) {}
+}
+
+impl<S: Simd> SIMDVoiceGenerator<S, SIMDSampleMono<S>> for SIMDStartRamp<S> {
+    #[inline(always)]
+    fn next_sample(&mut self) -> SIMDSampleMono<S> {
+        simd_invoke!(S, {
+            if self.position >= self.length {
+                return SIMDSampleMono(S::Vf32::set1(1.0));
+            }
+
+            let mut values = S::Vf32::zeroes();
+            for i in 0..S::Vf32::WIDTH {
+                values[i] = (self.position as f32 / self.length as f32).min(1.0);
+                self.position += 1;
+            }
+            SIMDSampleMono(values)
+        })
+    }
+}
@@ -5,7 +5,9 @@ use simdeez::prelude::*;
 use crate::soundfont::LoopParams;
 use crate::voice::{ReleaseType, VoiceControlData};
 
-use super::{SIMDSampleMono, SIMDSampleStereo, SIMDVoiceGenerator, VoiceGeneratorBase};
+use super::{
+    SIMDSampleMono, SIMDSampleStereo, SIMDVoiceGenerator, VoiceGeneratorBase, VoiceGeneratorState,
+};
 
 mod linear;
 pub use linear::*;
@@ -26,6 +28,7 @@ pub use nearest::*;
 // Base traits
 
 pub trait BufferSampler: Send + Sync {
+    /// Returns the sample at `pos`, or `0.0` past the end of the buffer.
     fn get(&self, pos: usize) -> f32;
     fn length(&self) -> usize;
 }
@@ -36,6 +39,10 @@ pub trait SIMDSampleGrabber<S: Simd>: Send + Sync {
     /// Fractional: The fractional part of the index, i.e. the 0-1 range decimal
     fn get(&mut self, indexes: S::Vi32, fractional: S::Vf32) -> S::Vf32;
 
+    /// Returns true once no frame at or after `pos` can contribute to the
+    /// output anymore. The fractional part of `pos` is ignored, so a
+    /// position between the last frame and the end of the sample still
+    /// plays, interpolating towards the silence past the end.
     fn is_past_end(&self, pos: f64) -> bool;
 
     fn signal_release(&mut self);
@@ -48,13 +55,8 @@ pub struct F32BufferSampler(Arc<[f32]>);
 impl BufferSampler for F32BufferSampler {
     #[inline(always)]
     fn get(&self, pos: usize) -> f32 {
-        // SAFETY: Callers ensure pos is within bounds via is_past_end checks
-        // Use unchecked access for maximum performance in the hot path
-        if pos < self.0.len() {
-            unsafe { *self.0.get_unchecked(pos) }
-        } else {
-            0.0
-        }
+        // Interpolating grabbers read one frame past the end of the sample
+        self.0.get(pos).copied().unwrap_or(0.0)
     }
 
     fn length(&self) -> usize {
@@ -93,7 +95,12 @@ impl BufferSampler for BufferSamplers {
 // Enum sampler reader
 
 pub trait SampleReader: Send + Sync {
+    /// Returns the sample at `pos` frames from the start of the voice.
+    /// Positions past the end of a non-looping sample return `0.0`.
     fn get(&mut self, pos: usize) -> f32;
+
+    /// Returns true if `pos` and every later position is past the end of
+    /// the sample.
     fn is_past_end(&self, pos: usize) -> bool;
     fn signal_release(&mut self);
 }
@@ -122,7 +129,7 @@ impl<Sampler: BufferSampler> SampleReader for SampleReaderNoLoop<Sampler> {
 
     fn is_past_end(&self, pos: usize) -> bool {
         if let Some(len) = self.length {
-            pos - self.offset.min(pos) >= len
+            pos + self.offset >= len
         } else {
             false
         }
@@ -175,7 +182,13 @@ pub struct SampleReaderLoopSustain<Sampler: BufferSampler> {
     offset: usize,
     loop_start: usize,
     loop_end: usize,
+
+    /// The furthest position read so far, including the offset
     last: usize,
+
+    /// The number of frames the looping skipped back by the release, which
+    /// are subtracted from the position while playing through
+    release_shift: usize,
     is_released: bool,
 }
 
@@ -189,31 +202,34 @@ impl<Sampler: BufferSampler> SampleReaderLoopSustain<Sampler> {
             loop_start: loop_params.start as usize,
             loop_end: loop_params.end as usize,
             last: 0,
+            release_shift: 0,
             is_released: false,
         }
     }
+
+    /// Maps a position including the offset into the loop.
+    fn wrap(&self, pos: usize) -> usize {
+        let end = self.loop_end;
+        let start = self.loop_start;
+        if pos > end && end > start {
+            start + (pos - end - 1) % (end - start)
+        } else {
+            pos
+        }
+    }
 }
 
 impl<Sampler: BufferSampler> SampleReader for SampleReaderLoopSustain<Sampler> {
     fn get(&mut self, pos: usize) -> f32 {
         let pos = pos + self.offset;
-        let end = self.loop_end;
-        let start = self.loop_start;
 
         let final_pos = if !self.is_released {
-            if pos > end && end > start {
-                // Calculate wrapped position within the loop
-                let loop_len = end - start;
-                let wrapped = start + (pos - end - 1) % loop_len;
-                self.last = pos - self.offset; // Store original position for release phase
-                wrapped
-            } else {
-                pos
-            }
+            self.last = self.last.max(pos);
+            self.wrap(pos)
         } else {
-            // After release, continue from where we left off
-            let release_pos = pos - self.last;
-            release_pos
+            // After release, continue from the current position in the loop
+            // through the loop end to the end of the sample
+            pos.saturating_sub(self.release_shift)
         };
 
         self.buffer.get(final_pos)
@@ -223,15 +239,9 @@ impl<Sampler: BufferSampler> SampleReader for SampleReaderLoopSustain<Sampler> {
         if !self.is_released {
             return false;
         }
-        
+
         if let Some(len) = self.length {
-            // Calculate effective position after accounting for loop
-            let effective_pos = if self.last > self.offset {
-                pos + self.last - self.offset
-            } else {
-                pos
-            };
-            effective_pos >= len
+            (pos + self.offset).saturating_sub(self.release_shift) >= len
         } else {
             false
         }
@@ -240,8 +250,9 @@ impl<Sampler: BufferSampler> SampleReader for SampleReaderLoopSustain<Sampler> {
     fn signal_release(&mut self) {
         if !self.is_released {
             self.is_released = true;
-            // Store the current position when released for smooth transition
-            self.last = self.last.max(self.offset);
+            // Keep playing from the same frame, so the release doesn't jump
+            // within the sample
+            self.release_shift = self.last - self.wrap(self.last);
         }
     }
 }
@@ -301,6 +312,9 @@ where
 
     pitch_gen: Pitch,
 
+    /// The position in the sample. It is accumulated in f64, so even notes
+    /// held for hours don't drift, and only converted to the i32 index and
+    /// f32 fraction of each lane for the grabber.
     time: f64,
 
     _s: PhantomData<S>,
@@ -321,6 +335,12 @@ where
         }
     }
 
+    /// Starts the playback at the given position in the sample, in frames.
+    pub fn starting_at(mut self, position: f64) -> Self {
+        self.time = position;
+        self
+    }
+
     fn increment_time(&mut self, by: f64) -> f64 {
         let time = self.time;
         self.time += by;
@@ -349,6 +369,10 @@ where
     fn process_controls(&mut self, control: &VoiceControlData) {
         self.pitch_gen.process_controls(control);
     }
+
+    fn inspect(&self, state: &mut VoiceGeneratorState) {
+        state.sample_position = Some(self.time);
+    }
 }
 
 impl<S, Pitch, Grabber> SIMDVoiceGenerator<S, SIMDSampleMono<S>>
@@ -416,6 +440,12 @@ where
         }
     }
 
+    /// Starts the playback at the given position in the sample, in frames.
+    pub fn starting_at(mut self, position: f64) -> Self {
+        self.time = position;
+        self
+    }
+
     fn increment_time(&mut self, by: f64) -> f64 {
         let time = self.time;
         self.time += by;
@@ -445,6 +475,10 @@ where
     fn process_controls(&mut self, control: &VoiceControlData) {
         self.pitch_gen.process_controls(control);
     }
+
+    fn inspect(&self, state: &mut VoiceGeneratorState) {
+        state.sample_position = Some(self.time);
+    }
 }
 
 impl<S, Pitch, Grabber> SIMDVoiceGenerator<S, SIMDSampleStereo<S>>
@@ -480,3 +514,149 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use simdeez::simd_runtime_generate;
+
+    use xsynth_soundfonts::LoopMode;
+
+    use super::*;
+    use crate::{soundfont::LoopParams, voice::SIMDConstant};
+
+    /// A non-looping reader of a 4 frame ramp from 0.25 to 1.0.
+    fn ramp_reader(offset: u32) -> SampleReaderNoLoop<BufferSamplers> {
+        let ramp: Arc<[f32]> = Arc::new([0.25, 0.5, 0.75, 1.0]);
+        let loop_params = LoopParams {
+            mode: LoopMode::NoLoop,
+            offset,
+            start: 0,
+            end: 0,
+        };
+        SampleReaderNoLoop::new(BufferSamplers::new_f32(ramp), loop_params)
+    }
+
+    /// A grabber of an endless silent sample.
+    struct SilentGrabber;
+
+    impl<S: Simd> SIMDSampleGrabber<S> for SilentGrabber {
+        fn get(&mut self, _indexes: S::Vi32, _fractional: S::Vf32) -> S::Vf32 {
+            S::Vf32::zeroes()
+        }
+
+        fn is_past_end(&self, _pos: f64) -> bool {
+            false
+        }
+
+        fn signal_release(&mut self) {}
+    }
+
+    #[test]
+    fn test_long_note_position() {
+        simd_runtime_generate!(
+            fn run() {
+                // A 10 minute note at 48kHz, pitched up by a fifth
+                let frames = 48000 * 600;
+                let mut sampler = SIMDMonoVoiceSampler::<S, _, _>::new(
+                    SilentGrabber,
+                    SIMDConstant::<S>::new(1.5),
+                );
+                for _ in 0..frames / S::Vf32::WIDTH {
+                    sampler.next_sample();
+                }
+
+                let rendered = (frames / S::Vf32::WIDTH * S::Vf32::WIDTH) as f64;
+                assert!((sampler.time - rendered * 1.5).abs() < 1.0);
+            }
+        );
+
+        run();
+    }
+    #[test]
+    fn test_linear_sample_end() {
+        simd_runtime_generate!(
+            fn run() {
+                // Play the ramp at half speed, at positions 0.0, 0.5, 1.0, ...
+                let grabber = SIMDLinearSampleGrabber::<S, _>::new(ramp_reader(0));
+                let mut sampler = SIMDMonoVoiceSampler::new(grabber, SIMDConstant::<S>::new(0.5));
+
+                let mut out = Vec::new();
+                while out.len() < 16 {
+                    let sample = sampler.next_sample().0;
+                    for i in 0..S::Vf32::WIDTH {
+                        out.push(unsafe { sample.get_unchecked(i) });
+                    }
+                }
+
+                // The last frame fades out towards the silence past the end
+                assert_eq!(out[5..10], [0.875, 1.0, 0.5, 0.0, 0.0]);
+            }
+        );
+
+        run();
+    }
+
+    #[test]
+    fn test_sample_past_end() {
+        simd_runtime_generate!(
+            fn run() {
+                let grabber = SIMDLinearSampleGrabber::<S, _>::new(ramp_reader(0));
+                assert!(!grabber.is_past_end(3.9));
+                assert!(grabber.is_past_end(4.0));
+
+                // The offset skips the start of the sample, so it also ends
+                // earlier
+                let grabber = SIMDLinearSampleGrabber::<S, _>::new(ramp_reader(2));
+                assert!(!grabber.is_past_end(1.5));
+                assert!(grabber.is_past_end(2.0));
+            }
+        );
+
+        run();
+    }
+    #[test]
+    fn test_sustain_loop_release_continues() {
+        simd_runtime_generate!(
+            fn run() {
+                // A ramp sample, so every jump within it is audible as a step
+                let ramp: Arc<[f32]> = (0..40).map(|i| i as f32).collect();
+                let loop_params = LoopParams {
+                    mode: LoopMode::LoopSustain,
+                    offset: 0,
+                    start: 10,
+                    end: 20,
+                };
+                let reader =
+                    SampleReaderLoopSustain::new(BufferSamplers::new_f32(ramp), loop_params);
+                let grabber = SIMDLinearSampleGrabber::<S, _>::new(reader);
+                let mut sampler = SIMDMonoVoiceSampler::new(grabber, SIMDConstant::<S>::new(1.0));
+
+                let mut out = Vec::new();
+                let mut released = false;
+                while !sampler.ended() {
+                    if !released && out.len() >= 64 {
+                        sampler.signal_release(ReleaseType::Standard);
+                        released = true;
+                        continue;
+                    }
+
+                    let sample = sampler.next_sample().0;
+                    for i in 0..S::Vf32::WIDTH {
+                        out.push(unsafe { sample.get_unchecked(i) });
+                    }
+                }
+
+                // The release plays on from the looped frame 12 to the end of
+                // the sample without jumping
+                let expected: Vec<f32> = (12..40).map(|i| i as f32).collect();
+                assert_eq!(out[63..91], expected);
+                assert_eq!(
+                    sampler.time as usize,
+                    91usize.next_multiple_of(S::Vf32::WIDTH)
+                );
+            }
+        );
+
+        run();
+    }
+}
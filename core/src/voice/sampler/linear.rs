@@ -26,6 +26,8 @@ impl<S: Simd, Reader: SampleReader> SIMDSampleGrabber<S> for SIMDLinearSampleGra
             let mut values_first = S::Vf32::zeroes();
             let mut values_second = S::Vf32::zeroes();
 
+            // After the last frame of a non-looping sample the next frame
+            // reads as silence, so the sample fades out towards zero
             unsafe {
                 for i in 0..S::Vf32::WIDTH {
                     let index = indexes.get_unchecked(i) as usize;
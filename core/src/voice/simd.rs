@@ -7,7 +7,7 @@ use simdeez::prelude::*;
 
 use crate::voice::{ReleaseType, VoiceControlData};
 
-use super::VoiceGeneratorBase;
+use super::{VoiceGeneratorBase, VoiceGeneratorState};
 
 /// The base SIMD voice sample trait, generally either mono or stereo
 pub trait SIMDSample<T: Simd>: Sync + Send {
@@ -170,6 +170,12 @@ where
         self.v1.process_controls(control);
         self.v2.process_controls(control);
     }
+
+    #[inline(always)]
+    fn inspect(&self, state: &mut VoiceGeneratorState) {
+        self.v1.inspect(state);
+        self.v2.inspect(state);
+    }
 }
 
 impl<T, TI, TO, V1, V2, F> SIMDVoiceGenerator<T, TO> for SIMDVoiceCombine<T, TI, TO, V1, V2, F>
@@ -6,13 +6,20 @@ use crate::voice::{ReleaseType, VoiceControlData};
 
 use super::{
     SIMDSample, SIMDSampleMono, SIMDSampleStereo, SIMDVoiceGenerator, VoiceGeneratorBase,
-    VoiceSampleGenerator,
+    VoiceGeneratorState, VoiceSampleGenerator,
 };
 
+/// Renders a stereo generator into interleaved L/R buffers.
+///
+/// The voice keeps track of its position in the interleaved stream, so
+/// consecutive calls to `render_to` continue where the previous one stopped,
+/// even if the slices don't start or end on frame boundaries.
 pub struct SIMDStereoVoice<S: Simd, T: SIMDVoiceGenerator<S, SIMDSampleStereo<S>>> {
     generator: T,
     remainder: SIMDSampleStereo<S>,
     remainder_pos: usize,
+    /// The right sample of a frame whose left sample ended the last buffer
+    pending_right: Option<f32>,
     _s: PhantomData<S>,
 }
 
@@ -22,6 +29,7 @@ impl<S: Simd, T: SIMDVoiceGenerator<S, SIMDSampleStereo<S>>> SIMDStereoVoice<S,
             generator,
             remainder: SIMDSampleStereo::<S>::zero(),
             remainder_pos: S::Vf32::WIDTH,
+            pending_right: None,
             _s: PhantomData,
         }
     }
@@ -46,6 +54,11 @@ where
     fn process_controls(&mut self, control: &VoiceControlData) {
         self.generator.process_controls(control)
     }
+
+    #[inline(always)]
+    fn inspect(&self, state: &mut VoiceGeneratorState) {
+        self.generator.inspect(state)
+    }
 }
 
 impl<S, T> VoiceSampleGenerator for SIMDStereoVoice<S, T>
@@ -59,17 +72,30 @@ where
             let width = S::Vf32::WIDTH;
             let mut buf_idx = 0;
             let buf_len = buffer.len();
-            
-            // First, consume any remainder from previous call
-            while buf_idx < buf_len && self.remainder_pos < width {
+
+            if buf_len == 0 {
+                return;
+            }
+
+            // Finish the frame that the previous buffer ended in the middle of
+            if let Some(right) = self.pending_right.take() {
+                buffer[0] += right;
+                buf_idx = 1;
+            }
+
+            // Consume any remainder from the previous call, one full frame
+            // at a time
+            while buf_idx + 1 < buf_len && self.remainder_pos < width {
                 unsafe {
-                    *buffer.get_unchecked_mut(buf_idx) += self.remainder.0.get_unchecked(self.remainder_pos);
-                    *buffer.get_unchecked_mut(buf_idx + 1) += self.remainder.1.get_unchecked(self.remainder_pos);
+                    *buffer.get_unchecked_mut(buf_idx) +=
+                        self.remainder.0.get_unchecked(self.remainder_pos);
+                    *buffer.get_unchecked_mut(buf_idx + 1) +=
+                        self.remainder.1.get_unchecked(self.remainder_pos);
                 }
                 buf_idx += 2;
                 self.remainder_pos += 1;
             }
-            
+
             // Stereo has interleaved L/R, so we need to process samples individually
             // But we can still benefit from batching generator calls
             let samples_per_batch = width * 2;
@@ -84,19 +110,25 @@ where
                 }
                 buf_idx += samples_per_batch;
             }
-            
-            // Handle remaining samples
-            if buf_idx < buf_len {
-                self.remainder = self.generator.next_sample();
-                self.remainder_pos = 0;
-                while buf_idx < buf_len {
-                    unsafe {
-                        *buffer.get_unchecked_mut(buf_idx) += self.remainder.0.get_unchecked(self.remainder_pos);
-                        *buffer.get_unchecked_mut(buf_idx + 1) += self.remainder.1.get_unchecked(self.remainder_pos);
-                    }
-                    buf_idx += 2;
-                    self.remainder_pos += 1;
+
+            // Handle remaining samples. If the buffer ends after a left
+            // sample, its right sample is kept for the next call.
+            while buf_idx < buf_len {
+                if self.remainder_pos >= width {
+                    self.remainder = self.generator.next_sample();
+                    self.remainder_pos = 0;
+                }
+
+                let left = self.remainder.0[self.remainder_pos];
+                let right = self.remainder.1[self.remainder_pos];
+                buffer[buf_idx] += left;
+                if buf_idx + 1 < buf_len {
+                    buffer[buf_idx + 1] += right;
+                } else {
+                    self.pending_right = Some(right);
                 }
+                buf_idx += 2;
+                self.remainder_pos += 1;
             }
         })
     }
@@ -139,6 +171,11 @@ where
     fn process_controls(&mut self, control: &VoiceControlData) {
         self.generator.process_controls(control)
     }
+
+    #[inline(always)]
+    fn inspect(&self, state: &mut VoiceGeneratorState) {
+        self.generator.inspect(state)
+    }
 }
 
 impl<S, T> VoiceSampleGenerator for SIMDMonoVoice<S, T>
@@ -188,3 +225,96 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use simdeez::simd_runtime_generate;
+
+    use super::*;
+
+    /// Generates frames whose left sample is the frame index and whose
+    /// right sample is the negated index minus 0.5.
+    struct CountingGenerator<S: Simd> {
+        frame: usize,
+        _s: PhantomData<S>,
+    }
+
+    impl<S: Simd> VoiceGeneratorBase for CountingGenerator<S> {
+        fn ended(&self) -> bool {
+            false
+        }
+
+        fn signal_release(&mut self, _rel_type: ReleaseType) {}
+
+        fn process_controls(&mut self, _control: &VoiceControlData) {}
+    }
+
+    impl<S: Simd> SIMDVoiceGenerator<S, SIMDSampleStereo<S>> for CountingGenerator<S> {
+        fn next_sample(&mut self) -> SIMDSampleStereo<S> {
+            simd_invoke!(S, {
+                let mut left = S::Vf32::zeroes();
+                let mut right = S::Vf32::zeroes();
+                for i in 0..S::Vf32::WIDTH {
+                    left[i] = self.frame as f32;
+                    right[i] = -(self.frame as f32) - 0.5;
+                    self.frame += 1;
+                }
+                SIMDSampleStereo(left, right)
+            })
+        }
+    }
+
+    /// Renders consecutive slices of the given lengths into one buffer,
+    /// which has an extra sample at the end to catch writes past the last
+    /// slice.
+    fn render_in_slices(slices: &[usize]) -> Vec<f32> {
+        simd_runtime_generate!(
+            fn render(slices: &[usize]) -> Vec<f32> {
+                let mut voice = SIMDStereoVoice::<S, _>::new(CountingGenerator {
+                    frame: 0,
+                    _s: PhantomData,
+                });
+                let mut out = vec![0.0; slices.iter().sum::<usize>() + 1];
+                let mut start = 0;
+                for &len in slices {
+                    voice.render_to(&mut out[start..start + len]);
+                    start += len;
+                }
+                out
+            }
+        );
+
+        render(slices)
+    }
+
+    fn expected(len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| {
+                let frame = (i / 2) as f32;
+                if i % 2 == 0 {
+                    frame
+                } else {
+                    -frame - 0.5
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_odd_length() {
+        for len in [1, 5, 33, 71] {
+            let out = render_in_slices(&[len]);
+            assert_eq!(out[..len], expected(len));
+            assert_eq!(out[len], 0.0);
+        }
+    }
+
+    #[test]
+    fn test_slices_split_mid_frame() {
+        let slices = [3, 1, 17, 2, 0, 33, 14];
+        let len = slices.iter().sum();
+        let out = render_in_slices(&slices);
+        assert_eq!(out[..len], expected(len));
+        assert_eq!(out[len], 0.0);
+    }
+}
@@ -67,7 +67,7 @@ pub extern "C" fn InitializeKDMAPIStream() -> i32 {
         ChannelConfigEvent::SetLayerCount(config.get_layers()),
     )));
     sender.send_event(SynthEvent::AllChannels(ChannelEvent::Config(
-        ChannelConfigEvent::SetSoundfonts(sflist.create_sfbase_vector(params)),
+        ChannelConfigEvent::SetSoundfonts(sflist.create_sfbase_vector(params).into()),
     )));
 
     let killed = Arc::new(Mutex::new(false));
@@ -111,7 +111,7 @@ pub extern "C" fn InitializeKDMAPIStream() -> i32 {
                     .unwrap()
                     .create_sfbase_vector(params);
                 sender_thread.send_event(SynthEvent::AllChannels(ChannelEvent::Config(
-                    ChannelConfigEvent::SetSoundfonts(sfs),
+                    ChannelConfigEvent::SetSoundfonts(sfs.into()),
                 )));
             }
         })
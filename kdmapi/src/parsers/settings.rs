@@ -40,11 +40,13 @@ impl Settings {
         XSynthRealtimeConfig {
             channel_init_options: ChannelInitOptions {
                 fade_out_killing: self.fade_out_killing,
+                ..Default::default()
             },
             render_window_ms: self.render_window_ms,
             format: SynthFormat::Midi,
             multithreading: self.multithreading,
             ignore_range: self.ignore_range.clone(),
+            ..Default::default()
         }
     }
 }
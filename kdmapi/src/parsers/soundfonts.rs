@@ -1,8 +1,8 @@
 use super::ConfigPath;
 use serde::{Deserialize, Serialize};
-use std::{path::PathBuf, sync::Arc};
+use std::{fs, path::PathBuf, sync::Arc};
 use xsynth_core::{
-    soundfont::{SampleSoundfont, SoundfontBase, SoundfontInitOptions},
+    soundfont::{SampleSoundfont, SoundfontBase, SoundfontInitOptions, SoundfontPatch},
     AudioStreamParams,
 };
 
@@ -12,6 +12,10 @@ pub struct SFDescriptor {
     pub path: PathBuf,
     pub enabled: bool,
     pub options: SoundfontInitOptions,
+
+    /// A JSON file with region overrides applied to the soundfont after it
+    /// is loaded, see `SampleSoundfont::export_patch`.
+    pub patch: Option<PathBuf>,
 }
 
 impl Default for SFDescriptor {
@@ -20,6 +24,7 @@ impl Default for SFDescriptor {
             path: PathBuf::new(),
             enabled: true,
             options: Default::default(),
+            patch: None,
         }
     }
 }
@@ -33,6 +38,20 @@ impl SFDescriptor {
             None
         }
     }
+
+    /// Applies the region overrides of the patch file, if any.
+    fn apply_patch(&self, soundfont: &SampleSoundfont) -> Result<(), String> {
+        let Some(path) = &self.patch else {
+            return Ok(());
+        };
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Error reading the patch {}: {e}", path.display()))?;
+        let patch: SoundfontPatch = serde_json::from_str(&contents)
+            .map_err(|e| format!("Error parsing the patch {}: {e}", path.display()))?;
+        soundfont
+            .apply_patch(&patch)
+            .map_err(|e| format!("Error applying the patch {}: {e}", path.display()))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -58,7 +77,12 @@ impl SFList {
         for sf in self.soundfonts {
             if let Some(path) = sf.path() {
                 match SampleSoundfont::new(path, stream_params, sf.options) {
-                    Ok(sf) => out.push(Arc::new(sf)),
+                    Ok(soundfont) => {
+                        if let Err(e) = sf.apply_patch(&soundfont) {
+                            println!("Error loading soundfont: {e}");
+                        }
+                        out.push(Arc::new(soundfont))
+                    }
                     Err(e) => println!("Error loading soundfont: {e}"),
                 }
             }
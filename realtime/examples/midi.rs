@@ -7,7 +7,7 @@ use std::{
 };
 
 use midi_toolkit::{
-    events::Event,
+    events::{Event, MIDIEventEnum},
     io::MIDIFile,
     pipe,
     sequence::{
@@ -19,17 +19,29 @@ use xsynth_core::{
     channel::{ChannelAudioEvent, ChannelConfigEvent, ChannelEvent, ControlEvent, ChannelInitOptions},
     soundfont::{SampleSoundfont, SoundfontBase},
 };
-use xsynth_realtime::{RealtimeSynth, SynthEvent};
+use xsynth_realtime::{RealtimeSynth, SynthEvent, SynthFormat};
 
 /// Maximum allowed render time in seconds before forcing exit
 const MAX_RENDER_TIME: f64 = 3.0;
 /// Maximum consecutive high render time readings before exit
 const MAX_CONSECUTIVE_HIGH: u32 = 3;
-/// Maximum consecutive negative buffer readings before exit
-const MAX_CONSECUTIVE_NEGATIVE_BUFFER: u32 = 3;
+/// Maximum consecutive readings with new buffer underruns before exit
+const MAX_CONSECUTIVE_XRUNS: u32 = 3;
 
 fn main() {
-    let args = std::env::args().collect::<Vec<String>>();
+    let mut args = std::env::args().collect::<Vec<String>>();
+    // Logs every event sent to the synthesizer and applied by its channels
+    let trace = args.iter().any(|arg| arg == "--trace");
+    args.retain(|arg| arg != "--trace");
+    // Writes the statistics of every render quantum as CSV when the playback ends
+    let history_path = match args.iter().position(|arg| arg == "--stats-history") {
+        Some(i) if i + 1 < args.len() => {
+            let path = args.remove(i + 1);
+            args.remove(i);
+            Some(path)
+        }
+        _ => None,
+    };
     let (Some(midi), Some(sfz)) = (
         args.get(1)
             .cloned()
@@ -39,7 +51,7 @@ fn main() {
             .or_else(|| std::env::var("XSYNTH_EXAMPLE_SF").ok()),
     ) else {
         println!(
-            "Usage: {} [midi] [sfz/sf2]",
+            "Usage: {} [--trace] [--stats-history out.csv] [midi] [sfz/sf2]",
             std::env::current_exe()
                 .unwrap_or("example".into())
                 .display()
@@ -47,12 +59,20 @@ fn main() {
         return;
     };
 
+    if trace {
+        enable_tracing();
+    }
+
     // Use multithreading for best performance with high voice counts
     let synth = RealtimeSynth::open_with_default_output(
         xsynth_realtime::XSynthRealtimeConfig {
             channel_init_options: ChannelInitOptions::default(),
             render_window_ms: 10.0,
             multithreading: xsynth_realtime::ThreadCount::Auto,
+            // Enough channels for files using multiple MIDI ports. Idle channels are cheap.
+            format: SynthFormat::MultiPort { ports: 16 },
+            // About 10 minutes of 10ms render quanta
+            stats_history: history_path.as_ref().map(|_| 60_000),
             ..Default::default()
         }
     );
@@ -67,7 +87,7 @@ fn main() {
     println!("Loaded");
 
     sender.send_event(SynthEvent::AllChannels(ChannelEvent::Config(
-        ChannelConfigEvent::SetSoundfonts(soundfonts),
+        ChannelConfigEvent::SetSoundfonts(soundfonts.into()),
     )));
 
     let stats = synth.get_stats();
@@ -78,7 +98,8 @@ fn main() {
     
     thread::spawn(move || {
         let mut consecutive_high = 0u32;
-        let mut consecutive_negative_buffer = 0u32;
+        let mut consecutive_xruns = 0u32;
+        let mut last_xruns = 0u64;
         loop {
             let render_time = stats.buffer().average_renderer_load();
             let voice_count = stats.voice_count();
@@ -89,25 +110,29 @@ fn main() {
                 voice_count, buffer, render_time
             );
             
-            // Check if buffer is negative (underrun)
-            if buffer < 0 {
-                consecutive_negative_buffer += 1;
+            // Check if the synth reported new buffer underruns
+            let xruns = stats.buffer().xrun_count();
+            if xruns > last_xruns {
+                consecutive_xruns += 1;
                 eprintln!(
-                    "WARNING: Buffer underrun! Buffer: {} (consecutive: {})",
-                    buffer, consecutive_negative_buffer
+                    "WARNING: {} buffer underrun(s)! Worst shortfall: {} samples (consecutive: {})",
+                    xruns - last_xruns,
+                    stats.buffer().worst_shortfall(),
+                    consecutive_xruns
                 );
-                
-                if consecutive_negative_buffer >= MAX_CONSECUTIVE_NEGATIVE_BUFFER {
+                last_xruns = xruns;
+
+                if consecutive_xruns >= MAX_CONSECUTIVE_XRUNS {
                     eprintln!(
                         "CRITICAL: Buffer underrun for {} consecutive readings. Forcing exit!",
-                        MAX_CONSECUTIVE_NEGATIVE_BUFFER
+                        MAX_CONSECUTIVE_XRUNS
                     );
                     should_exit_clone.store(true, Ordering::Relaxed);
                     thread::sleep(Duration::from_millis(100));
                     process::exit(1);
                 }
             } else {
-                consecutive_negative_buffer = 0;
+                consecutive_xruns = 0;
             }
             
             // Check if render time exceeds threshold
@@ -139,7 +164,7 @@ fn main() {
 
     let ppq = midi.ppq();
     let merged = pipe!(
-        midi.iter_all_events_merged_batches()
+        midi.iter_all_track_events_merged_batches()
         |>TimeCaster::<f64>::cast_event_delta()
         |>cancel_tempo_events(250000)
         |>scale_event_time(1.0 / ppq as f64)
@@ -156,6 +181,8 @@ fn main() {
 
     let now = Instant::now();
     let mut time = 0.0;
+    // The current MIDI port of each track, set by port meta events
+    let mut track_ports: Vec<u32> = Vec::new();
     for batch in rcv {
         // Check if we should exit due to high render time
         if should_exit.load(Ordering::Relaxed) {
@@ -171,11 +198,20 @@ fn main() {
             }
         }
 
-        for e in batch.iter_inner() {
-            match e {
+        for e in batch.iter_events() {
+            let track = e.track as usize;
+            if track_ports.len() <= track {
+                track_ports.resize(track + 1, 0);
+            }
+            let port = track_ports[track];
+
+            match e.as_event() {
+                Event::MIDIPort(e) => {
+                    track_ports[track] = e.channel as u32;
+                }
                 Event::NoteOn(e) => {
                     sender.send_event(SynthEvent::Channel(
-                        e.channel as u32,
+                        port * 16 + e.channel as u32,
                         ChannelEvent::Audio(ChannelAudioEvent::NoteOn {
                             key: e.key,
                             vel: e.velocity,
@@ -184,14 +220,14 @@ fn main() {
                 }
                 Event::NoteOff(e) => {
                     sender.send_event(SynthEvent::Channel(
-                        e.channel as u32,
+                        port * 16 + e.channel as u32,
                         ChannelEvent::Audio(ChannelAudioEvent::NoteOff { key: e.key }),
                     ));
                 }
                 Event::ControlChange(e) => {
                     sender.send_event(SynthEvent::Channel(
-                        e.channel as u32,
-                        ChannelEvent::Audio(ChannelAudioEvent::Control(ControlEvent::Raw(
+                        port * 16 + e.channel as u32,
+                        ChannelEvent::Audio(ChannelAudioEvent::Control(ControlEvent::from_raw(
                             e.controller,
                             e.value,
                         ))),
@@ -199,7 +235,7 @@ fn main() {
                 }
                 Event::PitchWheelChange(e) => {
                     sender.send_event(SynthEvent::Channel(
-                        e.channel as u32,
+                        port * 16 + e.channel as u32,
                         ChannelEvent::Audio(ChannelAudioEvent::Control(
                             ControlEvent::PitchBendValue(e.pitch as f32 / 8192.0),
                         )),
@@ -207,7 +243,7 @@ fn main() {
                 }
                 Event::ProgramChange(e) => {
                     sender.send_event(SynthEvent::Channel(
-                        e.channel as u32,
+                        port * 16 + e.channel as u32,
                         ChannelEvent::Audio(ChannelAudioEvent::ProgramChange(e.program)),
                     ));
                 }
@@ -216,5 +252,27 @@ fn main() {
         }
     }
 
+    if let Some(path) = history_path {
+        // Let the last notes ring out before writing the history
+        thread::sleep(Duration::from_secs(3));
+        let history = synth.stats_history().unwrap();
+        let file = std::io::BufWriter::new(std::fs::File::create(&path).unwrap());
+        history.drain_csv(file).unwrap();
+        println!("Wrote the stats history to {path}");
+        return;
+    }
+
     std::thread::sleep(Duration::from_secs(10000));
 }
+
+#[cfg(feature = "tracing")]
+fn enable_tracing() {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing_subscriber::filter::LevelFilter::TRACE)
+        .init();
+}
+
+#[cfg(not(feature = "tracing"))]
+fn enable_tracing() {
+    eprintln!("--trace needs the tracing feature: cargo run --example midi --features tracing");
+}
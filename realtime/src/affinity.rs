@@ -0,0 +1,131 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+/// Options for pinning the threads of the realtime synthesizer to specific
+/// CPU cores. Useful on CPUs with different core types (e.g. P and E cores)
+/// where the OS scheduler may not place the render threads well.
+///
+/// Core IDs are indices of the cores as reported by the OS, starting at 0.
+/// Cores that don't exist are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(default)
+)]
+pub struct ThreadLayout {
+    /// The cores the per-key render worker threads will be pinned to,
+    /// assigned in order and wrapping around if there are more workers
+    /// than cores. Leave empty to not pin the workers.
+    ///
+    /// Default: `[]`
+    pub worker_cores: Vec<usize>,
+
+    /// The cores the channel threads will be pinned to, assigned in order
+    /// and wrapping around. Cores used by dedicated channels are skipped.
+    /// Leave empty to not pin the channel threads.
+    ///
+    /// Default: `[]`
+    pub channel_cores: Vec<usize>,
+
+    /// Channels that get a core of their own, e.g. for a heavy piano
+    /// channel. The key is the channel index and the value is the core ID.
+    ///
+    /// Default: `{}`
+    pub dedicated_channels: BTreeMap<u32, usize>,
+}
+
+impl ThreadLayout {
+    /// Returns the core the given channel thread should be pinned to, if any.
+    pub(crate) fn channel_core(&self, channel: u32) -> Option<usize> {
+        if let Some(&core) = self.dedicated_channels.get(&channel) {
+            return Some(core);
+        }
+
+        let shared: Vec<usize> = self
+            .channel_cores
+            .iter()
+            .copied()
+            .filter(|c| !self.dedicated_channels.values().any(|d| d == c))
+            .collect();
+        if shared.is_empty() {
+            return None;
+        }
+
+        // Only count the channels sharing cores, so they are spread evenly
+        let index = (0..channel)
+            .filter(|c| !self.dedicated_channels.contains_key(c))
+            .count();
+        Some(shared[index % shared.len()])
+    }
+
+    /// Returns the core the given render worker should be pinned to, if any.
+    pub(crate) fn worker_core(&self, worker: usize) -> Option<usize> {
+        if self.worker_cores.is_empty() {
+            None
+        } else {
+            Some(self.worker_cores[worker % self.worker_cores.len()])
+        }
+    }
+}
+
+/// The kind of a thread in the realtime synthesizer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SynthThread {
+    /// The thread handling the given channel.
+    Channel(u32),
+
+    /// A per-key render worker thread with the given index.
+    Worker(usize),
+
+    /// The thread rendering the audio buffers from all the channels.
+    Render,
+}
+
+/// The realized placement of a thread of the realtime synthesizer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ThreadAssignment {
+    /// The thread this assignment is about.
+    pub thread: SynthThread,
+
+    /// The core the thread was requested to be pinned to, if any.
+    pub core: Option<usize>,
+
+    /// Whether the thread was successfully pinned to the core.
+    pub pinned: bool,
+}
+
+/// Collects the thread assignments as the threads start.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ThreadLayoutTracker(Arc<Mutex<Vec<ThreadAssignment>>>);
+
+impl ThreadLayoutTracker {
+    /// Pins the current thread to the given core, if any, and records the result.
+    pub fn pin_current(&self, thread: SynthThread, core: Option<usize>) {
+        let pinned =
+            core.is_some_and(|id| core_affinity::set_for_current(core_affinity::CoreId { id }));
+
+        let mut assignments = self.0.lock().unwrap();
+        assignments.push(ThreadAssignment {
+            thread,
+            core,
+            pinned,
+        });
+        assignments.sort_by_key(|a| a.thread);
+    }
+
+    /// Removes the assignment of a thread that stopped, e.g. the thread of a
+    /// removed channel.
+    pub fn release(&self, thread: SynthThread) {
+        let mut assignments = self.0.lock().unwrap();
+        if let Some(index) = assignments.iter().position(|a| a.thread == thread) {
+            assignments.remove(index);
+        }
+    }
+
+    pub fn assignments(&self) -> Vec<ThreadAssignment> {
+        self.0.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,129 @@
+use std::{fmt, time::Duration};
+
+use xsynth_core::SimdBackend;
+
+use crate::ThreadPriority;
+
+/// A summary of the capabilities a realtime synthesizer negotiated with the
+/// machine and the audio device, e.g. to show diagnostics to the user.
+///
+/// See `RealtimeSynth::capability_report`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct CapabilityReport {
+    /// The SIMD instruction set the voices are rendered with.
+    pub simd_backend: SimdBackend,
+
+    /// The number of channel threads.
+    pub channel_threads: u32,
+
+    /// The number of worker threads rendering the keys of the channels in
+    /// parallel, or `0` if the keys are rendered on the channel threads.
+    pub worker_threads: usize,
+
+    /// The name of the audio host, e.g. "ALSA" or "WASAPI". Only known when
+    /// the synthesizer opened the default output device itself.
+    pub audio_host: Option<String>,
+
+    /// The name of the audio output device.
+    pub device_name: String,
+
+    /// The sample format of the audio output, e.g. "f32".
+    pub sample_format: String,
+
+    /// The sample rate of the audio output, in Hz.
+    pub output_sample_rate: u32,
+
+    /// The sample rate the synthesizer renders at, in Hz. The rendered audio
+    /// is resampled if it differs from the output rate.
+    pub render_sample_rate: u32,
+
+    /// The number of audio channels of the output.
+    pub audio_channels: u16,
+
+    /// The buffer size requested from the audio device, in frames, or
+    /// `None` if the device default is used.
+    pub device_buffer_frames: Option<u32>,
+
+    /// The latency between sending an event and hearing it: the render
+    /// window and the render-ahead windows, the resampler, the master
+    /// effects and the requested device buffer.
+    pub latency: Duration,
+
+    /// The scheduling priority requested for the audio threads.
+    pub thread_priority: ThreadPriority,
+
+    /// Whether the audio threads obtained realtime priority. `None` if it
+    /// wasn't requested, `false` if it was refused for any thread that
+    /// started so far. See `RealtimeSynthStatsReader::diagnostics`.
+    pub realtime_priority: Option<bool>,
+}
+
+impl fmt::Display for CapabilityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "SIMD backend: {:?}", self.simd_backend)?;
+        writeln!(
+            f,
+            "Threads: {} channel threads, {} render workers",
+            self.channel_threads, self.worker_threads
+        )?;
+
+        write!(f, "Audio output: ")?;
+        if let Some(host) = &self.audio_host {
+            write!(f, "{host} / ")?;
+        }
+        write!(
+            f,
+            "{}, {}, {} Hz, {} channels",
+            self.device_name, self.sample_format, self.output_sample_rate, self.audio_channels
+        )?;
+        match self.device_buffer_frames {
+            Some(frames) => writeln!(f, ", {frames} frame buffer")?,
+            None => writeln!(f, ", default buffer")?,
+        }
+
+        writeln!(f, "Render sample rate: {} Hz", self.render_sample_rate)?;
+        writeln!(f, "Latency: {:.1} ms", self.latency.as_secs_f64() * 1000.0)?;
+
+        let priority = match self.realtime_priority {
+            None => "normal",
+            Some(true) => "realtime",
+            Some(false) => "realtime requested, but refused",
+        };
+        write!(f, "Thread priority: {priority}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let report = CapabilityReport {
+            simd_backend: SimdBackend::Avx2,
+            channel_threads: 16,
+            worker_threads: 0,
+            audio_host: Some("ALSA".to_string()),
+            device_name: "default".to_string(),
+            sample_format: "f32".to_string(),
+            output_sample_rate: 48000,
+            render_sample_rate: 48000,
+            audio_channels: 2,
+            device_buffer_frames: None,
+            latency: Duration::from_millis(20),
+            thread_priority: ThreadPriority::Realtime,
+            realtime_priority: Some(false),
+        };
+
+        assert_eq!(
+            report.to_string(),
+            "SIMD backend: Avx2\n\
+             Threads: 16 channel threads, 0 render workers\n\
+             Audio output: ALSA / default, f32, 48000 Hz, 2 channels, default buffer\n\
+             Render sample rate: 48000 Hz\n\
+             Latency: 20.0 ms\n\
+             Thread priority: realtime requested, but refused"
+        );
+    }
+}
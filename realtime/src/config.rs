@@ -1,11 +1,18 @@
 use std::ops::RangeInclusive;
+
+use thiserror::Error;
+use xsynth_core::effects::VolumeLimiter;
+
+use crate::{OutputRouting, SynthClock, ThreadLayout, ThreadPriority};
 pub use xsynth_core::{
     channel::ChannelInitOptions,
     channel_group::{SynthFormat, ThreadCount},
+    effects::{AutoGainOptions, EffectsChain, ResamplerQuality},
+    SimdBackend,
 };
 
 /// Options for initializing a new RealtimeSynth.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 #[cfg_attr(
     feature = "serde",
     derive(serde::Deserialize, serde::Serialize),
@@ -16,11 +23,58 @@ pub struct XSynthRealtimeConfig {
     /// See the `ChannelInitOptions` documentation for more information.
     pub channel_init_options: ChannelInitOptions,
 
-    /// The length of the buffer reader in ms.
+    /// The length of the buffer reader in ms. The render thread keeps about
+    /// this much audio rendered ahead of the audio output.
     ///
     /// Default: `10.0`
     pub render_window_ms: f64,
 
+    /// The number of frames rendered per iteration of the render thread.
+    /// Events are applied and the statistics are sampled once per quantum,
+    /// so a small quantum (e.g. `64` or `128`) with a larger render window
+    /// gives better event timing without more underruns. Must not be larger
+    /// than the render window. `None` renders the whole window at once.
+    ///
+    /// Default: `None`
+    pub render_quantum_frames: Option<usize>,
+
+    /// The buffer size requested from the audio output device, in frames.
+    /// It is clamped to the range supported by the device. `None` uses the
+    /// default buffer size of the device.
+    ///
+    /// Default: `None`
+    pub device_buffer_frames: Option<u32>,
+
+    /// The number of render windows to keep pre-rendered ahead of the audio
+    /// output. This helps avoid underruns caused by render time jitter, but
+    /// adds a fixed latency of `render_ahead * render_window_ms`. `0` disables
+    /// render-ahead.
+    ///
+    /// Default: `0`
+    pub render_ahead: usize,
+
+    /// If set to true, the events are rendered at the position within the
+    /// render window at which they arrived, instead of at the start of the
+    /// next rendered buffer. This removes the timing jitter of live input,
+    /// at the cost of delaying the events by up to one render window.
+    ///
+    /// Default: `false`
+    pub precise_event_timing: bool,
+
+    /// The sample rate the synthesizer renders at. If the audio output device
+    /// uses a different rate, the rendered audio is resampled to it. `None`
+    /// renders at the rate of the audio output device.
+    ///
+    /// Default: `None`
+    pub sample_rate: Option<u32>,
+
+    /// The interpolation used when resampling the rendered audio to the rate
+    /// of the audio output device. See the `ResamplerQuality` documentation
+    /// for the available options.
+    ///
+    /// Default: `ResamplerQuality::Polyphase`
+    pub resampler_quality: ResamplerQuality,
+
     /// Defines the format that the synthesizer will use. See the `SynthFormat`
     /// documentation for more information.
     ///
@@ -34,10 +88,100 @@ pub struct XSynthRealtimeConfig {
     /// Default: `ThreadCount::None`
     pub multithreading: ThreadCount,
 
+    /// Pins the channel threads and the per-key render worker threads to
+    /// specific CPU cores. See the `ThreadLayout` documentation for the
+    /// available options.
+    ///
+    /// Default: no pinning
+    pub thread_layout: ThreadLayout,
+
+    /// The scheduling priority requested for the audio threads. See the
+    /// `ThreadPriority` documentation for more information.
+    ///
+    /// Default: `ThreadPriority::Normal`
+    pub thread_priority: ThreadPriority,
+
+    /// If set to true, the audio threads flush denormal floats to zero, as
+    /// long decays in the voices and effects otherwise reach them and spike
+    /// the render time on x86. See `xsynth_core::flush_denormals_on_current_thread`
+    /// for more information.
+    ///
+    /// Default: `true`
+    pub flush_denormals: bool,
+
+    /// If set to true, the synthesizer is warmed up when it is opened, before
+    /// the audio output starts, so the first note doesn't glitch. This adds
+    /// the render time of a few quanta to the opening. See
+    /// `RealtimeSynth::warm_up` for more information.
+    ///
+    /// Default: `false`
+    pub warm_up: bool,
+
     /// A range of velocities that will not be played.
     ///
     /// Default: `0..=0`
     pub ignore_range: RangeInclusive<u8>,
+
+    /// The maximum number of events the event recorder will keep. When full,
+    /// the oldest events are dropped. See the `EventRecorder` documentation
+    /// for more information.
+    ///
+    /// Default: `1000000`
+    pub recording_capacity: usize,
+
+    /// The number of render quanta the statistics history keeps, or `None`
+    /// to disable it. The history records the voice count, render time,
+    /// buffer level and underruns of every rendered quantum. See the
+    /// `StatsHistory` documentation for more information.
+    ///
+    /// Default: `None`
+    pub stats_history: Option<usize>,
+
+    /// Overrides the SIMD instruction set used for rendering. The setting is
+    /// shared by all synthesizers of the process, so `None` keeps the current
+    /// one. See the `SimdBackend` documentation for the available options.
+    ///
+    /// Default: `None`
+    pub simd_backend: Option<SimdBackend>,
+
+    /// Routes the channels to output pairs of an audio device with more than
+    /// 2 channels, instead of mixing them together in the speaker layout of
+    /// the device. Each output pair gets its own volume limiter, and the
+    /// master effects chain isn't applied. Devices with a channel count that
+    /// has no speaker layout (e.g. 8) always use routed output, with all the
+    /// channels on outputs 1/2 by default. Ignored for mono and stereo
+    /// devices. See the `OutputRouting` documentation for more information.
+    ///
+    /// Default: `None`
+    pub output_routing: Option<OutputRouting>,
+
+    /// Divides the mixed output by a function of the active voice count
+    /// before the master effects, to keep the level of dense MIDIs in range
+    /// before the limiter, or `None` to disable it. The applied gain is read
+    /// with `RealtimeSynthStatsReader::auto_gain`. See the `AutoGain`
+    /// documentation in `xsynth_core::effects` for more information.
+    ///
+    /// Default: `None`
+    pub auto_gain: Option<AutoGainOptions>,
+
+    /// The effects applied to the mixed output, in order, at the rate of the
+    /// audio output device. The chain can be replaced at runtime with
+    /// `SynthEvent::SetMasterEffects`. See the `EffectsChain` documentation
+    /// for more information.
+    ///
+    /// Default: the volume limiter
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub master_effects: EffectsChain,
+
+    /// The clock the synthesizer timestamps its events with. Pass the clock
+    /// of another synthesizer (see `RealtimeSynth::clock`) to share its
+    /// timebase, so the times passed to `RealtimeEventSender::send_event_at`
+    /// mean the same for both. See the `SynthClock` documentation for more
+    /// information.
+    ///
+    /// Default: `None` (a new clock)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub clock: Option<SynthClock>,
 }
 
 impl Default for XSynthRealtimeConfig {
@@ -45,9 +189,74 @@ impl Default for XSynthRealtimeConfig {
         Self {
             channel_init_options: Default::default(),
             render_window_ms: 10.0,
+            render_quantum_frames: None,
+            device_buffer_frames: None,
+            render_ahead: 0,
+            precise_event_timing: false,
+            sample_rate: None,
+            resampler_quality: Default::default(),
             format: Default::default(),
             multithreading: ThreadCount::None,
+            thread_layout: Default::default(),
+            thread_priority: Default::default(),
+            flush_denormals: true,
+            warm_up: false,
             ignore_range: 0..=0,
+            recording_capacity: 1_000_000,
+            stats_history: None,
+            simd_backend: None,
+            output_routing: None,
+            auto_gain: None,
+            master_effects: EffectsChain::default().with(VolumeLimiter::default()),
+            clock: None,
         }
     }
 }
+
+impl XSynthRealtimeConfig {
+    /// Checks that the render timing options are consistent for the given
+    /// render sample rate. `RealtimeSynth::open` panics with an invalid
+    /// config.
+    pub fn validate(&self, sample_rate: u32) -> Result<(), ConfigError> {
+        if !self.render_window_ms.is_finite() || self.render_window_ms <= 0.0 {
+            return Err(ConfigError::InvalidRenderWindow(self.render_window_ms));
+        }
+        if self.device_buffer_frames == Some(0) {
+            return Err(ConfigError::ZeroDeviceBuffer);
+        }
+
+        if let Some(quantum) = self.render_quantum_frames {
+            let window = (sample_rate as f64 * self.render_window_ms / 1000.0) as usize;
+            if quantum == 0 {
+                return Err(ConfigError::ZeroRenderQuantum);
+            }
+            if quantum > window {
+                return Err(ConfigError::QuantumLargerThanWindow { quantum, window });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Errors returned by `XSynthRealtimeConfig::validate`.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ConfigError {
+    /// The render window is zero, negative or not finite.
+    #[error("Invalid render window: {0}ms")]
+    InvalidRenderWindow(f64),
+
+    /// The render quantum is zero.
+    #[error("The render quantum can't be zero")]
+    ZeroRenderQuantum,
+
+    /// The render quantum is larger than the render window, both in frames.
+    #[error(
+        "The render quantum ({quantum} frames) is larger than the render window ({window} frames)"
+    )]
+    QuantumLargerThanWindow { quantum: usize, window: usize },
+
+    /// The requested device buffer size is zero.
+    #[error("The device buffer size can't be zero")]
+    ZeroDeviceBuffer,
+}
@@ -0,0 +1,97 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use xsynth_core::channel::UnmatchedNote;
+
+use crate::{ChannelId, SynthThread};
+
+/// The maximum number of diagnostics kept. When more are reported, the
+/// oldest ones are dropped.
+const MAX_DIAGNOSTICS: usize = 256;
+
+/// A non-fatal issue reported by the realtime synthesizer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SynthDiagnostic {
+    /// Elevated scheduling was requested for a thread but the OS refused it.
+    /// The thread keeps running with normal priority.
+    PriorityRequestFailed {
+        /// The thread that could not be elevated.
+        thread: SynthThread,
+
+        /// A description of the error.
+        message: String,
+    },
+
+    /// A channel was routed to an output pair the audio device doesn't
+    /// have. The channel is played on outputs 1/2 instead. See
+    /// `OutputRouting`.
+    InvalidOutputPair {
+        /// The channel that was routed.
+        channel: ChannelId,
+
+        /// The requested output pair.
+        pair: u16,
+
+        /// The number of channels of the audio device used for the pairs.
+        device_channels: u16,
+    },
+
+    /// A note on of a channel matched no region of its soundfonts. Only
+    /// reported with `UnmatchedNotePolicy::Diagnostics` in the channel init
+    /// options, see its documentation.
+    UnmatchedNote {
+        /// The channel that received the note.
+        channel: ChannelId,
+
+        /// The program and the note.
+        note: UnmatchedNote,
+    },
+}
+
+/// Collects the diagnostics reported by the synth threads, in a ring buffer
+/// of the last `MAX_DIAGNOSTICS` ones, so a synthesizer that keeps
+/// reporting them doesn't grow without bounds.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DiagnosticsTracker(Arc<Mutex<VecDeque<SynthDiagnostic>>>);
+
+impl DiagnosticsTracker {
+    pub fn report(&self, diagnostic: SynthDiagnostic) {
+        let mut diagnostics = self.0.lock().unwrap();
+        if diagnostics.len() >= MAX_DIAGNOSTICS {
+            diagnostics.pop_front();
+        }
+        diagnostics.push_back(diagnostic);
+    }
+
+    pub fn diagnostics(&self) -> Vec<SynthDiagnostic> {
+        self.0.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oldest_diagnostics_are_dropped() {
+        let diagnostics = DiagnosticsTracker::default();
+        let invalid_pair = |pair| SynthDiagnostic::InvalidOutputPair {
+            channel: 0,
+            pair,
+            device_channels: 2,
+        };
+        for pair in 0..MAX_DIAGNOSTICS as u16 + 10 {
+            diagnostics.report(invalid_pair(pair));
+        }
+
+        let reported = diagnostics.diagnostics();
+        assert_eq!(reported.len(), MAX_DIAGNOSTICS);
+        assert_eq!(reported[0], invalid_pair(10));
+        assert_eq!(
+            reported.last(),
+            Some(&invalid_pair(MAX_DIAGNOSTICS as u16 + 9))
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use cpal::{BuildStreamError, DefaultStreamConfigError, PlayStreamError, SampleFormat};
+use thiserror::Error;
+use xsynth_core::soundfont::SoundfontLoadError;
+
+use crate::ConfigError;
+
+/// Errors that can be generated when opening the audio output of a
+/// `RealtimeSynth`, see `RealtimeSynth::try_open`. The errors of the audio
+/// backend are kept as the source.
+#[derive(Debug, Error)]
+pub enum AudioStreamError {
+    #[error("No audio output device found")]
+    NoOutputDevice,
+
+    #[error("Failed to get the default output config: {0}")]
+    DefaultConfig(#[from] DefaultStreamConfigError),
+
+    #[error("Unsupported output sample format: {0}")]
+    UnsupportedSampleFormat(SampleFormat),
+
+    #[error("Failed to build the output stream: {0}")]
+    BuildStream(#[from] BuildStreamError),
+
+    #[error("Failed to start the output stream: {0}")]
+    PlayStream(#[from] PlayStreamError),
+
+    #[error("Invalid realtime config: {0}")]
+    Config(#[from] ConfigError),
+
+    #[error("The output channel count can't change from {expected} to {found}")]
+    ChannelCountChanged { expected: u16, found: u16 },
+}
+
+/// Errors returned by `RealtimeSynth::set_sample_rate`.
+#[derive(Debug, Error)]
+pub enum SampleRateError {
+    #[error("Invalid sample rate: {0}")]
+    InvalidSampleRate(u32),
+
+    /// The synthesizer switched to the new rate, but these soundfonts
+    /// couldn't be loaded again for it, so the channels kept them at the
+    /// previous rate.
+    #[error("Failed to convert {} soundfont(s) to the new sample rate", .0.len())]
+    Soundfonts(Vec<SoundfontLoadError>),
+}
@@ -0,0 +1,198 @@
+use std::time::{Duration, Instant};
+
+use xsynth_core::channel::ChannelEvent;
+
+/// The clock used to timestamp the events sent to a realtime synthesizer and
+/// to place them within its render windows, in nanoseconds since the clock
+/// was created.
+///
+/// The clock can be shared with other synthesizer instances through
+/// `XSynthRealtimeConfig::clock`, so the times passed to
+/// `RealtimeEventSender::send_event_at` mean the same for all of them.
+#[derive(Clone, Copy, Debug)]
+pub struct SynthClock {
+    epoch: Instant,
+}
+
+impl SynthClock {
+    /// Creates a new clock starting at the current time.
+    pub fn new() -> Self {
+        SynthClock {
+            epoch: Instant::now(),
+        }
+    }
+
+    /// Returns the current time of the clock, in nanoseconds.
+    pub fn now(&self) -> u64 {
+        self.time_at(Instant::now())
+    }
+
+    /// Returns the time of the clock at the given instant, in nanoseconds.
+    /// Instants before the start of the clock return `0`.
+    pub fn time_at(&self, instant: Instant) -> u64 {
+        instant.saturating_duration_since(self.epoch).as_nanos() as u64
+    }
+
+    /// Returns the instant of the given time of the clock.
+    pub fn instant_at(&self, time: u64) -> Instant {
+        self.epoch + Duration::from_nanos(time)
+    }
+
+    /// Returns the current position of the clock in sample frames at the
+    /// given sample rate.
+    pub fn sample_position(&self, sample_rate: u32) -> u64 {
+        (self.now() as u128 * sample_rate as u128 / 1_000_000_000) as u64
+    }
+
+    /// Returns the time of the clock at the given sample position, in
+    /// nanoseconds.
+    pub fn time_of_sample(&self, position: u64, sample_rate: u32) -> u64 {
+        (position as u128 * 1_000_000_000 / sample_rate as u128) as u64
+    }
+}
+
+impl Default for SynthClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A channel event with the time it was sent at.
+pub(crate) struct TimedEvent {
+    pub time: u64,
+    pub event: ChannelEvent,
+}
+
+/// The events that arrived between the start of the previous render and the
+/// start of the current one are rendered in the current buffer, at an offset
+/// proportional to their arrival time within this window.
+///
+/// This delays the events by up to one render window, but the delay is
+/// constant, so the timing between the events is kept instead of every event
+/// landing on the start of a buffer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct RenderWindow {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl RenderWindow {
+    /// Returns the frame offset of an event that arrived at `time` within a
+    /// buffer of `frames` frames. Events that arrived after the window are
+    /// placed at the end of the buffer.
+    pub fn frame_offset(&self, time: u64, frames: usize) -> usize {
+        if self.end <= self.start {
+            return 0;
+        }
+
+        let elapsed = time.clamp(self.start, self.end) - self.start;
+        (elapsed as u128 * frames as u128 / (self.end - self.start) as u128) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u64 = 48000;
+    const FRAMES: usize = 480;
+    const WINDOW_NANOS: u64 = FRAMES as u64 * 1_000_000_000 / SAMPLE_RATE;
+
+    /// Renders a synthetic stream of events that arrive at irregular times,
+    /// and returns the smallest and largest delay between the arrival of an
+    /// event and the frame it is rendered at, in frames.
+    fn event_latency_range(timed: bool) -> (i64, i64) {
+        // The render thread wakes up with some jitter itself
+        let render_times: Vec<u64> = (0..200u64)
+            .map(|i| i * WINDOW_NANOS + (i * 7919 % 13) * 40_000)
+            .collect();
+
+        let mut range = (i64::MAX, i64::MIN);
+        for i in 0..1000u64 {
+            let time = WINDOW_NANOS + i * 1_700_000 + (i * 104_729 % 1_000_000);
+
+            // The event is rendered in the first buffer rendered after it arrived
+            let Some(buffer) = render_times.iter().position(|&r| r > time) else {
+                break;
+            };
+            let window = RenderWindow {
+                start: render_times[buffer - 1],
+                end: render_times[buffer],
+            };
+            let offset = if timed {
+                window.frame_offset(time, FRAMES)
+            } else {
+                0
+            };
+
+            let rendered_frame = (buffer * FRAMES + offset) as i64;
+            let arrival_frame = (time * SAMPLE_RATE / 1_000_000_000) as i64;
+            let latency = rendered_frame - arrival_frame;
+            range = (range.0.min(latency), range.1.max(latency));
+        }
+
+        range
+    }
+
+    #[test]
+    fn test_timed_event_jitter() {
+        let (min, max) = event_latency_range(false);
+        assert!(max - min > FRAMES as i64 / 2);
+
+        // Only the jitter of the render thread is left
+        let (min, max) = event_latency_range(true);
+        assert!(max - min < FRAMES as i64 / 8);
+        assert!(min >= 0);
+    }
+
+    /// Returns the clock time an event sent at `time` is played at, when
+    /// the synthesizer renders at `render_times` and plays each buffer one
+    /// window after it was rendered.
+    fn play_time(render_times: &[u64], time: u64) -> u64 {
+        let buffer = render_times.iter().position(|&r| r > time).unwrap();
+        let window = RenderWindow {
+            start: render_times[buffer - 1],
+            end: render_times[buffer],
+        };
+        let offset = window.frame_offset(time, FRAMES) as u64;
+        render_times[buffer] + WINDOW_NANOS + offset * 1_000_000_000 / SAMPLE_RATE
+    }
+
+    #[test]
+    fn test_shared_clock() {
+        let clock = SynthClock::new();
+        let position = clock.sample_position(SAMPLE_RATE as u32);
+        let time = clock.time_of_sample(position, SAMPLE_RATE as u32);
+        assert!(clock.now() >= time);
+        assert_eq!(clock.time_at(clock.instant_at(time)), time);
+
+        // Two synthesizers rendering out of phase with each other
+        let first: Vec<u64> = (0..100).map(|i| i * WINDOW_NANOS).collect();
+        let second: Vec<u64> = (0..100).map(|i| i * WINDOW_NANOS + 3_300_000).collect();
+
+        for i in 0..50 {
+            let time = WINDOW_NANOS * 2 + i * 1_234_567;
+
+            // With a shared clock the event plays at the same time on both
+            let shared = play_time(&first, time).abs_diff(play_time(&second, time));
+            assert!(shared < WINDOW_NANOS);
+
+            // With its own clock started later, the second synthesizer
+            // reads the time as later than it was meant
+            let own = play_time(&first, time).abs_diff(play_time(&second, time + 7_000_000));
+            assert!(own > shared);
+        }
+    }
+
+    #[test]
+    fn test_frame_offset() {
+        let window = RenderWindow {
+            start: 1000,
+            end: 2000,
+        };
+        assert_eq!(window.frame_offset(500, 100), 0);
+        assert_eq!(window.frame_offset(1250, 100), 25);
+        assert_eq!(window.frame_offset(3000, 100), 100);
+        assert_eq!(RenderWindow::default().frame_offset(10, 100), 0);
+    }
+}
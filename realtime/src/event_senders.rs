@@ -1,16 +1,27 @@
 use std::{
     collections::VecDeque,
     ops::RangeInclusive,
-    sync::{Arc, RwLock},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
     thread::{self, JoinHandle},
     time::{Duration, Instant},
 };
 
 use crossbeam_channel::Sender;
 
-use xsynth_core::channel::{ChannelAudioEvent, ChannelConfigEvent, ChannelEvent, ControlEvent};
+use xsynth_core::{
+    channel::{ChannelAudioEvent, ChannelConfigEvent, ChannelEvent},
+    channel_group::{midi1_events, ump_events, ChannelId, ChannelRouting},
+    effects::{AudioInsert, EffectsChain, TempoClock},
+};
 
-use crate::{util::ReadWriteAtomicU64, SynthEvent};
+use crate::{
+    event_clock::{SynthClock, TimedEvent},
+    util::ReadWriteAtomicU64,
+    EventRecorder, SynthEvent,
+};
 
 static NPS_WINDOW_MILLISECONDS: u64 = 20;
 
@@ -119,7 +130,7 @@ fn should_send_for_vel_and_nps(vel: u8, nps: u64, max: u64) -> bool {
 }
 
 struct EventSender {
-    sender: Sender<ChannelEvent>,
+    sender: Sender<TimedEvent>,
     nps: RoughNpsTracker,
     max_nps: Arc<ReadWriteAtomicU64>,
     skipped_notes: [u64; 128],
@@ -129,7 +140,7 @@ struct EventSender {
 impl EventSender {
     pub fn new(
         max_nps: Arc<ReadWriteAtomicU64>,
-        sender: Sender<ChannelEvent>,
+        sender: Sender<TimedEvent>,
         ignore_range: RangeInclusive<u8>,
     ) -> Self {
         EventSender {
@@ -141,9 +152,10 @@ impl EventSender {
         }
     }
 
-    pub fn send_audio(&mut self, event: ChannelAudioEvent) {
+    pub fn send_audio(&mut self, event: ChannelAudioEvent, time: u64) {
         match &event {
-            ChannelAudioEvent::NoteOn { vel, key } => {
+            ChannelAudioEvent::NoteOn { vel, key }
+            | ChannelAudioEvent::NoteOnDetuned { vel, key, .. } => {
                 if *key > 127 {
                     return;
                 }
@@ -153,7 +165,7 @@ impl EventSender {
                 if should_send_for_vel_and_nps(*vel, nps, self.max_nps.read())
                     && !self.ignore_range.contains(vel)
                 {
-                    self.sender.send(ChannelEvent::Audio(event)).ok();
+                    self.send(ChannelEvent::Audio(event), time);
                     self.nps.add_note();
                 } else {
                     self.skipped_notes[*key as usize] += 1;
@@ -167,17 +179,23 @@ impl EventSender {
                 if self.skipped_notes[*key as usize] > 0 {
                     self.skipped_notes[*key as usize] -= 1;
                 } else {
-                    self.sender.send(ChannelEvent::Audio(event)).ok();
+                    self.send(ChannelEvent::Audio(event), time);
                 }
             }
             _ => {
-                self.sender.send(ChannelEvent::Audio(event)).ok();
+                self.send(ChannelEvent::Audio(event), time);
             }
         }
     }
 
-    pub fn send_config(&mut self, event: ChannelConfigEvent) {
-        self.sender.send(ChannelEvent::Config(event)).ok();
+    pub fn send_config(&mut self, event: ChannelConfigEvent, time: u64) {
+        self.send(ChannelEvent::Config(event), time);
+    }
+
+    /// Sends an event to the channel, timestamped with the given time of the
+    /// synthesizer's clock.
+    fn send(&self, event: ChannelEvent, time: u64) {
+        self.sender.send(TimedEvent { time, event }).ok();
     }
 
     pub fn set_ignore_range(&mut self, ignore_range: RangeInclusive<u8>) {
@@ -203,111 +221,282 @@ impl Clone for EventSender {
     }
 }
 
+/// The event queues of the channels by ID, shared by an event sender and
+/// its clones, so they all see the channels added and removed at runtime.
+/// Removed channels leave an empty slot.
+#[derive(Default)]
+pub(crate) struct ChannelQueues {
+    queues: RwLock<Vec<Option<Sender<TimedEvent>>>>,
+
+    /// Incremented on every change, so the senders only read the queues
+    /// when they changed
+    version: AtomicU64,
+}
+
+impl ChannelQueues {
+    pub fn new(queues: Vec<Sender<TimedEvent>>) -> Self {
+        ChannelQueues {
+            queues: RwLock::new(queues.into_iter().map(Some).collect()),
+            version: AtomicU64::new(0),
+        }
+    }
+
+    /// Adds the queue of a new channel and returns its ID. The ID of a
+    /// removed channel is reused, otherwise the channel is added after the
+    /// last one.
+    pub fn add(&self, queue: Sender<TimedEvent>) -> ChannelId {
+        let mut queues = self.queues.write().unwrap();
+        let id = match queues.iter().position(|q| q.is_none()) {
+            Some(id) => {
+                queues[id] = Some(queue);
+                id
+            }
+            None => {
+                queues.push(Some(queue));
+                queues.len() - 1
+            }
+        };
+        self.version.fetch_add(1, Ordering::Release);
+        id as ChannelId
+    }
+
+    /// Removes the queue of a channel and returns it, or `None` if there is
+    /// no such channel.
+    pub fn remove(&self, id: ChannelId) -> Option<Sender<TimedEvent>> {
+        let mut queues = self.queues.write().unwrap();
+        let removed = queues.get_mut(id as usize).and_then(Option::take);
+        self.version.fetch_add(1, Ordering::Release);
+        removed
+    }
+
+    /// Returns true if a channel with the given ID exists.
+    pub fn contains(&self, id: ChannelId) -> bool {
+        matches!(self.queues.read().unwrap().get(id as usize), Some(Some(_)))
+    }
+}
+
+/// The senders of the events applied to the audio output of the realtime
+/// synthesizer, rather than to its channels.
+#[derive(Clone)]
+pub(crate) struct OutputSenders {
+    pub master_effects: Sender<EffectsChain>,
+    pub master_effects_latency: Arc<AtomicUsize>,
+    pub output_pairs: Sender<(ChannelId, u16)>,
+    pub tempo: TempoClock,
+}
+
 /// A helper object to send events to the realtime synthesizer.
 #[derive(Clone)]
 pub struct RealtimeEventSender {
-    senders: Vec<EventSender>,
+    senders: Vec<Option<EventSender>>,
+    queues: Arc<ChannelQueues>,
+
+    /// The version of `queues` that `senders` was built from
+    queues_version: u64,
+    max_nps: Arc<ReadWriteAtomicU64>,
+    ignore_range: RangeInclusive<u8>,
+    clock: SynthClock,
+    routing: ChannelRouting,
+    recorder: Arc<EventRecorder>,
+    closed: Arc<AtomicBool>,
+    output: OutputSenders,
 }
 
 impl RealtimeEventSender {
     pub(super) fn new(
-        senders: Vec<Sender<ChannelEvent>>,
+        queues: Arc<ChannelQueues>,
+        clock: SynthClock,
         max_nps: Arc<ReadWriteAtomicU64>,
         ignore_range: RangeInclusive<u8>,
+        recorder: Arc<EventRecorder>,
+        output: OutputSenders,
     ) -> RealtimeEventSender {
-        RealtimeEventSender {
-            senders: senders
-                .into_iter()
-                .map(|s| EventSender::new(max_nps.clone(), s, ignore_range.clone()))
-                .collect(),
+        let mut sender = RealtimeEventSender {
+            senders: Vec::new(),
+            queues,
+            queues_version: 0,
+            max_nps,
+            ignore_range,
+            clock,
+            routing: ChannelRouting::new(),
+            recorder,
+            closed: Arc::new(AtomicBool::new(false)),
+            output,
+        };
+        sender.rebuild_senders();
+        sender
+    }
+
+    /// Updates the senders of the channels if channels were added or
+    /// removed since they were built.
+    fn sync_senders(&mut self) {
+        if self.queues.version.load(Ordering::Acquire) != self.queues_version {
+            self.rebuild_senders();
         }
     }
 
+    /// Builds the senders of the channels from the shared queues, keeping
+    /// the senders, and their note limiting state, of unchanged channels.
+    fn rebuild_senders(&mut self) {
+        let queues = self.queues.queues.read().unwrap();
+        self.queues_version = self.queues.version.load(Ordering::Acquire);
+
+        let mut senders = std::mem::take(&mut self.senders);
+        senders.resize_with(queues.len(), || None);
+        for (sender, queue) in senders.iter_mut().zip(queues.iter()) {
+            let unchanged = match (&*sender, queue) {
+                (Some(sender), Some(queue)) => sender.sender.same_channel(queue),
+                _ => false,
+            };
+            if !unchanged {
+                *sender = queue.as_ref().map(|queue| {
+                    EventSender::new(
+                        self.max_nps.clone(),
+                        queue.clone(),
+                        self.ignore_range.clone(),
+                    )
+                });
+            }
+        }
+        self.senders = senders;
+    }
+
+    /// Returns the number of channel IDs of the realtime synthesizer, which
+    /// is one more than the highest ID. Includes the IDs of removed
+    /// channels, see `RealtimeSynth::remove_channel`.
+    pub fn channel_count(&self) -> u32 {
+        self.queues.queues.read().unwrap().len() as u32
+    }
+
+    /// Returns the latency of the last master effects chain sent to the
+    /// realtime synthesizer, in frames at the rate of the audio output.
+    pub fn master_effects_latency(&self) -> usize {
+        self.output.master_effects_latency.load(Ordering::Relaxed)
+    }
+
+    /// Returns the tempo clock of the realtime synthesizer, set with
+    /// `SynthEvent::SetTempo`.
+    pub fn tempo_clock(&self) -> TempoClock {
+        self.output.tempo.clone()
+    }
+
+    /// Returns true if the realtime synthesizer is shutting down and no longer
+    /// accepts events. Events sent after this will be ignored.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    /// Stops accepting events on this sender and all of its clones.
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the clock the events are timestamped with. See the
+    /// `SynthClock` documentation for more information.
+    pub fn clock(&self) -> SynthClock {
+        self.clock
+    }
+
     /// Sends a SynthEvent to the realtime synthesizer.
     ///
     /// See the `SynthEvent` documentation for more information.
     pub fn send_event(&mut self, event: SynthEvent) {
+        let time = self.clock.now();
+        self.send_event_at(event, time);
+    }
+
+    /// Sends a SynthEvent to the realtime synthesizer, timestamped with the
+    /// given time of its clock instead of the current time, e.g. to send the
+    /// same event to several synthesizers that share a clock.
+    ///
+    /// With `XSynthRealtimeConfig::precise_event_timing`, the event is
+    /// placed at the offset of its time within the next render window.
+    /// Times before the window are placed at its start and times after it at
+    /// its end, so the time should be close to the current time of the
+    /// clock. See `SynthClock::now`.
+    pub fn send_event_at(&mut self, event: SynthEvent, time: u64) {
+        if self.is_closed() {
+            return;
+        }
+        self.send_event_unchecked_at(event, time);
+    }
+
+    /// Sends an event even if the sender is closed.
+    pub(crate) fn send_event_unchecked(&mut self, event: SynthEvent) {
+        let time = self.clock.now();
+        self.send_event_unchecked_at(event, time);
+    }
+
+    fn send_event_unchecked_at(&mut self, event: SynthEvent, time: u64) {
+        self.sync_senders();
+        self.recorder.record(&event);
+        #[cfg(feature = "tracing")]
+        xsynth_core::trace::route(&event);
+
         match event {
-            SynthEvent::Channel(channel, event) => match event {
-                ChannelEvent::Audio(e) => self.senders[channel as usize].send_audio(e),
-                ChannelEvent::Config(e) => self.senders[channel as usize].send_config(e),
-            },
+            SynthEvent::Channel(channel, event) => {
+                if self.routing.is_passthrough() {
+                    Self::send_channel_event(&mut self.senders, channel, event, time);
+                    return;
+                }
+
+                for target in self.routing.targets(channel, &event) {
+                    Self::send_channel_event(&mut self.senders, target, event.clone(), time);
+                }
+            }
             SynthEvent::AllChannels(event) => match event {
                 ChannelEvent::Audio(e) => {
-                    for sender in self.senders.iter_mut() {
-                        sender.send_audio(e);
+                    for sender in self.senders.iter_mut().flatten() {
+                        sender.send_audio(e, time);
                     }
                 }
                 ChannelEvent::Config(e) => {
-                    for sender in self.senders.iter_mut() {
-                        sender.send_config(e.clone());
+                    for sender in self.senders.iter_mut().flatten() {
+                        sender.send_config(e.clone(), time);
                     }
                 }
             },
+            SynthEvent::SetMasterEffects(chain) => {
+                self.output
+                    .master_effects_latency
+                    .store(chain.latency_frames(), Ordering::Relaxed);
+                self.output.master_effects.send(chain).ok();
+            }
+            SynthEvent::SetOutputPair(channel, pair) => {
+                self.output.output_pairs.send((channel, pair)).ok();
+            }
+            SynthEvent::SetTempo(bpm) => self.output.tempo.set_bpm(bpm),
+            SynthEvent::Ump(packet) => {
+                // Recorded as the channel events it's translated to
+                for (channel, event) in ump_events(packet) {
+                    let event = SynthEvent::Channel(channel, ChannelEvent::Audio(event));
+                    self.send_event_unchecked_at(event, time);
+                }
+            }
         }
     }
 
-    /// Sends a MIDI event as raw bytes.
-    pub fn send_event_u32(&mut self, event: u32) {
-        let head = event & 0xFF;
-        let channel = head & 0xF;
-        let code = head >> 4;
-
-        macro_rules! val1 {
-            () => {
-                (event >> 8) as u8
-            };
-        }
-
-        macro_rules! val2 {
-            () => {
-                (event >> 16) as u8
-            };
+    fn send_channel_event(
+        senders: &mut [Option<EventSender>],
+        channel: ChannelId,
+        event: ChannelEvent,
+        time: u64,
+    ) {
+        // Events for channels that don't exist are ignored
+        let Some(Some(sender)) = senders.get_mut(channel as usize) else {
+            return;
+        };
+        match event {
+            ChannelEvent::Audio(e) => sender.send_audio(e, time),
+            ChannelEvent::Config(e) => sender.send_config(e, time),
         }
+    }
 
-        match code {
-            0x8 => {
-                self.send_event(SynthEvent::Channel(
-                    channel,
-                    ChannelEvent::Audio(ChannelAudioEvent::NoteOff { key: val1!() }),
-                ));
-            }
-            0x9 => {
-                self.send_event(SynthEvent::Channel(
-                    channel,
-                    ChannelEvent::Audio(ChannelAudioEvent::NoteOn {
-                        key: val1!(),
-                        vel: val2!(),
-                    }),
-                ));
-            }
-            0xB => {
-                self.send_event(SynthEvent::Channel(
-                    channel,
-                    ChannelEvent::Audio(ChannelAudioEvent::Control(ControlEvent::Raw(
-                        val1!(),
-                        val2!(),
-                    ))),
-                ));
-            }
-            0xC => {
-                self.send_event(SynthEvent::Channel(
-                    channel,
-                    ChannelEvent::Audio(ChannelAudioEvent::ProgramChange(val1!())),
-                ));
-            }
-            0xE => {
-                let value = (((val2!() as i16) << 7) | val1!() as i16) - 8192;
-                let value = value as f32 / 8192.0;
-                self.send_event(SynthEvent::Channel(
-                    channel,
-                    ChannelEvent::Audio(ChannelAudioEvent::Control(ControlEvent::PitchBendValue(
-                        value,
-                    ))),
-                ));
-            }
-
-            _ => {}
+    /// Sends a MIDI 1.0 event as raw bytes. See `midi1_events` for the
+    /// translated messages.
+    pub fn send_event_u32(&mut self, event: u32) {
+        for (channel, event) in midi1_events(event) {
+            self.send_event(SynthEvent::Channel(channel, ChannelEvent::Audio(event)));
         }
     }
 
@@ -317,7 +506,7 @@ impl RealtimeEventSender {
             ChannelAudioEvent::AllNotesKilled,
         )));
 
-        for sender in &mut self.senders {
+        for sender in self.senders.iter_mut().flatten() {
             for i in 0..128 {
                 sender.skipped_notes[i] = 0;
             }
@@ -328,11 +517,94 @@ impl RealtimeEventSender {
         )));
     }
 
+    /// Sets the matrix that remaps the events sent to single channels, for
+    /// the specific sender instance. Recorded events are kept as they were
+    /// sent, before the remapping.
+    ///
+    /// See the `ChannelRouting` documentation for more information.
+    pub fn set_routing(&mut self, routing: ChannelRouting) {
+        self.routing = routing;
+    }
+
+    /// Returns the channel routing matrix of the specific sender instance.
+    pub fn routing(&self) -> &ChannelRouting {
+        &self.routing
+    }
+
     /// Changes the range of velocities that will be ignored for the
     /// specific sender instance.
     pub fn set_ignore_range(&mut self, ignore_range: RangeInclusive<u8>) {
-        for sender in self.senders.iter_mut() {
+        for sender in self.senders.iter_mut().flatten() {
             sender.set_ignore_range(ignore_range.clone());
         }
+        self.ignore_range = ignore_range;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU64;
+
+    use crossbeam_channel::{unbounded, Receiver};
+
+    use super::*;
+
+    #[test]
+    fn test_dynamic_channels() {
+        let queues = Arc::new(ChannelQueues::new(Vec::new()));
+        let recorder = Arc::new(EventRecorder::new(Arc::new(AtomicU64::new(0)), 48000, 0, 0));
+        let (master_effects, _) = unbounded();
+        let (output_pairs, _) = unbounded();
+        let mut sender = RealtimeEventSender::new(
+            queues.clone(),
+            SynthClock::default(),
+            Arc::new(ReadWriteAtomicU64::new(10000)),
+            0..=0,
+            recorder,
+            OutputSenders {
+                master_effects,
+                master_effects_latency: Default::default(),
+                output_pairs,
+                tempo: Default::default(),
+            },
+        );
+        let mut clone = sender.clone();
+
+        let note_on = |key| ChannelEvent::Audio(ChannelAudioEvent::NoteOn { key, vel: 100 });
+        let received = |receiver: &Receiver<TimedEvent>| receiver.try_iter().count();
+
+        let mut receivers = Vec::new();
+        for _ in 0..100 {
+            let (queue, receiver) = unbounded();
+            receivers.push((queues.add(queue), receiver));
+        }
+        assert_eq!(clone.channel_count(), 100);
+
+        // Senders cloned before the channels were added see them too
+        for (id, receiver) in receivers.iter() {
+            clone.send_event(SynthEvent::Channel(*id, note_on(60)));
+            assert_eq!(received(receiver), 1);
+        }
+        sender.send_event(SynthEvent::AllChannels(note_on(62)));
+        assert!(receivers
+            .iter()
+            .all(|(_, receiver)| received(receiver) == 1));
+
+        // Removed channels no longer receive events, and their IDs are
+        // reused
+        for (id, _) in receivers.iter().step_by(2) {
+            assert!(queues.remove(*id).is_some());
+            assert!(queues.remove(*id).is_none());
+        }
+        clone.send_event(SynthEvent::AllChannels(note_on(64)));
+        for (i, (_, receiver)) in receivers.iter().enumerate() {
+            assert_eq!(received(receiver), i % 2);
+        }
+
+        let (queue, receiver) = unbounded();
+        assert_eq!(queues.add(queue), 0);
+        sender.send_event(SynthEvent::Channel(0, note_on(60)));
+        assert_eq!(received(&receiver), 1);
+        assert_eq!(received(&receivers[0].1), 0);
     }
 }
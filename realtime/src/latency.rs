@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+/// A latency, in frames at the sample rate of the audio output and in
+/// milliseconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Latency {
+    /// The latency in frames (samples per channel).
+    pub frames: u64,
+
+    /// The latency in milliseconds.
+    pub milliseconds: f64,
+}
+
+impl Latency {
+    fn from_frames(frames: u64, sample_rate: u32) -> Self {
+        Latency {
+            frames,
+            milliseconds: frames as f64 * 1000.0 / sample_rate as f64,
+        }
+    }
+
+    /// Returns the latency as a `Duration`.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.milliseconds / 1000.0)
+    }
+}
+
+/// An estimate of the latency between sending an event to a realtime
+/// synthesizer and hearing it, by the stage of the output path that adds
+/// it. The frames of every stage are counted at the output sample rate.
+///
+/// See `RealtimeSynth::latency`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct LatencyBreakdown {
+    /// The sample rate of the audio output, in Hz.
+    pub sample_rate: u32,
+
+    /// The buffer of the audio output device. Uses the buffer size the
+    /// device requested in its last callback, or the requested buffer size
+    /// before the first one. Zero if neither is known yet.
+    pub device_buffer: Latency,
+
+    /// The render window and the render-ahead windows kept rendered ahead of
+    /// the audio output.
+    pub render_buffer: Latency,
+
+    /// Resampling the rendered audio to the output rate. Zero when the
+    /// rates match.
+    pub resampler: Latency,
+
+    /// The lookahead of the master effects, such as a limiter.
+    pub master_effects: Latency,
+
+    /// The sum of all stages.
+    pub total: Latency,
+}
+
+impl LatencyBreakdown {
+    /// Creates a breakdown from the latency of each stage, in frames at the
+    /// output sample rate.
+    pub(crate) fn new(
+        sample_rate: u32,
+        device_buffer: u64,
+        render_buffer: u64,
+        resampler: u64,
+        master_effects: u64,
+    ) -> Self {
+        let latency = |frames| Latency::from_frames(frames, sample_rate);
+        LatencyBreakdown {
+            sample_rate,
+            device_buffer: latency(device_buffer),
+            render_buffer: latency(render_buffer),
+            resampler: latency(resampler),
+            master_effects: latency(master_effects),
+            total: latency(device_buffer + render_buffer + resampler + master_effects),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_total() {
+        let latency = LatencyBreakdown::new(48000, 256, 960, 32, 240);
+        assert_eq!(latency.total.frames, 1488);
+        assert_eq!(latency.total.milliseconds, 31.0);
+        assert_eq!(latency.render_buffer.milliseconds, 20.0);
+        assert_eq!(latency.total.duration(), Duration::from_millis(31));
+    }
+}
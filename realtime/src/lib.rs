@@ -1,12 +1,52 @@
+pub mod prelude;
+
 mod config;
 pub use config::*;
 
+mod error;
+pub use error::{AudioStreamError, SampleRateError};
+
 mod util;
 
-pub use xsynth_core::channel_group::SynthEvent;
+mod event_clock;
+pub use event_clock::SynthClock;
+
+mod affinity;
+pub use affinity::*;
+
+mod capability;
+pub use capability::CapabilityReport;
+
+mod priority;
+pub use priority::*;
+
+mod diagnostics;
+pub use diagnostics::SynthDiagnostic;
+
+pub use xsynth_core::channel_group::{ChannelId, SynthEvent};
 
 mod realtime_synth;
 pub use realtime_synth::*;
 
+mod render_timing;
+pub use render_timing::RenderTiming;
+
+mod latency;
+pub use latency::*;
+
+mod output_routing;
+pub use output_routing::OutputRouting;
+
 mod event_senders;
 pub use event_senders::*;
+
+mod recorder;
+pub use recorder::*;
+
+mod stats_history;
+pub use stats_history::*;
+
+#[cfg(feature = "player")]
+mod player;
+#[cfg(feature = "player")]
+pub use player::*;
@@ -0,0 +1,174 @@
+use xsynth_core::{channel_group::ChannelId, effects::VolumeLimiter};
+
+use crate::{diagnostics::DiagnosticsTracker, SynthDiagnostic};
+
+/// Maps the channels of the realtime synthesizer to output pairs of an audio
+/// device with more than 2 channels, e.g. to play the drums on outputs 3/4
+/// and the piano on outputs 1/2 of a multi-output interface.
+///
+/// Output pairs are numbered from `0`, which is outputs 1/2, `1` being
+/// outputs 3/4 and so on. Channels without a route play on outputs 1/2.
+/// Each output pair has its own volume limiter.
+///
+/// Routes can be changed at runtime with `SynthEvent::SetOutputPair`. See
+/// `XSynthRealtimeConfig::output_routing`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(transparent)
+)]
+pub struct OutputRouting {
+    pairs: Vec<u16>,
+}
+
+impl OutputRouting {
+    /// Creates a routing that plays all the channels on outputs 1/2.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Routes the given channel to an output pair.
+    pub fn with_route(mut self, channel: ChannelId, pair: u16) -> Self {
+        self.set_route(channel, pair);
+        self
+    }
+
+    /// Routes the given channel to an output pair.
+    pub fn set_route(&mut self, channel: ChannelId, pair: u16) {
+        let channel = channel as usize;
+        if self.pairs.len() <= channel {
+            self.pairs.resize(channel + 1, 0);
+        }
+        self.pairs[channel] = pair;
+    }
+
+    /// Returns the output pair of the given channel.
+    pub fn pair(&self, channel: ChannelId) -> u16 {
+        self.pairs.get(channel as usize).copied().unwrap_or(0)
+    }
+
+    /// Returns the number of output pairs of a device with the given number
+    /// of channels. An odd last channel is left silent.
+    pub fn pair_count(device_channels: u16) -> u16 {
+        (device_channels / 2).max(1)
+    }
+
+    /// Routes a channel to an output pair of a device with the given number
+    /// of pairs. Pairs the device doesn't have are reported as a diagnostic,
+    /// and the channel is played on outputs 1/2 instead.
+    pub(crate) fn set_checked_route(
+        &mut self,
+        channel: ChannelId,
+        pair: u16,
+        pair_count: u16,
+        diagnostics: &DiagnosticsTracker,
+    ) {
+        if pair < pair_count {
+            self.set_route(channel, pair);
+        } else {
+            diagnostics.report(SynthDiagnostic::InvalidOutputPair {
+                channel,
+                pair,
+                device_channels: pair_count * 2,
+            });
+            self.set_route(channel, 0);
+        }
+    }
+
+    /// Checks the routes against a device with the given number of pairs,
+    /// see `set_checked_route`.
+    pub(crate) fn check(&mut self, pair_count: u16, diagnostics: &DiagnosticsTracker) {
+        let pairs = std::mem::take(&mut self.pairs);
+        for (channel, pair) in pairs.into_iter().enumerate() {
+            self.set_checked_route(channel as ChannelId, pair, pair_count, diagnostics);
+        }
+    }
+}
+
+/// Adds the stereo audio of a channel to an output pair of the rendered
+/// mixes, which are interleaved with `pair_count * 2` channels.
+pub(crate) fn mix_into_pair(channel: &[f32], mixes: &mut [f32], pair: u16, pair_count: u16) {
+    let width = pair_count as usize * 2;
+    let offset = pair as usize * 2;
+    for (frame, mix) in channel.chunks_exact(2).zip(mixes.chunks_exact_mut(width)) {
+        mix[offset] += frame[0];
+        mix[offset + 1] += frame[1];
+    }
+}
+
+/// Applies the limiter of each output pair to the rendered mixes, and
+/// interleaves them into the output of a device with the given number of
+/// channels. The device channels without a pair are left silent.
+pub(crate) fn interleave_pairs(
+    mixes: &[f32],
+    limiters: &mut [VolumeLimiter],
+    pair_buffer: &mut Vec<f32>,
+    output: &mut [f32],
+    device_channels: usize,
+) {
+    let width = limiters.len() * 2;
+    let frames = output.len() / device_channels;
+    output.fill(0.0);
+
+    for (pair, limiter) in limiters.iter_mut().enumerate() {
+        let offset = pair * 2;
+        pair_buffer.clear();
+        for frame in mixes.chunks_exact(width).take(frames) {
+            pair_buffer.extend_from_slice(&frame[offset..offset + 2]);
+        }
+        limiter.limit(pair_buffer);
+
+        let outputs = output.chunks_exact_mut(device_channels);
+        for (out, frame) in outputs.zip(pair_buffer.chunks_exact(2)) {
+            out[offset..offset + 2].copy_from_slice(frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_pairs_fall_back() {
+        let diagnostics = DiagnosticsTracker::default();
+        let mut routing = OutputRouting::new().with_route(9, 1).with_route(2, 4);
+        routing.check(OutputRouting::pair_count(8), &diagnostics);
+        assert_eq!(
+            (routing.pair(9), routing.pair(2), routing.pair(0)),
+            (1, 0, 0)
+        );
+
+        routing.set_checked_route(9, 3, 4, &diagnostics);
+        assert_eq!(routing.pair(9), 3);
+        assert_eq!(
+            diagnostics.diagnostics(),
+            [SynthDiagnostic::InvalidOutputPair {
+                channel: 2,
+                pair: 4,
+                device_channels: 8,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_interleave_pairs() {
+        // Three channels on the two pairs of a 5 channel device, the last
+        // device channel is silent
+        let mut mixes = vec![0.0; 3 * 4];
+        mix_into_pair(&[0.1, 0.2, 0.1, 0.2, 0.1, 0.2], &mut mixes, 1, 2);
+        mix_into_pair(&[0.3, 0.4, 0.3, 0.4, 0.3, 0.4], &mut mixes, 1, 2);
+        mix_into_pair(&[0.5, 0.6, 0.5, 0.6, 0.5, 0.6], &mut mixes, 0, 2);
+
+        let mut limiters = vec![VolumeLimiter::new(2), VolumeLimiter::new(2)];
+        let mut output = vec![1.0; 3 * 5];
+        interleave_pairs(&mixes, &mut limiters, &mut Vec::new(), &mut output, 5);
+        for frame in output.chunks_exact(5) {
+            let expected = [0.5, 0.6, 0.4, 0.6, 0.0];
+            for (sample, expected) in frame.iter().zip(expected) {
+                assert!((sample - expected).abs() < 1e-6);
+            }
+        }
+    }
+}
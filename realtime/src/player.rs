@@ -0,0 +1,350 @@
+use std::{
+    fmt,
+    ops::Range,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use midi_toolkit::{
+    events::Event,
+    io::MIDIFile,
+    pipe,
+    sequence::{
+        event::{cancel_tempo_events, scale_event_time},
+        TimeCaster,
+    },
+};
+use xsynth_core::{
+    channel::{ChannelAudioEvent, ChannelEvent, ControlEvent},
+    channel_group::EventChaser,
+};
+
+use crate::{RealtimeEventSender, SynthEvent};
+
+/// How often the player thread checks for new events to send.
+const PLAYER_TICK: Duration = Duration::from_millis(1);
+
+/// Errors that can be generated when loading a MIDI file in the `MidiPlayer`.
+#[derive(Debug)]
+pub enum MidiPlayerError {
+    /// The file could not be opened or is not a valid MIDI file.
+    Load(String),
+
+    /// The MIDI events could not be parsed.
+    Parse(String),
+}
+
+impl fmt::Display for MidiPlayerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiPlayerError::Load(e) => write!(f, "Error loading the MIDI file: {e}"),
+            MidiPlayerError::Parse(e) => write!(f, "Error parsing the MIDI file: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for MidiPlayerError {}
+
+#[derive(Clone, Copy)]
+struct TimedEvent {
+    time: f64,
+    channel: u32,
+    event: ChannelAudioEvent,
+}
+
+struct PlayerState {
+    playing: bool,
+    position: f64,
+    next_event: usize,
+    last_tick: Instant,
+    tempo_scale: f64,
+    loop_range: Option<Range<f64>>,
+    seek_to: Option<f64>,
+    on_end: Option<Box<dyn FnMut() + Send>>,
+}
+
+struct PlayerShared {
+    state: Mutex<PlayerState>,
+    killed: AtomicBool,
+    length: f64,
+}
+
+/// A MIDI file player that feeds a realtime synthesizer through its event
+/// sender.
+///
+/// The whole file is parsed when loaded and played back from a separate
+/// thread, with tempo changes already applied. When seeking, all playing
+/// notes are released and the program, controller and pitch bend state of
+/// the target position is restored before playback continues.
+///
+/// Requires the `player` feature.
+pub struct MidiPlayer {
+    shared: Arc<PlayerShared>,
+    thread_handle: Option<JoinHandle<()>>,
+}
+
+impl MidiPlayer {
+    /// Loads a MIDI file and prepares it for playback. The player starts
+    /// paused at the beginning of the file.
+    ///
+    /// - `path`: The path of the MIDI file to be loaded.
+    /// - `sender`: The event sender of the realtime synthesizer to feed.
+    ///   It can be obtained by cloning the one returned from
+    ///   `RealtimeSynth::get_sender_ref`.
+    pub fn open(
+        path: impl AsRef<Path>,
+        sender: RealtimeEventSender,
+    ) -> Result<Self, MidiPlayerError> {
+        let events = load_events(path.as_ref())?;
+        let length = events.last().map(|e| e.time).unwrap_or(0.0);
+
+        let shared = Arc::new(PlayerShared {
+            state: Mutex::new(PlayerState {
+                playing: false,
+                position: 0.0,
+                next_event: 0,
+                last_tick: Instant::now(),
+                tempo_scale: 1.0,
+                loop_range: None,
+                seek_to: None,
+                on_end: None,
+            }),
+            killed: AtomicBool::new(false),
+            length,
+        });
+
+        let thread_handle = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name("xsynth_midi_player".to_string())
+                .spawn(move || run_player(shared, events, sender))
+                .unwrap()
+        };
+
+        Ok(MidiPlayer {
+            shared,
+            thread_handle: Some(thread_handle),
+        })
+    }
+
+    /// Starts or resumes the playback.
+    pub fn play(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.playing = true;
+        state.last_tick = Instant::now();
+    }
+
+    /// Pauses the playback. Notes that are already playing will keep playing
+    /// until the playback is resumed.
+    pub fn pause(&self) {
+        self.shared.state.lock().unwrap().playing = false;
+    }
+
+    /// Stops the playback, releases all notes and goes back to the start.
+    pub fn stop(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.playing = false;
+        state.seek_to = Some(0.0);
+    }
+
+    /// Moves the playback to the given position, in seconds.
+    pub fn seek(&self, seconds: f64) {
+        let seconds = seconds.clamp(0.0, self.shared.length);
+        self.shared.state.lock().unwrap().seek_to = Some(seconds);
+    }
+
+    /// Returns true if the player is currently playing.
+    pub fn is_playing(&self) -> bool {
+        self.shared.state.lock().unwrap().playing
+    }
+
+    /// Returns the current playback position, in seconds.
+    pub fn position(&self) -> f64 {
+        let state = self.shared.state.lock().unwrap();
+        state.seek_to.unwrap_or(state.position)
+    }
+
+    /// Returns the length of the loaded MIDI file, in seconds.
+    pub fn length(&self) -> f64 {
+        self.shared.length
+    }
+
+    /// Sets the playback speed multiplier. For example `2.0` plays the file
+    /// twice as fast. Values of zero or below are ignored.
+    pub fn set_tempo_scale(&self, scale: f64) {
+        if scale > 0.0 {
+            self.shared.state.lock().unwrap().tempo_scale = scale;
+        }
+    }
+
+    /// Sets a range of the file, in seconds, to be repeated. `None` disables
+    /// looping.
+    pub fn set_loop(&self, range: Option<Range<f64>>) {
+        self.shared.state.lock().unwrap().loop_range = range.filter(|r| r.start < r.end);
+    }
+
+    /// Sets a callback that will be called from the player thread when the
+    /// playback reaches the end of the file.
+    pub fn set_end_callback(&self, callback: impl FnMut() + Send + 'static) {
+        self.shared.state.lock().unwrap().on_end = Some(Box::new(callback));
+    }
+}
+
+impl Drop for MidiPlayer {
+    fn drop(&mut self) {
+        self.shared.killed.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.thread_handle.take() {
+            handle.join().ok();
+        }
+    }
+}
+
+fn load_events(path: &Path) -> Result<Vec<TimedEvent>, MidiPlayerError> {
+    let midi = MIDIFile::open(path, None).map_err(|e| MidiPlayerError::Load(format!("{e:?}")))?;
+
+    let ppq = midi.ppq();
+    let merged = pipe!(
+        midi.iter_all_events_merged_batches()
+        |>TimeCaster::<f64>::cast_event_delta()
+        |>cancel_tempo_events(250000)
+        |>scale_event_time(1.0 / ppq as f64)
+    );
+
+    let mut events = Vec::new();
+    let mut time = 0.0;
+    for batch in merged {
+        let batch = batch.map_err(|e| MidiPlayerError::Parse(format!("{e:?}")))?;
+        time += batch.delta;
+
+        for e in batch.iter_inner() {
+            let (channel, event) = match e {
+                Event::NoteOn(e) => (
+                    e.channel,
+                    ChannelAudioEvent::NoteOn {
+                        key: e.key,
+                        vel: e.velocity,
+                    },
+                ),
+                Event::NoteOff(e) => (e.channel, ChannelAudioEvent::NoteOff { key: e.key }),
+                Event::ControlChange(e) => (
+                    e.channel,
+                    ChannelAudioEvent::Control(ControlEvent::Raw(e.controller, e.value)),
+                ),
+                Event::PitchWheelChange(e) => (
+                    e.channel,
+                    ChannelAudioEvent::Control(ControlEvent::PitchBendValue(
+                        e.pitch as f32 / 8192.0,
+                    )),
+                ),
+                Event::ProgramChange(e) => (e.channel, ChannelAudioEvent::ProgramChange(e.program)),
+                _ => continue,
+            };
+
+            events.push(TimedEvent {
+                time,
+                channel: channel as u32,
+                event,
+            });
+        }
+    }
+
+    Ok(events)
+}
+
+/// Releases all notes and restores the program, controller and pitch bend
+/// state of the given position. Returns the index of the next event to play.
+fn seek_to(sender: &mut RealtimeEventSender, events: &[TimedEvent], position: f64) -> usize {
+    let reset = [
+        ChannelAudioEvent::AllNotesOff,
+        ChannelAudioEvent::ResetControl,
+        ChannelAudioEvent::Control(ControlEvent::Raw(0x00, 0)),
+        ChannelAudioEvent::ProgramChange(0),
+    ];
+    for event in reset {
+        sender.send_event(SynthEvent::AllChannels(ChannelEvent::Audio(event)));
+    }
+
+    let next_event = events.partition_point(|e| e.time < position);
+
+    let mut chaser = EventChaser::new(sender.channel_count());
+    for e in events[..next_event].iter() {
+        chaser.push(&SynthEvent::Channel(
+            e.channel,
+            ChannelEvent::Audio(e.event),
+        ));
+    }
+    for event in chaser.events() {
+        sender.send_event(event);
+    }
+
+    next_event
+}
+
+fn run_player(shared: Arc<PlayerShared>, events: Vec<TimedEvent>, mut sender: RealtimeEventSender) {
+    while !shared.killed.load(Ordering::Relaxed) {
+        let mut ended = false;
+
+        {
+            let mut state = shared.state.lock().unwrap();
+
+            if let Some(position) = state.seek_to.take() {
+                state.next_event = seek_to(&mut sender, &events, position);
+                state.position = position;
+                state.last_tick = Instant::now();
+            }
+
+            if state.playing {
+                let now = Instant::now();
+                let elapsed = now.duration_since(state.last_tick).as_secs_f64();
+                state.last_tick = now;
+                state.position += elapsed * state.tempo_scale;
+
+                while let Some(e) = events.get(state.next_event) {
+                    if e.time > state.position {
+                        break;
+                    }
+                    if let Some(range) = &state.loop_range {
+                        if e.time >= range.end {
+                            break;
+                        }
+                    }
+                    sender.send_event(SynthEvent::Channel(e.channel, ChannelEvent::Audio(e.event)));
+                    state.next_event += 1;
+                }
+
+                if let Some(range) = state.loop_range.clone() {
+                    if state.position >= range.end {
+                        state.next_event = seek_to(&mut sender, &events, range.start);
+                        state.position = range.start;
+                    }
+                } else if state.next_event >= events.len() {
+                    state.playing = false;
+                    ended = true;
+                }
+            }
+        }
+
+        if ended {
+            // Call the callback without holding the lock, so it can use the player
+            let callback = shared.state.lock().unwrap().on_end.take();
+            if let Some(mut callback) = callback {
+                callback();
+                let mut state = shared.state.lock().unwrap();
+                if state.on_end.is_none() {
+                    state.on_end = Some(callback);
+                }
+            }
+        }
+
+        spin_sleep::sleep(PLAYER_TICK);
+    }
+
+    sender.send_event(SynthEvent::AllChannels(ChannelEvent::Audio(
+        ChannelAudioEvent::AllNotesOff,
+    )));
+}
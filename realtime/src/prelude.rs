@@ -0,0 +1,17 @@
+//! Re-exports the types needed to play MIDI with the realtime synthesizer,
+//! including the ones of `xsynth_core::prelude`, so embedders can import
+//! them at once:
+//!
+//! ```
+//! use xsynth_realtime::prelude::*;
+//! ```
+
+pub use xsynth_core::prelude::*;
+
+#[cfg(feature = "player")]
+pub use crate::MidiPlayer;
+pub use crate::{
+    AudioStreamError, ConfigError, LatencyBreakdown, OutputRouting, RealtimeEventSender,
+    RealtimeSynth, RealtimeSynthStatsReader, RenderLoadBreakdown, SynthClock, SynthDiagnostic,
+    ThreadLayout, ThreadPriority, XSynthRealtimeConfig,
+};
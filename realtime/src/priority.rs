@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use crate::{diagnostics::DiagnosticsTracker, SynthDiagnostic, SynthThread};
+
+/// The scheduling priority requested for the audio threads of the realtime
+/// synthesizer (the render thread, the channel threads and the per-key
+/// render workers).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ThreadPriority {
+    /// Use the default scheduling of the OS.
+    #[default]
+    Normal,
+
+    /// Request realtime scheduling: `SCHED_FIFO` on Linux, the "Pro Audio"
+    /// MMCSS task on Windows and the time constraint policy on macOS.
+    ///
+    /// This often requires extra permissions (e.g. `CAP_SYS_NICE` or an
+    /// `rtprio` limit on Linux). If the request fails the threads keep
+    /// running with normal priority and a `SynthDiagnostic` is reported.
+    Realtime,
+}
+
+impl ThreadPriority {
+    /// Applies the priority to the current thread, reporting a diagnostic
+    /// if it fails. `period` is the expected time between render iterations.
+    pub(crate) fn apply_to_current(
+        self,
+        thread: SynthThread,
+        period: Duration,
+        diagnostics: &DiagnosticsTracker,
+    ) {
+        if self == ThreadPriority::Normal {
+            return;
+        }
+
+        if let Err(message) = set_realtime_priority(period) {
+            diagnostics.report(SynthDiagnostic::PriorityRequestFailed { thread, message });
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_realtime_priority(_period: Duration) -> Result<(), String> {
+    // A moderate priority, so the audio threads don't starve the system
+    const PRIORITY: i32 = 50;
+
+    unsafe {
+        let min = libc::sched_get_priority_min(libc::SCHED_FIFO);
+        let max = libc::sched_get_priority_max(libc::SCHED_FIFO);
+        let param = libc::sched_param {
+            sched_priority: PRIORITY.clamp(min, max),
+        };
+
+        match libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) {
+            0 => Ok(()),
+            libc::EPERM => Err(
+                "permission denied setting SCHED_FIFO. The process needs the CAP_SYS_NICE \
+                capability (e.g. `setcap cap_sys_nice+ep` on the executable) or an rtprio \
+                limit in /etc/security/limits.conf"
+                    .to_string(),
+            ),
+            err => Err(format!("failed to set SCHED_FIFO (error {err})")),
+        }
+    }
+}
+
+#[cfg(windows)]
+fn set_realtime_priority(_period: Duration) -> Result<(), String> {
+    use windows_sys::Win32::{
+        Foundation::GetLastError, System::Threading::AvSetMmThreadCharacteristicsW,
+    };
+
+    let task: Vec<u16> = "Pro Audio"
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .collect();
+    let mut task_index = 0;
+
+    // The handle is intentionally not reverted, the thread keeps the task until it exits
+    let handle = unsafe { AvSetMmThreadCharacteristicsW(task.as_ptr(), &mut task_index) };
+    if handle == 0 {
+        let err = unsafe { GetLastError() };
+        Err(format!(
+            "failed to join the \"Pro Audio\" MMCSS task (error {err})"
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn set_realtime_priority(period: Duration) -> Result<(), String> {
+    unsafe {
+        let mut timebase = libc::mach_timebase_info { numer: 0, denom: 0 };
+        libc::mach_timebase_info(&mut timebase);
+        let to_abs = |d: Duration| {
+            (d.as_nanos() as u64 * timebase.denom as u64 / timebase.numer.max(1) as u64) as u32
+        };
+
+        let mut policy = libc::thread_time_constraint_policy_data_t {
+            period: to_abs(period),
+            computation: to_abs(period / 2),
+            constraint: to_abs(period),
+            preemptible: 1,
+        };
+
+        let result = libc::thread_policy_set(
+            libc::pthread_mach_thread_np(libc::pthread_self()),
+            libc::THREAD_TIME_CONSTRAINT_POLICY as libc::thread_policy_flavor_t,
+            &mut policy as *mut _ as libc::thread_policy_t,
+            libc::THREAD_TIME_CONSTRAINT_POLICY_COUNT,
+        );
+
+        if result == libc::KERN_SUCCESS {
+            Ok(())
+        } else {
+            Err(format!(
+                "failed to set the time constraint policy (error {result})"
+            ))
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn set_realtime_priority(_period: Duration) -> Result<(), String> {
+    Err("realtime scheduling is not supported on this platform".to_string())
+}
@@ -1,44 +1,140 @@
 use std::{
     sync::{
-        atomic::{AtomicU64, Ordering},
-        Arc,
+        atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        Arc, OnceLock, RwLock,
     },
     thread::{self},
+    time::{Duration, Instant},
 };
 
 use cpal::{
     traits::{DeviceTrait, HostTrait, StreamTrait},
-    Device, PauseStreamError, PlayStreamError, SizedSample, Stream, SupportedStreamConfig,
+    BufferSize, Device, PauseStreamError, PlayStreamError, SizedSample, Stream, StreamConfig,
+    SupportedBufferSize, SupportedStreamConfig,
 };
-use crossbeam_channel::{bounded, unbounded};
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
 
 use xsynth_core::{
+    active_simd_backend,
     buffered_renderer::{BufferedRenderer, BufferedRendererStatsReader},
-    channel::{ChannelConfigEvent, ChannelEvent, VoiceChannel},
-    channel_group::SynthFormat,
-    effects::VolumeLimiter,
-    helpers::{fast_zero_fill, sum_simd},
-    AudioPipe, AudioStreamParams, FunctionAudioPipe,
+    channel::{
+        ChannelAudioEvent, ChannelConfigEvent, ChannelEvent, ChannelInitOptions,
+        ChannelStateSnapshot, SampleRateChange, VoiceChannel, VoiceChannelStatsReader,
+    },
+    channel_group::ChannelId,
+    effects::{
+        AudioInsert, AutoGain, EffectsChain, ResamplerQuality, StreamResampler, TempoClock,
+        VolumeLimiter,
+    },
+    fast_zero_fill, flush_denormals_on_current_thread, set_simd_backend, sum_simd, AudioPipe,
+    AudioStreamParams, ChannelCount, FunctionAudioPipe, Sample, SampleConverter, SectionTimer,
+    SectionTiming, SimdBackend,
 };
 
 use crate::{
-    util::ReadWriteAtomicU64, RealtimeEventSender, SynthEvent, ThreadCount, XSynthRealtimeConfig,
+    affinity::ThreadLayoutTracker,
+    diagnostics::DiagnosticsTracker,
+    event_clock::{RenderWindow, SynthClock, TimedEvent},
+    event_senders::{ChannelQueues, OutputSenders},
+    output_routing::{interleave_pairs, mix_into_pair},
+    render_timing::{calculate_render_size, RenderTiming},
+    util::ReadWriteAtomicU64,
+    AudioStreamError, CapabilityReport, EventRecorder, LatencyBreakdown, OutputRouting,
+    RealtimeEventSender, SampleRateError, StatsHistory, SynthDiagnostic, SynthEvent, SynthThread,
+    ThreadAssignment, ThreadCount, ThreadLayout, ThreadPriority, XSynthRealtimeConfig,
 };
 
+/// The maximum time `RealtimeSynth::shutdown` waits for the voices to end.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How often `RealtimeSynth::shutdown` checks the voice count.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Extra time to wait after the master fade, so the audio device can play it.
+const SHUTDOWN_FADE_MARGIN: Duration = Duration::from_millis(20);
+
+/// The number of silent quanta rendered by `RealtimeSynth::warm_up`.
+const WARM_UP_QUANTA: usize = 4;
+
+/// The master fade length used when the synth is dropped without `shutdown`.
+const DROP_FADE_MS: f64 = 5.0;
+
+/// The longest time a removed channel plays its released voices before its
+/// thread is stopped, see `RealtimeSynth::remove_channel`.
+const REMOVED_CHANNEL_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Holds the statistics for an instance of RealtimeSynth.
 #[derive(Debug, Clone)]
 struct RealtimeSynthStats {
     voice_count: Arc<AtomicU64>,
+    rendered_frames: Arc<AtomicU64>,
+    rendered_buffers: Arc<AtomicU64>,
+    thread_layout: ThreadLayoutTracker,
+    diagnostics: DiagnosticsTracker,
+
+    /// The statistics of the channels by ID, with empty slots for the
+    /// removed channels
+    channel_stats: Arc<RwLock<Vec<Option<VoiceChannelStatsReader>>>>,
+    mix_time: Arc<SectionTimer>,
+    limiter_time: Arc<SectionTimer>,
+    render_timing: Arc<RwLock<RenderTiming>>,
+    device_buffer_frames: Arc<AtomicU64>,
+    auto_gain: Arc<AtomicU32>,
 }
 
 impl RealtimeSynthStats {
-    pub fn new() -> RealtimeSynthStats {
+    pub fn new(
+        channel_stats: Vec<VoiceChannelStatsReader>,
+        thread_layout: ThreadLayoutTracker,
+        diagnostics: DiagnosticsTracker,
+        render_timing: RenderTiming,
+    ) -> RealtimeSynthStats {
         RealtimeSynthStats {
             voice_count: Arc::new(AtomicU64::new(0)),
+            rendered_frames: Arc::new(AtomicU64::new(0)),
+            rendered_buffers: Arc::new(AtomicU64::new(0)),
+            thread_layout,
+            diagnostics,
+            channel_stats: Arc::new(RwLock::new(channel_stats.into_iter().map(Some).collect())),
+            mix_time: Default::default(),
+            limiter_time: Default::default(),
+            render_timing: Arc::new(RwLock::new(render_timing)),
+            device_buffer_frames: Default::default(),
+            auto_gain: Arc::new(AtomicU32::new(1.0f32.to_bits())),
         }
     }
 }
 
+/// The time spent in each section of the render path of a channel.
+/// See the `SectionTiming` documentation for more information.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChannelLoad {
+    /// Rendering the voices, including dispatching the note events to the keys.
+    pub voice_render: SectionTiming,
+
+    /// Applying the channel effects (volume, pan, cutoff).
+    pub effects: SectionTiming,
+
+    /// Processing the incoming events.
+    pub events: SectionTiming,
+}
+
+/// A breakdown of the time spent in each section of the render path, to
+/// find out what causes an overload.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RenderLoadBreakdown {
+    /// The load of each channel. Idle channels are not rendered, so their
+    /// timings only reflect the buffers in which they were active.
+    pub channels: Vec<ChannelLoad>,
+
+    /// Summing the audio of the channels together.
+    pub mix: SectionTiming,
+
+    /// Applying the master effects chain, which contains the output volume
+    /// limiter by default.
+    pub limiter: SectionTiming,
+}
+
 /// Reads the statistics of an instance of RealtimeSynth in a usable way.
 pub struct RealtimeSynthStatsReader {
     buffered_stats: BufferedRendererStatsReader,
@@ -61,6 +157,124 @@ impl RealtimeSynthStatsReader {
         self.stats.voice_count.load(Ordering::Relaxed)
     }
 
+    /// Returns the number of MIDI channel IDs of the synthesizer, which is
+    /// one more than the highest ID. Includes the IDs of removed channels,
+    /// see `RealtimeSynth::remove_channel`.
+    pub fn channel_count(&self) -> u32 {
+        self.stats.channel_stats.read().unwrap().len() as u32
+    }
+
+    /// Returns the active voice count of the given MIDI channel, or `0` if
+    /// the channel doesn't exist.
+    pub fn channel_voice_count(&self, channel: ChannelId) -> u64 {
+        self.stats
+            .channel_stats
+            .read()
+            .unwrap()
+            .get(channel as usize)
+            .and_then(Option::as_ref)
+            .map(|c| c.voice_count())
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of audio frames (samples per channel) rendered so far.
+    pub fn rendered_frames(&self) -> u64 {
+        self.stats.rendered_frames.load(Ordering::Relaxed)
+    }
+
+    /// Returns the realized placement of the channel threads and the render
+    /// worker threads, sorted by thread. Worker threads are listed once they
+    /// have started.
+    ///
+    /// See the `ThreadLayout` documentation for more information.
+    pub fn thread_layout(&self) -> Vec<ThreadAssignment> {
+        self.stats.thread_layout.assignments()
+    }
+
+    /// Returns the non-fatal issues reported by the synthesizer so far, such
+    /// as failed thread priority requests and unmatched notes. Only the
+    /// last 256 are kept.
+    ///
+    /// See the `SynthDiagnostic` documentation for more information.
+    pub fn diagnostics(&self) -> Vec<SynthDiagnostic> {
+        // The channels keep their unmatched notes until they are read
+        let diagnostics = &self.stats.diagnostics;
+        let channel_stats = self.stats.channel_stats.read().unwrap();
+        for (channel, stats) in channel_stats.iter().enumerate() {
+            let Some(stats) = stats else {
+                continue;
+            };
+            for note in stats.take_unmatched_notes() {
+                diagnostics.report(SynthDiagnostic::UnmatchedNote {
+                    channel: channel as ChannelId,
+                    note,
+                });
+            }
+        }
+        diagnostics.diagnostics()
+    }
+
+    /// Returns a breakdown of the time spent in each section of the render
+    /// path, as rolling averages and maxima per buffer. The IDs of removed
+    /// channels have an empty load.
+    ///
+    /// See the `RenderLoadBreakdown` documentation for more information.
+    pub fn load_breakdown(&self) -> RenderLoadBreakdown {
+        RenderLoadBreakdown {
+            channels: self
+                .stats
+                .channel_stats
+                .read()
+                .unwrap()
+                .iter()
+                .map(|c| {
+                    c.as_ref()
+                        .map_or_else(ChannelLoad::default, |c| ChannelLoad {
+                            voice_render: c.voice_render_time(),
+                            effects: c.effects_time(),
+                            events: c.event_time(),
+                        })
+                })
+                .collect(),
+            mix: self.stats.mix_time.timing(),
+            limiter: self.stats.limiter_time.timing(),
+        }
+    }
+
+    /// Returns the controller state of each channel after the last rendered
+    /// buffer, e.g. to display it or to chase it. Events sent since then are
+    /// only included once the next buffer is rendered. The IDs of removed
+    /// channels have the default state.
+    ///
+    /// See the `ChannelStateSnapshot` documentation for more information.
+    pub fn channel_states(&self) -> Vec<ChannelStateSnapshot> {
+        let channel_stats = self.stats.channel_stats.read().unwrap();
+        channel_stats
+            .iter()
+            .map(|c| c.as_ref().map(|c| c.state()).unwrap_or_default())
+            .collect()
+    }
+
+    /// Returns the effective render timing of the synthesizer.
+    ///
+    /// See the `RenderTiming` documentation for more information.
+    pub fn render_timing(&self) -> RenderTiming {
+        *self.stats.render_timing.read().unwrap()
+    }
+
+    /// Returns the number of frames the audio output device requested in its
+    /// last callback, or `0` if it didn't request any yet.
+    pub fn device_buffer_frames(&self) -> u64 {
+        self.stats.device_buffer_frames.load(Ordering::Relaxed)
+    }
+
+    /// Returns the gain applied to the mixed output by the auto-gain stage
+    /// at the end of the last rendered quantum, or exactly `1.0` if it is
+    /// disabled. See `XSynthRealtimeConfig::auto_gain`.
+    pub fn auto_gain(&self) -> f32 {
+        f32::from_bits(self.stats.auto_gain.load(Ordering::Relaxed))
+    }
+
     /// Returns the statistics of the buffered renderer used.
     ///
     /// See the BufferedRendererStatsReader documentation for more information.
@@ -69,16 +283,337 @@ impl RealtimeSynthStatsReader {
     }
 }
 
+/// A summary of a `RealtimeSynth::shutdown` call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShutdownSummary {
+    /// The number of voices that were still active when the stream was closed.
+    pub voices_remaining: u64,
+
+    /// The number of buffers rendered between the start of the shutdown
+    /// and the stream being closed.
+    pub buffers_rendered: u64,
+}
+
 // A helper for making the stream be send/sync, allowing the entire synth to be passed between threads.
 // The stream is never actually accessed from multiple threads, it's only stored for ownership and then dropped.
 struct SendSyncStream(Stream);
 unsafe impl Sync for SendSyncStream {}
 unsafe impl Send for SendSyncStream {}
 
+// The user callback invoked on buffer underruns, see `RealtimeSynth::set_underrun_callback`.
+type UnderrunCallback = Arc<std::sync::Mutex<Option<Box<dyn FnMut(usize) + Send>>>>;
+
+// The render function of the render thread, shared with `RealtimeSynth::warm_up`,
+// which sets its flag to render the silent warm-up quanta.
+type RenderFn = Arc<std::sync::Mutex<dyn FnMut(&mut [f32], bool) + Send>>;
+
 struct RealtimeSynthThreadSharedData {
     buffered_renderer: Arc<std::sync::Mutex<BufferedRenderer>>,
     stream: SendSyncStream,
     event_senders: RealtimeEventSender,
+    channel_queues: Arc<ChannelQueues>,
+    channel_threads: ChannelThreadBuilder,
+    channel_changes: Sender<ChannelChange>,
+    recorder: Arc<EventRecorder>,
+    stats_history: Option<Arc<StatsHistory>>,
+    fade_frames: Arc<AtomicU64>,
+    underrun_callback: UnderrunCallback,
+    master_effects: Arc<std::sync::Mutex<MasterEffects>>,
+    render: RenderFn,
+
+    /// Sends the resamplers replacing the one of the output stream
+    resamplers: Sender<StreamResampler>,
+
+    /// Sends the sample rates switched to with `set_sample_rate` to the
+    /// render thread
+    sample_rates: Sender<u32>,
+}
+
+/// The master effects chain of the audio output, with the receiver of the
+/// chains replacing it. Shared with the output callback, so it is kept when
+/// the output is reopened.
+struct MasterEffects {
+    chain: EffectsChain,
+    receiver: Receiver<EffectsChain>,
+
+    /// The volume limiters of the output pairs, used instead of the chain
+    /// with routed output. Empty otherwise.
+    pair_limiters: Vec<VolumeLimiter>,
+}
+
+/// A channel as seen by the render thread.
+struct RenderChannel {
+    /// Sends the buffers to render to the channel thread. The thread stops
+    /// when it is dropped.
+    commands: Sender<(Vec<f32>, RenderWindow)>,
+    events: Sender<TimedEvent>,
+    stats: VoiceChannelStatsReader,
+}
+
+/// A removed channel, rendered until its released voices end.
+struct RemovedChannel {
+    channel: RenderChannel,
+    frames_left: u64,
+}
+
+/// A channel added or removed at runtime, applied by the render thread at
+/// the start of the next render quantum.
+enum ChannelChange {
+    Add(ChannelId, RenderChannel),
+    Remove(ChannelId),
+}
+
+/// Spawns the threads that render the channels, both the channels created
+/// when the synthesizer is opened and the ones added later.
+#[derive(Clone)]
+struct ChannelThreadBuilder {
+    pool: Option<Arc<rayon::ThreadPool>>,
+    output_sender: Sender<(ChannelId, Vec<f32>)>,
+    thread_layout: ThreadLayout,
+    tracker: ThreadLayoutTracker,
+    diagnostics: DiagnosticsTracker,
+    priority: ThreadPriority,
+    render_period: Duration,
+    flush_denormals: bool,
+    precise_event_timing: bool,
+    audio_channels: usize,
+}
+
+impl ChannelThreadBuilder {
+    /// Spawns the thread of a channel, which applies the events of its queue
+    /// and renders the buffers sent through the returned sender.
+    fn spawn(
+        &self,
+        id: ChannelId,
+        mut channel: VoiceChannel,
+        event_receiver: Receiver<TimedEvent>,
+    ) -> (Sender<(Vec<f32>, RenderWindow)>, thread::JoinHandle<()>) {
+        let (command_sender, command_receiver) = bounded::<(Vec<f32>, RenderWindow)>(1);
+
+        let builder = self.clone();
+        let core = self.thread_layout.channel_core(id);
+        let join_handle = thread::Builder::new()
+            .name("xsynth_channel_handler".to_string())
+            .spawn(move || {
+                let thread = SynthThread::Channel(id);
+                builder.tracker.pin_current(thread, core);
+                builder.priority.apply_to_current(
+                    thread,
+                    builder.render_period,
+                    &builder.diagnostics,
+                );
+                if builder.flush_denormals {
+                    flush_denormals_on_current_thread();
+                }
+                #[cfg(feature = "tracing")]
+                let _span = xsynth_core::trace::enter_channel(id);
+                loop {
+                    if !builder.precise_event_timing {
+                        channel.push_events_iter(event_receiver.try_iter().map(|e| e.event));
+                    }
+                    let (mut vec, window) = match command_receiver.recv() {
+                        Ok(command) => command,
+                        Err(_) => break,
+                    };
+                    if builder.precise_event_timing {
+                        let frames = vec.len() / builder.audio_channels;
+                        let events = event_receiver
+                            .try_iter()
+                            .map(|e| (window.frame_offset(e.time, frames), e.event));
+                        channel.read_samples_timed(&mut vec, events);
+                    } else {
+                        channel.push_events_iter(event_receiver.try_iter().map(|e| e.event));
+                        channel.read_samples(&mut vec);
+                    }
+                    builder.output_sender.send((id, vec)).unwrap();
+                }
+            })
+            .unwrap();
+
+        (command_sender, join_handle)
+    }
+}
+
+// The state moved into the audio output callback
+struct OutputState {
+    buffered: Arc<std::sync::Mutex<BufferedRenderer>>,
+    fade_frames: Arc<AtomicU64>,
+    limiter_time: Arc<SectionTimer>,
+    device_buffer_frames: Arc<AtomicU64>,
+    underrun_callback: UnderrunCallback,
+    resampler: StreamResampler,
+    resamplers: Receiver<StreamResampler>,
+    master_effects: Arc<std::sync::Mutex<MasterEffects>>,
+    flush_denormals: bool,
+    output_params: AudioStreamParams,
+
+    /// The number of interleaved channels of the rendered frames, which
+    /// holds a stereo mix per output pair with routed output
+    frame_channels: usize,
+}
+
+fn build_stream<T: SizedSample + Sample>(
+    device: &Device,
+    stream_config: StreamConfig,
+    state: OutputState,
+) -> Result<Stream, AudioStreamError> {
+    let OutputState {
+        buffered,
+        fade_frames,
+        limiter_time,
+        device_buffer_frames,
+        underrun_callback,
+        mut resampler,
+        resamplers,
+        master_effects,
+        flush_denormals,
+        output_params,
+        frame_channels,
+    } = state;
+
+    let err_fn = |err| eprintln!("an error occurred on stream: {err}");
+    let mut input_vec = Vec::new();
+    let mut render_vec = Vec::new();
+    let mut pair_vec = Vec::new();
+    let mut output_vec = Vec::new();
+
+    let channels = stream_config.channels as usize;
+    let mut converter = SampleConverter::new();
+    let mut fade_position = 0u64;
+
+    device
+        .build_output_stream(
+            &stream_config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                // The callback thread belongs to the audio backend, which may
+                // change it, so the setting is applied on every callback
+                if flush_denormals {
+                    flush_denormals_on_current_thread();
+                }
+
+                // Only replaced by `RealtimeSynth::set_sample_rate`, which
+                // drops the playing voices anyway
+                if let Some(new_resampler) = resamplers.try_iter().last() {
+                    resampler = new_resampler;
+                }
+
+                let frames = data.len() / channels;
+                device_buffer_frames.store(frames as u64, Ordering::Relaxed);
+                render_vec.resize(frames * frame_channels, 0.0);
+                let shortfall = if resampler.is_passthrough() {
+                    buffered.lock().unwrap().read(&mut render_vec)
+                } else {
+                    let input_frames = resampler.input_frames_needed(frames);
+                    input_vec.resize(input_frames * frame_channels, 0.0);
+                    let shortfall = buffered.lock().unwrap().read(&mut input_vec);
+                    resampler.process(&input_vec, &mut render_vec);
+                    shortfall
+                };
+                if shortfall > 0 {
+                    // Never block the audio thread on the callback being replaced
+                    if let Ok(mut callback) = underrun_callback.try_lock() {
+                        if let Some(callback) = callback.as_mut() {
+                            callback(shortfall);
+                        }
+                    }
+                }
+
+                // Only contended while the output is reopened on a host that
+                // starts the new stream when it is built, as the old stream
+                // is still running then
+                let mut master_effects = master_effects.lock().unwrap();
+                let MasterEffects {
+                    chain,
+                    receiver,
+                    pair_limiters,
+                } = &mut *master_effects;
+
+                // The replaced elements are dropped on a background thread
+                for new_chain in receiver.try_iter() {
+                    chain.replace(new_chain);
+                }
+
+                let start = Instant::now();
+                let output = if pair_limiters.is_empty() {
+                    chain.process(&mut render_vec, &output_params);
+                    &mut render_vec
+                } else {
+                    output_vec.resize(data.len(), 0.0);
+                    interleave_pairs(
+                        &render_vec,
+                        pair_limiters,
+                        &mut pair_vec,
+                        &mut output_vec,
+                        channels,
+                    );
+                    &mut output_vec
+                };
+                limiter_time.record_since(start);
+                drop(master_effects);
+
+                // Master fade out, used when shutting down. 0 means no fade.
+                let fade_length = fade_frames.load(Ordering::Relaxed);
+                if fade_length > 0 {
+                    for (i, s) in output.iter_mut().enumerate() {
+                        let position = fade_position + (i / channels) as u64;
+                        *s *= (1.0 - position as f32 / fade_length as f32).max(0.0);
+                    }
+                    fade_position += frames as u64;
+                }
+
+                converter.convert(output, data);
+            },
+            err_fn,
+            None,
+        )
+        .map_err(AudioStreamError::from)
+}
+
+/// Clamps the requested device buffer size to the range supported by the
+/// output config.
+fn clamp_device_buffer_frames(
+    frames: Option<u32>,
+    stream_config: &SupportedStreamConfig,
+) -> Option<u32> {
+    frames.map(|frames| match stream_config.buffer_size() {
+        SupportedBufferSize::Range { min, max } => frames.clamp(*min, *max),
+        SupportedBufferSize::Unknown => frames,
+    })
+}
+
+/// Checks that the output config has the channel count the synthesizer
+/// renders for, which can't change when the output is reopened.
+fn check_output_channels(
+    stream_config: &SupportedStreamConfig,
+    expected: u16,
+) -> Result<(), AudioStreamError> {
+    let found = stream_config.channels();
+    if found != expected {
+        return Err(AudioStreamError::ChannelCountChanged { expected, found });
+    }
+    Ok(())
+}
+
+/// Builds the audio output stream in the sample format of the config. The
+/// stream isn't started.
+fn build_output_stream(
+    device: &Device,
+    stream_config: SupportedStreamConfig,
+    device_buffer_frames: Option<u32>,
+    state: OutputState,
+) -> Result<Stream, AudioStreamError> {
+    let mut output_config: StreamConfig = stream_config.clone().into();
+    if let Some(frames) = device_buffer_frames {
+        output_config.buffer_size = BufferSize::Fixed(frames);
+    }
+    match stream_config.sample_format() {
+        cpal::SampleFormat::F32 => build_stream::<f32>(device, output_config, state),
+        cpal::SampleFormat::F64 => build_stream::<f64>(device, output_config, state),
+        cpal::SampleFormat::I16 => build_stream::<i16>(device, output_config, state),
+        cpal::SampleFormat::I32 => build_stream::<i32>(device, output_config, state),
+        cpal::SampleFormat::U16 => build_stream::<u16>(device, output_config, state),
+        format => Err(AudioStreamError::UnsupportedSampleFormat(format)),
+    }
 }
 
 /// A realtime MIDI synthesizer using an audio device for output.
@@ -89,39 +624,104 @@ pub struct RealtimeSynth {
     stats: RealtimeSynthStats,
 
     stream_params: AudioStreamParams,
+    output_params: AudioStreamParams,
+    device_channels: u16,
+    frame_channels: usize,
+    resampler_latency: Duration,
+    resampler_quality: ResamplerQuality,
+    requested_buffer_frames: Option<u32>,
+    flush_denormals: bool,
+
+    /// The parts of the capability report that are known when the output
+    /// is opened
+    report: CapabilityReport,
 }
 
 impl RealtimeSynth {
     /// Initializes a new realtime synthesizer using the default config and
     /// the default audio output.
+    ///
+    /// Panics if the audio output can't be opened, see
+    /// `try_open_with_default_output`.
     pub fn open_with_all_defaults() -> Self {
-        let host = cpal::default_host();
-
-        let device = host
-            .default_output_device()
-            .expect("failed to find output device");
-        println!("Output device: {}", device.name().unwrap());
-
-        let stream_config = device.default_output_config().unwrap();
-
-        RealtimeSynth::open(Default::default(), &device, stream_config)
+        Self::open_with_default_output(Default::default())
     }
 
     /// Initializes as new realtime synthesizer using a given config and
     /// the default audio output.
     ///
     /// See the `XSynthRealtimeConfig` documentation for the available options.
+    ///
+    /// Panics if the audio output can't be opened or the config is invalid,
+    /// see `try_open_with_default_output`.
     pub fn open_with_default_output(config: XSynthRealtimeConfig) -> Self {
+        Self::try_open_with_default_output(config).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Initializes as new realtime synthesizer using a given config and
+    /// the default audio output, returning an error instead of panicking if
+    /// there is no output device, the stream can't be opened or the config
+    /// is invalid.
+    ///
+    /// See the `XSynthRealtimeConfig` documentation for the available options.
+    pub fn try_open_with_default_output(
+        config: XSynthRealtimeConfig,
+    ) -> Result<Self, AudioStreamError> {
         let host = cpal::default_host();
 
         let device = host
             .default_output_device()
-            .expect("failed to find output device");
-        println!("Output device: {}", device.name().unwrap());
+            .ok_or(AudioStreamError::NoOutputDevice)?;
+        if let Ok(name) = device.name() {
+            println!("Output device: {name}");
+        }
 
-        let stream_config = device.default_output_config().unwrap();
+        let stream_config = RealtimeSynth::try_preferred_output_config(&device)?;
+
+        let mut synth = RealtimeSynth::try_open(config, &device, stream_config)?;
+        synth.report.audio_host = Some(host.id().name().to_string());
+        Ok(synth)
+    }
+
+    /// Returns the output config the synthesizer uses for a device when
+    /// opened with the default output: the default config of the device,
+    /// switched to f32 samples if supported, or to i16 samples otherwise.
+    /// Other sample formats are only used if the device supports neither.
+    ///
+    /// Panics if the device has no default output config, see
+    /// `try_preferred_output_config`.
+    pub fn preferred_output_config(device: &Device) -> SupportedStreamConfig {
+        Self::try_preferred_output_config(device).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Returns the output config the synthesizer uses for a device like
+    /// `preferred_output_config`, or an error if the device has no default
+    /// output config.
+    pub fn try_preferred_output_config(
+        device: &Device,
+    ) -> Result<SupportedStreamConfig, AudioStreamError> {
+        let default = device.default_output_config()?;
+        if default.sample_format() == cpal::SampleFormat::F32 {
+            return Ok(default);
+        }
+
+        let supported: Vec<_> = device
+            .supported_output_configs()
+            .map(|configs| configs.collect())
+            .unwrap_or_default();
+        for format in [cpal::SampleFormat::F32, cpal::SampleFormat::I16] {
+            let config = supported.iter().find(|c| {
+                c.sample_format() == format
+                    && c.channels() == default.channels()
+                    && c.min_sample_rate() <= default.sample_rate()
+                    && c.max_sample_rate() >= default.sample_rate()
+            });
+            if let Some(config) = config {
+                return Ok((*config).with_sample_rate(default.sample_rate()));
+            }
+        }
 
-        RealtimeSynth::open(config, &device, stream_config)
+        Ok(default)
     }
 
     /// Initializes a new realtime synthesizer using a given config and a
@@ -129,159 +729,456 @@ impl RealtimeSynth {
     ///
     /// See the `XSynthRealtimeConfig` documentation for the available options.
     /// See the `cpal` crate documentation for the `device` and `stream_config` parameters.
+    ///
+    /// Panics if the config is invalid, see `XSynthRealtimeConfig::validate`,
+    /// or if the stream can't be opened, see `try_open`.
     pub fn open(
         config: XSynthRealtimeConfig,
         device: &Device,
         stream_config: SupportedStreamConfig,
     ) -> Self {
-        let mut channel_stats = Vec::new();
-        let mut senders = Vec::new();
-        let mut command_senders = Vec::new();
+        Self::try_open(config, device, stream_config).unwrap_or_else(|e| panic!("{e}"))
+    }
 
-        let sample_rate = stream_config.sample_rate().0;
-        let stream_params = AudioStreamParams::new(sample_rate, stream_config.channels().into());
+    /// Initializes a new realtime synthesizer like `open`, returning an
+    /// error instead of panicking if the config is invalid or the output
+    /// stream can't be built or started.
+    pub fn try_open(
+        config: XSynthRealtimeConfig,
+        device: &Device,
+        stream_config: SupportedStreamConfig,
+    ) -> Result<Self, AudioStreamError> {
+        if let Some(backend) = config.simd_backend {
+            set_simd_backend(backend);
+        }
+
+        let mut channel_readers = Vec::new();
+        let mut render_channels = Vec::new();
+        let mut queues = Vec::new();
+
+        // Devices without a speaker layout for their channel count always
+        // use routed output, and render a stereo mix per output pair
+        let device_channels = stream_config.channels();
+        let layout = ChannelCount::from_count(device_channels);
+        let mut output_routing = match config.output_routing.clone() {
+            Some(routing) if device_channels > 2 => Some(routing),
+            _ if layout.is_none() => Some(OutputRouting::new()),
+            _ => None,
+        };
+        let pair_count = OutputRouting::pair_count(device_channels);
+        let routed = output_routing.is_some();
+
+        let output_params = AudioStreamParams::new(
+            stream_config.sample_rate().0,
+            layout.filter(|_| !routed).unwrap_or(ChannelCount::Stereo),
+        );
+        let sample_rate = config.sample_rate.unwrap_or(output_params.sample_rate);
+        let stream_params = AudioStreamParams::new(sample_rate, output_params.channels);
+
+        config.validate(sample_rate)?;
+
+        let device_buffer_frames =
+            clamp_device_buffer_frames(config.device_buffer_frames, &stream_config);
+        let render_timing = RenderTiming::new(&config, sample_rate, device_buffer_frames);
+
+        let thread_layout = ThreadLayoutTracker::default();
+        let diagnostics = DiagnosticsTracker::default();
+        if let Some(routing) = &mut output_routing {
+            routing.check(pair_count, &diagnostics);
+        }
+        let priority = config.thread_priority;
+        let flush_denormals = config.flush_denormals;
+        let render_period = render_timing.quantum_period(sample_rate);
+
+        let build_pool = |builder: rayon::ThreadPoolBuilder| {
+            let layout = config.thread_layout.clone();
+            let tracker = thread_layout.clone();
+            let diagnostics = diagnostics.clone();
+            let pool = builder
+                .thread_name(|i| format!("xsynth_render_worker_{i}"))
+                .start_handler(move |i| {
+                    let thread = SynthThread::Worker(i);
+                    tracker.pin_current(thread, layout.worker_core(i));
+                    priority.apply_to_current(thread, render_period, &diagnostics);
+                    if flush_denormals {
+                        flush_denormals_on_current_thread();
+                    }
+                })
+                .build()
+                .unwrap();
+            Arc::new(pool)
+        };
 
         let pool = match config.multithreading {
             ThreadCount::None => None,
-            ThreadCount::Auto => Some(Arc::new(rayon::ThreadPoolBuilder::new().build().unwrap())),
-            ThreadCount::Manual(threads) => Some(Arc::new(
-                rayon::ThreadPoolBuilder::new()
-                    .num_threads(threads)
-                    .build()
-                    .unwrap(),
+            ThreadCount::Auto => Some(build_pool(rayon::ThreadPoolBuilder::new())),
+            ThreadCount::Manual(threads) => Some(build_pool(
+                rayon::ThreadPoolBuilder::new().num_threads(threads),
             )),
         };
 
-        let channel_count = match config.format {
-            SynthFormat::Midi => 16,
-            SynthFormat::Custom { channels } => channels,
+        let channel_count = config.format.channel_count();
+        let clock = config.clock.unwrap_or_default();
+        let precise_event_timing = config.precise_event_timing;
+        let audio_channels = stream_params.channels.count() as usize;
+        let frame_channels = if routed {
+            pair_count as usize * 2
+        } else {
+            audio_channels
         };
 
-        let (output_sender, output_receiver) = bounded::<Vec<f32>>(channel_count as usize);
+        // Unbounded, as channels can be added at runtime
+        let (output_sender, output_receiver) = unbounded::<(ChannelId, Vec<f32>)>();
+        let (output_pair_sender, output_pair_receiver) = unbounded::<(ChannelId, u16)>();
+
+        let channel_threads = ChannelThreadBuilder {
+            pool: pool.clone(),
+            output_sender,
+            thread_layout: config.thread_layout.clone(),
+            tracker: thread_layout.clone(),
+            diagnostics: diagnostics.clone(),
+            priority,
+            render_period,
+            flush_denormals,
+            precise_event_timing,
+            audio_channels,
+        };
 
         let mut thread_handles = vec![];
+        let tempo = TempoClock::default();
 
-        for _ in 0u32..channel_count {
+        for i in 0u32..channel_count {
             let mut channel =
                 VoiceChannel::new(config.channel_init_options, stream_params, pool.clone());
+            channel.set_tempo_clock(tempo.clone());
+            if config.format.is_percussion(i) {
+                channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetPercussionMode(
+                    true,
+                )));
+            }
+            channel_readers.push(channel.get_channel_stats());
             let stats = channel.get_channel_stats();
-            channel_stats.push(stats);
 
             let (event_sender, event_receiver) = unbounded();
-            senders.push(event_sender);
-
-            let (command_sender, command_receiver) = bounded::<Vec<f32>>(1);
-
-            command_senders.push(command_sender);
-
-            let output_sender = output_sender.clone();
-            let join_handle = thread::Builder::new()
-                .name("xsynth_channel_handler".to_string())
-                .spawn(move || loop {
-                    channel.push_events_iter(event_receiver.try_iter());
-                    let mut vec = match command_receiver.recv() {
-                        Ok(vec) => vec,
-                        Err(_) => break,
-                    };
-                    channel.push_events_iter(event_receiver.try_iter());
-                    channel.read_samples(&mut vec);
-                    output_sender.send(vec).unwrap();
-                })
-                .unwrap();
-
+            queues.push(event_sender.clone());
+
+            let (commands, join_handle) = channel_threads.spawn(i, channel, event_receiver);
+            render_channels.push(Some(RenderChannel {
+                commands,
+                events: event_sender,
+                stats,
+            }));
             thread_handles.push(join_handle);
         }
 
-        if config.format == SynthFormat::Midi {
-            senders[9]
-                .send(ChannelEvent::Config(ChannelConfigEvent::SetPercussionMode(
-                    true,
-                )))
-                .unwrap();
-        }
-
         let mut vec_cache: std::collections::VecDeque<Vec<f32>> = std::collections::VecDeque::new();
         for _ in 0..channel_count {
             vec_cache.push_front(Vec::new());
         }
 
-        let stats = RealtimeSynthStats::new();
+        let stats = RealtimeSynthStats::new(
+            channel_readers,
+            thread_layout,
+            diagnostics.clone(),
+            render_timing,
+        );
 
         let total_voice_count = stats.voice_count.clone();
+        let rendered_frames = stats.rendered_frames.clone();
+        let rendered_buffers = stats.rendered_buffers.clone();
+        let mix_time = stats.mix_time.clone();
+        let auto_gain_level = stats.auto_gain.clone();
+        let auto_gain_options = config.auto_gain;
+        let mut auto_gain = auto_gain_options.map(|options| AutoGain::new(options, sample_rate));
+        let mut render_thread_started = false;
+        let mut last_render_time = 0;
+
+        let (channel_changes, channel_change_receiver) = unbounded::<ChannelChange>();
+        let mut removed_channels: Vec<RemovedChannel> = Vec::new();
+        let removed_channel_timeout =
+            |sample_rate: u32| (REMOVED_CHANNEL_TIMEOUT.as_secs_f64() * sample_rate as f64) as u64;
+        let mut removed_channel_frames = removed_channel_timeout(sample_rate);
+        let (sample_rates, sample_rate_receiver) = unbounded::<u32>();
+
+        let stats_history = config
+            .stats_history
+            .map(|capacity| Arc::new(StatsHistory::new(capacity)));
+        let history = stats_history.clone();
+        // The buffered renderer is created after the render function
+        let history_buffer_stats = Arc::new(OnceLock::<BufferedRendererStatsReader>::new());
+        let buffer_stats = history_buffer_stats.clone();
+
+        let render = move |out: &mut [f32], warm_up: bool| {
+            // The render thread is created by the buffered renderer, so it's
+            // set up on the first render
+            if !render_thread_started && !warm_up {
+                render_thread_started = true;
+                priority.apply_to_current(SynthThread::Render, render_period, &diagnostics);
+                if flush_denormals {
+                    flush_denormals_on_current_thread();
+                }
+            }
+            let render_start = history.as_ref().map(|_| Instant::now());
+
+            let now = clock.now();
+            let window = RenderWindow {
+                start: last_render_time,
+                end: now,
+            };
+            last_render_time = now;
+
+            for (channel, pair) in output_pair_receiver.try_iter() {
+                if let Some(routing) = &mut output_routing {
+                    routing.set_checked_route(channel, pair, pair_count, &diagnostics);
+                }
+            }
 
-        let render = FunctionAudioPipe::new(stream_params, move |out| {
-            for sender in command_senders.iter() {
-                let mut buf = vec_cache.pop_front().unwrap();
-                fast_zero_fill(&mut buf, out.len());
+            let frames = out.len() / frame_channels;
+            for change in channel_change_receiver.try_iter() {
+                match change {
+                    ChannelChange::Add(id, channel) => {
+                        let id = id as usize;
+                        if render_channels.len() <= id {
+                            render_channels.resize_with(id + 1, || None);
+                        }
+                        render_channels[id] = Some(channel);
+                    }
+                    ChannelChange::Remove(id) => {
+                        if let Some(channel) =
+                            render_channels.get_mut(id as usize).and_then(Option::take)
+                        {
+                            removed_channels.push(RemovedChannel {
+                                channel,
+                                frames_left: removed_channel_frames,
+                            });
+                        }
+                    }
+                }
+            }
 
-                sender.send(buf).unwrap();
+            // The removed channels don't receive the sample rate changes, so
+            // they are dropped with the voices they still play
+            for sample_rate in sample_rate_receiver.try_iter() {
+                auto_gain = auto_gain_options.map(|options| AutoGain::new(options, sample_rate));
+                removed_channel_frames = removed_channel_timeout(sample_rate);
+                removed_channels.clear();
             }
 
-            for _ in 0..channel_count {
-                let buf = output_receiver.recv().unwrap();
-                sum_simd(&buf, out);
-                vec_cache.push_front(buf);
+            // The channels sending to a sidechain are rendered in a first
+            // pass, so the compressors reading it get the audio of the same
+            // quantum
+            let mut mix_seconds = 0.0;
+            for sidechain_pass in [true, false] {
+                let mut active_channels = 0;
+                let channels = render_channels.iter().flatten();
+                for channel in channels.chain(removed_channels.iter().map(|r| &r.channel)) {
+                    let sidechain = channel.stats.has_sidechain_send();
+                    if sidechain != sidechain_pass {
+                        continue;
+                    }
+
+                    // Channels without voices or pending events only render
+                    // silence, so they are skipped to keep large channel
+                    // counts cheap. Sidechain sources still clear their audio.
+                    let idle = channel.stats.voice_count() == 0 && channel.events.is_empty();
+                    if idle && !sidechain && !warm_up {
+                        continue;
+                    }
+
+                    let mut buf = vec_cache.pop_front().unwrap_or_default();
+                    fast_zero_fill(&mut buf, frames * audio_channels);
+
+                    channel.commands.send((buf, window)).unwrap();
+                    active_channels += 1;
+                }
+
+                for _ in 0..active_channels {
+                    let (id, buf) = output_receiver.recv().unwrap();
+                    let start = Instant::now();
+                    match &output_routing {
+                        Some(routing) => mix_into_pair(&buf, out, routing.pair(id), pair_count),
+                        None => sum_simd(&buf, out),
+                    }
+                    mix_seconds += start.elapsed().as_secs_f64();
+                    vec_cache.push_front(buf);
+                }
             }
+            // The warm-up isn't counted in the statistics
+            if warm_up {
+                return;
+            }
+            mix_time.record(mix_seconds);
 
-            let total_voices = channel_stats.iter().map(|c| c.voice_count()).sum();
+            let frames = frames as u64;
+            let mut total_voices = 0;
+            for channel in render_channels.iter().flatten() {
+                total_voices += channel.stats.voice_count();
+            }
+
+            // Removed channels are dropped once their voices ended, which
+            // stops their threads
+            removed_channels.retain_mut(|removed| {
+                let voices = removed.channel.stats.voice_count();
+                total_voices += voices;
+                removed.frames_left = removed.frames_left.saturating_sub(frames);
+                let ended = voices == 0 && removed.channel.events.is_empty();
+                !ended && removed.frames_left > 0
+            });
             total_voice_count.store(total_voices, Ordering::Relaxed);
-        });
+            if let Some(auto_gain) = auto_gain.as_mut() {
+                auto_gain.process(out, frame_channels, total_voices);
+                auto_gain_level.store(auto_gain.gain().to_bits(), Ordering::Relaxed);
+            }
+            rendered_frames.fetch_add(frames, Ordering::Relaxed);
+            rendered_buffers.fetch_add(1, Ordering::Relaxed);
+
+            if let (Some(history), Some(start), Some(buffer)) =
+                (&history, render_start, buffer_stats.get())
+            {
+                history.record(
+                    total_voices,
+                    start.elapsed(),
+                    buffer.samples(),
+                    buffer.xrun_count(),
+                );
+            }
+        };
 
-        let buffered = Arc::new(std::sync::Mutex::new(BufferedRenderer::new(
-            render,
+        let render: RenderFn = Arc::new(std::sync::Mutex::new(render));
+        let shared_render = render.clone();
+        let render_pipe = FunctionAudioPipe::new(stream_params, move |out| {
+            (*shared_render.lock().unwrap())(out, false);
+        });
+        let buffered = BufferedRenderer::with_frame_channels(
+            render_pipe,
             stream_params,
-            calculate_render_size(sample_rate, config.render_window_ms),
-        )));
-
-        fn build_stream<T: SizedSample + ConvertSample>(
-            device: &Device,
-            stream_config: SupportedStreamConfig,
-            buffered: Arc<std::sync::Mutex<BufferedRenderer>>,
-        ) -> Stream {
-            let err_fn = |err| eprintln!("an error occurred on stream: {err}");
-            let mut output_vec = Vec::new();
-
-            let mut limiter = VolumeLimiter::new(stream_config.channels());
-
-            device
-                .build_output_stream(
-                    &stream_config.into(),
-                    move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
-                        output_vec.resize(data.len(), 0.0);
-                        buffered.lock().unwrap().read(&mut output_vec);
-                        for (i, s) in limiter.limit_iter(output_vec.drain(..)).enumerate() {
-                            data[i] = ConvertSample::from_f32(s);
-                        }
-                    },
-                    err_fn,
-                    None,
-                )
-                .unwrap()
+            render_timing.render_quantum_frames,
+            frame_channels,
+        );
+        render_timing.apply(&buffered);
+        if stats_history.is_some() {
+            let _ = history_buffer_stats.set(buffered.get_buffer_stats());
         }
-
-        let stream = match stream_config.sample_format() {
-            cpal::SampleFormat::F32 => build_stream::<f32>(device, stream_config, buffered.clone()),
-            cpal::SampleFormat::I16 => build_stream::<i16>(device, stream_config, buffered.clone()),
-            cpal::SampleFormat::U16 => build_stream::<u16>(device, stream_config, buffered.clone()),
-            _ => panic!("unsupported sample format"),
+        let buffered = Arc::new(std::sync::Mutex::new(buffered));
+
+        let fade_frames = Arc::new(AtomicU64::new(0));
+
+        let resampler = StreamResampler::new(
+            frame_channels as u16,
+            sample_rate,
+            output_params.sample_rate,
+            config.resampler_quality,
+        );
+        let resampler_latency =
+            Duration::from_secs_f64(resampler.latency_frames() as f64 / sample_rate as f64);
+        let underrun_callback: UnderrunCallback = Default::default();
+
+        let master_effects_latency =
+            Arc::new(AtomicUsize::new(config.master_effects.latency_frames()));
+        let (master_effects_sender, master_effects_receiver) = unbounded();
+        let (resampler_sender, resampler_receiver) = unbounded();
+
+        let master_effects = Arc::new(std::sync::Mutex::new(MasterEffects {
+            chain: config.master_effects,
+            receiver: master_effects_receiver,
+            pair_limiters: if routed {
+                (0..pair_count).map(|_| VolumeLimiter::new(2)).collect()
+            } else {
+                Vec::new()
+            },
+        }));
+
+        let state = OutputState {
+            buffered: buffered.clone(),
+            fade_frames: fade_frames.clone(),
+            limiter_time: stats.limiter_time.clone(),
+            device_buffer_frames: stats.device_buffer_frames.clone(),
+            underrun_callback: underrun_callback.clone(),
+            resampler,
+            resamplers: resampler_receiver,
+            master_effects: master_effects.clone(),
+            flush_denormals,
+            output_params,
+            frame_channels,
         };
-
-        stream.play().unwrap();
+        let report = CapabilityReport {
+            simd_backend: active_simd_backend(),
+            channel_threads: channel_count,
+            worker_threads: pool.as_ref().map_or(0, |pool| pool.current_num_threads()),
+            audio_host: None,
+            device_name: device.name().unwrap_or_default(),
+            sample_format: stream_config.sample_format().to_string(),
+            output_sample_rate: output_params.sample_rate,
+            render_sample_rate: sample_rate,
+            audio_channels: stream_config.channels(),
+            device_buffer_frames,
+            latency: Duration::ZERO,
+            thread_priority: priority,
+            realtime_priority: None,
+        };
+        let stream = build_output_stream(device, stream_config, device_buffer_frames, state)?;
 
         let max_nps = Arc::new(ReadWriteAtomicU64::new(10000));
 
-        Self {
+        let recorder = Arc::new(EventRecorder::new(
+            stats.rendered_frames.clone(),
+            sample_rate,
+            channel_count,
+            config.recording_capacity,
+        ));
+
+        let channel_queues = Arc::new(ChannelQueues::new(queues));
+
+        let mut synth = Self {
             data: Some(RealtimeSynthThreadSharedData {
                 buffered_renderer: buffered,
 
-                event_senders: RealtimeEventSender::new(senders, max_nps, config.ignore_range),
+                event_senders: RealtimeEventSender::new(
+                    channel_queues.clone(),
+                    clock,
+                    max_nps,
+                    config.ignore_range,
+                    recorder.clone(),
+                    OutputSenders {
+                        master_effects: master_effects_sender,
+                        master_effects_latency,
+                        output_pairs: output_pair_sender,
+                        tempo,
+                    },
+                ),
                 stream: SendSyncStream(stream),
+                channel_queues,
+                channel_threads,
+                channel_changes,
+                recorder,
+                stats_history,
+                fade_frames,
+                underrun_callback,
+                master_effects,
+                render,
+                resamplers: resampler_sender,
+                sample_rates,
             }),
             join_handles: thread_handles,
 
             stats,
             stream_params,
+            output_params,
+            device_channels,
+            frame_channels,
+            resampler_latency,
+            resampler_quality: config.resampler_quality,
+            requested_buffer_frames: config.device_buffer_frames,
+            flush_denormals,
+            report,
+        };
+
+        if config.warm_up {
+            synth.warm_up();
+        } else {
+            synth.resume()?;
         }
+        Ok(synth)
     }
 
     /// Sends a SynthEvent to the realtime synthesizer.
@@ -292,12 +1189,108 @@ impl RealtimeSynth {
         data.event_senders.send_event(event);
     }
 
+    /// Sends a SynthEvent to the realtime synthesizer, timestamped with the
+    /// given time of its clock.
+    ///
+    /// See `RealtimeEventSender::send_event_at` for more information.
+    pub fn send_event_at(&mut self, event: SynthEvent, time: u64) {
+        let data = self.data.as_mut().unwrap();
+        data.event_senders.send_event_at(event, time);
+    }
+
+    /// Returns the clock the synthesizer timestamps its events with. It can
+    /// be passed to `XSynthRealtimeConfig::clock` to share it with another
+    /// synthesizer.
+    pub fn clock(&self) -> SynthClock {
+        self.data.as_ref().unwrap().event_senders.clock()
+    }
+
     /// Sends a u32 event to the realtime synthesizer.
     pub fn send_event_u32(&mut self, event: u32) {
         let data = self.data.as_mut().unwrap();
         data.event_senders.send_event_u32(event);
     }
 
+    /// Adds a channel with the given options, and returns its ID, which is
+    /// the channel index of the events sent to it. The ID of a removed
+    /// channel is reused, otherwise the channel is added after the last one.
+    ///
+    /// The channel gets a thread of its own like the other channels, and is
+    /// rendered from the next render quantum. Events can be sent to it right
+    /// away, including from cloned event senders. It starts with the default
+    /// state and no soundfonts, so its soundfonts have to be set with a
+    /// `SynthEvent::Channel` event.
+    pub fn add_channel(&mut self, options: ChannelInitOptions) -> ChannelId {
+        let data = self.data.as_mut().unwrap();
+        let pool = data.channel_threads.pool.clone();
+        let mut channel = VoiceChannel::new(options, self.stream_params, pool);
+        channel.set_tempo_clock(data.event_senders.tempo_clock());
+        let stats = channel.get_channel_stats();
+
+        let (event_sender, event_receiver) = unbounded();
+        let id = data.channel_queues.add(event_sender.clone());
+
+        let mut channel_stats = self.stats.channel_stats.write().unwrap();
+        if channel_stats.len() <= id as usize {
+            channel_stats.resize_with(id as usize + 1, || None);
+        }
+        channel_stats[id as usize] = Some(channel.get_channel_stats());
+        drop(channel_stats);
+
+        let (commands, join_handle) = data.channel_threads.spawn(id, channel, event_receiver);
+        let channel = RenderChannel {
+            commands,
+            events: event_sender,
+            stats,
+        };
+        data.channel_changes
+            .send(ChannelChange::Add(id, channel))
+            .ok();
+
+        // The threads of removed channels end on their own
+        self.join_handles.retain(|handle| !handle.is_finished());
+        self.join_handles.push(join_handle);
+        id
+    }
+
+    /// Removes the channel with the given ID. Returns false if there is no
+    /// such channel.
+    ///
+    /// The events already sent to the channel are applied, and its notes are
+    /// released like with `ChannelAudioEvent::AllNotesOff`. The channel is
+    /// removed at the next render quantum, but keeps playing until its
+    /// voices end, for at most 5 seconds. Then its thread stops and drops
+    /// it, so the render thread never frees its memory. Events sent to the
+    /// ID afterwards are ignored, until the ID is reused by `add_channel`.
+    pub fn remove_channel(&mut self, id: ChannelId) -> bool {
+        let data = self.data.as_mut().unwrap();
+        let Some(queue) = data.channel_queues.remove(id) else {
+            return false;
+        };
+
+        let event = ChannelEvent::Audio(ChannelAudioEvent::AllNotesOff);
+        let time = data.event_senders.clock().now();
+        queue.send(TimedEvent { time, event }).ok();
+
+        if let Some(stats) = self
+            .stats
+            .channel_stats
+            .write()
+            .unwrap()
+            .get_mut(id as usize)
+        {
+            *stats = None;
+        }
+        self.stats.thread_layout.release(SynthThread::Channel(id));
+        data.channel_changes.send(ChannelChange::Remove(id)).ok();
+        true
+    }
+
+    /// Returns true if a channel with the given ID exists.
+    pub fn has_channel(&self, id: ChannelId) -> bool {
+        self.data.as_ref().unwrap().channel_queues.contains(id)
+    }
+
     /// Returns a reference to the event sender of the realtime synthesizer.
     /// This can be used to clone the sender so it can be passed in threads.
     ///
@@ -330,11 +1323,345 @@ impl RealtimeSynth {
         RealtimeSynthStatsReader::new(self.stats.clone(), buffered_stats)
     }
 
-    /// Returns the stream parameters of the audio output device.
+    /// Starts capturing the events sent to the synthesizer, including the ones
+    /// sent from cloned event senders.
+    ///
+    /// See the `EventRecorder` documentation for more information.
+    pub fn start_recording(&self) {
+        self.get_recorder().start();
+    }
+
+    /// Stops capturing events. The captured events are kept until cleared.
+    pub fn stop_recording(&self) {
+        self.get_recorder().stop();
+    }
+
+    /// Removes all the captured events.
+    pub fn clear_recording(&self) {
+        self.get_recorder().clear();
+    }
+
+    /// Returns a reference to the event recorder of the synthesizer, which
+    /// can be used to export the captured events.
+    ///
+    /// See the `EventRecorder` documentation for more information.
+    pub fn get_recorder(&self) -> &EventRecorder {
+        let data = self.data.as_ref().unwrap();
+        &data.recorder
+    }
+
+    /// Returns the statistics history of the synthesizer, or `None` if it
+    /// wasn't enabled in the config.
+    ///
+    /// See the `StatsHistory` documentation for more information.
+    pub fn stats_history(&self) -> Option<&StatsHistory> {
+        let data = self.data.as_ref().unwrap();
+        data.stats_history.as_deref()
+    }
+
+    /// Returns the stream parameters the synthesizer renders with. Soundfonts
+    /// should be loaded with these parameters.
+    ///
+    /// The sample rate may differ from the one of the audio output device if
+    /// a rate was set in the config, see `output_stream_params`.
     pub fn stream_params(&self) -> AudioStreamParams {
         self.stream_params
     }
 
+    /// Returns the stream parameters of the audio output device. If the sample
+    /// rate differs from the one in `stream_params`, the rendered audio is
+    /// resampled before being sent to the device. With routed output (see
+    /// `XSynthRealtimeConfig::output_routing`), the channels are the ones of
+    /// each output pair.
+    pub fn output_stream_params(&self) -> AudioStreamParams {
+        self.output_params
+    }
+
+    /// Returns the latency added by resampling the rendered audio to the rate
+    /// of the audio output device. Zero when the rates match.
+    pub fn resampler_latency(&self) -> Duration {
+        self.resampler_latency
+    }
+
+    /// Returns the latency added by the master effects chain, which is the
+    /// sum of the latencies of its elements.
+    pub fn master_effects_latency(&self) -> Duration {
+        let frames = self.get_sender_ref().master_effects_latency();
+        Duration::from_secs_f64(frames as f64 / self.output_params.sample_rate as f64)
+    }
+
+    /// Returns the tempo clock of the synthesizer, set with
+    /// `SynthEvent::SetTempo`, to sync effects such as a `StereoDelay` to
+    /// it. The tempo changes apply when they are sent, not at their time.
+    pub fn tempo_clock(&self) -> TempoClock {
+        self.get_sender_ref().tempo_clock()
+    }
+
+    /// Returns an estimate of the latency between sending an event and
+    /// hearing it, by stage of the output path. The estimate follows the
+    /// render timing and the output device, e.g. after `reopen_output`.
+    ///
+    /// See the `LatencyBreakdown` documentation for more information.
+    pub fn latency(&self) -> LatencyBreakdown {
+        let output_rate = self.output_params.sample_rate;
+        let to_output_frames =
+            |duration: Duration| (duration.as_secs_f64() * output_rate as f64).round() as u64;
+
+        let device_buffer = match self.stats.device_buffer_frames.load(Ordering::Relaxed) {
+            0 => self.report.device_buffer_frames.unwrap_or(0) as u64,
+            frames => frames,
+        };
+
+        let timing = self.render_timing();
+        let rendered_frames = timing.render_window_frames * (timing.render_ahead + 1);
+        let render_buffer = to_output_frames(Duration::from_secs_f64(
+            rendered_frames as f64 / self.stream_params.sample_rate as f64,
+        ));
+
+        LatencyBreakdown::new(
+            output_rate,
+            device_buffer,
+            render_buffer,
+            to_output_frames(self.resampler_latency),
+            self.get_sender_ref().master_effects_latency() as u64,
+        )
+    }
+
+    /// Returns the SIMD instruction set the synthesizer renders with. See
+    /// `XSynthRealtimeConfig::simd_backend` to override it.
+    pub fn simd_backend(&self) -> SimdBackend {
+        active_simd_backend()
+    }
+
+    /// Returns a summary of the SIMD backend, the threads, the audio output
+    /// and the latency of the synthesizer, e.g. to show diagnostics. Its
+    /// `Display` implementation formats it for humans.
+    ///
+    /// See the `CapabilityReport` documentation for more information.
+    pub fn capability_report(&self) -> CapabilityReport {
+        let timing = self.render_timing();
+        let rendered_frames = timing.render_window_frames * (timing.render_ahead + 1);
+        let render_latency =
+            Duration::from_secs_f64(rendered_frames as f64 / self.stream_params.sample_rate as f64);
+        let device_latency = self
+            .report
+            .device_buffer_frames
+            .map_or(Duration::ZERO, |frames| {
+                Duration::from_secs_f64(frames as f64 / self.output_params.sample_rate as f64)
+            });
+
+        let realtime_priority =
+            (self.report.thread_priority == ThreadPriority::Realtime).then(|| {
+                !self
+                    .stats
+                    .diagnostics
+                    .diagnostics()
+                    .iter()
+                    .any(|d| matches!(d, SynthDiagnostic::PriorityRequestFailed { .. }))
+            });
+
+        CapabilityReport {
+            latency: render_latency
+                + self.resampler_latency
+                + self.master_effects_latency()
+                + device_latency,
+            realtime_priority,
+            ..self.report.clone()
+        }
+    }
+
+    /// Moves the audio output to another device or output config, e.g. when
+    /// the sample rate of the audio interface changed, without recreating
+    /// the synthesizer.
+    ///
+    /// Only the output stream and the resampler are rebuilt. The synthesizer
+    /// keeps rendering at the rate of `stream_params`, so the loaded
+    /// soundfonts, the playing voices and the event senders are kept, and
+    /// the rendered audio is resampled to the new output rate. See
+    /// `set_sample_rate` to render natively at the new rate instead.
+    ///
+    /// The new stream is built while the previous one keeps playing, and the
+    /// previous one is closed before the new one is started. Some hosts start
+    /// streams when they are built, in which case both play for a moment.
+    ///
+    /// Returns an error if the channel count of `stream_config` differs from
+    /// the one the synthesizer renders with, or if the new stream can't be
+    /// built, in which cases the previous output keeps playing, or if the new
+    /// stream can't be started.
+    pub fn reopen_output(
+        &mut self,
+        device: &Device,
+        stream_config: SupportedStreamConfig,
+    ) -> Result<(), AudioStreamError> {
+        let data = self.data.as_mut().unwrap();
+        check_output_channels(&stream_config, self.device_channels)?;
+        let output_params =
+            AudioStreamParams::new(stream_config.sample_rate().0, self.output_params.channels);
+
+        let sample_rate = self.stream_params.sample_rate;
+        let resampler = StreamResampler::new(
+            self.frame_channels as u16,
+            sample_rate,
+            output_params.sample_rate,
+            self.resampler_quality,
+        );
+        let resampler_latency =
+            Duration::from_secs_f64(resampler.latency_frames() as f64 / sample_rate as f64);
+        let (resamplers, resampler_receiver) = unbounded();
+
+        let state = OutputState {
+            buffered: data.buffered_renderer.clone(),
+            fade_frames: data.fade_frames.clone(),
+            limiter_time: self.stats.limiter_time.clone(),
+            device_buffer_frames: self.stats.device_buffer_frames.clone(),
+            underrun_callback: data.underrun_callback.clone(),
+            resampler,
+            resamplers: resampler_receiver,
+            master_effects: data.master_effects.clone(),
+            flush_denormals: self.flush_denormals,
+            output_params,
+            frame_channels: self.frame_channels,
+        };
+        let device_buffer_frames =
+            clamp_device_buffer_frames(self.requested_buffer_frames, &stream_config);
+        let sample_format = stream_config.sample_format().to_string();
+        let stream = build_output_stream(device, stream_config, device_buffer_frames, state)?;
+        data.resamplers = resamplers;
+        self.resampler_latency = resampler_latency;
+        self.report.audio_host = None;
+        self.report.device_name = device.name().unwrap_or_default();
+        self.report.sample_format = sample_format;
+        self.report.output_sample_rate = output_params.sample_rate;
+        self.report.device_buffer_frames = device_buffer_frames;
+
+        // Unless the host already started the new stream, the old stream is
+        // closed before the new one starts, so they don't both read from the
+        // renderer
+        drop(std::mem::replace(&mut data.stream, SendSyncStream(stream)));
+        // The new device reports its own buffer size in its first callback
+        self.stats.device_buffer_frames.store(0, Ordering::Relaxed);
+        data.stream.0.play()?;
+        self.output_params = output_params;
+        Ok(())
+    }
+
+    /// Switches the synthesizer to render at another sample rate, e.g. to
+    /// render natively at the rate of an output moved with `reopen_output`,
+    /// without recreating the synthesizer.
+    ///
+    /// The playing voices are killed, as their samples were resampled for
+    /// the previous rate. The controls, the soundfont routing of the
+    /// channels and the event senders are kept, and the soundfonts are
+    /// loaded again at the new rate, see `ChannelConfigEvent::SetSampleRate`.
+    /// They are loaded on the calling thread before the channels switch to
+    /// them, so this blocks until they are loaded, while the output keeps
+    /// playing. The render window keeps its length in time, the rendered
+    /// audio is resampled if the rate differs from the one of the output
+    /// device, and the events captured by the recorder are removed.
+    ///
+    /// Returns an error if the rate is zero, in which case nothing changes,
+    /// or if soundfonts failed to load at the new rate, in which case the
+    /// synthesizer still switches to it and the channels keep playing these
+    /// soundfonts at the previous rate.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) -> Result<(), SampleRateError> {
+        if sample_rate == 0 {
+            return Err(SampleRateError::InvalidSampleRate(sample_rate));
+        }
+        let previous_rate = self.stream_params.sample_rate;
+        let data = self.data.as_mut().unwrap();
+
+        // The soundfonts are loaded here instead of on the render threads,
+        // which only swap them in when they receive the change
+        let change = SampleRateChange::new(sample_rate);
+        let channel_stats = self.stats.channel_stats.read().unwrap();
+        let soundfonts = channel_stats.iter().flatten().flat_map(|s| s.soundfonts());
+        change.prepare(soundfonts.collect::<Vec<_>>());
+        drop(channel_stats);
+        let errors = change.take_errors();
+        data.event_senders
+            .send_event(SynthEvent::AllChannels(ChannelEvent::Config(
+                ChannelConfigEvent::SetSampleRate(change),
+            )));
+        data.sample_rates.send(sample_rate).unwrap();
+
+        let mut timing = self.stats.render_timing.write().unwrap();
+        let window = timing.render_window_frames as u64 * sample_rate as u64 / previous_rate as u64;
+        timing.set_window(window as usize);
+        let mut buffered = data.buffered_renderer.lock().unwrap();
+        buffered.set_sample_rate(sample_rate);
+        timing.apply(&buffered);
+        drop(buffered);
+        data.channel_threads.render_period = timing.quantum_period(sample_rate);
+        drop(timing);
+
+        let resampler = StreamResampler::new(
+            self.frame_channels as u16,
+            sample_rate,
+            self.output_params.sample_rate,
+            self.resampler_quality,
+        );
+        self.resampler_latency =
+            Duration::from_secs_f64(resampler.latency_frames() as f64 / sample_rate as f64);
+        data.resamplers.send(resampler).ok();
+
+        data.recorder.set_sample_rate(sample_rate);
+        self.stream_params.sample_rate = sample_rate;
+        self.report.render_sample_rate = sample_rate;
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(SampleRateError::Soundfonts(errors))
+        }
+    }
+
+    /// Renders a few silent quanta through the whole render path (all the
+    /// channels, the mixing and the master effects, or the limiters of the
+    /// output pairs) and discards them. This allocates the buffers and
+    /// exercises the render code before the first note, which otherwise
+    /// can glitch. See `XSynthRealtimeConfig::warm_up` to warm up before
+    /// the audio output starts.
+    ///
+    /// The audio output is paused during the warm-up and resumed afterwards.
+    /// The events sent before are applied during the warm-up, so it should
+    /// be called before sending notes.
+    pub fn warm_up(&mut self) {
+        let frames = self.render_timing().render_quantum_frames;
+        let data = self.data.as_mut().unwrap();
+        data.stream.0.pause().ok();
+
+        let device_channels = self.device_channels as usize;
+        let mut rendered = vec![0.0; frames * self.frame_channels];
+        let mut output = vec![0.0; frames * device_channels];
+        let mut pair_buffer = Vec::new();
+        {
+            let mut render = data.render.lock().unwrap();
+            let mut master_effects = data.master_effects.lock().unwrap();
+            let MasterEffects {
+                chain,
+                pair_limiters,
+                ..
+            } = &mut *master_effects;
+            for _ in 0..WARM_UP_QUANTA {
+                rendered.fill(0.0);
+                (*render)(&mut rendered, true);
+                if pair_limiters.is_empty() {
+                    chain.process(&mut rendered, &self.output_params);
+                } else {
+                    interleave_pairs(
+                        &rendered,
+                        pair_limiters,
+                        &mut pair_buffer,
+                        &mut output,
+                        device_channels,
+                    );
+                }
+            }
+        }
+
+        data.stream.0.play().unwrap();
+    }
+
     /// Pauses the playback of the audio output device.
     pub fn pause(&mut self) -> Result<(), PauseStreamError> {
         let data = self.data.as_mut().unwrap();
@@ -347,47 +1674,130 @@ impl RealtimeSynth {
         data.stream.0.play()
     }
 
-    /// Changes the length of the buffer reader.
+    /// Changes the length of the buffer reader. The render quantum is shrunk
+    /// to the new window if it is larger.
     pub fn set_buffer(&self, render_window_ms: f64) {
         let data = self.data.as_ref().unwrap();
         let sample_rate = self.stream_params.sample_rate;
-        let size = calculate_render_size(sample_rate, render_window_ms);
-        data.buffered_renderer.lock().unwrap().set_render_size(size);
+        let mut timing = self.stats.render_timing.write().unwrap();
+        timing.set_window(calculate_render_size(sample_rate, render_window_ms));
+        timing.apply(&data.buffered_renderer.lock().unwrap());
     }
-}
 
-impl Drop for RealtimeSynth {
-    fn drop(&mut self) {
-        let data = self.data.take().unwrap();
+    /// Returns the effective render timing of the synthesizer.
+    ///
+    /// See the `RenderTiming` documentation for more information.
+    pub fn render_timing(&self) -> RenderTiming {
+        *self.stats.render_timing.read().unwrap()
+    }
+
+    /// Sets a callback that is invoked whenever the audio output requests more
+    /// samples than were rendered (a buffer underrun), with the number of
+    /// missing samples. Replaces the previous callback, if any.
+    ///
+    /// The callback runs on the audio output thread, so it should return
+    /// quickly and must not block.
+    pub fn set_underrun_callback(&self, callback: impl FnMut(usize) + Send + 'static) {
+        let data = self.data.as_ref().unwrap();
+        *data.underrun_callback.lock().unwrap() = Some(Box::new(callback));
+    }
+
+    /// Removes the underrun callback, if any.
+    pub fn clear_underrun_callback(&self) {
+        let data = self.data.as_ref().unwrap();
+        *data.underrun_callback.lock().unwrap() = None;
+    }
+
+    /// Changes the number of render windows kept pre-rendered ahead of the
+    /// audio output. `0` disables render-ahead.
+    pub fn set_render_ahead(&self, render_ahead: usize) {
+        let data = self.data.as_ref().unwrap();
+        let mut timing = self.stats.render_timing.write().unwrap();
+        timing.set_render_ahead(render_ahead);
+        timing.apply(&data.buffered_renderer.lock().unwrap());
+    }
+
+    /// Gracefully shuts down the realtime synthesizer.
+    ///
+    /// Stops accepting events (including from cloned event senders), releases
+    /// all notes and keeps rendering until all the voices have ended or a
+    /// timeout of a few seconds has elapsed. Then it applies a master fade
+    /// out of `fade_ms` milliseconds, closes the audio stream and waits for
+    /// all the render threads to finish.
+    ///
+    /// Dropping the synth does the same, but without waiting for the voices
+    /// and with a minimal fade.
+    pub fn shutdown(mut self, fade_ms: f64) -> ShutdownSummary {
+        self.shutdown_with_timeout(fade_ms, SHUTDOWN_TIMEOUT)
+    }
+
+    fn shutdown_with_timeout(&mut self, fade_ms: f64, timeout: Duration) -> ShutdownSummary {
+        let mut data = self.data.take().unwrap();
+        let start_buffers = self.stats.rendered_buffers.load(Ordering::Relaxed);
+
+        data.event_senders.close();
+        data.event_senders
+            .send_event_unchecked(SynthEvent::AllChannels(ChannelEvent::Audio(
+                ChannelAudioEvent::AllNotesOff,
+            )));
+
+        let deadline = Instant::now() + timeout;
+        while self.stats.voice_count.load(Ordering::Relaxed) > 0 && Instant::now() < deadline {
+            thread::sleep(SHUTDOWN_POLL_INTERVAL);
+        }
+
+        let fade_ms = fade_ms.max(0.0);
+        // The fade is applied to the device output, after resampling
+        let fade_frames = calculate_render_size(self.output_params.sample_rate, fade_ms) as u64;
+        data.fade_frames
+            .store(fade_frames.max(1), Ordering::Relaxed);
+        spin_sleep::sleep(Duration::from_secs_f64(fade_ms / 1000.0) + SHUTDOWN_FADE_MARGIN);
+
+        let summary = ShutdownSummary {
+            voices_remaining: self.stats.voice_count.load(Ordering::Relaxed),
+            buffers_rendered: self.stats.rendered_buffers.load(Ordering::Relaxed) - start_buffers,
+        };
+
         drop(data);
         for handle in self.join_handles.drain(..) {
             handle.join().unwrap();
         }
-    }
-}
 
-trait ConvertSample: SizedSample {
-    fn from_f32(s: f32) -> Self;
-}
-
-impl ConvertSample for f32 {
-    fn from_f32(s: f32) -> Self {
-        s
+        summary
     }
 }
 
-impl ConvertSample for i16 {
-    fn from_f32(s: f32) -> Self {
-        (s * i16::MAX as f32) as i16
+impl Drop for RealtimeSynth {
+    fn drop(&mut self) {
+        if self.data.is_some() {
+            self.shutdown_with_timeout(DROP_FADE_MS, Duration::ZERO);
+        }
     }
 }
 
-impl ConvertSample for u16 {
-    fn from_f32(s: f32) -> Self {
-        ((s * u16::MAX as f32) as i32 + i16::MIN as i32) as u16
+#[cfg(test)]
+mod tests {
+    use cpal::{SampleFormat, SampleRate};
+
+    use super::*;
+
+    #[test]
+    fn test_check_output_channels() {
+        let config = |channels| {
+            SupportedStreamConfig::new(
+                channels,
+                SampleRate(48000),
+                SupportedBufferSize::Unknown,
+                SampleFormat::F32,
+            )
+        };
+        assert!(check_output_channels(&config(2), 2).is_ok());
+        assert!(matches!(
+            check_output_channels(&config(6), 2),
+            Err(AudioStreamError::ChannelCountChanged {
+                expected: 2,
+                found: 6
+            })
+        ));
     }
 }
-
-fn calculate_render_size(sample_rate: u32, buffer_ms: f64) -> usize {
-    (sample_rate as f64 * buffer_ms / 1000.0) as usize
-}
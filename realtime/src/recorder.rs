@@ -0,0 +1,303 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use xsynth_core::channel::{ChannelAudioEvent, ChannelEvent, ControlEvent};
+
+use crate::SynthEvent;
+
+/// Ticks per quarter note used in exported MIDI files.
+const SMF_PPQ: u16 = 960;
+
+/// Tempo used in exported MIDI files, in microseconds per quarter note (120 BPM).
+const SMF_TEMPO: u32 = 500_000;
+
+/// A single event captured by the `EventRecorder`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RecordedEvent {
+    /// The audio clock position of the event, in frames (samples per channel).
+    pub frame: u64,
+
+    /// The channel the event was sent to. `None` if it was sent to all channels.
+    pub channel: Option<u32>,
+
+    /// The recorded event.
+    pub event: ChannelAudioEvent,
+}
+
+struct RecorderData {
+    events: VecDeque<RecordedEvent>,
+    capacity: usize,
+}
+
+/// Captures the audio events sent to a realtime synthesizer, timestamped
+/// against its audio clock, for debugging and reproduction purposes.
+///
+/// The recorder keeps at most the configured number of events and drops the
+/// oldest ones when full. Config events (e.g. soundfont changes) are not
+/// recorded.
+pub struct EventRecorder {
+    recording: AtomicBool,
+    clock: Arc<AtomicU64>,
+    sample_rate: AtomicU32,
+    channel_count: u32,
+    data: Mutex<RecorderData>,
+}
+
+impl EventRecorder {
+    pub(crate) fn new(
+        clock: Arc<AtomicU64>,
+        sample_rate: u32,
+        channel_count: u32,
+        capacity: usize,
+    ) -> Self {
+        EventRecorder {
+            recording: AtomicBool::new(false),
+            clock,
+            sample_rate: AtomicU32::new(sample_rate),
+            channel_count,
+            data: Mutex::new(RecorderData {
+                events: VecDeque::new(),
+                capacity,
+            }),
+        }
+    }
+
+    /// Starts capturing events.
+    pub fn start(&self) {
+        self.recording.store(true, Ordering::Relaxed);
+    }
+
+    /// Stops capturing events. The captured events are kept.
+    pub fn stop(&self) {
+        self.recording.store(false, Ordering::Relaxed);
+    }
+
+    /// Returns true if events are currently being captured.
+    pub fn is_recording(&self) -> bool {
+        self.recording.load(Ordering::Relaxed)
+    }
+
+    /// Removes all the captured events.
+    pub fn clear(&self) {
+        self.data.lock().unwrap().events.clear();
+    }
+
+    /// Sets the sample rate of the audio clock, see
+    /// `RealtimeSynth::set_sample_rate`. The captured events are removed, as
+    /// their frames were counted at the previous rate.
+    pub(crate) fn set_sample_rate(&self, sample_rate: u32) {
+        let mut data = self.data.lock().unwrap();
+        data.events.clear();
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+    }
+
+    /// Returns the number of captured events.
+    pub fn len(&self) -> usize {
+        self.data.lock().unwrap().events.len()
+    }
+
+    /// Returns true if no events have been captured.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a copy of the captured events, oldest first.
+    pub fn events(&self) -> Vec<RecordedEvent> {
+        self.data.lock().unwrap().events.iter().copied().collect()
+    }
+
+    pub(crate) fn record(&self, event: &SynthEvent) {
+        if !self.is_recording() {
+            return;
+        }
+
+        let (channel, event) = match event {
+            SynthEvent::Channel(channel, ChannelEvent::Audio(e)) => (Some(*channel), *e),
+            SynthEvent::AllChannels(ChannelEvent::Audio(e)) => (None, *e),
+            _ => return,
+        };
+
+        let frame = self.clock.load(Ordering::Relaxed);
+
+        let mut data = self.data.lock().unwrap();
+        if data.capacity == 0 {
+            return;
+        }
+        while data.events.len() >= data.capacity {
+            data.events.pop_front();
+        }
+        data.events.push_back(RecordedEvent {
+            frame,
+            channel,
+            event,
+        });
+    }
+
+    /// Writes the captured events as a standard MIDI file (type 1), with a
+    /// tempo track followed by one track per synth channel. Events sent to
+    /// all channels are written to every channel track. If the synth has more
+    /// than 16 channels, each track starts with a MIDI port meta event.
+    pub fn write_smf(&self, mut out: impl Write) -> io::Result<()> {
+        let events = self.events();
+        let start_frame = events.iter().map(|e| e.frame).min().unwrap_or(0);
+        let ticks_per_second = SMF_PPQ as f64 * 1_000_000.0 / SMF_TEMPO as f64;
+
+        let mut tracks = Vec::new();
+
+        // Tempo track
+        let mut tempo = Vec::new();
+        write_var_len(&mut tempo, 0);
+        tempo.extend_from_slice(&[0xFF, 0x51, 0x03]);
+        tempo.extend_from_slice(&SMF_TEMPO.to_be_bytes()[1..]);
+        write_end_of_track(&mut tempo);
+        tracks.push(tempo);
+
+        let sample_rate = self.sample_rate.load(Ordering::Relaxed);
+        for channel in 0..self.channel_count {
+            let mut track = Vec::new();
+            let mut last_tick = 0u64;
+            let status_channel = (channel & 0xF) as u8;
+
+            if self.channel_count > 16 {
+                // MIDI port meta event, so channels above 16 can be told apart
+                write_var_len(&mut track, 0);
+                track.extend_from_slice(&[0xFF, 0x21, 0x01, (channel / 16) as u8]);
+            }
+
+            for e in events
+                .iter()
+                .filter(|e| e.channel.is_none_or(|c| c == channel))
+            {
+                let seconds = e.frame.saturating_sub(start_frame) as f64 / sample_rate as f64;
+                // Senders on different threads can record slightly out of order
+                let tick = ((seconds * ticks_per_second).round() as u64).max(last_tick);
+
+                for message in midi_messages(&e.event, status_channel) {
+                    write_var_len(&mut track, (tick - last_tick) as u32);
+                    track.extend_from_slice(&message);
+                    last_tick = tick;
+                }
+            }
+
+            write_end_of_track(&mut track);
+            tracks.push(track);
+        }
+
+        out.write_all(b"MThd")?;
+        out.write_all(&6u32.to_be_bytes())?;
+        out.write_all(&1u16.to_be_bytes())?;
+        out.write_all(&(tracks.len() as u16).to_be_bytes())?;
+        out.write_all(&SMF_PPQ.to_be_bytes())?;
+
+        for track in tracks {
+            out.write_all(b"MTrk")?;
+            out.write_all(&(track.len() as u32).to_be_bytes())?;
+            out.write_all(&track)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the captured events as a JSON array, for debugging purposes.
+    pub fn write_json(&self, mut out: impl Write) -> io::Result<()> {
+        let events = self.events();
+        let sample_rate = self.sample_rate.load(Ordering::Relaxed);
+
+        writeln!(out, "[")?;
+        for (i, e) in events.iter().enumerate() {
+            let channel = match e.channel {
+                Some(c) => c.to_string(),
+                None => "null".to_string(),
+            };
+            let event = format!("{:?}", e.event)
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"");
+            let separator = if i + 1 < events.len() { "," } else { "" };
+            writeln!(
+                out,
+                "  {{\"frame\": {}, \"time\": {}, \"channel\": {}, \"event\": \"{}\"}}{}",
+                e.frame,
+                e.frame as f64 / sample_rate as f64,
+                channel,
+                event,
+                separator
+            )?;
+        }
+        writeln!(out, "]")?;
+
+        Ok(())
+    }
+}
+
+fn write_var_len(out: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = [0u8; 5];
+    let mut len = 0;
+    loop {
+        bytes[len] = (value & 0x7F) as u8;
+        len += 1;
+        value >>= 7;
+        if value == 0 {
+            break;
+        }
+    }
+    for (i, byte) in bytes[..len].iter().enumerate().rev() {
+        let continuation = if i > 0 { 0x80 } else { 0 };
+        out.push(byte | continuation);
+    }
+}
+
+fn write_end_of_track(out: &mut Vec<u8>) {
+    write_var_len(out, 0);
+    out.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+}
+
+/// Converts a channel audio event to the equivalent raw MIDI messages.
+fn midi_messages(event: &ChannelAudioEvent, channel: u8) -> Vec<Vec<u8>> {
+    let cc = |controller: u8, value: u8| vec![0xB0 | channel, controller, value & 0x7F];
+
+    match *event {
+        // MIDI can't carry the cents offset, so detuned notes are recorded at
+        // their key
+        ChannelAudioEvent::NoteOn { key, vel }
+        | ChannelAudioEvent::NoteOnDetuned { key, vel, .. } => {
+            vec![vec![0x90 | channel, key & 0x7F, vel & 0x7F]]
+        }
+        // A note off is the closest MIDI equivalent of killing a single key
+        ChannelAudioEvent::NoteOff { key } | ChannelAudioEvent::NoteKill { key } => {
+            vec![vec![0x80 | channel, key & 0x7F, 0]]
+        }
+        // MIDI 1.0 has no per-note pitch bend
+        ChannelAudioEvent::NotePitchBend { .. } => Vec::new(),
+        ChannelAudioEvent::AllNotesOff => vec![cc(0x7B, 0)],
+        ChannelAudioEvent::AllNotesKilled => vec![cc(0x78, 0)],
+        ChannelAudioEvent::ResetControl => vec![cc(0x79, 0)],
+        ChannelAudioEvent::ResetChannel => vec![cc(0x78, 0), cc(0x79, 0)],
+        ChannelAudioEvent::ProgramChange(preset) => vec![vec![0xC0 | channel, preset & 0x7F]],
+        ChannelAudioEvent::SystemReset => {
+            // GM System On
+            vec![vec![0xF0, 0x05, 0x7E, 0x7F, 0x09, 0x01, 0xF7]]
+        }
+        ChannelAudioEvent::Control(control) => match control {
+            ControlEvent::PitchBendValue(value) => {
+                let value = ((value.clamp(-1.0, 1.0) * 8192.0) as i32 + 8192).clamp(0, 16383);
+                vec![vec![
+                    0xE0 | channel,
+                    (value & 0x7F) as u8,
+                    ((value >> 7) & 0x7F) as u8,
+                ]]
+            }
+            ControlEvent::ChannelPressure(value) => vec![vec![0xD0 | channel, value & 0x7F]],
+            control => match control.to_raw() {
+                Some((controller, value)) => vec![cc(controller, value)],
+                // Processed control values have no direct MIDI equivalent
+                None => Vec::new(),
+            },
+        },
+    }
+}
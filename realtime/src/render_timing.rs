@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use xsynth_core::buffered_renderer::BufferedRenderer;
+
+use crate::XSynthRealtimeConfig;
+
+/// The effective render timing of a realtime synthesizer, in frames at the
+/// render sample rate. See the `XSynthRealtimeConfig` documentation for
+/// more information on each value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderTiming {
+    /// The buffer size requested from the audio output device after
+    /// clamping it to the supported range, or `None` if the device default
+    /// is used. The statistics report the buffer sizes actually requested
+    /// by the device.
+    pub device_buffer_frames: Option<u32>,
+
+    /// The number of frames rendered per iteration of the render thread.
+    /// Never larger than the render window.
+    pub render_quantum_frames: usize,
+
+    /// The number of frames the render thread keeps rendered ahead of the
+    /// audio output.
+    pub render_window_frames: usize,
+
+    /// The number of extra render windows kept pre-rendered.
+    pub render_ahead: usize,
+
+    /// The configured render quantum, kept to apply it again when the render
+    /// window changes
+    configured_quantum: Option<usize>,
+}
+
+impl RenderTiming {
+    pub(crate) fn new(
+        config: &XSynthRealtimeConfig,
+        sample_rate: u32,
+        device_buffer_frames: Option<u32>,
+    ) -> Self {
+        let mut timing = Self {
+            device_buffer_frames,
+            render_quantum_frames: 0,
+            render_window_frames: 0,
+            render_ahead: config.render_ahead,
+            configured_quantum: config.render_quantum_frames,
+        };
+        timing.set_window(calculate_render_size(sample_rate, config.render_window_ms));
+        timing
+    }
+
+    /// Changes the render window. The render quantum is shrunk to the window
+    /// if needed.
+    pub(crate) fn set_window(&mut self, frames: usize) {
+        self.render_window_frames = frames.max(1);
+        self.render_quantum_frames = match self.configured_quantum {
+            Some(quantum) => quantum.clamp(1, self.render_window_frames),
+            None => self.render_window_frames,
+        };
+    }
+
+    pub(crate) fn set_render_ahead(&mut self, render_ahead: usize) {
+        self.render_ahead = render_ahead;
+    }
+
+    /// The time it takes to play one render quantum.
+    pub(crate) fn quantum_period(&self, sample_rate: u32) -> Duration {
+        Duration::from_secs_f64(self.render_quantum_frames as f64 / sample_rate as f64)
+    }
+
+    /// The number of render quanta the buffered renderer keeps pre-rendered.
+    ///
+    /// A render iteration of a whole window already keeps one window
+    /// buffered, so with smaller quanta the renderer keeps the rest of the
+    /// window pre-rendered on top of the render-ahead windows.
+    fn render_ahead_quanta(&self) -> usize {
+        let quanta_per_window = self
+            .render_window_frames
+            .div_ceil(self.render_quantum_frames);
+        self.render_ahead * quanta_per_window + quanta_per_window - 1
+    }
+
+    /// Applies the timing to the buffered renderer of the synthesizer.
+    pub(crate) fn apply(&self, buffered: &BufferedRenderer) {
+        buffered.set_render_size(self.render_quantum_frames);
+        buffered.set_render_ahead(self.render_ahead_quanta());
+    }
+}
+
+pub(crate) fn calculate_render_size(sample_rate: u32, buffer_ms: f64) -> usize {
+    (sample_rate as f64 * buffer_ms / 1000.0) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(window_ms: f64, quantum: Option<usize>, render_ahead: usize) -> RenderTiming {
+        let config = XSynthRealtimeConfig {
+            render_window_ms: window_ms,
+            render_quantum_frames: quantum,
+            render_ahead,
+            ..Default::default()
+        };
+        RenderTiming::new(&config, 48000, None)
+    }
+
+    #[test]
+    fn test_render_ahead_quanta() {
+        // Without a quantum, the render-ahead is counted in windows as before
+        let t = timing(10.0, None, 2);
+        assert_eq!(t.render_quantum_frames, 480);
+        assert_eq!(t.render_ahead_quanta(), 2);
+
+        // With 120 frame quanta, the rest of the window is kept buffered
+        let t = timing(10.0, Some(120), 0);
+        assert_eq!(t.render_ahead_quanta(), 3);
+        let t = timing(10.0, Some(120), 2);
+        assert_eq!(t.render_ahead_quanta(), 11);
+    }
+
+    #[test]
+    fn test_quantum_shrinks_with_window() {
+        let mut t = timing(10.0, Some(128), 0);
+        t.set_window(64);
+        assert_eq!(t.render_quantum_frames, 64);
+        t.set_window(1024);
+        assert_eq!(t.render_quantum_frames, 128);
+    }
+
+    #[test]
+    fn test_validate() {
+        let config = XSynthRealtimeConfig {
+            render_quantum_frames: Some(1024),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(48000),
+            Err(crate::ConfigError::QuantumLargerThanWindow {
+                quantum: 1024,
+                window: 480
+            })
+        );
+        assert!(config.validate(192000).is_ok());
+    }
+}
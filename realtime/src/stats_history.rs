@@ -0,0 +1,157 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The statistics of a single render quantum, captured by the `StatsHistory`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StatsHistoryEntry {
+    /// The time the quantum finished rendering, since the synthesizer was
+    /// opened.
+    pub time: Duration,
+
+    /// The active voice count after the quantum was rendered.
+    pub voice_count: u64,
+
+    /// The time it took to render the quantum.
+    pub render_time: Duration,
+
+    /// The number of samples buffered ahead of the audio output when the
+    /// quantum was rendered. See `BufferedRendererStatsReader::samples`.
+    pub buffer_level: i64,
+
+    /// The total number of buffer underruns (xruns) so far.
+    pub xruns: u64,
+}
+
+impl StatsHistoryEntry {
+    /// Writes the entries as CSV, with a header row. The times are written
+    /// in seconds.
+    pub fn write_csv(entries: &[StatsHistoryEntry], mut out: impl Write) -> io::Result<()> {
+        writeln!(out, "time,voice_count,render_time,buffer_level,xruns")?;
+        for entry in entries {
+            writeln!(
+                out,
+                "{:.6},{},{:.6},{},{}",
+                entry.time.as_secs_f64(),
+                entry.voice_count,
+                entry.render_time.as_secs_f64(),
+                entry.buffer_level,
+                entry.xruns
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Records the statistics of every render quantum of a realtime synthesizer,
+/// to analyze a whole playback session afterwards instead of polling the
+/// statistics.
+///
+/// The history is a ring buffer allocated when the synthesizer is opened.
+/// When full, the oldest entries are dropped. See
+/// `XSynthRealtimeConfig::stats_history` for more information.
+pub struct StatsHistory {
+    created: Instant,
+    capacity: usize,
+    entries: Mutex<VecDeque<StatsHistoryEntry>>,
+}
+
+impl StatsHistory {
+    pub(crate) fn new(capacity: usize) -> Self {
+        StatsHistory {
+            created: Instant::now(),
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// The maximum number of entries the history keeps.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of entries currently recorded.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Returns true if no entries are recorded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes and returns the recorded entries, oldest first.
+    pub fn drain(&self) -> Vec<StatsHistoryEntry> {
+        // The new buffer is allocated before locking, so the render thread
+        // never waits for an allocation
+        let mut entries = VecDeque::with_capacity(self.capacity);
+        std::mem::swap(&mut entries, &mut *self.entries.lock().unwrap());
+        entries.into()
+    }
+
+    /// Removes the recorded entries and writes them as CSV. See
+    /// `StatsHistoryEntry::write_csv`.
+    pub fn drain_csv(&self, out: impl Write) -> io::Result<()> {
+        StatsHistoryEntry::write_csv(&self.drain(), out)
+    }
+
+    pub(crate) fn record(
+        &self,
+        voice_count: u64,
+        render_time: Duration,
+        buffer_level: i64,
+        xruns: u64,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let entry = StatsHistoryEntry {
+            time: self.created.elapsed(),
+            voice_count,
+            render_time,
+            buffer_level,
+            xruns,
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_and_csv() {
+        let history = StatsHistory::new(2);
+        for voices in 1..=3 {
+            history.record(voices, Duration::from_millis(2), 960, 0);
+        }
+
+        let entries = history.drain();
+        assert_eq!(
+            entries.iter().map(|e| e.voice_count).collect::<Vec<_>>(),
+            [2, 3]
+        );
+        assert!(history.is_empty());
+
+        let mut csv = Vec::new();
+        StatsHistoryEntry::write_csv(&entries[..1], &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("time,voice_count,render_time,buffer_level,xruns")
+        );
+        assert!(lines.next().unwrap().ends_with(",2,0.002000,960,0"));
+        assert_eq!(lines.next(), None);
+    }
+}
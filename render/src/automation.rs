@@ -0,0 +1,171 @@
+use xsynth_core::{
+    channel::{ChannelAudioEvent, ChannelEvent, ControlEvent},
+    channel_group::{ChannelGroup, ChannelId, SynthEvent},
+};
+
+/// The number of frames rendered between two evaluations of the automation
+/// lanes.
+pub const AUTOMATION_QUANTUM_FRAMES: usize = 64;
+
+/// A parameter of the render changed by an `AutomationLane`. More targets
+/// may be added in the future.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AutomationTarget {
+    /// The gain of the mixed output before the limiter, as an amplitude
+    /// multiplier. Doesn't apply to the stems.
+    MasterGain,
+
+    /// The volume of a channel, between 0 and 1. See `ControlEvent::Volume`.
+    ChannelVolume(ChannelId),
+
+    /// The pan of a channel, between 0 (left) and 1 (right). See
+    /// `ControlEvent::Pan`.
+    ChannelPan(ChannelId),
+
+    /// The expression of a channel, between 0 and 1. See
+    /// `ControlEvent::Expression`.
+    ChannelExpression(ChannelId),
+
+    /// The level the limiter keeps the output under, as an amplitude where
+    /// `1.0` is full scale. Only applies with the limiter enabled.
+    LimiterThreshold,
+}
+
+impl AutomationTarget {
+    /// Returns the event that applies a value to a channel target, or `None`
+    /// for the targets of the render itself.
+    fn event(self, value: f32) -> Option<SynthEvent> {
+        let (channel, control) = match self {
+            AutomationTarget::ChannelVolume(channel) => (channel, ControlEvent::Volume(value)),
+            AutomationTarget::ChannelPan(channel) => (channel, ControlEvent::Pan(value)),
+            AutomationTarget::ChannelExpression(channel) => {
+                (channel, ControlEvent::Expression(value))
+            }
+            AutomationTarget::MasterGain | AutomationTarget::LimiterThreshold => return None,
+        };
+
+        Some(SynthEvent::Channel(
+            channel,
+            ChannelEvent::Audio(ChannelAudioEvent::Control(control)),
+        ))
+    }
+}
+
+/// How an `AutomationLane` gets from one breakpoint to the next.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AutomationInterpolation {
+    /// A straight line between the breakpoints.
+    #[default]
+    Linear,
+
+    /// The value of each breakpoint is held until the next one.
+    Step,
+}
+
+/// A curve of values of a single parameter over the render, defined by
+/// breakpoints. Before the first breakpoint the parameter has the value of
+/// the first one, and after the last breakpoint the value of the last one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AutomationLane {
+    target: AutomationTarget,
+    breakpoints: Vec<(f64, f32)>,
+    interpolation: AutomationInterpolation,
+}
+
+impl AutomationLane {
+    /// Creates a new lane from breakpoints of a time in seconds and a
+    /// value. The breakpoints can be given in any order.
+    pub fn new(
+        target: AutomationTarget,
+        mut breakpoints: Vec<(f64, f32)>,
+        interpolation: AutomationInterpolation,
+    ) -> Self {
+        breakpoints.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self {
+            target,
+            breakpoints,
+            interpolation,
+        }
+    }
+
+    /// Returns the value of the lane at the given time in seconds, or `None`
+    /// if the lane has no breakpoints.
+    pub fn value_at(&self, time: f64) -> Option<f32> {
+        let next = self.breakpoints.partition_point(|&(t, _)| t <= time);
+        let (t1, v1) = match self.breakpoints.get(next) {
+            Some(&breakpoint) => breakpoint,
+            None => return self.breakpoints.last().map(|&(_, v)| v),
+        };
+        let Some(&(t0, v0)) = next.checked_sub(1).and_then(|i| self.breakpoints.get(i)) else {
+            return Some(v1);
+        };
+
+        match self.interpolation {
+            AutomationInterpolation::Linear => {
+                let position = ((time - t0) / (t1 - t0)) as f32;
+                Some(v0 + (v1 - v0) * position)
+            }
+            AutomationInterpolation::Step => Some(v0),
+        }
+    }
+}
+
+/// Applies the automation lanes of a render once per quantum.
+#[derive(Clone, Debug)]
+pub struct Automation {
+    lanes: Vec<AutomationLane>,
+    /// The last value of each lane, to only send the changes to the channels
+    values: Vec<Option<f32>>,
+    master_gain: f32,
+    limiter_threshold: f32,
+}
+
+impl Automation {
+    pub fn new(lanes: Vec<AutomationLane>) -> Self {
+        Self {
+            values: vec![None; lanes.len()],
+            lanes,
+            master_gain: 1.0,
+            limiter_threshold: 1.0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lanes.is_empty()
+    }
+
+    /// The current gain of the mixed output.
+    pub fn master_gain(&self) -> f32 {
+        self.master_gain
+    }
+
+    /// The current threshold of the limiters.
+    pub fn limiter_threshold(&self) -> f32 {
+        self.limiter_threshold
+    }
+
+    /// Evaluates the lanes at the given time in seconds, and sends the
+    /// values of the channel targets that changed to the channel group.
+    pub fn update(&mut self, time: f64, channel_group: &mut ChannelGroup) {
+        for (lane, last) in self.lanes.iter().zip(self.values.iter_mut()) {
+            let Some(value) = lane.value_at(time) else {
+                continue;
+            };
+            if *last == Some(value) {
+                continue;
+            }
+            *last = Some(value);
+
+            match lane.target {
+                AutomationTarget::MasterGain => self.master_gain = value,
+                AutomationTarget::LimiterThreshold => self.limiter_threshold = value,
+                target => {
+                    if let Some(event) = target.event(value) {
+                        channel_group.send_event(event);
+                    }
+                }
+            }
+        }
+    }
+}
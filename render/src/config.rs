@@ -1,13 +1,32 @@
-use crate::utils::*;
+use crate::{automation::AutomationLane, utils::*};
 use clap::{command, Arg, ArgAction};
 use std::path::PathBuf;
 use xsynth_core::{
     channel::ChannelInitOptions,
     channel_group::{ChannelGroupConfig, ParallelismOptions, SynthFormat, ThreadCount},
+    effects::{ClipMode, LimiterConfig},
     soundfont::{EnvelopeCurveType, EnvelopeOptions, Interpolator, SoundfontInitOptions},
     AudioStreamParams, ChannelCount,
 };
 
+/// The sample format of the output audio file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitDepth {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl BitDepth {
+    pub fn bits(self) -> u16 {
+        match self {
+            BitDepth::Int16 => 16,
+            BitDepth::Int24 => 24,
+            BitDepth::Float32 => 32,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct XSynthRenderConfig {
     pub group_options: ChannelGroupConfig,
@@ -15,6 +34,16 @@ pub struct XSynthRenderConfig {
     pub sf_options: SoundfontInitOptions,
 
     pub use_limiter: bool,
+
+    pub limiter_config: LimiterConfig,
+
+    pub bit_depth: BitDepth,
+
+    /// Also writes each MIDI channel to its own file next to the output.
+    pub stems: bool,
+
+    /// Curves of parameters applied over the render, see `AutomationLane`.
+    pub automation: Vec<AutomationLane>,
 }
 
 #[derive(Clone, Debug)]
@@ -58,12 +87,21 @@ impl State {
                         Default: 48000 (48kHz)",
                     )
                     .value_parser(int_parser),
+                Arg::new("bit depth")
+                    .short('b')
+                    .long("bit-depth")
+                    .help(
+                        "The sample format of the output audio.\n\
+                        Supported: \"16\" and \"24\" (integer) and \"32\" (float)\n\
+                        Default: 32",
+                    )
+                    .value_parser(bit_depth_parser),
                 Arg::new("audio channels")
                     .short('c')
                     .long("audio-channels")
                     .help(
                         "The audio channel count of the output audio.\n\
-                        Supported: \"mono\" and \"stereo\"\n\
+                        Supported: \"mono\", \"stereo\", \"quad\" and \"5.1\"\n\
                         Default: stereo",
                     )
                     .value_parser(audio_channels_parser),
@@ -89,6 +127,23 @@ impl State {
                     .long("apply-limiter")
                     .help("Apply an audio limiter to the output audio to prevent clipping.")
                     .action(ArgAction::SetTrue),
+                Arg::new("stems")
+                    .long("stems")
+                    .help(
+                        "Also write each MIDI channel to its own file, named after the\n\
+                        output with the channel number, e.g. \"out.ch01.wav\".\n\
+                        The limiter is applied to each file separately.",
+                    )
+                    .action(ArgAction::SetTrue),
+                Arg::new("clip mode")
+                    .long("clip-mode")
+                    .help(
+                        "The clipping applied by the limiter after reducing the gain.\n\
+                        Available options are \"soft\", \"hard\" and \"none\" (samples\n\
+                        may exceed full scale).\n\
+                        Default: \"soft\"",
+                    )
+                    .value_parser(clip_mode_parser),
                 Arg::new("disable fade out voice killing")
                     .long("disable-fade-out")
                     .help("Disables fade out when killing a voice. This may cause popping.")
@@ -106,6 +161,20 @@ impl State {
                         Default: \"linear\"",
                     )
                     .value_parser(interpolation_parser),
+                Arg::new("automation")
+                    .long("automate")
+                    .help(
+                        "Changes a parameter over the render with a curve, written as\n\
+                        \"<target>:<seconds>=<value>,...\" with straight lines between\n\
+                        the breakpoints, or with \":step\" at the end to hold each value.\n\
+                        Targets: \"gain\" (output gain before the limiter), \"limiter\"\n\
+                        (limiter threshold) and \"ch<N>.volume\", \"ch<N>.pan\" and\n\
+                        \"ch<N>.expression\" for MIDI channel N, from 1. Gains are in\n\
+                        amplitude units, the channel values between 0 and 1.\n\
+                        Example: --automate gain:0=1,10=0.5 --automate ch10.pan:0=0,4=1:step",
+                    )
+                    .action(ArgAction::Append)
+                    .value_parser(automation_parser),
             ])
             .get_matches();
 
@@ -132,6 +201,7 @@ impl State {
                         .get_one("disable fade out voice killing")
                         .copied()
                         .unwrap_or(true),
+                    ..Default::default()
                 },
                 format: SynthFormat::Midi,
                 audio_params: AudioStreamParams::new(
@@ -164,12 +234,14 @@ impl State {
                         attack_curve: EnvelopeCurveType::Exponential,
                         decay_curve: EnvelopeCurveType::Exponential,
                         release_curve: EnvelopeCurveType::Exponential,
+                        ..Default::default()
                     }
                 } else {
                     EnvelopeOptions {
                         attack_curve: EnvelopeCurveType::Exponential,
                         decay_curve: EnvelopeCurveType::Linear,
                         release_curve: EnvelopeCurveType::Linear,
+                        ..Default::default()
                     }
                 },
                 use_effects: true,
@@ -177,8 +249,26 @@ impl State {
                     .get_one("interpolation")
                     .copied()
                     .unwrap_or(Interpolator::Linear),
+                ..Default::default()
             },
             use_limiter: matches.get_one("limiter").copied().unwrap_or_default(),
+            limiter_config: LimiterConfig {
+                clip_mode: matches
+                    .get_one("clip mode")
+                    .copied()
+                    .unwrap_or(ClipMode::SoftClip),
+                ..Default::default()
+            },
+            bit_depth: matches
+                .get_one("bit depth")
+                .copied()
+                .unwrap_or(BitDepth::Float32),
+            stems: matches.get_one("stems").copied().unwrap_or_default(),
+            automation: matches
+                .get_many::<AutomationLane>("automation")
+                .unwrap_or_default()
+                .cloned()
+                .collect(),
         };
 
         Self {
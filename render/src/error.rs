@@ -0,0 +1,17 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+use xsynth_core::soundfont::SoundfontLoadError;
+
+/// Errors that can stop a render.
+#[derive(Debug, Error)]
+pub enum RenderError {
+    #[error(transparent)]
+    Soundfont(#[from] SoundfontLoadError),
+
+    #[error("Error loading the MIDI file {}: {message}", path.display())]
+    Midi { path: PathBuf, message: String },
+
+    #[error("Error writing the audio file {}: {source}", path.display())]
+    Output { path: PathBuf, source: hound::Error },
+}
@@ -1,6 +1,11 @@
+mod automation;
+
 mod config;
 use config::*;
 
+mod error;
+use error::RenderError;
+
 mod rendered;
 use rendered::*;
 
@@ -20,14 +25,15 @@ use midi_toolkit::{
     io::MIDIFile,
     pipe,
     sequence::{
-        event::{cancel_tempo_events, scale_event_time},
+        event::{cancel_tempo_events, convert_events_into_batches, scale_event_time},
         unwrap_items, TimeCaster,
     },
 };
 
 use std::{
+    process,
     sync::{
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc,
     },
     thread,
@@ -39,39 +45,49 @@ use atomic_float::AtomicF64;
 fn main() {
     let state = State::from_args();
 
-    let mut synth = XSynthRender::new(state.config.clone(), state.output.clone());
+    if let Err(e) = render(state) {
+        eprintln!("\n{e}");
+        process::exit(1);
+    }
+}
 
-    print!("Loading soundfonts...");
+fn render(state: State) -> Result<(), RenderError> {
+    println!("Loading soundfonts...");
+    let params = state.config.group_options.audio_params;
+    let soundfonts = state
+        .soundfonts
+        .iter()
+        .map(|path| {
+            SampleSoundfont::new(path, params, state.config.sf_options)
+                .map(|sf| Arc::new(sf) as Arc<dyn SoundfontBase>)
+                .map_err(RenderError::from)
+        })
+        .collect::<Result<Arc<[_]>, _>>()?;
+
+    let mut synth = XSynthRender::new(state.config.clone(), state.output.clone())?;
     synth.send_event(SynthEvent::AllChannels(ChannelEvent::Config(
-        ChannelConfigEvent::SetSoundfonts(
-            state
-                .soundfonts
-                .iter()
-                .map(|s| {
-                    let sf: Arc<dyn SoundfontBase> = Arc::new(
-                        SampleSoundfont::new(s, synth.get_params(), state.config.sf_options)
-                            .unwrap(),
-                    );
-                    sf
-                })
-                .collect::<Vec<Arc<dyn SoundfontBase>>>(),
-        ),
+        ChannelConfigEvent::SetSoundfonts(soundfonts),
     )));
 
     synth.send_event(SynthEvent::AllChannels(ChannelEvent::Config(
         ChannelConfigEvent::SetLayerCount(state.layers),
     )));
 
-    let length = get_midi_length(state.midi.to_str().unwrap());
-
-    let midi = MIDIFile::open(state.midi, None).unwrap();
+    let midi = MIDIFile::open(&state.midi, None).map_err(|e| RenderError::Midi {
+        path: state.midi.clone(),
+        message: format!("{e:?}"),
+    })?;
+    let length = get_midi_length(&state.midi);
 
+    // The tempo events are cancelled before the events are batched, as
+    // cancelling them in batches drops the other events of their batch
     let ppq = midi.ppq();
     let merged = pipe!(
-        midi.iter_all_track_events_merged_batches()
+        midi.iter_all_track_events_merged()
         |>TimeCaster::<f64>::cast_event_delta()
         |>cancel_tempo_events(250000)
         |>scale_event_time(1.0 / ppq as f64)
+        |>convert_events_into_batches()
         |>unwrap_items()
     );
 
@@ -85,14 +101,16 @@ fn main() {
 
     let position = Arc::new(AtomicF64::new(0.0));
     let voices = Arc::new(AtomicU64::new(0));
+    let finished = Arc::new(AtomicBool::new(false));
 
-    {
+    let progress_thread = {
         let position = position.clone();
         let voices = voices.clone();
+        let finished = finished.clone();
 
         thread::spawn(move || loop {
             let pos = position.load(Ordering::Relaxed);
-            let progress = (pos / length) * 100.0 + 0.0004;
+            let progress = ((pos / length) * 100.0 + 0.0004).min(100.0);
             print!("\rProgress: [");
             let bars = progress as u8 / 5;
             for _ in 0..bars {
@@ -106,12 +124,13 @@ fn main() {
             for _ in 0..10 {
                 print!(" ");
             }
-            if progress >= 100.0 {
+            if progress >= 100.0 || finished.load(Ordering::Relaxed) {
                 println!();
                 break;
             }
-        });
-    }
+            thread::sleep(Duration::from_millis(50));
+        })
+    };
 
     let now = Instant::now();
 
@@ -171,9 +190,11 @@ fn main() {
     synth.send_event(SynthEvent::AllChannels(ChannelEvent::Audio(
         ChannelAudioEvent::ResetControl,
     )));
-    synth.finalize();
+    finished.store(true, Ordering::Relaxed);
+    progress_thread.join().ok();
 
-    let elapsed = now.elapsed();
-    thread::sleep(Duration::from_millis(200));
-    println!("Render time: {:?}", elapsed);
+    println!("Finishing...");
+    let stats = synth.finalize(now)?;
+    println!("{stats}");
+    Ok(())
 }
@@ -1,58 +1,176 @@
 use xsynth_core::{
+    amp_to_db,
     channel_group::{ChannelGroup, SynthEvent},
     effects::VolumeLimiter,
-    AudioPipe, AudioStreamParams,
+    AudioPipe,
 };
 
-use std::path::PathBuf;
+use std::{
+    fmt,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
-use crate::{config::XSynthRenderConfig, writer::AudioFileWriter};
+use crate::{
+    automation::{Automation, AUTOMATION_QUANTUM_FRAMES},
+    config::XSynthRenderConfig,
+    error::RenderError,
+    writer::AudioFileWriter,
+};
 
 struct BatchRenderElements {
     output_vec: Vec<f32>,
+    stem_vecs: Vec<Vec<f32>>,
     missed_samples: f64,
 }
 
+/// An audio file being written, with its own limiter.
+struct RenderOutput {
+    path: PathBuf,
+    writer: AudioFileWriter,
+    limiter: Option<VolumeLimiter>,
+}
+
+impl RenderOutput {
+    fn new(config: &XSynthRenderConfig, path: PathBuf) -> Result<Self, RenderError> {
+        let writer =
+            AudioFileWriter::new(config, path.clone()).map_err(|source| RenderError::Output {
+                path: path.clone(),
+                source,
+            })?;
+
+        let limiter = if config.use_limiter {
+            Some(VolumeLimiter::with_config(
+                config.group_options.audio_params.channels.count(),
+                config.limiter_config,
+            ))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            path,
+            writer,
+            limiter,
+        })
+    }
+
+    /// Applies the limiter with the given threshold, by scaling the samples
+    /// to the limiter's full scale and back.
+    fn limit(&mut self, samples: &mut [f32], threshold: f32) {
+        let Some(limiter) = &mut self.limiter else {
+            return;
+        };
+
+        if threshold == 1.0 {
+            limiter.limit(samples);
+        } else {
+            let scale = 1.0 / threshold.max(f32::EPSILON);
+            samples.iter_mut().for_each(|s| *s *= scale);
+            limiter.limit(samples);
+            samples.iter_mut().for_each(|s| *s /= scale);
+        }
+    }
+
+    fn finish(self) -> Result<(), RenderError> {
+        let path = self.path;
+        self.writer
+            .finish()
+            .map_err(|source| RenderError::Output { path, source })
+    }
+}
+
+/// Statistics of a finished render.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderStats {
+    /// The highest absolute sample value of the output, after the limiter.
+    pub peak: f32,
+
+    /// The highest voice count of the synthesizer at the end of a batch.
+    pub max_voices: u64,
+
+    /// The duration of the rendered audio.
+    pub duration: Duration,
+
+    /// The time the render took.
+    pub elapsed: Duration,
+}
+
+impl RenderStats {
+    /// Returns how many times faster than realtime the audio was rendered.
+    pub fn realtime_factor(&self) -> f64 {
+        self.duration.as_secs_f64() / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Peak level: {:.2} dBFS ({:.4})",
+            amp_to_db(self.peak),
+            self.peak
+        )?;
+        writeln!(f, "Max voices: {}", self.max_voices)?;
+        writeln!(
+            f,
+            "Audio length: {:.2}s, render time: {:.2}s",
+            self.duration.as_secs_f64(),
+            self.elapsed.as_secs_f64()
+        )?;
+        write!(f, "Realtime factor: {:.2}x", self.realtime_factor())
+    }
+}
+
 /// Represents an XSynth MIDI synthesizer that renders a MIDI to a file.
 pub struct XSynthRender {
     config: XSynthRenderConfig,
     channel_group: ChannelGroup,
-    audio_writer: AudioFileWriter,
-    limiter: Option<VolumeLimiter>,
+    output: RenderOutput,
+    stems: Vec<RenderOutput>,
     render_elements: BatchRenderElements,
+    automation: Automation,
+    peak: f32,
+    max_voices: u64,
+    rendered_frames: u64,
 }
 
 impl XSynthRender {
     /// Initializes a new XSynthRender object with the given configuration and
-    /// audio output path.
-    pub fn new(config: XSynthRenderConfig, out_path: PathBuf) -> Self {
+    /// audio output path. With `stems` enabled in the configuration, each
+    /// channel is also written to a file named after the output path with
+    /// the channel number, e.g. "out.ch01.wav".
+    pub fn new(config: XSynthRenderConfig, out_path: PathBuf) -> Result<Self, RenderError> {
         let channel_group = ChannelGroup::new(config.group_options.clone());
 
-        let audio_writer = AudioFileWriter::new(config.clone(), out_path);
-
-        let limiter = if config.use_limiter {
-            Some(VolumeLimiter::new(
-                config.group_options.audio_params.channels.count(),
-            ))
+        let stems = if config.stems {
+            (0..channel_group.channel_count())
+                .map(|channel| {
+                    let path = out_path.with_extension(format!("ch{:02}.wav", channel + 1));
+                    RenderOutput::new(&config, path)
+                })
+                .collect::<Result<_, _>>()?
         } else {
-            None
+            Vec::new()
         };
+        let output = RenderOutput::new(&config, out_path)?;
+        let automation = Automation::new(config.automation.clone());
 
-        Self {
+        Ok(Self {
             config,
             channel_group,
-            audio_writer,
-            limiter,
+            output,
             render_elements: BatchRenderElements {
                 output_vec: vec![0.0],
+                stem_vecs: vec![Vec::new(); stems.len()],
                 missed_samples: 0.0,
             },
-        }
-    }
-
-    /// Returns the parameters of the output audio.
-    pub fn get_params(&self) -> AudioStreamParams {
-        self.config.group_options.audio_params
+            stems,
+            automation,
+            peak: 0.0,
+            max_voices: 0,
+            rendered_frames: 0,
+        })
     }
 
     /// Sends a SynthEvent to the XSynthRender object.
@@ -84,32 +202,18 @@ impl XSynthRender {
             let samples =
                 samples as usize * self.config.group_options.audio_params.channels.count() as usize;
 
-            self.render_elements.output_vec.resize(samples, 0.0);
-            self.channel_group
-                .read_samples(&mut self.render_elements.output_vec);
-
-            if let Some(limiter) = &mut self.limiter {
-                limiter.limit(&mut self.render_elements.output_vec);
-            }
-
-            self.audio_writer
-                .write_samples(&mut self.render_elements.output_vec);
+            self.read_samples(samples);
+            self.write_samples();
+            self.max_voices = self.max_voices.max(self.voice_count());
         }
     }
 
-    /// Finishes the render and finalizes the audio file.
-    pub fn finalize(mut self) {
+    /// Renders the remaining audio until it is silent and finalizes the
+    /// audio files. `started` is the time the render started at, to measure
+    /// its duration.
+    pub fn finalize(mut self, started: Instant) -> Result<RenderStats, RenderError> {
         loop {
-            self.render_elements.output_vec.resize(
-                self.config.group_options.audio_params.sample_rate as usize,
-                0.0,
-            );
-            self.channel_group
-                .read_samples(&mut self.render_elements.output_vec);
-
-            if let Some(limiter) = &mut self.limiter {
-                limiter.limit(&mut self.render_elements.output_vec);
-            }
+            self.read_samples(self.config.group_options.audio_params.sample_rate as usize);
 
             let mut is_empty = true;
             for s in &self.render_elements.output_vec {
@@ -121,13 +225,88 @@ impl XSynthRender {
             if is_empty {
                 break;
             }
-            self.audio_writer
-                .write_samples(&mut self.render_elements.output_vec);
+            self.write_samples();
+        }
+
+        self.output.finish()?;
+        for stem in self.stems {
+            stem.finish()?;
         }
+
+        let sample_rate = self.config.group_options.audio_params.sample_rate;
+        Ok(RenderStats {
+            peak: self.peak,
+            max_voices: self.max_voices,
+            duration: Duration::from_secs_f64(self.rendered_frames as f64 / sample_rate as f64),
+            elapsed: started.elapsed(),
+        })
     }
 
     /// Returns the active voice count of the MIDI synthesizer.
     pub fn voice_count(&self) -> u64 {
         self.channel_group.voice_count()
     }
+
+    /// Renders the given number of samples to the output buffer and the
+    /// stem buffers, and applies the limiters. With automation lanes, the
+    /// samples are rendered in quanta, with the lanes evaluated before each
+    /// of them.
+    fn read_samples(&mut self, samples: usize) {
+        let channels = self.config.group_options.audio_params.channels.count() as usize;
+        let sample_rate = self.config.group_options.audio_params.sample_rate as f64;
+        let quantum = if self.automation.is_empty() {
+            samples
+        } else {
+            AUTOMATION_QUANTUM_FRAMES * channels
+        };
+
+        let elements = &mut self.render_elements;
+        elements.output_vec.resize(samples, 0.0);
+        for stem_vec in elements.stem_vecs.iter_mut() {
+            stem_vec.clear();
+        }
+
+        let mut frame = self.rendered_frames;
+        for chunk in elements.output_vec.chunks_mut(quantum.max(1)) {
+            self.automation
+                .update(frame as f64 / sample_rate, &mut self.channel_group);
+            frame += (chunk.len() / channels) as u64;
+
+            self.channel_group.read_samples(chunk);
+            let gain = self.automation.master_gain();
+            if gain != 1.0 {
+                chunk.iter_mut().for_each(|s| *s *= gain);
+            }
+
+            let threshold = self.automation.limiter_threshold();
+            self.output.limit(chunk, threshold);
+            for (channel, (stem, stem_vec)) in self
+                .stems
+                .iter_mut()
+                .zip(elements.stem_vecs.iter_mut())
+                .enumerate()
+            {
+                let start = stem_vec.len();
+                stem_vec.extend_from_slice(self.channel_group.channel_samples(channel as u32));
+                stem.limit(&mut stem_vec[start..], threshold);
+            }
+        }
+    }
+
+    /// Writes the last rendered samples to the output and the stems.
+    fn write_samples(&mut self) {
+        let output_vec = &mut self.render_elements.output_vec;
+        let channels = self.config.group_options.audio_params.channels.count() as usize;
+        self.rendered_frames += (output_vec.len() / channels) as u64;
+        self.peak = output_vec
+            .iter()
+            .fold(self.peak, |peak, s| s.abs().max(peak));
+
+        let stem_vecs = self.render_elements.stem_vecs.iter_mut();
+        for (stem, stem_vec) in self.stems.iter_mut().zip(stem_vecs) {
+            stem.writer.write_samples(stem_vec);
+        }
+
+        self.output.writer.write_samples(output_vec);
+    }
 }
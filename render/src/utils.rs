@@ -1,7 +1,16 @@
+use crate::{
+    automation::{AutomationInterpolation, AutomationLane, AutomationTarget},
+    config::BitDepth,
+};
 use atomic_float::AtomicF64;
 use midi_toolkit::{io::MIDIFile, sequence::event::get_channels_array_statistics};
-use std::sync::{atomic::Ordering, Arc};
-use xsynth_core::{channel_group::ThreadCount, soundfont::Interpolator, ChannelCount};
+use std::{
+    path::Path,
+    sync::{atomic::Ordering, Arc},
+};
+use xsynth_core::{
+    channel_group::ThreadCount, effects::ClipMode, soundfont::Interpolator, ChannelCount,
+};
 
 #[inline(always)]
 pub fn layers_parser(s: &str) -> Result<Option<usize>, String> {
@@ -29,10 +38,22 @@ pub fn audio_channels_parser(s: &str) -> Result<ChannelCount, String> {
     match s {
         "mono" => Ok(ChannelCount::Mono),
         "stereo" => Ok(ChannelCount::Stereo),
+        "quad" => Ok(ChannelCount::Quad),
+        "5.1" => Ok(ChannelCount::Surround51),
         _ => Err("Invalid channel count".to_string()),
     }
 }
 
+#[inline(always)]
+pub fn bit_depth_parser(s: &str) -> Result<BitDepth, String> {
+    match s {
+        "16" => Ok(BitDepth::Int16),
+        "24" => Ok(BitDepth::Int24),
+        "32" => Ok(BitDepth::Float32),
+        _ => Err("Invalid bit depth".to_string()),
+    }
+}
+
 #[inline(always)]
 pub fn int_parser(s: &str) -> Result<u32, String> {
     s.parse().map_err(|e| format!("{}", e))
@@ -47,8 +68,72 @@ pub fn interpolation_parser(s: &str) -> Result<Interpolator, String> {
     }
 }
 
-pub fn get_midi_length(path: &str) -> f64 {
-    let midi = MIDIFile::open(path, None).unwrap();
+#[inline(always)]
+pub fn clip_mode_parser(s: &str) -> Result<ClipMode, String> {
+    match s {
+        "soft" => Ok(ClipMode::SoftClip),
+        "hard" => Ok(ClipMode::HardClip),
+        "none" => Ok(ClipMode::None),
+        _ => Err("Invalid clip mode".to_string()),
+    }
+}
+
+/// Parses an automation lane, e.g. "ch3.pan:0=0,2.5=1:step".
+pub fn automation_parser(s: &str) -> Result<AutomationLane, String> {
+    let mut parts = s.split(':');
+    let target = match parts.next().unwrap_or_default() {
+        "gain" => AutomationTarget::MasterGain,
+        "limiter" => AutomationTarget::LimiterThreshold,
+        target => {
+            let (channel, parameter) = target
+                .strip_prefix("ch")
+                .and_then(|target| target.split_once('.'))
+                .ok_or("Invalid automation target")?;
+            let channel = match channel.parse::<u32>() {
+                Ok(channel) if channel > 0 => channel - 1,
+                _ => return Err("Invalid automation channel".to_string()),
+            };
+            match parameter {
+                "volume" => AutomationTarget::ChannelVolume(channel),
+                "pan" => AutomationTarget::ChannelPan(channel),
+                "expression" => AutomationTarget::ChannelExpression(channel),
+                _ => return Err("Invalid automation target".to_string()),
+            }
+        }
+    };
+
+    let breakpoints = parts
+        .next()
+        .ok_or("Missing automation breakpoints")?
+        .split(',')
+        .map(|breakpoint| -> Result<(f64, f32), String> {
+            let (time, value) = breakpoint
+                .split_once('=')
+                .ok_or("Invalid automation breakpoint")?;
+            let time: f64 = time.parse().map_err(|e| format!("{}", e))?;
+            let value: f32 = value.parse().map_err(|e| format!("{}", e))?;
+            Ok((time, value))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let interpolation = match parts.next() {
+        None => AutomationInterpolation::Linear,
+        Some("step") => AutomationInterpolation::Step,
+        Some(_) => return Err("Invalid automation interpolation".to_string()),
+    };
+    if parts.next().is_some() {
+        return Err("Invalid automation".to_string());
+    }
+
+    Ok(AutomationLane::new(target, breakpoints, interpolation))
+}
+
+/// Returns the length of the MIDI file in seconds, or NaN if it can't be
+/// read.
+pub fn get_midi_length(path: &Path) -> f64 {
+    let Ok(midi) = MIDIFile::open(path, None) else {
+        return f64::NAN;
+    };
     let parse_length_outer = Arc::new(AtomicF64::new(f64::NAN));
     let ppq = midi.ppq();
     let tracks = midi.iter_all_tracks().collect();
@@ -1,4 +1,4 @@
-use crate::config::XSynthRenderConfig;
+use crate::config::{BitDepth, XSynthRenderConfig};
 
 use std::{path::PathBuf, thread};
 
@@ -7,33 +7,59 @@ use hound::{WavSpec, WavWriter};
 
 pub struct AudioFileWriter {
     sender: Sender<Vec<f32>>,
+    thread: thread::JoinHandle<Result<(), hound::Error>>,
 }
 
 impl AudioFileWriter {
-    pub fn new(config: XSynthRenderConfig, path: PathBuf) -> Self {
+    pub fn new(config: &XSynthRenderConfig, path: PathBuf) -> Result<Self, hound::Error> {
         let spec = WavSpec {
             channels: config.group_options.audio_params.channels.count(),
             sample_rate: config.group_options.audio_params.sample_rate,
-            bits_per_sample: 32,
-            sample_format: hound::SampleFormat::Float,
+            bits_per_sample: config.bit_depth.bits(),
+            sample_format: match config.bit_depth {
+                BitDepth::Float32 => hound::SampleFormat::Float,
+                BitDepth::Int16 | BitDepth::Int24 => hound::SampleFormat::Int,
+            },
         };
-        let mut writer = WavWriter::create(path, spec).unwrap();
+        let bit_depth = config.bit_depth;
+        let mut writer = WavWriter::create(path, spec)?;
 
         let (snd, rcv) = crossbeam_channel::unbounded::<Vec<f32>>();
 
-        thread::spawn(move || {
+        let thread = thread::spawn(move || {
             for batch in rcv {
                 for s in batch {
-                    writer.write_sample(s).unwrap();
+                    match bit_depth {
+                        BitDepth::Float32 => writer.write_sample(s)?,
+                        BitDepth::Int16 => writer.write_sample(to_int(s, 16) as i16)?,
+                        BitDepth::Int24 => writer.write_sample(to_int(s, 24))?,
+                    }
                 }
             }
-            writer.finalize().unwrap();
+            writer.finalize()
         });
 
-        Self { sender: snd }
+        Ok(Self {
+            sender: snd,
+            thread,
+        })
     }
 
     pub fn write_samples(&mut self, samples: &mut Vec<f32>) {
-        self.sender.send(std::mem::take(samples)).unwrap();
+        // If the writer thread failed, the error is returned by `finish`
+        self.sender.send(std::mem::take(samples)).ok();
     }
+
+    /// Waits until all samples are written and finalizes the file.
+    pub fn finish(self) -> Result<(), hound::Error> {
+        drop(self.sender);
+        self.thread.join().expect("audio writer thread panicked")
+    }
+}
+
+/// Converts a sample to a signed integer of the given bit depth, clipping
+/// it to full scale.
+fn to_int(sample: f32, bits: u32) -> i32 {
+    let max = ((1i64 << (bits - 1)) - 1) as f32;
+    (sample.clamp(-1.0, 1.0) * max).round() as i32
 }
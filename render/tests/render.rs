@@ -0,0 +1,142 @@
+//! Renders a bundled 2-second MIDI with a tiny soundfont through the
+//! command line interface.
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_xsynth-render"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn output_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn read_wav(path: &Path) -> (hound::WavSpec, Vec<i32>) {
+    let mut reader = hound::WavReader::open(path).unwrap();
+    let samples = reader.samples::<i32>().map(|s| s.unwrap()).collect();
+    (reader.spec(), samples)
+}
+
+#[test]
+fn test_render_with_stems() {
+    let dir = output_dir("render_with_stems");
+    let out = dir.join("out.wav");
+    let output = run(&[
+        &format!("{FIXTURES}/two_seconds.mid"),
+        &format!("{FIXTURES}/tone.sfz"),
+        "-o",
+        out.to_str().unwrap(),
+        "--bit-depth",
+        "16",
+        "--apply-limiter",
+        "--stems",
+        "--channel-threading",
+        "none",
+    ]);
+    assert!(output.status.success(), "{output:?}");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Peak level:"));
+    assert!(stdout.contains("Max voices: 2"));
+    assert!(stdout.contains("Realtime factor:"));
+
+    let (spec, samples) = read_wav(&out);
+    assert_eq!(spec.bits_per_sample, 16);
+    assert_eq!(spec.sample_format, hound::SampleFormat::Int);
+    assert_eq!(spec.channels, 2);
+    assert_eq!(spec.sample_rate, 48000);
+    let seconds = samples.len() as f64 / 2.0 / 48000.0;
+    assert!((1.99..3.5).contains(&seconds), "{seconds}s rendered");
+    assert!(samples.iter().any(|&s| s != 0));
+
+    // The first channel plays the first second, the second channel the
+    // next one, and the other channels are silent
+    let first = read_wav(&dir.join("out.ch01.wav")).1;
+    let second = read_wav(&dir.join("out.ch02.wav")).1;
+    let third = read_wav(&dir.join("out.ch03.wav")).1;
+    assert_eq!(first.len(), samples.len());
+    assert!(first[..48000].iter().any(|&s| s != 0));
+    assert!(first[96000 + 9600..].iter().all(|&s| s == 0));
+    assert!(second[..96000 - 9600].iter().all(|&s| s == 0));
+    assert!(second[96000..].iter().any(|&s| s != 0));
+    assert!(third.iter().all(|&s| s == 0));
+    assert!(dir.join("out.ch16.wav").exists());
+}
+
+#[test]
+fn test_missing_soundfont() {
+    let dir = output_dir("missing_soundfont");
+    let output = run(&[
+        &format!("{FIXTURES}/two_seconds.mid"),
+        &format!("{FIXTURES}/missing.sfz"),
+        "-o",
+        dir.join("out.wav").to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Error loading the soundfont"), "{stderr}");
+}
+
+fn read_float_wav(path: &Path) -> Vec<f32> {
+    let mut reader = hound::WavReader::open(path).unwrap();
+    reader.samples::<f32>().map(|s| s.unwrap()).collect()
+}
+
+#[test]
+fn test_gain_automation() {
+    let dir = output_dir("gain_automation");
+    let render = |name: &str, extra_args: &[&str]| {
+        let out = dir.join(name);
+        let midi = format!("{FIXTURES}/two_seconds.mid");
+        let sfz = format!("{FIXTURES}/tone.sfz");
+        let mut args = vec![
+            midi.as_str(),
+            sfz.as_str(),
+            "-o",
+            out.to_str().unwrap(),
+            "--channel-threading",
+            "none",
+            "--key-threading",
+            "none",
+        ];
+        args.extend_from_slice(extra_args);
+        let output = run(&args);
+        assert!(output.status.success(), "{output:?}");
+        read_float_wav(&out)
+    };
+
+    let plain = render("plain.wav", &[]);
+    let ramped = render("ramped.wav", &["--automate", "gain:0=0,2=1"]);
+    assert_eq!(plain.len(), ramped.len());
+
+    // The level of each 50 ms window follows the linear ramp from silence
+    // at the start to the full level after 2 seconds
+    let rms = |samples: &[f32]| {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    };
+    let window = 2400 * 2;
+    for (i, (plain, ramped)) in plain.chunks(window).zip(ramped.chunks(window)).enumerate() {
+        let plain_rms = rms(plain);
+        if plain_rms < 1e-3 {
+            continue;
+        }
+        let time = (i as f32 + 0.5) * 0.05;
+        let expected = (time / 2.0).min(1.0);
+        let ratio = rms(ramped) / plain_rms;
+        assert!(
+            (ratio - expected).abs() < 0.01,
+            "{ratio} instead of {expected} at {time}s"
+        );
+    }
+}
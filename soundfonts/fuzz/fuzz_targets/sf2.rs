@@ -0,0 +1,9 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = xsynth_soundfonts::sf2::load_soundfont_from_reader(&mut Cursor::new(data), 48000);
+});
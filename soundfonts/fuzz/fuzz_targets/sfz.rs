@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Includes and samples are resolved in an empty folder, so only the
+    // parser itself is exercised
+    let base_path = std::env::temp_dir().join("xsynth_sfz_fuzz");
+    let _ = std::fs::create_dir_all(&base_path);
+    let _ = xsynth_soundfonts::sfz::parse_soundfont_str(&String::from_utf8_lossy(data), base_path);
+});
@@ -14,7 +14,9 @@ pub fn resample_vecs(
         .collect()
 }
 
-/// Resample a single audio sample vector
+/// Resample a single audio sample vector. The result is aligned with the
+/// input and has its length converted to the new sample rate, so sample
+/// indices can be converted with `convert_sample_index`.
 pub fn resample_vec(vec: Vec<f32>, sample_rate: f32, new_sample_rate: f32) -> Arc<[f32]> {
     let params = SincInterpolationParameters {
         sinc_len: 32,
@@ -24,14 +26,16 @@ pub fn resample_vec(vec: Vec<f32>, sample_rate: f32, new_sample_rate: f32) -> Ar
         window: WindowFunction::BlackmanHarris2,
     };
 
-    let len = vec.len();
-    let mut resampler = SincFixedIn::<f32>::new(
-        new_sample_rate as f64 / sample_rate as f64,
-        2.0,
-        params,
-        len,
-        1,
-    )
-    .unwrap();
-    resampler.process(&[vec], None).unwrap()[0].clone().into()
+    let ratio = new_sample_rate as f64 / sample_rate as f64;
+    let len = (vec.len() as f64 * ratio).round() as usize;
+
+    // The input is padded with silence, as the resampler holds back the
+    // last frames of the chunk for the filter
+    let mut vec = vec;
+    vec.resize(vec.len() + params.sinc_len, 0.0);
+
+    let mut resampler = SincFixedIn::<f32>::new(ratio, 2.0, params, vec.len(), 1).unwrap();
+    let mut out = resampler.process(&[vec], None).unwrap().remove(0);
+    out.resize(len, 0.0);
+    out.into()
 }
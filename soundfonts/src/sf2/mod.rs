@@ -1,5 +1,12 @@
 use crate::{sfz::AmpegEnvelopeParams, LoopMode};
-use std::{fs::File, ops::RangeInclusive, path::PathBuf, sync::Arc};
+use std::{
+    fs::File,
+    io::{Read, Seek},
+    ops::RangeInclusive,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::Arc,
+};
 
 use thiserror::Error;
 
@@ -16,6 +23,31 @@ pub enum Sf2ParseError {
 
     #[error("Failed to parse file")]
     FailedToParseFile(String),
+
+    /// A record of the file is missing or inconsistent with the rest of it,
+    /// e.g. a sample header that points past the end of the sample data.
+    /// The offset is the index of the record in its chunk, or the sample
+    /// offset for the `smpl` and `sm24` chunks.
+    #[error("Malformed {chunk} chunk at offset {offset}: {reason}")]
+    MalformedChunk {
+        chunk: &'static str,
+        offset: u64,
+        reason: String,
+    },
+}
+
+impl Sf2ParseError {
+    pub(super) fn malformed(
+        chunk: &'static str,
+        offset: impl Into<u64>,
+        reason: impl Into<String>,
+    ) -> Self {
+        Sf2ParseError::MalformedChunk {
+            chunk,
+            offset: offset.into(),
+            reason: reason.into(),
+        }
+    }
 }
 
 /// Structure that holds the generator and modulator parameters of an SF2 region.
@@ -42,42 +74,184 @@ pub struct Sf2Region {
 /// Structure that holds the parameters of an SF2 preset.
 #[derive(Clone, Debug)]
 pub struct Sf2Preset {
+    pub name: String,
     pub bank: u16,
     pub preset: u16,
     pub regions: Vec<Sf2Region>,
 }
 
+/// Structure that holds the metadata of an SF2 file from its INFO chunk.
+#[derive(Clone, Debug, Default)]
+pub struct Sf2Info {
+    pub name: String,
+    pub author: Option<String>,
+    pub copyright: Option<String>,
+    pub comments: Option<String>,
+
+    /// The malformed parts of the file that were skipped, so the valid rest
+    /// of it could be loaded.
+    pub warnings: Vec<Sf2ParseError>,
+}
+
 /// Parses an SF2 file and returns its presets in a vector.
 pub fn load_soundfont(
     sf2_path: impl Into<PathBuf>,
     sample_rate: u32,
 ) -> Result<Vec<Sf2Preset>, Sf2ParseError> {
+    load_soundfont_with_info(sf2_path, sample_rate).map(|(_, presets)| presets)
+}
+
+/// Parses an SF2 file and returns its metadata and its presets in a vector.
+pub fn load_soundfont_with_info(
+    sf2_path: impl Into<PathBuf>,
+    sample_rate: u32,
+) -> Result<(Sf2Info, Vec<Sf2Preset>), Sf2ParseError> {
     let sf2_path: PathBuf = sf2_path.into();
     let sf2_path: PathBuf = sf2_path
         .canonicalize()
         .map_err(|_| Sf2ParseError::FailedToReadFile(sf2_path.clone()))?;
     let mut file = File::open(sf2_path.clone())
         .map_err(|_| Sf2ParseError::FailedToReadFile(sf2_path.clone()))?;
-    let file = &mut file;
-    let sf2 = soundfont::SoundFont2::load(file)
-        .map_err(|e| Sf2ParseError::FailedToParseFile(format!("{e:#?}")))?
-        .sort_presets();
+    load_soundfont_from_reader(&mut file, sample_rate)
+}
+
+/// Parses SF2 data from a reader and returns its metadata and its presets
+/// in a vector.
+///
+/// Malformed files never cause a panic. If only some samples, instruments
+/// or zones are malformed, the rest of the file is loaded and the skipped
+/// parts are listed in `Sf2Info::warnings`.
+pub fn load_soundfont_from_reader(
+    reader: &mut (impl Read + Seek),
+    sample_rate: u32,
+) -> Result<(Sf2Info, Vec<Sf2Preset>), Sf2ParseError> {
+    // The RIFF structure is parsed by the soundfont crate, which isn't
+    // hardened against malformed files
+    let sf2 = panic::catch_unwind(AssertUnwindSafe(|| {
+        soundfont::SoundFont2::load(reader).map(|sf2| sf2.sort_presets())
+    }))
+    .map_err(|_| Sf2ParseError::FailedToParseFile("Malformed RIFF structure".to_string()))?
+    .map_err(|e| Sf2ParseError::FailedToParseFile(format!("{e:#?}")))?;
+
+    let mut warnings = Vec::new();
 
     let sample_data = sample::Sf2Sample::parse_sf2_samples(
-        file,
+        reader,
         sf2.sample_headers,
         sf2.sample_data,
         sample_rate,
+        &mut warnings,
     )?;
 
     let instruments = instrument::Sf2Instrument::parse_instruments(sf2.instruments);
 
     let presets = preset::Sf2ParsedPreset::parse_presets(sf2.presets);
 
-    Ok(preset::Sf2ParsedPreset::merge_presets(
+    let presets = preset::Sf2ParsedPreset::merge_presets(
         sample_data,
         instruments,
         presets,
         sample_rate,
-    ))
+        &mut warnings,
+    );
+
+    let info = Sf2Info {
+        name: sf2.info.bank_name,
+        author: sf2.info.engineers,
+        copyright: sf2.info.copyright,
+        comments: sf2.info.comments,
+        warnings,
+    };
+
+    Ok((info, presets))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(name)
+    }
+
+    fn malformed_chunks(presets: &[Sf2Preset], info: &Sf2Info) -> Vec<(&'static str, u64)> {
+        assert_eq!(presets.len(), 1);
+        info.warnings
+            .iter()
+            .map(|w| match w {
+                Sf2ParseError::MalformedChunk { chunk, offset, .. } => (*chunk, *offset),
+                other => panic!("unexpected warning {other}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_malformed_files_dont_panic() {
+        for entry in fs::read_dir(fixture("malformed")).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "sf2") {
+                let _ = load_soundfont_with_info(&path, 48000);
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_valid_subset() {
+        let (info, presets) = load_soundfont_with_info(fixture("valid.sf2"), 48000).unwrap();
+        assert!(malformed_chunks(&presets, &info).is_empty());
+        assert_eq!(presets[0].regions.len(), 1);
+
+        let load = |name: &str| {
+            let (info, presets) =
+                load_soundfont_with_info(fixture("malformed").join(name), 48000).unwrap();
+            let regions = presets[0].regions.clone();
+            (malformed_chunks(&presets, &info), regions)
+        };
+
+        let (warnings, regions) = load("missing_instrument.sf2");
+        assert_eq!(warnings, [("inst", 7)]);
+        assert!(regions.is_empty());
+
+        let (warnings, regions) = load("missing_sample.sf2");
+        assert_eq!(warnings, [("shdr", 9)]);
+        assert!(regions.is_empty());
+
+        let (warnings, regions) = load("sample_past_end.sf2");
+        assert_eq!(warnings, [("shdr", 0), ("shdr", 0)]);
+        assert!(regions.is_empty());
+
+        let (warnings, regions) = load("loop_outside_sample.sf2");
+        assert!(warnings.is_empty());
+        assert_eq!(regions[0].loop_end, 100);
+
+        let (warnings, regions) = load("key_range_over_127.sf2");
+        assert!(warnings.is_empty());
+        assert_eq!(regions[0].keyrange, 0..=127);
+    }
+
+    #[test]
+    fn test_align_stereo_channels() {
+        // The right channel has a longer pre-roll and tail than the left one,
+        // and its loop points are shifted accordingly
+        let (info, presets) =
+            load_soundfont_with_info(fixture("stereo_mismatched.sf2"), 48000).unwrap();
+        assert_eq!(malformed_chunks(&presets, &info), [("shdr", 1)]);
+
+        let region = &presets[0].regions[0];
+        let [left, right] = &*region.sample else {
+            panic!("expected a stereo sample");
+        };
+        assert_eq!(left.len(), right.len());
+        assert_eq!((region.loop_start, region.loop_end), (10, 90));
+
+        // The loops play the same part of the wave on both channels
+        let (start, end) = (region.loop_start as usize, region.loop_end as usize);
+        for i in start..end {
+            assert!((left[i] - right[i]).abs() < 0.01);
+        }
+    }
 }
@@ -1,10 +1,14 @@
-use super::{instrument::Sf2Instrument, sample::Sf2Sample, zone::Sf2Zone, Sf2Preset, Sf2Region};
+use super::{
+    instrument::Sf2Instrument, sample::Sf2Sample, zone::Sf2Zone, Sf2ParseError, Sf2Preset,
+    Sf2Region,
+};
 use crate::{convert_sample_index, sfz::AmpegEnvelopeParams, LoopMode};
 use soundfont::Preset;
-use std::{ops::RangeInclusive, sync::Arc};
+use std::{collections::HashMap, ops::RangeInclusive, sync::Arc};
 
 #[derive(Clone, Debug)]
 pub struct Sf2ParsedPreset {
+    pub name: String,
     pub bank: u16,
     pub preset: u16,
     pub zones: Vec<Sf2Zone>,
@@ -18,6 +22,7 @@ impl Sf2ParsedPreset {
             let zones = Sf2Zone::parse(preset.zones);
 
             presets_parsed.push(Sf2ParsedPreset {
+                name: preset.header.name,
                 preset: preset.header.preset,
                 bank: preset.header.bank,
                 zones,
@@ -27,16 +32,25 @@ impl Sf2ParsedPreset {
         presets_parsed
     }
 
+    /// Builds the regions of the presets from the zones of their
+    /// instruments. Zones that point to a missing or malformed instrument or
+    /// sample are skipped and added to the warnings.
     pub fn merge_presets(
-        sample_data: Vec<Sf2Sample>,
+        sample_data: Vec<Option<Sf2Sample>>,
         instruments: Vec<Sf2Instrument>,
         presets: Vec<Sf2ParsedPreset>,
         sample_rate: u32,
+        warnings: &mut Vec<Sf2ParseError>,
     ) -> Vec<Sf2Preset> {
         let mut out: Vec<Sf2Preset> = Vec::new();
 
+        // The linked samples aligned to their pair, shared by the regions
+        // that use the same pair
+        let mut aligned_samples: HashMap<(u16, u16), Arc<[f32]>> = HashMap::new();
+
         for preset in presets {
             let mut new_preset = Sf2Preset {
+                name: preset.name,
                 preset: preset.preset,
                 bank: preset.bank,
                 regions: Vec::new(),
@@ -46,11 +60,25 @@ impl Sf2ParsedPreset {
 
             for zone in preset.zones {
                 if let Some(instrument_idx) = zone.index {
-                    let instrument = &instruments[instrument_idx as usize];
+                    let Some(instrument) = instruments.get(instrument_idx as usize) else {
+                        warnings.push(Sf2ParseError::malformed(
+                            "inst",
+                            instrument_idx,
+                            format!("Missing instrument used by preset {}", new_preset.name),
+                        ));
+                        continue;
+                    };
 
                     for subzone in &instrument.regions {
                         if let Some(sample_idx) = subzone.index {
-                            let sample = &sample_data[sample_idx as usize];
+                            let Some(Some(sample)) = sample_data.get(sample_idx as usize) else {
+                                warnings.push(Sf2ParseError::malformed(
+                                    "shdr",
+                                    sample_idx,
+                                    format!("Missing sample used by instrument {instrument_idx}"),
+                                ));
+                                continue;
+                            };
 
                             let new_region = Sf2Region {
                                 sample: Arc::new([]),
@@ -79,14 +107,14 @@ impl Sf2ParsedPreset {
                                     let offset = subzone.loop_start_offset.unwrap_or(0) as i32
                                         + (subzone.loop_start_offset_coarse.unwrap_or(0) as i32
                                             * 32768);
-                                    let v = (sample.loop_start as i32 + offset) as u32;
+                                    let v = (sample.loop_start as i32 + offset).max(0) as u32;
                                     convert_sample_index(v, sample.sample_rate, sample_rate)
                                 },
                                 loop_end: {
                                     let offset = subzone.loop_end_offset.unwrap_or(0) as i32
                                         + (subzone.loop_end_offset_coarse.unwrap_or(0) as i32
                                             * 32768);
-                                    let v = (sample.loop_end as i32 + offset) as u32;
+                                    let v = (sample.loop_end as i32 + offset).max(0) as u32;
                                     convert_sample_index(v, sample.sample_rate, sample_rate)
                                 },
                                 offset: {
@@ -130,7 +158,7 @@ impl Sf2ParsedPreset {
                                 },
                             };
 
-                            regions.push((new_region, sample.clone()));
+                            regions.push((new_region, sample.clone(), sample_idx));
                         }
                     }
                 }
@@ -146,10 +174,8 @@ impl Sf2ParsedPreset {
                 }
                 if region.1.link_type.abs() == 1 {
                     if region.0.pan.abs() == 500 {
-                        match regions
-                            .clone()
-                            .into_iter()
-                            .position(|v: (Sf2Region, Sf2Sample)| {
+                        match regions.clone().into_iter().position(
+                            |v: (Sf2Region, Sf2Sample, u16)| {
                                 let v1 = v.0.clone();
                                 let v2 = region.0.clone();
                                 v.1.link_type == -region.1.link_type
@@ -157,22 +183,26 @@ impl Sf2ParsedPreset {
                                     && v1.root_key == v2.root_key
                                     && v1.keyrange == v2.keyrange
                                     && v1.velrange == v2.velrange
-                            }) {
+                            },
+                        ) {
                             Some(reg) => {
-                                let sample_match = regions[reg].1.clone();
+                                let (_, sample_match, match_idx) = &regions[reg];
+                                let match_data = linked_sample_data(
+                                    (&region.1, region.2),
+                                    (sample_match, *match_idx),
+                                    sample_rate,
+                                    &mut aligned_samples,
+                                    warnings,
+                                );
                                 let mut new_region = region.0.clone();
                                 match region.1.link_type {
                                     -1 => {
-                                        new_region.sample = Arc::new([
-                                            region.1.data.clone(),
-                                            sample_match.data.clone(),
-                                        ])
+                                        new_region.sample =
+                                            Arc::new([region.1.data.clone(), match_data])
                                     }
                                     1 => {
-                                        new_region.sample = Arc::new([
-                                            sample_match.data.clone(),
-                                            region.1.data.clone(),
-                                        ])
+                                        new_region.sample =
+                                            Arc::new([match_data, region.1.data.clone()])
                                     }
                                     _ => {}
                                 }
@@ -200,6 +230,33 @@ impl Sf2ParsedPreset {
     }
 }
 
+/// Returns the data of the other sample of a stereo pair, aligned to the
+/// sample if their lengths or loop points differ, as the channels would
+/// drift apart while playing. Aligned samples are shared by the regions
+/// using the same pair, and a warning is added when they are aligned.
+fn linked_sample_data(
+    (sample, sample_idx): (&Sf2Sample, u16),
+    (other, other_idx): (&Sf2Sample, u16),
+    sample_rate: u32,
+    aligned_samples: &mut HashMap<(u16, u16), Arc<[f32]>>,
+    warnings: &mut Vec<Sf2ParseError>,
+) -> Arc<[f32]> {
+    let aligned = aligned_samples
+        .entry((sample_idx, other_idx))
+        .or_insert_with(|| match sample.align_linked(other, sample_rate) {
+            Some(aligned) => {
+                warnings.push(Sf2ParseError::malformed(
+                    "shdr",
+                    other_idx,
+                    format!("Length or loop differs from linked sample {sample_idx}, aligned"),
+                ));
+                aligned
+            }
+            None => other.data.clone(),
+        });
+    aligned.clone()
+}
+
 fn combine_ranges<T: Ord + Copy>(
     r1: RangeInclusive<T>,
     r2: RangeInclusive<T>,
@@ -1,9 +1,9 @@
 use super::Sf2ParseError;
-use crate::resample::resample_vec;
+use crate::{convert_sample_index, resample::resample_vec};
 use soundfont::raw::{SampleChunk, SampleData, SampleHeader, SampleLink};
 use std::{
-    fs::File,
     io::{self, Read, Seek, SeekFrom},
+    ops::Range,
     sync::Arc,
 };
 
@@ -19,23 +19,34 @@ pub struct Sf2Sample {
 }
 
 impl Sf2Sample {
-    fn read_chunk(file: &mut File, chunk: SampleChunk) -> io::Result<Vec<u8>> {
-        let mut buff = vec![0; chunk.len as usize];
+    fn read_chunk(reader: &mut (impl Read + Seek), chunk: SampleChunk) -> io::Result<Vec<u8>> {
+        // Not allocated upfront, as the length of a malformed chunk can be
+        // much larger than the file
+        let mut buff = Vec::new();
 
-        file.seek(SeekFrom::Start(chunk.offset))?;
-        file.read_exact(&mut buff)?;
+        reader.seek(SeekFrom::Start(chunk.offset))?;
+        let len = u64::from(chunk.len);
+        reader.by_ref().take(len).read_to_end(&mut buff)?;
+        if buff.len() as u64 != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
 
         Ok(buff)
     }
 
+    /// Parses the sample data and the sample headers. The returned vector
+    /// has an item for each header, so the sample IDs of the instruments
+    /// stay valid. Samples with malformed headers are `None` and added to
+    /// the warnings.
     pub fn parse_sf2_samples(
-        file: &mut File,
+        reader: &mut (impl Read + Seek),
         headers: Vec<SampleHeader>,
         data: SampleData,
         sample_rate: u32,
-    ) -> Result<Vec<Self>, Sf2ParseError> {
+        warnings: &mut Vec<Sf2ParseError>,
+    ) -> Result<Vec<Option<Self>>, Sf2ParseError> {
         let smpl = if let Some(chunk) = data.smpl {
-            Self::read_chunk(file, chunk).map_err(|_| {
+            Self::read_chunk(reader, chunk).map_err(|_| {
                 Sf2ParseError::FailedToParseFile("Error reading sample contents".to_string())
             })?
         } else {
@@ -44,47 +55,71 @@ impl Sf2Sample {
             ));
         };
 
-        let mut samples = Vec::new();
+        if smpl.len() % 2 != 0 {
+            warnings.push(Sf2ParseError::malformed(
+                "smpl",
+                smpl.len() as u64 / 2,
+                "Odd chunk length, the last byte is ignored",
+            ));
+        }
 
-        if let Some(sm24) = data.sm24 {
-            // SF2 is 24-bit
-            let extra = Self::read_chunk(file, sm24).map_err(|_| {
-                Sf2ParseError::FailedToParseFile("Error reading extra sample contents".to_string())
-            })?;
+        let mut samples = Vec::new();
 
+        // The 24-bit extension is optional, the samples are loaded as 16-bit
+        // if it is malformed
+        let extra = data.sm24.and_then(|sm24| {
+            let extra = Self::read_chunk(reader, sm24).ok();
             let smpllen = smpl.len() / 2;
-            let extralen = extra.len() - (smpllen % 2);
-            if smpllen != extralen {
-                return Err(Sf2ParseError::FailedToParseFile(
-                    "Invalid sample length".to_string(),
-                ));
+            match extra {
+                Some(extra) if extra.len() == smpllen || extra.len() == smpllen + smpllen % 2 => {
+                    Some(extra)
+                }
+                _ => {
+                    warnings.push(Sf2ParseError::malformed(
+                        "sm24",
+                        0u64,
+                        "Invalid sample length, the 24-bit extension is ignored",
+                    ));
+                    None
+                }
             }
+        });
 
-            for i in 0..extralen {
-                let n0 = 0;
-                let n1 = extra[i];
-                let n2 = smpl[i * 2];
-                let n3 = smpl[i * 2 + 1];
-                let sample = i32::from_le_bytes([n0, n1, n2, n3]);
+        if let Some(extra) = extra {
+            // SF2 is 24-bit
+            for (i, n) in smpl.chunks_exact(2).enumerate() {
+                let sample = i32::from_le_bytes([0, extra[i], n[0], n[1]]);
                 let conv = sample as f32 / i32::MAX as f32;
                 samples.push(conv);
             }
         } else {
             // SF2 is 16-bit
-            for i in smpl.chunks(2) {
-                let n0 = i[0];
-                let n1 = i[1];
-                let sample = i16::from_le_bytes([n0, n1]);
+            for n in smpl.chunks_exact(2) {
+                let sample = i16::from_le_bytes([n[0], n[1]]);
                 let conv = sample as f32 / i16::MAX as f32;
                 samples.push(conv);
             }
         }
 
-        let mut out: Vec<Sf2Sample> = Vec::new();
+        let mut out = Vec::new();
 
-        for h in headers {
+        for (i, h) in headers.into_iter().enumerate() {
             let start = h.start;
             let end = h.end;
+
+            let malformed = if start > end || end as usize > samples.len() {
+                Some("Sample points outside of the sample data")
+            } else if h.sample_rate == 0 {
+                Some("Sample rate of zero")
+            } else {
+                None
+            };
+            if let Some(reason) = malformed {
+                warnings.push(Sf2ParseError::malformed("shdr", i as u64, reason));
+                out.push(None);
+                continue;
+            }
+
             let sample: Vec<f32> = samples[start as usize..end as usize].into();
 
             let new = Sf2Sample {
@@ -98,15 +133,116 @@ impl Sf2Sample {
                     SampleLink::RightSample => 1,
                     _ => 0,
                 },
-                loop_start: h.loop_start - start,
-                loop_end: h.loop_end - start,
+                loop_start: h.loop_start.clamp(start, end) - start,
+                loop_end: h.loop_end.clamp(start, end) - start,
                 sample_rate: h.sample_rate,
                 origpitch: h.origpitch,
                 pitchadj: h.pitchadj,
             };
-            out.push(new)
+            out.push(Some(new))
         }
 
         Ok(out)
     }
+
+    /// Returns the loop points in samples at the output sample rate.
+    fn loop_points(&self, sample_rate: u32) -> (usize, usize) {
+        let convert = |index| {
+            let index = convert_sample_index(index, self.sample_rate, sample_rate) as usize;
+            index.min(self.data.len())
+        };
+        (convert(self.loop_start), convert(self.loop_end))
+    }
+
+    /// Returns the data of the other sample of a stereo pair, aligned to the
+    /// length and loop points of this sample so both channels play in sync,
+    /// or `None` if they are already aligned.
+    ///
+    /// The parts before, inside and after the loop of the other sample are
+    /// each stretched to the length of the matching part of this sample. If
+    /// either sample has no loop, the other sample is cut or padded with
+    /// silence instead.
+    pub fn align_linked(&self, other: &Sf2Sample, sample_rate: u32) -> Option<Arc<[f32]>> {
+        let len = self.data.len();
+        let (start, end) = self.loop_points(sample_rate);
+        let (other_start, other_end) = other.loop_points(sample_rate);
+        if other.data.len() == len && (other_start, other_end) == (start, end) {
+            return None;
+        }
+
+        let data = &other.data;
+        let aligned: Vec<f32> = if start < end && other_start < other_end {
+            stretch(data, 0..other_start, start, false)
+                .chain(stretch(data, other_start..other_end, end - start, true))
+                .chain(stretch(data, other_end..data.len(), len - end, false))
+                .collect()
+        } else {
+            (0..len)
+                .map(|i| data.get(i).copied().unwrap_or(0.0))
+                .collect()
+        };
+        Some(aligned.into())
+    }
+}
+
+/// Resamples a part of the data to the given length with a linear
+/// interpolation. Parts that already have the length are copied as is. The
+/// sample after a looped part is its start, so the loop stays seamless.
+fn stretch(
+    data: &[f32],
+    part: Range<usize>,
+    len: usize,
+    looped: bool,
+) -> impl '_ + Iterator<Item = f32> {
+    let step = part.len() as f64 / len.max(1) as f64;
+    let get = |i: usize| data.get(i).copied().unwrap_or(0.0);
+    (0..len).map(move |i| {
+        let pos = part.start as f64 + i as f64 * step;
+        let index = pos as usize;
+        let next = if looped && index + 1 >= part.end {
+            part.start
+        } else {
+            index + 1
+        };
+        let fract = (pos - index as f64) as f32;
+        get(index) + (get(next) - get(index)) * fract
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(data: &[f32], loop_start: u32, loop_end: u32) -> Sf2Sample {
+        Sf2Sample {
+            data: data.into(),
+            link_type: 0,
+            loop_start,
+            loop_end,
+            sample_rate: 48000,
+            origpitch: 60,
+            pitchadj: 0,
+        }
+    }
+
+    #[test]
+    fn test_align_linked() {
+        let left = sample(&[0.0, 1.0, 2.0, 3.0, 4.0, 5.0], 1, 5);
+        assert!(left.align_linked(&left, 48000).is_none());
+
+        // A longer pre-roll and tail are shortened to the ones of the left sample
+        let right = sample(&[9.0, 9.0, 0.0, 1.0, 2.0, 3.0, 4.0, 9.0, 9.0], 3, 7);
+        let aligned = left.align_linked(&right, 48000).unwrap();
+        assert_eq!(&*aligned, [9.0, 1.0, 2.0, 3.0, 4.0, 9.0]);
+
+        // A loop of another length is stretched, and wraps to its start
+        let right = sample(&[0.0, 1.0, 3.0, 0.0], 1, 3);
+        let aligned = left.align_linked(&right, 48000).unwrap();
+        assert_eq!(&*aligned, [0.0, 1.0, 2.0, 3.0, 2.0, 0.0]);
+
+        // Without a loop, the sample is padded with silence
+        let right = sample(&[1.0, 2.0, 3.0], 0, 0);
+        let aligned = left.align_linked(&right, 48000).unwrap();
+        assert_eq!(&*aligned, [1.0, 2.0, 3.0, 0.0, 0.0, 0.0]);
+    }
 }
@@ -92,11 +92,11 @@ impl Sf2Zone {
                     }
                     GeneratorType::KeyRange => {
                         let range = gen.amount.as_range().copied();
-                        region.keyrange = range.map(|v| v.low..=v.high)
+                        region.keyrange = range.map(|v| v.low.min(127)..=v.high.min(127))
                     }
                     GeneratorType::VelRange => {
                         let range = gen.amount.as_range().copied();
-                        region.velrange = range.map(|v| v.low..=v.high)
+                        region.velrange = range.map(|v| v.low.min(127)..=v.high.min(127))
                     }
                     GeneratorType::InitialAttenuation => {
                         region.attenuation = gen.amount.as_i16().copied()
@@ -1,10 +1,13 @@
 use std::{
-    collections::VecDeque,
+    collections::{BTreeMap, VecDeque},
     ops::RangeInclusive,
     path::{Path, PathBuf},
 };
 
-use self::parse::{parse_tokens_resolved, SfzAmpegEnvelope, SfzGroupType, SfzOpcode, SfzToken};
+use self::parse::{
+    parse_str_tokens_resolved, parse_tokens_resolved, SfzAmpegEnvelope, SfzGroupType, SfzOpcode,
+    SfzToken,
+};
 
 use crate::{FilterType, LoopMode};
 
@@ -55,6 +58,43 @@ impl AmpegEnvelopeParams {
     }
 }
 
+/// The event that plays a region.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum RegionTrigger {
+    /// The region is played when a note starts
+    #[default]
+    Attack,
+
+    /// The region is played when a note is released
+    Release,
+
+    /// The region is played when a note starts while no other notes are
+    /// held on the channel
+    First,
+
+    /// The region is played when a note starts while other notes are held
+    /// on the channel
+    Legato,
+}
+
+/// A condition on the value of a MIDI controller for a region to be
+/// played, set with the `loccN` and `hiccN` opcodes.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CcCondition {
+    /// The controller number
+    pub cc: u8,
+
+    /// The range of controller values the region is played in
+    pub range: RangeInclusive<u8>,
+}
+
+impl CcCondition {
+    /// Returns true if the controller value falls within the range.
+    pub fn matches(&self, controllers: &[u8; 128]) -> bool {
+        self.range.contains(&controllers[self.cc as usize & 0x7F])
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct RegionParamsBuilder {
     lovel: u8,
@@ -84,6 +124,19 @@ pub(crate) struct RegionParamsBuilder {
     filter_type: FilterType,
     ampeg_envelope: AmpegEnvelopeParams,
     tune: i16,
+    trigger: RegionTrigger,
+    locc: BTreeMap<u8, u8>,
+    hicc: BTreeMap<u8, u8>,
+    on_locc: BTreeMap<u8, u8>,
+    on_hicc: BTreeMap<u8, u8>,
+    global_label: Option<String>,
+    group_label: Option<String>,
+    region_label: Option<String>,
+    group: u32,
+    polyphony: Option<u32>,
+    note_polyphony: Option<u32>,
+    invert_phase: Option<bool>,
+    swap_channels: Option<bool>,
 }
 
 impl Default for RegionParamsBuilder {
@@ -116,6 +169,19 @@ impl Default for RegionParamsBuilder {
             filter_type: FilterType::default(),
             ampeg_envelope: AmpegEnvelopeParams::default(),
             tune: 0,
+            trigger: RegionTrigger::Attack,
+            locc: BTreeMap::new(),
+            hicc: BTreeMap::new(),
+            on_locc: BTreeMap::new(),
+            on_hicc: BTreeMap::new(),
+            global_label: None,
+            group_label: None,
+            region_label: None,
+            group: 0,
+            polyphony: None,
+            note_polyphony: None,
+            invert_phase: None,
+            swap_channels: None,
         }
     }
 }
@@ -155,20 +221,77 @@ impl RegionParamsBuilder {
             SfzOpcode::DefaultPath(val) => self.default_path = Some(val),
             SfzOpcode::AmpegEnvelope(flag) => self.ampeg_envelope.update_from_flag(flag),
             SfzOpcode::Tune(val) => self.tune = val,
+            SfzOpcode::Trigger(val) => self.trigger = val,
+            SfzOpcode::Locc(cc, val) => {
+                self.locc.insert(cc, val);
+            }
+            SfzOpcode::Hicc(cc, val) => {
+                self.hicc.insert(cc, val);
+            }
+            SfzOpcode::OnLocc(cc, val) => {
+                self.on_locc.insert(cc, val);
+            }
+            SfzOpcode::OnHicc(cc, val) => {
+                self.on_hicc.insert(cc, val);
+            }
+            SfzOpcode::GlobalLabel(val) => self.global_label = Some(val),
+            SfzOpcode::GroupLabel(val) => self.group_label = Some(val),
+            SfzOpcode::RegionLabel(val) => self.region_label = Some(val),
+            SfzOpcode::Group(val) => self.group = val,
+            SfzOpcode::Polyphony(val) => self.polyphony = Some(val),
+            SfzOpcode::NotePolyphony(val) => self.note_polyphony = Some(val),
+            SfzOpcode::InvertPhase(val) => self.invert_phase = Some(val),
+            SfzOpcode::SwapChannels(val) => self.swap_channels = Some(val),
         }
     }
 
+    fn cc_conditions(&self) -> Vec<CcCondition> {
+        let mut controllers: Vec<u8> = self.locc.keys().chain(self.hicc.keys()).copied().collect();
+        controllers.sort_unstable();
+        controllers.dedup();
+
+        controllers
+            .into_iter()
+            .map(|cc| CcCondition {
+                cc,
+                range: self.locc.get(&cc).copied().unwrap_or(0)
+                    ..=self.hicc.get(&cc).copied().unwrap_or(127),
+            })
+            // Conditions covering all values can be skipped
+            .filter(|c| c.range != (0..=127))
+            .collect()
+    }
+
+    fn cc_trigger(&self) -> Option<CcCondition> {
+        // Only one controller can trigger a region, the lowest one is used
+        let cc = self
+            .on_locc
+            .keys()
+            .chain(self.on_hicc.keys())
+            .copied()
+            .min()?;
+
+        Some(CcCondition {
+            cc,
+            range: self.on_locc.get(&cc).copied().unwrap_or(0)
+                ..=self.on_hicc.get(&cc).copied().unwrap_or(127),
+        })
+    }
+
     fn build(self, base_path: &Path) -> Option<RegionParams> {
+        let cc_conditions = self.cc_conditions();
+        let cc_trigger = self.cc_trigger();
+
         let relative_sample_path = if let Some(default_path) = self.default_path {
             PathBuf::from(default_path).join(self.sample?)
         } else {
             self.sample?.into()
         };
 
+        // Missing samples keep their path, so loading them reports it
         let mut sample_path = base_path.join(relative_sample_path);
-        match sample_path.canonicalize() {
-            Ok(path) => sample_path = path,
-            Err(_) => return None,
+        if let Ok(path) = sample_path.canonicalize() {
+            sample_path = path;
         }
 
         Some(RegionParams {
@@ -196,6 +319,17 @@ impl RegionParamsBuilder {
             filter_type: self.filter_type,
             ampeg_envelope: self.ampeg_envelope,
             tune: self.tune,
+            trigger: self.trigger,
+            cc_conditions,
+            cc_trigger,
+            global_label: self.global_label,
+            group_label: self.group_label,
+            region_label: self.region_label,
+            group: self.group,
+            polyphony: self.polyphony,
+            note_polyphony: self.note_polyphony,
+            invert_phase: self.invert_phase,
+            swap_channels: self.swap_channels,
         })
     }
 }
@@ -227,6 +361,46 @@ pub struct RegionParams {
     pub filter_type: FilterType,
     pub ampeg_envelope: AmpegEnvelopeParams,
     pub tune: i16,
+    pub trigger: RegionTrigger,
+    pub cc_conditions: Vec<CcCondition>,
+
+    /// The controller range that plays the region when the controller
+    /// moves into it, set with the `on_loccN`/`on_hiccN` opcodes. Such
+    /// regions aren't played by notes.
+    pub cc_trigger: Option<CcCondition>,
+
+    /// The label of the `<global>` or `<master>` header of the region, set
+    /// with the `global_label`/`master_label` opcodes.
+    pub global_label: Option<String>,
+
+    /// The label of the `<group>` header of the region, set with the
+    /// `group_label` opcode.
+    pub group_label: Option<String>,
+
+    /// The label of the region, set with the `region_label` opcode.
+    pub region_label: Option<String>,
+
+    /// The polyphony group of the region, set with the `group` opcode.
+    /// Regions without one are in group 0.
+    pub group: u32,
+
+    /// The maximum number of voices of the polyphony group playing at once
+    /// on all keys, set with the `polyphony` opcode.
+    pub polyphony: Option<u32>,
+
+    /// The maximum number of voices of the polyphony group playing at once
+    /// on each key, set with the `note_polyphony` opcode.
+    pub note_polyphony: Option<u32>,
+
+    /// Whether the polarity of the sample is inverted, set with the
+    /// `xsynth_invert_phase` extension opcode. `None` uses the option of the
+    /// soundfont.
+    pub invert_phase: Option<bool>,
+
+    /// Whether the channels of the stereo sample are swapped, set with the
+    /// `xsynth_swap_channels` extension opcode. `None` uses the option of the
+    /// soundfont.
+    pub swap_channels: Option<bool>,
 }
 
 fn get_group_level(group_type: SfzGroupType) -> Option<usize> {
@@ -262,16 +436,16 @@ fn parse_sf_root(tokens: impl Iterator<Item = SfzToken>, base_path: PathBuf) ->
                 if let Some(group_level) = get_group_level(group) {
                     current_group = Some(group);
 
-                    // If stepping inside
+                    // Step outside of the headers at the same or a deeper
+                    // level, so that the header starts from the opcodes of
+                    // its parents
+                    group_data_stack.truncate(group_level - 1);
+
+                    // Step inside
                     while group_data_stack.len() < group_level {
                         let parent_group = group_data_stack.back().cloned().unwrap_or_default();
                         group_data_stack.push_back(parent_group);
                     }
-
-                    // If stepping outside
-                    while group_data_stack.len() > group_level {
-                        group_data_stack.pop_back();
-                    }
                 } else {
                     current_group = None;
                 }
@@ -314,3 +488,65 @@ pub fn parse_soundfont(sfz_path: impl Into<PathBuf>) -> Result<Vec<RegionParams>
 
     Ok(regions)
 }
+
+/// Parses SFZ text and returns its regions in a vector. Includes and sample
+/// paths are resolved relative to `base_path`, like for an SFZ file in that
+/// folder.
+pub fn parse_soundfont_str(
+    sfz: &str,
+    base_path: impl Into<PathBuf>,
+) -> Result<Vec<RegionParams>, SfzParseError> {
+    let base_path = base_path.into();
+    let tokens = parse_str_tokens_resolved(sfz, &base_path)?;
+    Ok(parse_sf_root(tokens.into_iter(), base_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn malformed_fixtures() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/malformed")
+    }
+
+    #[test]
+    fn test_malformed_files_dont_panic() {
+        for entry in fs::read_dir(malformed_fixtures()).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "sfz") {
+                let _ = parse_soundfont(&path);
+                let _ = parse_soundfont_str(&fs::read_to_string(&path).unwrap_or_default(), "/");
+            }
+        }
+    }
+
+    #[test]
+    fn test_recursive_include() {
+        let result = parse_soundfont(malformed_fixtures().join("recursive_include.sfz"));
+        assert!(matches!(result, Err(SfzParseError::RecursiveInclude(_))));
+    }
+
+    #[test]
+    fn test_headers_start_from_parent() {
+        // The regions only need a sample path that exists
+        let dir = std::env::temp_dir().join(format!("xsynth_sfz_headers_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("sample.wav"), []).unwrap();
+        let path = dir.join("test.sfz");
+        fs::write(
+            &path,
+            "<master> master_label=Keys\n\
+             <group> group_label=Legato group=2\n<region> sample=sample.wav\n\
+             <group> group_label=Staccato\n<region> sample=sample.wav\n",
+        )
+        .unwrap();
+        let regions = parse_soundfont(&path).unwrap();
+
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[1].global_label.as_deref(), Some("Keys"));
+        assert_eq!(regions[1].group_label.as_deref(), Some("Staccato"));
+        assert_eq!(regions[1].group, 0);
+    }
+}
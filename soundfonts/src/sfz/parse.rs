@@ -8,6 +8,7 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use super::RegionTrigger;
 use crate::{FilterType, LoopMode};
 use encoding_rs::UTF_8;
 use encoding_rs_io::DecodeReaderBytesBuilder;
@@ -45,6 +46,19 @@ pub enum SfzOpcode {
     FilterType(FilterType),
     DefaultPath(String),
     Tune(i16),
+    Trigger(RegionTrigger),
+    Locc(u8, u8),
+    Hicc(u8, u8),
+    OnLocc(u8, u8),
+    OnHicc(u8, u8),
+    GlobalLabel(String),
+    GroupLabel(String),
+    RegionLabel(String),
+    Group(u32),
+    Polyphony(u32),
+    NotePolyphony(u32),
+    InvertPhase(bool),
+    SwapChannels(bool),
     AmpegEnvelope(SfzAmpegEnvelope),
 }
 
@@ -116,6 +130,9 @@ pub enum SfzParseError {
 
     #[error("Failed to read file: {0}")]
     FailedToReadFile(PathBuf),
+
+    #[error("File includes itself: {0}")]
+    RecursiveInclude(PathBuf),
 }
 
 fn parse_key_number(val: &str) -> Option<i8> {
@@ -217,6 +234,30 @@ fn parse_loop_mode(val: &str) -> Option<LoopMode> {
     }
 }
 
+fn parse_trigger(val: &str) -> Option<RegionTrigger> {
+    match val {
+        "attack" => Some(RegionTrigger::Attack),
+        "release" => Some(RegionTrigger::Release),
+        "first" => Some(RegionTrigger::First),
+        "legato" => Some(RegionTrigger::Legato),
+        _ => None,
+    }
+}
+
+fn parse_switch(val: &str) -> Option<bool> {
+    match val {
+        "on" | "true" | "1" => Some(true),
+        "off" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Returns the controller number of opcodes like `locc64`.
+fn parse_cc_opcode_number(name: &str, prefix: &str) -> Option<u8> {
+    let cc: u8 = name.strip_prefix(prefix)?.parse().ok()?;
+    (cc <= 127).then_some(cc)
+}
+
 fn parse_sfz_opcode(
     opcode: Opcode,
     defines: &RefCell<HashMap<String, String>>,
@@ -242,6 +283,24 @@ fn parse_sfz_opcode(
     let val = val.as_ref();
     let name = name.as_ref();
 
+    if let Some(cc) = parse_cc_opcode_number(name, "locc") {
+        return Ok(parse_u8_in_range(val, 0..=127).map(|val| Locc(cc, val)));
+    }
+    if let Some(cc) = parse_cc_opcode_number(name, "hicc") {
+        return Ok(parse_u8_in_range(val, 0..=127).map(|val| Hicc(cc, val)));
+    }
+    // `start_loccN`/`start_hiccN` are the older names of `on_loccN`/`on_hiccN`
+    if let Some(cc) = parse_cc_opcode_number(name, "on_locc")
+        .or_else(|| parse_cc_opcode_number(name, "start_locc"))
+    {
+        return Ok(parse_u8_in_range(val, 0..=127).map(|val| OnLocc(cc, val)));
+    }
+    if let Some(cc) = parse_cc_opcode_number(name, "on_hicc")
+        .or_else(|| parse_cc_opcode_number(name, "start_hicc"))
+    {
+        return Ok(parse_u8_in_range(val, 0..=127).map(|val| OnHicc(cc, val)));
+    }
+
     Ok(match name {
         "lokey" => parse_key_number(val).map(Lokey),
         "hikey" => parse_key_number(val).map(Hikey),
@@ -269,6 +328,14 @@ fn parse_sfz_opcode(
         "offset" => parse_u32_in_range(val, 0..=u32::MAX).map(Offset),
         "default_path" => Some(DefaultPath(val.replace('\\', "/"))),
         "tune" => parse_i16_in_range(val, -2400..=2400).map(Tune),
+        "trigger" => parse_trigger(val).map(Trigger),
+        "group" => parse_u32_in_range(val, 0..=u32::MAX).map(Group),
+        "polyphony" => parse_u32_in_range(val, 1..=u32::MAX).map(Polyphony),
+        "note_polyphony" => parse_u32_in_range(val, 1..=u32::MAX).map(NotePolyphony),
+
+        // XSynth extensions to fix samples when they are loaded
+        "xsynth_invert_phase" => parse_switch(val).map(InvertPhase),
+        "xsynth_swap_channels" => parse_switch(val).map(SwapChannels),
 
         "ampeg_delay" => parse_float_in_range(val, 0.0..=100.0)
             .map(AmpegDelay)
@@ -296,6 +363,9 @@ fn parse_sfz_opcode(
             .map(AmpegEnvelope),
 
         "sample" => Some(Sample(val.replace('\\', "/"))),
+        "global_label" | "master_label" => Some(GlobalLabel(val.to_owned())),
+        "group_label" => Some(GroupLabel(val.to_owned())),
+        "region_label" => Some(RegionLabel(val.to_owned())),
 
         _ => None,
     })
@@ -352,15 +422,22 @@ pub fn parse_tokens_raw<'a>(
     })
 }
 
-fn parse_tokens_resolved_recursive(
-    instr_path: &Path,
+/// Reads and tokenizes an SFZ file and the files it includes. `includes`
+/// holds the files that are being parsed, to reject recursive includes.
+fn parse_file_tokens(
+    base_path: &Path,
     file_path: &Path,
     defines: &RefCell<HashMap<String, String>>,
+    includes: &mut Vec<PathBuf>,
 ) -> Result<Vec<SfzToken>, SfzParseError> {
     let file_path = file_path
         .canonicalize()
         .map_err(|_| SfzParseError::FailedToReadFile(file_path.to_owned()))?;
 
+    if includes.contains(&file_path) {
+        return Err(SfzParseError::RecursiveInclude(file_path));
+    }
+
     let f = File::open(&file_path)
         .map_err(|_| SfzParseError::FailedToReadFile(file_path.to_owned()))?;
 
@@ -371,15 +448,28 @@ fn parse_tokens_resolved_recursive(
     );
     let mut file = String::new();
 
-    reader.read_to_string(&mut file).unwrap();
+    reader
+        .read_to_string(&mut file)
+        .map_err(|_| SfzParseError::FailedToReadFile(file_path.to_owned()))?;
 
-    // Unwrap here is safe because the path is confirmed to be a file (read above)
-    // and therefore it will always have a parent folder. The path is also canonicalized.
-    let parent_path = instr_path.parent().unwrap();
+    includes.push(file_path);
+    let tokens = parse_str_tokens(&file, base_path, defines, includes);
+    includes.pop();
 
+    tokens
+}
+
+/// Tokenizes SFZ text, resolving its includes relative to `base_path`, the
+/// folder of the root SFZ file.
+fn parse_str_tokens(
+    input: &str,
+    base_path: &Path,
+    defines: &RefCell<HashMap<String, String>>,
+    includes: &mut Vec<PathBuf>,
+) -> Result<Vec<SfzToken>, SfzParseError> {
     let mut tokens = Vec::new();
 
-    let iter = parse_tokens_raw(&file, defines);
+    let iter = parse_tokens_raw(input, defines);
 
     let mut parsed_includes = HashMap::new();
 
@@ -395,8 +485,8 @@ fn parse_tokens_resolved_recursive(
 
                     // Get the cached tokens for this current path, or parse them if they haven't been parsed yet
                     let parsed_tokens = parsed_includes.entry(path.clone()).or_insert_with(|| {
-                        let full_path = parent_path.join(&path);
-                        parse_tokens_resolved_recursive(instr_path, &full_path, defines)
+                        let full_path = base_path.join(&path);
+                        parse_file_tokens(base_path, &full_path, defines, includes)
                     });
 
                     if let Ok(parsed_tokens) = parsed_tokens {
@@ -426,6 +516,17 @@ fn parse_tokens_resolved_recursive(
 }
 
 pub fn parse_tokens_resolved(file_path: &Path) -> Result<Vec<SfzToken>, SfzParseError> {
+    // Unwrap here is safe because the path is canonicalized by the caller and
+    // is a file, therefore it will always have a parent folder.
+    let base_path = file_path.parent().unwrap();
+    let defines = RefCell::new(HashMap::new());
+    parse_file_tokens(base_path, file_path, &defines, &mut Vec::new())
+}
+
+pub fn parse_str_tokens_resolved(
+    input: &str,
+    base_path: &Path,
+) -> Result<Vec<SfzToken>, SfzParseError> {
     let defines = RefCell::new(HashMap::new());
-    parse_tokens_resolved_recursive(file_path, file_path, &defines)
+    parse_str_tokens(input, base_path, &defines, &mut Vec::new())
 }